@@ -0,0 +1,97 @@
+//! Times the CPU side of culling instances and picking their levels of detail
+//! on the GPU, see [`RenderState::dispatch_cull_lod`], for growing numbers of
+//! instances. Recording the compute pass and the frame drawing its results
+//! should take about as long for each, as only the GPU's work grows.
+//!
+//! Run with `cargo bench --bench cull_lod`. Skipped without a GPU.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use nalgebra::{Matrix4, Vector3};
+use wgpu::util::DeviceExt;
+use wgpu_experiments::builder::RenderStateBuilder;
+use wgpu_experiments::gpu_culling::{InstanceData, MAX_CULLED_LODS};
+use wgpu_experiments::material::MaterialBuilder;
+use wgpu_experiments::mesh::{generate_cube, LodMesh};
+use wgpu_experiments::render_state::RenderState;
+
+const INSTANCE_COUNTS: [u32; 3] = [1_000, 10_000, 100_000];
+/// Frames timed per instance count, after as many to warm up.
+const FRAMES: u32 = 50;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	let Ok(mut state) =
+		pollster::block_on(RenderStateBuilder::new().build_headless(256, 256))
+	else {
+		println!("No GPU, skipping");
+		return Ok(());
+	};
+
+	let (vertices, indices) = generate_cube(0.5);
+	let indices: Vec<u32> = indices.into_iter().map(u32::from).collect();
+	// The same triangles at both levels, as only the CPU cost is measured.
+	let mesh = LodMesh::from_full_mesh(
+		state.device(),
+		&vertices,
+		&[(20., indices.clone()), (f32::MAX, indices)],
+	);
+	let aabb = mesh.aabb;
+	let material = state.build_material(MaterialBuilder::new());
+	let capacity = INSTANCE_COUNTS[INSTANCE_COUNTS.len() - 1];
+	if state
+		.set_cull_lod(Some((Arc::new(mesh), Arc::new(material))), capacity)
+		.is_err()
+	{
+		println!("No compute shaders, skipping");
+		return Ok(());
+	}
+
+	// Past the levels of the mesh, the distances are ignored.
+	let mut lod_distances = [0.; MAX_CULLED_LODS];
+	lod_distances[..2].copy_from_slice(&[20., 100.]);
+	for count in INSTANCE_COUNTS {
+		// Rows of 100 cubes spreading away from the default camera, so that some
+		// are culled and the others use both levels.
+		let instances: Vec<InstanceData> = (0..count)
+			.map(|i| {
+				let (x, z) = ((i % 100) as f32 - 50., -((i / 100) as f32) - 2.);
+				let transform = Matrix4::new_translation(&Vector3::new(x, 0., z));
+				InstanceData::new(&transform, &aabb, lod_distances)
+			})
+			.collect();
+		let descriptor = wgpu::util::BufferInitDescriptor {
+			label: Some("Benchmark Instances"),
+			contents: bytemuck::cast_slice(&instances),
+			usage: wgpu::BufferUsages::STORAGE,
+		};
+		let instance_buf = state.device().create_buffer_init(&descriptor);
+
+		for _ in 0..FRAMES {
+			frame(&mut state, &instance_buf, count);
+		}
+		let mut total = Duration::ZERO;
+		for _ in 0..FRAMES {
+			total += frame(&mut state, &instance_buf, count);
+		}
+		println!("{count:>7} instances: {:?} per frame", total / FRAMES);
+	}
+	Ok(())
+}
+
+/// Culls the first `count` instances of `instance_buf` and renders a frame.
+/// Returns how long the CPU took, without waiting for the GPU to finish.
+fn frame(state: &mut RenderState, instance_buf: &wgpu::Buffer, count: u32) -> Duration {
+	let start = Instant::now();
+	let mut encoder = state
+		.device()
+		.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+	state.dispatch_cull_lod(&mut encoder, instance_buf, count);
+	state.queue().submit([encoder.finish()]);
+	state.render().expect("Failed to render a frame");
+	let elapsed = start.elapsed();
+	state.device().poll(wgpu::Maintain::Wait);
+	elapsed
+}
@@ -0,0 +1,61 @@
+//! Draws 1000 cubes with a single indirect draw call, and saves the result to
+//! `indirect.png`.
+
+use color_eyre::Result;
+use nalgebra::{point, Matrix4, Vector3};
+use std::path::Path;
+use wgpu_experiments::builder::RenderStateBuilder;
+use wgpu_experiments::camera::Camera;
+use wgpu_experiments::indirect::{build_indirect_buffer, DrawCall};
+use wgpu_experiments::mesh::generate_cube;
+use wgpu_experiments::screenshot::save_screenshot;
+
+/// The cubes are laid out in a cube of this many along each side.
+const SIDE: u32 = 10;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	env_logger::init();
+	pollster::block_on(run())
+}
+
+async fn run() -> Result<()> {
+	let proj = nalgebra::Perspective3::new(4. / 3., 1., 0.1, 100.);
+	// Looks down at the grid from above and in front of it.
+	let camera = Camera::new(point![0., 6., 14.], 0., -0.4, proj);
+	let mut state = RenderStateBuilder::new()
+		.sample_count(4)
+		.camera(Box::new(camera))
+		.build_headless(640, 480)
+		.await?;
+
+	let (vertices, indices) = generate_cube(0.2);
+	let indices: Vec<u32> = indices.iter().copied().map(u32::from).collect();
+	state.load_mesh(&vertices, &indices);
+	let offset = (SIDE - 1) as f32 / 2.;
+	let transforms: Vec<Matrix4<f32>> = (0..SIDE.pow(3))
+		.map(|i| {
+			let [x, y, z] = [i % SIDE, i / SIDE % SIDE, i / SIDE / SIDE]
+				.map(|coord| coord as f32 - offset);
+			Matrix4::new_translation(&Vector3::new(x, y, z))
+		})
+		.collect();
+	state.set_instances(&transforms);
+
+	let draw = DrawCall {
+		index_count: indices.len() as u32,
+		instance_count: transforms.len() as u32,
+		first_index: 0,
+		base_vertex: 0,
+		first_instance: 0,
+	};
+	let indirect_buf = build_indirect_buffer(state.device(), &[draw]);
+	state.set_indirect_draws(Some((indirect_buf, 1)));
+
+	state.render()?;
+	let img = state.capture_screenshot()?;
+	let path = Path::new("indirect.png");
+	save_screenshot(img, path)?;
+	println!("Saved {} cubes to {}", transforms.len(), path.display());
+	Ok(())
+}
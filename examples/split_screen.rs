@@ -0,0 +1,69 @@
+//! Draws a cube from two cameras side by side, in a single render pass, and saves
+//! the result to `split_screen.png`.
+
+use color_eyre::Result;
+use nalgebra::{point, Matrix4, Perspective3, Vector3};
+use std::f32::consts::FRAC_PI_4;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu_experiments::builder::RenderStateBuilder;
+use wgpu_experiments::camera::OrbitCamera;
+use wgpu_experiments::material::MaterialBuilder;
+use wgpu_experiments::mesh::{generate_cube, GpuMesh};
+use wgpu_experiments::render_object::RenderObject;
+use wgpu_experiments::render_state::StencilMode;
+use wgpu_experiments::screenshot::save_screenshot;
+use wgpu_experiments::tex2d::Tex2d;
+use wgpu_experiments::viewport::Viewport;
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	env_logger::init();
+	pollster::block_on(run())
+}
+
+async fn run() -> Result<()> {
+	let mut state = RenderStateBuilder::new()
+		.sample_count(4)
+		.build_headless(WIDTH, HEIGHT)
+		.await?;
+	// Only the cube below is drawn.
+	state.set_instances(&[]);
+
+	let (vertices, indices) = generate_cube(0.2);
+	let indices: Vec<u32> = indices.iter().copied().map(u32::from).collect();
+	let cube = Arc::new(GpuMesh::new(state.device(), &vertices, &indices));
+	let color =
+		Tex2d::from_color(state.device(), state.queue(), None, [230, 120, 40, 255]);
+	let material = state.build_material(MaterialBuilder::new().diffuse(&color));
+	state.add_object(RenderObject {
+		mesh: cube,
+		material: Arc::new(material),
+		transform: Matrix4::new_rotation(Vector3::new(0.4, 0.6, 0.)),
+		stencil: StencilMode::Disabled,
+	});
+
+	let (half_width, height) = (WIDTH as f32 / 2., HEIGHT as f32);
+	let camera = |yaw, pitch| {
+		let proj = Perspective3::new(1., FRAC_PI_4, 0.1, 100.);
+		Box::new(OrbitCamera::new(point![0., 0., 0.], 1.5, yaw, pitch, proj))
+	};
+	// The main camera draws into the left half, looking at the cube from the
+	// front, and a second camera into the right half, looking down from the side.
+	state.set_camera(camera(0., 0.));
+	state.set_viewport(Some(Viewport::new(0., 0., half_width, height)));
+	state.add_view(
+		Viewport::new(half_width, 0., half_width, height),
+		camera(FRAC_PI_4 * 3., -FRAC_PI_4),
+	);
+
+	state.render()?;
+	let img = state.capture_screenshot()?;
+	let path = Path::new("split_screen.png");
+	save_screenshot(img, path)?;
+	println!("Saved {}", path.display());
+	Ok(())
+}
@@ -0,0 +1,77 @@
+//! Draws quads showing different cells of a texture atlas, all with the same
+//! material, and saves the result to `sprites.png`.
+
+use color_eyre::Result;
+use nalgebra::{Matrix4, Vector3};
+use std::f32::consts::FRAC_PI_2;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu_experiments::atlas::TextureAtlas;
+use wgpu_experiments::builder::RenderStateBuilder;
+use wgpu_experiments::material::MaterialBuilder;
+use wgpu_experiments::mesh::{generate_plane, GpuMesh};
+use wgpu_experiments::render_object::RenderObject;
+use wgpu_experiments::render_state::StencilMode;
+use wgpu_experiments::screenshot::save_screenshot;
+
+/// The atlas is cut into this many cells along each side.
+const GRID: u32 = 2;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	env_logger::init();
+	pollster::block_on(run())
+}
+
+async fn run() -> Result<()> {
+	let mut state = RenderStateBuilder::new()
+		.sample_count(4)
+		.build_headless(640, 480)
+		.await?;
+	// Only the sprites below are drawn.
+	state.set_instances(&[]);
+
+	let atlas = TextureAtlas::from_grid(
+		state.device(),
+		state.queue(),
+		include_bytes!("../src/tree.png"),
+		GRID,
+		GRID,
+	);
+	let material =
+		Arc::new(state.build_material(MaterialBuilder::new().diffuse(&atlas.texture)));
+
+	// The plane faces +y, so it is stood up to face the camera.
+	let stand_up = Matrix4::new_rotation(Vector3::new(FRAC_PI_2, 0., 0.));
+	let (vertices, indices) = generate_plane(0.2, 0.2, 1);
+	let indices: Vec<u32> = indices.iter().copied().map(u32::from).collect();
+	// The cells in reverse order, so the sprites show the image rearranged.
+	for (i, (col, row)) in (0..GRID)
+		.flat_map(|row| (0..GRID).map(move |col| (col, row)))
+		.rev()
+		.enumerate()
+	{
+		let rect = atlas
+			.rect(&format!("{col},{row}"))
+			.expect("Cell is in the grid");
+		// Each quad gets the UVs of its own cell.
+		let mut vertices = vertices.clone();
+		for vertex in &mut vertices {
+			vertex.uv = rect.remap(vertex.uv);
+		}
+		let x = (i as f32 - 1.5) * 0.25;
+		state.add_object(RenderObject {
+			mesh: Arc::new(GpuMesh::new(state.device(), &vertices, &indices)),
+			material: material.clone(),
+			transform: Matrix4::new_translation(&Vector3::new(x, 0., -0.5)) * stand_up,
+			stencil: StencilMode::Disabled,
+		});
+	}
+
+	state.render()?;
+	let img = state.capture_screenshot()?;
+	let path = Path::new("sprites.png");
+	save_screenshot(img, path)?;
+	println!("Saved {}", path.display());
+	Ok(())
+}
@@ -0,0 +1,116 @@
+//! Renders a cube over a mirror, with the reflection limited to the mirror by the
+//! stencil buffer, and saves the result to `stencil_reflection.png`.
+
+use color_eyre::Result;
+use nalgebra::{Matrix4, Vector3};
+use std::path::Path;
+use std::sync::Arc;
+use wgpu_experiments::builder::RenderStateBuilder;
+use wgpu_experiments::material::Material;
+use wgpu_experiments::mesh::{generate_cube, generate_plane, GpuMesh};
+use wgpu_experiments::render_object::RenderObject;
+use wgpu_experiments::render_state::{RenderState, StencilMode};
+use wgpu_experiments::screenshot::save_screenshot;
+use wgpu_experiments::tex2d::{SamplerConfig, Shape, Tex2d};
+use wgpu_experiments::vertex::Vertex;
+
+/// Height of the mirror, which lies flat.
+const MIRROR_Y: f32 = -0.25;
+/// The stencil value marking the mirror.
+const MIRROR_STENCIL: u32 = 1;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	env_logger::init();
+	pollster::block_on(run())
+}
+
+async fn run() -> Result<()> {
+	let mut state = RenderStateBuilder::new()
+		.sample_count(4)
+		.stencil(true)
+		.build_headless(640, 480)
+		.await?;
+	// Only the objects below are drawn.
+	state.set_instances(&[]);
+
+	let mirror = {
+		let (vertices, indices) = generate_plane(0.8, 1.2, 1);
+		mesh(&state, &vertices, &indices, false)
+	};
+	let (vertices, indices) = generate_cube(0.1);
+	let cube = mesh(&state, &vertices, &indices, false);
+	// Mirroring turns the triangles inside out, which is undone by flipping them.
+	let reflected_cube = mesh(&state, &vertices, &indices, true);
+
+	let mirror_material = Arc::new(material(&state, [40, 50, 70, 255]));
+	let cube_material = Arc::new(material(&state, [230, 120, 40, 255]));
+
+	let cube_transform =
+		Matrix4::new_translation(&Vector3::new(0., MIRROR_Y + 0.15, -0.4))
+			* Matrix4::new_rotation(Vector3::new(0.4, 0.6, 0.));
+	// Reflects across the plane y = MIRROR_Y.
+	let reflection = Matrix4::new_translation(&Vector3::new(0., MIRROR_Y, 0.))
+		* Matrix4::new_nonuniform_scaling(&Vector3::new(1., -1., 1.))
+		* Matrix4::new_translation(&Vector3::new(0., -MIRROR_Y, 0.));
+
+	// Marks the mirror in the stencil buffer, without writing depth.
+	state.add_object(RenderObject {
+		mesh: mirror,
+		material: mirror_material,
+		transform: Matrix4::new_translation(&Vector3::new(0., MIRROR_Y, -0.3)),
+		stencil: StencilMode::WriteRef(MIRROR_STENCIL),
+	});
+	// Only drawn where the mirror is.
+	state.add_object(RenderObject {
+		mesh: reflected_cube,
+		material: cube_material.clone(),
+		transform: reflection * cube_transform,
+		stencil: StencilMode::TestEqual(MIRROR_STENCIL),
+	});
+	state.add_object(RenderObject {
+		mesh: cube,
+		material: cube_material,
+		transform: cube_transform,
+		stencil: StencilMode::Disabled,
+	});
+
+	state.render()?;
+	let img = state.capture_screenshot()?;
+	let path = Path::new("stencil_reflection.png");
+	save_screenshot(img, path)?;
+	println!("Saved {}", path.display());
+	Ok(())
+}
+
+fn mesh(
+	state: &RenderState,
+	vertices: &[Vertex],
+	indices: &[u16],
+	flip_winding: bool,
+) -> Arc<GpuMesh> {
+	let mut indices: Vec<u32> = indices.iter().copied().map(u32::from).collect();
+	if flip_winding {
+		for triangle in indices.chunks_exact_mut(3) {
+			triangle.swap(1, 2);
+		}
+	}
+	Arc::new(GpuMesh::new(state.device(), vertices, &indices))
+}
+
+/// An untextured material of a single color.
+fn material(state: &RenderState, rgba: [u8; 4]) -> Material {
+	let color = Tex2d::new_from_rgba8(
+		state.device(),
+		state.queue(),
+		None,
+		&rgba,
+		Shape {
+			width: 1,
+			height: 1,
+		},
+		SamplerConfig::default(),
+	);
+	let flat = Tex2d::flat_normal_map(state.device(), state.queue());
+	state.create_material(&color, &flat)
+}
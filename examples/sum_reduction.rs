@@ -0,0 +1,121 @@
+//! Sums a buffer of floats on the GPU with a compute shader, and checks the
+//! result against the CPU.
+
+use color_eyre::{eyre::eyre, eyre::WrapErr, Result};
+use wgpu::util::DeviceExt;
+use wgpu_experiments::compute::ComputePass;
+
+/// Must match the shader's workgroup size.
+const WORKGROUP_SIZE: u32 = 64;
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	env_logger::init();
+	pollster::block_on(run())
+}
+
+async fn run() -> Result<()> {
+	let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+		backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+		dx12_shader_compiler: Default::default(),
+	});
+	let adapter = instance
+		.request_adapter(&wgpu::RequestAdapterOptions::default())
+		.await
+		.ok_or(eyre!("Failed to get a wgpu Adapter"))?;
+	let (device, queue) = adapter
+		.request_device(
+			&wgpu::DeviceDescriptor {
+				label: None,
+				features: wgpu::Features::empty(),
+				limits: wgpu::Limits::downlevel_defaults(),
+			},
+			None,
+		)
+		.await
+		.wrap_err("Failed to get wgpu Device")?;
+
+	let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::COMPUTE,
+		ty: wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Storage { read_only },
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		},
+		count: None,
+	};
+	let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+		label: Some("Sum Bind Group Layout"),
+		entries: &[storage_entry(0, true), storage_entry(1, false)],
+	});
+	let mut pass =
+		ComputePass::new(&device, include_str!("sum_reduction.wgsl"), "sum", layout)?;
+
+	let values: Vec<f32> = (0..100_000).map(|i| (i % 7) as f32 * 0.5).collect();
+	let expected: f32 = values.iter().sum();
+
+	// Each dispatch sums blocks of `WORKGROUP_SIZE` values into a smaller buffer,
+	// until a single value is left.
+	let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some("Sum Input"),
+		contents: bytemuck::cast_slice(&values),
+		usage: wgpu::BufferUsages::STORAGE,
+	});
+	let mut buffers = vec![input];
+	let mut len = values.len() as u32;
+	let mut encoder = device.create_command_encoder(&Default::default());
+	while len > 1 {
+		// Rounded up. `len` is at least 2 here.
+		let workgroups = (len - 1) / WORKGROUP_SIZE + 1;
+		let output = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Partial Sums"),
+			size: workgroups as u64 * 4,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+			mapped_at_creation: false,
+		});
+		let input = buffers.last().unwrap();
+		pass.set_bind_group(device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: pass.bind_group_layout(),
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: input.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: output.as_entire_binding(),
+				},
+			],
+		}));
+		pass.dispatch(&mut encoder, workgroups, 1, 1);
+		buffers.push(output);
+		len = workgroups;
+	}
+
+	let readback = device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Sum Readback"),
+		size: 4,
+		usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	encoder.copy_buffer_to_buffer(buffers.last().unwrap(), 0, &readback, 0, 4);
+	queue.submit([encoder.finish()]);
+
+	let (tx, rx) = std::sync::mpsc::channel();
+	readback
+		.slice(..)
+		.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+	device.poll(wgpu::Maintain::Wait);
+	rx.recv()?.wrap_err("Failed to map readback buffer")?;
+	let sum: f32 = bytemuck::pod_read_unaligned(&readback.slice(..).get_mapped_range());
+
+	println!("GPU sum: {sum}, CPU sum: {expected}");
+	// The order of additions differs, so allow for rounding.
+	let tolerance = expected.abs() * 1e-5;
+	if (sum - expected).abs() > tolerance {
+		return Err(eyre!("Sums differ by more than {tolerance}"));
+	}
+	Ok(())
+}
@@ -0,0 +1,33 @@
+// Sums each block of 64 values of `input` into one value of `output`.
+
+@group(0) @binding(0)
+var<storage, read> input: array<f32>;
+@group(0) @binding(1)
+var<storage, read_write> output: array<f32>;
+
+var<workgroup> partial_sums: array<f32, 64>;
+
+@compute @workgroup_size(64)
+fn sum(
+	@builtin(global_invocation_id) global_id: vec3<u32>,
+	@builtin(local_invocation_index) local_index: u32,
+	@builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+	var value = 0.0;
+	if global_id.x < arrayLength(&input) {
+		value = input[global_id.x];
+	}
+	partial_sums[local_index] = value;
+	workgroupBarrier();
+
+	// Tree reduction: each step halves the number of values left.
+	for (var stride = 32u; stride > 0u; stride = stride / 2u) {
+		if local_index < stride {
+			partial_sums[local_index] += partial_sums[local_index + stride];
+		}
+		workgroupBarrier();
+	}
+	if local_index == 0u {
+		output[workgroup_id.x] = partial_sums[0];
+	}
+}
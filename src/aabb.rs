@@ -0,0 +1,210 @@
+//! Axis aligned bounding boxes.
+
+use nalgebra::{Matrix4, Point3, Vector3};
+
+use crate::camera::{Frustum, Ray};
+use crate::debug_lines::DebugLines;
+use crate::vertex::Vertex;
+
+/// An axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+	pub min: Point3<f32>,
+	pub max: Point3<f32>,
+}
+impl Aabb {
+	pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+		Self { min, max }
+	}
+
+	/// The smallest box containing the positions of `verts`.
+	///
+	/// # Panics
+	/// If `verts` is empty.
+	pub fn from_vertices(verts: &[Vertex]) -> Self {
+		let pos = |v: &Vertex| Point3::new(v.pos.x, v.pos.y, v.pos.z);
+		let first = pos(verts.first().expect("No vertices to bound"));
+		verts
+			.iter()
+			.map(pos)
+			.fold(Self::new(first, first), |aabb, p| Self {
+				min: aabb.min.inf(&p),
+				max: aabb.max.sup(&p),
+			})
+	}
+
+	/// The smallest box containing `points`.
+	///
+	/// # Panics
+	/// If `points` is empty.
+	pub fn from_points(points: &[Point3<f32>]) -> Self {
+		let first = *points.first().expect("No points to bound");
+		points.iter().fold(Self::new(first, first), |aabb, p| Self {
+			min: aabb.min.inf(p),
+			max: aabb.max.sup(p),
+		})
+	}
+
+	/// The box bounding this one once moved by `transform`, which may be larger
+	/// than needed if it rotates.
+	pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+		Self::from_points(&self.corners().map(|p| transform.transform_point(&p)))
+	}
+
+	/// Whether any of the box is within `radius` of `center`.
+	pub fn intersects_sphere(&self, center: &Point3<f32>, radius: f32) -> bool {
+		let closest = center.sup(&self.min).inf(&self.max);
+		nalgebra::distance_squared(&closest, center) <= radius * radius
+	}
+
+	pub fn center(&self) -> Point3<f32> {
+		nalgebra::center(&self.min, &self.max)
+	}
+
+	pub fn half_extents(&self) -> Vector3<f32> {
+		(self.max - self.min) / 2.
+	}
+
+	/// Whether any of the box may be inside `frustum`. Boxes near the frustum's
+	/// edges can be reported as intersecting when they aren't, which is fine for
+	/// culling.
+	pub fn intersects_frustum(&self, frustum: &Frustum) -> bool {
+		frustum.planes.iter().all(|plane| {
+			// The corner furthest along the plane's normal.
+			let corner = Point3::new(
+				if plane.x >= 0. {
+					self.max.x
+				} else {
+					self.min.x
+				},
+				if plane.y >= 0. {
+					self.max.y
+				} else {
+					self.min.y
+				},
+				if plane.z >= 0. {
+					self.max.z
+				} else {
+					self.min.z
+				},
+			);
+			plane.dot(&corner.to_homogeneous()) >= 0.
+		})
+	}
+
+	/// How far along `ray` it first enters the box, or 0 if it starts inside of
+	/// it. `None` if it misses the box (slab method).
+	pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+		let (mut t_min, mut t_max) = (0_f32, f32::INFINITY);
+		for i in 0..3 {
+			let (origin, dir) = (ray.origin[i], ray.direction[i]);
+			if dir == 0. {
+				// Parallel to the slab, so either always or never between its planes.
+				if origin < self.min[i] || origin > self.max[i] {
+					return None;
+				}
+				continue;
+			}
+			let t0 = (self.min[i] - origin) / dir;
+			let t1 = (self.max[i] - origin) / dir;
+			t_min = t_min.max(t0.min(t1));
+			t_max = t_max.min(t0.max(t1));
+		}
+		(t_min <= t_max).then_some(t_min)
+	}
+
+	/// The 8 corners. Corner `i` takes its x from `max` if bit 0 of `i` is set,
+	/// its y if bit 1 is set and its z if bit 2 is set.
+	pub fn corners(&self) -> [Point3<f32>; 8] {
+		std::array::from_fn(|i| {
+			let pick = |bit: usize, min: f32, max: f32| {
+				if i & (1 << bit) != 0 {
+					max
+				} else {
+					min
+				}
+			};
+			Point3::new(
+				pick(0, self.min.x, self.max.x),
+				pick(1, self.min.y, self.max.y),
+				pick(2, self.min.z, self.max.z),
+			)
+		})
+	}
+
+	/// Adds the 12 edges of the box to `lines`.
+	pub fn draw_debug(&self, lines: &mut DebugLines, color: [f32; 4]) {
+		let corners = self.corners();
+		for i in 0..8 {
+			for bit in [1, 2, 4] {
+				// Each edge once, from the corner without the bit.
+				if i & bit == 0 {
+					lines.add_line(corners[i], corners[i | bit], color);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::builder::RenderStateBuilder;
+	use crate::camera::{Camera, CameraLike};
+	use crate::mesh::QUAD_VERTICES;
+	use nalgebra::{point, Perspective3};
+
+	fn cube(x: f32, y: f32, z: f32) -> Aabb {
+		Aabb::new(
+			point![x - 1., y - 1., z - 1.],
+			point![x + 1., y + 1., z + 1.],
+		)
+	}
+
+	#[test]
+	fn frustum_culling() {
+		// At the origin, looking down -z, seeing 5 units to each side at z = -5.
+		let proj = Perspective3::new(1., std::f32::consts::FRAC_PI_2, 0.1, 100.);
+		let frustum = Camera::new(Point3::origin(), 0., 0., proj).frustum();
+		assert!(cube(0., 0., -5.).intersects_frustum(&frustum));
+		// Straddling the left and top planes.
+		assert!(cube(-5.5, 0., -5.).intersects_frustum(&frustum));
+		assert!(cube(0., 5.5, -5.).intersects_frustum(&frustum));
+		// Behind the camera, past the far plane, and off to the side.
+		assert!(!cube(0., 0., 5.).intersects_frustum(&frustum));
+		assert!(!cube(0., 0., -200.).intersects_frustum(&frustum));
+		assert!(!cube(-20., 0., -5.).intersects_frustum(&frustum));
+		assert!(!cube(0., -20., -5.).intersects_frustum(&frustum));
+	}
+
+	#[test]
+	fn default_camera_sees_the_origin() {
+		let camera = RenderStateBuilder::new().default_camera(16. / 9.);
+		let frustum = camera.frustum();
+		let unit = Aabb::new(point![-0.5, -0.5, -0.5], point![0.5, 0.5, 0.5]);
+		assert!(unit.intersects_frustum(&frustum));
+		// Behind the camera, and past its far plane.
+		assert!(!cube(0., 0., 1000.).intersects_frustum(&frustum));
+		assert!(!cube(0., 0., -1000.).intersects_frustum(&frustum));
+	}
+
+	#[test]
+	fn screen_center_ray_hits_quad() {
+		// Two units in front of the unit quad at the origin, looking at it.
+		let proj =
+			Perspective3::new(800. / 600., std::f32::consts::FRAC_PI_4, 0.1, 100.);
+		let camera = Camera::new(point![0., 0., 2.], 0., 0., proj);
+		let quad = Aabb::from_vertices(QUAD_VERTICES);
+
+		let ray = camera.ray_from_screen((400., 300.), (800, 600));
+		let t = quad
+			.intersect_ray(&ray)
+			.expect("The center ray misses the quad");
+		// The ray starts on the near plane.
+		assert!((t - 1.9).abs() < 1e-3, "{t}");
+		assert!((ray.at(t) - Point3::origin()).norm() < 1e-3);
+		// The quad only covers the middle of the screen.
+		let corner = camera.ray_from_screen((0., 0.), (800, 600));
+		assert_eq!(quad.intersect_ray(&corner), None);
+	}
+}
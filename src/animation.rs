@@ -0,0 +1,225 @@
+//! Playing keyframed animations on a [`Skeleton`].
+
+use std::sync::Arc;
+
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3};
+
+use crate::skinning::{compose, Skeleton};
+
+/// The keyframes of a bone. Each of `translations`, `rotations` and `scales`
+/// either has a key per time or is empty, leaving that part of the bone at
+/// rest.
+#[derive(Clone, Debug, Default)]
+pub struct BoneTrack {
+	pub bone_index: u32,
+	/// In seconds, increasing.
+	pub times: Vec<f32>,
+	pub translations: Vec<[f32; 3]>,
+	/// Quaternions, as `[x, y, z, w]`.
+	pub rotations: Vec<[f32; 4]>,
+	pub scales: Vec<[f32; 3]>,
+}
+impl BoneTrack {
+	/// The pair of keys around `time` and how far it is from the first to the
+	/// second, holding the first and last keys before and after the track.
+	fn keys_at(&self, time: f32) -> (usize, usize, f32) {
+		let next = self.times.partition_point(|&t| t <= time);
+		if next == 0 {
+			(0, 0, 0.)
+		} else if next == self.times.len() {
+			(next - 1, next - 1, 0.)
+		} else {
+			let (start, end) = (self.times[next - 1], self.times[next]);
+			(next - 1, next, (time - start) / (end - start))
+		}
+	}
+}
+
+/// A named animation of some bones of a skeleton.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationClip {
+	pub name: String,
+	/// In seconds.
+	pub duration: f32,
+	pub tracks: Vec<BoneTrack>,
+}
+
+/// Plays an [`AnimationClip`] on a [`Skeleton`], looping by default.
+pub struct AnimationPlayer {
+	clip: Arc<AnimationClip>,
+	skeleton: Arc<Skeleton>,
+	/// In seconds, from the start of the clip.
+	time: f32,
+	/// Whether the clip starts over once it ends, or holds its last pose.
+	pub looping: bool,
+}
+impl AnimationPlayer {
+	/// # Panics
+	/// If a track is of a bone the skeleton doesn't have, or has a number of keys
+	/// other than its number of times.
+	pub fn new(clip: Arc<AnimationClip>, skeleton: Arc<Skeleton>) -> Self {
+		for track in &clip.tracks {
+			assert!(
+				(track.bone_index as usize) < skeleton.bones().len(),
+				"Clip {:?} animates bone {}, which the skeleton doesn't have",
+				clip.name,
+				track.bone_index
+			);
+			let num_keys = track.times.len();
+			assert!(
+				[
+					track.translations.len(),
+					track.rotations.len(),
+					track.scales.len()
+				]
+				.iter()
+				.all(|&len| len == 0 || len == num_keys),
+				"Clip {:?} has keys without times for bone {}",
+				clip.name,
+				track.bone_index
+			);
+		}
+		Self {
+			clip,
+			skeleton,
+			time: 0.,
+			looping: true,
+		}
+	}
+
+	pub fn clip(&self) -> &AnimationClip {
+		&self.clip
+	}
+
+	/// In seconds, from the start of the clip.
+	pub fn time(&self) -> f32 {
+		self.time
+	}
+
+	/// Moves the playback to `time`, within the clip.
+	pub fn seek(&mut self, time: f32) {
+		let duration = self.clip.duration;
+		self.time = if self.looping && duration > 0. {
+			time.rem_euclid(duration)
+		} else {
+			time.clamp(0., duration.max(0.))
+		};
+	}
+
+	/// Advances the playback by `dt` seconds and returns the pose of the skeleton
+	/// at the new time, for [`RenderState::update_skeleton`].
+	///
+	/// [`RenderState::update_skeleton`]: crate::render_state::RenderState::update_skeleton
+	pub fn advance(&mut self, dt: f32) -> Vec<Matrix4<f32>> {
+		self.seek(self.time + dt);
+		self.sample(self.time)
+	}
+
+	/// The skinning matrices of the skeleton at `time`: the keys of each track
+	/// interpolated, linearly for translation and scale and spherically for
+	/// rotation, and bones without a track at rest.
+	pub fn sample(&self, time: f32) -> Vec<Matrix4<f32>> {
+		let bones = self.skeleton.bones();
+		let mut local: Vec<Matrix4<f32>> =
+			bones.iter().map(|bone| bone.local_transform()).collect();
+		for track in &self.clip.tracks {
+			if track.times.is_empty() {
+				continue;
+			}
+			let bone = &bones[track.bone_index as usize];
+			let (a, b, t) = track.keys_at(time);
+			let lerp = |keys: &[[f32; 3]], rest: Vector3<f32>| {
+				if keys.is_empty() {
+					return rest;
+				}
+				Vector3::from(keys[a]).lerp(&Vector3::from(keys[b]), t)
+			};
+			let translation = lerp(&track.translations, bone.translation);
+			let scale = lerp(&track.scales, bone.scale);
+			let rotation = if track.rotations.is_empty() {
+				bone.rotation
+			} else {
+				let key = |i: usize| {
+					let [x, y, z, w] = track.rotations[i];
+					UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z))
+				};
+				slerp(key(a), key(b), t)
+			};
+			local[track.bone_index as usize] = compose(translation, rotation, scale);
+		}
+		self.skeleton.skinning_matrices(&local)
+	}
+}
+
+/// Interpolates along the shortest arc from `a` to `b`, falling back to a
+/// normalized lerp when they're too close for the angle between them to be
+/// precise.
+fn slerp(
+	a: UnitQuaternion<f32>,
+	b: UnitQuaternion<f32>,
+	t: f32,
+) -> UnitQuaternion<f32> {
+	// `b` and `-b` are the same rotation, on opposite sides of `a`.
+	let b = if a.coords.dot(&b.coords) < 0. {
+		UnitQuaternion::new_unchecked(-b.into_inner())
+	} else {
+		b
+	};
+	a.try_slerp(&b, t, 1e-6).unwrap_or_else(|| a.nlerp(&b, t))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skinning::Bone;
+
+	/// A root bone and a child, bound at their rest poses.
+	fn skeleton() -> Skeleton {
+		let mut root = Bone {
+			parent: None,
+			translation: Vector3::new(1., 2., 3.),
+			rotation: UnitQuaternion::identity(),
+			scale: Vector3::repeat(1.),
+			inverse_bind: Matrix4::identity(),
+		};
+		let mut child = Bone {
+			parent: Some(0),
+			translation: Vector3::y(),
+			rotation: UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5),
+			scale: Vector3::repeat(2.),
+			inverse_bind: Matrix4::identity(),
+		};
+		let root_bind = root.local_transform();
+		let child_bind = root_bind * child.local_transform();
+		root.inverse_bind = root_bind.try_inverse().unwrap();
+		child.inverse_bind = child_bind.try_inverse().unwrap();
+		Skeleton::new(vec![root, child])
+	}
+
+	#[test]
+	fn rest_pose_clip_is_identity() {
+		let skeleton = Arc::new(skeleton());
+		let rest = &skeleton.bones()[1];
+		let [x, y, z, w] = [0, 1, 2, 3].map(|i| rest.rotation.coords[i]);
+		let clip = AnimationClip {
+			name: "rest".into(),
+			duration: 2.,
+			tracks: vec![BoneTrack {
+				bone_index: 1,
+				times: vec![0., 2.],
+				translations: vec![rest.translation.into(); 2],
+				rotations: vec![[x, y, z, w]; 2],
+				scales: vec![],
+			}],
+		};
+		let mut player = AnimationPlayer::new(Arc::new(clip), skeleton);
+		for dt in [0., 0.5, 1.75, 3.] {
+			for matrix in player.advance(dt) {
+				assert!(
+					(matrix - Matrix4::identity()).abs().max() < 1e-5,
+					"{matrix}"
+				);
+			}
+		}
+	}
+}
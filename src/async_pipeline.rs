@@ -0,0 +1,95 @@
+//! Creating render pipelines off the render thread, as compiling their shaders
+//! can take hundreds of milliseconds.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use color_eyre::Result;
+
+cfg_if::cfg_if! {
+	if #[cfg(target_arch = "wasm32")] {
+		/// Creates a pipeline for a [`PendingPipeline`].
+		pub trait MakePipeline: FnOnce(&wgpu::Device) -> wgpu::RenderPipeline {}
+		impl<F: FnOnce(&wgpu::Device) -> wgpu::RenderPipeline> MakePipeline for F {}
+	} else {
+		/// Creates a pipeline for a [`PendingPipeline`], on another thread.
+		pub trait MakePipeline:
+			FnOnce(&wgpu::Device) -> wgpu::RenderPipeline + Send + 'static
+		{
+		}
+		impl<F> MakePipeline for F where
+			F: FnOnce(&wgpu::Device) -> wgpu::RenderPipeline + Send + 'static
+		{
+		}
+	}
+}
+
+/// What the thread creating the pipeline shares with its [`PendingPipeline`].
+#[derive(Default)]
+struct Shared {
+	result: Option<Result<wgpu::RenderPipeline>>,
+	/// Woken once `result` is set.
+	waker: Option<Waker>,
+}
+
+/// A render pipeline being created on another thread natively. On the web,
+/// where there are no threads, it is created right away.
+///
+/// It can be awaited, or polled every frame with [`Self::try_take`]. Creating it
+/// fails if the device reports a validation error.
+pub struct PendingPipeline {
+	shared: Arc<Mutex<Shared>>,
+}
+impl PendingPipeline {
+	/// Starts creating a pipeline with `make_pipeline`.
+	pub fn spawn(device: Arc<wgpu::Device>, make_pipeline: impl MakePipeline) -> Self {
+		let shared = Arc::new(Mutex::new(Shared::default()));
+		cfg_if::cfg_if! {
+			if #[cfg(target_arch = "wasm32")] {
+				shared.lock().unwrap().result = Some(Ok(make_pipeline(&device)));
+			} else {
+				use color_eyre::eyre::eyre;
+				use std::panic::{catch_unwind, AssertUnwindSafe};
+
+				let thread_shared = shared.clone();
+				std::thread::spawn(move || {
+					// wgpu panics on validation errors, in the thread that made them.
+					let result = catch_unwind(AssertUnwindSafe(|| make_pipeline(&device)))
+						.map_err(|_| eyre!("Failed to create a render pipeline"));
+					let mut shared = thread_shared.lock().unwrap();
+					shared.result = Some(result);
+					if let Some(waker) = shared.waker.take() {
+						waker.wake();
+					}
+				});
+			}
+		}
+		Self { shared }
+	}
+
+	/// Whether the pipeline was created, or failed to, and can be taken.
+	pub fn is_ready(&self) -> bool {
+		self.shared.lock().unwrap().result.is_some()
+	}
+
+	/// The pipeline if it is ready, without waiting. It is only returned once.
+	pub fn try_take(&mut self) -> Option<Result<wgpu::RenderPipeline>> {
+		self.shared.lock().unwrap().result.take()
+	}
+}
+impl Future for PendingPipeline {
+	type Output = Result<wgpu::RenderPipeline>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let mut shared = self.shared.lock().unwrap();
+		match shared.result.take() {
+			Some(result) => Poll::Ready(result),
+			None => {
+				shared.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
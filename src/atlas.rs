@@ -0,0 +1,106 @@
+//! Many small images packed into one texture.
+
+use std::collections::HashMap;
+
+use crate::tex2d::{SamplerConfig, Tex2d};
+use crate::vertex::Uv;
+
+/// A rectangle of a texture in UV coordinates, with v going down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvRect {
+	pub u_min: f32,
+	pub v_min: f32,
+	pub u_max: f32,
+	pub v_max: f32,
+}
+impl UvRect {
+	/// Maps `uv` from the whole texture to the rectangle, so that a mesh
+	/// textured with the whole texture shows only the rectangle.
+	pub fn remap(&self, uv: Uv) -> Uv {
+		Uv {
+			u: self.u_min + uv.u * (self.u_max - self.u_min),
+			v: self.v_min + uv.v * (self.v_max - self.v_min),
+		}
+	}
+}
+
+/// A texture made of named sub-images, such as the frames of sprites, so that
+/// they can all be drawn with a single material.
+pub struct TextureAtlas {
+	pub texture: Tex2d,
+	rects: HashMap<String, UvRect>,
+}
+impl TextureAtlas {
+	/// Loads an image divided into a grid of `cols` by `rows` cells of the same
+	/// size. The cells are named `"col,row"`, from `"0,0"` at the top left to
+	/// `"{cols - 1},{rows - 1}"` at the bottom right.
+	///
+	/// # Panics
+	/// If `bytes` isn't an image, or `cols` or `rows` is 0.
+	pub fn from_grid(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		bytes: &[u8],
+		cols: u32,
+		rows: u32,
+	) -> Self {
+		assert!(cols > 0 && rows > 0, "Atlas grid is empty");
+		let texture = Tex2d::new_from_img_bytes(
+			device,
+			queue,
+			bytes,
+			Some("Texture Atlas"),
+			SamplerConfig::default(),
+		);
+		let rects = grid_rects(cols, rows);
+		Self { texture, rects }
+	}
+
+	/// The region of the sub-image called `name`, if there is one.
+	pub fn rect(&self, name: &str) -> Option<UvRect> {
+		self.rects.get(name).copied()
+	}
+}
+
+/// The cells of a grid of `cols` by `rows`, named as in
+/// [`TextureAtlas::from_grid`].
+fn grid_rects(cols: u32, rows: u32) -> HashMap<String, UvRect> {
+	let mut rects = HashMap::with_capacity((cols * rows) as usize);
+	for row in 0..rows {
+		for col in 0..cols {
+			let rect = UvRect {
+				u_min: col as f32 / cols as f32,
+				v_min: row as f32 / rows as f32,
+				u_max: (col + 1) as f32 / cols as f32,
+				v_max: (row + 1) as f32 / rows as f32,
+			};
+			rects.insert(format!("{col},{row}"), rect);
+		}
+	}
+	rects
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn last_cell_reaches_the_corner() {
+		let rects = grid_rects(4, 2);
+		assert_eq!(rects.len(), 8);
+		let last = rects["3,1"];
+		assert_eq!(
+			last,
+			UvRect {
+				u_min: 0.75,
+				v_min: 0.5,
+				u_max: 1.,
+				v_max: 1.,
+			}
+		);
+		let corner = last.remap(Uv { u: 1., v: 1. });
+		assert_eq!((corner.u, corner.v), (1., 1.));
+		assert!(!rects.contains_key("4,1"));
+	}
+}
@@ -0,0 +1,403 @@
+//! Bloom post-processing: bright parts of the frame glow.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::tex2d::Tex2d;
+
+/// How strong the bloom is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BloomSettings {
+	/// Pixels with a higher luminance glow, from 0 to 1.
+	pub threshold: f32,
+	/// How much of the glow is added to the frame.
+	pub intensity: f32,
+	/// How far the glow spreads, in half resolution pixels. At most 32.
+	pub blur_radius: u32,
+}
+impl Default for BloomSettings {
+	fn default() -> Self {
+		Self {
+			threshold: 0.8,
+			intensity: 1.0,
+			blur_radius: 8,
+		}
+	}
+}
+
+/// The layout of the shader's `BloomUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct BloomUniform {
+	threshold: f32,
+	intensity: f32,
+	blur_radius: u32,
+	_pad: u32,
+}
+impl From<BloomSettings> for BloomUniform {
+	fn from(settings: BloomSettings) -> Self {
+		Self {
+			threshold: settings.threshold,
+			intensity: settings.intensity,
+			blur_radius: settings.blur_radius,
+			_pad: 0,
+		}
+	}
+}
+
+/// Adds a glow around the bright pixels of a frame.
+///
+/// The bright pixels are extracted into a half resolution texture, which is then
+/// blurred horizontally into another and finally blurred vertically while being
+/// added onto the output.
+pub struct BloomPass {
+	settings: BloomSettings,
+	uniform_buf: wgpu::Buffer,
+	uniform_bind_group: wgpu::BindGroup,
+	/// Layout of the bind groups of sampled textures, see [`Tex2d::layout`].
+	texture_layout: wgpu::BindGroupLayout,
+	sampler: wgpu::Sampler,
+	format: wgpu::TextureFormat,
+	/// The bright pixels.
+	bright: HalfResTarget,
+	/// `bright` blurred horizontally.
+	blurred: HalfResTarget,
+	threshold_pipeline: wgpu::RenderPipeline,
+	blur_h_pipeline: wgpu::RenderPipeline,
+	/// The vertical blur, which also adds the result to the frame.
+	blur_v_pipeline: wgpu::RenderPipeline,
+}
+impl BloomPass {
+	/// Creates a pass for `width` x `height` frames, drawing into `format`
+	/// textures. The intermediate textures have the same format.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Bloom Uniform"),
+			size: std::mem::size_of::<BloomUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let uniform_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Bloom Uniform Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("bloom_uniform_bind_group"),
+			layout: &uniform_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			}],
+		});
+		let texture_layout = Tex2d::layout(device);
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("Bloom Sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+		let pipeline = |label, entry_point, bind_group_layouts: &[_]| {
+			let layout =
+				device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+					label: Some(label),
+					bind_group_layouts,
+					push_constant_ranges: &[],
+				});
+			device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: Some(label),
+				layout: Some(&layout),
+				vertex: wgpu::VertexState {
+					module: &shader,
+					entry_point: "vs_main",
+					buffers: &[],
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: &shader,
+					entry_point,
+					targets: &[Some(format.into())],
+				}),
+				primitive: wgpu::PrimitiveState::default(),
+				depth_stencil: None,
+				multisample: wgpu::MultisampleState::default(),
+				multiview: None,
+			})
+		};
+		let single_texture = [&uniform_layout, &texture_layout];
+		let threshold_pipeline =
+			pipeline("Bloom Threshold Pipeline", "fs_threshold", &single_texture);
+		let blur_h_pipeline =
+			pipeline("Bloom Blur H Pipeline", "fs_blur_h", &single_texture);
+		let blur_v_pipeline = pipeline(
+			"Bloom Blur V Pipeline",
+			"fs_composite",
+			&[&uniform_layout, &texture_layout, &texture_layout],
+		);
+
+		let mut result = Self {
+			settings: BloomSettings::default(),
+			uniform_buf,
+			uniform_bind_group,
+			bright: HalfResTarget::new(
+				device,
+				&texture_layout,
+				&sampler,
+				width,
+				height,
+				format,
+				"Bloom Bright",
+			),
+			blurred: HalfResTarget::new(
+				device,
+				&texture_layout,
+				&sampler,
+				width,
+				height,
+				format,
+				"Bloom Blurred",
+			),
+			texture_layout,
+			sampler,
+			format,
+			threshold_pipeline,
+			blur_h_pipeline,
+			blur_v_pipeline,
+		};
+		result.set_settings(queue, BloomSettings::default());
+		result
+	}
+
+	pub fn settings(&self) -> BloomSettings {
+		self.settings
+	}
+
+	pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: BloomSettings) {
+		self.settings = settings;
+		let uniform = BloomUniform::from(settings);
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+	}
+
+	/// Recreates the intermediate textures for `width` x `height` frames.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		let texture = |label| {
+			HalfResTarget::new(
+				device,
+				&self.texture_layout,
+				&self.sampler,
+				width,
+				height,
+				self.format,
+				label,
+			)
+		};
+		self.bright = texture("Bloom Bright");
+		self.blurred = texture("Bloom Blurred");
+	}
+
+	/// Records drawing `input` with bloom into all of `output`. They must be
+	/// different textures, and `input` must be sampleable.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		input_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("bloom_input_bind_group"),
+			layout: &self.texture_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(input_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+			],
+		});
+		let passes = [
+			(
+				"Bloom Threshold Pass",
+				&self.threshold_pipeline,
+				&self.bright.view,
+				&[&input][..],
+			),
+			(
+				"Bloom Blur H Pass",
+				&self.blur_h_pipeline,
+				&self.blurred.view,
+				&[&self.bright.bind_group],
+			),
+			(
+				"Bloom Blur V Pass",
+				&self.blur_v_pipeline,
+				output_view,
+				&[&self.blurred.bind_group, &input],
+			),
+		];
+		for (label, pipeline, view, bind_groups) in passes {
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some(label),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						// Every pixel is drawn over.
+						load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: None,
+			});
+			pass.set_pipeline(pipeline);
+			pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+			for (i, bind_group) in bind_groups.iter().enumerate() {
+				pass.set_bind_group(i as u32 + 1, bind_group, &[]);
+			}
+			pass.draw(0..3, 0..1);
+		}
+	}
+}
+
+/// A texture at half the resolution of the frame, to draw into and sample.
+struct HalfResTarget {
+	view: wgpu::TextureView,
+	bind_group: wgpu::BindGroup,
+}
+impl HalfResTarget {
+	/// Creates a target for a `width` x `height` frame.
+	fn new(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		sampler: &wgpu::Sampler,
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+		label: &str,
+	) -> Self {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size: wgpu::Extent3d {
+				width: (width / 2).max(1),
+				height: (height / 2).max(1),
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some(label),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(sampler),
+				},
+			],
+		});
+		Self { view, bind_group }
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::gpu_context::{read_texture, test_context};
+
+	#[test]
+	fn single_white_pixel_glows() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let (device, queue) = (&context.device, &context.queue);
+		const SIZE: u32 = 32;
+		let format = wgpu::TextureFormat::Rgba8Unorm;
+		let texture = |usage| {
+			device.create_texture(&wgpu::TextureDescriptor {
+				label: None,
+				size: wgpu::Extent3d {
+					width: SIZE,
+					height: SIZE,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage,
+				view_formats: &[],
+			})
+		};
+		let input = texture(
+			wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+		);
+		let output = texture(
+			wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+		);
+		let mut image =
+			image::RgbaImage::from_pixel(SIZE, SIZE, image::Rgba([0, 0, 0, 255]));
+		image.put_pixel(16, 16, image::Rgba([255; 4]));
+		queue.write_texture(
+			input.as_image_copy(),
+			&image,
+			wgpu::ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(SIZE * 4),
+				rows_per_image: None,
+			},
+			input.size(),
+		);
+
+		let mut bloom = BloomPass::new(device, queue, SIZE, SIZE, format);
+		// A single pixel is dim once spread out, so it is made stronger.
+		let settings = BloomSettings {
+			intensity: 16.,
+			blur_radius: 2,
+			..Default::default()
+		};
+		bloom.set_settings(queue, settings);
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+		let view = |texture: &wgpu::Texture| {
+			texture.create_view(&wgpu::TextureViewDescriptor::default())
+		};
+		bloom.apply(device, &mut encoder, &view(&input), &view(&output));
+		queue.submit([encoder.finish()]);
+
+		let result = read_texture(&context, &output);
+		assert_eq!(result.get_pixel(16, 16).0, [255; 4]);
+		for (x, y) in [(13, 16), (19, 16), (16, 13), (16, 19), (18, 18)] {
+			let [r, g, b, _] = result.get_pixel(x, y).0;
+			assert!(r > 0 && g > 0 && b > 0, "No glow at {x}, {y}");
+		}
+		assert_eq!(result.get_pixel(0, 0).0, [0, 0, 0, 255]);
+	}
+}
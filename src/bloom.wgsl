@@ -0,0 +1,91 @@
+// Bloom: bright parts of the frame bleed light into their surroundings.
+//
+// `fs_threshold` keeps the bright pixels at half resolution, `fs_blur_h` blurs
+// them horizontally and `fs_composite` blurs them vertically while adding them
+// to the frame.
+
+struct BloomUniform {
+	threshold: f32,
+	intensity: f32,
+	blur_radius: u32,
+};
+@group(0) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+@group(1) @binding(0)
+var src_t: texture_2d<f32>;
+@group(1) @binding(1)
+var src_s: sampler;
+
+// The frame the bloom is added to, only used by `fs_composite`.
+@group(2) @binding(0)
+var scene_t: texture_2d<f32>;
+@group(2) @binding(1)
+var scene_s: sampler;
+
+// Bounds the cost of the blur, however large the radius is set.
+const MAX_RADIUS: u32 = 32u;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen, with UVs from 0 to 1 over the visible part.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+fn bright(uv: vec2<f32>) -> vec3<f32> {
+	let color = textureSampleLevel(src_t, src_s, uv, 0.0).rgb;
+	let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+	return select(vec3<f32>(0.0), color, luminance > bloom.threshold);
+}
+
+// Averages the 2x2 source pixels under each output pixel. They are thresholded
+// one by one, so that small bright spots aren't diluted below the threshold.
+@fragment
+fn fs_threshold(in: VertexOutput) -> @location(0) vec4<f32> {
+	let half_texel = 0.5 / vec2<f32>(textureDimensions(src_t, 0));
+	let sum = bright(in.uv + half_texel * vec2<f32>(-1.0, -1.0))
+		+ bright(in.uv + half_texel * vec2<f32>(1.0, -1.0))
+		+ bright(in.uv + half_texel * vec2<f32>(-1.0, 1.0))
+		+ bright(in.uv + half_texel * vec2<f32>(1.0, 1.0));
+	return vec4<f32>(sum / 4.0, 1.0);
+}
+
+// A Gaussian blur of `src_t` along `direction`, out to `bloom.blur_radius`
+// pixels on either side.
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec3<f32> {
+	let step = direction / vec2<f32>(textureDimensions(src_t, 0));
+	let radius = i32(min(bloom.blur_radius, MAX_RADIUS));
+	// Cuts the curve off at two standard deviations.
+	let sigma = max(f32(radius) / 2.0, 0.5);
+	var sum = vec3<f32>(0.0);
+	var total = 0.0;
+	for (var i = -radius; i <= radius; i += 1) {
+		let x = f32(i);
+		let weight = exp(-x * x / (2.0 * sigma * sigma));
+		sum += textureSampleLevel(src_t, src_s, uv + step * x, 0.0).rgb * weight;
+		total += weight;
+	}
+	return sum / total;
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+	return vec4<f32>(blur(in.uv, vec2<f32>(1.0, 0.0)), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+	let scene = textureSample(scene_t, scene_s, in.uv);
+	let glow = blur(in.uv, vec2<f32>(0.0, 1.0)) * bloom.intensity;
+	return vec4<f32>(scene.rgb + glow, scene.a);
+}
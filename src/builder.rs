@@ -0,0 +1,265 @@
+use color_eyre::Result;
+use nalgebra::{point, Perspective3};
+use std::sync::Arc;
+use tracing::Instrument;
+use winit::window::Window;
+
+use crate::camera::{Camera, CameraKeymap, CameraLike};
+use crate::gpu_context::SharedGpuContext;
+use crate::msaa_resolve::ResolveFilter;
+use crate::render_state::RenderState;
+use crate::tonemap::HDR_FORMAT;
+
+/// Options for creating a [`RenderState`]. Everything not set has a default.
+pub struct RenderStateBuilder {
+	pub(crate) power_preference: wgpu::PowerPreference,
+	pub(crate) force_fallback: bool,
+	pub(crate) present_mode: Option<wgpu::PresentMode>,
+	pub(crate) clear_color: wgpu::Color,
+	pub(crate) features: wgpu::Features,
+	pub(crate) limits: Option<wgpu::Limits>,
+	pub(crate) sample_count: u32,
+	pub(crate) resolve_filter: Option<ResolveFilter>,
+	pub(crate) stencil: bool,
+	pub(crate) use_reverse_z: bool,
+	pub(crate) hdr: bool,
+	pub(crate) camera: Option<Box<dyn CameraLike>>,
+	pub(crate) camera_keymap: CameraKeymap,
+}
+impl Default for RenderStateBuilder {
+	fn default() -> Self {
+		Self {
+			power_preference: wgpu::PowerPreference::LowPower,
+			force_fallback: false,
+			present_mode: None,
+			clear_color: wgpu::Color {
+				r: 0.1,
+				g: 0.2,
+				b: 0.3,
+				a: 1.0,
+			},
+			features: wgpu::Features::empty(),
+			limits: None,
+			sample_count: 1,
+			resolve_filter: None,
+			stencil: false,
+			use_reverse_z: false,
+			hdr: false,
+			camera: None,
+			camera_keymap: CameraKeymap::default(),
+		}
+	}
+}
+impl std::fmt::Debug for RenderStateBuilder {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("RenderStateBuilder")
+			.field("power_preference", &self.power_preference)
+			.field("force_fallback", &self.force_fallback)
+			.field("present_mode", &self.present_mode)
+			.field("clear_color", &self.clear_color)
+			.field("features", &self.features)
+			.field("limits", &self.limits)
+			.field("sample_count", &self.sample_count)
+			.field("resolve_filter", &self.resolve_filter)
+			.field("stencil", &self.stencil)
+			.field("use_reverse_z", &self.use_reverse_z)
+			.field("hdr", &self.hdr)
+			.field("camera", &self.camera.as_ref().map(|_| "dyn CameraLike"))
+			.field("camera_keymap", &self.camera_keymap)
+			.finish()
+	}
+}
+impl RenderStateBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Which kind of adapter to prefer. Defaults to `LowPower`.
+	pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+		self.power_preference = power_preference;
+		self
+	}
+
+	/// Forces a software adapter, if there is one.
+	pub fn force_fallback(mut self, force_fallback: bool) -> Self {
+		self.force_fallback = force_fallback;
+		self
+	}
+
+	/// The surface's present mode. Defaults to the surface's preferred mode, which
+	/// is also used if this one isn't supported. Ignored when headless.
+	pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+		self.present_mode = Some(present_mode);
+		self
+	}
+
+	/// The color the scene is drawn over, see [`RenderState::set_clear_color`].
+	/// Defaults to a dark blue.
+	pub fn clear_color(mut self, clear_color: wgpu::Color) -> Self {
+		self.clear_color = clear_color;
+		self
+	}
+
+	/// Features the device must have. Building fails if the adapter lacks any of
+	/// them. Some optional features are enabled regardless, when available.
+	pub fn features(mut self, features: wgpu::Features) -> Self {
+		self.features = features;
+		self
+	}
+
+	/// Defaults to the downlevel limits, or the WebGL2 ones on web.
+	pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+		self.limits = Some(limits);
+		self
+	}
+
+	/// MSAA samples per pixel. Lowered to one the adapter supports, if needed.
+	/// Defaults to 1.
+	pub fn sample_count(mut self, sample_count: u32) -> Self {
+		self.sample_count = sample_count;
+		self
+	}
+
+	/// Resolves MSAA with a compute shader weighting the samples around each
+	/// pixel by `filter`, rather than with the hardware. Ignored without MSAA, or
+	/// if the device lacks compute shaders. Defaults to the hardware resolve.
+	pub fn resolve_filter(mut self, filter: ResolveFilter) -> Self {
+		self.resolve_filter = Some(filter);
+		self
+	}
+
+	/// Whether the depth buffer has a stencil buffer, for [`StencilMode`]s other
+	/// than `Disabled`. Defaults to false.
+	///
+	/// [`StencilMode`]: crate::render_state::StencilMode
+	pub fn stencil(mut self, stencil: bool) -> Self {
+		self.stencil = stencil;
+		self
+	}
+
+	/// Whether depth goes from 1 at the near plane to 0 at the far plane, for more
+	/// precision far from the camera. With a stencil buffer, the depth format
+	/// may not be a float one, and gains less from it. Defaults to false.
+	pub fn use_reverse_z(mut self, use_reverse_z: bool) -> Self {
+		self.use_reverse_z = use_reverse_z;
+		self
+	}
+
+	/// Whether the scene is drawn in [`HDR_FORMAT`] and then tone mapped into the
+	/// frame, so that colors brighter than white keep their detail. See
+	/// [`RenderState::set_tone_mapping`]. Defaults to false.
+	///
+	/// [`HDR_FORMAT`]: crate::tonemap::HDR_FORMAT
+	pub fn hdr(mut self, hdr: bool) -> Self {
+		self.hdr = hdr;
+		self
+	}
+
+	/// The camera to draw from. Defaults to a fly camera at `(0, 0, 1)` looking at
+	/// the origin. Its aspect ratio is set to the frame's.
+	pub fn camera(mut self, camera: Box<dyn CameraLike>) -> Self {
+		self.camera = Some(camera);
+		self
+	}
+
+	/// The keys moving the default fly camera. Ignored if a camera is set with
+	/// [`Self::camera`]. Defaults to WASD, with E and Q to go up and down.
+	pub fn camera_keymap(mut self, keymap: CameraKeymap) -> Self {
+		self.camera_keymap = keymap;
+		self
+	}
+
+	/// The format the scene is drawn in, for frames in `surface_format`.
+	pub(crate) fn color_format(
+		&self,
+		surface_format: wgpu::TextureFormat,
+	) -> wgpu::TextureFormat {
+		if self.hdr {
+			HDR_FORMAT
+		} else {
+			surface_format
+		}
+	}
+
+	/// The fly camera used when none is set with [`Self::camera`], for frames of
+	/// `aspect` width / height. It is at `(0, 0, 1)`, looking at the origin.
+	pub(crate) fn default_camera(&self, aspect: f32) -> Camera {
+		// to_radians() wasn't const yet :(
+		const FOVY: f32 = 45.0 / 180.0 * std::f32::consts::PI;
+		const ZNEAR: f32 = 0.1;
+		const ZFAR: f32 = 100.0;
+		let proj = Perspective3::new(aspect, FOVY, ZNEAR, ZFAR);
+		let mut camera = Camera::new(point![0., 0., 1.], 0., 0., proj);
+		camera.keymap = self.camera_keymap;
+		camera
+	}
+
+	pub(crate) fn depth_format(&self) -> wgpu::TextureFormat {
+		if self.stencil {
+			RenderState::DEPTH_STENCIL_FORMAT
+		} else {
+			RenderState::DEPTH_FORMAT
+		}
+	}
+
+	/// Creates a `RenderState` drawing to `window`.
+	pub async fn build(mut self, window: Window) -> Result<RenderState> {
+		let camera = self.camera.take();
+		let mut state = RenderState::from_builder(window, &self)
+			.instrument(tracing::info_span!("wgpu_init"))
+			.await?;
+		if let Some(camera) = camera {
+			state.set_camera(camera);
+		}
+		Ok(state)
+	}
+
+	/// Creates a `RenderState` drawing to `window` with the device of `context`,
+	/// see [`RenderState::new_with_context`]. The options choosing the adapter and
+	/// device are ignored, as `context` already has them.
+	pub fn build_with_context(
+		mut self,
+		window: Window,
+		context: Arc<SharedGpuContext>,
+	) -> Result<RenderState> {
+		let camera = self.camera.take();
+		let mut state = RenderState::context_from_builder(window, context, &self)?;
+		if let Some(camera) = camera {
+			state.set_camera(camera);
+		}
+		Ok(state)
+	}
+
+	/// Creates a `RenderState` that renders into a `width` x `height` texture, see
+	/// [`RenderState::new_headless`].
+	pub async fn build_headless(
+		mut self,
+		width: u32,
+		height: u32,
+	) -> Result<RenderState> {
+		let camera = self.camera.take();
+		let mut state = RenderState::headless_from_builder(width, height, &self)
+			.instrument(tracing::info_span!("wgpu_init"))
+			.await?;
+		if let Some(camera) = camera {
+			state.set_camera(camera);
+		}
+		Ok(state)
+	}
+	/// Like [`Self::build_headless`], with the device of `context`. The options
+	/// choosing the adapter and device are ignored, as `context` already has them.
+	pub fn build_headless_with_context(
+		mut self,
+		context: Arc<SharedGpuContext>,
+		width: u32,
+		height: u32,
+	) -> Result<RenderState> {
+		let camera = self.camera.take();
+		let mut state =
+			RenderState::headless_context_from_builder(context, width, height, &self)?;
+		if let Some(camera) = camera {
+			state.set_camera(camera);
+		}
+		Ok(state)
+	}
+}
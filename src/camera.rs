@@ -1,8 +1,18 @@
-use nalgebra::geometry::{IsometryMatrix3, Perspective3};
-use nalgebra::{matrix, Matrix4, Translation3};
+use bytemuck::{Pod, Zeroable};
+use nalgebra::geometry::{
+	IsometryMatrix3, Orthographic3, Perspective3, Point3, Rotation3,
+};
+use nalgebra::{matrix, vector, Matrix4, Vector3, Vector4};
 use winit::event::VirtualKeyCode;
 use winit_input_helper::WinitInputHelper;
 
+use crate::types::mat4_to_wgsl;
+
+/// The indices `WinitInputHelper` gives the mouse buttons cameras are dragged
+/// with.
+const LEFT_MOUSE: usize = 0;
+const MIDDLE_MOUSE: usize = 2;
+
 /// OpenGL convention (which nalgebra follows): z goes from [-1, 1].
 /// WebGPU uses [0, 1] for z.
 const OPENGL_TO_WGPU_M: Matrix4<f32> = matrix![
@@ -11,42 +21,696 @@ const OPENGL_TO_WGPU_M: Matrix4<f32> = matrix![
 	0.0, 0.0, 0.5, 0.0;
 	0.0, 0.0, 0.5, 1.0;
 ];
+/// The inverse of [`OPENGL_TO_WGPU_M`].
+const WGPU_TO_OPENGL_M: Matrix4<f32> = matrix![
+	1.0, 0.0, 0.0, 0.0;
+	0.0, 1.0, 0.0, 0.0;
+	0.0, 0.0, 2.0, 0.0;
+	0.0, 0.0, -1.0, 1.0;
+];
+/// Like [`OPENGL_TO_WGPU_M`], but for a reverse-Z depth buffer: z goes from
+/// [-1, 1] to [1, 0], so the near plane is at depth 1 and the far plane at 0.
+/// Floats are most precise near 0, which makes up for the precision perspective
+/// projections lose with distance.
+const OPENGL_TO_WGPU_REVERSE_Z_M: Matrix4<f32> = matrix![
+	1.0, 0.0, 0.0, 0.0;
+	0.0, 1.0, 0.0, 0.0;
+	0.0, 0.0, -0.5, 0.5;
+	0.0, 0.0, 0.0, 1.0;
+];
+
+/// Turns a projection made with [`OPENGL_TO_WGPU_M`], like
+/// [`CameraLike::proj_view`], into one for a reverse-Z depth buffer.
+pub(crate) fn reverse_z(proj_view: &Matrix4<f32>) -> Matrix4<f32> {
+	OPENGL_TO_WGPU_REVERSE_Z_M * WGPU_TO_OPENGL_M * proj_view
+}
+
+/// The layout of `shader.wgsl`'s `CameraUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct CameraUniform {
+	view_proj: [[f32; 4]; 4],
+	/// In world space, for view dependent lighting.
+	position: [f32; 3],
+	_pad: f32,
+}
+impl CameraUniform {
+	pub fn new(view_proj: Matrix4<f32>, position: Point3<f32>) -> Self {
+		Self {
+			view_proj: mat4_to_wgsl(view_proj),
+			position: position.into(),
+			_pad: 0.,
+		}
+	}
+
+	/// The uniform of a camera at `view`, with the projection of `camera`,
+	/// optionally for a reverse-Z depth buffer.
+	pub fn from_view(
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+		reverse: bool,
+	) -> Self {
+		Self::from_jittered_view(camera, view, reverse, &Matrix4::identity())
+	}
+
+	/// The layout of the bind groups of [`Self::create_bind_group`].
+	pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("Camera Bind Group Layout"),
+			entries: &[wgpu::BindGroupLayoutEntry {
+				binding: 0,
+				visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Buffer {
+					ty: wgpu::BufferBindingType::Uniform,
+					has_dynamic_offset: false,
+					// We could specify this for more performance, but meh
+					min_binding_size: None,
+				},
+				count: None,
+			}],
+		})
+	}
+
+	/// Binds `buffer`, holding a `CameraUniform`, with `layout` from
+	/// [`Self::create_bind_group_layout`].
+	pub fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		buffer: &wgpu::Buffer,
+		label: &str,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some(label),
+			layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: buffer.as_entire_binding(),
+			}],
+		})
+	}
+
+	/// Like [`Self::from_view`], with clip space then moved by `jitter`.
+	pub fn from_jittered_view(
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+		reverse: bool,
+		jitter: &Matrix4<f32>,
+	) -> Self {
+		let mut proj_view = camera.proj_view_from(view);
+		if reverse {
+			proj_view = reverse_z(&proj_view);
+		}
+		Self::new(
+			jitter * proj_view,
+			view.inverse_transform_point(&Point3::origin()),
+		)
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProjectionKind {
+	Perspective(Perspective3<f32>),
+	Orthographic(Orthographic3<f32>),
+}
+impl ProjectionKind {
+	pub fn as_matrix(&self) -> &Matrix4<f32> {
+		match self {
+			Self::Perspective(p) => p.as_matrix(),
+			Self::Orthographic(o) => o.as_matrix(),
+		}
+	}
+
+	/// The distance of the near clipping plane.
+	pub fn znear(&self) -> f32 {
+		match self {
+			Self::Perspective(p) => p.znear(),
+			Self::Orthographic(o) => o.znear(),
+		}
+	}
+
+	/// The distance of the far clipping plane.
+	pub fn zfar(&self) -> f32 {
+		match self {
+			Self::Perspective(p) => p.zfar(),
+			Self::Orthographic(o) => o.zfar(),
+		}
+	}
+
+	/// Sets the width / height ratio of the view. Orthographic projections keep
+	/// their vertical extent and horizontal center.
+	pub fn set_aspect(&mut self, aspect: f32) {
+		match self {
+			Self::Perspective(p) => p.set_aspect(aspect),
+			Self::Orthographic(o) => {
+				let center = (o.left() + o.right()) / 2.;
+				let half_width = (o.top() - o.bottom()) * aspect / 2.;
+				o.set_left_and_right(center - half_width, center + half_width);
+			}
+		}
+	}
+}
+impl From<Perspective3<f32>> for ProjectionKind {
+	fn from(p: Perspective3<f32>) -> Self {
+		Self::Perspective(p)
+	}
+}
+impl From<Orthographic3<f32>> for ProjectionKind {
+	fn from(o: Orthographic3<f32>) -> Self {
+		Self::Orthographic(o)
+	}
+}
+
+/// The volume of world space visible through a projection, bounded by six
+/// planes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+	/// Left, right, bottom, top, near and far. Each is `(a, b, c, d)` such that
+	/// points with `a * x + b * y + c * z + d >= 0` are on the inner side. The
+	/// normals aren't normalized.
+	pub planes: [Vector4<f32>; 6],
+}
+impl Frustum {
+	/// Extracts the planes of `proj_view`, which transforms world space into
+	/// wgpu's clip space, with depth from 0 to 1 (Gribb and Hartmann's method).
+	pub fn from_matrix(proj_view: &Matrix4<f32>) -> Self {
+		let row = |i: usize| proj_view.row(i).transpose();
+		let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+		Self {
+			planes: [w + x, w - x, w + y, w - y, z, w - z],
+		}
+	}
+
+	/// Whether `point` is inside the frustum, or on its boundary.
+	pub fn contains(&self, point: &Point3<f32>) -> bool {
+		let p = point.to_homogeneous();
+		self.planes.iter().all(|plane| plane.dot(&p) >= 0.)
+	}
+
+	/// The 8 corners, where three planes meet. Corner `i` is on the right plane if
+	/// bit 0 of `i` is set, on the top one if bit 1 is set and on the far one if
+	/// bit 2 is set, and on the opposite planes otherwise.
+	pub fn corners(&self) -> [Point3<f32>; 8] {
+		std::array::from_fn(|i| {
+			let [a, b, c] = [i & 1, 2 + ((i >> 1) & 1), 4 + ((i >> 2) & 1)]
+				.map(|plane| self.planes[plane]);
+			let (n1, n2, n3) = (a.xyz(), b.xyz(), c.xyz());
+			let point =
+				-(n2.cross(&n3) * a.w + n3.cross(&n1) * b.w + n1.cross(&n2) * c.w)
+					/ n1.dot(&n2.cross(&n3));
+			point.into()
+		})
+	}
+}
+
+/// A half-line in world space, such as what is under the mouse cursor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+	pub origin: Point3<f32>,
+	/// Not necessarily normalized. Distances along the ray are in its units.
+	pub direction: Vector3<f32>,
+}
+impl Ray {
+	pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+		Self { origin, direction }
+	}
 
+	/// The point `t` directions from the origin.
+	pub fn at(&self, t: f32) -> Point3<f32> {
+		self.origin + self.direction * t
+	}
+}
+
+/// A camera `RenderState` can draw from, so that it can switch between kinds of
+/// camera.
+pub trait CameraLike {
+	/// Transforms world space into view space.
+	fn view(&self) -> IsometryMatrix3<f32>;
+
+	fn projection(&self) -> &ProjectionKind;
+
+	/// The projection, after `view` instead of the camera's own.
+	fn proj_view_from(&self, view: &IsometryMatrix3<f32>) -> Matrix4<f32>;
+
+	fn proj_view(&self) -> Matrix4<f32> {
+		self.proj_view_from(&self.view())
+	}
+
+	/// The volume of world space the camera sees.
+	fn frustum(&self) -> Frustum {
+		Frustum::from_matrix(&self.proj_view())
+	}
+
+	/// The ray through the pixel at `screen_pos` of a `viewport` the camera draws
+	/// into, both in pixels with the origin in the top left corner. It starts on
+	/// the near plane, and its direction is normalized.
+	fn ray_from_screen(&self, screen_pos: (f32, f32), viewport: (u32, u32)) -> Ray {
+		let x = 2. * screen_pos.0 / viewport.0 as f32 - 1.;
+		// NDC's y goes up, while the screen's goes down.
+		let y = 1. - 2. * screen_pos.1 / viewport.1 as f32;
+		let inverse = self
+			.proj_view()
+			.try_inverse()
+			.expect("Projections are invertible");
+		let near = inverse.transform_point(&Point3::new(x, y, 0.));
+		let far = inverse.transform_point(&Point3::new(x, y, 1.));
+		Ray::new(near, (far - near).normalize())
+	}
+
+	/// Applies this frame's mouse input. Called once per frame, before any
+	/// [`Self::step`].
+	fn update(&mut self, input: &WinitInputHelper);
+
+	/// Moves the camera for a simulation tick of `dt` seconds. Does nothing by
+	/// default.
+	fn step(&mut self, _input: &WinitInputHelper, _dt: f32) {}
+
+	/// Called when the width / height ratio of the frame changes.
+	fn on_resize(&mut self, aspect: f32);
+
+	/// A fly [`Camera`] with the same view and projection, to save the pose of
+	/// the camera. `None` by default.
+	fn to_camera(&self) -> Option<Camera> {
+		None
+	}
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
+	/// Derived from `position`, `yaw` and `pitch` whenever the camera moves.
 	pub view: IsometryMatrix3<f32>,
-	pub proj: Perspective3<f32>,
+	pub proj: ProjectionKind,
+	pub position: Point3<f32>,
+	/// Rotation about the world's up axis, in radians. At 0 the camera looks
+	/// down -z, and positive values turn left.
+	pub yaw: f32,
+	/// Rotation above the horizon, in radians. Clamped to [`Self::MAX_PITCH`].
+	pub pitch: f32,
+	/// Units per second.
 	pub speed: f32,
+	/// Radians per pixel of mouse movement, passed to [`Self::handle_mouse`] by
+	/// [`CameraLike::update`].
+	pub sensitivity: f32,
+	/// The keys moving the camera.
+	pub keymap: CameraKeymap,
 }
 impl Camera {
+	/// Looking straight up or down would flip the camera over.
+	pub const MAX_PITCH: f32 = 89.0 / 180.0 * std::f32::consts::PI;
+
+	pub fn new(
+		position: Point3<f32>,
+		yaw: f32,
+		pitch: f32,
+		proj: impl Into<ProjectionKind>,
+	) -> Self {
+		let mut result = Self {
+			view: IsometryMatrix3::identity(),
+			proj: proj.into(),
+			position,
+			yaw,
+			pitch: pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH),
+			speed: 12.,
+			sensitivity: 0.003,
+			keymap: CameraKeymap::default(),
+		};
+		result.update_view();
+		result
+	}
+
+	/// Creates a camera at the origin looking down -z, with an orthographic
+	/// projection of the given box in view space.
+	pub fn new_orthographic(
+		left: f32,
+		right: f32,
+		bottom: f32,
+		top: f32,
+		near: f32,
+		far: f32,
+	) -> Self {
+		let proj = Orthographic3::new(left, right, bottom, top, near, far);
+		Self::new(Point3::origin(), 0., 0., proj)
+	}
+
 	/// # Arguments
 	/// - `cam_t`: The isometry of the camera, with respect to world
 	pub fn proj_view(&self) -> Matrix4<f32> {
-		OPENGL_TO_WGPU_M * self.proj.as_matrix() * self.view.to_matrix()
+		self.proj_view_from(&self.view)
 	}
 
-	pub fn update(&mut self, input: &WinitInputHelper) {
-		use VirtualKeyCode as K;
-		let z = if input.key_held(K::W) {
-			self.speed
-		} else if input.key_held(K::S) {
-			-self.speed
+	/// Like [`Self::proj_view`], but with `view` instead of the camera's own.
+	pub fn proj_view_from(&self, view: &IsometryMatrix3<f32>) -> Matrix4<f32> {
+		OPENGL_TO_WGPU_M * self.proj.as_matrix() * view.to_matrix()
+	}
+
+	/// Sets the width / height ratio of the projection, keeping its vertical
+	/// field of view and clipping planes.
+	pub fn set_aspect(&mut self, aspect: f32) {
+		self.proj.set_aspect(aspect);
+	}
+
+	/// The orientation of the camera with respect to world.
+	pub fn rotation(&self) -> Rotation3<f32> {
+		Rotation3::from_axis_angle(&Vector3::y_axis(), self.yaw)
+			* Rotation3::from_axis_angle(&Vector3::x_axis(), self.pitch)
+	}
+
+	/// Moves the camera to `position`, facing `target`. Keeps the pitch within
+	/// [`Self::MAX_PITCH`], and the yaw if `target` is at `position`.
+	pub fn look_at(&mut self, position: Point3<f32>, target: Point3<f32>) {
+		self.position = position;
+		let direction = target - position;
+		let length = direction.norm();
+		if length > 0. {
+			self.yaw = (-direction.x).atan2(-direction.z);
+			self.pitch = (direction.y / length)
+				.asin()
+				.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+		}
+		self.update_view();
+	}
+
+	fn update_view(&mut self) {
+		let cam_t = IsometryMatrix3::from_parts(self.position.into(), self.rotation());
+		self.view = cam_t.inverse();
+	}
+
+	/// Rotates the camera by the mouse movement while the left mouse button is
+	/// held, `mouse_sensitivity` radians per pixel, and pans it across the view
+	/// while the middle one is. The mouse moves nothing otherwise, so that clicks
+	/// meant for UI don't. Call this once per frame, as mouse movement isn't tied
+	/// to ticks.
+	pub fn handle_mouse(&mut self, input: &WinitInputHelper, mouse_sensitivity: f32) {
+		let (dx, dy) = input.mouse_diff();
+		if (dx, dy) == (0., 0.) {
+			return;
+		}
+		if input.mouse_held(LEFT_MOUSE) {
+			self.yaw -= dx * mouse_sensitivity;
+			self.pitch = (self.pitch - dy * mouse_sensitivity)
+				.clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+		} else if input.mouse_held(MIDDLE_MOUSE) {
+			// Drags the view along with the mouse, faster for faster cameras.
+			let scale = self.speed * mouse_sensitivity;
+			self.position += self.rotation() * vector![-dx * scale, dy * scale, 0.];
+		} else {
+			return;
+		}
+		self.update_view();
+	}
+
+	/// Moves the camera for a step of `dt` seconds, relative to where it faces.
+	pub fn update(&mut self, input: &WinitInputHelper, dt: f32) {
+		let keys = &self.keymap;
+		let speed = self.speed * dt;
+		let z = if input.key_held(keys.forward) {
+			-speed
+		} else if input.key_held(keys.backward) {
+			speed
 		} else {
 			0.0
 		};
-		let x = if input.key_held(K::A) {
-			self.speed
-		} else if input.key_held(K::D) {
-			-self.speed
+		let x = if input.key_held(keys.strafe_left) {
+			-speed
+		} else if input.key_held(keys.strafe_right) {
+			speed
 		} else {
 			0.0
 		};
-		let y = if input.key_held(K::Q) {
-			self.speed
-		} else if input.key_held(K::E) {
-			-self.speed
+		let y = if input.key_held(keys.down) {
+			-speed
+		} else if input.key_held(keys.up) {
+			speed
 		} else {
 			0.0
 		};
-		self.view = Translation3::new(x, y, z) * self.view;
+		if (x, y, z) == (0., 0., 0.) {
+			return;
+		}
+		self.position += self.rotation() * vector![x, y, z];
+		self.update_view();
+	}
+}
+impl CameraLike for Camera {
+	fn view(&self) -> IsometryMatrix3<f32> {
+		self.view
+	}
+
+	fn projection(&self) -> &ProjectionKind {
+		&self.proj
+	}
+
+	fn proj_view_from(&self, view: &IsometryMatrix3<f32>) -> Matrix4<f32> {
+		Camera::proj_view_from(self, view)
+	}
+
+	fn update(&mut self, input: &WinitInputHelper) {
+		self.handle_mouse(input, self.sensitivity);
+	}
+
+	fn step(&mut self, input: &WinitInputHelper, dt: f32) {
+		Camera::update(self, input, dt);
+	}
+
+	fn on_resize(&mut self, aspect: f32) {
+		self.set_aspect(aspect);
+	}
+
+	fn to_camera(&self) -> Option<Camera> {
+		Some(self.clone())
+	}
+}
+
+/// The keys moving a [`Camera`], relative to where it faces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraKeymap {
+	pub forward: VirtualKeyCode,
+	pub backward: VirtualKeyCode,
+	pub strafe_left: VirtualKeyCode,
+	pub strafe_right: VirtualKeyCode,
+	pub up: VirtualKeyCode,
+	pub down: VirtualKeyCode,
+}
+impl Default for CameraKeymap {
+	/// WASD to move, E to go up and Q to go down.
+	fn default() -> Self {
+		use VirtualKeyCode as K;
+		Self {
+			forward: K::W,
+			backward: K::S,
+			strafe_left: K::A,
+			strafe_right: K::D,
+			up: K::E,
+			down: K::Q,
+		}
+	}
+}
+impl CameraKeymap {
+	/// The arrow keys to move, page up to go up and page down to go down.
+	pub fn arrow_keys() -> Self {
+		use VirtualKeyCode as K;
+		Self {
+			forward: K::Up,
+			backward: K::Down,
+			strafe_left: K::Left,
+			strafe_right: K::Right,
+			up: K::PageUp,
+			down: K::PageDown,
+		}
+	}
+}
+
+/// A camera circling `focus`, controlled with the mouse. Dragging with the left
+/// button orbits, dragging with the middle button pans and scrolling zooms.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrbitCamera {
+	/// The point the camera looks at.
+	pub focus: Point3<f32>,
+	/// Distance from `focus`. Clamped to [`Self::MIN_RADIUS`] and
+	/// [`Self::MAX_RADIUS`].
+	pub radius: f32,
+	/// Rotation about the world's up axis, in radians. At 0 the camera is on the
+	/// +z side of `focus`, and positive values orbit to the right.
+	pub yaw: f32,
+	/// Rotation above the horizon of the view direction, in radians, like
+	/// [`Camera::pitch`]. Negative values look down on `focus` from above.
+	pub pitch: f32,
+	pub proj: ProjectionKind,
+	/// Radians per pixel of mouse movement.
+	pub sensitivity: f32,
+}
+impl OrbitCamera {
+	pub const MIN_RADIUS: f32 = 0.1;
+	pub const MAX_RADIUS: f32 = 100.;
+	/// How much one step of the scroll wheel scales the radius.
+	const ZOOM_FACTOR: f32 = 0.9;
+
+	pub fn new(
+		focus: Point3<f32>,
+		radius: f32,
+		yaw: f32,
+		pitch: f32,
+		proj: impl Into<ProjectionKind>,
+	) -> Self {
+		Self {
+			focus,
+			radius: radius.clamp(Self::MIN_RADIUS, Self::MAX_RADIUS),
+			yaw,
+			pitch: pitch.clamp(-Camera::MAX_PITCH, Camera::MAX_PITCH),
+			proj: proj.into(),
+			sensitivity: 0.005,
+		}
+	}
+
+	/// The orientation of the camera with respect to world.
+	pub fn rotation(&self) -> Rotation3<f32> {
+		Rotation3::from_axis_angle(&Vector3::y_axis(), self.yaw)
+			* Rotation3::from_axis_angle(&Vector3::x_axis(), self.pitch)
+	}
+
+	/// Where the camera is, `radius` behind `focus`.
+	pub fn position(&self) -> Point3<f32> {
+		self.focus + self.rotation() * vector![0., 0., self.radius]
+	}
+
+	/// Zooms with the scroll wheel, orbits while the left mouse button is held and
+	/// pans while the middle one is.
+	pub fn update(&mut self, input: &WinitInputHelper) {
+		let scroll = input.scroll_diff();
+		if scroll != 0. {
+			self.radius = (self.radius * Self::ZOOM_FACTOR.powf(scroll))
+				.clamp(Self::MIN_RADIUS, Self::MAX_RADIUS);
+		}
+		let (dx, dy) = input.mouse_diff();
+		if (dx, dy) == (0., 0.) {
+			return;
+		}
+		if input.mouse_held(LEFT_MOUSE) {
+			self.yaw -= dx * self.sensitivity;
+			self.pitch = (self.pitch - dy * self.sensitivity)
+				.clamp(-Camera::MAX_PITCH, Camera::MAX_PITCH);
+		} else if input.mouse_held(MIDDLE_MOUSE) {
+			// Drags the focus along with the mouse, faster when further away.
+			let scale = self.radius * self.sensitivity * 0.2;
+			self.focus += self.rotation() * vector![-dx * scale, dy * scale, 0.];
+		}
+	}
+}
+impl CameraLike for OrbitCamera {
+	fn view(&self) -> IsometryMatrix3<f32> {
+		IsometryMatrix3::from_parts(self.position().into(), self.rotation()).inverse()
+	}
+
+	fn projection(&self) -> &ProjectionKind {
+		&self.proj
+	}
+
+	fn proj_view_from(&self, view: &IsometryMatrix3<f32>) -> Matrix4<f32> {
+		OPENGL_TO_WGPU_M * self.proj.as_matrix() * view.to_matrix()
+	}
+
+	fn update(&mut self, input: &WinitInputHelper) {
+		OrbitCamera::update(self, input);
+	}
+
+	fn on_resize(&mut self, aspect: f32) {
+		self.proj.set_aspect(aspect);
+	}
+
+	/// Both cameras turn by yaw and then pitch, so only the position differs.
+	fn to_camera(&self) -> Option<Camera> {
+		Some(Camera::new(
+			self.position(),
+			self.yaw,
+			self.pitch,
+			self.proj,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts::FRAC_PI_2;
+
+	fn assert_near(a: Vector3<f32>, b: Vector3<f32>) {
+		assert!((a - b).norm() < 1e-5, "{a:?} != {b:?}");
+	}
+
+	#[test]
+	fn quarter_turn_of_yaw_faces_negative_x() {
+		let proj = Perspective3::new(1., FRAC_PI_2, 0.1, 100.);
+		let mut camera = Camera::new(Point3::origin(), 0., 0., proj);
+		assert_near(camera.rotation() * -Vector3::z(), -Vector3::z());
+
+		camera.yaw += FRAC_PI_2;
+		camera.update_view();
+		assert_near(camera.rotation() * -Vector3::z(), -Vector3::x());
+		// A point along -x is now straight ahead, in the middle of the screen.
+		let ahead = camera.view.transform_point(&Point3::new(-5., 0., 0.));
+		assert_near(ahead.coords, vector![0., 0., -5.]);
+		let ndc = camera
+			.proj_view()
+			.transform_point(&Point3::new(-5., 0., 0.));
+		assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5);
+	}
+
+	#[test]
+	fn orthographic_corners_map_to_ndc_corners() {
+		let camera = Camera::new_orthographic(-4., 4., -2., 2., 1., 10.);
+		let proj_view = camera.proj_view();
+		for (x, ndc_x) in [(-4., -1.), (4., 1.)] {
+			for (y, ndc_y) in [(-2., -1.), (2., 1.)] {
+				// The camera looks down -z, and WebGPU's depth goes from 0 to 1.
+				for (z, ndc_z) in [(-1., 0.), (-10., 1.)] {
+					let ndc = proj_view.transform_point(&Point3::new(x, y, z));
+					assert_near(ndc.coords, vector![ndc_x, ndc_y, ndc_z]);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn set_aspect_changes_projection() {
+		let proj = Perspective3::new(800. / 600., FRAC_PI_2, 0.1, 100.);
+		let mut camera = Camera::new(Point3::origin(), 0., 0., proj);
+		let before = camera.proj_view();
+		camera.set_aspect(1600. / 600.);
+		let after = camera.proj_view();
+		assert_ne!(before, after);
+		// Twice as wide, with the same vertical field of view.
+		assert!((after[(0, 0)] - before[(0, 0)] / 2.).abs() < 1e-5);
+		assert_eq!(after[(1, 1)], before[(1, 1)]);
+	}
+
+	#[test]
+	fn remapped_keys_move_camera() {
+		use crate::event_replay::{EventReplayer, RecordedFrame};
+
+		let proj = Perspective3::new(1., FRAC_PI_2, 0.1, 100.);
+		let mut camera = Camera::new(Point3::origin(), 0., 0., proj);
+		camera.keymap = CameraKeymap::arrow_keys();
+		let frame = |key| RecordedFrame {
+			key_held: vec![key],
+			mouse_delta: (0., 0.),
+			scroll: 0.,
+			dt: 0.5,
+		};
+		let mut replayer = EventReplayer::from_frames(vec![
+			frame(VirtualKeyCode::W),
+			frame(VirtualKeyCode::Up),
+		]);
+
+		// The default forward key no longer does anything.
+		let (input, dt) = replayer.step().unwrap();
+		camera.update(input, dt);
+		assert_eq!(camera.position, Point3::origin());
+		// Forward is down -z, at `speed` units per second.
+		let (input, dt) = replayer.step().unwrap();
+		camera.update(input, dt);
+		assert_near(camera.position.coords, vector![0., 0., -camera.speed * dt]);
 	}
 }
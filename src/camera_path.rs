@@ -0,0 +1,213 @@
+//! Flying a camera along a path through waypoints, for fly-throughs and
+//! cutscenes.
+
+use nalgebra::{IsometryMatrix3, Matrix4, Point3};
+use winit_input_helper::WinitInputHelper;
+
+use crate::camera::{Camera, CameraLike, ProjectionKind};
+
+/// Where a [`CameraPath`] passes at a time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraWaypoint {
+	/// In seconds.
+	pub time: f32,
+	pub position: Point3<f32>,
+	/// What the camera faces.
+	pub target: Point3<f32>,
+	/// The vertical field of view, in radians.
+	pub fov: f32,
+}
+
+/// A smooth path through waypoints: positions and targets follow Catmull-Rom
+/// splines, and the field of view changes linearly between waypoints.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraPath {
+	/// In order of time.
+	pub waypoints: Vec<CameraWaypoint>,
+}
+impl CameraPath {
+	pub fn new(waypoints: Vec<CameraWaypoint>) -> Self {
+		Self { waypoints }
+	}
+
+	/// The time of the last waypoint, in seconds, or 0 without waypoints.
+	pub fn duration(&self) -> f32 {
+		self.waypoints.last().map_or(0., |w| w.time)
+	}
+
+	/// The position, target and field of view at `t` seconds. They are those of
+	/// the first and last waypoints before and after the path.
+	///
+	/// # Panics
+	/// If there are no waypoints.
+	pub fn sample(&self, t: f32) -> (Point3<f32>, Point3<f32>, f32) {
+		assert!(!self.waypoints.is_empty(), "Camera paths need a waypoint");
+		let w = &self.waypoints;
+		let next = w.partition_point(|w| w.time <= t);
+		if next == 0 {
+			return (w[0].position, w[0].target, w[0].fov);
+		}
+		if next == w.len() {
+			let last = &w[next - 1];
+			return (last.position, last.target, last.fov);
+		}
+		let (i, j) = (next - 1, next);
+		// The ends of the path are repeated for the splines' outer points.
+		let (before, after) = (i.saturating_sub(1), (j + 1).min(w.len() - 1));
+		let s = (t - w[i].time) / (w[j].time - w[i].time);
+		let spline = |p: fn(&CameraWaypoint) -> Point3<f32>| {
+			catmull_rom(p(&w[before]), p(&w[i]), p(&w[j]), p(&w[after]), s)
+		};
+		(
+			spline(|w| w.position),
+			spline(|w| w.target),
+			w[i].fov + (w[j].fov - w[i].fov) * s,
+		)
+	}
+}
+
+/// The point at `s` from 0 to 1 on the Catmull-Rom spline between `p1` and
+/// `p2`.
+fn catmull_rom(
+	p0: Point3<f32>,
+	p1: Point3<f32>,
+	p2: Point3<f32>,
+	p3: Point3<f32>,
+	s: f32,
+) -> Point3<f32> {
+	let (s2, s3) = (s * s, s * s * s);
+	let v = p1.coords * 2.
+		+ (p2.coords - p0.coords) * s
+		+ (p0.coords * 2. - p1.coords * 5. + p2.coords * 4. - p3.coords) * s2
+		+ (p1.coords * 3. - p0.coords - p2.coords * 3. + p3.coords) * s3;
+	(v * 0.5).into()
+}
+
+/// Moves a [`Camera`] along a [`CameraPath`] as time passes. As a
+/// [`CameraLike`], it ignores input and advances every simulation tick.
+#[derive(Clone, Debug)]
+pub struct CameraPathPlayer {
+	pub path: CameraPath,
+	/// In seconds, from the start of the path.
+	pub elapsed: f32,
+	/// Whether the path starts over once it ends, or holds its last pose.
+	pub looping: bool,
+	camera: Camera,
+}
+impl CameraPathPlayer {
+	/// A player at the start of `path`, with a camera of projection `proj`. Only
+	/// perspective projections follow the field of view of the path.
+	///
+	/// # Panics
+	/// If the path has no waypoints.
+	pub fn new(path: CameraPath, proj: impl Into<ProjectionKind>) -> Self {
+		let first = path
+			.waypoints
+			.first()
+			.expect("Camera paths need a waypoint");
+		let camera = Camera::new(first.position, 0., 0., proj);
+		let mut result = Self {
+			path,
+			elapsed: 0.,
+			looping: false,
+			camera,
+		};
+		result.apply();
+		result
+	}
+
+	/// Moves along the path by `dt` seconds, and returns the camera there.
+	pub fn advance(&mut self, dt: f32) -> &Camera {
+		self.elapsed += dt;
+		let duration = self.path.duration();
+		if self.looping && duration > 0. {
+			self.elapsed = self.elapsed.rem_euclid(duration);
+		}
+		self.apply();
+		&self.camera
+	}
+
+	/// The camera at the current point of the path.
+	pub fn camera(&self) -> &Camera {
+		&self.camera
+	}
+
+	/// Whether the player is past the end of the path. Never true when looping.
+	pub fn finished(&self) -> bool {
+		!self.looping && self.elapsed >= self.path.duration()
+	}
+
+	/// Moves the camera to where the path is at `elapsed`.
+	fn apply(&mut self) {
+		let (position, target, fov) = self.path.sample(self.elapsed);
+		if let ProjectionKind::Perspective(p) = &mut self.camera.proj {
+			p.set_fovy(fov);
+		}
+		self.camera.look_at(position, target);
+	}
+}
+impl CameraLike for CameraPathPlayer {
+	fn view(&self) -> IsometryMatrix3<f32> {
+		self.camera.view
+	}
+
+	fn projection(&self) -> &ProjectionKind {
+		&self.camera.proj
+	}
+
+	fn proj_view_from(&self, view: &IsometryMatrix3<f32>) -> Matrix4<f32> {
+		self.camera.proj_view_from(view)
+	}
+
+	fn update(&mut self, _input: &WinitInputHelper) {}
+
+	fn step(&mut self, _input: &WinitInputHelper, dt: f32) {
+		self.advance(dt);
+	}
+
+	fn on_resize(&mut self, aspect: f32) {
+		self.camera.set_aspect(aspect);
+	}
+
+	fn to_camera(&self) -> Option<Camera> {
+		Some(self.camera.clone())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::point;
+
+	fn waypoint(time: f32, x: f32, fov: f32) -> CameraWaypoint {
+		CameraWaypoint {
+			time,
+			position: point![x, 1., 0.],
+			target: point![x, 0., -1.],
+			fov,
+		}
+	}
+
+	#[test]
+	fn passes_through_the_waypoints() {
+		let path = CameraPath::new(vec![
+			waypoint(0., 0., 1.),
+			waypoint(1., 2., 0.5),
+			waypoint(2., 4., 1.),
+			waypoint(3., 6., 1.),
+		]);
+		for w in &path.waypoints {
+			assert_eq!(path.sample(w.time), (w.position, w.target, w.fov));
+		}
+		let (first, last) = (path.waypoints[0], path.waypoints[3]);
+		assert_eq!(path.sample(-1.), (first.position, first.target, first.fov));
+		assert_eq!(path.sample(5.), (last.position, last.target, last.fov));
+
+		// Evenly spaced waypoints are followed in a straight line.
+		let (position, _, fov) = path.sample(1.5);
+		assert!((position - point![3., 1., 0.]).norm() < 1e-5, "{position}");
+		assert_eq!(fov, 0.75);
+	}
+}
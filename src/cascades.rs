@@ -0,0 +1,401 @@
+//! Cascaded shadow maps: the directional light's shadows in a map per slice of
+//! the camera's view, so that near shadows get as many texels as far ones.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{Camera, CameraLike, CameraUniform};
+use crate::shadow::{light_projection, DepthPass, ShadowMap};
+use crate::types::mat4_to_wgsl;
+
+/// How many slices the camera's view is split into.
+pub const N_CASCADES: usize = 4;
+
+/// The layout of `shader.wgsl`'s `CascadeUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct CascadeUniform {
+	view_proj: [[[f32; 4]; 4]; N_CASCADES],
+	/// The view depth each cascade ends at.
+	splits: [f32; N_CASCADES],
+	/// The direction the camera looks in, to measure view depths with.
+	forward: [f32; 3],
+	/// 0 when the cascades are disabled.
+	count: u32,
+	blend: f32,
+	/// How far apart the shadow samples of each fragment are, in texels. Applies
+	/// to the single shadow map too.
+	pcf_radius: f32,
+	_pad: [f32; 2],
+}
+
+/// [`N_CASCADES`] depth maps of the scene as seen by the directional light, each
+/// covering a slice of the camera's view further away than the last. The
+/// fragment shader samples the cascade of each fragment's view depth, and blends
+/// between neighbouring cascades where they meet.
+///
+/// Disabled until [`Self::set_enabled`], in which case `shader.wgsl` samples the
+/// single [`ShadowMap`] instead.
+pub struct CascadedShadowMap {
+	texture: wgpu::Texture,
+	/// All cascades, as the layers of an array.
+	pub view: wgpu::TextureView,
+	/// The layer of each cascade, to draw into.
+	layer_views: Vec<wgpu::TextureView>,
+	/// The cascades' matrices and splits, as a `CascadeUniform`.
+	pub uniform_buf: wgpu::Buffer,
+	/// The camera uniform each cascade is drawn with, and the bind group of it.
+	cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+	/// Transforms world space into each cascade's clip space.
+	light_view_projs: [Matrix4<f32>; N_CASCADES],
+	splits: [f32; N_CASCADES + 1],
+	/// Width and height of each cascade, in texels.
+	size: u32,
+	lambda: f32,
+	blend: f32,
+	pcf_radius: f32,
+	enabled: bool,
+	depth_pass: DepthPass,
+}
+impl CascadedShadowMap {
+	pub const DEFAULT_LAMBDA: f32 = 0.5;
+	/// How far beyond its slice of the view, towards the light, a cascade
+	/// captures shadow casters.
+	pub const CASTER_DISTANCE: f32 = 20.;
+	/// Spreads the shadow samples over about a 3×3 block of texels.
+	pub const DEFAULT_PCF_RADIUS: f32 = 1.5;
+
+	/// Creates the cascades with `size`×`size` texels each, drawn like
+	/// [`ShadowMap::new`].
+	pub fn new(
+		device: &wgpu::Device,
+		size: u32,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+	) -> Self {
+		let (texture, view, layer_views) = create_texture(device, size);
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Cascade Uniform"),
+				contents: bytemuck::bytes_of(&disabled_uniform(
+					Self::DEFAULT_PCF_RADIUS,
+				)),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let cameras = (0..N_CASCADES)
+			.map(|_| {
+				let buffer =
+					device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+						label: Some("Cascade Camera Uniform"),
+						contents: bytemuck::bytes_of(&CameraUniform::new(
+							Matrix4::identity(),
+							Point3::origin(),
+						)),
+						usage: wgpu::BufferUsages::UNIFORM
+							| wgpu::BufferUsages::COPY_DST,
+					});
+				let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+					label: Some("cascade_camera_bind_group"),
+					layout: camera_layout,
+					entries: &[wgpu::BindGroupEntry {
+						binding: 0,
+						resource: buffer.as_entire_binding(),
+					}],
+				});
+				(buffer, bind_group)
+			})
+			.collect();
+		let depth_pass = DepthPass::new(
+			device,
+			shader,
+			camera_layout,
+			object_layout,
+			push_constant_ranges,
+		);
+		Self {
+			texture,
+			view,
+			layer_views,
+			uniform_buf,
+			cameras,
+			light_view_projs: [Matrix4::identity(); N_CASCADES],
+			splits: [0.; N_CASCADES + 1],
+			size,
+			lambda: Self::DEFAULT_LAMBDA,
+			blend: 0.1,
+			pcf_radius: Self::DEFAULT_PCF_RADIUS,
+			enabled: false,
+			depth_pass,
+		}
+	}
+
+	/// The view depths the cascades of `camera` start and end at, from its near
+	/// plane to its far plane, with the "practical" split scheme: `lambda` blends
+	/// between logarithmic splits at 1, which give each cascade the same texel
+	/// density on screen, and uniform splits at 0.
+	pub fn compute_splits(camera: &Camera, lambda: f32) -> [f32; N_CASCADES + 1] {
+		splits_between(camera.proj.znear(), camera.proj.zfar(), lambda)
+	}
+
+	/// The view depths the cascades start and end at, as of the last
+	/// [`Self::update`].
+	pub fn splits(&self) -> &[f32; N_CASCADES + 1] {
+		&self.splits
+	}
+
+	/// Transforms world space into the clip space of the cascade at `index`.
+	pub fn light_view_proj(&self, index: usize) -> &Matrix4<f32> {
+		&self.light_view_projs[index]
+	}
+
+	pub fn lambda(&self) -> f32 {
+		self.lambda
+	}
+
+	/// See [`Self::compute_splits`]. Applies from the next [`Self::update`].
+	pub fn set_lambda(&mut self, lambda: f32) {
+		self.lambda = lambda.clamp(0., 1.);
+	}
+
+	/// Sets the share of each cascade's depth range, at its far end, that fades
+	/// into the next cascade. Applies from the next [`Self::update`].
+	pub fn set_blend(&mut self, blend: f32) {
+		self.blend = blend.clamp(0., 1.);
+	}
+
+	pub fn pcf_radius(&self) -> f32 {
+		self.pcf_radius
+	}
+
+	/// Sets how far apart, in texels, the fragment shader samples the shadows
+	/// around each fragment, either in the cascades or the single shadow map.
+	/// Larger radii give softer edges. Applies from the next [`Self::update`],
+	/// or immediately while disabled. Returns the number of bytes uploaded.
+	pub fn set_pcf_radius(&mut self, queue: &wgpu::Queue, radius: f32) -> u64 {
+		self.pcf_radius = radius.max(0.);
+		if self.enabled {
+			return 0;
+		}
+		self.upload_disabled(queue)
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// Switches `shader.wgsl` between the cascades and the single shadow map.
+	/// Applies from the next [`Self::update`], or immediately when disabling.
+	/// Returns the number of bytes uploaded.
+	pub fn set_enabled(&mut self, queue: &wgpu::Queue, enabled: bool) -> u64 {
+		self.enabled = enabled;
+		if enabled {
+			return 0;
+		}
+		self.upload_disabled(queue)
+	}
+
+	fn upload_disabled(&self, queue: &wgpu::Queue) -> u64 {
+		let uniform = disabled_uniform(self.pcf_radius);
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of_val(&uniform) as u64
+	}
+
+	/// Recreates the cascades if their size changed. Returns whether they were,
+	/// in which case bind groups sampling them must be recreated too.
+	pub fn set_size(&mut self, device: &wgpu::Device, size: u32) -> bool {
+		if size == self.size {
+			return false;
+		}
+		(self.texture, self.view, self.layer_views) = create_texture(device, size);
+		self.size = size;
+		true
+	}
+
+	/// Recreates the pipeline with a new version of `shader.wgsl`.
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		self.depth_pass.set_shader(device, shader);
+	}
+
+	/// Splits the view of `camera` and fits each cascade around its slice, for a
+	/// light travelling along `direction`. Returns the number of bytes uploaded.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		direction: Vector3<f32>,
+	) -> u64 {
+		let projection = camera.projection();
+		self.splits =
+			splits_between(projection.znear(), projection.zfar(), self.lambda);
+		let proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		let proj_inverse = proj.try_inverse().unwrap_or_else(Matrix4::identity);
+		let camera_to_world = camera.view().inverse();
+
+		let mut bytes = 0;
+		for (i, (buffer, _)) in self.cameras.iter().enumerate() {
+			let slice_corners = [self.splits[i], self.splits[i + 1]]
+				.into_iter()
+				.flat_map(|depth| view_corners(&proj, &proj_inverse, depth))
+				.map(|corner| camera_to_world * corner)
+				.collect::<Vec<_>>();
+			let center = Point3::from(
+				slice_corners.iter().map(|p| p.coords).sum::<Vector3<f32>>()
+					/ slice_corners.len() as f32,
+			);
+			// Rounded up, so that float errors don't resize the cascade as the
+			// camera turns.
+			let radius = slice_corners
+				.iter()
+				.map(|p| nalgebra::distance(p, &center))
+				.fold(0., f32::max);
+			let radius = (radius * 16.).ceil() / 16.;
+			let (eye, light_view_proj) =
+				light_projection(direction, center, radius, Self::CASTER_DISTANCE);
+			// Moves the cascade by less than a texel, so that its texels stay put
+			// in world space rather than shimmering as the camera moves.
+			let half_size = self.size as f32 / 2.;
+			let origin = light_view_proj.transform_point(&Point3::origin());
+			let offset = origin.coords.xy() * half_size;
+			let snap = (offset.map(f32::round) - offset) / half_size;
+			self.light_view_projs[i] =
+				Matrix4::new_translation(&Vector3::new(snap.x, snap.y, 0.))
+					* light_view_proj;
+
+			let uniform = CameraUniform::new(self.light_view_projs[i], eye);
+			queue.write_buffer(buffer, 0, bytemuck::bytes_of(&uniform));
+			bytes += std::mem::size_of_val(&uniform) as u64;
+		}
+
+		let mut splits = [0.; N_CASCADES];
+		splits.copy_from_slice(&self.splits[1..]);
+		let forward = camera_to_world * -Vector3::z();
+		let uniform = CascadeUniform {
+			view_proj: self.light_view_projs.map(mat4_to_wgsl),
+			splits,
+			forward: forward.into(),
+			count: if self.enabled { N_CASCADES as u32 } else { 0 },
+			blend: self.blend,
+			pcf_radius: self.pcf_radius,
+			_pad: [0.; 2],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		bytes + std::mem::size_of_val(&uniform) as u64
+	}
+
+	/// Records the pass drawing the cascade at `cascade_index`, in which `scene`
+	/// draws the shadow casters. The object bind group, if any, is left to
+	/// `scene`.
+	pub fn render_cascade<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+		scene: impl FnOnce(&mut wgpu::RenderPass<'a>),
+		cascade_index: usize,
+	) {
+		let (_, camera_bind_group) = &self.cameras[cascade_index];
+		let mut pass = self.depth_pass.begin(
+			"Shadow Cascade Pass",
+			encoder,
+			&self.layer_views[cascade_index],
+			camera_bind_group,
+		);
+		scene(&mut pass);
+	}
+}
+
+/// The uniform telling `shader.wgsl` to sample the single shadow map.
+fn disabled_uniform(pcf_radius: f32) -> CascadeUniform {
+	CascadeUniform {
+		pcf_radius,
+		..CascadeUniform::zeroed()
+	}
+}
+
+/// The practical split scheme between view depths `near` and `far`, see
+/// [`CascadedShadowMap::compute_splits`].
+fn splits_between(near: f32, far: f32, lambda: f32) -> [f32; N_CASCADES + 1] {
+	let mut splits = [0.; N_CASCADES + 1];
+	for (i, split) in splits.iter_mut().enumerate() {
+		let fraction = i as f32 / N_CASCADES as f32;
+		let log = near * (far / near).powf(fraction);
+		let uniform = near + (far - near) * fraction;
+		*split = lambda * log + (1. - lambda) * uniform;
+	}
+	splits
+}
+
+/// The corners of the view of `proj` at `depth`, in view space.
+fn view_corners(
+	proj: &Matrix4<f32>,
+	proj_inverse: &Matrix4<f32>,
+	depth: f32,
+) -> [Point3<f32>; 4] {
+	let clip_depth = proj.transform_point(&Point3::new(0., 0., -depth)).z;
+	[(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+		.map(|(x, y)| proj_inverse.transform_point(&Point3::new(x, y, clip_depth)))
+}
+
+fn create_texture(
+	device: &wgpu::Device,
+	size: u32,
+) -> (wgpu::Texture, wgpu::TextureView, Vec<wgpu::TextureView>) {
+	let texture = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Shadow Cascades"),
+		size: wgpu::Extent3d {
+			width: size,
+			height: size,
+			depth_or_array_layers: N_CASCADES as u32,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: ShadowMap::FORMAT,
+		usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+			| wgpu::TextureUsages::TEXTURE_BINDING,
+		view_formats: &[],
+	});
+	let view = texture.create_view(&wgpu::TextureViewDescriptor {
+		dimension: Some(wgpu::TextureViewDimension::D2Array),
+		..Default::default()
+	});
+	let layer_views = (0..N_CASCADES as u32)
+		.map(|layer| {
+			texture.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(wgpu::TextureViewDimension::D2),
+				base_array_layer: layer,
+				array_layer_count: Some(1),
+				..Default::default()
+			})
+		})
+		.collect();
+	(texture, view, layer_views)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_close(actual: [f32; N_CASCADES + 1], expected: [f32; N_CASCADES + 1]) {
+		for (a, e) in actual.iter().zip(expected) {
+			assert!((a - e).abs() <= e * 1e-4, "{actual:?} != {expected:?}");
+		}
+	}
+
+	#[test]
+	fn split_distances() {
+		assert_close(
+			splits_between(1., 10_000., 1.),
+			[1., 10., 100., 1_000., 10_000.],
+		);
+		assert_close(
+			splits_between(1., 10_001., 0.),
+			[1., 2_501., 5_001., 7_501., 10_001.],
+		);
+		let practical = splits_between(0.1, 100., CascadedShadowMap::DEFAULT_LAMBDA);
+		assert_eq!((practical[0], practical[N_CASCADES]), (0.1, 100.));
+		assert!(practical.windows(2).all(|pair| pair[0] < pair[1]));
+	}
+}
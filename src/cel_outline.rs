@@ -0,0 +1,217 @@
+//! Outlines for cel shading, drawn where the depth or normals of the frame
+//! change sharply.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::IsometryMatrix3;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::types::mat4_to_wgsl;
+
+/// The layout of the shader's `OutlineUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct OutlineUniform {
+	inv_proj: [[f32; 4]; 4],
+	color: [f32; 4],
+	far_depth: f32,
+	line_width: f32,
+	depth_threshold: f32,
+	normal_threshold: f32,
+}
+
+/// Blends outlines over the frame, from the depth and normals of a
+/// [`GBuffer`](crate::gbuffer::GBuffer).
+///
+/// A Sobel operator finds the gradients of the view space depth and of the
+/// normals around each pixel. Pixels where either is past its threshold are
+/// drawn in `color`; the others are left as they are.
+///
+/// The public fields are uploaded by [`Self::update`].
+pub struct CelOutlinePass {
+	/// The depth gradient, relative to the depth, past which there is an edge.
+	/// Lower finds more edges between overlapping surfaces.
+	pub depth_threshold: f32,
+	/// The normal gradient past which there is an edge. Lower finds more creases.
+	pub normal_threshold: f32,
+	/// How far the samples of the operator are apart, in pixels. Wider spacing
+	/// draws thicker lines.
+	pub line_width: f32,
+	/// The color of the lines, blended by its alpha.
+	pub color: [f32; 4],
+	/// The camera's projection, for `uniform_buf`.
+	camera: OutlineUniform,
+	uniform_buf: wgpu::Buffer,
+	input_layout: wgpu::BindGroupLayout,
+	reverse_z: bool,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	pipeline: wgpu::RenderPipeline,
+}
+impl CelOutlinePass {
+	/// Creates a pass drawing into `format` textures, with a depth buffer that is
+	/// optionally reversed.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Cel Outline Uniform"),
+			size: std::mem::size_of::<OutlineUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: false },
+			},
+			count: None,
+		};
+		// The textures are read with `textureLoad`, so they need no samplers.
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Cel Outline Input Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					texture_entry(1),
+					texture_entry(2),
+				],
+			});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Cel Outline Pipeline Layout"),
+			bind_group_layouts: &[&input_layout],
+			push_constant_ranges: &[],
+		});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("cel_outline.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		Self {
+			depth_threshold: 0.5,
+			normal_threshold: 1.,
+			line_width: 1.,
+			color: [0., 0., 0., 1.],
+			camera: OutlineUniform::zeroed(),
+			uniform_buf,
+			input_layout,
+			reverse_z,
+			layout,
+			shader,
+			pipeline,
+		}
+	}
+
+	/// Recreates the pipeline to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Uploads the projection of `camera`, and the public fields. Returns the
+	/// number of bytes written.
+	pub fn update(&mut self, queue: &wgpu::Queue, camera: &dyn CameraLike) -> u64 {
+		let mut proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			proj = reverse_z(&proj);
+			far_depth = 0.;
+		}
+		self.camera.inv_proj = mat4_to_wgsl(proj.try_inverse().unwrap_or_default());
+		self.camera.far_depth = far_depth;
+		let uniform = OutlineUniform {
+			color: self.color,
+			line_width: self.line_width,
+			depth_threshold: self.depth_threshold,
+			normal_threshold: self.normal_threshold,
+			..self.camera
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<OutlineUniform>() as u64
+	}
+
+	/// Records blending the outlines found in the G-buffer's `depth_view` and
+	/// `normal_view` over `output_view`.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		normal_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("cel_outline_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(normal_view),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Cel Outline Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Cel Outline Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
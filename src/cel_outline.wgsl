@@ -0,0 +1,84 @@
+// Outlines for cel shading: lines where the depth or normals of the G-buffer
+// change sharply, found with a Sobel operator and blended over the frame.
+
+struct OutlineUniform {
+	inv_proj: mat4x4<f32>,
+	color: vec4<f32>,
+	// The depth of the G-buffer where nothing was drawn.
+	far_depth: f32,
+	// How far the samples of the operator are apart, in pixels.
+	line_width: f32,
+	// The depth gradient, relative to the depth, past which there is an edge.
+	depth_threshold: f32,
+	// The normal gradient past which there is an edge.
+	normal_threshold: f32,
+};
+@group(0) @binding(0)
+var<uniform> outline: OutlineUniform;
+
+// The depth, in the red channel.
+@group(0) @binding(1)
+var depth_t: texture_2d<f32>;
+// World space normals, mapped to 0 to 1.
+@group(0) @binding(2)
+var normal_t: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+// The distance from the camera drawn at `coords`, so that depth changes the
+// same near and far. Where nothing was drawn, it is far past anything that was.
+fn view_depth(coords: vec2<i32>) -> f32 {
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth == outline.far_depth {
+		return 1e6;
+	}
+	let view = outline.inv_proj * vec4<f32>(0.0, 0.0, depth, 1.0);
+	return -view.z / view.w;
+}
+
+fn load_normal(coords: vec2<i32>) -> vec3<f32> {
+	return textureLoad(normal_t, coords, 0).xyz * 2.0 - 1.0;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let size = vec2<i32>(textureDimensions(depth_t));
+	let center = vec2<i32>(in.clip_pos.xy);
+	let step = max(i32(outline.line_width + 0.5), 1);
+	// The 3x3 neighbourhood, row by row from the top left.
+	var depths: array<f32, 9>;
+	var normals: array<vec3<f32>, 9>;
+	for (var i = 0; i < 9; i += 1) {
+		let offset = vec2<i32>(i % 3 - 1, i / 3 - 1) * step;
+		let coords = clamp(center + offset, vec2<i32>(0), size - 1);
+		depths[i] = view_depth(coords);
+		normals[i] = load_normal(coords);
+	}
+	let depth_x = depths[2] + 2.0 * depths[5] + depths[8]
+		- depths[0] - 2.0 * depths[3] - depths[6];
+	let depth_y = depths[6] + 2.0 * depths[7] + depths[8]
+		- depths[0] - 2.0 * depths[1] - depths[2];
+	let normal_x = normals[2] + 2.0 * normals[5] + normals[8]
+		- normals[0] - 2.0 * normals[3] - normals[6];
+	let normal_y = normals[6] + 2.0 * normals[7] + normals[8]
+		- normals[0] - 2.0 * normals[1] - normals[2];
+
+	let depth_edge = length(vec2<f32>(depth_x, depth_y)) / max(depths[4], 0.0001);
+	let normal_edge = sqrt(dot(normal_x, normal_x) + dot(normal_y, normal_y));
+	if depth_edge < outline.depth_threshold && normal_edge < outline.normal_threshold {
+		discard;
+	}
+	return outline.color;
+}
@@ -0,0 +1,370 @@
+//! Cloth simulated on the GPU, see `cloth.wgsl`.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, Result};
+use nalgebra::Vector3;
+use wgpu::util::DeviceExt;
+
+use crate::aabb::Aabb;
+use crate::mesh::GpuMesh;
+use crate::vertex::{Pos, Vertex};
+
+/// Keeps vertices `a` and `b` `rest_length` apart, in the layout of
+/// `cloth.wgsl`'s `DistanceConstraint`.
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct DistanceConstraint {
+	pub a: u32,
+	pub b: u32,
+	pub rest_length: f32,
+}
+
+/// The layout of `cloth.wgsl`'s `ClothUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct ClothUniform {
+	acceleration: [f32; 3],
+	dt: f32,
+	damping: f32,
+	num_vertices: u32,
+	_pad: [u32; 2],
+}
+
+/// A mesh whose vertices move like cloth, simulated by compute shaders.
+///
+/// Each [`Self::step`] moves the vertices by Verlet integration under gravity
+/// and the wind, and then pulls the ends of each edge of the triangles back to
+/// their rest length, over [`Self::iterations`] passes. Every pass reads the
+/// positions from one buffer and writes them to the other. Pinned vertices
+/// don't move.
+///
+/// The positions are then written into the vertex buffer of [`Self::mesh`],
+/// which draws like any other mesh. Its normals stay those of the rest shape.
+pub struct ClothSimulation {
+	/// The acceleration of every vertex, in world units per second squared.
+	pub gravity: [f32; 3],
+	/// The share of the velocity kept every step, below 1 to settle.
+	pub damping: f32,
+	/// How many passes pull the vertices back to their rest distances every
+	/// step. More keep the cloth stiffer.
+	pub iterations: u32,
+	mesh: Arc<GpuMesh>,
+	num_vertices: u32,
+	num_constraints: u32,
+	uniform_buf: wgpu::Buffer,
+	/// Read the positions from one buffer and write them to the other.
+	bind_groups: [wgpu::BindGroup; 2],
+	/// The index of the bind group reading the latest positions.
+	current: usize,
+	integrate_pipeline: wgpu::ComputePipeline,
+	constrain_pipeline: wgpu::ComputePipeline,
+	write_pipeline: wgpu::ComputePipeline,
+}
+impl ClothSimulation {
+	const WORKGROUP_SIZE: u32 = 64;
+
+	/// Uploads the rest shape of the cloth, the `indices` of its triangles and
+	/// the indices of the vertices that are `pinned` in place. The edges of the
+	/// triangles become its [`DistanceConstraint`]s.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If there are no vertices, or an index is out of bounds.
+	pub fn new(
+		device: &wgpu::Device,
+		vertices: &[Vertex],
+		indices: &[u32],
+		pinned: &[u32],
+	) -> Result<Self> {
+		assert!(!vertices.is_empty(), "Cloth needs vertices");
+		let num_vertices = vertices.len() as u32;
+		assert!(
+			indices.iter().chain(pinned).all(|&i| i < num_vertices),
+			"Cloth indices must be within its vertices"
+		);
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+
+		let pinned: HashSet<u32> = pinned.iter().copied().collect();
+		let positions: Vec<[f32; 4]> = (vertices.iter().enumerate())
+			.map(|(i, vertex)| {
+				let Pos { x, y, z } = vertex.pos;
+				let inverse_mass = if pinned.contains(&(i as u32)) { 0. } else { 1. };
+				[x, y, z, inverse_mass]
+			})
+			.collect();
+		let constraints = edge_constraints(vertices, indices);
+		// The constraints of each vertex, one vertex after the other.
+		let mut by_vertex = vec![Vec::new(); vertices.len()];
+		for (i, constraint) in constraints.iter().enumerate() {
+			by_vertex[constraint.a as usize].push(i as u32);
+			by_vertex[constraint.b as usize].push(i as u32);
+		}
+		let mut offsets = vec![0];
+		offsets.extend(by_vertex.iter().scan(0, |end, list| {
+			*end += list.len() as u32;
+			Some(*end)
+		}));
+		let vertex_constraints: Vec<u32> = by_vertex.concat();
+
+		// Storage buffers can't be empty.
+		let storage_init = |label, contents: &[u8]| {
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some(label),
+				contents: if contents.is_empty() {
+					&[0; 16]
+				} else {
+					contents
+				},
+				usage: wgpu::BufferUsages::STORAGE,
+			})
+		};
+		let constraints_buf =
+			storage_init("Cloth Constraints", bytemuck::cast_slice(&constraints));
+		let offsets_buf =
+			storage_init("Cloth Constraint Offsets", bytemuck::cast_slice(&offsets));
+		let vertex_constraints_buf = storage_init(
+			"Cloth Vertex Constraints",
+			bytemuck::cast_slice(&vertex_constraints),
+		);
+		let positions_bufs = ["Cloth Positions A", "Cloth Positions B"].map(|label| {
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some(label),
+				contents: bytemuck::cast_slice(&positions),
+				usage: wgpu::BufferUsages::STORAGE,
+			})
+		});
+		// At rest.
+		let previous_buf =
+			storage_init("Cloth Previous Positions", bytemuck::cast_slice(&positions));
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Cloth Uniform"),
+			size: std::mem::size_of::<ClothUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let mut mesh = GpuMesh::new(device, vertices, indices);
+		// Copied from by tests, to read the positions back.
+		mesh.vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Cloth Vertices"),
+			contents: bytemuck::cast_slice(vertices),
+			usage: wgpu::BufferUsages::VERTEX
+				| wgpu::BufferUsages::STORAGE
+				| wgpu::BufferUsages::COPY_SRC,
+		});
+		// Grown by its size, so that it bounds the cloth hanging from any side.
+		let size = mesh.aabb.max - mesh.aabb.min;
+		let reach = Vector3::repeat(size.norm());
+		mesh.aabb = Aabb::new(mesh.aabb.min - reach, mesh.aabb.max + reach);
+
+		let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+		let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Cloth Bind Group Layout"),
+				entries: &[
+					buffer_entry(0, wgpu::BufferBindingType::Uniform),
+					buffer_entry(1, read_only),
+					buffer_entry(2, read_only),
+					buffer_entry(3, read_only),
+					buffer_entry(4, read_only),
+					buffer_entry(5, read_write),
+					buffer_entry(6, read_write),
+					buffer_entry(7, read_write),
+				],
+			});
+		let bind_groups = [0, 1].map(|i| {
+			device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("cloth_bind_group"),
+				layout: &bind_group_layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: uniform_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: constraints_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: offsets_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 3,
+						resource: vertex_constraints_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 4,
+						resource: positions_bufs[i].as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 5,
+						resource: positions_bufs[1 - i].as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 6,
+						resource: previous_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 7,
+						resource: mesh.vtx_buf.as_entire_binding(),
+					},
+				],
+			})
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Cloth Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("cloth.wgsl"));
+		let create_pipeline = |entry_point| {
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some(entry_point),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point,
+			})
+		};
+
+		Ok(Self {
+			gravity: [0., -9.81, 0.],
+			damping: 0.99,
+			iterations: 6,
+			mesh: Arc::new(mesh),
+			num_vertices,
+			num_constraints: constraints.len() as u32,
+			uniform_buf,
+			bind_groups,
+			current: 0,
+			integrate_pipeline: create_pipeline("cs_integrate"),
+			constrain_pipeline: create_pipeline("cs_constrain"),
+			write_pipeline: create_pipeline("cs_write_vertices"),
+		})
+	}
+
+	/// The mesh with the simulated vertices, to draw in a [`RenderObject`]. Its
+	/// bounding box is the rest shape's grown by its size on every side.
+	///
+	/// [`RenderObject`]: crate::render_object::RenderObject
+	pub fn mesh(&self) -> Arc<GpuMesh> {
+		self.mesh.clone()
+	}
+
+	/// The number of edges kept at their rest length.
+	pub fn num_constraints(&self) -> u32 {
+		self.num_constraints
+	}
+
+	/// Records simulating `dt` seconds, with the `wind` accelerating every
+	/// vertex on top of gravity, and writing the positions into the vertex
+	/// buffer of [`Self::mesh`]. Returns the number of bytes uploaded.
+	pub fn step(
+		&mut self,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		dt: f32,
+		wind: [f32; 3],
+	) -> u64 {
+		let acceleration = Vector3::from(self.gravity) + Vector3::from(wind);
+		let uniform = ClothUniform {
+			acceleration: acceleration.into(),
+			dt,
+			damping: self.damping,
+			num_vertices: self.num_vertices,
+			_pad: [0; 2],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Cloth Pass"),
+		});
+		let workgroups = (self.num_vertices - 1) / Self::WORKGROUP_SIZE + 1;
+		pass.set_pipeline(&self.integrate_pipeline);
+		pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+		pass.dispatch_workgroups(workgroups, 1, 1);
+		self.current = 1 - self.current;
+		pass.set_pipeline(&self.constrain_pipeline);
+		for _ in 0..self.iterations {
+			pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+			pass.dispatch_workgroups(workgroups, 1, 1);
+			self.current = 1 - self.current;
+		}
+		pass.set_pipeline(&self.write_pipeline);
+		pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+		pass.dispatch_workgroups(workgroups, 1, 1);
+		std::mem::size_of::<ClothUniform>() as u64
+	}
+}
+
+/// A constraint per edge of the triangles, at its length in `vertices`.
+fn edge_constraints(vertices: &[Vertex], indices: &[u32]) -> Vec<DistanceConstraint> {
+	let mut edges = HashSet::new();
+	let mut constraints = Vec::new();
+	for triangle in indices.chunks_exact(3) {
+		for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+			let (a, b) = (triangle[i], triangle[j]);
+			if a == b || !edges.insert((a.min(b), a.max(b))) {
+				continue;
+			}
+			let (pa, pb) = (&vertices[a as usize].pos, &vertices[b as usize].pos);
+			let rest_length =
+				Vector3::new(pa.x - pb.x, pa.y - pb.y, pa.z - pb.z).norm();
+			constraints.push(DistanceConstraint { a, b, rest_length });
+		}
+	}
+	constraints
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::gpu_context::{read_buffer, test_context};
+	use crate::mesh::{QUAD_INDICES, QUAD_VERTICES};
+
+	#[test]
+	fn cloth_pinned_by_a_corner_falls() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let (device, queue) = (&context.device, &context.queue);
+		// Pinned by its top left corner.
+		let Ok(mut cloth) = ClothSimulation::new(device, QUAD_VERTICES, QUAD_INDICES, &[0])
+		else {
+			return;
+		};
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+		for _ in 0..30 {
+			cloth.step(queue, &mut encoder, 1. / 30., [0.; 3]);
+		}
+		queue.submit([encoder.finish()]);
+
+		let vertices: Vec<Vertex> =
+			bytemuck::pod_collect_to_vec(&read_buffer(&context, &cloth.mesh.vtx_buf));
+		let (pinned, rest) = (vertices[0].pos, QUAD_VERTICES[0].pos);
+		assert_eq!([pinned.x, pinned.y, pinned.z], [rest.x, rest.y, rest.z]);
+		// The bottom left corner already hangs straight below the pinned one,
+		// and the right side swings down.
+		for i in [2, 3] {
+			let (fallen, rest) = (vertices[i].pos.y, QUAD_VERTICES[i].pos.y);
+			assert!(fallen < rest - 0.1, "vertex {i} didn't fall: {fallen}");
+		}
+	}
+}
@@ -0,0 +1,104 @@
+// Simulates cloth: its vertices move by Verlet integration, and are then pulled
+// back to their rest distances from their neighbours over a few iterations.
+
+// `Vertex` in `vertex.rs`, as floats. The position comes first.
+const VERTEX_FLOATS: u32 = 14u;
+
+// Matches `DistanceConstraint` in `cloth.rs`.
+struct DistanceConstraint {
+	a: u32,
+	b: u32,
+	rest_length: f32,
+};
+
+// Matches `ClothUniform` in `cloth.rs`.
+struct ClothUniform {
+	// The acceleration of every vertex, from gravity and the wind.
+	acceleration: vec3<f32>,
+	dt: f32,
+	// The share of the velocity kept every step.
+	damping: f32,
+	num_vertices: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> cloth: ClothUniform;
+@group(0) @binding(1)
+var<storage, read> constraints: array<DistanceConstraint>;
+// The constraints of vertex `v` are those listed in `vertex_constraints` from
+// `constraint_offsets[v]` up to `constraint_offsets[v + 1]`.
+@group(0) @binding(2)
+var<storage, read> constraint_offsets: array<u32>;
+@group(0) @binding(3)
+var<storage, read> vertex_constraints: array<u32>;
+// The positions, with the inverse mass in w: 0 for pinned vertices. Each pass
+// reads one buffer and writes the other.
+@group(0) @binding(4)
+var<storage, read> positions_in: array<vec4<f32>>;
+@group(0) @binding(5)
+var<storage, read_write> positions_out: array<vec4<f32>>;
+// The positions before the last integration.
+@group(0) @binding(6)
+var<storage, read_write> previous: array<vec4<f32>>;
+// The vertex buffer drawn.
+@group(0) @binding(7)
+var<storage, read_write> vertices: array<f32>;
+
+@compute @workgroup_size(64)
+fn cs_integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+	let v = id.x;
+	if v >= cloth.num_vertices {
+		return;
+	}
+	let pos = positions_in[v];
+	var next = pos;
+	if pos.w > 0.0 {
+		let velocity = (pos.xyz - previous[v].xyz) * cloth.damping;
+		next = vec4<f32>(pos.xyz + velocity + cloth.acceleration * cloth.dt * cloth.dt, pos.w);
+	}
+	previous[v] = pos;
+	positions_out[v] = next;
+}
+
+// Moves each vertex by the average of the corrections its constraints ask for,
+// in proportion to its share of their inverse masses. Vertices only write their
+// own position, so the constraints don't race each other.
+@compute @workgroup_size(64)
+fn cs_constrain(@builtin(global_invocation_id) id: vec3<u32>) {
+	let v = id.x;
+	if v >= cloth.num_vertices {
+		return;
+	}
+	let pos = positions_in[v];
+	let first = constraint_offsets[v];
+	let count = constraint_offsets[v + 1u] - first;
+	if pos.w == 0.0 || count == 0u {
+		positions_out[v] = pos;
+		return;
+	}
+	var correction = vec3<f32>(0.0);
+	for (var i = first; i < first + count; i += 1u) {
+		let constraint = constraints[vertex_constraints[i]];
+		let other = positions_in[select(constraint.a, constraint.b, constraint.a == v)];
+		let delta = other.xyz - pos.xyz;
+		let stretched = length(delta);
+		if stretched > 0.0 {
+			let share = pos.w / (pos.w + other.w);
+			correction += delta * (stretched - constraint.rest_length) / stretched * share;
+		}
+	}
+	positions_out[v] = vec4<f32>(pos.xyz + correction / f32(count), pos.w);
+}
+
+@compute @workgroup_size(64)
+fn cs_write_vertices(@builtin(global_invocation_id) id: vec3<u32>) {
+	let v = id.x;
+	if v >= cloth.num_vertices {
+		return;
+	}
+	let pos = positions_in[v];
+	let first = v * VERTEX_FLOATS;
+	vertices[first] = pos.x;
+	vertices[first + 1u] = pos.y;
+	vertices[first + 2u] = pos.z;
+}
@@ -0,0 +1,52 @@
+//! The final adjustment of the colors the scene is shaded with, between linear
+//! output, gamma encoding and a filmic curve.
+
+use bytemuck::{Pod, Zeroable};
+
+/// In the layout of the shader's `ColorCorrectionUniform`. The default leaves
+/// colors as they are.
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct ColorCorrectionUniform {
+	/// Colors are raised to `1 / gamma` by [`Self::GAMMA`] and [`Self::ACES`].
+	/// sRGB surfaces already encode their colors, so 1 leaves them as they are.
+	pub gamma: f32,
+	/// Colors are multiplied by this first, by [`Self::GAMMA`] and
+	/// [`Self::ACES`].
+	pub exposure: f32,
+	/// One of [`Self::LINEAR`], [`Self::GAMMA`] and [`Self::ACES`].
+	pub output_mode: u32,
+}
+impl ColorCorrectionUniform {
+	/// Colors are left as they are.
+	pub const LINEAR: u32 = 0;
+	/// Colors are exposed and gamma encoded.
+	pub const GAMMA: u32 = 1;
+	/// Colors are exposed, mapped by the ACES filmic curve and gamma encoded.
+	pub const ACES: u32 = 2;
+
+	pub fn gamma(gamma: f32, exposure: f32) -> Self {
+		Self {
+			gamma,
+			exposure,
+			output_mode: Self::GAMMA,
+		}
+	}
+
+	pub fn aces(gamma: f32, exposure: f32) -> Self {
+		Self {
+			gamma,
+			exposure,
+			output_mode: Self::ACES,
+		}
+	}
+}
+impl Default for ColorCorrectionUniform {
+	fn default() -> Self {
+		Self {
+			gamma: 1.,
+			exposure: 1.,
+			output_mode: Self::LINEAR,
+		}
+	}
+}
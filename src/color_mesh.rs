@@ -0,0 +1,173 @@
+//! Untextured, unlit meshes drawn with their vertex colors, such as debug
+//! geometry.
+
+use wgpu::util::DeviceExt;
+
+use crate::vertex::ColorVertex;
+
+/// An untextured, unlit mesh of [`ColorVertex`]es, in world space.
+struct ColorMesh {
+	vtx_buf: wgpu::Buffer,
+	idx_buf: wgpu::Buffer,
+	num_indices: u32,
+}
+
+/// The meshes added with [`RenderState::add_color_mesh`], and the pipeline
+/// drawing them with `color_shader.wgsl` in the main pass.
+///
+/// [`RenderState::add_color_mesh`]: crate::render_state::RenderState::add_color_mesh
+pub(crate) struct ColorMeshes {
+	meshes: Vec<ColorMesh>,
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	reverse_z: bool,
+}
+impl ColorMeshes {
+	/// No meshes, drawn in render passes with the given attachments.
+	/// `camera_layout` is the layout of the main pipeline's camera bind group,
+	/// bound at group 0.
+	pub fn new(
+		device: &wgpu::Device,
+		camera_layout: &wgpu::BindGroupLayout,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("color_shader.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Color Mesh Pipeline Layout"),
+			bind_group_layouts: &[camera_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = Self::create_pipeline(
+			device,
+			&layout,
+			&shader,
+			format,
+			sample_count,
+			depth_format,
+			reverse_z,
+		);
+		Self {
+			meshes: Vec::new(),
+			shader,
+			layout,
+			pipeline,
+			reverse_z,
+		}
+	}
+
+	/// Adds the triangles of `vertices` listed by `indices`, in world space.
+	pub fn add(
+		&mut self,
+		device: &wgpu::Device,
+		vertices: &[ColorVertex],
+		indices: &[u32],
+	) {
+		let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Color Mesh Vertex Buffer"),
+			contents: bytemuck::cast_slice(vertices),
+			usage: wgpu::BufferUsages::VERTEX,
+		});
+		let idx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Color Mesh Index Buffer"),
+			contents: bytemuck::cast_slice(indices),
+			usage: wgpu::BufferUsages::INDEX,
+		});
+		self.meshes.push(ColorMesh {
+			vtx_buf,
+			idx_buf,
+			num_indices: indices.len() as u32,
+		});
+	}
+
+	pub fn clear(&mut self) {
+		self.meshes.clear();
+	}
+
+	/// Recreates the pipeline for render passes with different attachments.
+	pub fn set_format(
+		&mut self,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+	) {
+		self.pipeline = Self::create_pipeline(
+			device,
+			&self.layout,
+			&self.shader,
+			format,
+			sample_count,
+			depth_format,
+			self.reverse_z,
+		);
+	}
+
+	fn create_pipeline(
+		device: &wgpu::Device,
+		layout: &wgpu::PipelineLayout,
+		shader: &wgpu::ShaderModule,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> wgpu::RenderPipeline {
+		device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Color Mesh Pipeline"),
+			layout: Some(layout),
+			vertex: wgpu::VertexState {
+				module: shader,
+				entry_point: "vs_main",
+				buffers: &[ColorVertex::vb_layout()],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: shader,
+				entry_point: "fs_main",
+				targets: &[Some(format.into())],
+			}),
+			primitive: wgpu::PrimitiveState {
+				// Debug meshes are often seen from both sides.
+				cull_mode: None,
+				..Default::default()
+			},
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: depth_format,
+				depth_write_enabled: true,
+				depth_compare: if reverse_z {
+					wgpu::CompareFunction::Greater
+				} else {
+					wgpu::CompareFunction::Less
+				},
+				stencil: wgpu::StencilState::default(),
+				bias: wgpu::DepthBiasState::default(),
+			}),
+			multisample: wgpu::MultisampleState {
+				count: sample_count,
+				..Default::default()
+			},
+			multiview: None,
+		})
+	}
+
+	/// Records drawing the meshes, seen through `camera_bind_group`. Returns the
+	/// number of draws.
+	pub fn draw<'a>(
+		&'a self,
+		render_pass: &mut wgpu::RenderPass<'a>,
+		camera_bind_group: &'a wgpu::BindGroup,
+	) -> u32 {
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, camera_bind_group, &[]);
+		for mesh in &self.meshes {
+			render_pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+			render_pass
+				.set_index_buffer(mesh.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+		}
+		self.meshes.len() as u32
+	}
+}
@@ -0,0 +1,3 @@
+// Math shared by the shaders.
+
+const PI: f32 = 3.14159265;
@@ -0,0 +1,82 @@
+//! Running compute shaders.
+
+use color_eyre::{eyre::bail, Result};
+
+/// A compute pipeline with a single bind group, at index 0.
+pub struct ComputePass {
+	pipeline: wgpu::ComputePipeline,
+	bind_group_layout: wgpu::BindGroupLayout,
+	bind_group: Option<wgpu::BindGroup>,
+}
+impl ComputePass {
+	/// Compiles `shader_source` (WGSL) into a pipeline running `entry_point`, with
+	/// `bind_group_layout` as its only bind group.
+	///
+	/// Fails if the shader is invalid, or the device doesn't support compute
+	/// shaders, like on WebGL2.
+	pub fn new(
+		device: &wgpu::Device,
+		shader_source: &str,
+		entry_point: &str,
+		bind_group_layout: wgpu::BindGroupLayout,
+	) -> Result<Self> {
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+
+		// Catch validation errors instead of letting them panic.
+		device.push_error_scope(wgpu::ErrorFilter::Validation);
+		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some(entry_point),
+			source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Compute Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some(entry_point),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point,
+			});
+		if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+			bail!("Failed to create compute pipeline {entry_point:?}: {err}");
+		}
+
+		Ok(Self {
+			pipeline,
+			bind_group_layout,
+			bind_group: None,
+		})
+	}
+
+	pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+		&self.bind_group_layout
+	}
+
+	/// Sets the resources used by the next dispatches. `bind_group` must have been
+	/// created with [`Self::bind_group_layout`].
+	pub fn set_bind_group(&mut self, bind_group: wgpu::BindGroup) {
+		self.bind_group = Some(bind_group);
+	}
+
+	/// Records a compute pass running `x * y * z` workgroups.
+	///
+	/// # Panics
+	/// If no bind group has been set.
+	pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, x: u32, y: u32, z: u32) {
+		let bind_group = self
+			.bind_group
+			.as_ref()
+			.expect("ComputePass dispatched without a bind group");
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Compute Pass"),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, bind_group, &[]);
+		pass.dispatch_workgroups(x, y, z);
+	}
+}
@@ -0,0 +1,212 @@
+//! Cube textures, sampled by direction.
+
+use color_eyre::{
+	eyre::{ensure, WrapErr},
+	Result,
+};
+use std::path::Path;
+
+use crate::tex2d::SamplerConfig;
+
+/// A texture with six square faces, in the order +x, -x, +y, -y, +z, -z.
+pub struct Cubemap {
+	pub texture: wgpu::Texture,
+	/// Has dimension `Cube`.
+	pub view: wgpu::TextureView,
+	pub sampler: wgpu::Sampler,
+}
+impl Cubemap {
+	const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+	/// Creates a cubemap from six images of the same square size, in the order
+	/// +x, -x, +y, -y, +z, -z.
+	pub fn from_images(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		faces: [image::DynamicImage; 6],
+		label: Option<&str>,
+	) -> Result<Self> {
+		Self::from_mip_images(device, queue, vec![faces], label)
+	}
+
+	/// Creates a cubemap with a mip level for each element of `levels`, such as
+	/// an environment map pre-filtered for increasing roughness. Each level's
+	/// faces are half the size of the previous level's, rounded down.
+	pub fn from_mip_images(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		levels: Vec<[image::DynamicImage; 6]>,
+		label: Option<&str>,
+	) -> Result<Self> {
+		ensure!(!levels.is_empty(), "A cubemap needs at least one mip level");
+		let size = levels[0][0].width();
+		let max_levels = u32::BITS - size.leading_zeros();
+		ensure!(
+			levels.len() as u32 <= max_levels,
+			"A {size}x{size} cubemap has at most {max_levels} mip levels, got {}",
+			levels.len()
+		);
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label,
+			size: wgpu::Extent3d {
+				width: size,
+				height: size,
+				depth_or_array_layers: 6,
+			},
+			mip_level_count: levels.len() as u32,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: Self::FORMAT,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		for (mip_level, faces) in levels.into_iter().enumerate() {
+			let level_size = (size >> mip_level).max(1);
+			for face in &faces {
+				ensure!(
+					face.width() == level_size && face.height() == level_size,
+					"Faces of cubemap mip level {mip_level} must all be \
+					 {level_size}x{level_size}, got {}x{}",
+					face.width(),
+					face.height()
+				);
+			}
+			for (layer, face) in faces.into_iter().enumerate() {
+				queue.write_texture(
+					wgpu::ImageCopyTexture {
+						texture: &texture,
+						mip_level: mip_level as u32,
+						origin: wgpu::Origin3d {
+							x: 0,
+							y: 0,
+							z: layer as u32,
+						},
+						aspect: wgpu::TextureAspect::All,
+					},
+					&face.into_rgba8(),
+					wgpu::ImageDataLayout {
+						offset: 0,
+						bytes_per_row: Some(level_size * 4),
+						rows_per_image: None,
+					},
+					wgpu::Extent3d {
+						width: level_size,
+						height: level_size,
+						depth_or_array_layers: 1,
+					},
+				);
+			}
+		}
+		let view = texture.create_view(&wgpu::TextureViewDescriptor {
+			label,
+			dimension: Some(wgpu::TextureViewDimension::Cube),
+			..Default::default()
+		});
+		let sampler = SamplerConfig {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::FilterMode::Linear,
+			..SamplerConfig::default()
+		}
+		.create_sampler(device);
+		Ok(Self {
+			texture,
+			view,
+			sampler,
+		})
+	}
+
+	/// A 1x1 cubemap of a single sRGB color on every face.
+	pub fn from_color(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		rgba: [u8; 4],
+	) -> Self {
+		let face = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+			1,
+			1,
+			image::Rgba(rgba),
+		));
+		let faces = std::array::from_fn(|_| face.clone());
+		Self::from_images(device, queue, faces, label).expect("The faces are 1x1")
+	}
+
+	/// The number of mip levels, 1 unless created with
+	/// [`Self::from_mip_images`].
+	pub fn mip_level_count(&self) -> u32 {
+		self.texture.mip_level_count()
+	}
+
+	/// Loads six image files, in the order +x, -x, +y, -y, +z, -z.
+	pub fn load_from_paths(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		paths: [&Path; 6],
+	) -> Result<Self> {
+		let mut faces = Vec::with_capacity(6);
+		for path in paths {
+			let img = image::open(path).wrap_err_with(|| {
+				format!("Failed to load cubemap face {}", path.display())
+			})?;
+			faces.push(img);
+		}
+		let faces: [image::DynamicImage; 6] = faces.try_into().unwrap();
+		let label = paths[0].to_string_lossy();
+		Self::from_images(device, queue, faces, Some(&label))
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::builder::RenderStateBuilder;
+	use crate::camera::Camera;
+	use crate::gpu_context::test_state;
+	use nalgebra::{Perspective3, Point3};
+	use std::f32::consts::{FRAC_PI_2, PI};
+
+	#[test]
+	fn skybox_shows_the_face_looked_at() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 32, 32) else {
+			return;
+		};
+		let colors = [
+			[255, 0, 0, 255],
+			[0, 255, 0, 255],
+			[0, 0, 255, 255],
+			[255, 255, 0, 255],
+			[255, 0, 255, 255],
+			[0, 255, 255, 255],
+		];
+		let faces = colors.map(|rgba| {
+			image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+				1,
+				1,
+				image::Rgba(rgba),
+			))
+		});
+		let cubemap =
+			Cubemap::from_images(state.device(), state.queue(), faces, None).unwrap();
+		state.set_skybox(Some(&cubemap));
+
+		// The yaw and pitch looking along +x, -x, +y, -y, +z and -z. The default
+		// quad is in the camera's near plane, so it is clipped.
+		let max_pitch = Camera::MAX_PITCH;
+		let views = [
+			(-FRAC_PI_2, 0.),
+			(FRAC_PI_2, 0.),
+			(0., max_pitch),
+			(0., -max_pitch),
+			(PI, 0.),
+			(0., 0.),
+		];
+		for ((yaw, pitch), rgba) in views.into_iter().zip(colors) {
+			let proj = Perspective3::new(1., FRAC_PI_2, 0.1, 100.);
+			let camera = Camera::new(Point3::origin(), yaw, pitch, proj);
+			state.set_camera(Box::new(camera));
+			let center = *state.capture_screenshot().unwrap().get_pixel(16, 16);
+			assert_eq!(center.0, rgba, "Looking with yaw {yaw} and pitch {pitch}");
+		}
+	}
+}
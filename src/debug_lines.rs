@@ -0,0 +1,160 @@
+//! Colored lines drawn over the frame, for debugging.
+
+use nalgebra::Point3;
+
+use crate::vertex::{ColorVertex, Pos};
+
+/// Lines in world space, drawn over everything else, without depth testing.
+///
+/// Lines are added every frame: they are drawn and cleared at the end of
+/// [`RenderState::render`].
+///
+/// [`RenderState::render`]: crate::render_state::RenderState::render
+pub struct DebugLines {
+	/// Two per line.
+	vertices: Vec<ColorVertex>,
+	/// Grows to fit `vertices`.
+	vtx_buf: wgpu::Buffer,
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+impl DebugLines {
+	/// Lines that fit in the vertex buffer before it has to grow.
+	const INITIAL_CAPACITY: u64 = 256;
+
+	/// Creates a renderer drawing into `format` textures. `camera_layout` is the
+	/// layout of the main pipeline's camera bind group, bound at group 0.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		camera_layout: &wgpu::BindGroupLayout,
+	) -> Self {
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("debug_lines.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Debug Lines Pipeline Layout"),
+			bind_group_layouts: &[camera_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		Self {
+			vertices: Vec::new(),
+			vtx_buf: create_vertex_buffer(device, 2 * Self::INITIAL_CAPACITY),
+			shader,
+			layout,
+			pipeline,
+		}
+	}
+
+	/// Recreates the pipeline for a different frame format.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	pub fn add_line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+		for p in [from, to] {
+			self.vertices
+				.push(ColorVertex::new(Pos::new(p.x, p.y, p.z), color.into()));
+		}
+	}
+
+	/// Removes all lines.
+	pub fn clear(&mut self) {
+		self.vertices.clear();
+	}
+
+	/// The number of lines that will be drawn.
+	pub fn len(&self) -> usize {
+		self.vertices.len() / 2
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.vertices.is_empty()
+	}
+
+	/// Records uploading the lines through `belt` and drawing them over `view`,
+	/// seen through `camera_bind_group`. Does nothing if there are no lines.
+	/// Returns the number of bytes uploaded.
+	///
+	/// `belt` must be finished before the commands are submitted.
+	pub fn flush(
+		&mut self,
+		device: &wgpu::Device,
+		belt: &mut wgpu::util::StagingBelt,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		camera_bind_group: &wgpu::BindGroup,
+	) -> u64 {
+		let bytes: &[u8] = bytemuck::cast_slice(&self.vertices);
+		let Some(size) = wgpu::BufferSize::new(bytes.len() as u64) else {
+			return 0;
+		};
+		if size.get() > self.vtx_buf.size() {
+			let count = (self.vertices.len() as u64).next_power_of_two();
+			self.vtx_buf = create_vertex_buffer(device, count);
+		}
+		belt.write_buffer(encoder, &self.vtx_buf, 0, size, device)
+			.copy_from_slice(bytes);
+
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Debug Lines Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, camera_bind_group, &[]);
+		pass.set_vertex_buffer(0, self.vtx_buf.slice(..size.get()));
+		pass.draw(0..self.vertices.len() as u32, 0..1);
+		size.get()
+	}
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, vertices: u64) -> wgpu::Buffer {
+	device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Debug Lines Vertices"),
+		size: vertices * std::mem::size_of::<ColorVertex>() as u64,
+		usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	})
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Debug Lines Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[ColorVertex::vb_layout()],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState {
+			topology: wgpu::PrimitiveTopology::LineList,
+			..Default::default()
+		},
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
@@ -0,0 +1,29 @@
+// Draws the colored lines of `debug_lines.rs`, in world space.
+
+struct CameraUniform {
+	view_proj: mat4x4<f32>,
+	position: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+	@location(0) pos: vec3<f32>,
+	@location(1) color: vec4<f32>,
+) -> VertexOutput {
+	var out: VertexOutput;
+	out.clip_pos = camera.view_proj * vec4<f32>(pos, 1.0);
+	out.color = color;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	return in.color;
+}
@@ -0,0 +1,171 @@
+//! The egui debug panel drawn on top of the scene.
+
+use egui::plot::{Bar, BarChart, Legend, Plot, VLine};
+use egui_wgpu::renderer::ScreenDescriptor;
+use instant::{Duration, Instant};
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::profiler::{GpuProfiler, ScopeTiming};
+
+/// The frame time we are aiming for, in milliseconds.
+const FRAME_BUDGET_MS: f64 = 1000. / 60.;
+
+pub struct DebugUi {
+	ctx: egui::Context,
+	winit_state: egui_winit::State,
+	renderer: egui_wgpu::Renderer,
+	/// The outermost `GpuProfiler` scopes, as shown in the pass chart.
+	passes: Vec<ScopeTiming>,
+	fps: f32,
+	last_refresh: Instant,
+}
+impl DebugUi {
+	/// How often the displayed numbers are refreshed. Updating them every frame
+	/// makes them unreadable.
+	const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+	pub fn new(
+		window: &Window,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+	) -> Self {
+		let mut winit_state = egui_winit::State::new(window);
+		winit_state.set_pixels_per_point(window.scale_factor() as f32);
+		winit_state
+			.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+		Self {
+			ctx: egui::Context::default(),
+			winit_state,
+			renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+			passes: Vec::new(),
+			fps: 0.,
+			last_refresh: Instant::now(),
+		}
+	}
+
+	/// Must be called with every window event. Returns `true` if egui consumed
+	/// the event.
+	pub fn on_event(&mut self, event: &WindowEvent<'_>) -> bool {
+		self.winit_state.on_event(&self.ctx, event).consumed
+	}
+
+	/// Recreates the renderer for a new surface format.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+	}
+
+	/// Draws the panel into `view`, on top of what is already there.
+	#[allow(clippy::too_many_arguments)]
+	pub fn render(
+		&mut self,
+		window: &Window,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		fps: f32,
+		profiler: Option<&GpuProfiler>,
+	) {
+		if self.last_refresh.elapsed() >= Self::REFRESH_INTERVAL {
+			self.fps = fps;
+			self.passes.clear();
+			if let Some(profiler) = profiler {
+				self.passes
+					.extend(profiler.results().iter().filter(|s| s.depth == 0));
+			}
+			self.last_refresh = Instant::now();
+		}
+
+		let raw_input = self.winit_state.take_egui_input(window);
+		let full_output = self.ctx.run(raw_input, |ctx| {
+			egui::Window::new("Debug").show(ctx, |ui| {
+				ui.label(format!("FPS: {:.1}", self.fps));
+				if profiler.is_none() {
+					ui.label("GPU timings unavailable");
+					return;
+				}
+				pass_chart(ui, &self.passes);
+			});
+		});
+		self.winit_state.handle_platform_output(
+			window,
+			&self.ctx,
+			full_output.platform_output,
+		);
+		let paint_jobs = self.ctx.tessellate(full_output.shapes);
+
+		let size = window.inner_size();
+		let screen = ScreenDescriptor {
+			size_in_pixels: [size.width, size.height],
+			pixels_per_point: self.winit_state.pixels_per_point(),
+		};
+		for (id, delta) in full_output.textures_delta.set.iter() {
+			self.renderer.update_texture(device, queue, *id, delta);
+		}
+		let commands =
+			self.renderer
+				.update_buffers(device, queue, encoder, &paint_jobs, &screen);
+		// The renderer doesn't use any callbacks, so this should always be empty.
+		debug_assert!(commands.is_empty());
+		{
+			let mut render_pass =
+				encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+					label: Some("Debug UI Pass"),
+					color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+						view,
+						resolve_target: None,
+						ops: wgpu::Operations {
+							load: wgpu::LoadOp::Load,
+							store: true,
+						},
+					})],
+					depth_stencil_attachment: None,
+				});
+			self.renderer.render(&mut render_pass, &paint_jobs, &screen);
+		}
+		for id in full_output.textures_delta.free.iter() {
+			self.renderer.free_texture(id);
+		}
+	}
+}
+
+/// Draws the GPU time of each pass as one stacked horizontal bar.
+fn pass_chart(ui: &mut egui::Ui, passes: &[ScopeTiming]) {
+	let mut charts: Vec<BarChart> = Vec::new();
+	for pass in passes {
+		let chart = {
+			let below: Vec<&BarChart> = charts.iter().collect();
+			let bar = Bar::new(0., pass.ms as f64).name(pass.name);
+			BarChart::new(vec![bar])
+				.name(pass.name)
+				.horizontal()
+				.element_formatter(Box::new(|bar, _| {
+					format!(
+						"{}\n{:.0} µs ({:.1}% of frame budget)",
+						bar.name,
+						bar.value * 1000.,
+						bar.value / FRAME_BUDGET_MS * 100.,
+					)
+				}))
+				.stack_on(&below)
+		};
+		charts.push(chart);
+	}
+
+	Plot::new("GPU Passes")
+		.height(80.)
+		.legend(Legend::default())
+		.show_y(false)
+		.include_x(0.)
+		.include_x(FRAME_BUDGET_MS)
+		.allow_zoom(false)
+		.allow_drag(false)
+		.allow_scroll(false)
+		.show(ui, |plot_ui| {
+			for chart in charts {
+				plot_ui.bar_chart(chart);
+			}
+			plot_ui.vline(VLine::new(FRAME_BUDGET_MS).name("Frame budget"));
+		});
+}
@@ -0,0 +1,362 @@
+//! Decals: textures projected onto whatever geometry is already drawn, such as
+//! bullet holes and paint splashes, see `decal.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Point3, Vector3};
+use rustc_hash::FxHashMap;
+use wgpu::util::DeviceExt;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::mesh::generate_cube;
+use crate::resources::{Handle, ResourceManager};
+use crate::tex2d::Tex2d;
+use crate::types::mat4_to_wgsl;
+
+/// The layout of `decal.wgsl`'s `DecalCamera`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DecalCamera {
+	proj_view: [[f32; 4]; 4],
+	inv_proj_view: [[f32; 4]; 4],
+}
+
+/// The box of a decal, read from the instance buffer.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DecalInstance {
+	/// Scales, rotates and moves a unit cube centered on the origin over the box.
+	model: [[f32; 4]; 4],
+	inv_model: [[f32; 4]; 4],
+}
+impl DecalInstance {
+	const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
+		// A matrix doesn't fit in one attribute, so it is passed as its columns.
+		const ATTRIBS: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+			1 => Float32x4, 2 => Float32x4, 3 => Float32x4, 4 => Float32x4,
+			5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4
+		];
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<DecalInstance>() as _,
+			step_mode: wgpu::VertexStepMode::Instance,
+			attributes: &ATTRIBS,
+		}
+	}
+}
+
+/// A decal waiting to be drawn.
+struct Decal {
+	transform: Matrix4<f32>,
+	texture: Handle<Tex2d>,
+}
+
+/// Projects textures onto the scene within boxes.
+///
+/// Each box is drawn as a cube by its back faces, so that it still covers the screen with
+/// the camera inside of it. Its fragments reconstruct the world space position
+/// of the scene behind them from the depth buffer, and sample the texture at
+/// that position's x and y in the box, from 0 to 1. Positions outside of the
+/// box are discarded. The texture's alpha blends it over the scene.
+pub struct DecalRenderer {
+	decals: Vec<Decal>,
+	/// The positions and `u16` indices of a unit cube centered on the origin.
+	cube_vtx_buf: wgpu::Buffer,
+	cube_idx_buf: wgpu::Buffer,
+	num_cube_indices: u32,
+	camera_buf: wgpu::Buffer,
+	/// Binds `camera_buf` and the depth buffer.
+	input_layout: wgpu::BindGroupLayout,
+	texture_layout: wgpu::BindGroupLayout,
+	/// Holds `capacity` instances.
+	instance_buf: wgpu::Buffer,
+	capacity: usize,
+	reverse_z: bool,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	pipeline: wgpu::RenderPipeline,
+}
+impl DecalRenderer {
+	/// Creates a renderer drawing into `format` textures, over a depth buffer that
+	/// is optionally reversed.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let camera_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Decal Camera"),
+			size: std::mem::size_of::<DecalCamera>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Decal Input Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					// Read with `textureLoad`, so it needs no sampler.
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Depth,
+						},
+						count: None,
+					},
+				],
+			});
+		let texture_layout = Tex2d::layout(device);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Decal Pipeline Layout"),
+			bind_group_layouts: &[&input_layout, &texture_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("decal.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		let (vertices, indices) = generate_cube(0.5);
+		let positions: Vec<[f32; 3]> = vertices
+			.iter()
+			.map(|v| [v.pos.x, v.pos.y, v.pos.z])
+			.collect();
+		let cube_vtx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Decal Cube Vertices"),
+				contents: bytemuck::cast_slice(&positions),
+				usage: wgpu::BufferUsages::VERTEX,
+			});
+		let cube_idx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Decal Cube Indices"),
+				contents: bytemuck::cast_slice(&indices),
+				usage: wgpu::BufferUsages::INDEX,
+			});
+		let capacity = 1;
+		Self {
+			decals: Vec::new(),
+			cube_vtx_buf,
+			cube_idx_buf,
+			num_cube_indices: indices.len() as u32,
+			camera_buf,
+			input_layout,
+			texture_layout,
+			instance_buf: create_instance_buffer(device, capacity),
+			capacity,
+			reverse_z,
+			layout,
+			shader,
+			pipeline,
+		}
+	}
+
+	/// Recreates the pipeline to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Queues a decal projecting `texture` onto the scene within a box of `size`
+	/// centered on `position`, turned by the rotation of `rotation`. The texture's
+	/// u goes along the box's x axis and its v down its y axis, and it is
+	/// projected along the z axis.
+	pub fn add_decal(
+		&mut self,
+		position: Point3<f32>,
+		rotation: IsometryMatrix3<f32>,
+		size: [f32; 3],
+		texture: Handle<Tex2d>,
+	) {
+		let transform = Matrix4::new_translation(&position.coords)
+			* rotation.rotation.to_homogeneous()
+			* Matrix4::new_nonuniform_scaling(&Vector3::from(size));
+		self.decals.push(Decal { transform, texture });
+	}
+
+	/// The number of decals queued since the last flush.
+	pub fn len(&self) -> usize {
+		self.decals.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.decals.is_empty()
+	}
+
+	/// Drops the queued decals without drawing them.
+	pub fn clear(&mut self) {
+		self.decals.clear();
+	}
+
+	/// Uploads the projection of `camera`, seen from `view`. Returns the number of
+	/// bytes written.
+	pub fn update(
+		&self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut proj_view = camera.proj_view_from(view);
+		if self.reverse_z {
+			proj_view = reverse_z(&proj_view);
+		}
+		let uniform = DecalCamera {
+			proj_view: mat4_to_wgsl(proj_view),
+			inv_proj_view: mat4_to_wgsl(proj_view.try_inverse().unwrap_or_default()),
+		};
+		queue.write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<DecalCamera>() as u64
+	}
+
+	/// Records drawing the queued decals over `color_view`, at the positions read
+	/// from `depth_view`, and empties the queue. The depth buffer must be single
+	/// sampled, with the size of `color_view`. Decals whose texture was removed
+	/// from `resources` are skipped. Returns the number of draw calls.
+	pub fn flush(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		resources: &ResourceManager,
+		depth_view: &wgpu::TextureView,
+		color_view: &wgpu::TextureView,
+	) -> u32 {
+		let mut decals = std::mem::take(&mut self.decals);
+		decals.retain(|decal| resources.get(decal.texture).is_some());
+		if decals.is_empty() {
+			return 0;
+		}
+		// Grouped by texture, to draw each group as instances.
+		let mut groups: FxHashMap<Handle<Tex2d>, Vec<DecalInstance>> =
+			FxHashMap::default();
+		for decal in &decals {
+			groups
+				.entry(decal.texture)
+				.or_default()
+				.push(DecalInstance {
+					model: mat4_to_wgsl(decal.transform),
+					inv_model: mat4_to_wgsl(
+						decal.transform.try_inverse().unwrap_or_default(),
+					),
+				});
+		}
+		let instances: Vec<_> = groups.values().flatten().copied().collect();
+		if instances.len() > self.capacity {
+			self.capacity = instances.len().next_power_of_two();
+			self.instance_buf = create_instance_buffer(device, self.capacity);
+		}
+		queue.write_buffer(&self.instance_buf, 0, bytemuck::cast_slice(&instances));
+
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("decal_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.camera_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+			],
+		});
+		let textures: Vec<_> = groups
+			.iter()
+			.map(|(&handle, group)| {
+				let texture =
+					resources.get(handle).expect("Removed decals were skipped");
+				let bind_group = texture.bind_group(
+					device,
+					&self.texture_layout,
+					Some("decal_texture_bind_group"),
+				);
+				(bind_group, group.len() as u32)
+			})
+			.collect();
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Decal Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: color_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &input, &[]);
+		pass.set_vertex_buffer(0, self.cube_vtx_buf.slice(..));
+		pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+		pass.set_index_buffer(self.cube_idx_buf.slice(..), wgpu::IndexFormat::Uint16);
+		let mut first_instance = 0;
+		for (bind_group, count) in &textures {
+			pass.set_bind_group(1, bind_group, &[]);
+			let instances = first_instance..first_instance + count;
+			pass.draw_indexed(0..self.num_cube_indices, 0, instances);
+			first_instance += count;
+		}
+		// Keeps the allocation for the next frame's decals.
+		decals.clear();
+		self.decals = decals;
+		textures.len() as u32
+	}
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+	device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some("Decal Instances"),
+		contents: bytemuck::cast_slice(&vec![DecalInstance::zeroed(); capacity]),
+		usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+	})
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Decal Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[
+				wgpu::VertexBufferLayout {
+					array_stride: std::mem::size_of::<[f32; 3]>() as _,
+					step_mode: wgpu::VertexStepMode::Vertex,
+					attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+				},
+				DecalInstance::vb_layout(),
+			],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState {
+			// The back faces, which are there with the camera inside the box too.
+			cull_mode: Some(wgpu::Face::Front),
+			..Default::default()
+		},
+		// The depth buffer is read instead.
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
@@ -0,0 +1,77 @@
+// Projects a texture onto the scene already drawn, within the box of each
+// instance.
+
+struct DecalCamera {
+	proj_view: mat4x4<f32>,
+	inv_proj_view: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: DecalCamera;
+@group(0) @binding(1)
+var depth_t: texture_depth_2d;
+
+@group(1) @binding(0)
+var decal_t: texture_2d<f32>;
+@group(1) @binding(1)
+var decal_s: sampler;
+
+struct InstanceInput {
+	@location(1) model_0: vec4<f32>,
+	@location(2) model_1: vec4<f32>,
+	@location(3) model_2: vec4<f32>,
+	@location(4) model_3: vec4<f32>,
+	@location(5) inv_model_0: vec4<f32>,
+	@location(6) inv_model_1: vec4<f32>,
+	@location(7) inv_model_2: vec4<f32>,
+	@location(8) inv_model_3: vec4<f32>,
+};
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) inv_model_0: vec4<f32>,
+	@location(1) inv_model_1: vec4<f32>,
+	@location(2) inv_model_2: vec4<f32>,
+	@location(3) inv_model_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) pos: vec3<f32>, instance: InstanceInput) -> VertexOutput {
+	let model = mat4x4<f32>(
+		instance.model_0,
+		instance.model_1,
+		instance.model_2,
+		instance.model_3,
+	);
+	var out: VertexOutput;
+	out.clip_pos = camera.proj_view * model * vec4<f32>(pos, 1.0);
+	out.inv_model_0 = instance.inv_model_0;
+	out.inv_model_1 = instance.inv_model_1;
+	out.inv_model_2 = instance.inv_model_2;
+	out.inv_model_3 = instance.inv_model_3;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	// The scene's world space position behind the fragment.
+	let pixel = vec2<i32>(in.clip_pos.xy);
+	let depth = textureLoad(depth_t, pixel, 0);
+	let size = vec2<f32>(textureDimensions(depth_t));
+	let uv = in.clip_pos.xy / size;
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = camera.inv_proj_view * ndc;
+
+	let inv_model = mat4x4<f32>(
+		in.inv_model_0,
+		in.inv_model_1,
+		in.inv_model_2,
+		in.inv_model_3,
+	);
+	let local = (inv_model * vec4<f32>(world.xyz / world.w, 1.0)).xyz;
+	// Sampled before discarding, in uniform control flow.
+	let color = textureSample(decal_t, decal_s, vec2<f32>(local.x + 0.5, 0.5 - local.y));
+	if any(abs(local) > vec3<f32>(0.5)) {
+		discard;
+	}
+	return color;
+}
@@ -0,0 +1,379 @@
+//! Depth of field: the frame blurred by how far each pixel is out of focus, as
+//! through a camera lens.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::IsometryMatrix3;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::types::mat4_to_wgsl;
+
+/// The layout of the shader's `DofUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DofUniform {
+	inv_proj: [[f32; 4]; 4],
+	far_depth: f32,
+	focus_distance: f32,
+	aperture: f32,
+	focal_length: f32,
+	/// Converts circles of confusion on the sensor into pixels.
+	pixels_per_unit: f32,
+	_pad: [f32; 3],
+}
+
+/// Blurs each pixel of the frame over its circle of confusion, from the depth of
+/// a [`GBuffer`](crate::gbuffer::GBuffer), for a thin lens focused at
+/// `focus_distance`.
+///
+/// The circle of confusion's radius on the sensor is
+/// `|depth - focus_distance| * aperture * focal_length / (depth *
+/// (focus_distance - focal_length))`, converted into pixels by the frame's
+/// height over `sensor_height`, and clamped to [`Self::MAX_COC`]. A Gaussian
+/// blur of that radius is applied horizontally, then vertically.
+///
+/// What is behind the focus plane and what is in front of it are blurred in
+/// separate layers. The far layer only gathers far pixels, so that the
+/// foreground doesn't bleed into it. The near layer spreads the foreground over
+/// its surroundings, with premultiplied alpha, and is blended over the far
+/// layer, so that blurry foreground edges show the background behind them.
+///
+/// The public fields are uploaded by [`Self::update`].
+pub struct DepthOfFieldPass {
+	/// The distance from the camera that is in focus.
+	pub focus_distance: f32,
+	/// The diameter of the lens' opening, in the units of `focal_length`. Wider
+	/// blurs more.
+	pub aperture: f32,
+	/// The lens' focal length, in world units.
+	pub focal_length: f32,
+	/// The height of the sensor, in the units of `focal_length`.
+	pub sensor_height: f32,
+	/// The size of the frames, in pixels.
+	size: (u32, u32),
+	/// The horizontally blurred layers, see [`create_layers`].
+	layers: Layers,
+	uniform_buf: wgpu::Buffer,
+	horizontal_layout: wgpu::BindGroupLayout,
+	vertical_layout: wgpu::BindGroupLayout,
+	reverse_z: bool,
+	vertical_pipeline_layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	horizontal_pipeline: wgpu::RenderPipeline,
+	vertical_pipeline: wgpu::RenderPipeline,
+}
+impl DepthOfFieldPass {
+	/// The largest radius of the blur, in pixels.
+	pub const MAX_COC: f32 = 20.;
+	/// The far layer, and the premultiplied near layer.
+	const LAYER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	/// The circles of confusion of the near layer, in pixels.
+	const NEAR_COC_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+	/// Creates a pass for `width`×`height` frames drawn into `format` textures,
+	/// with a depth buffer that is optionally reversed. It is focused 10 units
+	/// away through a 50mm f/4 lens, with world units as meters.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+		reverse_z: bool,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Depth of Field Uniform"),
+			size: std::mem::size_of::<DofUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let uniform_entry = wgpu::BindGroupLayoutEntry {
+			binding: 0,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: false },
+			},
+			count: None,
+		};
+		// The textures are read with `textureLoad`, so they need no samplers.
+		let horizontal_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Depth of Field Horizontal Layout"),
+				entries: &[uniform_entry, texture_entry(1), texture_entry(2)],
+			});
+		let vertical_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Depth of Field Vertical Layout"),
+				entries: &[
+					uniform_entry,
+					texture_entry(3),
+					texture_entry(4),
+					texture_entry(5),
+				],
+			});
+		let horizontal_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Depth of Field Horizontal Pipeline Layout"),
+				bind_group_layouts: &[&horizontal_layout],
+				push_constant_ranges: &[],
+			});
+		let vertical_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Depth of Field Vertical Pipeline Layout"),
+				bind_group_layouts: &[&vertical_layout],
+				push_constant_ranges: &[],
+			});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("depth_of_field.wgsl"));
+		let horizontal_pipeline = create_pipeline(
+			device,
+			&horizontal_pipeline_layout,
+			&shader,
+			"fs_horizontal",
+			&[
+				Some(Self::LAYER_FORMAT.into()),
+				Some(Self::LAYER_FORMAT.into()),
+				Some(Self::NEAR_COC_FORMAT.into()),
+			],
+		);
+		let vertical_pipeline = create_pipeline(
+			device,
+			&vertical_pipeline_layout,
+			&shader,
+			"fs_vertical",
+			&[Some(format.into())],
+		);
+		Self {
+			focus_distance: 10.,
+			aperture: 0.05 / 4.,
+			focal_length: 0.05,
+			sensor_height: 0.024,
+			size: (width, height),
+			layers: create_layers(device, width, height),
+			uniform_buf,
+			horizontal_layout,
+			vertical_layout,
+			reverse_z,
+			vertical_pipeline_layout,
+			shader,
+			horizontal_pipeline,
+			vertical_pipeline,
+		}
+	}
+
+	/// Recreates the layers for `width`×`height` frames.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		self.size = (width, height);
+		self.layers = create_layers(device, width, height);
+	}
+
+	/// Recreates the pipeline to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.vertical_pipeline = create_pipeline(
+			device,
+			&self.vertical_pipeline_layout,
+			&self.shader,
+			"fs_vertical",
+			&[Some(format.into())],
+		);
+	}
+
+	/// The radius in pixels of the circle of confusion at view depth `depth`,
+	/// as computed by the shader.
+	pub fn circle_of_confusion(&self, depth: f32) -> f32 {
+		let coc =
+			(depth - self.focus_distance).abs() * self.aperture * self.focal_length
+				/ (depth * (self.focus_distance - self.focal_length));
+		(coc * self.pixels_per_unit()).clamp(0., Self::MAX_COC)
+	}
+
+	fn pixels_per_unit(&self) -> f32 {
+		self.size.1 as f32 / self.sensor_height
+	}
+
+	/// Uploads the projection of `camera`, and the public fields. Returns the
+	/// number of bytes written.
+	pub fn update(&mut self, queue: &wgpu::Queue, camera: &dyn CameraLike) -> u64 {
+		let mut proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			proj = reverse_z(&proj);
+			far_depth = 0.;
+		}
+		let uniform = DofUniform {
+			inv_proj: mat4_to_wgsl(proj.try_inverse().unwrap_or_default()),
+			far_depth,
+			focus_distance: self.focus_distance,
+			aperture: self.aperture,
+			focal_length: self.focal_length,
+			pixels_per_unit: self.pixels_per_unit(),
+			_pad: [0.; 3],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<DofUniform>() as u64
+	}
+
+	/// Records blurring `color_view` by the G-buffer's `depth_view` into all of
+	/// `output_view`.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		color_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let horizontal = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("dof_horizontal_bind_group"),
+			layout: &self.horizontal_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(color_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+			],
+		});
+		let vertical = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("dof_vertical_bind_group"),
+			layout: &self.vertical_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(&self.layers.far),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: wgpu::BindingResource::TextureView(&self.layers.near),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: wgpu::BindingResource::TextureView(&self.layers.near_coc),
+				},
+			],
+		});
+		// Every pixel is drawn over.
+		let attachment = |view| {
+			Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+					store: true,
+				},
+			})
+		};
+		{
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Depth of Field Horizontal Pass"),
+				color_attachments: &[
+					attachment(&self.layers.far),
+					attachment(&self.layers.near),
+					attachment(&self.layers.near_coc),
+				],
+				depth_stencil_attachment: None,
+			});
+			pass.set_pipeline(&self.horizontal_pipeline);
+			pass.set_bind_group(0, &horizontal, &[]);
+			pass.draw(0..3, 0..1);
+		}
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Depth of Field Vertical Pass"),
+			color_attachments: &[attachment(output_view)],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.vertical_pipeline);
+		pass.set_bind_group(0, &vertical, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+/// The frame blurred horizontally, for the vertical pass to blur and composite.
+struct Layers {
+	/// What is behind the focus plane, with its circle of confusion in alpha,
+	/// or -1 where the foreground is.
+	far: wgpu::TextureView,
+	/// The foreground spread over its surroundings, premultiplied by how much of
+	/// each pixel it covers.
+	near: wgpu::TextureView,
+	/// The circle of confusion of the foreground spread over each pixel.
+	near_coc: wgpu::TextureView,
+}
+
+fn create_layers(device: &wgpu::Device, width: u32, height: u32) -> Layers {
+	let create_view = |label, format| {
+		device
+			.create_texture(&wgpu::TextureDescriptor {
+				label: Some(label),
+				size: wgpu::Extent3d {
+					width,
+					height,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+					| wgpu::TextureUsages::TEXTURE_BINDING,
+				view_formats: &[],
+			})
+			.create_view(&Default::default())
+	};
+	Layers {
+		far: create_view("Depth of Field Far", DepthOfFieldPass::LAYER_FORMAT),
+		near: create_view("Depth of Field Near", DepthOfFieldPass::LAYER_FORMAT),
+		near_coc: create_view(
+			"Depth of Field Near CoC",
+			DepthOfFieldPass::NEAR_COC_FORMAT,
+		),
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	entry_point: &str,
+	targets: &[Option<wgpu::ColorTargetState>],
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Depth of Field Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point,
+			targets,
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
@@ -0,0 +1,192 @@
+// Depth of field: each pixel blurred over its circle of confusion, horizontally
+// then vertically, with the foreground blurred in a layer of its own.
+
+// Matches `DofUniform` in `depth_of_field.rs`.
+struct DofUniform {
+	inv_proj: mat4x4<f32>,
+	// The depth of the G-buffer where nothing was drawn.
+	far_depth: f32,
+	focus_distance: f32,
+	aperture: f32,
+	focal_length: f32,
+	// Converts circles of confusion on the sensor into pixels.
+	pixels_per_unit: f32,
+};
+@group(0) @binding(0)
+var<uniform> dof: DofUniform;
+
+// The frame, read by the horizontal pass.
+@group(0) @binding(1)
+var color_t: texture_2d<f32>;
+// The G-buffer's depth, in the red channel.
+@group(0) @binding(2)
+var depth_t: texture_2d<f32>;
+// The horizontally blurred layers, read by the vertical pass. The far layer's
+// alpha is the circle of confusion, or -1 where the foreground is. The near
+// layer is premultiplied.
+@group(0) @binding(3)
+var far_t: texture_2d<f32>;
+@group(0) @binding(4)
+var near_t: texture_2d<f32>;
+@group(0) @binding(5)
+var near_coc_t: texture_2d<f32>;
+
+// The largest radius of the blur, in pixels.
+const MAX_COC: f32 = 20.0;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+// The distance from the camera drawn at `coords`. Where nothing was drawn, it
+// is far past anything that was.
+fn view_depth(coords: vec2<i32>) -> f32 {
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth == dof.far_depth {
+		return 1e6;
+	}
+	let view = dof.inv_proj * vec4<f32>(0.0, 0.0, depth, 1.0);
+	return -view.z / view.w;
+}
+
+// The radius of the circle of confusion at view depth `depth`, in pixels.
+fn circle_of_confusion(depth: f32) -> f32 {
+	let coc = abs(depth - dof.focus_distance) * dof.aperture * dof.focal_length
+		/ (depth * (dof.focus_distance - dof.focal_length));
+	return clamp(coc * dof.pixels_per_unit, 0.0, MAX_COC);
+}
+
+// The weight of a tap `offset` pixels away in a Gaussian blur of `radius`,
+// normalized so that a pixel spread over its taps adds up to about 1.
+fn kernel_weight(offset: f32, radius: f32) -> f32 {
+	if radius < 0.5 {
+		return select(0.0, 1.0, abs(offset) < 0.5);
+	}
+	if abs(offset) > radius {
+		return 0.0;
+	}
+	let sigma = radius / 2.0;
+	// sqrt(2 * pi)
+	return exp(-offset * offset / (2.0 * sigma * sigma)) / (sigma * 2.5066283);
+}
+
+// Full coverage doesn't brighten the layer.
+fn clamp_coverage(near: vec4<f32>) -> vec4<f32> {
+	if near.a > 1.0 {
+		return near / near.a;
+	}
+	return near;
+}
+
+struct HorizontalOutput {
+	@location(0) far: vec4<f32>,
+	@location(1) near: vec4<f32>,
+	@location(2) near_coc: vec4<f32>,
+};
+
+@fragment
+fn fs_horizontal(in: VertexOutput) -> HorizontalOutput {
+	let size = vec2<i32>(textureDimensions(color_t));
+	let center = vec2<i32>(in.clip_pos.xy);
+	var out: HorizontalOutput;
+
+	// The far layer gathers the far pixels within its own circle of confusion.
+	let depth = view_depth(center);
+	if depth < dof.focus_distance {
+		out.far = vec4<f32>(textureLoad(color_t, center, 0).rgb, -1.0);
+	} else {
+		let radius = circle_of_confusion(depth);
+		let taps = i32(ceil(radius));
+		var far = vec3<f32>(0.0);
+		var total = 0.0;
+		for (var i = -taps; i <= taps; i += 1) {
+			let coords = clamp(center + vec2<i32>(i, 0), vec2<i32>(0), size - 1);
+			if view_depth(coords) < dof.focus_distance {
+				continue;
+			}
+			let weight = kernel_weight(f32(i), radius);
+			far += textureLoad(color_t, coords, 0).rgb * weight;
+			total += weight;
+		}
+		// The center always has some weight.
+		out.far = vec4<f32>(far / total, radius);
+	}
+
+	// The near layer gathers the foreground pixels whose circles of confusion
+	// reach this one, which spreads them out.
+	var near = vec4<f32>(0.0);
+	var near_coc = 0.0;
+	let max_taps = i32(MAX_COC);
+	for (var i = -max_taps; i <= max_taps; i += 1) {
+		let coords = center + vec2<i32>(i, 0);
+		if coords.x < 0 || coords.x >= size.x {
+			continue;
+		}
+		let tap_depth = view_depth(coords);
+		if tap_depth >= dof.focus_distance {
+			continue;
+		}
+		let radius = circle_of_confusion(tap_depth);
+		let weight = kernel_weight(f32(i), radius);
+		near += vec4<f32>(textureLoad(color_t, coords, 0).rgb, 1.0) * weight;
+		near_coc += radius * weight;
+	}
+	out.near_coc = vec4<f32>(near_coc / max(near.a, 0.0001));
+	out.near = clamp_coverage(near);
+	return out;
+}
+
+@fragment
+fn fs_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+	let size = vec2<i32>(textureDimensions(far_t));
+	let center = vec2<i32>(in.clip_pos.xy);
+
+	let center_far = textureLoad(far_t, center, 0);
+	var far = center_far.rgb;
+	if center_far.a >= 0.0 {
+		let radius = center_far.a;
+		let taps = i32(ceil(radius));
+		var sum = vec3<f32>(0.0);
+		var total = 0.0;
+		for (var i = -taps; i <= taps; i += 1) {
+			let coords = clamp(center + vec2<i32>(0, i), vec2<i32>(0), size - 1);
+			let tap = textureLoad(far_t, coords, 0);
+			if tap.a < 0.0 {
+				continue;
+			}
+			let weight = kernel_weight(f32(i), radius);
+			sum += tap.rgb * weight;
+			total += weight;
+		}
+		far = sum / total;
+	}
+
+	var near = vec4<f32>(0.0);
+	let max_taps = i32(MAX_COC);
+	for (var i = -max_taps; i <= max_taps; i += 1) {
+		let coords = center + vec2<i32>(0, i);
+		if coords.y < 0 || coords.y >= size.y {
+			continue;
+		}
+		let tap = textureLoad(near_t, coords, 0);
+		if tap.a == 0.0 {
+			continue;
+		}
+		let radius = textureLoad(near_coc_t, coords, 0).r;
+		near += tap * kernel_weight(f32(i), radius);
+	}
+	near = clamp_coverage(near);
+
+	// The premultiplied foreground over the background.
+	return vec4<f32>(near.rgb + far * (1.0 - near.a), 1.0);
+}
@@ -0,0 +1,78 @@
+//! Logging what the adapter supports, to explain failures on low-end hardware.
+
+use log::{debug, warn};
+
+/// Logs the features the adapter supports and which of them are requested, and
+/// the requested limits next to the adapter's. Warns about every requested
+/// feature or limit the adapter doesn't support.
+pub fn log_adapter_capabilities(
+	adapter: &wgpu::Adapter,
+	requested: &wgpu::DeviceDescriptor<'_>,
+) {
+	let supported = adapter.features();
+	let mark = |set: bool| if set { "yes" } else { "no" };
+	debug!("Features (requested / adapter):");
+	for (name, feature) in wgpu::Features::all().iter_names() {
+		debug!(
+			"  {name:<50} {:<3} / {}",
+			mark(requested.features.contains(feature)),
+			mark(supported.contains(feature)),
+		);
+		if requested.features.contains(feature) && !supported.contains(feature) {
+			warn!("Requested feature {name} is not supported by the adapter");
+		}
+	}
+
+	let actual = adapter.limits();
+	debug!("Limits (requested / adapter):");
+	macro_rules! log_limits {
+		($($limit:ident),* $(,)?) => {
+			$(debug!(
+				"  {:<50} {} / {}",
+				stringify!($limit),
+				requested.limits.$limit,
+				actual.$limit,
+			);)*
+		};
+	}
+	log_limits!(
+		max_texture_dimension_1d,
+		max_texture_dimension_2d,
+		max_texture_dimension_3d,
+		max_texture_array_layers,
+		max_bind_groups,
+		max_bindings_per_bind_group,
+		max_dynamic_uniform_buffers_per_pipeline_layout,
+		max_dynamic_storage_buffers_per_pipeline_layout,
+		max_sampled_textures_per_shader_stage,
+		max_samplers_per_shader_stage,
+		max_storage_buffers_per_shader_stage,
+		max_storage_textures_per_shader_stage,
+		max_uniform_buffers_per_shader_stage,
+		max_uniform_buffer_binding_size,
+		max_storage_buffer_binding_size,
+		max_vertex_buffers,
+		max_buffer_size,
+		max_vertex_attributes,
+		max_vertex_buffer_array_stride,
+		min_uniform_buffer_offset_alignment,
+		min_storage_buffer_offset_alignment,
+		max_inter_stage_shader_components,
+		max_compute_workgroup_storage_size,
+		max_compute_invocations_per_workgroup,
+		max_compute_workgroup_size_x,
+		max_compute_workgroup_size_y,
+		max_compute_workgroup_size_z,
+		max_compute_workgroups_per_dimension,
+		max_push_constant_size,
+	);
+	requested.limits.check_limits_with_fail_fn(
+		&actual,
+		false,
+		|name, requested, allowed| {
+			warn!(
+				"Requested limit {name} of {requested} exceeds the adapter's {allowed}"
+			);
+		},
+	);
+}
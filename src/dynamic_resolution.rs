@@ -0,0 +1,228 @@
+//! Drawing the scene at a lower resolution when frames take too long, and
+//! upscaling it to the frame.
+
+use crate::render_target::RenderTarget;
+
+/// Adjusts the scale the scene is drawn at to the time frames take, so that
+/// they keep to `target_fps`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DynamicResolutionScaler {
+	pub target_fps: f32,
+	/// The smallest and largest scales, of the width and height of the frame.
+	pub min_scale: f32,
+	pub max_scale: f32,
+	pub current_scale: f32,
+}
+impl DynamicResolutionScaler {
+	/// How much the scale changes by each frame.
+	pub const STEP: f32 = 0.05;
+
+	/// A scaler starting at full resolution, which can go down to half of it.
+	pub fn new(target_fps: f32) -> Self {
+		Self {
+			target_fps,
+			min_scale: 0.5,
+			max_scale: 1.,
+			current_scale: 1.,
+		}
+	}
+
+	/// Lowers the scale by [`Self::STEP`] if a frame took `frame_time` seconds,
+	/// more than the target allows, and raises it if the frame took less. Frames
+	/// within a step under the target keep the scale, so that it settles rather
+	/// than changing every frame. Returns the new scale.
+	pub fn update(&mut self, frame_time: f32) -> f32 {
+		let budget = 1. / self.target_fps;
+		if frame_time > budget {
+			self.current_scale *= 1. - Self::STEP;
+		} else if frame_time < budget * (1. - Self::STEP) {
+			self.current_scale *= 1. + Self::STEP;
+		}
+		self.current_scale = self.current_scale.clamp(self.min_scale, self.max_scale);
+		self.current_scale
+	}
+
+	/// The size to draw a `width`×`height` frame at, rounded to even numbers of
+	/// pixels.
+	pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+		let scale = |size: u32| {
+			let scaled = (size as f32 * self.current_scale / 2.).round() as u32 * 2;
+			scaled.clamp(size.min(2), size.max(1))
+		};
+		(scale(width), scale(height))
+	}
+}
+impl Default for DynamicResolutionScaler {
+	/// Keeps to 60 FPS.
+	fn default() -> Self {
+		Self::new(60.)
+	}
+}
+
+/// Upscales the scene, drawn into `source` at the scaler's resolution, to the
+/// frame with bilinear filtering.
+pub(crate) struct DynamicResolutionPass {
+	source: RenderTarget,
+	pipeline: wgpu::RenderPipeline,
+	sampler: wgpu::Sampler,
+	bind_group: wgpu::BindGroup,
+}
+impl DynamicResolutionPass {
+	/// Creates a pass upscaling a `width`×`height` scene into `format` frames.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+	) -> Self {
+		let pipeline = create_pipeline(device, format);
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("Dynamic Resolution Sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+		let source = RenderTarget::new(
+			device,
+			width,
+			height,
+			format,
+			"Dynamic Resolution Source",
+		);
+		let bind_group = create_bind_group(device, &pipeline, &sampler, &source);
+		Self {
+			source,
+			pipeline,
+			sampler,
+			bind_group,
+		}
+	}
+
+	/// The view the scene is drawn into.
+	pub fn source_view(&self) -> &wgpu::TextureView {
+		self.source.color_view()
+	}
+
+	/// Recreates the source for a `width`×`height` scene.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		let format = self.source.color().texture.format();
+		self.source = RenderTarget::new(
+			device,
+			width,
+			height,
+			format,
+			"Dynamic Resolution Source",
+		);
+		self.bind_group =
+			create_bind_group(device, &self.pipeline, &self.sampler, &self.source);
+	}
+
+	/// Recreates the pipeline and source for `format` frames.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, format);
+		let size = self.source.size();
+		self.source = RenderTarget::new(
+			device,
+			size.width,
+			size.height,
+			format,
+			"Dynamic Resolution Source",
+		);
+		self.bind_group =
+			create_bind_group(device, &self.pipeline, &self.sampler, &self.source);
+	}
+
+	/// Records drawing the source over all of `view`.
+	pub fn apply(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Dynamic Resolution Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.bind_group, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Dynamic Resolution Pipeline"),
+		// Derived from the shader.
+		layout: None,
+		vertex: wgpu::VertexState {
+			module: &shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: &shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
+
+fn create_bind_group(
+	device: &wgpu::Device,
+	pipeline: &wgpu::RenderPipeline,
+	sampler: &wgpu::Sampler,
+	source: &RenderTarget,
+) -> wgpu::BindGroup {
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("dynamic_resolution_bind_group"),
+		layout: &pipeline.get_bind_group_layout(0),
+		entries: &[
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::TextureView(source.color_view()),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: wgpu::BindingResource::Sampler(sampler),
+			},
+		],
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn slow_frames_lower_the_scale() {
+		let mut scaler = DynamicResolutionScaler::new(60.);
+		let mut scale = scaler.current_scale;
+		for _ in 0..5 {
+			let lowered = scaler.update(0.05);
+			assert!(lowered < scale, "{lowered} >= {scale}");
+			scale = lowered;
+		}
+		for _ in 0..100 {
+			scaler.update(0.05);
+		}
+		assert_eq!(scaler.current_scale, scaler.min_scale);
+		assert_eq!(scaler.scaled_size(1280, 720), (640, 360));
+		// Fast frames raise it back up to full resolution.
+		for _ in 0..100 {
+			scaler.update(0.001);
+		}
+		assert_eq!(scaler.current_scale, scaler.max_scale);
+	}
+}
@@ -0,0 +1,362 @@
+//! A minimal entity component system for render objects. Entities are ids, and
+//! each kind of component is stored in a vec of its own, indexed by entity.
+
+use nalgebra::Matrix4;
+
+use crate::aabb::Aabb;
+use crate::camera::CameraLike;
+use crate::material::Material;
+use crate::mesh::GpuMesh;
+use crate::resources::Handle;
+use crate::spatial_audio::SoundEmitter;
+
+/// Refers to an entity of a [`World`]. Once the entity is despawned, the id
+/// stays invalid, even if its index is reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+	index: u32,
+	/// Which use of `index` the id refers to.
+	generation: u32,
+}
+
+/// The model matrix of an entity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform(pub Matrix4<f32>);
+impl Transform {
+	pub fn identity() -> Self {
+		Self(Matrix4::identity())
+	}
+}
+
+/// The mesh an entity is drawn with, in [`RenderState::resources`].
+///
+/// [`RenderState::resources`]: crate::render_state::RenderState::resources
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub Handle<GpuMesh>);
+
+/// The material an entity is drawn with, in [`RenderState::resources`].
+///
+/// [`RenderState::resources`]: crate::render_state::RenderState::resources
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(pub Handle<Material>);
+
+/// Whether an entity is in view, as last found by [`visibility_system`].
+/// Entities without one are always drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Visible(pub bool);
+
+/// The components of a single kind, at the indices of their entities.
+pub struct ComponentStore<T> {
+	components: Vec<Option<T>>,
+}
+impl<T> ComponentStore<T> {
+	fn new() -> Self {
+		Self {
+			components: Vec::new(),
+		}
+	}
+
+	fn get(&self, index: u32) -> Option<&T> {
+		self.components.get(index as usize)?.as_ref()
+	}
+
+	fn get_mut(&mut self, index: u32) -> Option<&mut T> {
+		self.components.get_mut(index as usize)?.as_mut()
+	}
+
+	fn insert(&mut self, index: u32, component: T) {
+		let index = index as usize;
+		if index >= self.components.len() {
+			self.components.resize_with(index + 1, || None);
+		}
+		self.components[index] = Some(component);
+	}
+
+	fn remove(&mut self, index: u32) -> Option<T> {
+		self.components.get_mut(index as usize)?.take()
+	}
+
+	/// The number of entities with the component.
+	pub fn len(&self) -> usize {
+		self.components.iter().flatten().count()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+/// A kind of component, with a [`ComponentStore`] in every [`World`].
+pub trait Component: Sized + 'static {
+	fn store(world: &World) -> &ComponentStore<Self>;
+	fn store_mut(world: &mut World) -> &mut ComponentStore<Self>;
+}
+
+macro_rules! impl_component {
+	($($component:ty => $field:ident),* $(,)?) => {
+		$(
+			impl Component for $component {
+				fn store(world: &World) -> &ComponentStore<Self> {
+					&world.$field
+				}
+
+				fn store_mut(world: &mut World) -> &mut ComponentStore<Self> {
+					&mut world.$field
+				}
+			}
+		)*
+	};
+}
+impl_component!(
+	Transform => transforms,
+	MeshHandle => meshes,
+	MaterialHandle => materials,
+	Visible => visible,
+	Aabb => aabbs,
+	SoundEmitter => sound_emitters,
+);
+
+/// Components spawned together, see [`World::spawn`]. Implemented for tuples of
+/// up to five components.
+pub trait Bundle {
+	fn insert_into(self, world: &mut World, entity: Entity);
+}
+
+macro_rules! impl_bundle {
+	($($component:ident),*) => {
+		impl<$($component: Component),*> Bundle for ($($component,)*) {
+			#[allow(non_snake_case)]
+			fn insert_into(self, world: &mut World, entity: Entity) {
+				let ($($component,)*) = self;
+				$(world.insert(entity, $component);)*
+			}
+		}
+	};
+}
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+
+/// The entities drawn by [`RenderState`], with their components.
+///
+/// [`RenderState`]: crate::render_state::RenderState
+pub struct World {
+	/// The current generation of each index, see [`Entity`].
+	generations: Vec<u32>,
+	/// Whether each index is used by a live entity.
+	alive: Vec<bool>,
+	/// The indices of despawned entities, to reuse.
+	free: Vec<u32>,
+	transforms: ComponentStore<Transform>,
+	meshes: ComponentStore<MeshHandle>,
+	materials: ComponentStore<MaterialHandle>,
+	visible: ComponentStore<Visible>,
+	/// Bound the entities' meshes in model space.
+	aabbs: ComponentStore<Aabb>,
+	sound_emitters: ComponentStore<SoundEmitter>,
+}
+impl Default for World {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl World {
+	pub fn new() -> Self {
+		Self {
+			generations: Vec::new(),
+			alive: Vec::new(),
+			free: Vec::new(),
+			transforms: ComponentStore::new(),
+			meshes: ComponentStore::new(),
+			materials: ComponentStore::new(),
+			visible: ComponentStore::new(),
+			aabbs: ComponentStore::new(),
+			sound_emitters: ComponentStore::new(),
+		}
+	}
+
+	/// Creates an entity with the components of `bundle`, such as
+	/// `(Transform::identity(), MeshHandle(mesh), MaterialHandle(material))`.
+	pub fn spawn(&mut self, bundle: impl Bundle) -> Entity {
+		let index = match self.free.pop() {
+			Some(index) => index,
+			None => {
+				self.generations.push(0);
+				self.alive.push(false);
+				(self.generations.len() - 1) as u32
+			}
+		};
+		self.alive[index as usize] = true;
+		let entity = Entity {
+			index,
+			generation: self.generations[index as usize],
+		};
+		bundle.insert_into(self, entity);
+		entity
+	}
+
+	/// Removes `entity` and its components. Returns whether it was alive.
+	pub fn despawn(&mut self, entity: Entity) -> bool {
+		if !self.is_alive(entity) {
+			return false;
+		}
+		let index = entity.index;
+		self.transforms.remove(index);
+		self.meshes.remove(index);
+		self.materials.remove(index);
+		self.visible.remove(index);
+		self.aabbs.remove(index);
+		self.sound_emitters.remove(index);
+		self.alive[index as usize] = false;
+		// Invalidates the existing ids of the entity.
+		let generation = &mut self.generations[index as usize];
+		*generation = generation.wrapping_add(1);
+		self.free.push(index);
+		true
+	}
+
+	pub fn is_alive(&self, entity: Entity) -> bool {
+		let index = entity.index as usize;
+		self.alive.get(index) == Some(&true)
+			&& self.generations[index] == entity.generation
+	}
+
+	/// The number of live entities.
+	pub fn len(&self) -> usize {
+		self.generations.len() - self.free.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// The live entities, by increasing index.
+	pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+		(self.alive.iter().zip(&self.generations).enumerate())
+			.filter(|(_, (&alive, _))| alive)
+			.map(|(index, (_, &generation))| Entity {
+				index: index as u32,
+				generation,
+			})
+	}
+
+	/// Adds `component` to `entity`, replacing the one it had of its kind. Does
+	/// nothing if the entity was despawned.
+	pub fn insert<T: Component>(&mut self, entity: Entity, component: T) {
+		if self.is_alive(entity) {
+			T::store_mut(self).insert(entity.index, component);
+		}
+	}
+
+	pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+		if !self.is_alive(entity) {
+			return None;
+		}
+		T::store(self).get(entity.index)
+	}
+
+	pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+		if !self.is_alive(entity) {
+			return None;
+		}
+		T::store_mut(self).get_mut(entity.index)
+	}
+
+	/// Takes the component of kind `T` out of `entity`, if it has one.
+	pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+		if !self.is_alive(entity) {
+			return None;
+		}
+		T::store_mut(self).remove(entity.index)
+	}
+
+	/// The store of the components of kind `T`.
+	pub fn store<T: Component>(&self) -> &ComponentStore<T> {
+		T::store(self)
+	}
+
+	/// The entities with a transform, a mesh and a material, unless they aren't
+	/// [`Visible`], by increasing index.
+	pub fn drawables(
+		&self,
+	) -> impl Iterator<Item = (Entity, &Transform, &MeshHandle, &MaterialHandle)> + '_ {
+		self.entities().filter_map(|entity| {
+			if let Some(Visible(false)) = self.visible.get(entity.index) {
+				return None;
+			}
+			Some((
+				entity,
+				self.transforms.get(entity.index)?,
+				self.meshes.get(entity.index)?,
+				self.materials.get(entity.index)?,
+			))
+		})
+	}
+}
+
+/// Sets the [`Visible`] component of every entity with an [`Aabb`], moved by its
+/// [`Transform`] if it has one, to whether the box may be in `camera`'s frustum.
+pub fn visibility_system(camera: &dyn CameraLike, world: &mut World) {
+	let frustum = camera.frustum();
+	let entities: Vec<_> = world.entities().collect();
+	for entity in entities {
+		let Some(aabb) = world.aabbs.get(entity.index) else {
+			continue;
+		};
+		let aabb = match world.transforms.get(entity.index) {
+			Some(Transform(transform)) => aabb.transformed(transform),
+			None => *aabb,
+		};
+		let visible = Visible(aabb.intersects_frustum(&frustum));
+		world.visible.insert(entity.index, visible);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Vector3;
+
+	fn drawable(world: &mut World, x: f32) -> Entity {
+		world.spawn((
+			Transform(Matrix4::new_translation(&Vector3::new(x, 0., 0.))),
+			MeshHandle(Handle::for_tests(0)),
+			MaterialHandle(Handle::for_tests(0)),
+		))
+	}
+
+	#[test]
+	fn despawned_entities_are_gone() {
+		let mut world = World::new();
+		let first = drawable(&mut world, 1.);
+		assert!(world.despawn(first));
+		assert!(!world.despawn(first));
+		// Reuses the index of `first`.
+		let second = world.spawn((Visible(true),));
+		assert_eq!(world.len(), 1);
+		assert!(!world.is_alive(first));
+		assert_eq!(world.get::<Visible>(first), None);
+		assert_eq!(world.get::<Transform>(second), None);
+		world.insert(first, Transform::identity());
+		assert!(world.store::<Transform>().is_empty());
+	}
+
+	#[test]
+	fn drawables_have_every_component() {
+		let mut world = World::new();
+		let a = drawable(&mut world, 1.);
+		let hidden = drawable(&mut world, 2.);
+		world.insert(hidden, Visible(false));
+		let b = drawable(&mut world, 3.);
+		world.remove::<MaterialHandle>(b);
+		let c = drawable(&mut world, 4.);
+		world.insert(c, Visible(true));
+		let drawn: Vec<_> = world
+			.drawables()
+			.map(|(entity, transform, ..)| (entity, transform.0[(0, 3)]))
+			.collect();
+		assert_eq!(drawn, [(a, 1.), (c, 4.)]);
+	}
+}
@@ -0,0 +1,121 @@
+//! Image-based lighting: reflections of a cubemap surrounding the scene.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::cubemap::Cubemap;
+use crate::reflection_probe::ReflectionProbe;
+use crate::tex2d::Tex2d;
+
+/// In the layout of the shader's `EnvironmentUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct EnvironmentUniform {
+	/// The mip level sampled by fully rough surfaces.
+	max_lod: f32,
+	/// 1 when a [`ReflectionProbe`] is bound, so the shader uses its irradiance
+	/// and BRDF lookup table.
+	probe: u32,
+	_padding: [f32; 2],
+}
+
+/// The cubemap reflected by PBR materials. Reflections are sharp on smooth
+/// surfaces and blurred by sampling smaller mip levels on rough ones, so the
+/// cubemap's mips should be pre-filtered for increasing roughness.
+///
+/// A [`ReflectionProbe`] takes the cubemap's place while it is set.
+pub struct EnvironmentMap {
+	cubemap: Option<Cubemap>,
+	probe: Option<ReflectionProbe>,
+	/// Bound when `cubemap` isn't set. It is black, so nothing is reflected.
+	black: Cubemap,
+	/// Bound in place of the probe's BRDF lookup table without a probe.
+	no_brdf_lut: Tex2d,
+	/// The [`EnvironmentUniform`] of the bound cubemap.
+	pub uniform_buf: wgpu::Buffer,
+}
+impl EnvironmentMap {
+	pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+		let black =
+			Cubemap::from_color(device, queue, Some("No Environment"), [0, 0, 0, 255]);
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Environment Uniform"),
+				contents: bytemuck::bytes_of(&EnvironmentUniform::zeroed()),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let no_brdf_lut =
+			Tex2d::from_color(device, queue, Some("No BRDF Lookup Table"), [0; 4]);
+		Self {
+			cubemap: None,
+			probe: None,
+			black,
+			no_brdf_lut,
+			uniform_buf,
+		}
+	}
+
+	/// Replaces the reflected cubemap, or stops reflecting one with `None`.
+	/// Returns the number of bytes uploaded. The light bind group has to be
+	/// recreated afterwards.
+	pub fn set(&mut self, queue: &wgpu::Queue, cubemap: Option<Cubemap>) -> u64 {
+		self.cubemap = cubemap;
+		self.upload(queue)
+	}
+
+	/// Replaces the reflection probe lighting PBR materials, or goes back to the
+	/// cubemap with `None`. Returns the number of bytes uploaded. The light bind
+	/// group has to be recreated afterwards.
+	pub fn set_probe(
+		&mut self,
+		queue: &wgpu::Queue,
+		probe: Option<ReflectionProbe>,
+	) -> u64 {
+		self.probe = probe;
+		self.upload(queue)
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		let uniform = EnvironmentUniform {
+			max_lod: (self.bound().mip_level_count() - 1) as f32,
+			probe: self.probe.is_some() as u32,
+			..EnvironmentUniform::zeroed()
+		};
+		let bytes = bytemuck::bytes_of(&uniform);
+		queue.write_buffer(&self.uniform_buf, 0, bytes);
+		bytes.len() as u64
+	}
+
+	pub fn cubemap(&self) -> Option<&Cubemap> {
+		self.cubemap.as_ref()
+	}
+
+	pub fn probe(&self) -> Option<&ReflectionProbe> {
+		self.probe.as_ref()
+	}
+
+	/// The cubemap the shader samples for reflections: the probe's pre-filtered
+	/// map if there is one.
+	pub fn bound(&self) -> &Cubemap {
+		match &self.probe {
+			Some(probe) => &probe.prefiltered_map,
+			None => self.cubemap.as_ref().unwrap_or(&self.black),
+		}
+	}
+
+	/// The cubemap the shader samples for diffuse light, black without a probe.
+	pub fn bound_irradiance(&self) -> &Cubemap {
+		match &self.probe {
+			Some(probe) => &probe.irradiance_map,
+			None => &self.black,
+		}
+	}
+
+	/// The BRDF lookup table the shader samples, black without a probe.
+	pub fn bound_brdf_lut(&self) -> &wgpu::TextureView {
+		match &self.probe {
+			Some(probe) => &probe.brdf_lut,
+			None => &self.no_brdf_lut.view,
+		}
+	}
+}
@@ -0,0 +1,255 @@
+//! Recording and replaying of input, for reproducing bugs deterministically.
+
+use color_eyre::{eyre::WrapErr, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+	DeviceId, ElementState, Event, KeyboardInput, MouseScrollDelta, StartCause,
+	TouchPhase, VirtualKeyCode, WindowEvent,
+};
+use winit::window::WindowId;
+use winit_input_helper::WinitInputHelper;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::render_state::RenderState;
+
+/// The input state of a single step of the event loop.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+	pub key_held: Vec<VirtualKeyCode>,
+	pub mouse_delta: (f32, f32),
+	pub scroll: f32,
+	/// Seconds since the previous frame.
+	pub dt: f32,
+}
+
+pub struct EventRecorder {
+	path: PathBuf,
+	frames: Vec<RecordedFrame>,
+	// `WinitInputHelper` can't list the held keys, so we track them ourselves.
+	key_held: Vec<VirtualKeyCode>,
+}
+impl EventRecorder {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			frames: Vec::new(),
+			key_held: Vec::new(),
+		}
+	}
+
+	/// Must be called with every event, before it is given to `WinitInputHelper`.
+	pub fn observe<T>(&mut self, event: &Event<T>) {
+		let Event::WindowEvent {
+			event: WindowEvent::KeyboardInput { input, .. },
+			..
+		} = event
+		else {
+			return;
+		};
+		let Some(key) = input.virtual_keycode else {
+			return;
+		};
+		match input.state {
+			ElementState::Pressed if !self.key_held.contains(&key) => {
+				self.key_held.push(key)
+			}
+			ElementState::Pressed => (),
+			ElementState::Released => self.key_held.retain(|k| *k != key),
+		}
+	}
+
+	/// Snapshots `input` at the end of a step of the event loop that took `dt`
+	/// seconds.
+	pub fn end_frame(&mut self, input: &WinitInputHelper, dt: f32) {
+		self.frames.push(RecordedFrame {
+			key_held: self.key_held.clone(),
+			mouse_delta: input.mouse_diff(),
+			scroll: input.scroll_diff(),
+			dt,
+		});
+	}
+
+	pub fn save(&self) -> Result<()> {
+		let file = File::create(&self.path).wrap_err_with(|| {
+			format!("Failed to create recording {}", self.path.display())
+		})?;
+		bincode::serialize_into(BufWriter::new(file), &self.frames)
+			.wrap_err("Failed to serialize recording")
+	}
+}
+
+/// Feeds recorded frames back through a `WinitInputHelper`, so that the rest of
+/// the app sees the same input it did when recording.
+pub struct EventReplayer {
+	frames: std::vec::IntoIter<RecordedFrame>,
+	input: WinitInputHelper,
+	key_held: Vec<VirtualKeyCode>,
+	cursor: PhysicalPosition<f64>,
+	/// Number of frames replayed so far.
+	#[cfg(not(target_arch = "wasm32"))]
+	replayed: usize,
+	/// Where [`Self::capture_screenshot`] saves the frames, if anywhere.
+	#[cfg(not(target_arch = "wasm32"))]
+	screenshot_dir: Option<PathBuf>,
+}
+impl EventReplayer {
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let file = File::open(path)
+			.wrap_err_with(|| format!("Failed to open recording {}", path.display()))?;
+		let frames: Vec<RecordedFrame> =
+			bincode::deserialize_from(BufReader::new(file))
+				.wrap_err("Failed to deserialize recording")?;
+		Ok(Self::from_frames(frames))
+	}
+
+	/// Replays `frames` instead of a recording, such as input made up by tests.
+	pub fn from_frames(frames: Vec<RecordedFrame>) -> Self {
+		Self {
+			frames: frames.into_iter(),
+			input: WinitInputHelper::new(),
+			key_held: Vec::new(),
+			cursor: PhysicalPosition::new(0., 0.),
+			#[cfg(not(target_arch = "wasm32"))]
+			replayed: 0,
+			#[cfg(not(target_arch = "wasm32"))]
+			screenshot_dir: None,
+		}
+	}
+
+	/// Makes [`Self::capture_screenshot`] save each replayed frame in `dir`, as
+	/// `frame_00000.png` and onwards.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn with_screenshots(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.screenshot_dir = Some(dir.into());
+		self
+	}
+
+	/// Number of frames that have not been replayed yet.
+	pub fn remaining(&self) -> usize {
+		self.frames.len()
+	}
+
+	/// Replays the next frame, returning the input helper in the recorded state
+	/// along with the recorded frame time, or `None` once the recording is
+	/// exhausted.
+	pub fn step(&mut self) -> Option<(&WinitInputHelper, f32)> {
+		let frame = self.frames.next()?;
+
+		// Safety: The dummy ids are never used to look anything up.
+		let (window_id, device_id) = unsafe { (WindowId::dummy(), DeviceId::dummy()) };
+		#[allow(deprecated)]
+		let key_event = |key, state| WindowEvent::KeyboardInput {
+			device_id,
+			input: KeyboardInput {
+				scancode: 0,
+				state,
+				virtual_keycode: Some(key),
+				modifiers: Default::default(),
+			},
+			is_synthetic: true,
+		};
+
+		let mut events = Vec::new();
+		for &key in frame.key_held.iter() {
+			if !self.key_held.contains(&key) {
+				events.push(key_event(key, ElementState::Pressed));
+			}
+		}
+		for &key in self.key_held.iter() {
+			if !frame.key_held.contains(&key) {
+				events.push(key_event(key, ElementState::Released));
+			}
+		}
+		// The input helper derives the mouse delta from cursor movement.
+		if frame.mouse_delta != (0., 0.) {
+			self.cursor.x += frame.mouse_delta.0 as f64;
+			self.cursor.y += frame.mouse_delta.1 as f64;
+			#[allow(deprecated)]
+			events.push(WindowEvent::CursorMoved {
+				device_id,
+				position: self.cursor,
+				modifiers: Default::default(),
+			});
+		}
+		if frame.scroll != 0. {
+			#[allow(deprecated)]
+			events.push(WindowEvent::MouseWheel {
+				device_id,
+				delta: MouseScrollDelta::LineDelta(0., frame.scroll),
+				phase: TouchPhase::Moved,
+				modifiers: Default::default(),
+			});
+		}
+
+		let events = std::iter::once(Event::NewEvents(StartCause::Poll))
+			.chain(
+				events
+					.into_iter()
+					.map(|event| Event::WindowEvent { window_id, event }),
+			)
+			.chain(std::iter::once(Event::MainEventsCleared));
+		for event in events {
+			self.input.update::<()>(&event);
+		}
+		self.key_held = frame.key_held;
+		#[cfg(not(target_arch = "wasm32"))]
+		{
+			self.replayed += 1;
+		}
+
+		Some((&self.input, frame.dt))
+	}
+
+	/// Saves what `state` rendered for the last replayed frame, if enabled with
+	/// [`Self::with_screenshots`]. Must be called after rendering.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn capture_screenshot(&self, state: &mut RenderState) -> Result<()> {
+		let Some(dir) = &self.screenshot_dir else {
+			return Ok(());
+		};
+		let Some(frame) = self.replayed.checked_sub(1) else {
+			return Ok(());
+		};
+		let path = dir.join(format!("frame_{frame:05}.png"));
+		state
+			.capture_screenshot()?
+			.save(&path)
+			.wrap_err_with(|| format!("Failed to save screenshot {}", path.display()))
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::builder::RenderStateBuilder;
+	use crate::gpu_context::test_state;
+
+	#[test]
+	fn saves_a_screenshot_per_frame() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 48) else {
+			return;
+		};
+		let dir = std::env::temp_dir().join("wgpu_experiments_replay_screenshots");
+		std::fs::create_dir_all(&dir).unwrap();
+		let frame = RecordedFrame {
+			key_held: Vec::new(),
+			mouse_delta: (0., 0.),
+			scroll: 0.,
+			dt: 1. / 60.,
+		};
+		let mut replayer = EventReplayer::from_frames(vec![frame.clone(), frame])
+			.with_screenshots(&dir);
+		while replayer.step().is_some() {
+			replayer.capture_screenshot(&mut state).unwrap();
+		}
+		for name in ["frame_00000.png", "frame_00001.png"] {
+			let image = image::open(dir.join(name)).unwrap();
+			assert_eq!((image.width(), image.height()), (64, 48));
+		}
+	}
+}
@@ -0,0 +1,45 @@
+//! Decouples the simulation rate from the frame rate, see
+//! <https://gafferongames.com/post/fix_your_timestep/>.
+
+pub struct FixedTimestep {
+	/// Simulation ticks per second.
+	pub tick_hz: f32,
+	/// Time that has passed but not been simulated yet, in seconds.
+	accumulator: f32,
+}
+impl FixedTimestep {
+	/// The maximum number of ticks per [`Self::advance`]. If simulating is slower
+	/// than real time, we drop time rather than falling further and further behind.
+	pub const MAX_TICKS: u32 = 8;
+
+	pub fn new(tick_hz: f32) -> Self {
+		Self {
+			tick_hz,
+			accumulator: 0.,
+		}
+	}
+
+	/// The duration of a single tick, in seconds.
+	pub fn tick_dt(&self) -> f32 {
+		1. / self.tick_hz
+	}
+
+	/// Accumulates `dt` seconds, and returns how many whole ticks should be
+	/// simulated.
+	pub fn advance(&mut self, dt: f32) -> u32 {
+		let tick_dt = self.tick_dt();
+		self.accumulator += dt;
+		let mut ticks = 0;
+		while self.accumulator >= tick_dt {
+			self.accumulator -= tick_dt;
+			ticks += 1;
+		}
+		ticks.min(Self::MAX_TICKS)
+	}
+
+	/// How far we are between the last tick and the next one, in `[0, 1)`. Used
+	/// to interpolate between the last two simulated states when rendering.
+	pub fn alpha(&self) -> f32 {
+		self.accumulator * self.tick_hz
+	}
+}
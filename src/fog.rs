@@ -0,0 +1,85 @@
+//! Fog, which fades surfaces into a color with their distance from the camera.
+
+use bytemuck::{Pod, Zeroable};
+
+/// Fog, in the layout of the shader's `FogUniform`.
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct FogUniform {
+	/// The color surfaces fade into. Alpha is ignored.
+	pub color: [f32; 4],
+	/// Where linear fog starts, in world units from the camera.
+	pub start: f32,
+	/// Where linear fog completely hides surfaces.
+	pub end: f32,
+	/// How quickly exponential fog thickens.
+	pub density: f32,
+	/// One of [`Self::LINEAR`], [`Self::EXPONENTIAL`] and
+	/// [`Self::EXPONENTIAL_SQUARED`]. Other values disable fog.
+	pub mode: u32,
+}
+impl FogUniform {
+	/// Fog grows linearly from `start` to `end`.
+	pub const LINEAR: u32 = 0;
+	/// Surfaces are visible by `exp(-density * distance)`.
+	pub const EXPONENTIAL: u32 = 1;
+	/// Surfaces are visible by `exp(-(density * distance)²)`, so fog starts
+	/// more gradually.
+	pub const EXPONENTIAL_SQUARED: u32 = 2;
+	/// The shader leaves surfaces as they are.
+	pub(crate) const DISABLED: u32 = u32::MAX;
+
+	pub fn linear(color: [f32; 3], start: f32, end: f32) -> Self {
+		Self {
+			start,
+			end,
+			mode: Self::LINEAR,
+			..Self::with_color(color)
+		}
+	}
+
+	pub fn exponential(color: [f32; 3], density: f32) -> Self {
+		Self {
+			density,
+			mode: Self::EXPONENTIAL,
+			..Self::with_color(color)
+		}
+	}
+
+	pub fn exponential_squared(color: [f32; 3], density: f32) -> Self {
+		Self {
+			density,
+			mode: Self::EXPONENTIAL_SQUARED,
+			..Self::with_color(color)
+		}
+	}
+
+	/// No fog at all.
+	pub fn disabled() -> Self {
+		Self {
+			mode: Self::DISABLED,
+			..Self::with_color([0.; 3])
+		}
+	}
+
+	fn with_color([r, g, b]: [f32; 3]) -> Self {
+		Self {
+			color: [r, g, b, 1.],
+			start: 0.,
+			end: 0.,
+			density: 0.,
+			mode: Self::DISABLED,
+		}
+	}
+
+	/// Whether the fog has any effect.
+	pub fn is_enabled(&self) -> bool {
+		self.mode <= Self::EXPONENTIAL_SQUARED
+	}
+}
+impl Default for FogUniform {
+	fn default() -> Self {
+		Self::disabled()
+	}
+}
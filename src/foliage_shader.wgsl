@@ -0,0 +1,34 @@
+// Foliage swaying in the wind, appended to `shader.wgsl`.
+
+// See `wind.rs`. It shares the light's group, as all four groups are in use.
+struct WindUniform {
+	direction: vec3<f32>,
+	strength: f32,
+	frequency: f32,
+	// The phase of the waves.
+	time: f32,
+};
+@group(2) @binding(15)
+var<uniform> wind: WindUniform;
+
+// Like `vs_main`, with the vertices moved along the wind in world space. Their
+// uv's v masks the movement, so that the base of a plant, at v = 0, stays in
+// place.
+@vertex
+fn vs_foliage(
+	verts: VertexInput,
+	instance: InstanceInput,
+) -> VertexOutput {
+	let transform = model * mat4x4<f32>(
+		instance.transform_0,
+		instance.transform_1,
+		instance.transform_2,
+		instance.transform_3,
+	);
+	var out = transform_vertex(verts, transform);
+	let sway = sin(out.world_pos.x * wind.frequency + wind.time) * wind.strength
+		* verts.uv.y;
+	out.world_pos += wind.direction * sway;
+	out.clip_pos = camera.view_proj * vec4<f32>(out.world_pos, 1.0);
+	return out;
+}
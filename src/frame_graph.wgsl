@@ -0,0 +1,11 @@
+// Draws the frame time graph of `perf.rs`, as a line strip in clip space.
+
+@vertex
+fn vs_main(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+	return vec4<f32>(pos, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+	return vec4<f32>(0.2, 1.0, 0.3, 1.0);
+}
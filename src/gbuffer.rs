@@ -0,0 +1,395 @@
+//! The depth, normals and motion of the opaque geometry, drawn in a pass of
+//! their own before the scene, for screen-space effects to read.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::Matrix4;
+
+use crate::types::mat4_to_wgsl;
+use crate::vertex::{Instance, Vertex};
+
+/// The layout of `shader.wgsl`'s `PreviousTransform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct PreviousTransform {
+	current: [[f32; 4]; 4],
+	previous: [[f32; 4]; 4],
+}
+
+/// Textures holding the depth, world space normals and motion vectors of the
+/// main camera's view, and the pipeline drawing them.
+///
+/// The motion of each object is found from its projection times model matrix
+/// this frame and the previous one, without jitter, uploaded by
+/// [`Self::upload_transforms`] and kept by [`Self::update_previous_transforms`].
+pub struct GBuffer {
+	/// The depth, copied into a color texture as depth textures can't be read
+	/// with `textureLoad` on all backends.
+	depth_view: wgpu::TextureView,
+	/// Depth tests the geometry pass.
+	depth_buffer_view: wgpu::TextureView,
+	/// The world space normals, mapped to 0 to 1.
+	normal_view: wgpu::TextureView,
+	/// How far each pixel moved on screen since the previous frame, in NDC.
+	motion_view: wgpu::TextureView,
+	/// The projection times model matrices of the objects, by their index in the
+	/// object uniforms, and of the previous frame.
+	transforms: Vec<Matrix4<f32>>,
+	previous_transforms: Vec<Matrix4<f32>>,
+	/// Holds a [`PreviousTransform`] per object, `transform_stride` apart.
+	transform_buf: wgpu::Buffer,
+	transform_layout: wgpu::BindGroupLayout,
+	transform_bind_group: wgpu::BindGroup,
+	transform_capacity: u32,
+	transform_stride: u32,
+	/// The depth where nothing was drawn.
+	far_depth: f32,
+	/// Bound to the groups of the main pipeline layout that the pass doesn't use.
+	empty_bind_group: wgpu::BindGroup,
+	#[cfg(feature = "hot-reload")]
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+impl GBuffer {
+	pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+	const DEPTH_BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+	pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+	pub const MOTION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+	/// The bind group of the transforms, in place of the main pipeline's light.
+	const TRANSFORM_BIND_GROUP: u32 = 2;
+
+	/// Creates the textures for `width` x `height` frames. The geometry pass uses
+	/// `vs_motion` and `fs_normal` of `shader`, with the main pipeline's camera
+	/// and object bind group layouts.
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		device: &wgpu::Device,
+		width: u32,
+		height: u32,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+		reverse_z: bool,
+	) -> Self {
+		let empty_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Empty Bind Group Layout"),
+				entries: &[],
+			});
+		let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("empty_bind_group"),
+			layout: &empty_layout,
+			entries: &[],
+		});
+		let transform_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Previous Transform Bind Group Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					// After the light's bindings, which share the group in the shader.
+					binding: 15,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: true,
+						min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
+							PreviousTransform,
+						>() as u64),
+					},
+					count: None,
+				}],
+			});
+		let alignment = device.limits().min_uniform_buffer_offset_alignment;
+		let size = std::mem::size_of::<PreviousTransform>() as u32;
+		let transform_stride = ((size - 1) / alignment + 1) * alignment;
+		let (transform_buf, transform_bind_group) =
+			create_transform_buffer(device, &transform_layout, 1, transform_stride);
+		// The material group is left empty.
+		let mut bind_group_layouts =
+			vec![&empty_layout, camera_layout, &transform_layout];
+		bind_group_layouts.extend(object_layout);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("G-Buffer Pipeline Layout"),
+			bind_group_layouts: &bind_group_layouts,
+			push_constant_ranges,
+		});
+		let pipeline = create_pipeline(device, &layout, shader, reverse_z);
+		let (depth_view, depth_buffer_view, normal_view, motion_view) =
+			create_views(device, width, height);
+		Self {
+			depth_view,
+			depth_buffer_view,
+			normal_view,
+			motion_view,
+			transforms: Vec::new(),
+			previous_transforms: Vec::new(),
+			transform_buf,
+			transform_layout,
+			transform_bind_group,
+			transform_capacity: 1,
+			transform_stride,
+			far_depth: if reverse_z { 0. } else { 1. },
+			empty_bind_group,
+			#[cfg(feature = "hot-reload")]
+			layout,
+			pipeline,
+		}
+	}
+
+	/// Recreates the textures for `width` x `height` frames. Bind groups reading
+	/// them must be recreated too.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		(
+			self.depth_view,
+			self.depth_buffer_view,
+			self.normal_view,
+			self.motion_view,
+		) = create_views(device, width, height);
+	}
+
+	/// Uploads the matrices placing the objects this frame, by their index in
+	/// the object uniforms, times the unjittered `view_proj`. Objects without
+	/// matrices from the previous frame haven't moved. Returns the number of
+	/// bytes written.
+	pub fn upload_transforms(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		view_proj: &Matrix4<f32>,
+		transforms: &[Matrix4<f32>],
+	) -> u64 {
+		self.transforms.clear();
+		self.transforms
+			.extend(transforms.iter().map(|transform| view_proj * transform));
+		let count = transforms.len() as u32;
+		if count > self.transform_capacity {
+			self.transform_capacity = count.next_power_of_two();
+			(self.transform_buf, self.transform_bind_group) = create_transform_buffer(
+				device,
+				&self.transform_layout,
+				self.transform_capacity,
+				self.transform_stride,
+			);
+		}
+		let mut bytes = vec![0; count as usize * self.transform_stride as usize];
+		for (i, chunk) in bytes
+			.chunks_exact_mut(self.transform_stride as usize)
+			.enumerate()
+		{
+			let current = self.transforms[i];
+			let previous = self.previous_transforms.get(i).unwrap_or(&current);
+			let uniform = PreviousTransform {
+				current: mat4_to_wgsl(current),
+				previous: mat4_to_wgsl(*previous),
+			};
+			chunk[..std::mem::size_of::<PreviousTransform>()]
+				.copy_from_slice(bytemuck::bytes_of(&uniform));
+		}
+		queue.write_buffer(&self.transform_buf, 0, &bytes);
+		bytes.len() as u64
+	}
+
+	/// Keeps the matrices last uploaded as the previous frame's, at the end of a
+	/// frame.
+	pub fn update_previous_transforms(&mut self) {
+		std::mem::swap(&mut self.previous_transforms, &mut self.transforms);
+		self.transforms.clear();
+	}
+
+	/// Binds the matrices of the `index`th object, for the following draws.
+	pub fn set_transform<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, index: usize) {
+		pass.set_bind_group(
+			Self::TRANSFORM_BIND_GROUP,
+			&self.transform_bind_group,
+			&[index as u32 * self.transform_stride],
+		);
+	}
+
+	/// Recreates the pipeline with a new version of `shader.wgsl`.
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		let reverse_z = self.far_depth == 0.;
+		self.pipeline = create_pipeline(device, &self.layout, shader, reverse_z);
+	}
+
+	/// The depth drawn by [`Self::begin_pass`], in the red channel.
+	pub fn depth_view(&self) -> &wgpu::TextureView {
+		&self.depth_view
+	}
+
+	/// The normals drawn by [`Self::begin_pass`].
+	pub fn normal_view(&self) -> &wgpu::TextureView {
+		&self.normal_view
+	}
+
+	/// The motion vectors drawn by [`Self::begin_pass`]: how far each pixel moved
+	/// in NDC since the previous frame, in the red and green channels. 0 where
+	/// nothing was drawn.
+	pub fn motion_view(&self) -> &wgpu::TextureView {
+		&self.motion_view
+	}
+
+	/// Begins the pass drawing the depth, normals and motion of the scene, with
+	/// its pipeline set. The camera and object bind groups are left to the
+	/// caller, and each object's transforms, see [`Self::set_transform`].
+	pub fn begin_pass<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+	) -> wgpu::RenderPass<'a> {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("G-Buffer Pass"),
+			color_attachments: &[
+				Some(wgpu::RenderPassColorAttachment {
+					view: &self.normal_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+						store: true,
+					},
+				}),
+				Some(wgpu::RenderPassColorAttachment {
+					view: &self.depth_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color {
+							r: self.far_depth.into(),
+							..wgpu::Color::TRANSPARENT
+						}),
+						store: true,
+					},
+				}),
+				Some(wgpu::RenderPassColorAttachment {
+					view: &self.motion_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+						store: true,
+					},
+				}),
+			],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_buffer_view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(self.far_depth),
+					store: true,
+				}),
+				stencil_ops: None,
+			}),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.empty_bind_group, &[]);
+		pass
+	}
+}
+
+fn create_transform_buffer(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	capacity: u32,
+	stride: u32,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+	let buf = device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Previous Transforms"),
+		size: capacity as u64 * stride as u64,
+		usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("previous_transform_bind_group"),
+		layout,
+		entries: &[wgpu::BindGroupEntry {
+			binding: 15,
+			resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+				buffer: &buf,
+				offset: 0,
+				size: wgpu::BufferSize::new(
+					std::mem::size_of::<PreviousTransform>() as u64
+				),
+			}),
+		}],
+	});
+	(buf, bind_group)
+}
+
+/// The depth, depth buffer, normal and motion textures, for `width` x `height`
+/// frames.
+fn create_views(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+) -> (
+	wgpu::TextureView,
+	wgpu::TextureView,
+	wgpu::TextureView,
+	wgpu::TextureView,
+) {
+	let view = |label, format| {
+		device
+			.create_texture(&wgpu::TextureDescriptor {
+				label: Some(label),
+				size: wgpu::Extent3d {
+					width,
+					height,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+					| wgpu::TextureUsages::TEXTURE_BINDING,
+				view_formats: &[],
+			})
+			.create_view(&wgpu::TextureViewDescriptor::default())
+	};
+	(
+		view("G-Buffer Depth", GBuffer::DEPTH_FORMAT),
+		view("G-Buffer Depth Buffer", GBuffer::DEPTH_BUFFER_FORMAT),
+		view("G-Buffer Normals", GBuffer::NORMAL_FORMAT),
+		view("G-Buffer Motion", GBuffer::MOTION_FORMAT),
+	)
+}
+
+/// Draws the depth, world space normals and motion of opaque triangles, with
+/// `vs_motion` and `fs_normal` of `shader.wgsl`.
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	reverse_z: bool,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("G-Buffer Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_motion",
+			buffers: &[Vertex::vb_layout(), Instance::vb_layout()],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_normal",
+			targets: &[
+				Some(GBuffer::NORMAL_FORMAT.into()),
+				Some(GBuffer::DEPTH_FORMAT.into()),
+				Some(GBuffer::MOTION_FORMAT.into()),
+			],
+		}),
+		primitive: wgpu::PrimitiveState {
+			cull_mode: Some(wgpu::Face::Back),
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: GBuffer::DEPTH_BUFFER_FORMAT,
+			depth_write_enabled: true,
+			depth_compare: if reverse_z {
+				wgpu::CompareFunction::Greater
+			} else {
+				wgpu::CompareFunction::Less
+			},
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
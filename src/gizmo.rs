@@ -0,0 +1,294 @@
+//! Arrows showing the axes of a selected object, for editor tools to drag.
+
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Ray;
+use crate::types::mat4_to_wgsl;
+use crate::vertex::{ColorVertex, Pos};
+
+/// Sides of the shafts and cone heads.
+const SEGMENTS: u32 = 12;
+/// In units of the gizmo's scale, like the other sizes. The arrows are 1 long.
+const SHAFT_RADIUS: f32 = 0.02;
+const SHAFT_LENGTH: f32 = 0.8;
+const HEAD_RADIUS: f32 = 0.07;
+/// How close a ray must pass by an axis to hit it, wider than the arrows so
+/// that they are easier to grab.
+const HIT_RADIUS: f32 = 0.1;
+
+/// An axis of the gizmo.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GizmoAxis {
+	X,
+	Y,
+	Z,
+}
+impl GizmoAxis {
+	pub const ALL: [Self; 3] = [Self::X, Self::Y, Self::Z];
+
+	/// The unit vector along the axis, before the gizmo's transform.
+	pub fn direction(self) -> Vector3<f32> {
+		match self {
+			Self::X => Vector3::x(),
+			Self::Y => Vector3::y(),
+			Self::Z => Vector3::z(),
+		}
+	}
+
+	/// Red, green and blue for X, Y and Z.
+	pub fn color(self) -> [f32; 4] {
+		let [r, g, b] = self.direction().into();
+		[r, g, b, 1.]
+	}
+}
+
+/// Draws an arrow along each axis of a transform, over everything else, and
+/// finds the axis under the mouse cursor.
+///
+/// Each arrow is a cylinder shaft with a cone head, in its axis' color. The
+/// pipeline has no depth test, so the gizmo is never hidden by the scene.
+pub struct GizmoRenderer {
+	vtx_buf: wgpu::Buffer,
+	idx_buf: wgpu::Buffer,
+	num_indices: u32,
+	/// The model matrix of the arrows.
+	model_buf: wgpu::Buffer,
+	model_bind_group: wgpu::BindGroup,
+	/// Of the last draw, which `hit_test` tests against.
+	transform: Matrix4<f32>,
+	scale: f32,
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+impl GizmoRenderer {
+	/// Creates a renderer drawing into `format` textures, in passes without a
+	/// depth attachment. `camera_layout` is the layout of the main pipeline's
+	/// camera bind group, bound at group 0.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		camera_layout: &wgpu::BindGroupLayout,
+	) -> Self {
+		let mut vertices = Vec::new();
+		let mut indices = Vec::new();
+		for axis in GizmoAxis::ALL {
+			add_arrow(&mut vertices, &mut indices, axis);
+		}
+		let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Gizmo Vertex Buffer"),
+			contents: bytemuck::cast_slice(&vertices),
+			usage: wgpu::BufferUsages::VERTEX,
+		});
+		let idx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Gizmo Index Buffer"),
+			contents: bytemuck::cast_slice(&indices),
+			usage: wgpu::BufferUsages::INDEX,
+		});
+		let model_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Gizmo Model Uniform"),
+			size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let model_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Gizmo Model Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let model_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("gizmo_model_bind_group"),
+			layout: &model_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: model_buf.as_entire_binding(),
+			}],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("gizmo.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Gizmo Pipeline Layout"),
+			bind_group_layouts: &[camera_layout, &model_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		Self {
+			vtx_buf,
+			idx_buf,
+			num_indices: indices.len() as u32,
+			model_buf,
+			model_bind_group,
+			transform: Matrix4::identity(),
+			scale: 1.,
+			shader,
+			layout,
+			pipeline,
+		}
+	}
+
+	/// Recreates the pipeline for a different frame format.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Records drawing the arrows at `center`, along the axes of `transform`
+	/// (whose translation is ignored), `scale` long. The camera bind group must
+	/// already be set at group 0. Returns the number of bytes uploaded.
+	pub fn draw<'a>(
+		&'a mut self,
+		queue: &wgpu::Queue,
+		render_pass: &mut wgpu::RenderPass<'a>,
+		center: Point3<f32>,
+		transform: Matrix4<f32>,
+		scale: f32,
+	) -> u64 {
+		self.transform = transform;
+		self.transform.set_column(3, &Vector4::w());
+		self.scale = scale;
+		let model = Matrix4::new_translation(&center.coords)
+			* self.transform
+			* Matrix4::new_scaling(scale);
+		let model = mat4_to_wgsl(model);
+		queue.write_buffer(&self.model_buf, 0, bytemuck::bytes_of(&model));
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(1, &self.model_bind_group, &[]);
+		render_pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+		render_pass.set_index_buffer(self.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+		render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+		std::mem::size_of_val(&model) as u64
+	}
+
+	/// The axis that `ray` passes closest to the camera by, among those it passes
+	/// near enough to grab, for arrows at `center` with the transform and scale
+	/// of the last [`Self::draw`].
+	pub fn hit_test(&self, ray: &Ray, center: Point3<f32>) -> Option<GizmoAxis> {
+		GizmoAxis::ALL
+			.into_iter()
+			.filter_map(|axis| {
+				let end = center
+					+ self
+						.transform
+						.transform_vector(&(axis.direction() * self.scale));
+				let (distance, t) = ray_segment_distance(ray, &center, &end);
+				(distance <= HIT_RADIUS * self.scale).then_some((t, axis))
+			})
+			.min_by(|(a, _), (b, _)| a.total_cmp(b))
+			.map(|(_, axis)| axis)
+	}
+}
+
+/// The distance between `ray` and the segment from `a` to `b` where they pass
+/// closest, and how far along the ray that is.
+fn ray_segment_distance(ray: &Ray, a: &Point3<f32>, b: &Point3<f32>) -> (f32, f32) {
+	let (d1, d2, r) = (ray.direction, b - a, ray.origin - a);
+	let (dd1, dd2) = (d1.dot(&d1), d2.dot(&d2));
+	let (d12, r1, r2) = (d1.dot(&d2), d1.dot(&r), d2.dot(&r));
+	// Closest to the segment's start, for degenerate rays and segments.
+	let closest_to_start = || (-r1 / dd1).max(0.);
+	let (t, s) = if dd1 <= f32::EPSILON {
+		(0., (r2 / dd2).clamp(0., 1.))
+	} else if dd2 <= f32::EPSILON {
+		(closest_to_start(), 0.)
+	} else {
+		let denom = dd1 * dd2 - d12 * d12;
+		// Parallel lines are closest everywhere, so start at the ray's origin.
+		let t = if denom <= f32::EPSILON * dd1 * dd2 {
+			0.
+		} else {
+			((d12 * r2 - r1 * dd2) / denom).max(0.)
+		};
+		let s = (d12 * t + r2) / dd2;
+		if s < 0. {
+			(closest_to_start(), 0.)
+		} else if s > 1. {
+			(((d12 - r1) / dd1).max(0.), 1.)
+		} else {
+			(t, s)
+		}
+	};
+	(nalgebra::distance(&ray.at(t), &(a + d2 * s)), t)
+}
+
+/// Adds the triangles of the arrow along `axis`, from the origin to 1 along it.
+fn add_arrow(vertices: &mut Vec<ColorVertex>, indices: &mut Vec<u32>, axis: GizmoAxis) {
+	let dir = axis.direction();
+	// Two directions perpendicular to the axis and each other.
+	let (u, v) = (dir.yzx(), dir.zxy());
+	let color = axis.color();
+	let mut point = |p: Vector3<f32>| {
+		vertices.push(ColorVertex::new(Pos::new(p.x, p.y, p.z), color.into()));
+		vertices.len() as u32 - 1
+	};
+	let mut ring = |along: f32, radius: f32| -> Vec<u32> {
+		(0..SEGMENTS)
+			.map(|i| {
+				let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+				let offset = (u * angle.cos() + v * angle.sin()) * radius;
+				point(dir * along + offset)
+			})
+			.collect()
+	};
+	let shaft_start = ring(0., SHAFT_RADIUS);
+	let shaft_end = ring(SHAFT_LENGTH, SHAFT_RADIUS);
+	let head_base = ring(SHAFT_LENGTH, HEAD_RADIUS);
+	let start = point(Vector3::zeros());
+	let head_center = point(dir * SHAFT_LENGTH);
+	let tip = point(dir);
+
+	let n = SEGMENTS as usize;
+	for i in 0..n {
+		let j = (i + 1) % n;
+		let (a, b, c, d) = (shaft_start[i], shaft_start[j], shaft_end[j], shaft_end[i]);
+		indices.extend_from_slice(&[a, b, c, c, d, a]);
+	}
+	// The fans closing the shaft's start, the head's base and its cone.
+	for (center, rim) in [
+		(start, &shaft_start),
+		(head_center, &head_base),
+		(tip, &head_base),
+	] {
+		for i in 0..n {
+			indices.extend_from_slice(&[center, rim[i], rim[(i + 1) % n]]);
+		}
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Gizmo Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[ColorVertex::vb_layout()],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState {
+			// The fans are wound both ways.
+			cull_mode: None,
+			..Default::default()
+		},
+		// Drawn over everything, without depth testing.
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
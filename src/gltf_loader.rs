@@ -0,0 +1,373 @@
+//! Loading binary glTF (GLB) files.
+//!
+//! Only what can be drawn with the basic pipeline is read: triangle meshes, base
+//! color textures, node transforms and cameras. Data must be embedded in the
+//! file, external buffers and images are not supported.
+
+use color_eyre::{
+	eyre::{bail, eyre, WrapErr},
+	Result,
+};
+use gltf::{buffer, camera::Projection, image, mesh::Mode, texture};
+use log::warn;
+use nalgebra::{Matrix4, Orthographic3, Perspective3, Point3, Vector3};
+
+use crate::camera::Camera;
+use crate::mesh::{compute_tangents, GpuMesh};
+use crate::obj_loader::compute_missing_normals;
+use crate::tex2d::{SamplerConfig, Shape, Tex2d};
+use crate::vertex::{Normal, Pos, Uv, Vertex};
+
+/// The default far plane, for perspective cameras without one.
+const DEFAULT_ZFAR: f32 = 1000.;
+
+/// Everything in a glTF file, uploaded to the GPU.
+pub struct GltfScene {
+	/// One per primitive of each glTF mesh.
+	pub meshes: Vec<GpuMesh>,
+	pub textures: Vec<Tex2d>,
+	/// One per node with a camera, placed where that node is.
+	pub cameras: Vec<Camera>,
+	/// What to draw: every mesh of every node in the default scene.
+	pub instances: Vec<GltfInstance>,
+}
+
+/// A mesh placed in the scene.
+#[derive(Copy, Clone, Debug)]
+pub struct GltfInstance {
+	/// Index into [`GltfScene::meshes`].
+	pub mesh: usize,
+	/// Index into [`GltfScene::textures`] of the base color texture. Meshes without
+	/// one use a white texture.
+	pub texture: usize,
+	/// The model matrix, with the transforms of all parent nodes applied.
+	pub transform: Matrix4<f32>,
+}
+
+/// Parses a GLB file and uploads its contents.
+///
+/// Only `TRIANGLES` primitives are loaded, others are skipped with a warning.
+/// Missing UVs default to `(0, 0)` and missing normals are computed from the
+/// triangles. Camera nodes can't be rolled, so any roll is dropped.
+pub fn load_gltf(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	bytes: &[u8],
+) -> Result<GltfScene> {
+	let gltf = gltf::Gltf::from_slice(bytes).wrap_err("Failed to parse glTF")?;
+	let blob = gltf.blob.as_deref();
+	let buffer_data = |buffer: buffer::Buffer<'_>| match buffer.source() {
+		buffer::Source::Bin => blob,
+		buffer::Source::Uri(_) => None,
+	};
+	if let Some(buffer) = gltf.buffers().find(|b| buffer_data(b.clone()).is_none()) {
+		bail!("Buffer {} is not embedded in the GLB file", buffer.index());
+	}
+
+	let mut textures = gltf
+		.textures()
+		.map(|t| load_texture(device, queue, blob, &t))
+		.collect::<Result<Vec<_>>>()?;
+	// Created only if some mesh needs it.
+	let mut white_texture: Option<usize> = None;
+
+	// The loaded meshes of each glTF mesh, with their texture.
+	let mut mesh_primitives: Vec<Vec<(usize, usize)>> = Vec::new();
+	let mut meshes = Vec::new();
+	for mesh in gltf.meshes() {
+		let mut primitives = Vec::new();
+		for primitive in mesh.primitives() {
+			if primitive.mode() != Mode::Triangles {
+				warn!(
+					"Skipping {:?} primitive of mesh {}",
+					primitive.mode(),
+					mesh.index()
+				);
+				continue;
+			}
+			let reader = primitive.reader(buffer_data);
+			let positions = reader.read_positions().ok_or_else(|| {
+				eyre!("Primitive of mesh {} has no positions", mesh.index())
+			})?;
+			let mut vertices: Vec<Vertex> = positions
+				.map(|[x, y, z]| {
+					Vertex::new(
+						Pos::new(x, y, z),
+						Uv { u: 0., v: 0. },
+						Normal::new(0., 0., 0.),
+					)
+				})
+				.collect();
+			let indices: Vec<u32> = match reader.read_indices() {
+				Some(indices) => indices.into_u32().collect(),
+				None => (0..vertices.len() as u32).collect(),
+			};
+			if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+				bail!("Index {i} of mesh {} is out of bounds", mesh.index());
+			}
+
+			let info = primitive
+				.material()
+				.pbr_metallic_roughness()
+				.base_color_texture();
+			let tex_coord = info.as_ref().map_or(0, |info| info.tex_coord());
+			if let Some(uvs) = reader.read_tex_coords(tex_coord) {
+				for (vertex, [u, v]) in vertices.iter_mut().zip(uvs.into_f32()) {
+					vertex.uv = Uv { u, v };
+				}
+			}
+			match reader.read_normals() {
+				Some(normals) => {
+					for (vertex, [x, y, z]) in vertices.iter_mut().zip(normals) {
+						vertex.normal = Normal::new(x, y, z);
+					}
+				}
+				None => {
+					let missing = vec![true; vertices.len()];
+					compute_missing_normals(&mut vertices, &indices, &missing);
+				}
+			}
+			compute_tangents(&mut vertices, &indices);
+
+			let texture = match info {
+				Some(info) => info.texture().index(),
+				None => *white_texture.get_or_insert_with(|| {
+					textures.push(Tex2d::new_from_rgba8(
+						device,
+						queue,
+						Some("glTF White"),
+						&[255; 4],
+						Shape {
+							width: 1,
+							height: 1,
+						},
+						SamplerConfig::default(),
+					));
+					textures.len() - 1
+				}),
+			};
+			primitives.push((meshes.len(), texture));
+			meshes.push(GpuMesh::new(device, &vertices, &indices));
+		}
+		mesh_primitives.push(primitives);
+	}
+
+	let mut instances = Vec::new();
+	let mut cameras = Vec::new();
+	let scene = gltf.default_scene().or_else(|| gltf.scenes().next());
+	// Nodes to visit, with the transform of their parent.
+	let mut stack: Vec<(gltf::Node<'_>, Matrix4<f32>)> = scene
+		.into_iter()
+		.flat_map(|s| s.nodes())
+		.map(|n| (n, Matrix4::identity()))
+		.collect();
+	while let Some((node, parent)) = stack.pop() {
+		let transform = parent * Matrix4::from(node.transform().matrix());
+		if let Some(mesh) = node.mesh() {
+			for &(mesh, texture) in &mesh_primitives[mesh.index()] {
+				instances.push(GltfInstance {
+					mesh,
+					texture,
+					transform,
+				});
+			}
+		}
+		if let Some(camera) = node.camera() {
+			cameras.push(load_camera(&camera, &transform));
+		}
+		stack.extend(node.children().map(|n| (n, transform)));
+	}
+
+	Ok(GltfScene {
+		meshes,
+		textures,
+		cameras,
+		instances,
+	})
+}
+
+fn load_texture(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	blob: Option<&[u8]>,
+	texture: &texture::Texture<'_>,
+) -> Result<Tex2d> {
+	let image = texture.source();
+	let image::Source::View { view, .. } = image.source() else {
+		bail!("Image {} is not embedded in the GLB file", image.index());
+	};
+	let bytes = blob
+		.and_then(|b| b.get(view.offset()..view.offset() + view.length()))
+		.ok_or_else(|| eyre!("Image {} is out of bounds", image.index()))?;
+	let img = ::image::load_from_memory(bytes)
+		.wrap_err_with(|| format!("Failed to decode image {}", image.index()))?;
+	let label = format!("glTF Texture {}", texture.index());
+	Ok(Tex2d::new_from_img(
+		device,
+		queue,
+		Some(&label),
+		img,
+		sampler_config(&texture.sampler()),
+	))
+}
+
+/// glTF leaves filtering up to the implementation when unspecified, so that
+/// defaults to linear with mipmaps.
+fn sampler_config(sampler: &texture::Sampler<'_>) -> SamplerConfig {
+	use texture::{MagFilter, MinFilter, WrappingMode};
+
+	let address_mode = |mode| match mode {
+		WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+		WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+		WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+	};
+	let mut config = SamplerConfig {
+		address_mode_u: address_mode(sampler.wrap_s()),
+		address_mode_v: address_mode(sampler.wrap_t()),
+		..SamplerConfig::tiling()
+	};
+	if sampler.mag_filter() == Some(MagFilter::Nearest) {
+		config.mag_filter = wgpu::FilterMode::Nearest;
+	}
+	if let Some(min_filter) = sampler.min_filter() {
+		let nearest = wgpu::FilterMode::Nearest;
+		match min_filter {
+			MinFilter::Nearest => {
+				config.min_filter = nearest;
+				config.generate_mipmaps = false;
+			}
+			MinFilter::Linear => config.generate_mipmaps = false,
+			MinFilter::NearestMipmapNearest => {
+				config.min_filter = nearest;
+				config.mipmap_filter = nearest;
+			}
+			MinFilter::LinearMipmapNearest => config.mipmap_filter = nearest,
+			MinFilter::NearestMipmapLinear => config.min_filter = nearest,
+			MinFilter::LinearMipmapLinear => {}
+		}
+	}
+	config
+}
+
+/// Creates a camera at `transform`, looking down its -z axis as in glTF.
+fn load_camera(camera: &gltf::Camera<'_>, transform: &Matrix4<f32>) -> Camera {
+	let position = transform.transform_point(&Point3::origin());
+	let forward = transform
+		.transform_vector(&-Vector3::z())
+		.try_normalize(f32::EPSILON)
+		.unwrap_or_else(|| -Vector3::z());
+	// The inverse of `Camera::rotation`, which looks down -z at zero yaw and
+	// pitch.
+	let yaw = (-forward.x).atan2(-forward.z);
+	let pitch = forward.y.clamp(-1., 1.).asin();
+	match camera.projection() {
+		Projection::Perspective(p) => {
+			let proj = Perspective3::new(
+				p.aspect_ratio().unwrap_or(1.),
+				p.yfov(),
+				p.znear(),
+				p.zfar().unwrap_or(DEFAULT_ZFAR),
+			);
+			Camera::new(position, yaw, pitch, proj)
+		}
+		Projection::Orthographic(o) => {
+			let proj = Orthographic3::new(
+				-o.xmag(),
+				o.xmag(),
+				-o.ymag(),
+				o.ymag(),
+				o.znear(),
+				o.zfar(),
+			);
+			Camera::new(position, yaw, pitch, proj)
+		}
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::gpu_context::test_context;
+
+	/// A GLB file of `json` and the binary chunk `bin`, each padded to 4 bytes.
+	fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+		let mut json = json.as_bytes().to_vec();
+		json.resize((json.len() + 3) / 4 * 4, b' ');
+		let mut bin = bin.to_vec();
+		bin.resize((bin.len() + 3) / 4 * 4, 0);
+		let length = 12 + 8 + json.len() + 8 + bin.len();
+
+		let mut out = Vec::with_capacity(length);
+		out.extend_from_slice(b"glTF");
+		out.extend_from_slice(&2u32.to_le_bytes());
+		out.extend_from_slice(&(length as u32).to_le_bytes());
+		out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		out.extend_from_slice(b"JSON");
+		out.extend_from_slice(&json);
+		out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+		out.extend_from_slice(b"BIN\0");
+		out.extend_from_slice(&bin);
+		out
+	}
+
+	#[test]
+	fn loads_a_triangle_and_a_camera() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		// One untextured triangle, and a camera behind and above it.
+		let json = r#"{
+			"asset": {"version": "2.0"},
+			"scene": 0,
+			"scenes": [{"nodes": [0, 1]}],
+			"nodes": [
+				{"mesh": 0, "translation": [1, 2, 3]},
+				{"camera": 0, "translation": [0, 1, 5]}
+			],
+			"cameras": [
+				{"type": "perspective", "perspective": {"yfov": 1.0, "znear": 0.1}}
+			],
+			"meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+			"buffers": [{"byteLength": 42}],
+			"bufferViews": [
+				{"buffer": 0, "byteOffset": 0, "byteLength": 36},
+				{"buffer": 0, "byteOffset": 36, "byteLength": 6}
+			],
+			"accessors": [
+				{
+					"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+					"min": [0, 0, 0], "max": [1, 1, 0]
+				},
+				{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+			]
+		}"#;
+		let mut bin = Vec::new();
+		for p in [0f32, 0., 0., 1., 0., 0., 0., 1., 0.] {
+			bin.extend_from_slice(&p.to_le_bytes());
+		}
+		for i in [0u16, 1, 2] {
+			bin.extend_from_slice(&i.to_le_bytes());
+		}
+
+		let scene =
+			load_gltf(&context.device, &context.queue, &glb(json, &bin)).unwrap();
+		assert_eq!(scene.meshes.len(), 1);
+		assert_eq!(scene.meshes[0].num_indices, 3);
+		assert_eq!(scene.meshes[0].aabb.max, Point3::new(1., 1., 0.));
+		// The white texture of meshes without one.
+		assert_eq!(scene.textures.len(), 1);
+
+		assert_eq!(scene.instances.len(), 1);
+		let instance = scene.instances[0];
+		assert_eq!(instance.mesh, 0);
+		assert_eq!(instance.texture, 0);
+		assert_eq!(
+			instance.transform,
+			Matrix4::new_translation(&Vector3::new(1., 2., 3.))
+		);
+
+		assert_eq!(scene.cameras.len(), 1);
+		assert_eq!(scene.cameras[0].position, Point3::new(0., 1., 5.));
+		assert_eq!(scene.cameras[0].yaw, 0.);
+	}
+}
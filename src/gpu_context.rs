@@ -0,0 +1,233 @@
+//! The GPU objects that several windows can share.
+
+use color_eyre::{eyre::bail, eyre::eyre, eyre::WrapErr, Help, Result};
+use log::debug;
+use std::sync::Arc;
+
+use crate::builder::RenderStateBuilder;
+use crate::diagnostics::log_adapter_capabilities;
+use crate::render_object::MODEL_PUSH_CONSTANT_RANGE;
+
+/// The device and queue of [`RenderState`]s drawing to different windows, such
+/// as a scene view and an inspector. Resources created with the device can be
+/// used by all of them.
+///
+/// [`RenderState`]: crate::render_state::RenderState
+pub struct SharedGpuContext {
+	/// Creates the surfaces of the windows.
+	pub instance: wgpu::Instance,
+	pub adapter: wgpu::Adapter,
+	pub device: Arc<wgpu::Device>,
+	pub queue: Arc<wgpu::Queue>,
+}
+impl SharedGpuContext {
+	/// Creates a device without a surface, with the default options of a
+	/// [`RenderStateBuilder`]. Windows are added with
+	/// [`RenderState::new_with_context`].
+	///
+	/// Some backends, like OpenGL, can only present to surfaces created before
+	/// the adapter. Otherwise, share the [`RenderState::context`] of the first
+	/// window instead.
+	///
+	/// [`RenderState::new_with_context`]: crate::render_state::RenderState::new_with_context
+	/// [`RenderState::context`]: crate::render_state::RenderState::context
+	pub async fn new() -> Result<Self> {
+		Self::request(create_instance(), &RenderStateBuilder::new(), None).await
+	}
+
+	/// Picks an adapter of `instance` that can present to `compatible_surface`,
+	/// if any, and creates its device.
+	pub(crate) async fn request(
+		instance: wgpu::Instance,
+		options: &RenderStateBuilder,
+		compatible_surface: Option<&wgpu::Surface>,
+	) -> Result<Self> {
+		let adapter = instance
+			.request_adapter(&wgpu::RequestAdapterOptions {
+				power_preference: options.power_preference,
+				force_fallback_adapter: options.force_fallback,
+				// Surface that is required to be presentable with the requested adapter. This does not
+				// create the surface, only guarantees that the adapter can present to said surface.
+				compatible_surface,
+			})
+			.await
+			.ok_or(eyre!("Failed to get a wgpu Adapter"))?;
+		debug!("Chosen adapter: {:#?}", adapter.get_info());
+		let (device, queue) = request_device(&adapter, options).await?;
+		Ok(Self {
+			instance,
+			adapter,
+			device: Arc::new(device),
+			queue: Arc::new(queue),
+		})
+	}
+}
+
+/// A context for tests, or `None` on machines without a GPU, where the tests
+/// needing one pass without checking anything.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn test_context() -> Option<SharedGpuContext> {
+	match pollster::block_on(SharedGpuContext::new()) {
+		Ok(context) => Some(context),
+		Err(err) => {
+			eprintln!("Skipping GPU test: {err}");
+			None
+		}
+	}
+}
+
+/// A headless state of `width` x `height` pixels built by `builder` with the
+/// device of [`test_context`], or `None` on machines without a GPU.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn test_state(
+	builder: RenderStateBuilder,
+	width: u32,
+	height: u32,
+) -> Option<crate::render_state::RenderState> {
+	let context = Arc::new(test_context()?);
+	Some(
+		builder
+			.build_headless_with_context(context, width, height)
+			.expect("Failed to create a headless state"),
+	)
+}
+
+/// Reads back mip 0 of `texture`, which must have 4 bytes per pixel and the
+/// `COPY_SRC` usage.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn read_texture(
+	context: &SharedGpuContext,
+	texture: &wgpu::Texture,
+) -> image::RgbaImage {
+	let (width, height) = (texture.width(), texture.height());
+	// Rows of buffer copies must be padded to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+	let row_bytes = width * 4;
+	let padded_row_bytes = (row_bytes + wgpu::COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+		/ wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+		* wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+	let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Test Readback"),
+		size: (padded_row_bytes * height) as u64,
+		usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	let mut encoder = context
+		.device
+		.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+	encoder.copy_texture_to_buffer(
+		texture.as_image_copy(),
+		wgpu::ImageCopyBuffer {
+			buffer: &buffer,
+			layout: wgpu::ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(padded_row_bytes),
+				rows_per_image: None,
+			},
+		},
+		wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		},
+	);
+	context.queue.submit([encoder.finish()]);
+
+	let slice = buffer.slice(..);
+	slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+	context.device.poll(wgpu::Maintain::Wait);
+	let data = slice.get_mapped_range();
+	let pixels = data
+		.chunks(padded_row_bytes as usize)
+		.flat_map(|row| &row[..row_bytes as usize])
+		.copied()
+		.collect();
+	image::RgbaImage::from_raw(width, height, pixels).expect("The size matches")
+}
+
+/// Reads back the contents of `buffer`, which must have the `COPY_SRC` usage.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn read_buffer(
+	context: &SharedGpuContext,
+	buffer: &wgpu::Buffer,
+) -> Vec<u8> {
+	let readback = context.device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Test Readback"),
+		size: buffer.size(),
+		usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	let mut encoder = context
+		.device
+		.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+	encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, buffer.size());
+	context.queue.submit([encoder.finish()]);
+
+	let slice = readback.slice(..);
+	slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+	context.device.poll(wgpu::Maintain::Wait);
+	let data = slice.get_mapped_range().to_vec();
+	readback.unmap();
+	data
+}
+
+pub(crate) fn create_instance() -> wgpu::Instance {
+	let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+	let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+		backends,
+		dx12_shader_compiler: Default::default(),
+	});
+
+	debug!(
+		"Available wgpu adapters: {:#?}",
+		instance
+			.enumerate_adapters(backends)
+			.map(|a| a.get_info())
+			.collect::<Vec<_>>()
+	);
+	instance
+}
+
+async fn request_device(
+	adapter: &wgpu::Adapter,
+	options: &RenderStateBuilder,
+) -> Result<(wgpu::Device, wgpu::Queue)> {
+	let mut limits = options.limits.clone().unwrap_or_else(|| {
+		if cfg!(target_arch = "wasm32") {
+			wgpu::Limits::downlevel_webgl2_defaults()
+		} else {
+			wgpu::Limits::downlevel_defaults()
+		}
+	});
+	// Used when available, by `GpuProfiler`, `OcclusionCuller`, for higher MSAA
+	// sample counts and for model matrices respectively.
+	let mut features = options.features
+		| adapter.features()
+			& (wgpu::Features::TIMESTAMP_QUERY
+				| wgpu::Features::PIPELINE_STATISTICS_QUERY
+				| wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+				| wgpu::Features::PUSH_CONSTANTS);
+	let push_constant_size = MODEL_PUSH_CONSTANT_RANGE.range.end;
+	if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+		if adapter.limits().max_push_constant_size >= push_constant_size {
+			limits.max_push_constant_size =
+				limits.max_push_constant_size.max(push_constant_size);
+		} else if !options.features.contains(wgpu::Features::PUSH_CONSTANTS) {
+			features -= wgpu::Features::PUSH_CONSTANTS;
+		}
+	}
+	let desc = wgpu::DeviceDescriptor {
+		label: None,
+		features,
+		limits,
+	};
+	log_adapter_capabilities(adapter, &desc);
+	let missing = options.features - adapter.features();
+	if !missing.is_empty() {
+		bail!("Adapter doesn't support the required features {missing:?}");
+	}
+	adapter
+		.request_device(&desc, None)
+		.await
+		.wrap_err("Failed to get wgpu Device")
+		.with_note(|| format!("WGPU Adapter was: {:#?}", adapter.get_info()))
+}
@@ -0,0 +1,563 @@
+//! Culling the instances of a mesh against the camera's frustum on the GPU, see
+//! `gpu_culling.wgsl`, and picking their level of detail, see
+//! `lod_culling.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, Result};
+use wgpu::util::DeviceExt;
+
+use nalgebra::{Matrix4, Point3};
+
+use crate::aabb::Aabb;
+use crate::camera::Frustum;
+use crate::mesh::LodMesh;
+use crate::types::mat4_to_wgsl;
+use crate::vertex::Instance;
+
+/// The bounds of an instance in world space, in the layout of
+/// `gpu_culling.wgsl`'s `CullData`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct CullData {
+	pub aabb_min: [f32; 3],
+	pub _p0: f32,
+	pub aabb_max: [f32; 3],
+	pub _p1: f32,
+}
+impl CullData {
+	pub fn new(aabb: &Aabb) -> Self {
+		Self {
+			aabb_min: aabb.min.into(),
+			_p0: 0.,
+			aabb_max: aabb.max.into(),
+			_p1: 0.,
+		}
+	}
+}
+
+/// The layout of `gpu_culling.wgsl`'s `CullUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct CullUniform {
+	planes: [[f32; 4]; 6],
+	num_objects: u32,
+	_pad: [u32; 3],
+}
+
+/// Culls instances by their [`CullData`] with a compute shader, which packs the
+/// visible ones into an instance buffer and counts them into the arguments of
+/// an indirect draw, so that the CPU neither tests nor draws them one by one.
+pub(crate) struct GpuCuller {
+	/// The number of bounds, in a buffer only referenced by the bind group.
+	num_objects: u32,
+	/// A copy of the instances, as the instance buffer can't be bound as storage.
+	instances: wgpu::Buffer,
+	/// The visible instances, drawn as instances.
+	visible: wgpu::Buffer,
+	/// A [`wgpu::util::DrawIndexedIndirect`], counting `visible`.
+	draw_args: wgpu::Buffer,
+	/// A `CullUniform`.
+	uniform_buf: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::ComputePipeline,
+}
+impl GpuCuller {
+	const WORKGROUP_SIZE: u32 = 64;
+
+	/// Uploads the bounds of the instances, by the instance they bound.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If there are no objects.
+	pub fn new(device: &wgpu::Device, objects: &[CullData]) -> Result<Self> {
+		assert!(!objects.is_empty(), "There must be objects to cull");
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+		let num_objects = objects.len() as u32;
+		let instances_size =
+			objects.len() as u64 * std::mem::size_of::<Instance>() as u64;
+		let objects = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Cull Data"),
+			contents: bytemuck::cast_slice(objects),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let instances = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Cull Instances"),
+			size: instances_size,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let visible = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Visible Instances"),
+			size: instances_size,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+			mapped_at_creation: false,
+		});
+		// Copied from by tests, to read the count back.
+		let draw_args = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Culled Draw Args"),
+			size: std::mem::size_of::<wgpu::util::DrawIndexedIndirect>() as u64,
+			usage: wgpu::BufferUsages::STORAGE
+				| wgpu::BufferUsages::INDIRECT
+				| wgpu::BufferUsages::COPY_DST
+				| wgpu::BufferUsages::COPY_SRC,
+			mapped_at_creation: false,
+		});
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Cull Uniform"),
+			size: std::mem::size_of::<CullUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+		let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Cull Bind Group Layout"),
+				entries: &[
+					buffer_entry(0, read_only),
+					buffer_entry(1, read_only),
+					buffer_entry(2, wgpu::BufferBindingType::Uniform),
+					buffer_entry(3, read_write),
+					buffer_entry(4, read_write),
+				],
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("cull_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: objects.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: instances.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: visible.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: draw_args.as_entire_binding(),
+				},
+			],
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Cull Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("gpu_culling.wgsl"));
+		let pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("Frustum Culling"),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point: "cs_main",
+			});
+
+		Ok(Self {
+			num_objects,
+			instances,
+			visible,
+			draw_args,
+			uniform_buf,
+			bind_group,
+			pipeline,
+		})
+	}
+
+	/// Records culling the first `num_instances` instances of `instance_buf`
+	/// against `frustum`, to be drawn with `num_indices` indices each. Instances
+	/// without bounds aren't drawn. Returns the number of bytes uploaded.
+	pub fn cull(
+		&self,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		instance_buf: &wgpu::Buffer,
+		num_instances: u32,
+		num_indices: u32,
+		frustum: &Frustum,
+	) -> u64 {
+		let num_objects = self.num_objects.min(num_instances);
+		// Written before the commands of the next submission run, so the count
+		// starts over every frame.
+		let args = wgpu::util::DrawIndexedIndirect {
+			vertex_count: num_indices,
+			instance_count: 0,
+			base_index: 0,
+			vertex_offset: 0,
+			base_instance: 0,
+		};
+		queue.write_buffer(&self.draw_args, 0, args.as_bytes());
+		let uniform = CullUniform {
+			planes: frustum.planes.map(Into::into),
+			num_objects,
+			_pad: [0; 3],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		let bytes_uploaded =
+			(args.as_bytes().len() + std::mem::size_of_val(&uniform)) as u64;
+		if num_objects == 0 {
+			return bytes_uploaded;
+		}
+
+		let size = num_objects as u64 * std::mem::size_of::<Instance>() as u64;
+		encoder.copy_buffer_to_buffer(instance_buf, 0, &self.instances, 0, size);
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Frustum Culling Pass"),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.bind_group, &[]);
+		let workgroups = (num_objects - 1) / Self::WORKGROUP_SIZE + 1;
+		pass.dispatch_workgroups(workgroups, 1, 1);
+		bytes_uploaded
+	}
+
+	/// Records drawing the visible instances of the mesh, whose vertex and index
+	/// buffers must already be set, with a single indirect draw.
+	pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+		render_pass.set_vertex_buffer(1, self.visible.slice(..));
+		render_pass.draw_indexed_indirect(&self.draw_args, 0);
+	}
+}
+
+/// The most levels of detail [`GpuLodCuller`] picks from.
+pub const MAX_CULLED_LODS: usize = 4;
+
+/// An instance of a [`LodMesh`] with its bounds in world space and the furthest
+/// camera distance each level is drawn at, in the layout of
+/// `lod_culling.wgsl`'s `InstanceData`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct InstanceData {
+	pub transform: [[f32; 4]; 4],
+	pub aabb_min: [f32; 3],
+	pub _p0: f32,
+	pub aabb_max: [f32; 3],
+	pub _p1: f32,
+	/// By level, as in [`LodMesh::lods`]. Levels past the mesh's are ignored.
+	pub lod_distances: [f32; MAX_CULLED_LODS],
+}
+impl InstanceData {
+	/// An instance placed by `transform`, bounded by the mesh's model space
+	/// `aabb` once moved.
+	pub fn new(
+		transform: &Matrix4<f32>,
+		aabb: &Aabb,
+		lod_distances: [f32; MAX_CULLED_LODS],
+	) -> Self {
+		let aabb = aabb.transformed(transform);
+		Self {
+			transform: mat4_to_wgsl(*transform),
+			aabb_min: aabb.min.into(),
+			_p0: 0.,
+			aabb_max: aabb.max.into(),
+			_p1: 0.,
+			lod_distances,
+		}
+	}
+}
+
+/// The layout of `lod_culling.wgsl`'s `LodCullUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct LodCullUniform {
+	planes: [[f32; 4]; 6],
+	eye: [f32; 3],
+	num_instances: u32,
+	num_lods: u32,
+	capacity: u32,
+	_pad: [u32; 2],
+}
+
+/// Culls instances by their [`InstanceData`] and picks the level of detail of
+/// the visible ones with a single compute shader. It packs them into a range of
+/// an instance buffer per level, and counts each range into the arguments of
+/// that level's indirect draw, so that the CPU does the same work for any
+/// number of instances.
+pub(crate) struct GpuLodCuller {
+	/// How many instances each level's range of `visible` holds.
+	capacity: u32,
+	/// The visible instances, drawn as instances.
+	visible: wgpu::Buffer,
+	/// A [`wgpu::util::DrawIndexedIndirect`] per level, counting its range of
+	/// `visible`.
+	draw_args: wgpu::Buffer,
+	/// A `LodCullUniform`.
+	uniform_buf: wgpu::Buffer,
+	bind_group_layout: wgpu::BindGroupLayout,
+	pipeline: wgpu::ComputePipeline,
+}
+impl GpuLodCuller {
+	const WORKGROUP_SIZE: u32 = 64;
+	const DRAW_ARGS_SIZE: u64 =
+		std::mem::size_of::<wgpu::util::DrawIndexedIndirect>() as u64;
+
+	/// Creates the buffers for culling up to `capacity` instances.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If `capacity` is 0.
+	pub fn new(device: &wgpu::Device, capacity: u32) -> Result<Self> {
+		assert!(capacity > 0, "There must be room for instances to cull");
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+		let visible = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Visible LOD Instances"),
+			size: (MAX_CULLED_LODS as u64 * capacity as u64)
+				* std::mem::size_of::<Instance>() as u64,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+			mapped_at_creation: false,
+		});
+		let draw_args = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Culled LOD Draw Args"),
+			size: MAX_CULLED_LODS as u64 * Self::DRAW_ARGS_SIZE,
+			usage: wgpu::BufferUsages::STORAGE
+				| wgpu::BufferUsages::INDIRECT
+				| wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("LOD Cull Uniform"),
+			size: std::mem::size_of::<LodCullUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("LOD Cull Bind Group Layout"),
+				entries: &[
+					buffer_entry(
+						0,
+						wgpu::BufferBindingType::Storage { read_only: true },
+					),
+					buffer_entry(1, wgpu::BufferBindingType::Uniform),
+					buffer_entry(2, read_write),
+					buffer_entry(3, read_write),
+				],
+			});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("LOD Cull Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("lod_culling.wgsl"));
+		let pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("Frustum Culling and LOD Selection"),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point: "cs_main",
+			});
+
+		Ok(Self {
+			capacity,
+			visible,
+			draw_args,
+			uniform_buf,
+			bind_group_layout,
+			pipeline,
+		})
+	}
+
+	/// Records culling the first `num_instances` [`InstanceData`] of `instances`
+	/// against `frustum`, picking the levels of `lod_mesh` for their distance
+	/// from `eye`. Instances past the capacity aren't drawn. Returns the number
+	/// of bytes uploaded.
+	///
+	/// # Panics
+	/// If `lod_mesh` has more than [`MAX_CULLED_LODS`] levels.
+	#[allow(clippy::too_many_arguments)]
+	pub fn cull(
+		&self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		instances: &wgpu::Buffer,
+		num_instances: u32,
+		lod_mesh: &LodMesh,
+		eye: &Point3<f32>,
+		frustum: &Frustum,
+	) -> u64 {
+		assert!(
+			lod_mesh.lods.len() <= MAX_CULLED_LODS,
+			"At most {MAX_CULLED_LODS} levels of detail can be culled"
+		);
+		let num_instances = self.capacity.min(num_instances);
+		// Written before the commands of the next submission run, so the counts
+		// start over every frame.
+		let args: Vec<u8> = (lod_mesh.lods.iter())
+			.flat_map(|&(_, _, num_indices)| {
+				let args = wgpu::util::DrawIndexedIndirect {
+					vertex_count: num_indices,
+					instance_count: 0,
+					base_index: 0,
+					vertex_offset: 0,
+					base_instance: 0,
+				};
+				args.as_bytes().to_vec()
+			})
+			.collect();
+		queue.write_buffer(&self.draw_args, 0, &args);
+		let uniform = LodCullUniform {
+			planes: frustum.planes.map(Into::into),
+			eye: (*eye).into(),
+			num_instances,
+			num_lods: lod_mesh.lods.len() as u32,
+			capacity: self.capacity,
+			_pad: [0; 2],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		let bytes_uploaded = (args.len() + std::mem::size_of_val(&uniform)) as u64;
+		if num_instances == 0 {
+			return bytes_uploaded;
+		}
+
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("lod_cull_bind_group"),
+			layout: &self.bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: instances.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: self.visible.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: self.draw_args.as_entire_binding(),
+				},
+			],
+		});
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("LOD Culling Pass"),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &bind_group, &[]);
+		let workgroups = (num_instances - 1) / Self::WORKGROUP_SIZE + 1;
+		pass.dispatch_workgroups(workgroups, 1, 1);
+		bytes_uploaded
+	}
+
+	/// Records drawing the visible instances of `lod_mesh`, the mesh last culled,
+	/// with an indirect draw per level. The pipeline and bind groups must already
+	/// be set. Returns the number of draws.
+	pub fn draw<'a>(
+		&'a self,
+		render_pass: &mut wgpu::RenderPass<'a>,
+		lod_mesh: &'a LodMesh,
+	) -> u32 {
+		let range_size = self.capacity as u64 * std::mem::size_of::<Instance>() as u64;
+		render_pass.set_vertex_buffer(0, lod_mesh.vtx_buf.slice(..));
+		for (level, (_, idx_buf, _)) in lod_mesh.lods.iter().enumerate() {
+			let level = level as u64;
+			render_pass.set_index_buffer(idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			let range = level * range_size..(level + 1) * range_size;
+			render_pass.set_vertex_buffer(1, self.visible.slice(range));
+			render_pass
+				.draw_indexed_indirect(&self.draw_args, level * Self::DRAW_ARGS_SIZE);
+		}
+		lod_mesh.lods.len() as u32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::camera::{Camera, CameraLike};
+	use crate::gpu_context::{read_buffer, test_context};
+	use nalgebra::Vector3;
+
+	#[test]
+	fn objects_outside_the_frustum_are_not_drawn() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let (device, queue) = (&context.device, &context.queue);
+		// Seen from -1 to 1 on x and y, from z = -0.1 to -10.
+		let camera = Camera::new_orthographic(-1., 1., -1., 1., 0.1, 10.);
+		let small_box = |x: f32, z: f32| {
+			let center = Point3::new(x, 0., z);
+			CullData::new(&Aabb::new(
+				center - Vector3::repeat(0.25),
+				center + Vector3::repeat(0.25),
+			))
+		};
+		// Half of them to the side of or behind the camera.
+		let objects = [
+			small_box(0., -5.),
+			small_box(5., -5.),
+			small_box(0.5, -2.),
+			small_box(0., 5.),
+		];
+		let Ok(culler) = GpuCuller::new(device, &objects) else {
+			return;
+		};
+		let instances = vec![Instance::new(Matrix4::identity()); objects.len()];
+		let instance_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: None,
+				contents: bytemuck::cast_slice(&instances),
+				usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
+			});
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+		let num_instances = instances.len() as u32;
+		culler.cull(
+			queue,
+			&mut encoder,
+			&instance_buf,
+			num_instances,
+			6,
+			&camera.frustum(),
+		);
+		queue.submit([encoder.finish()]);
+
+		// Index and instance counts, then offsets.
+		let args: Vec<u32> =
+			bytemuck::pod_collect_to_vec(&read_buffer(&context, &culler.draw_args));
+		assert_eq!(args[..2], [6, 2]);
+	}
+}
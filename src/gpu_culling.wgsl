@@ -0,0 +1,64 @@
+// Culls the instances of a mesh against the camera's frustum, packing the
+// visible ones together to be drawn with a single indirect draw.
+
+// Matches `CullData` in `gpu_culling.rs`. Bounds an instance in world space.
+struct CullData {
+	aabb_min: vec3<f32>,
+	aabb_max: vec3<f32>,
+};
+
+// Matches `Instance` in `vertex.rs`.
+struct Instance {
+	transform: mat4x4<f32>,
+};
+
+// Matches `wgpu::util::DrawIndexedIndirect`.
+struct DrawArgs {
+	index_count: u32,
+	instance_count: atomic<u32>,
+	first_index: u32,
+	base_vertex: i32,
+	first_instance: u32,
+};
+
+struct CullUniform {
+	// Left, right, bottom, top, near and far, with the inside in front.
+	planes: array<vec4<f32>, 6>,
+	num_objects: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> objects: array<CullData>;
+@group(0) @binding(1)
+var<storage, read> instances: array<Instance>;
+@group(0) @binding(2)
+var<uniform> cull: CullUniform;
+// The visible instances, packed together.
+@group(0) @binding(3)
+var<storage, read_write> visible: array<Instance>;
+@group(0) @binding(4)
+var<storage, read_write> draw_args: DrawArgs;
+
+// Whether the box may be inside the frustum: for every plane, its corner
+// furthest along the plane's normal is in front of it.
+fn intersects_frustum(object: CullData) -> bool {
+	for (var i = 0u; i < 6u; i += 1u) {
+		let plane = cull.planes[i];
+		let corner = select(object.aabb_min, object.aabb_max, plane.xyz >= vec3<f32>(0.0));
+		if dot(plane.xyz, corner) + plane.w < 0.0 {
+			return false;
+		}
+	}
+	return true;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let i = id.x;
+	if i >= cull.num_objects {
+		return;
+	}
+	if intersects_frustum(objects[i]) {
+		visible[atomicAdd(&draw_args.instance_count, 1u)] = instances[i];
+	}
+}
@@ -0,0 +1,63 @@
+//! Watching of source files, so they can be reloaded without rebuilding.
+
+use color_eyre::{eyre::WrapErr, Result};
+use log::warn;
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Watches a single file for changes.
+pub struct FileWatcher {
+	path: PathBuf,
+	changed: Arc<AtomicBool>,
+	// Stops watching when dropped.
+	_watcher: notify::RecommendedWatcher,
+}
+impl FileWatcher {
+	pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+		let path: PathBuf = path.into();
+		let changed = Arc::new(AtomicBool::new(false));
+		let mut watcher = {
+			let path = path.clone();
+			let changed = changed.clone();
+			notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+				match res {
+					Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+						// We watch the whole directory.
+						if event
+							.paths
+							.iter()
+							.any(|p| p.file_name() == path.file_name())
+						{
+							changed.store(true, Ordering::Relaxed);
+						}
+					}
+					Ok(_) => (),
+					Err(err) => warn!("Error while watching {}: {err}", path.display()),
+				}
+			})
+			.wrap_err("Failed to create file watcher")?
+		};
+		// Many editors save by replacing the file, which would end a watch on the
+		// file itself. So we watch its directory instead.
+		let dir = path.parent().unwrap_or(Path::new("."));
+		watcher
+			.watch(dir, RecursiveMode::NonRecursive)
+			.wrap_err_with(|| format!("Failed to watch {}", dir.display()))?;
+		Ok(Self {
+			path,
+			changed,
+			_watcher: watcher,
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Returns whether the file changed since the last call.
+	pub fn take_changed(&self) -> bool {
+		self.changed.swap(false, Ordering::Relaxed)
+	}
+}
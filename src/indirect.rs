@@ -0,0 +1,62 @@
+//! Draw calls whose arguments are read from a buffer, so that they can also be
+//! written by the GPU.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// The arguments of an indexed draw call, in the layout `draw_indexed_indirect`
+/// reads them in, like [`wgpu::util::DrawIndexedIndirect`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct DrawCall {
+	pub index_count: u32,
+	pub instance_count: u32,
+	pub first_index: u32,
+	/// Added to the indices before reading the vertex buffer.
+	pub base_vertex: i32,
+	/// Must be 0 without [`wgpu::Features::INDIRECT_FIRST_INSTANCE`].
+	pub first_instance: u32,
+}
+impl DrawCall {
+	/// The stride of the draw calls in an indirect buffer.
+	pub const SIZE: u64 = std::mem::size_of::<Self>() as u64;
+}
+
+/// Creates a buffer holding `draws` one after the other, to draw with
+/// [`wgpu::RenderPass::draw_indexed_indirect`] at offsets that are multiples of
+/// [`DrawCall::SIZE`]. It can be rewritten with `Queue::write_buffer`.
+pub fn build_indirect_buffer(
+	device: &wgpu::Device,
+	draws: &[DrawCall],
+) -> wgpu::Buffer {
+	device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some("Indirect Buffer"),
+		contents: bytemuck::cast_slice(draws),
+		usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn matches_wgpu_layout() {
+		let draw = DrawCall {
+			index_count: 36,
+			instance_count: 4,
+			first_index: 6,
+			base_vertex: -2,
+			first_instance: 0,
+		};
+		let expected = wgpu::util::DrawIndexedIndirect {
+			vertex_count: 36,
+			instance_count: 4,
+			base_index: 6,
+			vertex_offset: -2,
+			base_instance: 0,
+		};
+		assert_eq!(DrawCall::SIZE, 20);
+		assert_eq!(bytemuck::bytes_of(&draw), expected.as_bytes());
+	}
+}
@@ -0,0 +1,28 @@
+// Draws a texture onto the whole render target with its colors inverted.
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen, with UVs from 0 to 1 over the visible part.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+@group(0) @binding(0)
+var src_t: texture_2d<f32>;
+@group(0) @binding(1)
+var src_s: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(src_t, src_s, in.uv);
+	return vec4<f32>(1.0 - color.rgb, color.a);
+}
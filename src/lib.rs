@@ -1,22 +1,158 @@
-mod camera;
-mod render_state;
-mod tex2d;
-mod vertex;
+pub mod aabb;
+pub mod animation;
+pub mod async_pipeline;
+pub mod atlas;
+pub mod bloom;
+pub mod builder;
+pub mod camera;
+pub mod camera_path;
+pub mod cascades;
+pub mod cel_outline;
+pub mod cloth;
+pub mod color_correction;
+mod color_mesh;
+pub mod compute;
+pub mod cubemap;
+pub mod debug_lines;
+mod debug_ui;
+pub mod decal;
+pub mod depth_of_field;
+mod diagnostics;
+pub mod dynamic_resolution;
+pub mod ecs;
+mod environment;
+mod event_replay;
+mod fixed_timestep;
+pub mod fog;
+pub mod gbuffer;
+pub mod gizmo;
+pub mod gltf_loader;
+pub mod gpu_context;
+pub mod gpu_culling;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+pub mod indirect;
+pub mod light;
+pub mod material;
+pub mod memory;
+pub mod mesh;
+pub mod morph;
+pub mod msaa_resolve;
+mod obj_loader;
+mod occlusion;
+pub mod particles;
+pub mod perf;
+pub mod picking;
+mod pipeline_cache;
+pub mod point_shadow;
+pub mod pool;
+mod post_effects;
+pub mod post_process;
+pub mod procedural_sky;
+pub mod profiler;
+pub mod reflection_probe;
+pub mod render_graph;
+pub mod render_object;
+pub mod render_state;
+pub mod render_target;
+pub mod resources;
+#[cfg(feature = "serde")]
+pub mod scene_file;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod screenshot;
+pub mod sdf_ao;
+pub mod sdf_font;
+pub mod shader;
+mod shadow;
+pub mod skinning;
+mod skybox;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod spatial;
+pub mod spatial_audio;
+pub mod ssao;
+pub mod ssr;
+pub mod stencil_decal;
+pub mod taa;
+pub mod terrain;
+#[cfg(all(feature = "test-utils", not(target_arch = "wasm32")))]
+pub mod testing;
+pub mod tex2d;
+pub mod text;
+pub mod texture_loader;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod texture_streamer;
+pub mod title;
+pub mod tonemap;
+#[cfg(not(target_arch = "wasm32"))]
+mod trace;
+mod types;
+pub mod uv_animation;
+pub mod vertex;
+pub mod viewport;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod virtual_texture;
+pub mod volumetric_fog;
+pub mod wboit;
+pub mod wind;
 
 use cfg_if::cfg_if;
-use color_eyre::{eyre::WrapErr, Result};
+use color_eyre::{eyre::eyre, eyre::WrapErr, Result};
+use instant::Instant;
 use log::error;
 use log::{info, warn};
-use winit::event::VirtualKeyCode;
+use std::path::PathBuf;
+use winit::event::{Event, VirtualKeyCode, WindowEvent};
 use winit::event_loop::ControlFlow;
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::window::{Window, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-use crate::render_state::RenderState;
+use crate::bloom::BloomSettings;
+use crate::builder::RenderStateBuilder;
+use crate::event_replay::{EventRecorder, EventReplayer};
+use crate::fixed_timestep::FixedTimestep;
+use crate::render_state::{RecoveryAction, RenderState};
+
+/// Command line arguments.
+#[derive(Debug, Default)]
+struct Args {
+	/// Record input to this file, see [`EventRecorder`].
+	record: Option<PathBuf>,
+	/// Replay input from this file instead of using the real input.
+	replay: Option<PathBuf>,
+	/// Save a screenshot of each replayed frame in this directory.
+	#[cfg(not(target_arch = "wasm32"))]
+	replay_screenshots: Option<PathBuf>,
+}
+impl Args {
+	fn parse() -> Result<Self> {
+		let mut result = Self::default();
+		// There are no command line arguments on web.
+		if cfg!(target_arch = "wasm32") {
+			return Ok(result);
+		}
+		let mut args = std::env::args().skip(1);
+		while let Some(arg) = args.next() {
+			let mut path = || {
+				args.next()
+					.map(PathBuf::from)
+					.ok_or_else(|| eyre!("Expected a path after {arg}"))
+			};
+			match arg.as_str() {
+				"--record" => result.record = Some(path()?),
+				"--replay" => result.replay = Some(path()?),
+				#[cfg(not(target_arch = "wasm32"))]
+				"--replay-screenshots" => result.replay_screenshots = Some(path()?),
+				_ => warn!("Ignoring unknown argument {arg:?}"),
+			}
+		}
+		Ok(result)
+	}
+}
 
 pub async fn run() -> Result<()> {
 	color_eyre::install()?;
@@ -26,19 +162,42 @@ pub async fn run() -> Result<()> {
 			console_log::init_with_level(log::Level::Debug)
 				.expect("Couldn't initialize logger");
 		} else {
-			use env_logger::Env;
-			let env = Env::default().default_filter_or("wgpu_experiments=debug");
-			env_logger::Builder::from_env(env).init();
+			// Dropped when the event loop is destroyed, to finish the trace.
+			let mut trace_guard = trace::init();
 		}
 	}
 
+	let args = Args::parse()?;
+	let mut recorder = args.record.map(EventRecorder::new);
+	let mut replayer = args
+		.replay
+		.map(EventReplayer::load)
+		.transpose()
+		.wrap_err("Error when loading input recording")?;
+	#[cfg(not(target_arch = "wasm32"))]
+	if let Some(dir) = args.replay_screenshots.filter(|_| replayer.is_some()) {
+		std::fs::create_dir_all(&dir).wrap_err_with(|| {
+			format!("Failed to create screenshot directory {}", dir.display())
+		})?;
+		replayer = replayer.map(|replayer| replayer.with_screenshots(dir));
+	}
+	if let Some(replayer) = &replayer {
+		info!("Replaying {} recorded frames", replayer.remaining());
+	}
+
 	let event_loop = EventLoop::new();
 	let window = WindowBuilder::new().build(&event_loop).unwrap();
 
+	// The parent element's new size, set by a `ResizeObserver` and applied in
+	// the event loop.
+	#[cfg(target_arch = "wasm32")]
+	let parent_resized = std::rc::Rc::new(std::cell::Cell::new(None));
 	#[cfg(target_arch = "wasm32")]
 	{
 		// Winit prevents sizing with CSS, so we have to set
 		// the size manually when on web.
+		use wasm_bindgen::closure::Closure;
+		use wasm_bindgen::JsCast;
 		use winit::dpi::PhysicalSize;
 
 		use winit::platform::web::WindowExtWebSys;
@@ -59,22 +218,83 @@ pub async fn run() -> Result<()> {
 
 				parent.append_child(&canvas).ok()?;
 
+				// Otherwise the canvas keeps its size when the page is resized.
+				let observed = parent.clone();
+				let resized = parent_resized.clone();
+				let on_resize = Closure::<dyn FnMut()>::new(move || {
+					let width = observed.client_width() as u32;
+					let height = observed.client_height() as u32;
+					resized.set(Some(PhysicalSize::new(width, height)));
+				});
+				let observer =
+					web_sys::ResizeObserver::new(on_resize.as_ref().unchecked_ref())
+						.ok()?;
+				observer.observe(&parent);
+				// Both are needed for as long as the page is open.
+				on_resize.forget();
+				std::mem::forget(observer);
+
 				Some(())
 			})
 			.expect("Couldn't append canvas to document body.");
 	}
 
 	let mut input = WinitInputHelper::new();
-	let mut state = RenderState::new(window)
+	let mut timestep = FixedTimestep::new(60.);
+	let state = RenderStateBuilder::new()
+		.sample_count(4)
+		.build(window)
 		.await
 		.wrap_err("Error when initializing wgpu state")?;
+	// One per window, sharing the device of the first through
+	// `RenderState::new_with_context`. Input controls the first, and closing any
+	// of them exits.
+	let mut states = vec![state];
+	let mut last_frame = Instant::now();
+	// The size to restore when leaving fullscreen.
+	#[cfg(not(target_arch = "wasm32"))]
+	let mut windowed_size = None;
 
 	info!("Starting event loop");
-	event_loop.run(move |event, _e_loop, control_flow| {
+	event_loop.run(move |event, e_loop, control_flow| {
+		#[cfg(not(target_arch = "wasm32"))]
+		if let Event::LoopDestroyed = event {
+			trace_guard.take();
+		}
+		if let Some(recorder) = &mut recorder {
+			recorder.observe(&event);
+		}
+		if let Event::WindowEvent { window_id, event } = &event {
+			let state = states
+				.iter_mut()
+				.find(|state| state.window().map(Window::id) == Some(*window_id));
+			if let Some(state) = state {
+				state.on_window_event(event);
+				let resized = match event {
+					WindowEvent::Resized(size) => Some(*size),
+					WindowEvent::ScaleFactorChanged {
+						scale_factor,
+						new_inner_size,
+					} => {
+						state.set_scale_factor(*scale_factor as f32);
+						Some(**new_inner_size)
+					}
+					_ => None,
+				};
+				if let Some(size) = resized {
+					if let Err(err) = state.resize(size) {
+						error!("{err}");
+						*control_flow = ControlFlow::Exit;
+						return;
+					}
+				}
+			}
+		}
 		// When true, input_helper is done processing events.
 		if !input.update(&event) {
 			return;
 		}
+		let state = &mut states[0];
 
 		// Handle close events
 		{
@@ -83,32 +303,211 @@ pub async fn run() -> Result<()> {
 				|| input.destroyed()
 			{
 				info!("Close Requested");
+				if let Some(recorder) = &recorder {
+					match recorder.save() {
+						Ok(()) => info!("Saved input recording"),
+						Err(err) => error!("{err:?}"),
+					}
+				}
 				*control_flow = ControlFlow::Exit;
 				return;
 			}
 		}
 
-		if let Some(size) = input.window_resized() {
-			state.resize(size);
+		// File dialogs block, so they are opened here rather than during rendering.
+		#[cfg(not(target_arch = "wasm32"))]
+		if input.held_control() {
+			if input.key_pressed(VirtualKeyCode::O) {
+				if let Some(path) = RenderState::open_texture_dialog() {
+					// Large images would otherwise stall the window while decoding.
+					state.load_texture_in_background(path);
+				}
+			}
+			if input.held_shift() && input.key_pressed(VirtualKeyCode::S) {
+				if let Some(path) = RenderState::save_scene_dialog() {
+					#[cfg(feature = "serde")]
+					match state.save_scene(&path) {
+						Ok(()) => info!("Saved scene to {}", path.display()),
+						Err(err) => error!("{err:?}"),
+					}
+					#[cfg(not(feature = "serde"))]
+					warn!(
+						"Saving scenes needs the serde feature, skipping {}",
+						path.display()
+					);
+				}
+			}
+		}
+		// The browser's file picker doesn't block.
+		#[cfg(target_arch = "wasm32")]
+		if input.held_control() && input.key_pressed(VirtualKeyCode::O) {
+			state.open_texture_dialog();
+		}
+		// Opens another window, drawing a scene of its own with the device of the
+		// first.
+		#[cfg(not(target_arch = "wasm32"))]
+		if input.held_control() && input.key_pressed(VirtualKeyCode::N) {
+			let window = WindowBuilder::new()
+				.build(e_loop)
+				.wrap_err("Error when creating a window");
+			let context = states[0].context().clone();
+			match window
+				.and_then(|window| RenderState::new_with_context(window, context))
+			{
+				Ok(new_state) => states.push(new_state),
+				Err(err) => error!("{err:?}"),
+			}
+		}
+		// Pages have a single canvas.
+		#[cfg(target_arch = "wasm32")]
+		let _ = e_loop;
+		let state = &mut states[0];
+		#[cfg(not(target_arch = "wasm32"))]
+		if input.key_pressed(VirtualKeyCode::F12) {
+			let path = screenshot::default_path();
+			match state
+				.capture_screenshot()
+				.and_then(|img| screenshot::save_screenshot(img, &path))
+			{
+				Ok(()) => info!("Saved screenshot to {}", path.display()),
+				Err(err) => error!("{err:?}"),
+			}
 		}
 
-		state.update(&input);
+		if input.key_pressed(VirtualKeyCode::F11) {
+			if let Some(window) = state.window() {
+				#[cfg(not(target_arch = "wasm32"))]
+				toggle_fullscreen(window, &mut windowed_size);
+				#[cfg(target_arch = "wasm32")]
+				toggle_fullscreen(window);
+			}
+		}
+		if input.key_pressed(VirtualKeyCode::F9) {
+			state.set_invert_colors(!state.invert_colors());
+		}
+		if input.key_pressed(VirtualKeyCode::F3) {
+			state.set_show_frame_graph(!state.show_frame_graph());
+		}
+		if input.key_pressed(VirtualKeyCode::F8) {
+			state.set_bloom(state.bloom().is_none().then(BloomSettings::default));
+		}
+		if input.key_pressed(VirtualKeyCode::P) {
+			// Cycles through the supported modes.
+			let modes = state.present_modes();
+			let current = modes.iter().position(|&mode| mode == state.present_mode());
+			let next = modes[current.map_or(0, |i| (i + 1) % modes.len())];
+			state.set_present_mode(next);
+			info!("Present mode: {:?}", state.present_mode());
+		}
 
-		use wgpu::SurfaceError as E;
-		match state.render() {
-			Ok(_) => {}
-			Err(E::Lost) => state.resize(state.size()),
-			Err(E::OutOfMemory) => {
-				error!("Out of memory!");
+		#[cfg(target_arch = "wasm32")]
+		if let Some(size) = parent_resized.take() {
+			if let Err(err) = state.resize(size) {
+				error!("{err}");
 				*control_flow = ControlFlow::Exit;
+				return;
+			}
+		}
+
+		let now = Instant::now();
+		let dt = (now - last_frame).as_secs_f32();
+		last_frame = now;
+		// Replays use the recorded frame times, so that they simulate the same.
+		let (frame_input, dt) = match &mut replayer {
+			Some(replayer) => match replayer.step() {
+				Some(step) => step,
+				None => {
+					info!("Replay finished");
+					*control_flow = ControlFlow::Exit;
+					return;
+				}
+			},
+			None => (&input, dt),
+		};
+		let ticks = timestep.advance(dt);
+		state.run_simulation_ticks(ticks, timestep.tick_dt(), frame_input);
+		state.interpolate(timestep.alpha());
+		if let Some(recorder) = &mut recorder {
+			recorder.end_frame(&input, dt);
+		}
+
+		for state in &mut states {
+			#[cfg(feature = "hot-reload")]
+			if let Err(err) = state.try_reload_shader() {
+				error!("{err:?}");
+			}
+
+			if let Err(err) = state.render() {
+				match state.handle_surface_error(err) {
+					RecoveryAction::Skip => {}
+					RecoveryAction::Resize => {
+						if let Err(err) = state.resize(state.size()) {
+							error!("{err}");
+							*control_flow = ControlFlow::Exit;
+						}
+					}
+					RecoveryAction::Recreate => {
+						if let Err(err) = state.recreate_surface() {
+							error!("{err:?}");
+							*control_flow = ControlFlow::Exit;
+						}
+					}
+					RecoveryAction::Exit => *control_flow = ControlFlow::Exit,
+				}
 			}
-			Err(err) => {
-				warn!("Error in event loop: {:?}", err);
+		}
+		#[cfg(not(target_arch = "wasm32"))]
+		if let Some(replayer) = &replayer {
+			if let Err(err) = replayer.capture_screenshot(&mut states[0]) {
+				error!("{err:?}");
 			}
 		}
 	})
 }
 
+/// Switches `window` between borderless fullscreen and windowed mode.
+///
+/// This doesn't resize the `RenderState`. The OS may animate the transition, so
+/// we wait for the resize events it sends instead.
+///
+/// When leaving fullscreen, the size from before entering it is restored, since
+/// some platforms keep the fullscreen size otherwise.
+#[cfg(not(target_arch = "wasm32"))]
+fn toggle_fullscreen(
+	window: &Window,
+	windowed_size: &mut Option<winit::dpi::PhysicalSize<u32>>,
+) {
+	use winit::window::Fullscreen;
+	if window.fullscreen().is_some() {
+		window.set_fullscreen(None);
+		if let Some(size) = windowed_size.take() {
+			window.set_inner_size(size);
+		}
+	} else {
+		*windowed_size = Some(window.inner_size());
+		window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+	}
+}
+
+/// Switches the canvas of `window` in and out of fullscreen, with the browser's
+/// Fullscreen API.
+///
+/// Browsers only allow entering fullscreen while handling user input, such as the
+/// key press that calls this. The browser restores the page layout on exit, and
+/// the canvas keeps its size, being stretched to fill the screen in between.
+#[cfg(target_arch = "wasm32")]
+fn toggle_fullscreen(window: &Window) {
+	use winit::platform::web::WindowExtWebSys;
+	let Some(document) = web_sys::window().and_then(|win| win.document()) else {
+		return;
+	};
+	if document.fullscreen_element().is_some() {
+		document.exit_fullscreen();
+	} else if let Err(err) = window.canvas().request_fullscreen() {
+		warn!("Failed to enter fullscreen: {err:?}");
+	}
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub async fn wasm_start() -> Result<(), JsError> {
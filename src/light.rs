@@ -0,0 +1,302 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::cascades::CascadedShadowMap;
+use crate::environment::EnvironmentMap;
+use crate::point_shadow::{PointShadowMap, MAX_POINT_SHADOWS};
+use crate::shadow::ShadowMap;
+
+/// A directional light, in the layout of the shader's `LightUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct LightUniform {
+	/// The direction the light travels in, in world space. Must be normalized.
+	pub direction: [f32; 3],
+	// A `vec3` is aligned to 16 bytes in WGSL.
+	#[cfg_attr(feature = "serde", serde(skip))]
+	pub _pad: f32,
+	pub color: [f32; 3],
+	/// How much of `color` reaches surfaces facing away from the light.
+	pub ambient: f32,
+}
+impl LightUniform {
+	pub fn new(direction: [f32; 3], color: [f32; 3], ambient: f32) -> Self {
+		let [x, y, z] = direction;
+		let len = (x * x + y * y + z * z).sqrt();
+		Self {
+			direction: [x / len, y / len, z / len],
+			_pad: 0.,
+			color,
+			ambient,
+		}
+	}
+}
+impl Default for LightUniform {
+	/// White light coming from above, to the left and behind the default camera.
+	fn default() -> Self {
+		Self::new([0.3, -0.5, -1.], [1., 1., 1.], 0.2)
+	}
+}
+
+/// The layout of the main pipeline's group 2: the lights and their shadows, and
+/// the other uniforms and textures lighting surfaces, bound by
+/// [`create_bind_group`].
+pub(crate) fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+	let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::FRAGMENT,
+		ty: wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Uniform,
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		},
+		count: None,
+	};
+	let cube_shadow_entry = |binding| wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::FRAGMENT,
+		ty: wgpu::BindingType::Texture {
+			multisampled: false,
+			view_dimension: wgpu::TextureViewDimension::Cube,
+			sample_type: wgpu::TextureSampleType::Depth,
+		},
+		count: None,
+	};
+	device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+		label: Some("Light Bind Group Layout"),
+		entries: &[
+			uniform_entry(0),
+			// The shadow map's matrix, texture and sampler.
+			uniform_entry(1),
+			wgpu::BindGroupLayoutEntry {
+				binding: 2,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::D2,
+					sample_type: wgpu::TextureSampleType::Depth,
+				},
+				count: None,
+			},
+			wgpu::BindGroupLayoutEntry {
+				binding: 3,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+				count: None,
+			},
+			uniform_entry(4),
+			// The environment map's texture, sampler and mip count.
+			wgpu::BindGroupLayoutEntry {
+				binding: 5,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::Cube,
+					sample_type: wgpu::TextureSampleType::Float { filterable: true },
+				},
+				count: None,
+			},
+			wgpu::BindGroupLayoutEntry {
+				binding: 6,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+				count: None,
+			},
+			uniform_entry(7),
+			// The occlusion of SSAO, read by pixel.
+			wgpu::BindGroupLayoutEntry {
+				binding: 8,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::D2,
+					sample_type: wgpu::TextureSampleType::Float { filterable: false },
+				},
+				count: None,
+			},
+			uniform_entry(9),
+			uniform_entry(10),
+			// The shadow cascades' matrices and texture array.
+			uniform_entry(11),
+			wgpu::BindGroupLayoutEntry {
+				binding: 12,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::D2Array,
+					sample_type: wgpu::TextureSampleType::Depth,
+				},
+				count: None,
+			},
+			// A reflection probe's irradiance and BRDF lookup table,
+			// sampled with the environment map's sampler.
+			wgpu::BindGroupLayoutEntry {
+				binding: 13,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::Cube,
+					sample_type: wgpu::TextureSampleType::Float { filterable: true },
+				},
+				count: None,
+			},
+			wgpu::BindGroupLayoutEntry {
+				binding: 14,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					multisampled: false,
+					view_dimension: wgpu::TextureViewDimension::D2,
+					sample_type: wgpu::TextureSampleType::Float { filterable: true },
+				},
+				count: None,
+			},
+			// The wind, moving the vertices of foliage.
+			wgpu::BindGroupLayoutEntry {
+				binding: 15,
+				visibility: wgpu::ShaderStages::VERTEX,
+				ty: wgpu::BindingType::Buffer {
+					ty: wgpu::BufferBindingType::Uniform,
+					has_dynamic_offset: false,
+					min_binding_size: None,
+				},
+				count: None,
+			},
+			// The point lights, and a shadow cubemap for each.
+			uniform_entry(16),
+			wgpu::BindGroupLayoutEntry {
+				binding: 17,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+				count: None,
+			},
+			cube_shadow_entry(18),
+			cube_shadow_entry(19),
+			cube_shadow_entry(20),
+			cube_shadow_entry(21),
+		],
+	})
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_bind_group(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	light_buf: &wgpu::Buffer,
+	shadow_map: &ShadowMap,
+	cascaded_shadows: &CascadedShadowMap,
+	fog_buf: &wgpu::Buffer,
+	environment_map: &EnvironmentMap,
+	occlusion_view: &wgpu::TextureView,
+	uv_animation_buf: &wgpu::Buffer,
+	wind_buf: &wgpu::Buffer,
+	color_correction_buf: &wgpu::Buffer,
+	point_lights_buf: &wgpu::Buffer,
+	point_shadows: &[PointShadowMap],
+	no_point_shadow: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+	let environment = environment_map.bound();
+	let point_shadow_views: [_; MAX_POINT_SHADOWS] = std::array::from_fn(|i| {
+		point_shadows
+			.get(i)
+			.map_or(no_point_shadow, |point_shadow| &point_shadow.view)
+	});
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("light_bind_group"),
+		layout,
+		entries: &[
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: light_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: shadow_map.uniform_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 2,
+				resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+			},
+			wgpu::BindGroupEntry {
+				binding: 3,
+				resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+			},
+			wgpu::BindGroupEntry {
+				binding: 4,
+				resource: fog_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 5,
+				resource: wgpu::BindingResource::TextureView(&environment.view),
+			},
+			wgpu::BindGroupEntry {
+				binding: 6,
+				resource: wgpu::BindingResource::Sampler(&environment.sampler),
+			},
+			wgpu::BindGroupEntry {
+				binding: 7,
+				resource: environment_map.uniform_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 8,
+				resource: wgpu::BindingResource::TextureView(occlusion_view),
+			},
+			wgpu::BindGroupEntry {
+				binding: 9,
+				resource: uv_animation_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 10,
+				resource: color_correction_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 11,
+				resource: cascaded_shadows.uniform_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 12,
+				resource: wgpu::BindingResource::TextureView(&cascaded_shadows.view),
+			},
+			wgpu::BindGroupEntry {
+				binding: 13,
+				resource: wgpu::BindingResource::TextureView(
+					&environment_map.bound_irradiance().view,
+				),
+			},
+			wgpu::BindGroupEntry {
+				binding: 14,
+				resource: wgpu::BindingResource::TextureView(
+					environment_map.bound_brdf_lut(),
+				),
+			},
+			wgpu::BindGroupEntry {
+				binding: 15,
+				resource: wind_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 16,
+				resource: point_lights_buf.as_entire_binding(),
+			},
+			// The point shadows compare like the directional light's.
+			wgpu::BindGroupEntry {
+				binding: 17,
+				resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+			},
+			wgpu::BindGroupEntry {
+				binding: 18,
+				resource: wgpu::BindingResource::TextureView(point_shadow_views[0]),
+			},
+			wgpu::BindGroupEntry {
+				binding: 19,
+				resource: wgpu::BindingResource::TextureView(point_shadow_views[1]),
+			},
+			wgpu::BindGroupEntry {
+				binding: 20,
+				resource: wgpu::BindingResource::TextureView(point_shadow_views[2]),
+			},
+			wgpu::BindGroupEntry {
+				binding: 21,
+				resource: wgpu::BindingResource::TextureView(point_shadow_views[3]),
+			},
+		],
+	})
+}
@@ -0,0 +1,88 @@
+// Culls instances against the camera's frustum and picks the level of detail of
+// the visible ones, packing them together by level to be drawn with one
+// indirect draw per level.
+
+// Matches `InstanceData` in `gpu_culling.rs`.
+struct InstanceData {
+	transform: mat4x4<f32>,
+	// Bounds the instance in world space.
+	aabb_min: vec3<f32>,
+	aabb_max: vec3<f32>,
+	// The furthest camera distance each level is drawn at.
+	lod_distances: vec4<f32>,
+};
+
+// Matches `Instance` in `vertex.rs`.
+struct Instance {
+	transform: mat4x4<f32>,
+};
+
+// Matches `wgpu::util::DrawIndexedIndirect`.
+struct DrawArgs {
+	index_count: u32,
+	instance_count: atomic<u32>,
+	first_index: u32,
+	base_vertex: i32,
+	first_instance: u32,
+};
+
+struct LodCullUniform {
+	// Left, right, bottom, top, near and far, with the inside in front.
+	planes: array<vec4<f32>, 6>,
+	eye: vec3<f32>,
+	num_instances: u32,
+	num_lods: u32,
+	// How many instances each level's range of `visible` holds.
+	capacity: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> instances: array<InstanceData>;
+@group(0) @binding(1)
+var<uniform> cull: LodCullUniform;
+// The visible instances, packed together in a range per level.
+@group(0) @binding(2)
+var<storage, read_write> visible: array<Instance>;
+// The draw of each level.
+@group(0) @binding(3)
+var<storage, read_write> draw_args: array<DrawArgs, 4>;
+
+// Whether the box may be inside the frustum: for every plane, its corner
+// furthest along the plane's normal is in front of it.
+fn intersects_frustum(aabb_min: vec3<f32>, aabb_max: vec3<f32>) -> bool {
+	for (var i = 0u; i < 6u; i += 1u) {
+		let plane = cull.planes[i];
+		let corner = select(aabb_min, aabb_max, plane.xyz >= vec3<f32>(0.0));
+		if dot(plane.xyz, corner) + plane.w < 0.0 {
+			return false;
+		}
+	}
+	return true;
+}
+
+// The first level drawn this far away, or the last one past all of them, as in
+// `LodMesh::select_lod`.
+fn select_lod(distance: f32, lod_distances: vec4<f32>) -> u32 {
+	for (var i = 0u; i < cull.num_lods; i += 1u) {
+		if distance <= lod_distances[i] {
+			return i;
+		}
+	}
+	return cull.num_lods - 1u;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let i = id.x;
+	if i >= cull.num_instances {
+		return;
+	}
+	let instance = instances[i];
+	if !intersects_frustum(instance.aabb_min, instance.aabb_max) {
+		return;
+	}
+	let center = (instance.aabb_min + instance.aabb_max) * 0.5;
+	let lod = select_lod(distance(cull.eye, center), instance.lod_distances);
+	let slot = atomicAdd(&draw_args[lod].instance_count, 1u);
+	visible[lod * cull.capacity + slot] = Instance(instance.transform);
+}
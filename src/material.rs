@@ -0,0 +1,367 @@
+//! Surface properties of drawn meshes.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::render_state::BlendMode;
+use crate::resources::{Handle, ResourceManager};
+use crate::tex2d::{SamplerConfig, Tex2d};
+
+/// The layout of `shader.wgsl`'s `MaterialUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct MaterialUniform {
+	parallax_depth_scale: f32,
+	_pad: [f32; 3],
+}
+
+/// The textures a mesh is drawn with, bound at group 0 of the main pipeline, and
+/// the pipeline state they need.
+pub struct Material {
+	pub bind_group: wgpu::BindGroup,
+	pub blend: BlendMode,
+	/// The faces that aren't drawn, unless `double_sided` is set.
+	pub cull_mode: Option<wgpu::Face>,
+	/// Draws both faces whatever `cull_mode` is, for thin surfaces like leaves.
+	pub double_sided: bool,
+	/// Whether the surface is shaded physically, from its metallic-roughness map,
+	/// rather than with Blinn-Phong highlights from its specular map.
+	pub pbr: bool,
+	/// Whether the mesh sways in the wind, drawn with `foliage_shader.wgsl`. See
+	/// [`RenderState::set_wind_params`].
+	///
+	/// [`RenderState::set_wind_params`]: crate::render_state::RenderState::set_wind_params
+	pub foliage: bool,
+	/// See [`Self::parallax_depth_scale`].
+	parallax_depth_scale: f32,
+}
+impl Material {
+	/// Creates an opaque material culling back faces, with Blinn-Phong
+	/// highlights. `layout` must be [`Self::layout`].
+	pub fn new(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		diffuse: &Tex2d,
+		normal_map: &Tex2d,
+		specular: &Tex2d,
+		label: Option<&str>,
+	) -> Self {
+		// The metallic-roughness and height slots aren't read, but must be bound.
+		let textures = [diffuse, normal_map, specular, specular];
+		Self::with_textures(device, layout, textures, false, None, label)
+	}
+
+	/// Creates an opaque material culling back faces, shaded physically with
+	/// roughness from the green channel of `metallic_roughness` and metalness from
+	/// its blue channel. `layout` must be [`Self::layout`].
+	pub fn new_pbr(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		diffuse: &Tex2d,
+		normal_map: &Tex2d,
+		metallic_roughness: &Tex2d,
+		label: Option<&str>,
+	) -> Self {
+		// The specular and height slots aren't read, but must be bound.
+		let textures = [diffuse, normal_map, metallic_roughness, metallic_roughness];
+		Self::with_textures(device, layout, textures, true, None, label)
+	}
+
+	/// `textures` are the diffuse, normal, specular and metallic-roughness maps.
+	/// `parallax` is a height map and its depth scale.
+	fn with_textures(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		[diffuse, normal_map, specular, metallic_roughness]: [&Tex2d; 4],
+		pbr: bool,
+		parallax: Option<(&Tex2d, f32)>,
+		label: Option<&str>,
+	) -> Self {
+		let (height_map, parallax_depth_scale) = parallax.unwrap_or((diffuse, 0.));
+		let uniform = MaterialUniform {
+			parallax_depth_scale,
+			_pad: [0.; 3],
+		};
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Material Uniform"),
+				contents: bytemuck::bytes_of(&uniform),
+				usage: wgpu::BufferUsages::UNIFORM,
+			});
+		let [diffuse_t, diffuse_s] = diffuse.bind_group_entries(0);
+		let [normal_t, normal_s] = normal_map.bind_group_entries(2);
+		let [specular_t, specular_s] = specular.bind_group_entries(4);
+		let [mr_t, mr_s] = metallic_roughness.bind_group_entries(6);
+		let [height_t, height_s] = height_map.bind_group_entries(8);
+		let uniform = wgpu::BindGroupEntry {
+			binding: 10,
+			resource: uniform_buf.as_entire_binding(),
+		};
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label,
+			layout,
+			entries: &[
+				diffuse_t, diffuse_s, normal_t, normal_s, specular_t, specular_s, mr_t,
+				mr_s, height_t, height_s, uniform,
+			],
+		});
+		Self {
+			bind_group,
+			blend: BlendMode::default(),
+			cull_mode: Some(wgpu::Face::Back),
+			double_sided: false,
+			pbr,
+			foliage: false,
+			parallax_depth_scale,
+		}
+	}
+
+	/// A layout with a diffuse texture, a normal map, a specular map, a
+	/// metallic-roughness map and a height map at bindings 0, 2, 4, 6 and 8, each
+	/// followed by its sampler, and a uniform at binding 10.
+	pub fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		let [diffuse_t, diffuse_s] = Tex2d::layout_entries(0);
+		let [normal_t, normal_s] = Tex2d::layout_entries(2);
+		let [specular_t, specular_s] = Tex2d::layout_entries(4);
+		let [mr_t, mr_s] = Tex2d::layout_entries(6);
+		let [height_t, height_s] = Tex2d::layout_entries(8);
+		let uniform = wgpu::BindGroupLayoutEntry {
+			binding: 10,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("Material Bind Group Layout"),
+			entries: &[
+				diffuse_t, diffuse_s, normal_t, normal_s, specular_t, specular_s, mr_t,
+				mr_s, height_t, height_s, uniform,
+			],
+		})
+	}
+
+	/// How deep the surface looks below the mesh where its height map is 0, in
+	/// texture coordinates, usually from 0.01 to 0.1. Materials with a height
+	/// map and a scale other than 0 are drawn with parallax occlusion mapping,
+	/// see [`MaterialBuilder::height_map`].
+	pub fn parallax_depth_scale(&self) -> f32 {
+		self.parallax_depth_scale
+	}
+
+	/// The faces the pipeline culls, taking `double_sided` into account.
+	pub fn pipeline_cull_mode(&self) -> Option<wgpu::Face> {
+		if self.double_sided {
+			None
+		} else {
+			self.cull_mode
+		}
+	}
+}
+
+/// Describes a [`Material`], see [`RenderState::build_material`]. Missing
+/// textures default to white, a flat normal map and no specular highlights.
+/// Materials with a metallic-roughness map are shaded physically instead.
+///
+/// [`RenderState::build_material`]: crate::render_state::RenderState::build_material
+pub struct MaterialBuilder<'a> {
+	diffuse: Option<&'a Tex2d>,
+	normal_map: Option<&'a Tex2d>,
+	specular: Option<&'a Tex2d>,
+	metallic_roughness: Option<&'a Tex2d>,
+	height_map: Option<&'a Tex2d>,
+	parallax_depth_scale: f32,
+	blend: BlendMode,
+	cull_mode: Option<wgpu::Face>,
+	double_sided: bool,
+	foliage: bool,
+	label: Option<&'a str>,
+}
+impl Default for MaterialBuilder<'_> {
+	fn default() -> Self {
+		Self {
+			diffuse: None,
+			normal_map: None,
+			specular: None,
+			metallic_roughness: None,
+			height_map: None,
+			parallax_depth_scale: 0.05,
+			blend: BlendMode::default(),
+			cull_mode: Some(wgpu::Face::Back),
+			double_sided: false,
+			foliage: false,
+			label: None,
+		}
+	}
+}
+impl<'a> MaterialBuilder<'a> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn diffuse(mut self, texture: &'a Tex2d) -> Self {
+		self.diffuse = Some(texture);
+		self
+	}
+
+	/// A tangent space normal map, see [`Tex2d::load_normal_map_from_path`].
+	pub fn normal_map(mut self, texture: &'a Tex2d) -> Self {
+		self.normal_map = Some(texture);
+		self
+	}
+
+	/// The color of specular highlights, black for none.
+	pub fn specular(mut self, texture: &'a Tex2d) -> Self {
+		self.specular = Some(texture);
+		self
+	}
+
+	/// Roughness in the green channel and metalness in the blue channel, as in
+	/// glTF. Replaces the specular map with physically based shading.
+	pub fn metallic_roughness(mut self, texture: &'a Tex2d) -> Self {
+		self.metallic_roughness = Some(texture);
+		self
+	}
+
+	/// How high the surface is, in the red channel, from 0 at the deepest to 1.
+	/// Makes the surface look bumpy with parallax occlusion mapping, which shifts
+	/// where all the textures are sampled.
+	pub fn height_map(mut self, texture: &'a Tex2d) -> Self {
+		self.height_map = Some(texture);
+		self
+	}
+
+	/// See [`Material::parallax_depth_scale`]. 0.05 by default, and only used
+	/// with a height map.
+	pub fn parallax_depth_scale(mut self, scale: f32) -> Self {
+		self.parallax_depth_scale = scale;
+		self
+	}
+
+	pub fn blend(mut self, blend: BlendMode) -> Self {
+		self.blend = blend;
+		self
+	}
+
+	pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+		self.cull_mode = cull_mode;
+		self
+	}
+
+	pub fn double_sided(mut self, double_sided: bool) -> Self {
+		self.double_sided = double_sided;
+		self
+	}
+
+	/// See [`Material::foliage`].
+	pub fn foliage(mut self, foliage: bool) -> Self {
+		self.foliage = foliage;
+		self
+	}
+
+	pub fn label(mut self, label: &'a str) -> Self {
+		self.label = Some(label);
+		self
+	}
+
+	/// Creates the material. `layout` must be [`Material::layout`].
+	pub fn build(
+		self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		layout: &wgpu::BindGroupLayout,
+	) -> Material {
+		// Only created for the slots that are empty.
+		let white;
+		let flat;
+		let black;
+		let diffuse = match self.diffuse {
+			Some(texture) => texture,
+			None => {
+				white = Tex2d::from_color(device, queue, Some("White"), [255; 4]);
+				&white
+			}
+		};
+		let normal_map = match self.normal_map {
+			Some(texture) => texture,
+			None => {
+				flat = Tex2d::flat_normal_map(device, queue);
+				&flat
+			}
+		};
+		let specular = match self.specular {
+			Some(texture) => texture,
+			None => {
+				black = Tex2d::from_color(device, queue, Some("Black"), [0, 0, 0, 255]);
+				&black
+			}
+		};
+		// The unused slots are bound with another texture, as in `Material::new`.
+		let (textures, pbr) = match self.metallic_roughness {
+			Some(texture) => ([diffuse, normal_map, texture, texture], true),
+			None => ([diffuse, normal_map, specular, specular], false),
+		};
+		let parallax = (self.height_map)
+			.filter(|_| self.parallax_depth_scale != 0.)
+			.map(|texture| (texture, self.parallax_depth_scale));
+		let material = Material::with_textures(
+			device, layout, textures, pbr, parallax, self.label,
+		);
+		Material {
+			blend: self.blend,
+			cull_mode: self.cull_mode,
+			double_sided: self.double_sided,
+			foliage: self.foliage,
+			..material
+		}
+	}
+}
+
+/// The material a `RenderState` draws its mesh with until another is loaded,
+/// with its textures in a [`ResourceManager`].
+pub(crate) struct DefaultMaterial {
+	/// See [`Material::layout`].
+	pub layout: wgpu::BindGroupLayout,
+	pub material: Material,
+	pub diffuse_tex: Handle<Tex2d>,
+	pub normal_map: Handle<Tex2d>,
+	pub specular_map: Handle<Tex2d>,
+}
+impl DefaultMaterial {
+	/// A tree without specular highlights, adding its textures to `resources`.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		resources: &mut ResourceManager,
+	) -> Self {
+		let diffuse_tex = Tex2d::new_from_img_bytes(
+			device,
+			queue,
+			include_bytes!("tree.png"),
+			Some("Diffuse Texture"),
+			SamplerConfig::default(),
+		);
+		let normal_map = Tex2d::flat_normal_map(device, queue);
+		let specular_map =
+			Tex2d::from_color(device, queue, Some("Specular Map"), [0, 0, 0, 255]);
+		let layout = Material::layout(device);
+		let material = Material::new(
+			device,
+			&layout,
+			&diffuse_tex,
+			&normal_map,
+			&specular_map,
+			Some("material_bind_group"),
+		);
+		Self {
+			layout,
+			material,
+			diffuse_tex: resources.insert(diffuse_tex),
+			normal_map: resources.insert(normal_map),
+			specular_map: resources.insert(specular_map),
+		}
+	}
+}
@@ -0,0 +1,235 @@
+//! Counts the GPU memory of the buffers and textures created through a
+//! [`GpuMemoryTracker`], for as long as they live.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+/// How much memory the tracked buffers and textures take, at one point in time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+	pub buffer_bytes: u64,
+	pub texture_bytes: u64,
+	pub buffer_count: u64,
+	pub texture_count: u64,
+}
+impl std::fmt::Display for MemoryReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		const MIB: f64 = (1 << 20) as f64;
+		write!(
+			f,
+			"{} buffers ({:.2} MiB), {} textures ({:.2} MiB)",
+			self.buffer_count,
+			self.buffer_bytes as f64 / MIB,
+			self.texture_count,
+			self.texture_bytes as f64 / MIB,
+		)
+	}
+}
+
+#[derive(Default)]
+struct Counters {
+	buffer_bytes: AtomicU64,
+	texture_bytes: AtomicU64,
+	buffer_count: AtomicU64,
+	texture_count: AtomicU64,
+}
+
+/// Creates buffers and textures that add their size to the tracker's totals,
+/// and take it away again when dropped. Texture sizes are estimated from their
+/// format, as drivers may pad them.
+#[derive(Clone, Default)]
+pub struct GpuMemoryTracker {
+	counters: Arc<Counters>,
+}
+impl GpuMemoryTracker {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn create_buffer(
+		&self,
+		device: &wgpu::Device,
+		desc: &wgpu::BufferDescriptor<'_>,
+	) -> TrackedBuffer {
+		self.track_buffer(device.create_buffer(desc))
+	}
+
+	pub fn create_buffer_init(
+		&self,
+		device: &wgpu::Device,
+		desc: &wgpu::util::BufferInitDescriptor<'_>,
+	) -> TrackedBuffer {
+		self.track_buffer(device.create_buffer_init(desc))
+	}
+
+	pub fn create_texture(
+		&self,
+		device: &wgpu::Device,
+		desc: &wgpu::TextureDescriptor<'_>,
+	) -> TrackedTexture {
+		let bytes = texture_bytes(desc);
+		self.counters
+			.texture_bytes
+			.fetch_add(bytes, Ordering::Relaxed);
+		self.counters.texture_count.fetch_add(1, Ordering::Relaxed);
+		TrackedTexture {
+			texture: device.create_texture(desc),
+			bytes,
+			tracker: self.clone(),
+		}
+	}
+
+	/// The totals of the buffers and textures that are alive.
+	pub fn report(&self) -> MemoryReport {
+		let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+		MemoryReport {
+			buffer_bytes: load(&self.counters.buffer_bytes),
+			texture_bytes: load(&self.counters.texture_bytes),
+			buffer_count: load(&self.counters.buffer_count),
+			texture_count: load(&self.counters.texture_count),
+		}
+	}
+
+	fn track_buffer(&self, buffer: wgpu::Buffer) -> TrackedBuffer {
+		let bytes = buffer.size();
+		self.counters
+			.buffer_bytes
+			.fetch_add(bytes, Ordering::Relaxed);
+		self.counters.buffer_count.fetch_add(1, Ordering::Relaxed);
+		TrackedBuffer {
+			buffer,
+			tracker: self.clone(),
+		}
+	}
+}
+
+/// A buffer counted by a [`GpuMemoryTracker`] until it is dropped.
+pub struct TrackedBuffer {
+	buffer: wgpu::Buffer,
+	tracker: GpuMemoryTracker,
+}
+impl std::ops::Deref for TrackedBuffer {
+	type Target = wgpu::Buffer;
+
+	fn deref(&self) -> &wgpu::Buffer {
+		&self.buffer
+	}
+}
+impl Drop for TrackedBuffer {
+	fn drop(&mut self) {
+		let counters = &self.tracker.counters;
+		counters
+			.buffer_bytes
+			.fetch_sub(self.buffer.size(), Ordering::Relaxed);
+		counters.buffer_count.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// A texture counted by a [`GpuMemoryTracker`] until it is dropped.
+pub struct TrackedTexture {
+	texture: wgpu::Texture,
+	/// What it added to the tracker's total.
+	bytes: u64,
+	tracker: GpuMemoryTracker,
+}
+impl std::ops::Deref for TrackedTexture {
+	type Target = wgpu::Texture;
+
+	fn deref(&self) -> &wgpu::Texture {
+		&self.texture
+	}
+}
+impl Drop for TrackedTexture {
+	fn drop(&mut self) {
+		let counters = &self.tracker.counters;
+		counters
+			.texture_bytes
+			.fetch_sub(self.bytes, Ordering::Relaxed);
+		counters.texture_count.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// The size of all mip levels, layers and samples of a texture.
+fn texture_bytes(desc: &wgpu::TextureDescriptor<'_>) -> u64 {
+	let format = desc.format;
+	let (block_width, block_height) = format.block_dimensions();
+	// Combined depth-stencil formats have a size per aspect, and the size of
+	// `Depth24Plus` is up to the driver.
+	let block_size = format.block_size(None).unwrap_or_else(|| {
+		let depth = format.block_size(Some(wgpu::TextureAspect::DepthOnly));
+		let stencil = format.block_size(Some(wgpu::TextureAspect::StencilOnly));
+		depth.unwrap_or(4) + stencil.unwrap_or(0)
+	}) as u64;
+	let level_bytes = (0..desc.mip_level_count)
+		.filter_map(|level| desc.mip_level_size(level))
+		.map(|size| {
+			let size = size.physical_size(format);
+			let blocks = (size.width / block_width) as u64
+				* (size.height / block_height) as u64
+				* size.depth_or_array_layers as u64;
+			blocks * block_size
+		})
+		.sum::<u64>();
+	level_bytes * desc.sample_count as u64
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::gpu_context::test_context;
+
+	fn texture_desc(mip_level_count: u32) -> wgpu::TextureDescriptor<'static> {
+		wgpu::TextureDescriptor {
+			label: None,
+			size: wgpu::Extent3d {
+				width: 4,
+				height: 4,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8Unorm,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		}
+	}
+
+	#[test]
+	fn texture_sizes_include_mips() {
+		assert_eq!(texture_bytes(&texture_desc(1)), 64);
+		assert_eq!(texture_bytes(&texture_desc(3)), 64 + 16 + 4);
+	}
+
+	#[test]
+	fn counters_return_to_zero() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let device = &context.device;
+		let tracker = GpuMemoryTracker::new();
+		let buffer = tracker.create_buffer(
+			device,
+			&wgpu::BufferDescriptor {
+				label: None,
+				size: 256,
+				usage: wgpu::BufferUsages::UNIFORM,
+				mapped_at_creation: false,
+			},
+		);
+		let texture = tracker.create_texture(device, &texture_desc(3));
+		assert_eq!(
+			tracker.report(),
+			MemoryReport {
+				buffer_bytes: 256,
+				texture_bytes: 84,
+				buffer_count: 1,
+				texture_count: 1,
+			}
+		);
+		drop((buffer, texture));
+		assert_eq!(tracker.report(), MemoryReport::default());
+	}
+}
@@ -0,0 +1,539 @@
+//! Procedurally generated meshes, and meshes uploaded to the GPU.
+//!
+//! All meshes wind their triangles counter-clockwise when seen from outside, to
+//! match the pipeline's `FrontFace::Ccw`.
+
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::f32::consts::PI;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+use crate::aabb::Aabb;
+use crate::camera::Ray;
+use crate::vertex::{Normal, Pos, Uv, Vertex};
+
+/// A vertex buffer and the index buffer of its triangles, ready to draw.
+pub struct GpuMesh {
+	pub vtx_buf: wgpu::Buffer,
+	/// `u32` indices.
+	pub idx_buf: wgpu::Buffer,
+	pub num_indices: u32,
+	/// How the indices form primitives, `TriangleList` by default.
+	pub topology: wgpu::PrimitiveTopology,
+	/// Bounds the vertices in model space. Empty at the origin if there are none.
+	pub aabb: Aabb,
+}
+impl GpuMesh {
+	pub fn new(device: &wgpu::Device, vertices: &[Vertex], indices: &[u32]) -> Self {
+		let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Vertex Buffer"),
+			contents: bytemuck::cast_slice(vertices),
+			// Copied from to bake distance fields, see `sdf_ao.rs`.
+			usage: wgpu::BufferUsages::VERTEX
+				| wgpu::BufferUsages::COPY_DST
+				| wgpu::BufferUsages::COPY_SRC,
+		});
+		let idx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Index Buffer"),
+			contents: bytemuck::cast_slice(indices),
+			usage: wgpu::BufferUsages::INDEX
+				| wgpu::BufferUsages::COPY_DST
+				| wgpu::BufferUsages::COPY_SRC,
+		});
+		Self {
+			vtx_buf,
+			idx_buf,
+			num_indices: indices.len() as u32,
+			topology: wgpu::PrimitiveTopology::TriangleList,
+			aabb: if vertices.is_empty() {
+				Aabb::new(Point3::origin(), Point3::origin())
+			} else {
+				Aabb::from_vertices(vertices)
+			},
+		}
+	}
+
+	/// Draws the indices as `topology` instead. Only triangle lists cast shadows.
+	pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+		self.topology = topology;
+		self
+	}
+
+	/// How far along `ray` it hits the mesh's bounding box, once moved by
+	/// `transform`, in the ray's units. Picks the box rather than the triangles,
+	/// as their vertices are only on the GPU.
+	pub fn intersect_ray(&self, ray: &Ray, transform: Matrix4<f32>) -> Option<f32> {
+		// Moving the ray into model space keeps distances along it.
+		let inverse = transform.try_inverse()?;
+		let local = Ray::new(
+			inverse.transform_point(&ray.origin),
+			inverse.transform_vector(&ray.direction),
+		);
+		self.aabb.intersect_ray(&local)
+	}
+}
+
+/// A mesh with an index buffer per level of detail, over the same vertices. The
+/// further it is from the camera, the fewer triangles it is drawn with.
+pub struct LodMesh {
+	pub vtx_buf: wgpu::Buffer,
+	/// The furthest camera distance each level is drawn at, its `u32` index
+	/// buffer and its number of indices, by increasing distance.
+	pub lods: Vec<(f32, wgpu::Buffer, u32)>,
+	/// Bounds the vertices in model space. Its center is where the distance to
+	/// the camera is measured from.
+	pub aabb: Aabb,
+}
+impl LodMesh {
+	/// Uploads `vertices` and the indices of each level, given with the furthest
+	/// camera distance it is drawn at.
+	///
+	/// # Panics
+	/// If `lod_meshes` is empty.
+	pub fn from_full_mesh(
+		device: &wgpu::Device,
+		vertices: &[Vertex],
+		lod_meshes: &[(f32, Vec<u32>)],
+	) -> Self {
+		assert!(!lod_meshes.is_empty(), "A LodMesh needs at least one level");
+		let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("LOD Vertex Buffer"),
+			contents: bytemuck::cast_slice(vertices),
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+		});
+		let mut lods: Vec<_> = lod_meshes
+			.iter()
+			.enumerate()
+			.map(|(level, (max_distance, indices))| {
+				let label = format!("LOD {level} Index Buffer");
+				let idx_buf =
+					device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+						label: Some(&label),
+						contents: bytemuck::cast_slice(indices),
+						usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+					});
+				(*max_distance, idx_buf, indices.len() as u32)
+			})
+			.collect();
+		lods.sort_by(|(a, ..), (b, ..)| a.total_cmp(b));
+		Self {
+			vtx_buf,
+			lods,
+			aabb: if vertices.is_empty() {
+				Aabb::new(Point3::origin(), Point3::origin())
+			} else {
+				Aabb::from_vertices(vertices)
+			},
+		}
+	}
+
+	/// The index buffer and number of indices of the level drawn at
+	/// `camera_distance`. Beyond the furthest distance, that of the least
+	/// detailed level.
+	pub fn select_lod(&self, camera_distance: f32) -> (&wgpu::Buffer, u32) {
+		let (_, idx_buf, num_indices) = self
+			.lods
+			.iter()
+			.find(|(max_distance, ..)| camera_distance <= *max_distance)
+			.unwrap_or_else(|| self.lods.last().expect("There is at least one level"));
+		(idx_buf, *num_indices)
+	}
+}
+
+/// Generates a UV sphere centered on the origin.
+///
+/// `stacks` is the number of rings from pole to pole, and `slices` the number of
+/// segments around the equator. Each pole gets one vertex per slice, so that the
+/// triangles touching it don't all share a single UV. Each ring also repeats its
+/// first vertex, so the texture doesn't wrap backwards at the seam.
+///
+/// The mesh has `2 * slices + (stacks - 1) * (slices + 1)` vertices.
+///
+/// # Panics
+/// If `stacks < 2`, `slices < 3`, or there are too many vertices for `u16`
+/// indices.
+pub fn generate_sphere(
+	radius: f32,
+	stacks: u32,
+	slices: u32,
+) -> (Vec<Vertex>, Vec<u16>) {
+	assert!(stacks >= 2 && slices >= 3, "Sphere is too coarse");
+	let n_vertices = 2 * slices + (stacks - 1) * (slices + 1);
+	assert!(n_vertices <= u16::MAX as u32 + 1, "Sphere is too fine");
+
+	// The derivatives of the position with respect to u and v, normalized.
+	let tangents = |u: f32, v: f32| {
+		let (phi, theta) = (v * PI, u * 2. * PI);
+		let tangent = [theta.cos(), 0., -theta.sin()];
+		let bitangent = [phi.cos() * theta.sin(), -phi.sin(), phi.cos() * theta.cos()];
+		(tangent, bitangent)
+	};
+
+	let mut vertices = Vec::with_capacity(n_vertices as usize);
+	// North pole
+	for j in 0..slices {
+		let u = (j as f32 + 0.5) / slices as f32;
+		let (tangent, bitangent) = tangents(u, 0.);
+		vertices.push(
+			Vertex::new(
+				Pos::new(0., radius, 0.),
+				Uv { u, v: 0. },
+				Normal::new(0., 1., 0.),
+			)
+			.with_tangents(tangent, bitangent),
+		);
+	}
+	// Rings
+	for i in 1..stacks {
+		let v = i as f32 / stacks as f32;
+		let phi = v * PI;
+		for j in 0..=slices {
+			let u = j as f32 / slices as f32;
+			let theta = u * 2. * PI;
+			let (x, y, z) =
+				(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos());
+			let (tangent, bitangent) = tangents(u, v);
+			vertices.push(
+				Vertex::new(
+					Pos::new(radius * x, radius * y, radius * z),
+					Uv { u, v },
+					Normal::new(x, y, z),
+				)
+				.with_tangents(tangent, bitangent),
+			);
+		}
+	}
+	// South pole
+	for j in 0..slices {
+		let u = (j as f32 + 0.5) / slices as f32;
+		let (tangent, bitangent) = tangents(u, 1.);
+		vertices.push(
+			Vertex::new(
+				Pos::new(0., -radius, 0.),
+				Uv { u, v: 1. },
+				Normal::new(0., -1., 0.),
+			)
+			.with_tangents(tangent, bitangent),
+		);
+	}
+
+	let ring = |i: u32, j: u32| (slices + (i - 1) * (slices + 1) + j) as u16;
+	let south = slices + (stacks - 1) * (slices + 1);
+	let mut indices = Vec::with_capacity((6 * slices * (stacks - 1)) as usize);
+	for j in 0..slices {
+		indices.extend_from_slice(&[j as u16, ring(1, j), ring(1, j + 1)]);
+	}
+	for i in 1..stacks - 1 {
+		for j in 0..slices {
+			let (tl, bl) = (ring(i, j), ring(i + 1, j));
+			let (br, tr) = (ring(i + 1, j + 1), ring(i, j + 1));
+			indices.extend_from_slice(&[tl, bl, br, br, tr, tl]);
+		}
+	}
+	for j in 0..slices {
+		let last = stacks - 1;
+		indices.extend_from_slice(&[
+			ring(last, j),
+			(south + j) as u16,
+			ring(last, j + 1),
+		]);
+	}
+	(vertices, indices)
+}
+
+/// Generates an axis aligned cube centered on the origin, with each face mapped
+/// to the whole texture.
+///
+/// The mesh has 24 vertices, so that faces don't share UVs.
+pub fn generate_cube(half_extent: f32) -> (Vec<Vertex>, Vec<u16>) {
+	// (normal, right, up) of each face, as seen from outside.
+	const FACES: [[[f32; 3]; 3]; 6] = [
+		[[0., 0., 1.], [1., 0., 0.], [0., 1., 0.]],
+		[[0., 0., -1.], [-1., 0., 0.], [0., 1., 0.]],
+		[[1., 0., 0.], [0., 0., -1.], [0., 1., 0.]],
+		[[-1., 0., 0.], [0., 0., 1.], [0., 1., 0.]],
+		[[0., 1., 0.], [1., 0., 0.], [0., 0., -1.]],
+		[[0., -1., 0.], [1., 0., 0.], [0., 0., 1.]],
+	];
+	let h = half_extent;
+	let mut vertices = Vec::with_capacity(24);
+	let mut indices = Vec::with_capacity(36);
+	for [n, r, up] in FACES {
+		let corner = |sr: f32, su: f32, uv: Uv| {
+			let p = |k: usize| (n[k] + sr * r[k] + su * up[k]) * h;
+			let normal = Normal::new(n[0], n[1], n[2]);
+			// v increases downwards.
+			let bitangent = up.map(|x| -x);
+			Vertex::new(Pos::new(p(0), p(1), p(2)), uv, normal)
+				.with_tangents(r, bitangent)
+		};
+		let base = vertices.len() as u16;
+		vertices.extend_from_slice(&[
+			corner(-1., 1., Uv { u: 0., v: 0. }),
+			corner(-1., -1., Uv { u: 0., v: 1. }),
+			corner(1., -1., Uv { u: 1., v: 1. }),
+			corner(1., 1., Uv { u: 1., v: 0. }),
+		]);
+		indices.extend([0, 1, 2, 2, 3, 0].map(|i| base + i));
+	}
+	(vertices, indices)
+}
+
+/// Describes a square, facing the camera.
+const fn quad_vertex(x: f32, y: f32, uv: Uv) -> Vertex {
+	Vertex::new(Pos::new(x, y, 0.0), uv, Normal::new(0.0, 0.0, 1.0))
+		.with_tangents([1.0, 0.0, 0.0], [0.0, -1.0, 0.0])
+}
+/// A unit square on the XY axes facing +z, centered on the origin. It is the
+/// mesh a `RenderState` draws until another is loaded.
+pub(crate) const QUAD_VERTICES: &[Vertex] = &[
+	// Starts at top left of square, goes Ccw
+	quad_vertex(-0.5, 0.5, Uv { u: 0.0, v: 0.0 }),
+	quad_vertex(-0.5, -0.5, Uv { u: 0.0, v: 1.0 }),
+	quad_vertex(0.5, -0.5, Uv { u: 1.0, v: 1.0 }),
+	quad_vertex(0.5, 0.5, Uv { u: 1.0, v: 0.0 }),
+];
+pub(crate) const QUAD_INDICES: &[u32] = &[0, 1, 2, 2, 3, 0];
+
+/// Generates a plane on the XZ axes facing +y, centered on the origin.
+///
+/// Each side is split into `subdivisions` quads, so the mesh has
+/// `(subdivisions + 1)^2` vertices.
+///
+/// # Panics
+/// If `subdivisions` is 0, or there are too many vertices for `u16` indices.
+pub fn generate_plane(
+	width: f32,
+	depth: f32,
+	subdivisions: u32,
+) -> (Vec<Vertex>, Vec<u16>) {
+	assert!(subdivisions >= 1, "Plane needs at least one subdivision");
+	let side = subdivisions + 1;
+	assert!(side * side <= u16::MAX as u32 + 1, "Plane is too fine");
+
+	let mut vertices = Vec::with_capacity((side * side) as usize);
+	for row in 0..side {
+		let v = row as f32 / subdivisions as f32;
+		for col in 0..side {
+			let u = col as f32 / subdivisions as f32;
+			let pos = Pos::new((u - 0.5) * width, 0., (v - 0.5) * depth);
+			let normal = Normal::new(0., 1., 0.);
+			vertices.push(
+				Vertex::new(pos, Uv { u, v }, normal)
+					.with_tangents([1., 0., 0.], [0., 0., 1.]),
+			);
+		}
+	}
+
+	let idx = |row: u32, col: u32| (row * side + col) as u16;
+	let mut indices = Vec::with_capacity((6 * subdivisions * subdivisions) as usize);
+	for row in 0..subdivisions {
+		for col in 0..subdivisions {
+			let (tl, bl) = (idx(row, col), idx(row + 1, col));
+			let (br, tr) = (idx(row + 1, col + 1), idx(row, col + 1));
+			indices.extend_from_slice(&[tl, bl, br, br, tr, tl]);
+		}
+	}
+	(vertices, indices)
+}
+
+/// Generates terrain from a `width` x `depth` grayscale heightmap, with one byte
+/// per pixel in row major order, as from [`image::GrayImage`]. The terrain lies
+/// on the XZ axes centered on the origin, with one vertex per pixel spaced one
+/// unit apart. Rows go along +z.
+///
+/// Each vertex is lifted by `value / 255 * max_height`, where `value` is its
+/// pixel's, from 0 for black to `max_height` for white. Mid-gray, 128, is then
+/// slightly above half of `max_height`. Normals come from the central
+/// differences of neighboring heights, with the edges using their own height in
+/// place of the missing neighbor.
+///
+/// # Panics
+/// If `width` or `depth` is less than 2, or `heightmap` doesn't have
+/// `width * depth` bytes.
+pub fn generate_terrain(
+	heightmap: &[u8],
+	width: u32,
+	depth: u32,
+	max_height: f32,
+) -> (Vec<Vertex>, Vec<u32>) {
+	generate_terrain_region(heightmap, width, depth, max_height, 0..width, 0..depth)
+}
+
+/// Like [`generate_terrain`], but only the vertices of the pixels in columns
+/// `cols` and rows `rows`, indexed from the region's first one. They are placed
+/// and lit as in the whole terrain, so regions sharing their edge pixels meet
+/// without seams.
+///
+/// # Panics
+/// If either range has less than 2 pixels or reaches past the heightmap, or
+/// `heightmap` doesn't have `width * depth` bytes.
+pub fn generate_terrain_region(
+	heightmap: &[u8],
+	width: u32,
+	depth: u32,
+	max_height: f32,
+	cols: Range<u32>,
+	rows: Range<u32>,
+) -> (Vec<Vertex>, Vec<u32>) {
+	assert!(
+		cols.len() >= 2 && rows.len() >= 2,
+		"Terrain needs at least 2x2 pixels"
+	);
+	assert!(
+		cols.end <= width && rows.end <= depth,
+		"Terrain region {cols:?}x{rows:?} is outside {width}x{depth}"
+	);
+	assert_eq!(
+		heightmap.len(),
+		width as usize * depth as usize,
+		"Heightmap size doesn't match {width}x{depth}"
+	);
+	let height = |col: u32, row: u32| {
+		let col = col.min(width - 1);
+		let row = row.min(depth - 1);
+		heightmap[(row * width + col) as usize] as f32 / 255. * max_height
+	};
+
+	let region_width = cols.len() as u32;
+	let mut vertices = Vec::with_capacity(cols.len() * rows.len());
+	for row in rows.clone() {
+		let v = row as f32 / (depth - 1) as f32;
+		for col in cols.clone() {
+			let u = col as f32 / (width - 1) as f32;
+			// Neighbors are one unit away, so differences span two units.
+			let dh_dx =
+				(height(col + 1, row) - height(col.saturating_sub(1), row)) / 2.;
+			let dh_dz =
+				(height(col, row + 1) - height(col, row.saturating_sub(1))) / 2.;
+			let n = Vector3::new(-dh_dx, 1., -dh_dz).normalize();
+			let tangent = Vector3::new(1., dh_dx, 0.).normalize();
+			let bitangent = Vector3::new(0., dh_dz, 1.).normalize();
+			let pos = Pos::new(
+				col as f32 - (width - 1) as f32 / 2.,
+				height(col, row),
+				row as f32 - (depth - 1) as f32 / 2.,
+			);
+			vertices.push(
+				Vertex::new(pos, Uv { u, v }, Normal::new(n.x, n.y, n.z))
+					.with_tangents(tangent.into(), bitangent.into()),
+			);
+		}
+	}
+
+	let idx = |row: u32, col: u32| row * region_width + col;
+	let region_depth = rows.len() as u32;
+	let mut indices = Vec::with_capacity(
+		6 * (region_width - 1) as usize * (region_depth - 1) as usize,
+	);
+	for row in 0..region_depth - 1 {
+		for col in 0..region_width - 1 {
+			let (tl, bl) = (idx(row, col), idx(row + 1, col));
+			let (br, tr) = (idx(row + 1, col + 1), idx(row, col + 1));
+			indices.extend_from_slice(&[tl, bl, br, br, tr, tl]);
+		}
+	}
+	(vertices, indices)
+}
+
+/// Computes the tangent and bitangent of each vertex from the UVs of the
+/// triangles around it, for normal mapping.
+///
+/// The tangents of each triangle are summed, then made orthogonal to the
+/// vertex's normal and normalized.
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+	let pos = |v: &Vertex| Vector3::new(v.pos.x, v.pos.y, v.pos.z);
+	let mut tangents = vec![Vector3::<f32>::zeros(); vertices.len()];
+	let mut bitangents = vec![Vector3::<f32>::zeros(); vertices.len()];
+	for tri in indices.chunks_exact(3) {
+		let [v0, v1, v2] = [0, 1, 2].map(|i| &vertices[tri[i] as usize]);
+		let (e1, e2) = (pos(v1) - pos(v0), pos(v2) - pos(v0));
+		let (du1, dv1) = (v1.uv.u - v0.uv.u, v1.uv.v - v0.uv.v);
+		let (du2, dv2) = (v2.uv.u - v0.uv.u, v2.uv.v - v0.uv.v);
+		// Solves e1 = du1 * t + dv1 * b and e2 = du2 * t + dv2 * b.
+		let r = 1. / (du1 * dv2 - du2 * dv1);
+		if !r.is_finite() {
+			// The UVs are degenerate.
+			continue;
+		}
+		let t = (e1 * dv2 - e2 * dv1) * r;
+		let b = (e2 * du1 - e1 * du2) * r;
+		for &i in tri {
+			tangents[i as usize] += t;
+			bitangents[i as usize] += b;
+		}
+	}
+
+	for ((vertex, t), b) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+		let n = Vector3::new(vertex.normal.x, vertex.normal.y, vertex.normal.z);
+		// Gram-Schmidt, falling back to any direction orthogonal to the normal.
+		let t = (t - n * n.dot(&t))
+			.try_normalize(f32::EPSILON)
+			.or_else(|| n.cross(&Vector3::x()).try_normalize(f32::EPSILON))
+			.unwrap_or_else(Vector3::z);
+		let b = (b - n * n.dot(&b) - t * t.dot(&b))
+			.try_normalize(f32::EPSILON)
+			.unwrap_or_else(|| n.cross(&t));
+		vertex.tangent = t.into();
+		vertex.bitangent = b.into();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_in_range(vertices: &[Vertex], indices: &[u16]) {
+		assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+	}
+
+	#[test]
+	fn sphere_counts() {
+		let (stacks, slices) = (8, 12);
+		let (vertices, indices) = generate_sphere(1., stacks, slices);
+		assert_eq!(
+			vertices.len() as u32,
+			2 * slices + (stacks - 1) * (slices + 1)
+		);
+		assert_eq!(indices.len() as u32, 6 * slices * (stacks - 1));
+		assert_in_range(&vertices, &indices);
+	}
+
+	#[test]
+	fn cube_counts() {
+		let (vertices, indices) = generate_cube(0.5);
+		assert_eq!(vertices.len(), 24);
+		assert_eq!(indices.len(), 36);
+		assert_in_range(&vertices, &indices);
+	}
+
+	#[test]
+	fn plane_counts() {
+		let (vertices, indices) = generate_plane(2., 3., 4);
+		assert_eq!(vertices.len(), 5 * 5);
+		assert_eq!(indices.len(), 6 * 4 * 4);
+		assert_in_range(&vertices, &indices);
+	}
+
+	#[test]
+	fn flat_terrain_faces_up() {
+		let (width, depth) = (4, 3);
+		let heightmap = vec![128; width as usize * depth as usize];
+		let (vertices, indices) = generate_terrain(&heightmap, width, depth, 10.);
+		assert_eq!(vertices.len(), 12);
+		assert_eq!(indices.len(), 6 * 3 * 2);
+		for vertex in &vertices {
+			let n = vertex.normal;
+			assert_eq!([n.x, n.y, n.z], [0., 1., 0.]);
+			// `value / 255 * max_height`.
+			assert_eq!(vertex.pos.y, 128. / 255. * 10.);
+		}
+	}
+
+	#[test]
+	fn terrain_heights_span_max_height() {
+		let (vertices, _) = generate_terrain(&[0, 255, 255, 0], 2, 2, 10.);
+		let heights: Vec<_> = vertices.iter().map(|v| v.pos.y).collect();
+		assert_eq!(heights, [0., 10., 10., 0.]);
+	}
+}
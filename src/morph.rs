@@ -0,0 +1,244 @@
+//! Meshes blended between shapes on the GPU, such as the expressions of a face,
+//! see `morph.wgsl`.
+
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, Result};
+use wgpu::util::DeviceExt;
+
+use crate::aabb::Aabb;
+use crate::mesh::GpuMesh;
+use crate::vertex::Vertex;
+
+/// How many targets a mesh can blend between.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// The layout of `morph.wgsl`'s `MorphUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct MorphUniform {
+	weights: [f32; MAX_MORPH_TARGETS],
+	num_vertices: u32,
+	num_targets: u32,
+	_pad: [u32; 2],
+}
+
+/// A shape of a mesh, with the vertices of the base shape moved.
+pub struct MorphTarget {
+	/// Matches the base's vertices one to one. Only their positions and normals
+	/// are blended.
+	pub vertices: Vec<Vertex>,
+	/// How much of the target is blended in at first, from 0 to 1.
+	pub weight: f32,
+}
+
+/// A mesh whose vertices are blended from a base shape towards up to
+/// [`MAX_MORPH_TARGETS`] targets by a compute shader, each by its weight:
+/// `base + sum(weight * (target - base))`.
+///
+/// The blended vertices are written into the vertex buffer of [`Self::mesh`],
+/// which draws like any other mesh.
+pub struct MorphedMesh {
+	mesh: Arc<GpuMesh>,
+	num_vertices: u32,
+	/// The contents of `uniform_buf`.
+	uniform: MorphUniform,
+	uniform_buf: wgpu::Buffer,
+	pipeline: wgpu::ComputePipeline,
+	/// Binds the base and target vertices, the uniform and the blended vertices.
+	bind_group: wgpu::BindGroup,
+}
+impl MorphedMesh {
+	const WORKGROUP_SIZE: u32 = 64;
+
+	/// Uploads the base shape of the mesh, the `indices` of its triangles and its
+	/// `targets`. The vertices are blended by the targets' weights at the first
+	/// [`Self::dispatch_blend`].
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If there are no targets or more than [`MAX_MORPH_TARGETS`], or their
+	/// vertices don't match the base's.
+	pub fn new(
+		device: &wgpu::Device,
+		base: &[Vertex],
+		indices: &[u32],
+		targets: &[MorphTarget],
+	) -> Result<Self> {
+		assert!(
+			(1..=MAX_MORPH_TARGETS).contains(&targets.len()),
+			"A morphed mesh needs from 1 to {MAX_MORPH_TARGETS} targets"
+		);
+		assert!(
+			targets.iter().all(|t| t.vertices.len() == base.len()),
+			"Morph targets must have as many vertices as the base"
+		);
+		assert!(!base.is_empty(), "A morphed mesh needs vertices");
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+
+		let base_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Morph Base Vertices"),
+			contents: bytemuck::cast_slice(base),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let target_vertices: Vec<Vertex> = (targets.iter())
+			.flat_map(|t| t.vertices.iter().copied())
+			.collect();
+		let targets_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Morph Target Vertices"),
+				contents: bytemuck::cast_slice(&target_vertices),
+				usage: wgpu::BufferUsages::STORAGE,
+			});
+		let mut weights = [0.; MAX_MORPH_TARGETS];
+		for (weight, target) in weights.iter_mut().zip(targets) {
+			*weight = target.weight;
+		}
+		let uniform = MorphUniform {
+			weights,
+			num_vertices: base.len() as u32,
+			num_targets: targets.len() as u32,
+			_pad: [0; 2],
+		};
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Morph Uniform"),
+				contents: bytemuck::bytes_of(&uniform),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		// Starts as the base shape, until the first blend.
+		let mut mesh = GpuMesh::new(device, base, indices);
+		mesh.vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Morphed Vertices"),
+			contents: bytemuck::cast_slice(base),
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+		});
+		// Bounds every shape the weights from 0 to 1 blend between.
+		let bounds = Aabb::from_vertices(&target_vertices);
+		mesh.aabb = Aabb::new(
+			mesh.aabb.min.inf(&bounds.min),
+			mesh.aabb.max.sup(&bounds.max),
+		);
+
+		let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Storage { read_only },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Morph Bind Group Layout"),
+				entries: &[
+					storage_entry(0, true),
+					storage_entry(1, true),
+					wgpu::BindGroupLayoutEntry {
+						binding: 2,
+						visibility: wgpu::ShaderStages::COMPUTE,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					storage_entry(3, false),
+				],
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("morph_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: base_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: targets_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: mesh.vtx_buf.as_entire_binding(),
+				},
+			],
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Morph Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("morph.wgsl"));
+		let pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("Morph Blend"),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point: "cs_main",
+			});
+
+		Ok(Self {
+			mesh: Arc::new(mesh),
+			num_vertices: base.len() as u32,
+			uniform,
+			uniform_buf,
+			pipeline,
+			bind_group,
+		})
+	}
+
+	/// The mesh with the blended vertices, to draw in a [`RenderObject`]. Its
+	/// bounding box holds the base and every target.
+	///
+	/// [`RenderObject`]: crate::render_object::RenderObject
+	pub fn mesh(&self) -> Arc<GpuMesh> {
+		self.mesh.clone()
+	}
+
+	pub fn num_targets(&self) -> usize {
+		self.uniform.num_targets as usize
+	}
+
+	/// The weight of each target.
+	pub fn weights(&self) -> &[f32] {
+		&self.uniform.weights[..self.num_targets()]
+	}
+
+	/// Sets the weight of each target, in order, for the next blends. Targets
+	/// past the end of `weights` get 0, and weights past the last target are
+	/// ignored. Returns the number of bytes uploaded.
+	pub fn update_weights(&mut self, queue: &wgpu::Queue, weights: &[f32]) -> u64 {
+		let num_targets = self.num_targets();
+		self.uniform.weights = [0.; MAX_MORPH_TARGETS];
+		for (weight, &new) in
+			self.uniform.weights[..num_targets].iter_mut().zip(weights)
+		{
+			*weight = new;
+		}
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&self.uniform));
+		std::mem::size_of::<MorphUniform>() as u64
+	}
+
+	/// Records blending the vertices into the vertex buffer of [`Self::mesh`].
+	pub fn dispatch_blend(&self, encoder: &mut wgpu::CommandEncoder) {
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Morph Blend Pass"),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.bind_group, &[]);
+		let workgroups = (self.num_vertices - 1) / Self::WORKGROUP_SIZE + 1;
+		pass.dispatch_workgroups(workgroups, 1, 1);
+	}
+}
@@ -0,0 +1,66 @@
+// Blends the vertices of a mesh between its base shape and its morph targets.
+
+// `Vertex` in `vertex.rs`, as floats: the position, uv, normal, tangent and
+// bitangent. Arrays of floats keep it tightly packed.
+const VERTEX_FLOATS: u32 = 14u;
+const NORMAL_OFFSET: u32 = 5u;
+
+struct MorphUniform {
+	// The weight of each target, 4 to a vector to align them in the uniform.
+	weights: array<vec4<f32>, 2>,
+	num_vertices: u32,
+	num_targets: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> base: array<f32>;
+// The vertices of every target, one target after the other.
+@group(0) @binding(1)
+var<storage, read> targets: array<f32>;
+@group(0) @binding(2)
+var<uniform> morph: MorphUniform;
+// The vertex buffer drawn.
+@group(0) @binding(3)
+var<storage, read_write> blended: array<f32>;
+
+fn load_base(i: u32) -> vec3<f32> {
+	return vec3<f32>(base[i], base[i + 1u], base[i + 2u]);
+}
+
+fn load_target(i: u32) -> vec3<f32> {
+	return vec3<f32>(targets[i], targets[i + 1u], targets[i + 2u]);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let v = id.x;
+	if v >= morph.num_vertices {
+		return;
+	}
+	let first = v * VERTEX_FLOATS;
+	// Everything but the position and normal is the base's.
+	for (var i = 0u; i < VERTEX_FLOATS; i += 1u) {
+		blended[first + i] = base[first + i];
+	}
+
+	let base_pos = load_base(first);
+	let base_normal = load_base(first + NORMAL_OFFSET);
+	var pos = base_pos;
+	var normal = base_normal;
+	for (var t = 0u; t < morph.num_targets; t += 1u) {
+		let weight = morph.weights[t / 4u][t % 4u];
+		let target_first = (t * morph.num_vertices + v) * VERTEX_FLOATS;
+		pos += weight * (load_target(target_first) - base_pos);
+		normal += weight
+			* (load_target(target_first + NORMAL_OFFSET) - base_normal);
+	}
+	if dot(normal, normal) > 0.0 {
+		normal = normalize(normal);
+	}
+	blended[first] = pos.x;
+	blended[first + 1u] = pos.y;
+	blended[first + 2u] = pos.z;
+	blended[first + NORMAL_OFFSET] = normal.x;
+	blended[first + NORMAL_OFFSET + 1u] = normal.y;
+	blended[first + NORMAL_OFFSET + 2u] = normal.z;
+}
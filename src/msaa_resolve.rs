@@ -0,0 +1,355 @@
+//! Resolving MSAA with a compute shader, so that the samples can be weighted by
+//! a wider filter than the hardware's box.
+
+use bytemuck::{Pod, Zeroable};
+use log::warn;
+
+/// How the samples of an MSAA texture are weighted into each pixel, by their
+/// distance from its center.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResolveFilter {
+	/// The pixel's own samples, weighted equally, like the hardware resolve.
+	#[default]
+	Box,
+	/// The samples within a pixel of the center, weighted linearly down to 0.
+	Tent,
+	/// The samples within 2 pixels of the center, weighted by the Catmull-Rom
+	/// spline. Sharper than the tent, at the cost of slight ringing.
+	CatmullRom,
+}
+impl ResolveFilter {
+	fn index(self) -> u32 {
+		match self {
+			Self::Box => 0,
+			Self::Tent => 1,
+			Self::CatmullRom => 2,
+		}
+	}
+}
+
+/// The layout of the shader's `ResolveUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct ResolveUniform {
+	filter: u32,
+	_pad: [u32; 3],
+}
+
+/// Resolves a multisampled texture into a single sampled one with a
+/// [`ResolveFilter`], see `msaa_resolve.wgsl`.
+///
+/// Frame formats usually can't be written by compute shaders, so the samples
+/// are resolved into a texture of [`Self::RESOLVED_FORMAT`] that is then copied
+/// into the output.
+pub(crate) struct MsaaResolvePass {
+	filter: ResolveFilter,
+	uniform_buf: wgpu::Buffer,
+	/// The frame resolved by the compute shader, copied into the output.
+	resolved: wgpu::TextureView,
+	resolve_layout: wgpu::BindGroupLayout,
+	resolve_pipeline: wgpu::ComputePipeline,
+	copy_layout: wgpu::BindGroupLayout,
+	copy_pipeline_layout: wgpu::PipelineLayout,
+	copy_shader: wgpu::ShaderModule,
+	copy_pipeline: wgpu::RenderPipeline,
+	sampler: wgpu::Sampler,
+}
+impl MsaaResolvePass {
+	pub const RESOLVED_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	const WORKGROUP_SIZE: u32 = 8;
+
+	/// Creates a pass resolving the `size` targets of `sample_count` samples into
+	/// `format` ones, or `None` if the hardware resolves them, either because
+	/// there is no MSAA or because the device lacks compute shaders.
+	pub fn for_target(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		format: wgpu::TextureFormat,
+		(width, height): (u32, u32),
+		sample_count: u32,
+		filter: ResolveFilter,
+	) -> Option<Self> {
+		if sample_count == 1 {
+			return None;
+		}
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			warn!("Resolving MSAA in hardware, as compute shaders are unsupported");
+			return None;
+		}
+		Some(Self::new(device, queue, format, width, height, filter))
+	}
+
+	/// Creates a pass resolving `width`×`height` textures into `format` ones.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+		filter: ResolveFilter,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("MSAA Resolve Uniform"),
+			size: std::mem::size_of::<ResolveUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let resolve_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("MSAA Resolve Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::COMPUTE,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::COMPUTE,
+						ty: wgpu::BindingType::Texture {
+							multisampled: true,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: false,
+							},
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 2,
+						visibility: wgpu::ShaderStages::COMPUTE,
+						ty: wgpu::BindingType::StorageTexture {
+							access: wgpu::StorageTextureAccess::WriteOnly,
+							format: Self::RESOLVED_FORMAT,
+							view_dimension: wgpu::TextureViewDimension::D2,
+						},
+						count: None,
+					},
+				],
+			});
+		let resolve_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("MSAA Resolve Pipeline Layout"),
+				bind_group_layouts: &[&resolve_layout],
+				push_constant_ranges: &[],
+			});
+		let resolve_shader =
+			device.create_shader_module(wgpu::include_wgsl!("msaa_resolve.wgsl"));
+		let resolve_pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("MSAA Resolve Pipeline"),
+				layout: Some(&resolve_pipeline_layout),
+				module: &resolve_shader,
+				entry_point: "cs_main",
+			});
+
+		let copy_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("MSAA Copy Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: true,
+							},
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Sampler(
+							wgpu::SamplerBindingType::Filtering,
+						),
+						count: None,
+					},
+				],
+			});
+		let copy_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("MSAA Copy Pipeline Layout"),
+				bind_group_layouts: &[&copy_layout],
+				push_constant_ranges: &[],
+			});
+		let copy_shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+		let copy_pipeline =
+			create_copy_pipeline(device, &copy_pipeline_layout, &copy_shader, format);
+		// The copy is pixel for pixel.
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("MSAA Copy Sampler"),
+			..Default::default()
+		});
+
+		let mut result = Self {
+			filter,
+			uniform_buf,
+			resolved: create_resolved(device, width, height),
+			resolve_layout,
+			resolve_pipeline,
+			copy_layout,
+			copy_pipeline_layout,
+			copy_shader,
+			copy_pipeline,
+			sampler,
+		};
+		result.set_filter(queue, filter);
+		result
+	}
+
+	/// Recreates the resolved texture for `width`×`height` textures.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		self.resolved = create_resolved(device, width, height);
+	}
+
+	/// Recreates the pipeline copying into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.copy_pipeline = create_copy_pipeline(
+			device,
+			&self.copy_pipeline_layout,
+			&self.copy_shader,
+			format,
+		);
+	}
+
+	pub fn filter(&self) -> ResolveFilter {
+		self.filter
+	}
+
+	/// Returns the number of bytes uploaded.
+	pub fn set_filter(&mut self, queue: &wgpu::Queue, filter: ResolveFilter) -> u64 {
+		self.filter = filter;
+		let uniform = ResolveUniform {
+			filter: filter.index(),
+			_pad: [0; 3],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<ResolveUniform>() as u64
+	}
+
+	/// Records resolving the multisampled `msaa_view` into all of `output_view`.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		msaa_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+		width: u32,
+		height: u32,
+	) {
+		let resolve = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("msaa_resolve_bind_group"),
+			layout: &self.resolve_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(msaa_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(&self.resolved),
+				},
+			],
+		});
+		let copy = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("msaa_copy_bind_group"),
+			layout: &self.copy_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&self.resolved),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+			],
+		});
+		{
+			let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+				label: Some("MSAA Resolve Pass"),
+			});
+			pass.set_pipeline(&self.resolve_pipeline);
+			pass.set_bind_group(0, &resolve, &[]);
+			let workgroups = |size: u32| (size - 1) / Self::WORKGROUP_SIZE + 1;
+			pass.dispatch_workgroups(workgroups(width), workgroups(height), 1);
+		}
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("MSAA Copy Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.copy_pipeline);
+		pass.set_bind_group(0, &copy, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_resolved(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+) -> wgpu::TextureView {
+	device
+		.create_texture(&wgpu::TextureDescriptor {
+			label: Some("MSAA Resolved"),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: MsaaResolvePass::RESOLVED_FORMAT,
+			usage: wgpu::TextureUsages::STORAGE_BINDING
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		})
+		.create_view(&Default::default())
+}
+
+fn create_copy_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("MSAA Copy Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
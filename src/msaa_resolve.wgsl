@@ -0,0 +1,116 @@
+// Resolves a multisampled texture by weighting the samples around each pixel
+// with a filter, by their distance from its center.
+
+struct ResolveUniform {
+	// 0 for box, 1 for tent, 2 for Catmull-Rom.
+	filter: u32,
+};
+@group(0) @binding(0)
+var<uniform> resolve: ResolveUniform;
+@group(0) @binding(1)
+var msaa_t: texture_multisampled_2d<f32>;
+@group(0) @binding(2)
+var resolved_t: texture_storage_2d<rgba16float, write>;
+
+const BOX: u32 = 0u;
+const TENT: u32 = 1u;
+
+// Where the sample at `index` is within its pixel, relative to the center, in
+// the standard patterns of Direct3D, Metal and Vulkan. Other sample counts are
+// taken to be at the center.
+fn sample_position(index: u32, count: u32) -> vec2<f32> {
+	if count == 2u {
+		var positions = array<vec2<f32>, 2>(
+			vec2<f32>(4.0, 4.0),
+			vec2<f32>(-4.0, -4.0),
+		);
+		return positions[index] / 16.0;
+	}
+	if count == 4u {
+		var positions = array<vec2<f32>, 4>(
+			vec2<f32>(-2.0, -6.0),
+			vec2<f32>(6.0, -2.0),
+			vec2<f32>(-6.0, 2.0),
+			vec2<f32>(2.0, 6.0),
+		);
+		return positions[index] / 16.0;
+	}
+	if count == 8u {
+		var positions = array<vec2<f32>, 8>(
+			vec2<f32>(1.0, -3.0),
+			vec2<f32>(-1.0, 3.0),
+			vec2<f32>(5.0, 1.0),
+			vec2<f32>(-3.0, -5.0),
+			vec2<f32>(-5.0, 5.0),
+			vec2<f32>(-7.0, -1.0),
+			vec2<f32>(3.0, 7.0),
+			vec2<f32>(7.0, -7.0),
+		);
+		return positions[index] / 16.0;
+	}
+	return vec2<f32>(0.0);
+}
+
+fn tent(x: f32) -> f32 {
+	return max(1.0 - abs(x), 0.0);
+}
+
+fn catmull_rom(x: f32) -> f32 {
+	let d = abs(x);
+	if d < 1.0 {
+		return (1.5 * d - 2.5) * d * d + 1.0;
+	}
+	if d < 2.0 {
+		return ((-0.5 * d + 2.5) * d - 4.0) * d + 2.0;
+	}
+	return 0.0;
+}
+
+// The weight of a sample `offset` pixels from the center being resolved.
+fn filter_weight(offset: vec2<f32>) -> f32 {
+	if resolve.filter == BOX {
+		return select(0.0, 1.0, all(abs(offset) <= vec2<f32>(0.5)));
+	}
+	if resolve.filter == TENT {
+		return tent(offset.x) * tent(offset.y);
+	}
+	return catmull_rom(offset.x) * catmull_rom(offset.y);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = vec2<i32>(textureDimensions(msaa_t));
+	let pixel = vec2<i32>(id.xy);
+	if any(pixel >= size) {
+		return;
+	}
+	let count = textureNumSamples(msaa_t);
+	// How many pixels away the filter reaches.
+	var radius = 2;
+	if resolve.filter == BOX {
+		radius = 0;
+	} else if resolve.filter == TENT {
+		radius = 1;
+	}
+	var sum = vec4<f32>(0.0);
+	var total = 0.0;
+	for (var y = -radius; y <= radius; y += 1) {
+		for (var x = -radius; x <= radius; x += 1) {
+			let coords = pixel + vec2<i32>(x, y);
+			if any(coords < vec2<i32>(0)) || any(coords >= size) {
+				continue;
+			}
+			for (var i = 0u; i < count; i += 1u) {
+				let offset = vec2<f32>(f32(x), f32(y)) + sample_position(i, count);
+				let weight = filter_weight(offset);
+				if weight == 0.0 {
+					continue;
+				}
+				sum += textureLoad(msaa_t, coords, i32(i)) * weight;
+				total += weight;
+			}
+		}
+	}
+	// Catmull-Rom's negative lobes can undershoot.
+	textureStore(resolved_t, pixel, max(sum / max(total, 0.0001), vec4<f32>(0.0)));
+}
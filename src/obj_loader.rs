@@ -0,0 +1,208 @@
+//! A minimal Wavefront OBJ parser.
+//!
+//! Only positions, texture coordinates, normals and faces are read. Everything
+//! else (groups, materials, ...) is ignored.
+
+use color_eyre::{
+	eyre::{bail, eyre, WrapErr},
+	Result,
+};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+use crate::mesh::compute_tangents;
+use crate::vertex::{Normal, Pos, Uv, Vertex};
+
+/// Parses an OBJ file into a vertex buffer and an index buffer.
+///
+/// Vertices that share both a position and a texture coordinate are only emitted
+/// once. Polygons with more than three vertices are triangulated as a fan, so
+/// they must be convex. Faces without texture coordinates get a UV of `(0, 0)`.
+/// Vertices without a normal get the average normal of the faces around them,
+/// weighted by area. Tangents are computed from the UVs.
+pub fn load_obj(bytes: &[u8]) -> Result<(Vec<Vertex>, Vec<u32>)> {
+	let text = std::str::from_utf8(bytes).wrap_err("OBJ file is not UTF-8")?;
+
+	let mut positions: Vec<Pos> = Vec::new();
+	let mut uvs: Vec<Uv> = Vec::new();
+	let mut normals: Vec<Normal> = Vec::new();
+	let mut vertices: Vec<Vertex> = Vec::new();
+	let mut indices: Vec<u32> = Vec::new();
+	// Which emitted vertices need their normal computed.
+	let mut missing_normal: Vec<bool> = Vec::new();
+	// Maps (position index, uv index, normal index) to the index of the emitted
+	// vertex.
+	let mut dedup: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+	for (line_idx, line) in text.lines().enumerate() {
+		let line_no = line_idx + 1;
+		let mut tokens = line.split_whitespace();
+		let Some(keyword) = tokens.next() else {
+			continue;
+		};
+		match keyword {
+			"v" => {
+				let [x, y, z] = parse_floats(tokens, line_no)?;
+				positions.push(Pos::new(x, y, z));
+			}
+			"vt" => {
+				let [u, v] = parse_floats(tokens, line_no)?;
+				// OBJ puts the origin at the bottom left, wgpu at the top left.
+				uvs.push(Uv { u, v: 1. - v });
+			}
+			"vn" => {
+				let [x, y, z] = parse_floats(tokens, line_no)?;
+				normals.push(Normal::new(x, y, z));
+			}
+			"f" => {
+				let mut face: Vec<u32> = Vec::new();
+				for token in tokens {
+					let mut parts = token.split('/');
+					let pos_idx = parts
+						.next()
+						.ok_or_else(|| eyre!("line {line_no}: Empty face vertex"))
+						.and_then(|s| resolve_index(s, positions.len(), line_no))?;
+					let uv_idx = match parts.next() {
+						None | Some("") => None,
+						Some(s) => Some(resolve_index(s, uvs.len(), line_no)?),
+					};
+					let normal_idx = match parts.next() {
+						None | Some("") => None,
+						Some(s) => Some(resolve_index(s, normals.len(), line_no)?),
+					};
+					let key = (pos_idx, uv_idx, normal_idx);
+					let index = *dedup.entry(key).or_insert_with(|| {
+						let uv = uv_idx.map_or(Uv { u: 0., v: 0. }, |i| uvs[i]);
+						let normal =
+							normal_idx.map_or(Normal::new(0., 0., 0.), |i| normals[i]);
+						vertices.push(Vertex::new(positions[pos_idx], uv, normal));
+						missing_normal.push(normal_idx.is_none());
+						vertices.len() as u32 - 1
+					});
+					face.push(index);
+				}
+				if face.len() < 3 {
+					bail!("line {line_no}: Face has fewer than 3 vertices");
+				}
+				for i in 1..face.len() - 1 {
+					indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+				}
+			}
+			_ => (),
+		}
+	}
+	compute_missing_normals(&mut vertices, &indices, &missing_normal);
+	compute_tangents(&mut vertices, &indices);
+	Ok((vertices, indices))
+}
+
+/// Sets the normal of each vertex flagged in `missing` to the area weighted
+/// average of the normals of the triangles that use it.
+pub(crate) fn compute_missing_normals(
+	vertices: &mut [Vertex],
+	indices: &[u32],
+	missing: &[bool],
+) {
+	if !missing.contains(&true) {
+		return;
+	}
+	let pos = |v: &Vertex| Vector3::new(v.pos.x, v.pos.y, v.pos.z);
+	let mut sums = vec![Vector3::<f32>::zeros(); vertices.len()];
+	for tri in indices.chunks_exact(3) {
+		let [a, b, c] = [0, 1, 2].map(|i| pos(&vertices[tri[i] as usize]));
+		// The cross product's length is twice the triangle's area.
+		let face_normal = (b - a).cross(&(c - a));
+		for &i in tri {
+			sums[i as usize] += face_normal;
+		}
+	}
+	for ((vertex, sum), _) in vertices
+		.iter_mut()
+		.zip(sums)
+		.zip(missing)
+		.filter(|(_, &missing)| missing)
+	{
+		let n = sum.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::y);
+		vertex.normal = Normal::new(n.x, n.y, n.z);
+	}
+}
+
+/// Parses the first `N` tokens as floats, ignoring any others (such as the
+/// optional `w` of a position).
+fn parse_floats<'a, const N: usize>(
+	mut tokens: impl Iterator<Item = &'a str>,
+	line_no: usize,
+) -> Result<[f32; N]> {
+	let mut result = [0.; N];
+	for value in result.iter_mut() {
+		let token = tokens
+			.next()
+			.ok_or_else(|| eyre!("line {line_no}: Expected {N} numbers"))?;
+		*value = token
+			.parse()
+			.wrap_err_with(|| format!("line {line_no}: Invalid number {token:?}"))?;
+	}
+	Ok(result)
+}
+
+/// Converts a 1-based, possibly negative (relative to the end) OBJ index into a
+/// 0-based index into a list of `len` elements.
+fn resolve_index(token: &str, len: usize, line_no: usize) -> Result<usize> {
+	let index: isize = token
+		.parse()
+		.wrap_err_with(|| format!("line {line_no}: Invalid index {token:?}"))?;
+	let resolved = if index < 0 {
+		len as isize + index
+	} else {
+		index - 1
+	};
+	if resolved < 0 || resolved as usize >= len {
+		bail!("line {line_no}: Index {index} is out of bounds");
+	}
+	Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mesh::generate_cube;
+
+	fn fields(v: &Vertex) -> [f32; 8] {
+		let (p, uv, n) = (v.pos, v.uv, v.normal);
+		[p.x, p.y, p.z, uv.u, uv.v, n.x, n.y, n.z]
+	}
+
+	#[test]
+	fn round_trip() {
+		let (cube, cube_indices) = generate_cube(0.5);
+		let mut obj = String::new();
+		for v in &cube {
+			let (p, uv, n) = (v.pos, v.uv, v.normal);
+			obj += &format!("v {} {} {}\n", p.x, p.y, p.z);
+			obj += &format!("vt {} {}\n", uv.u, 1. - uv.v);
+			obj += &format!("vn {} {} {}\n", n.x, n.y, n.z);
+		}
+		for tri in cube_indices.chunks_exact(3) {
+			let [a, b, c] = [0, 1, 2].map(|i| tri[i] + 1);
+			obj += &format!("f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}\n");
+		}
+
+		let (vertices, indices) = load_obj(obj.as_bytes()).unwrap();
+		let cube_indices: Vec<u32> = cube_indices.into_iter().map(u32::from).collect();
+		assert_eq!(indices, cube_indices);
+		assert_eq!(vertices.len(), cube.len());
+		for (loaded, original) in vertices.iter().zip(&cube) {
+			assert_eq!(fields(loaded), fields(original));
+		}
+	}
+
+	#[test]
+	fn polygons_are_fans_with_computed_normals() {
+		let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 -1\n";
+		let (vertices, indices) = load_obj(obj.as_bytes()).unwrap();
+		assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+		for vertex in &vertices {
+			assert_eq!(fields(vertex)[5..], [0., 0., 1.]);
+		}
+	}
+}
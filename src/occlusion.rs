@@ -0,0 +1,346 @@
+//! Occlusion culling: objects hidden behind others in the previous frame are
+//! skipped.
+//!
+//! wgpu has no occlusion queries yet, so each object's bounding box is drawn
+//! against the frame's depth buffer inside a pipeline statistics query, which
+//! counts the fragments that pass the depth test. Boxes with no fragments are
+//! hidden.
+
+use log::{debug, warn};
+use nalgebra::Matrix4;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+use crate::types::mat4_to_wgsl;
+use crate::viewport::{RenderPassExt, Viewport};
+
+/// The corners of a cube from -1 to 1.
+#[rustfmt::skip]
+const CUBE_CORNERS: [[f32; 3]; 8] = [
+	[-1., -1., -1.], [1., -1., -1.], [1., 1., -1.], [-1., 1., -1.],
+	[-1., -1., 1.], [1., -1., 1.], [1., 1., 1.], [-1., 1., 1.],
+];
+/// The triangles of the cube's faces. Both sides of the faces are drawn, so
+/// their winding doesn't matter.
+#[rustfmt::skip]
+const CUBE_INDICES: [u16; 36] = [
+	0, 1, 2, 0, 2, 3, // -z
+	4, 6, 5, 4, 7, 6, // +z
+	0, 4, 5, 0, 5, 1, // -y
+	3, 2, 6, 3, 6, 7, // +y
+	0, 3, 7, 0, 7, 4, // -x
+	1, 5, 6, 1, 6, 2, // +x
+];
+
+/// A buffer the query results of a frame are copied into, to be mapped once the
+/// GPU is done with them.
+struct Readback {
+	buf: wgpu::Buffer,
+	/// The number of queries copied into `buf`. 0 when `buf` is free.
+	count: u32,
+	/// Set once `buf` is mapped. `None` until mapping is requested.
+	mapped: Option<Arc<AtomicBool>>,
+}
+
+/// Tests which objects were visible, by drawing their bounding boxes in a pass of
+/// their own after the scene.
+///
+/// Requires [`wgpu::Features::PIPELINE_STATISTICS_QUERY`]. Results are read back
+/// like [`GpuProfiler`]'s, so they are a frame or two late.
+///
+/// [`GpuProfiler`]: crate::profiler::GpuProfiler
+pub(crate) struct OcclusionCuller {
+	pipeline: wgpu::RenderPipeline,
+	cube_vtx_buf: wgpu::Buffer,
+	cube_idx_buf: wgpu::Buffer,
+	/// The transforms placing the cube over each object's bounding box.
+	proxy_buf: wgpu::Buffer,
+	/// One query per object, recreated with the buffers below when there are
+	/// more objects than `capacity`.
+	query_set: wgpu::QuerySet,
+	capacity: u32,
+	resolve_buf: wgpu::Buffer,
+	readbacks: Vec<Readback>,
+	/// Index into `readbacks` of the buffer the next frame is copied into.
+	next_readback: usize,
+	/// Whether each object was visible in the last read back frame.
+	visible: Vec<bool>,
+}
+impl OcclusionCuller {
+	/// How many frames can be in flight before we start dropping their results.
+	const READBACK_FRAMES: usize = 2;
+
+	/// Returns `None` if `device` doesn't have pipeline statistics queries
+	/// enabled. The depth buffer tested against has `depth_format` and
+	/// `sample_count` samples.
+	pub fn new(
+		device: &wgpu::Device,
+		camera_layout: &wgpu::BindGroupLayout,
+		depth_format: wgpu::TextureFormat,
+		sample_count: u32,
+		reverse_z: bool,
+	) -> Option<Self> {
+		if !device
+			.features()
+			.contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+		{
+			return None;
+		}
+		let shader = device.create_shader_module(wgpu::include_wgsl!("occlusion.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Occlusion Pipeline Layout"),
+			bind_group_layouts: &[camera_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Occlusion Pipeline"),
+			layout: Some(&layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[
+					wgpu::VertexBufferLayout {
+						array_stride: std::mem::size_of::<[f32; 3]>() as u64,
+						step_mode: wgpu::VertexStepMode::Vertex,
+						attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+					},
+					wgpu::VertexBufferLayout {
+						array_stride: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+						step_mode: wgpu::VertexStepMode::Instance,
+						attributes: &wgpu::vertex_attr_array![
+							1 => Float32x4,
+							2 => Float32x4,
+							3 => Float32x4,
+							4 => Float32x4,
+						],
+					},
+				],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[],
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: depth_format,
+				// The boxes only test against the scene.
+				depth_write_enabled: false,
+				depth_compare: if reverse_z {
+					wgpu::CompareFunction::GreaterEqual
+				} else {
+					wgpu::CompareFunction::LessEqual
+				},
+				stencil: wgpu::StencilState::default(),
+				bias: wgpu::DepthBiasState::default(),
+			}),
+			multisample: wgpu::MultisampleState {
+				count: sample_count,
+				..Default::default()
+			},
+			multiview: None,
+		});
+		let cube_vtx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Occlusion Cube Vertices"),
+				contents: bytemuck::cast_slice(&CUBE_CORNERS),
+				usage: wgpu::BufferUsages::VERTEX,
+			});
+		let cube_idx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Occlusion Cube Indices"),
+				contents: bytemuck::cast_slice(&CUBE_INDICES),
+				usage: wgpu::BufferUsages::INDEX,
+			});
+		let capacity = 1;
+		let (proxy_buf, query_set, resolve_buf, readbacks) =
+			Self::create_buffers(device, capacity);
+		Some(Self {
+			pipeline,
+			cube_vtx_buf,
+			cube_idx_buf,
+			proxy_buf,
+			query_set,
+			capacity,
+			resolve_buf,
+			readbacks,
+			next_readback: 0,
+			visible: Vec::new(),
+		})
+	}
+
+	/// Creates the proxy buffer, query set, resolve buffer and readbacks for
+	/// `capacity` objects.
+	fn create_buffers(
+		device: &wgpu::Device,
+		capacity: u32,
+	) -> (wgpu::Buffer, wgpu::QuerySet, wgpu::Buffer, Vec<Readback>) {
+		let proxy_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Occlusion Proxies"),
+			size: capacity as u64 * std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+			label: Some("Occlusion Queries"),
+			ty: wgpu::QueryType::PipelineStatistics(
+				wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS,
+			),
+			count: capacity,
+		});
+		let size = (capacity * wgpu::QUERY_SIZE) as wgpu::BufferAddress;
+		let resolve_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Occlusion Resolve"),
+			size,
+			usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+			mapped_at_creation: false,
+		});
+		let readbacks = (0..Self::READBACK_FRAMES)
+			.map(|_| Readback {
+				buf: device.create_buffer(&wgpu::BufferDescriptor {
+					label: Some("Occlusion Readback"),
+					size,
+					usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+					mapped_at_creation: false,
+				}),
+				count: 0,
+				mapped: None,
+			})
+			.collect();
+		(proxy_buf, query_set, resolve_buf, readbacks)
+	}
+
+	/// Whether the `index`th object was visible in the last read back frame.
+	/// Objects that weren't tested yet are visible.
+	pub fn is_visible(&self, index: usize) -> bool {
+		self.visible.get(index).copied().unwrap_or(true)
+	}
+
+	/// Records testing the bounding boxes placed by `proxies` against
+	/// `depth_view`, as seen by the camera in `camera_bind_group`, and copies the
+	/// results into a free readback buffer. The results are indexed like
+	/// `proxies`.
+	#[allow(clippy::too_many_arguments)]
+	pub fn record(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		camera_bind_group: &wgpu::BindGroup,
+		viewport: Option<&Viewport>,
+		proxies: &[Matrix4<f32>],
+	) -> u64 {
+		let count = proxies.len() as u32;
+		if count == 0 {
+			self.visible.clear();
+			return 0;
+		}
+		if count > self.capacity {
+			self.capacity = count.next_power_of_two();
+			(
+				self.proxy_buf,
+				self.query_set,
+				self.resolve_buf,
+				self.readbacks,
+			) = Self::create_buffers(device, self.capacity);
+			self.next_readback = 0;
+		}
+		let readback = &mut self.readbacks[self.next_readback];
+		if readback.count != 0 {
+			debug!("GPU is behind, skipping occlusion queries this frame");
+			return 0;
+		}
+
+		let proxies: Vec<_> = proxies.iter().map(|&m| mat4_to_wgsl(m)).collect();
+		let bytes: &[u8] = bytemuck::cast_slice(&proxies);
+		queue.write_buffer(&self.proxy_buf, 0, bytes);
+		{
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Occlusion Pass"),
+				color_attachments: &[],
+				depth_stencil_attachment: Some(
+					wgpu::RenderPassDepthStencilAttachment {
+						view: depth_view,
+						depth_ops: Some(wgpu::Operations {
+							load: wgpu::LoadOp::Load,
+							store: true,
+						}),
+						stencil_ops: None,
+					},
+				),
+			});
+			if let Some(viewport) = viewport {
+				pass.apply_viewport(viewport);
+			}
+			pass.set_pipeline(&self.pipeline);
+			pass.set_bind_group(0, camera_bind_group, &[]);
+			pass.set_vertex_buffer(0, self.cube_vtx_buf.slice(..));
+			pass.set_vertex_buffer(1, self.proxy_buf.slice(..));
+			pass.set_index_buffer(
+				self.cube_idx_buf.slice(..),
+				wgpu::IndexFormat::Uint16,
+			);
+			for i in 0..count {
+				pass.begin_pipeline_statistics_query(&self.query_set, i);
+				pass.draw_indexed(0..CUBE_INDICES.len() as u32, 0, i..i + 1);
+				pass.end_pipeline_statistics_query();
+			}
+		}
+		encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buf, 0);
+		encoder.copy_buffer_to_buffer(
+			&self.resolve_buf,
+			0,
+			&readback.buf,
+			0,
+			(count * wgpu::QUERY_SIZE) as wgpu::BufferAddress,
+		);
+		readback.count = count;
+		bytes.len() as u64
+	}
+
+	/// Starts reading back the frame that was just submitted, and collects the
+	/// results of earlier frames that have finished. Doesn't block.
+	pub fn read_back(&mut self, device: &wgpu::Device) {
+		let submitted = &mut self.readbacks[self.next_readback];
+		if submitted.count != 0 && submitted.mapped.is_none() {
+			let mapped = Arc::new(AtomicBool::new(false));
+			submitted.buf.slice(..).map_async(wgpu::MapMode::Read, {
+				let mapped = mapped.clone();
+				move |result| match result {
+					Ok(()) => mapped.store(true, Ordering::Release),
+					Err(err) => warn!("Failed to read back occlusion queries: {err}"),
+				}
+			});
+			submitted.mapped = Some(mapped);
+			self.next_readback = (self.next_readback + 1) % self.readbacks.len();
+		}
+		device.poll(wgpu::Maintain::Poll);
+
+		// Oldest first, so the newest results are the ones kept.
+		for i in 0..self.readbacks.len() {
+			let i = (self.next_readback + i) % self.readbacks.len();
+			let readback = &mut self.readbacks[i];
+			let Some(mapped) = &readback.mapped else {
+				continue;
+			};
+			if !mapped.load(Ordering::Acquire) {
+				continue;
+			}
+			{
+				let data = readback.buf.slice(..).get_mapped_range();
+				let fragments: &[u64] = bytemuck::cast_slice(&data);
+				self.visible.clear();
+				self.visible.extend(
+					fragments[..readback.count as usize]
+						.iter()
+						.map(|&fragments| fragments > 0),
+				);
+			}
+			readback.buf.unmap();
+			readback.count = 0;
+			readback.mapped = None;
+		}
+	}
+}
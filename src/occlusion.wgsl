@@ -0,0 +1,33 @@
+// Bounding box proxies for occlusion culling, see `occlusion.rs`. Nothing is
+// written, the fragments that pass the depth test are only counted.
+
+struct CameraUniform {
+	view_proj: mat4x4<f32>,
+	position: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct ProxyInput {
+	// A corner of the cube from -1 to 1.
+	@location(0) pos: vec3<f32>,
+	// The columns of the matrix placing the cube over the object's bounding box.
+	@location(1) transform_0: vec4<f32>,
+	@location(2) transform_1: vec4<f32>,
+	@location(3) transform_2: vec4<f32>,
+	@location(4) transform_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: ProxyInput) -> @builtin(position) vec4<f32> {
+	let transform = mat4x4<f32>(
+		in.transform_0,
+		in.transform_1,
+		in.transform_2,
+		in.transform_3,
+	);
+	return camera.view_proj * transform * vec4<f32>(in.pos, 1.0);
+}
+
+@fragment
+fn fs_main() {}
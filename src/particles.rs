@@ -0,0 +1,694 @@
+//! Particles simulated and counted on the GPU, see `particles.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, Result};
+use nalgebra::{Matrix4, Point3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::types::mat4_to_wgsl;
+
+/// The layout of `particles.wgsl`'s `Particle`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct Particle {
+	pub position: [f32; 3],
+	pub velocity: [f32; 3],
+	/// Seconds left to live. Negative before the particle is first emitted.
+	pub lifetime: f32,
+	pub _pad: f32,
+}
+
+/// The layout of `particles.wgsl`'s `SoftUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SoftUniform {
+	/// The inverse of the camera's projection, to find view space depths.
+	inv_proj: [[f32; 4]; 4],
+	fade_distance: f32,
+	_pad: [f32; 3],
+}
+
+/// The layout of `particles.wgsl`'s `EmitterUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct EmitterUniform {
+	position: [f32; 3],
+	time: f32,
+}
+
+/// Particles shooting up from an emitter and falling back down.
+///
+/// Every frame a compute shader moves them, emits dead ones again and packs the
+/// live ones into an instance buffer, whose count it writes into the arguments
+/// of an indirect draw. Nothing is read back to the CPU.
+pub struct ParticleSystem {
+	capacity: u32,
+	pub emitter: Point3<f32>,
+	/// How far in front of the scene, along the view direction, particles start
+	/// to fade out, so that they don't end in hard edges where they cross it.
+	/// Particles aren't faded at 0.
+	pub fade_distance: f32,
+	/// Seconds simulated so far, which seeds the randomness of emitted particles.
+	time: f32,
+	/// The live particles, drawn as instances.
+	live: wgpu::Buffer,
+	/// A [`wgpu::util::DrawIndirect`], counting `live`.
+	draw_args: wgpu::Buffer,
+	/// An `EmitterUniform`.
+	emitter_buf: wgpu::Buffer,
+	pipeline: wgpu::ComputePipeline,
+	/// Also binds the buffer of every particle, live or not.
+	bind_group: wgpu::BindGroup,
+}
+impl ParticleSystem {
+	/// How long particles live, in seconds. Matches `particles.wgsl`.
+	pub const LIFETIME: f32 = 2.0;
+	const WORKGROUP_SIZE: u32 = 64;
+	/// Vertices of the quad drawn per particle.
+	const QUAD_VERTICES: u32 = 4;
+
+	/// Allocates `capacity` particles at `emitter`. They are emitted one after the
+	/// other over [`Self::LIFETIME`], so that they don't move in a single burst.
+	///
+	/// Fails if the device doesn't support compute shaders or push constants.
+	///
+	/// # Panics
+	/// If `capacity` is 0.
+	pub fn new(
+		device: &wgpu::Device,
+		capacity: u32,
+		emitter: Point3<f32>,
+	) -> Result<Self> {
+		assert!(
+			capacity > 0,
+			"A particle system needs at least one particle"
+		);
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+		if !device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+			bail!("Push constants are not supported by this device");
+		}
+
+		let initial: Vec<Particle> = (0..capacity)
+			.map(|i| Particle {
+				position: emitter.into(),
+				velocity: [0.; 3],
+				lifetime: -(i as f32) / capacity as f32 * Self::LIFETIME,
+				_pad: 0.,
+			})
+			.collect();
+		let particles = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Particles"),
+			contents: bytemuck::cast_slice(&initial),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let live = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Live Particles"),
+			size: std::mem::size_of_val(initial.as_slice()) as u64,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+			mapped_at_creation: false,
+		});
+		let draw_args = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Particle Draw Args"),
+			contents: Self::empty_draw_args().as_bytes(),
+			usage: wgpu::BufferUsages::STORAGE
+				| wgpu::BufferUsages::INDIRECT
+				| wgpu::BufferUsages::COPY_DST,
+		});
+
+		let emitter_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Particle Emitter"),
+			size: std::mem::size_of::<EmitterUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Storage { read_only: false },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Particle Bind Group Layout"),
+				entries: &[
+					storage_entry(0),
+					storage_entry(1),
+					storage_entry(2),
+					wgpu::BindGroupLayoutEntry {
+						binding: 3,
+						visibility: wgpu::ShaderStages::COMPUTE,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+				],
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("particle_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: particles.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: live.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: draw_args.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: emitter_buf.as_entire_binding(),
+				},
+			],
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Particle Simulation Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[wgpu::PushConstantRange {
+				stages: wgpu::ShaderStages::COMPUTE,
+				// `dt`
+				range: 0..4,
+			}],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("particles.wgsl"));
+		let pipeline =
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("Particle Simulation"),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point: "cs_main",
+			});
+
+		Ok(Self {
+			capacity,
+			emitter,
+			fade_distance: 0.2,
+			time: 0.,
+			live,
+			draw_args,
+			emitter_buf,
+			pipeline,
+			bind_group,
+		})
+	}
+
+	pub fn capacity(&self) -> u32 {
+		self.capacity
+	}
+
+	fn empty_draw_args() -> wgpu::util::DrawIndirect {
+		wgpu::util::DrawIndirect {
+			vertex_count: Self::QUAD_VERTICES,
+			instance_count: 0,
+			base_vertex: 0,
+			base_instance: 0,
+		}
+	}
+
+	/// Records advancing the simulation by `dt` seconds. Returns the number of
+	/// bytes uploaded.
+	pub fn simulate(
+		&mut self,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		dt: f32,
+	) -> u64 {
+		// Written before the commands of the next submission run, so the count
+		// starts over every frame.
+		let args = Self::empty_draw_args();
+		queue.write_buffer(&self.draw_args, 0, args.as_bytes());
+		self.time += dt;
+		let emitter = EmitterUniform {
+			position: self.emitter.into(),
+			time: self.time,
+		};
+		queue.write_buffer(&self.emitter_buf, 0, bytemuck::bytes_of(&emitter));
+
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Particle Simulation Pass"),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.bind_group, &[]);
+		pass.set_push_constants(0, bytemuck::bytes_of(&dt));
+		let workgroups = (self.capacity - 1) / Self::WORKGROUP_SIZE + 1;
+		pass.dispatch_workgroups(workgroups, 1, 1);
+		(args.as_bytes().len() + std::mem::size_of_val(&emitter)) as u64
+	}
+
+	/// Records drawing the live particles with a [`ParticlePipeline`].
+	pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+		render_pass.set_vertex_buffer(0, self.live.slice(..));
+		render_pass.draw_indirect(&self.draw_args, 0);
+	}
+}
+
+/// Draws [`ParticleSystem`]s as camera facing quads, blended additively over the
+/// scene. They are depth tested, but don't write depth, and fade out close to
+/// the depth buffer's surfaces.
+///
+/// The depth buffer is also bound to be read, so they are drawn in a pass of
+/// their own, where it is read-only.
+pub(crate) struct ParticlePipeline {
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	/// Binds the depth buffer and `soft_buf`.
+	soft_layout: wgpu::BindGroupLayout,
+	/// A `SoftUniform`.
+	soft_buf: wgpu::Buffer,
+	/// Bound instead of multisampled depth buffers, which can't be read.
+	placeholder_depth: wgpu::TextureView,
+	/// Whether the depth buffer is reverse-Z, with nearer fragments at greater
+	/// depths.
+	reverse_z: bool,
+}
+impl ParticlePipeline {
+	/// Creates a pipeline for render passes with the given attachments, the depth
+	/// buffer being reverse-Z if `reverse_z` is set. `camera_layout` is the layout
+	/// of the main pipeline's camera bind group, bound at group 0.
+	pub fn new(
+		device: &wgpu::Device,
+		camera_layout: &wgpu::BindGroupLayout,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let shader = device.create_shader_module(wgpu::include_wgsl!("particles.wgsl"));
+		let soft_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Soft Particle Bind Group Layout"),
+				entries: &[
+					// Read with `textureLoad`, so it needs no sampler.
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Depth,
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+				],
+			});
+		let soft_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Soft Particle Uniform"),
+			size: std::mem::size_of::<SoftUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let placeholder_depth = device
+			.create_texture(&wgpu::TextureDescriptor {
+				label: Some("Placeholder Particle Depth"),
+				size: wgpu::Extent3d {
+					width: 1,
+					height: 1,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format: wgpu::TextureFormat::Depth32Float,
+				usage: wgpu::TextureUsages::TEXTURE_BINDING,
+				view_formats: &[],
+			})
+			.create_view(&wgpu::TextureViewDescriptor::default());
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Particle Pipeline Layout"),
+			bind_group_layouts: &[camera_layout, &soft_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = create_pipeline(
+			device,
+			&layout,
+			&shader,
+			format,
+			sample_count,
+			depth_format,
+			reverse_z,
+		);
+		Self {
+			shader,
+			layout,
+			pipeline,
+			soft_layout,
+			soft_buf,
+			placeholder_depth,
+			reverse_z,
+		}
+	}
+
+	/// Recreates the pipeline for render passes with different attachments.
+	pub fn set_format(
+		&mut self,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+	) {
+		self.pipeline = create_pipeline(
+			device,
+			&self.layout,
+			&self.shader,
+			format,
+			sample_count,
+			depth_format,
+			self.reverse_z,
+		);
+	}
+
+	/// Uploads how `system` fades out in front of the depth buffer of a camera
+	/// with the projection `proj`, such as [`CameraLike::proj_view_from`] an
+	/// identity view. Particles don't fade unless `soft` is set. Returns the
+	/// number of bytes written.
+	pub fn update(
+		&self,
+		queue: &wgpu::Queue,
+		system: &ParticleSystem,
+		soft: bool,
+		proj: &Matrix4<f32>,
+	) -> u64 {
+		let proj = if self.reverse_z {
+			reverse_z(proj)
+		} else {
+			*proj
+		};
+		let uniform = SoftUniform {
+			inv_proj: mat4_to_wgsl(proj.try_inverse().unwrap_or_default()),
+			fade_distance: if soft { system.fade_distance } else { 0. },
+			_pad: [0.; 3],
+		};
+		queue.write_buffer(&self.soft_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SoftUniform>() as u64
+	}
+
+	/// Creates the bind group reading `depth_view`, a single sampled depth
+	/// buffer, or a placeholder without it. The depth buffer must only be a
+	/// read-only attachment of the passes drawing with the bind group.
+	pub fn bind_depth(
+		&self,
+		device: &wgpu::Device,
+		depth_view: Option<&wgpu::TextureView>,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("soft_particle_bind_group"),
+			layout: &self.soft_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(
+						depth_view.unwrap_or(&self.placeholder_depth),
+					),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: self.soft_buf.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	/// Records drawing `system`, seen through `camera_bind_group`, with a bind
+	/// group from [`Self::bind_depth`].
+	pub fn draw<'a>(
+		&'a self,
+		render_pass: &mut wgpu::RenderPass<'a>,
+		camera_bind_group: &'a wgpu::BindGroup,
+		soft_bind_group: &'a wgpu::BindGroup,
+		system: &'a ParticleSystem,
+	) {
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, camera_bind_group, &[]);
+		render_pass.set_bind_group(1, soft_bind_group, &[]);
+		system.draw(render_pass);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+	sample_count: u32,
+	depth_format: wgpu::TextureFormat,
+	reverse_z: bool,
+) -> wgpu::RenderPipeline {
+	let additive = wgpu::BlendComponent {
+		src_factor: wgpu::BlendFactor::One,
+		dst_factor: wgpu::BlendFactor::One,
+		operation: wgpu::BlendOperation::Add,
+	};
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Particle Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[wgpu::VertexBufferLayout {
+				array_stride: std::mem::size_of::<Particle>() as u64,
+				step_mode: wgpu::VertexStepMode::Instance,
+				attributes: &[
+					wgpu::VertexAttribute {
+						format: wgpu::VertexFormat::Float32x3,
+						offset: 0,
+						shader_location: 0,
+					},
+					wgpu::VertexAttribute {
+						format: wgpu::VertexFormat::Float32,
+						offset: std::mem::size_of::<[f32; 6]>() as u64,
+						shader_location: 1,
+					},
+				],
+			}],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState {
+					color: additive,
+					alpha: additive,
+				}),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState {
+			topology: wgpu::PrimitiveTopology::TriangleStrip,
+			// The quads always face the camera.
+			cull_mode: None,
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: depth_format,
+			depth_write_enabled: false,
+			depth_compare: if reverse_z {
+				wgpu::CompareFunction::Greater
+			} else {
+				wgpu::CompareFunction::Less
+			},
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState {
+			count: sample_count,
+			..Default::default()
+		},
+		multiview: None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::camera::{Camera, CameraUniform};
+	use crate::gpu_context::{read_texture, test_context};
+	use nalgebra::IsometryMatrix3;
+
+	const FADE_DISTANCE: f32 = 0.5;
+
+	/// The color of a particle drawn `in_front` of a surface 5 units away, in a
+	/// frame of a single pixel, whose center is the middle of the particle where
+	/// it is opaque. `None` on machines without a GPU, compute shaders or push
+	/// constants.
+	fn particle_in_front_of_surface(in_front: f32) -> Option<[u8; 4]> {
+		let context = test_context()?;
+		let (device, queue) = (&context.device, &context.queue);
+		let mut system = ParticleSystem::new(device, 1, Point3::origin()).ok()?;
+		system.fade_distance = FADE_DISTANCE;
+		// Drawn without simulating it first, which would move it.
+		let particle = Particle {
+			position: [0., 0., -5. + in_front],
+			velocity: [0.; 3],
+			lifetime: ParticleSystem::LIFETIME,
+			_pad: 0.,
+		};
+		system.live = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			contents: bytemuck::bytes_of(&particle),
+			usage: wgpu::BufferUsages::VERTEX,
+		});
+		let args = wgpu::util::DrawIndirect {
+			instance_count: 1,
+			..ParticleSystem::empty_draw_args()
+		};
+		queue.write_buffer(&system.draw_args, 0, args.as_bytes());
+
+		let camera = Camera::new_orthographic(-1., 1., -1., 1., 0.1, 10.);
+		let proj_view = camera.proj_view();
+		let uniform = CameraUniform::new(proj_view, Point3::origin());
+		let camera_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			contents: bytemuck::bytes_of(&uniform),
+			usage: wgpu::BufferUsages::UNIFORM,
+		});
+		let camera_layout = CameraUniform::create_bind_group_layout(device);
+		let camera_bind_group =
+			CameraUniform::create_bind_group(device, &camera_layout, &camera_buf, "");
+
+		let format = wgpu::TextureFormat::Rgba8Unorm;
+		let depth_format = wgpu::TextureFormat::Depth32Float;
+		let texture = |format, usage| {
+			device.create_texture(&wgpu::TextureDescriptor {
+				label: None,
+				size: wgpu::Extent3d {
+					width: 1,
+					height: 1,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage,
+				view_formats: &[],
+			})
+		};
+		let target = texture(
+			format,
+			wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+		);
+		let depth = texture(
+			depth_format,
+			wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+		);
+		let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+		let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+		let pipeline = ParticlePipeline::new(
+			device,
+			&camera_layout,
+			format,
+			1,
+			depth_format,
+			false,
+		);
+		let proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		pipeline.update(queue, &system, true, &proj);
+		let soft_bind_group = pipeline.bind_depth(device, Some(&depth_view));
+
+		// The surface is only drawn into the depth buffer, by clearing it to its
+		// depth.
+		let surface = proj_view * Point3::new(0., 0., -5.).to_homogeneous();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+		encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: None,
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: &target_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &depth_view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(surface.z / surface.w),
+					store: true,
+				}),
+				stencil_ops: None,
+			}),
+		});
+		{
+			let mut render_pass =
+				encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+					label: None,
+					color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+						view: &target_view,
+						resolve_target: None,
+						ops: wgpu::Operations {
+							load: wgpu::LoadOp::Load,
+							store: true,
+						},
+					})],
+					depth_stencil_attachment: Some(
+						wgpu::RenderPassDepthStencilAttachment {
+							view: &depth_view,
+							depth_ops: None,
+							stencil_ops: None,
+						},
+					),
+				});
+			pipeline.draw(
+				&mut render_pass,
+				&camera_bind_group,
+				&soft_bind_group,
+				&system,
+			);
+		}
+		queue.submit([encoder.finish()]);
+		Some(read_texture(&context, &target).get_pixel(0, 0).0)
+	}
+
+	#[test]
+	fn particles_fade_out_at_the_surface() {
+		// Only just in front, so that it passes the depth test.
+		let Some([r, g, b, _]) = particle_in_front_of_surface(0.001) else {
+			return;
+		};
+		assert!(r <= 2 && g <= 2 && b <= 2, "{:?} is not faded", [r, g, b]);
+	}
+
+	#[test]
+	fn particles_are_opaque_at_the_fade_distance() {
+		let Some([r, g, b, _]) = particle_in_front_of_surface(FADE_DISTANCE) else {
+			return;
+		};
+		// `fs_main`'s color, at full alpha.
+		let expected = [255, 153, 51];
+		let near = [r, g, b]
+			.iter()
+			.zip(expected)
+			.all(|(&c, e)| c.abs_diff(e) <= 1);
+		assert!(near, "{:?} is not {expected:?}", [r, g, b]);
+	}
+}
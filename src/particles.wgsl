@@ -0,0 +1,169 @@
+// A particle system simulated by `cs_main` and drawn with `vs_main` and
+// `fs_main`, one camera facing quad per live particle.
+
+// Matches `Particle` in `particles.rs`. Arrays keep the vectors 4 byte aligned.
+struct Particle {
+	position: array<f32, 3>,
+	velocity: array<f32, 3>,
+	// Seconds left to live. Negative before the particle is first emitted.
+	lifetime: f32,
+	_pad: f32,
+};
+
+// Matches `wgpu::util::DrawIndirect`.
+struct DrawArgs {
+	vertex_count: u32,
+	instance_count: atomic<u32>,
+	first_vertex: u32,
+	first_instance: u32,
+};
+
+// Seconds since the last frame.
+var<push_constant> dt: f32;
+
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+// The live particles, packed together to be drawn as instances.
+@group(0) @binding(1)
+var<storage, read_write> live: array<Particle>;
+@group(0) @binding(2)
+var<storage, read_write> draw_args: DrawArgs;
+
+struct EmitterUniform {
+	position: vec3<f32>,
+	// Seconds simulated so far, so that emitted particles differ.
+	time: f32,
+};
+@group(0) @binding(3)
+var<uniform> emitter: EmitterUniform;
+
+// Matches `ParticleSystem::LIFETIME`.
+const LIFETIME: f32 = 2.0;
+const GRAVITY: vec3<f32> = vec3<f32>(0.0, -1.0, 0.0);
+
+// A pseudo random number from 0 to 1, advancing `state`.
+fn random(state: ptr<function, u32>) -> f32 {
+	// PCG hash
+	let s = *state * 747796405u + 2891336453u;
+	*state = s;
+	let word = ((s >> ((s >> 28u) + 4u)) ^ s) * 277803737u;
+	return f32((word >> 22u) ^ word) / 4294967295.0;
+}
+
+// A particle leaving the emitter in a random upwards direction.
+fn emit(i: u32) -> Particle {
+	var state = i ^ (bitcast<u32>(emitter.time) * 2654435769u);
+	var p: Particle;
+	p.position = array<f32, 3>(emitter.position.x, emitter.position.y, emitter.position.z);
+	p.velocity =
+		array<f32, 3>(random(&state) - 0.5, 1.0 + random(&state), random(&state) - 0.5);
+	p.lifetime = LIFETIME;
+	return p;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let i = id.x;
+	if i >= arrayLength(&particles) {
+		return;
+	}
+	var p = particles[i];
+	if p.lifetime < 0.0 {
+		// Waiting to be emitted for the first time.
+		p.lifetime += dt;
+		if p.lifetime >= 0.0 {
+			p = emit(i);
+		}
+	} else {
+		var velocity = vec3<f32>(p.velocity[0], p.velocity[1], p.velocity[2]);
+		velocity += GRAVITY * dt;
+		p.position[0] += velocity.x * dt;
+		p.position[1] += velocity.y * dt;
+		p.position[2] += velocity.z * dt;
+		p.velocity = array<f32, 3>(velocity.x, velocity.y, velocity.z);
+		p.lifetime -= dt;
+		if p.lifetime <= 0.0 {
+			// Dead particles wrap back to the emitter.
+			p = emit(i);
+		}
+	}
+	particles[i] = p;
+	if p.lifetime > 0.0 {
+		live[atomicAdd(&draw_args.instance_count, 1u)] = p;
+	}
+}
+
+struct CameraUniform {
+	view_proj: mat4x4<f32>,
+	position: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// The scene's depth buffer, to fade out particles close to its surfaces.
+@group(1) @binding(0)
+var depth_t: texture_depth_2d;
+struct SoftUniform {
+	// The inverse of the camera's projection, to find view space depths.
+	inv_proj: mat4x4<f32>,
+	// Particles aren't faded at 0.
+	fade_distance: f32,
+};
+@group(1) @binding(1)
+var<uniform> soft: SoftUniform;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	// From -1 to 1 across the quad.
+	@location(0) corner: vec2<f32>,
+	@location(1) fade: f32,
+};
+
+// Half the width of a quad in clip space, at a distance of 1.
+const SIZE: f32 = 0.02;
+
+// Draw as a triangle strip of 4 vertices per instance.
+@vertex
+fn vs_main(
+	@builtin(vertex_index) index: u32,
+	@location(0) position: vec3<f32>,
+	@location(1) lifetime: f32,
+) -> VertexOutput {
+	let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u)) * 2.0 - 1.0;
+	var out: VertexOutput;
+	out.clip_pos = camera.view_proj * vec4<f32>(position, 1.0);
+	// Offset in clip space, so the quad faces the camera and shrinks with
+	// distance.
+	out.clip_pos += vec4<f32>(corner * SIZE, 0.0, 0.0);
+	out.corner = corner;
+	out.fade = clamp(lifetime / LIFETIME, 0.0, 1.0);
+	return out;
+}
+
+// The distance in front of the camera of the point at `depth` in the depth
+// buffer, at the framebuffer position `pixel`.
+fn view_depth(pixel: vec2<f32>, depth: f32) -> f32 {
+	let uv = pixel / vec2<f32>(textureDimensions(depth_t));
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let view = soft.inv_proj * ndc;
+	return -view.z / view.w;
+}
+
+// From 0 where a particle at `depth` touches the scene to 1 at `fade_distance`
+// in front of it.
+fn soft_factor(pixel: vec2<f32>, depth: f32) -> f32 {
+	if soft.fade_distance <= 0.0 {
+		return 1.0;
+	}
+	let scene_depth = textureLoad(depth_t, vec2<i32>(pixel), 0);
+	let distance = view_depth(pixel, scene_depth) - view_depth(pixel, depth);
+	return clamp(distance / soft.fade_distance, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	// A round, soft edged dot.
+	var alpha = (1.0 - smoothstep(0.5, 1.0, length(in.corner))) * in.fade;
+	alpha *= soft_factor(in.clip_pos.xy, in.clip_pos.z);
+	return vec4<f32>(vec3<f32>(1.0, 0.6, 0.2) * alpha, alpha);
+}
@@ -0,0 +1,30 @@
+// The terms of the Cook-Torrance BRDF.
+
+// #include "common.wgsl"
+
+// The reflectance of dielectrics seen head on.
+const DIELECTRIC_F0: f32 = 0.04;
+
+// The GGX (Trowbridge-Reitz) distribution of microfacet normals, the share of
+// them facing `halfway`.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+	let a = roughness * roughness;
+	let a2 = a * a;
+	let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+	return a2 / (PI * d * d);
+}
+
+// Smith's shadowing and masking of microfacets, with Schlick's approximation of
+// GGX for each direction.
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+	let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+	let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+	let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+	return g_v * g_l;
+}
+
+// Schlick's approximation of the share of light reflected rather than
+// refracted.
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+	return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
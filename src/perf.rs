@@ -0,0 +1,213 @@
+//! Frame time statistics, and a graph of them drawn over the frame.
+
+use bytemuck::{Pod, Zeroable};
+
+/// How many frames [`FrameTimer`] remembers.
+pub const FRAME_HISTORY: usize = 256;
+
+/// The durations of the last [`FRAME_HISTORY`] frames.
+#[derive(Clone, Debug)]
+pub struct FrameTimer {
+	/// In seconds. Slots that weren't recorded yet are 0.
+	durations: [f32; FRAME_HISTORY],
+	/// Where the next duration goes, after the most recent one.
+	head: usize,
+	/// How many slots were recorded, up to `FRAME_HISTORY`.
+	len: usize,
+}
+impl Default for FrameTimer {
+	fn default() -> Self {
+		Self {
+			durations: [0.; FRAME_HISTORY],
+			head: 0,
+			len: 0,
+		}
+	}
+}
+impl FrameTimer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Records a frame that took `dt_seconds`, forgetting the oldest one if full.
+	pub fn record(&mut self, dt_seconds: f32) {
+		self.durations[self.head] = dt_seconds;
+		self.head = (self.head + 1) % FRAME_HISTORY;
+		self.len = (self.len + 1).min(FRAME_HISTORY);
+		tracing::debug!(frame_ms = dt_seconds * 1000., "Frame recorded");
+	}
+
+	fn recorded(&self) -> impl Iterator<Item = f32> + '_ {
+		self.durations.iter().copied().take(self.len)
+	}
+
+	/// Frames per second over the recorded frames, or 0 if there are none.
+	pub fn average_fps(&self) -> f32 {
+		let total: f32 = self.recorded().sum();
+		if total > 0. {
+			self.len as f32 / total
+		} else {
+			0.
+		}
+	}
+
+	/// The frame rate of the slowest recorded frame.
+	pub fn min_fps(&self) -> f32 {
+		fps(self.recorded().fold(0., f32::max))
+	}
+
+	/// The frame rate of the fastest recorded frame.
+	pub fn max_fps(&self) -> f32 {
+		fps(self.recorded().fold(f32::INFINITY, f32::min))
+	}
+
+	/// The durations from oldest to newest, divided by the longest one so that
+	/// they go from 0 to 1. Slots that weren't recorded yet are 0.
+	pub fn as_normalized_slice(&self) -> [f32; FRAME_HISTORY] {
+		let longest = self.recorded().fold(0., f32::max);
+		let mut result = [0.; FRAME_HISTORY];
+		if longest > 0. {
+			let (newer, older) = self.durations.split_at(self.head);
+			for (out, dt) in result.iter_mut().zip(older.iter().chain(newer)) {
+				*out = dt / longest;
+			}
+		}
+		result
+	}
+}
+
+/// The frame rate of frames lasting `dt` seconds, or 0 if there are none.
+fn fps(dt: f32) -> f32 {
+	if dt > 0. && dt.is_finite() {
+		1. / dt
+	} else {
+		0.
+	}
+}
+
+/// A point of the graph, in clip space.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct GraphVertex {
+	pos: [f32; 2],
+}
+
+/// Draws a [`FrameTimer`]'s history as a line strip in the top left corner, one
+/// logical pixel per frame.
+pub struct FrameGraph {
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	vtx_buf: wgpu::Buffer,
+}
+impl FrameGraph {
+	/// Height of the graph, in logical pixels, for the longest frame.
+	const HEIGHT: f32 = 64.;
+	/// Distance from the corner of the frame, in logical pixels.
+	const MARGIN: f32 = 8.;
+
+	pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("frame_graph.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Frame Graph Pipeline Layout"),
+			bind_group_layouts: &[],
+			push_constant_ranges: &[],
+		});
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		let vtx_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Frame Graph Vertices"),
+			size: (FRAME_HISTORY * std::mem::size_of::<GraphVertex>()) as u64,
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		Self {
+			shader,
+			layout,
+			pipeline,
+			vtx_buf,
+		}
+	}
+
+	/// Recreates the pipeline for a different frame format.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Uploads the graph of `timer` for a frame `width` x `height` logical pixels
+	/// big. Returns the number of bytes written.
+	pub fn update(
+		&self,
+		queue: &wgpu::Queue,
+		timer: &FrameTimer,
+		width: f32,
+		height: f32,
+	) -> u64 {
+		// Logical pixels to clip space, with y pointing down.
+		let to_clip = |x: f32, y: f32| [x / width * 2. - 1., 1. - y / height * 2.];
+		let bottom = Self::MARGIN + Self::HEIGHT;
+		let vertices: Vec<GraphVertex> = timer
+			.as_normalized_slice()
+			.iter()
+			.enumerate()
+			.map(|(i, value)| GraphVertex {
+				pos: to_clip(Self::MARGIN + i as f32, bottom - value * Self::HEIGHT),
+			})
+			.collect();
+		let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+		queue.write_buffer(&self.vtx_buf, 0, bytes);
+		bytes.len() as u64
+	}
+
+	/// Records drawing the graph over `view`.
+	pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Frame Graph Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+		pass.draw(0..FRAME_HISTORY as u32, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Frame Graph Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[wgpu::VertexBufferLayout {
+				array_stride: std::mem::size_of::<GraphVertex>() as u64,
+				step_mode: wgpu::VertexStepMode::Vertex,
+				attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+			}],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState {
+			topology: wgpu::PrimitiveTopology::LineStrip,
+			..Default::default()
+		},
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
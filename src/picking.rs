@@ -0,0 +1,386 @@
+//! Picking objects on the GPU: each object is drawn in a color encoding its
+//! index, and the pixel under the cursor is read back.
+
+use crate::vertex::{Instance, Vertex};
+
+/// The index that the picking texture is cleared to, where no object was drawn.
+pub const NO_ID: u32 = u32::MAX;
+
+/// The color that the `i`th object is drawn in, by the bytes of `i`, least
+/// significant first. `i` must not be [`NO_ID`].
+pub fn id_to_color(i: u32) -> [u8; 4] {
+	i.to_le_bytes()
+}
+
+/// The index of the object drawn in `color`, or `None` where none was.
+pub fn color_to_id(color: [u8; 4]) -> Option<u32> {
+	let id = u32::from_le_bytes(color);
+	(id != NO_ID).then_some(id)
+}
+
+/// A texture holding the index of the object drawn at each pixel of the main
+/// camera's view, and the pipeline drawing it.
+///
+/// The index of each object is uploaded by [`Self::prepare_pass`], and bound by
+/// [`Self::set_id`].
+pub(crate) struct IdPicker {
+	/// The index of the object at each pixel, as an [`id_to_color`].
+	id_texture: wgpu::Texture,
+	id_view: wgpu::TextureView,
+	/// Depth tests the picking pass.
+	depth_view: wgpu::TextureView,
+	width: u32,
+	height: u32,
+	/// Whether `id_texture` was drawn since it was created.
+	drawn: bool,
+	/// Holds an [`id_to_color`] per object, as a `vec4<u32>`, `id_stride` apart.
+	id_buf: wgpu::Buffer,
+	id_layout: wgpu::BindGroupLayout,
+	id_bind_group: wgpu::BindGroup,
+	/// How many objects' colors are in `id_buf`.
+	id_count: u32,
+	id_capacity: u32,
+	id_stride: u32,
+	/// The depth where nothing was drawn.
+	far_depth: f32,
+	/// Bound to the groups of the main pipeline layout that the pass doesn't use.
+	empty_bind_group: wgpu::BindGroup,
+	#[cfg(feature = "hot-reload")]
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+impl IdPicker {
+	/// Rendered to on all backends, unlike `R32Uint` with WebGL.
+	pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Uint;
+	const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+	/// The bind group of the indices, in place of the main pipeline's light.
+	const ID_BIND_GROUP: u32 = 2;
+	/// The size of a `vec4<u32>`.
+	const ID_SIZE: u64 = 16;
+
+	/// Creates the textures for `width` x `height` frames. The picking pass uses
+	/// `vs_main` and `fs_id` of `shader`, with the main pipeline's camera and
+	/// object bind group layouts.
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		device: &wgpu::Device,
+		width: u32,
+		height: u32,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+		reverse_z: bool,
+	) -> Self {
+		let empty_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Empty Bind Group Layout"),
+				entries: &[],
+			});
+		let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("empty_bind_group"),
+			layout: &empty_layout,
+			entries: &[],
+		});
+		let id_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Picking Id Bind Group Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					// After the light's bindings, which share the group in the shader.
+					binding: 22,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: true,
+						min_binding_size: wgpu::BufferSize::new(Self::ID_SIZE),
+					},
+					count: None,
+				}],
+			});
+		let alignment = device.limits().min_uniform_buffer_offset_alignment;
+		let id_stride = ((Self::ID_SIZE as u32 - 1) / alignment + 1) * alignment;
+		let (id_buf, id_bind_group) =
+			create_id_buffer(device, &id_layout, 1, id_stride);
+		// The material group is left empty.
+		let mut bind_group_layouts = vec![&empty_layout, camera_layout, &id_layout];
+		bind_group_layouts.extend(object_layout);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Picking Pipeline Layout"),
+			bind_group_layouts: &bind_group_layouts,
+			push_constant_ranges,
+		});
+		let pipeline = create_pipeline(device, &layout, shader, reverse_z);
+		let (id_texture, id_view, depth_view) = create_textures(device, width, height);
+		Self {
+			id_texture,
+			id_view,
+			depth_view,
+			width,
+			height,
+			drawn: false,
+			id_buf,
+			id_layout,
+			id_bind_group,
+			id_count: 0,
+			id_capacity: 1,
+			id_stride,
+			far_depth: if reverse_z { 0. } else { 1. },
+			empty_bind_group,
+			#[cfg(feature = "hot-reload")]
+			layout,
+			pipeline,
+		}
+	}
+
+	/// Recreates the textures for `width` x `height` frames. Nothing can be
+	/// picked until they are drawn again.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		(self.id_texture, self.id_view, self.depth_view) =
+			create_textures(device, width, height);
+		(self.width, self.height) = (width, height);
+		self.drawn = false;
+	}
+
+	/// Recreates the pipeline with a new version of `shader.wgsl`.
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		let reverse_z = self.far_depth == 0.;
+		self.pipeline = create_pipeline(device, &self.layout, shader, reverse_z);
+	}
+
+	/// Uploads the colors of objects 0 to `count`, unless they already are, for
+	/// the pass that must follow. Returns the number of bytes written.
+	pub fn prepare_pass(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		count: u32,
+	) -> u64 {
+		self.drawn = true;
+		if count <= self.id_count {
+			return 0;
+		}
+		if count > self.id_capacity {
+			self.id_capacity = count.next_power_of_two();
+			(self.id_buf, self.id_bind_group) = create_id_buffer(
+				device,
+				&self.id_layout,
+				self.id_capacity,
+				self.id_stride,
+			);
+		}
+		let mut bytes = vec![0; count as usize * self.id_stride as usize];
+		for (i, chunk) in bytes.chunks_exact_mut(self.id_stride as usize).enumerate() {
+			let color = id_to_color(i as u32).map(u32::from);
+			chunk[..Self::ID_SIZE as usize]
+				.copy_from_slice(bytemuck::cast_slice(&color));
+		}
+		queue.write_buffer(&self.id_buf, 0, &bytes);
+		self.id_count = count;
+		bytes.len() as u64
+	}
+
+	/// Binds the color of the `index`th object, for the following draws.
+	pub fn set_id<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, index: usize) {
+		pass.set_bind_group(
+			Self::ID_BIND_GROUP,
+			&self.id_bind_group,
+			&[index as u32 * self.id_stride],
+		);
+	}
+
+	/// Begins the pass drawing the indices of the objects, after
+	/// [`Self::prepare_pass`], with its pipeline set. The camera and object bind
+	/// groups are left to the caller, and each object's index, see
+	/// [`Self::set_id`].
+	pub fn begin_pass<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+	) -> wgpu::RenderPass<'a> {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Picking Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: &self.id_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Integer targets are cleared to the color's values as is.
+					load: wgpu::LoadOp::Clear(wgpu::Color {
+						r: 255.,
+						g: 255.,
+						b: 255.,
+						a: 255.,
+					}),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(self.far_depth),
+					store: true,
+				}),
+				stencil_ops: None,
+			}),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.empty_bind_group, &[]);
+		pass
+	}
+
+	/// Records copying the color at `x`, `y` into the start of `buffer`, which
+	/// takes 4 bytes. Returns `false` if there is nothing to copy, as the pixel is
+	/// outside the texture or it wasn't drawn.
+	pub fn copy_pixel(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		x: u32,
+		y: u32,
+		buffer: &wgpu::Buffer,
+	) -> bool {
+		if !self.drawn || x >= self.width || y >= self.height {
+			return false;
+		}
+		encoder.copy_texture_to_buffer(
+			wgpu::ImageCopyTexture {
+				texture: &self.id_texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d { x, y, z: 0 },
+				aspect: wgpu::TextureAspect::All,
+			},
+			wgpu::ImageCopyBuffer {
+				buffer,
+				layout: wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: None,
+					rows_per_image: None,
+				},
+			},
+			wgpu::Extent3d {
+				width: 1,
+				height: 1,
+				depth_or_array_layers: 1,
+			},
+		);
+		true
+	}
+}
+
+fn create_id_buffer(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	capacity: u32,
+	stride: u32,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+	let buf = device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Picking Ids"),
+		size: capacity as u64 * stride as u64,
+		usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("picking_id_bind_group"),
+		layout,
+		entries: &[wgpu::BindGroupEntry {
+			binding: 22,
+			resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+				buffer: &buf,
+				offset: 0,
+				size: wgpu::BufferSize::new(IdPicker::ID_SIZE),
+			}),
+		}],
+	});
+	(buf, bind_group)
+}
+
+/// The index and depth textures, for `width` x `height` frames.
+fn create_textures(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+) -> (wgpu::Texture, wgpu::TextureView, wgpu::TextureView) {
+	let texture = |label, format, usage| {
+		device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | usage,
+			view_formats: &[],
+		})
+	};
+	let id_texture = texture(
+		"Picking Ids",
+		IdPicker::FORMAT,
+		wgpu::TextureUsages::COPY_SRC,
+	);
+	let id_view = id_texture.create_view(&wgpu::TextureViewDescriptor::default());
+	let depth_view = texture(
+		"Picking Depth",
+		IdPicker::DEPTH_FORMAT,
+		wgpu::TextureUsages::empty(),
+	)
+	.create_view(&wgpu::TextureViewDescriptor::default());
+	(id_texture, id_view, depth_view)
+}
+
+/// Draws the indices of the objects, with `vs_main` and `fs_id` of
+/// `shader.wgsl`.
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	reverse_z: bool,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Picking Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[Vertex::vb_layout(), Instance::vb_layout()],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_id",
+			targets: &[Some(IdPicker::FORMAT.into())],
+		}),
+		primitive: wgpu::PrimitiveState {
+			cull_mode: Some(wgpu::Face::Back),
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: IdPicker::DEPTH_FORMAT,
+			depth_write_enabled: true,
+			depth_compare: if reverse_z {
+				wgpu::CompareFunction::Greater
+			} else {
+				wgpu::CompareFunction::Less
+			},
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ids_survive_encoding() {
+		for id in [0, 1, 255, 256, 0x0102_0304, NO_ID - 1] {
+			assert_eq!(color_to_id(id_to_color(id)), Some(id));
+		}
+		assert_eq!(id_to_color(0x0102_0304), [4, 3, 2, 1]);
+		// The clear color of the picking texture.
+		assert_eq!(color_to_id([255; 4]), None);
+	}
+}
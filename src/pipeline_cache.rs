@@ -0,0 +1,309 @@
+use rustc_hash::{FxHashMap, FxHasher};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use {
+	color_eyre::{eyre::eyre, eyre::WrapErr, Result},
+	instant::Instant,
+	log::{debug, warn},
+	std::borrow::Cow,
+	std::path::{Path, PathBuf},
+};
+
+/// Shares render pipelines created from equal descriptors, see
+/// [`descriptor_hash`].
+///
+/// Only weak references are kept, so pipelines are dropped once nothing else
+/// uses them.
+#[derive(Default)]
+pub struct PipelineCache {
+	pipelines: FxHashMap<u64, Weak<wgpu::RenderPipeline>>,
+}
+impl PipelineCache {
+	/// Returns the pipeline created for `descriptor_hash`, if it is still in use.
+	/// Otherwise `make_pipeline` creates it.
+	pub fn get_or_create(
+		&mut self,
+		device: &wgpu::Device,
+		descriptor_hash: u64,
+		make_pipeline: impl FnOnce(&wgpu::Device) -> wgpu::RenderPipeline,
+	) -> Arc<wgpu::RenderPipeline> {
+		if let Some(pipeline) = self.get(descriptor_hash) {
+			return pipeline;
+		}
+		self.insert(descriptor_hash, make_pipeline(device))
+	}
+
+	/// Returns the pipeline created for `descriptor_hash`, if it is still in use.
+	pub fn get(&self, descriptor_hash: u64) -> Option<Arc<wgpu::RenderPipeline>> {
+		self.pipelines.get(&descriptor_hash).and_then(Weak::upgrade)
+	}
+
+	/// Shares `pipeline`, created for `descriptor_hash` elsewhere, such as in the
+	/// background.
+	pub fn insert(
+		&mut self,
+		descriptor_hash: u64,
+		pipeline: wgpu::RenderPipeline,
+	) -> Arc<wgpu::RenderPipeline> {
+		self.pipelines
+			.retain(|_, pipeline| pipeline.strong_count() > 0);
+		let pipeline = Arc::new(pipeline);
+		self.pipelines
+			.insert(descriptor_hash, Arc::downgrade(&pipeline));
+		pipeline
+	}
+}
+
+/// Hashes what a pipeline is created from, such as its shader source, vertex
+/// buffer layouts, blend mode, topology and depth stencil state. Unlike
+/// `std`'s hasher, the hash is the same across runs.
+pub fn descriptor_hash(descriptor: impl Hash) -> u64 {
+	let mut hasher = FxHasher::default();
+	descriptor.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Caches shader modules on disk as SPIR-V, so that restarts can skip the WGSL
+/// frontend.
+///
+/// Entries are keyed by the hashes of the WGSL source and of the pipeline key, and
+/// store the SPIR-V words. Changing the source changes the key, so stale entries
+/// are never read, and different sources for the same pipeline key are cached
+/// side by side.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct PipelineDiskCache {
+	db: sled::Db,
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl PipelineDiskCache {
+	/// Environment variable that overrides the location of the cache.
+	pub const PATH_ENV: &'static str = "WGPU_PIPELINE_CACHE";
+
+	pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let db = sled::open(path).wrap_err_with(|| {
+			format!("Failed to open pipeline cache at {}", path.display())
+		})?;
+		Ok(Self { db })
+	}
+
+	/// Opens the cache at [`Self::PATH_ENV`], or in the temp dir if it isn't set.
+	pub fn open_default() -> Result<Self> {
+		let path = std::env::var_os(Self::PATH_ENV)
+			.map(PathBuf::from)
+			.unwrap_or_else(|| {
+				std::env::temp_dir().join("wgpu-experiments-pipeline-cache")
+			});
+		Self::open(path)
+	}
+
+	/// Creates a shader module from `wgsl`, using the cached SPIR-V when there is
+	/// an up to date entry for `pipeline_key`.
+	pub fn create_shader_module(
+		&self,
+		device: &wgpu::Device,
+		label: Option<&str>,
+		wgsl: &str,
+		pipeline_key: impl Hash,
+	) -> Result<wgpu::ShaderModule> {
+		let start = Instant::now();
+		let key = entry_key(hash(wgsl), hash(pipeline_key));
+
+		let cached = self
+			.db
+			.get(key)
+			.wrap_err("Failed to read from pipeline cache")?
+			// Corrupt entries are simply overwritten below.
+			.and_then(|entry| decode_entry(&entry));
+		let hit = cached.is_some();
+		let spirv = match cached {
+			Some(spirv) => spirv,
+			None => {
+				let spirv = compile_spirv(wgsl)?;
+				let entry: &[u8] = bytemuck::cast_slice(&spirv);
+				self.db
+					.insert(key, entry)
+					.wrap_err("Failed to write to pipeline cache")?;
+				if let Err(err) = self.db.flush() {
+					warn!("Failed to flush pipeline cache: {err}");
+				}
+				spirv
+			}
+		};
+
+		let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label,
+			source: wgpu::ShaderSource::SpirV(Cow::Owned(spirv)),
+		});
+		debug!(
+			"Shader {:?} created in {:?} (cache {})",
+			label,
+			start.elapsed(),
+			if hit { "hit" } else { "miss" }
+		);
+		Ok(module)
+	}
+}
+
+/// The key of the entry compiled from the source with `source_hash`, for the
+/// pipeline key with `key_hash`.
+#[cfg(not(target_arch = "wasm32"))]
+fn entry_key(source_hash: u64, key_hash: u64) -> [u8; 16] {
+	let mut key = [0; 16];
+	key[..8].copy_from_slice(&source_hash.to_le_bytes());
+	key[8..].copy_from_slice(&key_hash.to_le_bytes());
+	key
+}
+
+/// The SPIR-V words of a cache entry, if it isn't empty or truncated.
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_entry(entry: &[u8]) -> Option<Vec<u32>> {
+	if entry.is_empty() || entry.len() % 4 != 0 {
+		return None;
+	}
+	Some(
+		entry
+			.chunks_exact(4)
+			.map(|w| u32::from_ne_bytes([w[0], w[1], w[2], w[3]]))
+			.collect(),
+	)
+}
+
+/// Hashes keys of the disk cache. `std`'s hasher may change between Rust
+/// releases, which would make the cache miss after updating the toolchain.
+#[cfg(not(target_arch = "wasm32"))]
+fn hash(value: impl Hash) -> u64 {
+	descriptor_hash(value)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compile_spirv(wgsl: &str) -> Result<Vec<u32>> {
+	let module = naga::front::wgsl::parse_str(wgsl)
+		.map_err(|e| eyre!("Failed to parse WGSL: {}", e.emit_to_string(wgsl)))?;
+	let info = naga::valid::Validator::new(
+		naga::valid::ValidationFlags::all(),
+		naga::valid::Capabilities::all(),
+	)
+	.validate(&module)
+	.wrap_err("Failed to validate shader")?;
+	let options = naga::back::spv::Options {
+		// wgpu reads SPIR-V without adjusting the coordinate space, so we must not
+		// adjust it on the way out either.
+		flags: naga::back::spv::WriterFlags::LABEL_VARYINGS
+			| naga::back::spv::WriterFlags::CLAMP_FRAG_DEPTH,
+		..Default::default()
+	};
+	naga::back::spv::write_vec(&module, &info, &options, None)
+		.wrap_err("Failed to write SPIR-V")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::gpu_context::test_context;
+
+	fn create_pipeline(device: &wgpu::Device) -> wgpu::RenderPipeline {
+		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: None,
+			source: wgpu::ShaderSource::Wgsl(
+				"@vertex fn vs_main() -> @builtin(position) vec4<f32> { \
+				 return vec4<f32>(0.0, 0.0, 0.0, 1.0); }"
+					.into(),
+			),
+		});
+		device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: None,
+			layout: None,
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[],
+			},
+			fragment: None,
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview: None,
+		})
+	}
+
+	#[test]
+	fn equal_descriptors_share_pipelines() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let device = &context.device;
+		let mut cache = PipelineCache::default();
+		let key = descriptor_hash(("empty", wgpu::PrimitiveTopology::TriangleList));
+		let first = cache.get_or_create(device, key, create_pipeline);
+		let second = cache.get_or_create(device, key, |_| panic!("Already cached"));
+		assert!(Arc::ptr_eq(&first, &second));
+
+		let other_key = descriptor_hash(("empty", wgpu::PrimitiveTopology::LineList));
+		let other = cache.get_or_create(device, other_key, create_pipeline);
+		assert!(!Arc::ptr_eq(&first, &other));
+
+		drop((first, second));
+		assert!(cache.get(key).is_none());
+	}
+
+	#[test]
+	fn truncated_entries_are_misses() {
+		let entry: &[u8] = bytemuck::cast_slice(&[0x0723_0203u32, 42]);
+		assert_eq!(decode_entry(entry), Some(vec![0x0723_0203, 42]));
+		assert_eq!(decode_entry(&entry[..5]), None);
+		assert_eq!(decode_entry(&entry[..3]), None);
+		assert_eq!(decode_entry(&[]), None);
+	}
+
+	#[test]
+	fn sources_of_one_pipeline_key_have_separate_entries() {
+		let key_hash = hash(("shader.wgsl", false));
+		assert_ne!(
+			entry_key(hash("const A = 1;"), key_hash),
+			entry_key(hash("const A = 2;"), key_hash)
+		);
+		assert_ne!(
+			entry_key(1, 2),
+			entry_key(2, 1),
+			"The hashes must not be interchangeable"
+		);
+	}
+
+	/// Compiles 16 variants of the main shader with an empty cache, then again
+	/// after reopening it, like on a restart. Run by CI, which prints the times.
+	#[test]
+	#[ignore]
+	fn cold_and_warm_startup() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let path = std::env::temp_dir().join("wgpu-experiments-startup-bench");
+		std::fs::remove_dir_all(&path).ok();
+		let main =
+			crate::shader::shader_source(include_str!("shader.wgsl"), false).unwrap();
+		let variants: Vec<_> = (0..16)
+			.map(|i| format!("{main}\nconst VARIANT: u32 = {i}u;\n"))
+			.collect();
+
+		let mut times = Vec::new();
+		for _ in 0..2 {
+			// Reopening reads the entries back from disk, as a restart would.
+			let cache = PipelineDiskCache::open(&path).unwrap();
+			let start = Instant::now();
+			for (i, wgsl) in variants.iter().enumerate() {
+				cache
+					.create_shader_module(&context.device, None, wgsl, ("variant", i))
+					.unwrap();
+			}
+			times.push(start.elapsed());
+			assert_eq!(cache.db.len(), variants.len());
+		}
+		println!(
+			"16 shader variants: cold {:?}, warm {:?}",
+			times[0], times[1]
+		);
+		std::fs::remove_dir_all(&path).ok();
+	}
+}
@@ -0,0 +1,113 @@
+// Draws surfaces in a solid color, while the variants of `shader.wgsl` they need
+// are created in the background. It has the same entry points and inputs, and
+// is quick to compile.
+//
+// `model` is declared by `render_object.rs`, as for `shader.wgsl`.
+
+struct CameraUniform {
+	view_proj: mat4x4<f32>,
+	position: vec3<f32>,
+};
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct SkinUniform {
+	bones: array<mat4x4<f32>, 64>,
+};
+@group(3) @binding(1)
+var<uniform> skin: SkinUniform;
+
+const COLOR: vec4<f32> = vec4<f32>(0.5, 0.5, 0.5, 1.0);
+
+struct InstanceInput {
+	@location(5) transform_0: vec4<f32>,
+	@location(6) transform_1: vec4<f32>,
+	@location(7) transform_2: vec4<f32>,
+	@location(8) transform_3: vec4<f32>,
+};
+
+fn instance_transform(instance: InstanceInput) -> mat4x4<f32> {
+	return model * mat4x4<f32>(
+		instance.transform_0,
+		instance.transform_1,
+		instance.transform_2,
+		instance.transform_3,
+	);
+}
+
+@vertex
+fn vs_main(@location(0) pos: vec3<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+	return camera.view_proj * instance_transform(instance) * vec4<f32>(pos, 1.0);
+}
+
+// Foliage doesn't sway.
+@vertex
+fn vs_foliage(@location(0) pos: vec3<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+	return camera.view_proj * instance_transform(instance) * vec4<f32>(pos, 1.0);
+}
+
+@vertex
+fn vs_skinned(
+	@location(0) pos: vec3<f32>,
+	@location(9) joints: vec4<u32>,
+	@location(10) weights: vec4<f32>,
+) -> @builtin(position) vec4<f32> {
+	let transform = skin.bones[joints.x] * weights.x
+		+ skin.bones[joints.y] * weights.y
+		+ skin.bones[joints.z] * weights.z
+		+ skin.bones[joints.w] * weights.w;
+	return camera.view_proj * transform * vec4<f32>(pos, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+	return COLOR;
+}
+
+@fragment
+fn fs_parallax() -> @location(0) vec4<f32> {
+	return COLOR;
+}
+
+@fragment
+fn fs_pbr() -> @location(0) vec4<f32> {
+	return COLOR;
+}
+
+@fragment
+fn fs_pbr_parallax() -> @location(0) vec4<f32> {
+	return COLOR;
+}
+
+// Transparent surfaces are accumulated with a weight of 1.
+struct AccumulationOutput {
+	@location(0) accumulation: vec4<f32>,
+	@location(1) revealage: vec4<f32>,
+};
+
+fn accumulate() -> AccumulationOutput {
+	var out: AccumulationOutput;
+	out.accumulation = COLOR;
+	out.revealage = vec4<f32>(COLOR.a);
+	return out;
+}
+
+@fragment
+fn fs_wboit() -> AccumulationOutput {
+	return accumulate();
+}
+
+@fragment
+fn fs_wboit_parallax() -> AccumulationOutput {
+	return accumulate();
+}
+
+@fragment
+fn fs_wboit_pbr() -> AccumulationOutput {
+	return accumulate();
+}
+
+@fragment
+fn fs_wboit_pbr_parallax() -> AccumulationOutput {
+	return accumulate();
+}
@@ -0,0 +1,306 @@
+//! Shadows of point lights, from a depth cubemap rendered from each light's
+//! position.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Perspective3, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraUniform;
+use crate::shadow::{DepthPass, ShadowMap};
+
+/// How many point lights `shader.wgsl` has room for.
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+/// A point light, in the layout of `shader.wgsl`'s `PointLight`.
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+#[repr(C)]
+struct PointLightUniform {
+	position: [f32; 3],
+	range: f32,
+	color: [f32; 3],
+	near: f32,
+}
+
+/// The layout of `shader.wgsl`'s `PointLightsUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct PointLightsUniform {
+	lights: [PointLightUniform; MAX_POINT_SHADOWS],
+	count: u32,
+	_pad: [u32; 3],
+}
+impl PointLightsUniform {
+	/// The uniform lighting the scene with `maps`' lights.
+	///
+	/// # Panics
+	///
+	/// If there are more than [`MAX_POINT_SHADOWS`] maps.
+	pub fn new(maps: &[PointShadowMap]) -> Self {
+		assert!(
+			maps.len() <= MAX_POINT_SHADOWS,
+			"At most {MAX_POINT_SHADOWS} point lights cast shadows, got {}",
+			maps.len()
+		);
+		let mut lights = [PointLightUniform::default(); MAX_POINT_SHADOWS];
+		for (light, map) in lights.iter_mut().zip(maps) {
+			*light = PointLightUniform {
+				position: map.position.into(),
+				range: map.range,
+				color: map.color,
+				near: PointShadowMap::NEAR,
+			};
+		}
+		Self {
+			lights,
+			count: maps.len() as u32,
+			_pad: [0; 3],
+		}
+	}
+}
+
+/// The direction each face's camera looks in and its up vector, in the order
+/// of the cubemap's layers.
+///
+/// The cameras are right-handed while cubemaps are sampled left-handed, so the
+/// faces come out mirrored along z: the +z layer holds what is seen along -z
+/// and the other way around, and `shader.wgsl` flips the z of the directions
+/// it samples to match.
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+	([1., 0., 0.], [0., 1., 0.]),
+	([-1., 0., 0.], [0., 1., 0.]),
+	([0., 1., 0.], [0., 0., 1.]),
+	([0., -1., 0.], [0., 0., -1.]),
+	([0., 0., -1.], [0., 1., 0.]),
+	([0., 0., 1.], [0., 1., 0.]),
+];
+
+/// The depth of the scene as seen by a point light in every direction, as the
+/// faces of a cubemap, and the light itself. Each face is drawn in a pass of its
+/// own, by a camera with a 90° field of view looking along an axis.
+///
+/// Lights up to [`Self::range`] away, where the depth map ends too.
+pub struct PointShadowMap {
+	texture: wgpu::Texture,
+	/// All faces, as a cubemap.
+	pub view: wgpu::TextureView,
+	/// The layer of each face, to draw into.
+	face_views: Vec<wgpu::TextureView>,
+	/// The camera uniform each face is drawn with, and the bind group of it.
+	cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+	/// Transforms world space into each face's clip space.
+	face_view_projs: [Matrix4<f32>; 6],
+	position: Point3<f32>,
+	range: f32,
+	color: [f32; 3],
+	/// Width and height of each face, in texels.
+	size: u32,
+	depth_pass: DepthPass,
+}
+impl PointShadowMap {
+	pub const DEFAULT_SIZE: u32 = 512;
+	/// The near plane of the faces' cameras. Casters closer to the light than
+	/// this cast no shadows.
+	pub const NEAR: f32 = 0.05;
+
+	/// Creates a white light at `position` that reaches `range` away, with faces
+	/// of `size`×`size` texels, drawn like [`ShadowMap::new`].
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		size: u32,
+		position: Point3<f32>,
+		range: f32,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+	) -> Self {
+		let (texture, view, face_views) = create_texture(device, size);
+		let cameras = FACES
+			.iter()
+			.map(|_| {
+				let buffer =
+					device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+						label: Some("Point Shadow Camera Uniform"),
+						contents: bytemuck::bytes_of(&CameraUniform::new(
+							Matrix4::identity(),
+							Point3::origin(),
+						)),
+						usage: wgpu::BufferUsages::UNIFORM
+							| wgpu::BufferUsages::COPY_DST,
+					});
+				let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+					label: Some("point_shadow_camera_bind_group"),
+					layout: camera_layout,
+					entries: &[wgpu::BindGroupEntry {
+						binding: 0,
+						resource: buffer.as_entire_binding(),
+					}],
+				});
+				(buffer, bind_group)
+			})
+			.collect();
+		let depth_pass = DepthPass::new(
+			device,
+			shader,
+			camera_layout,
+			object_layout,
+			push_constant_ranges,
+		);
+		let mut result = Self {
+			texture,
+			view,
+			face_views,
+			cameras,
+			face_view_projs: [Matrix4::identity(); 6],
+			position,
+			range: range.max(Self::NEAR * 2.),
+			color: [1.; 3],
+			size,
+			depth_pass,
+		};
+		result.upload(queue);
+		result
+	}
+
+	pub fn position(&self) -> Point3<f32> {
+		self.position
+	}
+
+	/// Moves the light to `position`. Returns the number of bytes uploaded.
+	pub fn set_position(&mut self, queue: &wgpu::Queue, position: Point3<f32>) -> u64 {
+		self.position = position;
+		self.upload(queue)
+	}
+
+	pub fn range(&self) -> f32 {
+		self.range
+	}
+
+	/// Sets how far the light reaches, and its shadows with it. Returns the
+	/// number of bytes uploaded.
+	pub fn set_range(&mut self, queue: &wgpu::Queue, range: f32) -> u64 {
+		self.range = range.max(Self::NEAR * 2.);
+		self.upload(queue)
+	}
+
+	pub fn color(&self) -> [f32; 3] {
+		self.color
+	}
+
+	/// Sets the light's color, in the units of [`LightUniform::color`]. Applies
+	/// once the point lights are uploaded again.
+	///
+	/// [`LightUniform::color`]: crate::light::LightUniform::color
+	pub fn set_color(&mut self, color: [f32; 3]) {
+		self.color = color;
+	}
+
+	/// Transforms world space into the clip space of the face at `face`, in the
+	/// order of the cubemap's layers.
+	pub fn face_view_proj(&self, face: usize) -> &Matrix4<f32> {
+		&self.face_view_projs[face]
+	}
+
+	/// Recreates the faces if their size changed. Returns whether they were, in
+	/// which case bind groups sampling them must be recreated too.
+	pub fn set_size(&mut self, device: &wgpu::Device, size: u32) -> bool {
+		if size == self.size {
+			return false;
+		}
+		(self.texture, self.view, self.face_views) = create_texture(device, size);
+		self.size = size;
+		true
+	}
+
+	/// Recreates the pipeline with a new version of `shader.wgsl`.
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		self.depth_pass.set_shader(device, shader);
+	}
+
+	/// Points each face's camera from the light along its axis. Returns the
+	/// number of bytes uploaded.
+	fn upload(&mut self, queue: &wgpu::Queue) -> u64 {
+		let proj =
+			Perspective3::new(1., std::f32::consts::FRAC_PI_2, Self::NEAR, self.range);
+		// nalgebra's depth goes from -1 to 1, wgpu's from 0 to 1.
+		let depth_to_wgpu = Matrix4::new_translation(&Vector3::new(0., 0., 0.5))
+			* Matrix4::new_nonuniform_scaling(&Vector3::new(1., 1., 0.5));
+		let mut bytes = 0;
+		for (i, &(forward, up)) in FACES.iter().enumerate() {
+			let target = self.position + Vector3::from(forward);
+			let view = IsometryMatrix3::look_at_rh(
+				&self.position,
+				&target,
+				&Vector3::from(up),
+			);
+			self.face_view_projs[i] =
+				depth_to_wgpu * proj.as_matrix() * view.to_matrix();
+			let uniform = CameraUniform::new(self.face_view_projs[i], self.position);
+			queue.write_buffer(&self.cameras[i].0, 0, bytemuck::bytes_of(&uniform));
+			bytes += std::mem::size_of_val(&uniform) as u64;
+		}
+		bytes
+	}
+
+	/// Records the pass drawing the face at `face`, in which `scene` draws the
+	/// shadow casters. The object bind group, if any, is left to `scene`.
+	pub fn render_face<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+		scene: impl FnOnce(&mut wgpu::RenderPass<'a>),
+		face: usize,
+	) {
+		let (_, camera_bind_group) = &self.cameras[face];
+		let mut pass = self.depth_pass.begin(
+			"Point Shadow Pass",
+			encoder,
+			&self.face_views[face],
+			camera_bind_group,
+		);
+		scene(&mut pass);
+	}
+}
+
+/// A 1×1 cubemap bound in place of the point shadow maps that aren't there.
+pub(crate) fn create_placeholder(device: &wgpu::Device) -> wgpu::TextureView {
+	create_texture(device, 1).1
+}
+
+fn create_texture(
+	device: &wgpu::Device,
+	size: u32,
+) -> (wgpu::Texture, wgpu::TextureView, Vec<wgpu::TextureView>) {
+	let texture = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Point Shadow Map"),
+		size: wgpu::Extent3d {
+			width: size,
+			height: size,
+			depth_or_array_layers: 6,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: ShadowMap::FORMAT,
+		usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+			| wgpu::TextureUsages::TEXTURE_BINDING,
+		view_formats: &[],
+	});
+	let view = texture.create_view(&wgpu::TextureViewDescriptor {
+		dimension: Some(wgpu::TextureViewDimension::Cube),
+		..Default::default()
+	});
+	let face_views = (0..6)
+		.map(|layer| {
+			texture.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(wgpu::TextureViewDimension::D2),
+				base_array_layer: layer,
+				array_layer_count: Some(1),
+				..Default::default()
+			})
+		})
+		.collect();
+	(texture, view, face_views)
+}
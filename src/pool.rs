@@ -0,0 +1,154 @@
+//! Reuses buffers for data that is replaced often, rather than creating new ones
+//! every frame.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Buffers that were released, for [`BufferPool::acquire`] to hand out again.
+/// Buffers unused for more than `max_idle_frames` frames are destroyed.
+#[derive(Clone)]
+pub struct BufferPool {
+	inner: Arc<Mutex<PoolInner>>,
+}
+
+struct PoolInner {
+	/// The available buffers, by increasing size, with the frame they were
+	/// released in.
+	free: Vec<(wgpu::Buffer, u64)>,
+	frame: u64,
+	max_idle_frames: u64,
+}
+
+impl BufferPool {
+	pub fn new(max_idle_frames: u64) -> Self {
+		Self {
+			inner: Arc::new(Mutex::new(PoolInner {
+				free: Vec::new(),
+				frame: 0,
+				max_idle_frames,
+			})),
+		}
+	}
+
+	/// The smallest available buffer of `usage` with at least `size` bytes, or a
+	/// new buffer of `size` bytes if there is none. It goes back to the pool when
+	/// the returned [`PooledBuffer`] is released or dropped.
+	pub fn acquire(
+		&self,
+		device: &wgpu::Device,
+		size: u64,
+		usage: wgpu::BufferUsages,
+	) -> PooledBuffer {
+		let mut inner = self.lock();
+		let reused = inner
+			.free
+			.iter()
+			.position(|(buffer, _)| buffer.size() >= size && buffer.usage() == usage);
+		let buffer = match reused {
+			Some(i) => inner.free.remove(i).0,
+			None => device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some("Pooled Buffer"),
+				size,
+				usage,
+				mapped_at_creation: false,
+			}),
+		};
+		PooledBuffer {
+			buffer: Some(buffer),
+			pool: self.clone(),
+		}
+	}
+
+	/// Starts a new frame, destroying the buffers that weren't acquired for more
+	/// than `max_idle_frames` frames.
+	pub fn next_frame(&self) {
+		let mut inner = self.lock();
+		inner.frame += 1;
+		let (frame, max_idle_frames) = (inner.frame, inner.max_idle_frames);
+		inner.free.retain(|(buffer, released)| {
+			let keep = frame - released <= max_idle_frames;
+			if !keep {
+				buffer.destroy();
+			}
+			keep
+		});
+	}
+
+	/// How many buffers are waiting to be acquired again.
+	pub fn available(&self) -> usize {
+		self.lock().free.len()
+	}
+
+	fn release(&self, buffer: wgpu::Buffer) {
+		let mut inner = self.lock();
+		let frame = inner.frame;
+		let i = inner
+			.free
+			.partition_point(|(other, _)| other.size() <= buffer.size());
+		inner.free.insert(i, (buffer, frame));
+	}
+
+	fn lock(&self) -> MutexGuard<'_, PoolInner> {
+		// The pool is never left half updated, so a panic elsewhere doesn't
+		// invalidate it.
+		self.inner.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// A buffer of a [`BufferPool`], that goes back to it when released or dropped.
+/// It may be bigger than was asked for.
+pub struct PooledBuffer {
+	/// Only taken when dropped.
+	buffer: Option<wgpu::Buffer>,
+	pool: BufferPool,
+}
+impl PooledBuffer {
+	/// Returns the buffer to its pool. The same as dropping it.
+	pub fn release(self) {}
+}
+impl std::ops::Deref for PooledBuffer {
+	type Target = wgpu::Buffer;
+
+	fn deref(&self) -> &wgpu::Buffer {
+		self.buffer.as_ref().expect("Only taken when dropped")
+	}
+}
+impl Drop for PooledBuffer {
+	fn drop(&mut self) {
+		if let Some(buffer) = self.buffer.take() {
+			self.pool.release(buffer);
+		}
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::gpu_context::test_context;
+
+	#[test]
+	fn buffers_are_reused() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let device = &context.device;
+		let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+		let pool = BufferPool::new(2);
+		pool.acquire(device, 256, usage).release();
+		assert_eq!(pool.available(), 1);
+
+		// A new buffer would only have the 128 bytes asked for.
+		let reused = pool.acquire(device, 128, usage);
+		assert_eq!(reused.size(), 256);
+		assert_eq!(pool.available(), 0);
+		let other = pool.acquire(device, 128, wgpu::BufferUsages::UNIFORM);
+		assert_eq!(other.size(), 128);
+		drop((reused, other));
+		assert_eq!(pool.available(), 2);
+
+		// Destroyed once idle for more than 2 frames.
+		for _ in 0..3 {
+			pool.next_frame();
+		}
+		assert_eq!(pool.available(), 0);
+	}
+}
@@ -0,0 +1,723 @@
+//! The effects drawn over the scene once it is drawn, each from the target the
+//! scene or the previous effect drew into, into the next effect's target.
+
+use nalgebra::{IsometryMatrix3, Matrix4};
+
+use crate::bloom::{BloomPass, BloomSettings};
+use crate::camera::CameraLike;
+use crate::depth_of_field::DepthOfFieldPass;
+use crate::dynamic_resolution::DynamicResolutionPass;
+use crate::gbuffer::GBuffer;
+use crate::post_process::{PostProcessPass, PostProcessSettings};
+use crate::render_graph::{RenderGraph, ResourceId};
+use crate::render_target::RenderTarget;
+use crate::ssr::SsrPass;
+use crate::taa::TaaPass;
+use crate::tex2d::Tex2d;
+use crate::tonemap::{ToneMapPass, ToneMapSettings, HDR_FORMAT};
+
+/// An effect of [`PostEffects`].
+pub(crate) trait PostEffect {
+	/// The name of its pass in the frame's [`RenderGraph`].
+	fn name(&self) -> &'static str;
+
+	/// How many draws it records, each binding textures of its own.
+	fn draws(&self) -> u32 {
+		1
+	}
+
+	/// Whether it reads the G-buffer. It is skipped in frames without one.
+	fn reads_gbuffer(&self) -> bool {
+		false
+	}
+
+	/// Records drawing `input` with the effect over all of `output`.
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	);
+}
+
+impl PostEffect for TaaPass {
+	fn name(&self) -> &'static str {
+		"taa"
+	}
+
+	fn reads_gbuffer(&self) -> bool {
+		true
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		let Some(gbuffer) = gbuffer else {
+			return;
+		};
+		let (depth, motion) = (gbuffer.depth_view(), gbuffer.motion_view());
+		TaaPass::apply(self, device, encoder, input, depth, motion, output);
+	}
+}
+
+impl PostEffect for SsrPass {
+	fn name(&self) -> &'static str {
+		"ssr"
+	}
+
+	fn reads_gbuffer(&self) -> bool {
+		true
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		let Some(gbuffer) = gbuffer else {
+			return;
+		};
+		let (normal, depth) = (gbuffer.normal_view(), gbuffer.depth_view());
+		SsrPass::apply(self, device, encoder, normal, depth, input, output);
+	}
+}
+
+impl PostEffect for DepthOfFieldPass {
+	fn name(&self) -> &'static str {
+		"depth_of_field"
+	}
+
+	/// A horizontal and a vertical blur.
+	fn draws(&self) -> u32 {
+		2
+	}
+
+	fn reads_gbuffer(&self) -> bool {
+		true
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		let Some(gbuffer) = gbuffer else {
+			return;
+		};
+		let depth = gbuffer.depth_view();
+		DepthOfFieldPass::apply(self, device, encoder, depth, input, output);
+	}
+}
+
+impl PostEffect for BloomPass {
+	fn name(&self) -> &'static str {
+		"bloom"
+	}
+
+	/// Extracting the bright parts, blurring them and adding them back.
+	fn draws(&self) -> u32 {
+		3
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		BloomPass::apply(self, device, encoder, input, output);
+	}
+}
+
+impl PostEffect for ToneMapPass {
+	fn name(&self) -> &'static str {
+		"tonemap"
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		ToneMapPass::apply(self, device, encoder, input, output);
+	}
+}
+
+impl PostEffect for PostProcessPass {
+	fn name(&self) -> &'static str {
+		"post_process"
+	}
+
+	fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_gbuffer: Option<&GBuffer>,
+		input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		PostProcessPass::apply(self, device, encoder, input, output);
+	}
+}
+
+impl PostEffect for InvertPass {
+	fn name(&self) -> &'static str {
+		"invert"
+	}
+
+	/// `input` is the pass's own source, which is already bound.
+	fn apply(
+		&self,
+		_device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_gbuffer: Option<&GBuffer>,
+		_input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		self.draw(encoder, output);
+	}
+}
+
+impl PostEffect for DynamicResolutionPass {
+	fn name(&self) -> &'static str {
+		"dynamic_resolution"
+	}
+
+	/// `input` is the pass's own source, which is already bound.
+	fn apply(
+		&self,
+		_device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_gbuffer: Option<&GBuffer>,
+		_input: &wgpu::TextureView,
+		output: &wgpu::TextureView,
+	) {
+		DynamicResolutionPass::apply(self, encoder, output);
+	}
+}
+
+/// A demo post-processing effect: the scene is drawn into `source`, which is then
+/// drawn into the frame with its colors inverted.
+struct InvertPass {
+	source: RenderTarget,
+	pipeline: wgpu::RenderPipeline,
+	bind_group: wgpu::BindGroup,
+}
+impl InvertPass {
+	fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+	) -> Self {
+		let source = RenderTarget::new(device, width, height, format, "Invert Source");
+		let layout = Tex2d::layout(device);
+		let shader = device.create_shader_module(wgpu::include_wgsl!("invert.wgsl"));
+		let pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Invert Pipeline Layout"),
+				bind_group_layouts: &[&layout],
+				push_constant_ranges: &[],
+			});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Invert Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[Some(format.into())],
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview: None,
+		});
+		let bind_group = source.bind_group(device, &layout);
+		Self {
+			source,
+			pipeline,
+			bind_group,
+		}
+	}
+
+	/// Records drawing the inverted `source` over all of `view`.
+	fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Invert Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.bind_group, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+/// The effects drawn over the scene, in the order they are applied, each with
+/// the target the scene or the previous effect draws into. Effects are only
+/// created while enabled.
+pub(crate) struct PostEffects {
+	/// The format of the frame, which the effects after tone mapping draw in.
+	frame_format: wgpu::TextureFormat,
+	/// Whether depth goes from 1 at the near plane to 0 at the far plane.
+	reverse_z: bool,
+	/// Blends the scene into its history, jittering the camera every frame.
+	taa: Option<(RenderTarget, TaaPass)>,
+	/// Adds screen-space reflections.
+	ssr: Option<(RenderTarget, SsrPass)>,
+	/// Blurs the scene by how far it is out of focus.
+	depth_of_field: Option<(RenderTarget, DepthOfFieldPass)>,
+	bloom: Option<(RenderTarget, BloomPass)>,
+	/// With HDR, the scene is drawn in [`HDR_FORMAT`] and then tone mapped into
+	/// the frame's format.
+	tonemap: Option<(RenderTarget, ToneMapPass)>,
+	/// Adds chromatic aberration and a vignette.
+	post_process: Option<(RenderTarget, PostProcessPass)>,
+	invert: Option<InvertPass>,
+	/// Upscales the frame, drawn at a lower resolution, into the output.
+	dynamic_resolution: Option<DynamicResolutionPass>,
+}
+impl PostEffects {
+	/// No effects, for `frame_format` frames.
+	pub fn new(frame_format: wgpu::TextureFormat, reverse_z: bool) -> Self {
+		Self {
+			frame_format,
+			reverse_z,
+			taa: None,
+			ssr: None,
+			depth_of_field: None,
+			bloom: None,
+			tonemap: None,
+			post_process: None,
+			invert: None,
+			dynamic_resolution: None,
+		}
+	}
+
+	/// Takes the effects out, leaving none, so that the scene can be drawn into
+	/// their targets while the owner of `self` is borrowed.
+	pub fn take(&mut self) -> Self {
+		std::mem::replace(self, Self::new(self.frame_format, self.reverse_z))
+	}
+
+	/// The format the effects before tone mapping draw in.
+	fn color_format(&self) -> wgpu::TextureFormat {
+		if self.tonemap.is_some() {
+			HDR_FORMAT
+		} else {
+			self.frame_format
+		}
+	}
+
+	/// The enabled effects in the order they are applied, with their sources.
+	/// Those reading the G-buffer are left out unless `has_gbuffer`.
+	fn chain(&self, has_gbuffer: bool) -> Vec<(&wgpu::TextureView, &dyn PostEffect)> {
+		fn link<'a>(
+			(source, effect): &'a (RenderTarget, impl PostEffect),
+		) -> (&'a wgpu::TextureView, &'a dyn PostEffect) {
+			(source.color_view(), effect)
+		}
+		[
+			self.taa.as_ref().map(link),
+			self.ssr.as_ref().map(link),
+			self.depth_of_field.as_ref().map(link),
+			self.bloom.as_ref().map(link),
+			self.tonemap.as_ref().map(link),
+			self.post_process.as_ref().map(link),
+			(self.invert.as_ref())
+				.map(|invert| (invert.source.color_view(), invert as &dyn PostEffect)),
+			(self.dynamic_resolution.as_ref())
+				.map(|pass| (pass.source_view(), pass as &dyn PostEffect)),
+		]
+		.into_iter()
+		.flatten()
+		.filter(|(_, effect)| has_gbuffer || !effect.reads_gbuffer())
+		.collect()
+	}
+
+	/// Adds a pass per effect to `graph`, the last drawing into `output`. Returns
+	/// what the scene should be drawn into: the first effect's source, or
+	/// `output` without effects.
+	pub fn add_passes<'a>(
+		&'a self,
+		graph: &mut RenderGraph<'a>,
+		output: ResourceId,
+		gbuffer: Option<&'a GBuffer>,
+	) -> ResourceId {
+		let chain = self.chain(gbuffer.is_some());
+		let sources: Vec<ResourceId> = (chain.iter())
+			.map(|&(source, _)| graph.import_transient(source))
+			.collect();
+		for (i, (_, effect)) in chain.into_iter().enumerate() {
+			let input = sources[i];
+			let target = sources.get(i + 1).copied().unwrap_or(output);
+			graph.add_pass(effect.name(), &[input], &[target], move |encoder, res| {
+				let (input, target) = (res.view(input), res.view(target));
+				effect.apply(res.device, encoder, gbuffer, input, target);
+			});
+		}
+		sources.first().copied().unwrap_or(output)
+	}
+
+	/// How many draws [`Self::add_passes`] records, each binding textures of its
+	/// own.
+	pub fn draws(&self, has_gbuffer: bool) -> u32 {
+		let chain = self.chain(has_gbuffer);
+		chain.iter().map(|(_, effect)| effect.draws()).sum()
+	}
+
+	/// Whether an enabled effect reads the G-buffer.
+	pub fn reads_gbuffer(&self) -> bool {
+		self.chain(true)
+			.iter()
+			.any(|(_, effect)| effect.reads_gbuffer())
+	}
+
+	/// Uploads the projection of `camera` from `view` to the effects that need
+	/// it. Returns the number of bytes written.
+	pub fn update_camera(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut bytes = 0;
+		if let Some((_, ssr)) = &mut self.ssr {
+			bytes += ssr.update(queue, camera, view);
+		}
+		if let Some((_, taa)) = &mut self.taa {
+			bytes += taa.update(queue, camera, view);
+		}
+		if let Some((_, depth_of_field)) = &mut self.depth_of_field {
+			bytes += depth_of_field.update(queue, camera);
+		}
+		bytes
+	}
+
+	/// Moves the jitter of temporal anti-aliasing on to that of `frame`.
+	pub fn next_frame(&mut self, frame: u64) {
+		if let Some((_, taa)) = &mut self.taa {
+			taa.update_jitter(frame);
+		}
+	}
+
+	/// Offsets the projection within a pixel while temporal anti-aliasing is
+	/// enabled.
+	pub fn jitter_matrix(&self) -> Matrix4<f32> {
+		match &self.taa {
+			Some((_, taa)) => taa.jitter_matrix(),
+			None => Matrix4::identity(),
+		}
+	}
+
+	/// Recreates the effects' targets for `width` x `height` scenes, and their
+	/// pipelines if the frame's format is no longer `frame_format`.
+	pub fn resize(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		(width, height): (u32, u32),
+		frame_format: wgpu::TextureFormat,
+	) {
+		let format_changed = frame_format != self.frame_format;
+		self.frame_format = frame_format;
+		let color_format = self.color_format();
+		let target =
+			|format, label| RenderTarget::new(device, width, height, format, label);
+		if let Some((source, taa)) = &mut self.taa {
+			*source = target(color_format, "TAA Source");
+			taa.resize(device, width, height);
+			if format_changed {
+				taa.set_format(device, color_format);
+			}
+		}
+		if let Some((source, ssr)) = &mut self.ssr {
+			*source = target(color_format, "SSR Source");
+			if format_changed {
+				ssr.set_format(device, color_format);
+			}
+		}
+		if let Some((source, depth_of_field)) = &mut self.depth_of_field {
+			*source = target(color_format, "Depth of Field Source");
+			depth_of_field.resize(device, width, height);
+			depth_of_field.update(queue, camera);
+			if format_changed {
+				depth_of_field.set_format(device, color_format);
+			}
+		}
+		if let Some((source, bloom)) = &mut self.bloom {
+			*source = target(color_format, "Bloom Source");
+			bloom.resize(device, width, height);
+		}
+		if let Some((source, tonemap)) = &mut self.tonemap {
+			*source = target(HDR_FORMAT, "HDR Target");
+			if format_changed {
+				let settings = tonemap.settings();
+				*tonemap = ToneMapPass::new(device, queue, frame_format);
+				tonemap.set_settings(queue, settings);
+			}
+		}
+		if let Some((source, post_process)) = &mut self.post_process {
+			*source = target(frame_format, "Post Source");
+			if format_changed {
+				let settings = post_process.settings();
+				*post_process = PostProcessPass::new(device, frame_format);
+				post_process.set_settings(queue, settings);
+			}
+		}
+		if self.invert.is_some() {
+			self.invert = Some(InvertPass::new(device, frame_format, width, height));
+		}
+		if let Some(dynamic_resolution) = &mut self.dynamic_resolution {
+			dynamic_resolution.resize(device, width, height);
+			if format_changed {
+				dynamic_resolution.set_format(device, frame_format);
+			}
+		}
+	}
+
+	/// Enables temporal anti-aliasing for `width` x `height` scenes, or disables
+	/// it. Returns whether it was toggled.
+	pub fn set_taa(
+		&mut self,
+		device: &wgpu::Device,
+		enable: bool,
+		(width, height): (u32, u32),
+	) -> bool {
+		if enable == self.taa.is_some() {
+			return false;
+		}
+		if !enable {
+			self.taa = None;
+			return true;
+		}
+		let format = self.color_format();
+		let source = RenderTarget::new(device, width, height, format, "TAA Source");
+		let taa = TaaPass::new(device, format, width, height, self.reverse_z);
+		self.taa = Some((source, taa));
+		true
+	}
+
+	pub fn taa(&self) -> Option<&TaaPass> {
+		self.taa.as_ref().map(|(_, taa)| taa)
+	}
+
+	pub fn taa_mut(&mut self) -> Option<&mut TaaPass> {
+		self.taa.as_mut().map(|(_, taa)| taa)
+	}
+
+	/// Enables screen-space reflections for `width` x `height` scenes seen from
+	/// `camera`, or disables them. Returns whether they were toggled.
+	pub fn set_ssr(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		enable: bool,
+		(width, height): (u32, u32),
+	) -> bool {
+		if enable == self.ssr.is_some() {
+			return false;
+		}
+		if !enable {
+			self.ssr = None;
+			return true;
+		}
+		let format = self.color_format();
+		let source = RenderTarget::new(device, width, height, format, "SSR Source");
+		let mut ssr = SsrPass::new(device, format, self.reverse_z);
+		ssr.update(queue, camera, &camera.view());
+		self.ssr = Some((source, ssr));
+		true
+	}
+
+	pub fn ssr(&self) -> Option<&SsrPass> {
+		self.ssr.as_ref().map(|(_, ssr)| ssr)
+	}
+
+	pub fn ssr_mut(&mut self) -> Option<&mut SsrPass> {
+		self.ssr.as_mut().map(|(_, ssr)| ssr)
+	}
+
+	/// Enables depth of field for `width` x `height` scenes seen from `camera`,
+	/// or disables it. Returns whether it was toggled.
+	pub fn set_depth_of_field(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		enable: bool,
+		(width, height): (u32, u32),
+	) -> bool {
+		if enable == self.depth_of_field.is_some() {
+			return false;
+		}
+		if !enable {
+			self.depth_of_field = None;
+			return true;
+		}
+		let format = self.color_format();
+		let source =
+			RenderTarget::new(device, width, height, format, "Depth of Field Source");
+		let mut depth_of_field =
+			DepthOfFieldPass::new(device, format, width, height, self.reverse_z);
+		depth_of_field.update(queue, camera);
+		self.depth_of_field = Some((source, depth_of_field));
+		true
+	}
+
+	pub fn depth_of_field(&self) -> Option<&DepthOfFieldPass> {
+		self.depth_of_field.as_ref().map(|(_, dof)| dof)
+	}
+
+	pub fn depth_of_field_mut(&mut self) -> Option<&mut DepthOfFieldPass> {
+		self.depth_of_field.as_mut().map(|(_, dof)| dof)
+	}
+
+	/// Enables bloom for `width` x `height` scenes with `settings`, or disables
+	/// it with `None`.
+	pub fn set_bloom(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		settings: Option<BloomSettings>,
+		(width, height): (u32, u32),
+	) {
+		let Some(settings) = settings else {
+			self.bloom = None;
+			return;
+		};
+		let format = self.color_format();
+		let (_, bloom) = self.bloom.get_or_insert_with(|| {
+			(
+				RenderTarget::new(device, width, height, format, "Bloom Source"),
+				BloomPass::new(device, queue, width, height, format),
+			)
+		});
+		bloom.set_settings(queue, settings);
+	}
+
+	pub fn bloom(&self) -> Option<BloomSettings> {
+		self.bloom.as_ref().map(|(_, bloom)| bloom.settings())
+	}
+
+	/// Draws the scene in [`HDR_FORMAT`] for `width` x `height` scenes, tone
+	/// mapped into the frame. Can't be undone, as the scene's pipelines are
+	/// created for the format.
+	pub fn enable_tone_mapping(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		(width, height): (u32, u32),
+	) {
+		self.tonemap = Some((
+			RenderTarget::new(device, width, height, HDR_FORMAT, "HDR Target"),
+			ToneMapPass::new(device, queue, self.frame_format),
+		));
+	}
+
+	/// Returns whether HDR is enabled, and so tone mapping.
+	pub fn set_tone_mapping(
+		&mut self,
+		queue: &wgpu::Queue,
+		settings: ToneMapSettings,
+	) -> bool {
+		match &mut self.tonemap {
+			Some((_, tonemap)) => {
+				tonemap.set_settings(queue, settings);
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub fn tone_mapping(&self) -> Option<ToneMapSettings> {
+		self.tonemap.as_ref().map(|(_, tonemap)| tonemap.settings())
+	}
+
+	/// Enables chromatic aberration and a vignette for `width` x `height` scenes
+	/// with `settings`, or disables them with `None`.
+	pub fn set_post_process(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		settings: Option<PostProcessSettings>,
+		(width, height): (u32, u32),
+	) {
+		let Some(settings) = settings else {
+			self.post_process = None;
+			return;
+		};
+		let format = self.frame_format;
+		let (_, pass) = self.post_process.get_or_insert_with(|| {
+			(
+				RenderTarget::new(device, width, height, format, "Post Source"),
+				PostProcessPass::new(device, format),
+			)
+		});
+		pass.set_settings(queue, settings);
+	}
+
+	pub fn post_process(&self) -> Option<PostProcessSettings> {
+		self.post_process.as_ref().map(|(_, pass)| pass.settings())
+	}
+
+	/// Enables or disables inverting the colors of `width` x `height` scenes.
+	pub fn set_invert(
+		&mut self,
+		device: &wgpu::Device,
+		enable: bool,
+		(width, height): (u32, u32),
+	) {
+		self.invert =
+			enable.then(|| InvertPass::new(device, self.frame_format, width, height));
+	}
+
+	pub fn invert(&self) -> bool {
+		self.invert.is_some()
+	}
+
+	/// Draws `width` x `height` scenes into a target upscaled into the frame, or
+	/// into the frame with `None`.
+	pub fn set_dynamic_resolution(
+		&mut self,
+		device: &wgpu::Device,
+		size: Option<(u32, u32)>,
+	) {
+		self.dynamic_resolution = size.map(|(width, height)| {
+			DynamicResolutionPass::new(device, self.frame_format, width, height)
+		});
+	}
+}
@@ -0,0 +1,165 @@
+//! Cheap screen-space effects for a stylized look: chromatic aberration and a
+//! vignette.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::tex2d::Tex2d;
+
+/// The strength of each effect. All zeroes, the default, leave the frame as it
+/// is.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct PostProcessSettings {
+	/// How much the corners are darkened, from 0 to 1.
+	pub vignette_strength: f32,
+	/// The distance from the center at which the vignette is darkest, where the
+	/// frame's sides are 0.5 away.
+	pub vignette_radius: f32,
+	/// How far red and blue are shifted apart, as a fraction of the distance
+	/// from the center.
+	pub aberration_strength: f32,
+	/// The texture samples each of red and blue is averaged over, to smear
+	/// them instead of shifting them. At least one is taken.
+	pub aberration_samples: u32,
+}
+
+/// Draws a frame into another with chromatic aberration and then a vignette.
+pub struct PostProcessPass {
+	settings: PostProcessSettings,
+	uniform_buf: wgpu::Buffer,
+	uniform_bind_group: wgpu::BindGroup,
+	/// Layout of the bind group of the input, see [`Tex2d::layout`].
+	texture_layout: wgpu::BindGroupLayout,
+	sampler: wgpu::Sampler,
+	pipeline: wgpu::RenderPipeline,
+}
+impl PostProcessPass {
+	/// Creates a pass drawing into `format` textures, with the default settings.
+	pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+		let settings = PostProcessSettings::default();
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Post Process Uniform"),
+				contents: bytemuck::bytes_of(&settings),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let uniform_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Post Process Uniform Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("post_process_uniform_bind_group"),
+			layout: &uniform_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			}],
+		});
+		let texture_layout = Tex2d::layout(device);
+		// Shifted samples fall between texels. Unshifted ones are on their centers,
+		// where filtering keeps the texel as it is.
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("Post Process Sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("post_process.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Post Process Pipeline Layout"),
+			bind_group_layouts: &[&uniform_layout, &texture_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Post Process Pipeline"),
+			layout: Some(&layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[Some(format.into())],
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview: None,
+		});
+
+		Self {
+			settings,
+			uniform_buf,
+			uniform_bind_group,
+			texture_layout,
+			sampler,
+			pipeline,
+		}
+	}
+
+	pub fn settings(&self) -> PostProcessSettings {
+		self.settings
+	}
+
+	pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: PostProcessSettings) {
+		self.settings = settings;
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&settings));
+	}
+
+	/// Records drawing `input` with the effects into all of `output`, which must
+	/// have the same size.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		input_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("post_process_input_bind_group"),
+			layout: &self.texture_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(input_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Post Process Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+		pass.set_bind_group(1, &input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
@@ -0,0 +1,57 @@
+// Chromatic aberration and a vignette, over a texture drawn onto the whole
+// render target.
+
+struct PostProcessUniform {
+	vignette_strength: f32,
+	vignette_radius: f32,
+	aberration_strength: f32,
+	aberration_samples: u32,
+};
+@group(0) @binding(0)
+var<uniform> settings: PostProcessUniform;
+
+@group(1) @binding(0)
+var src_t: texture_2d<f32>;
+@group(1) @binding(1)
+var src_s: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen, with UVs from 0 to 1 over the visible part.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let from_center = in.uv - 0.5;
+	let color = textureSample(src_t, src_s, in.uv);
+
+	// Red is pushed out from the center and blue pulled in, each averaged over
+	// the samples up to its offset.
+	let offset = settings.aberration_strength * from_center;
+	let samples = max(settings.aberration_samples, 1u);
+	var red = 0.0;
+	var blue = 0.0;
+	for (var i = 1u; i <= samples; i += 1u) {
+		let t = f32(i) / f32(samples);
+		red += textureSample(src_t, src_s, in.uv + offset * t).r;
+		blue += textureSample(src_t, src_s, in.uv - offset * t).b;
+	}
+	var rgb = vec3<f32>(red / f32(samples), color.g, blue / f32(samples));
+
+	// Darkens towards the corners, fully at `vignette_radius` from the center.
+	let dist = length(from_center) / max(settings.vignette_radius, 1e-4);
+	let falloff = pow(min(dist, 1.0), 2.0);
+	rgb *= mix(1.0, 1.0 - falloff, settings.vignette_strength);
+	return vec4<f32>(rgb, color.a);
+}
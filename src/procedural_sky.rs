@@ -0,0 +1,198 @@
+//! A sky computed from the direction of the sun, see `procedural_sky.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Translation3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraLike;
+use crate::types::mat4_to_wgsl;
+
+/// The layout of `procedural_sky.wgsl`'s `SkyUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SkyUniform {
+	/// The inverse of the camera's projection, after its rotation only.
+	inv_proj_view: [[f32; 4]; 4],
+	/// Towards the sun, normalized.
+	sun_direction: [f32; 3],
+	turbidity: f32,
+	ground_albedo: f32,
+	_pad: [f32; 3],
+}
+
+/// Draws the sky with the Preetham model, from the Rayleigh and Mie scattering
+/// of sunlight, so that it follows the time of day. An alternative to a cubemap
+/// skybox, drawn before anything else in the same render pass.
+pub struct ProceduralSky {
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	/// The contents of `uniform_buf`.
+	uniform: SkyUniform,
+	uniform_buf: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+}
+impl ProceduralSky {
+	/// Creates a pipeline for render passes with the given color format, one
+	/// sample per pixel and no depth buffer. Use [`Self::set_format`] for others.
+	/// The sun starts high in the sky, in a clear atmosphere.
+	pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Procedural Sky Bind Group Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let uniform = SkyUniform {
+			inv_proj_view: mat4_to_wgsl(Matrix4::identity()),
+			sun_direction: Vector3::new(0.3, 0.8, -0.5).normalize().into(),
+			turbidity: 2.,
+			ground_albedo: 0.3,
+			_pad: [0.; 3],
+		};
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Procedural Sky Uniform"),
+				contents: bytemuck::bytes_of(&uniform),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("procedural_sky_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			}],
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Procedural Sky Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("procedural_sky.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format, 1, None);
+		Self {
+			shader,
+			layout,
+			pipeline,
+			uniform,
+			uniform_buf,
+			bind_group,
+		}
+	}
+
+	/// Recreates the pipeline for render passes with different attachments.
+	pub fn set_format(
+		&mut self,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+	) {
+		self.pipeline = create_pipeline(
+			device,
+			&self.layout,
+			&self.shader,
+			format,
+			sample_count,
+			Some(depth_format),
+		);
+	}
+
+	/// Points the sun along `dir`, from the ground towards the sky. It sets below
+	/// the horizon as `dir` points down.
+	pub fn set_sun_direction(&mut self, queue: &wgpu::Queue, dir: Vector3<f32>) {
+		self.uniform.sun_direction = dir.normalize().into();
+		self.upload(queue);
+	}
+
+	/// How hazy the air is, from 2 for a clear sky to about 10 for a hazy one.
+	pub fn set_turbidity(&mut self, queue: &wgpu::Queue, turbidity: f32) {
+		self.uniform.turbidity = turbidity;
+		self.upload(queue);
+	}
+
+	/// The fraction of light the ground below the horizon reflects.
+	pub fn set_ground_albedo(&mut self, queue: &wgpu::Queue, ground_albedo: f32) {
+		self.uniform.ground_albedo = ground_albedo;
+		self.upload(queue);
+	}
+
+	/// Uploads the orientation and projection of `camera`, seen from `view`
+	/// rather than the camera's own view. Returns the number of bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		// Without the translation, so that the sky never moves with the camera.
+		let rotation =
+			IsometryMatrix3::from_parts(Translation3::identity(), view.rotation);
+		let inv_proj_view = camera
+			.proj_view_from(&rotation)
+			.try_inverse()
+			.expect("Projections are invertible");
+		self.uniform.inv_proj_view = mat4_to_wgsl(inv_proj_view);
+		self.upload(queue)
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&self.uniform));
+		std::mem::size_of::<SkyUniform>() as u64
+	}
+
+	pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+	sample_count: u32,
+	depth_format: Option<wgpu::TextureFormat>,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Procedural Sky Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		// Drawn first over the cleared depth buffer, so its depth doesn't matter,
+		// with either direction of depth.
+		depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+			format,
+			depth_write_enabled: false,
+			depth_compare: wgpu::CompareFunction::Always,
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState {
+			count: sample_count,
+			..Default::default()
+		},
+		multiview: None,
+	})
+}
@@ -0,0 +1,109 @@
+// Draws the sky with the Preetham model, from the Rayleigh and Mie scattering of
+// sunlight through the atmosphere, behind everything else.
+
+struct SkyUniform {
+	// The inverse of the camera's projection, after its rotation only.
+	inv_proj_view: mat4x4<f32>,
+	// Towards the sun, normalized.
+	sun_direction: vec3<f32>,
+	turbidity: f32,
+	ground_albedo: f32,
+};
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+
+const PI: f32 = 3.141592653589793;
+const UP: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+// Rayleigh scattering coefficients at sea level, for red, green and blue.
+const TOTAL_RAYLEIGH: vec3<f32> = vec3<f32>(5.804542996261093e-6, 1.3562911419845635e-5, 3.0265902468824876e-5);
+// The wavelength dependent part of Mie scattering.
+const MIE_CONST: vec3<f32> = vec3<f32>(1.8399918514433978e14, 2.7798023919660528e14, 4.0790479543861094e14);
+const MIE_COEFFICIENT: f32 = 0.005;
+// How much Mie scattering favors the direction of the light.
+const MIE_DIRECTIONAL_G: f32 = 0.8;
+const RAYLEIGH_ZENITH_LENGTH: f32 = 8.4e3;
+const MIE_ZENITH_LENGTH: f32 = 1.25e3;
+// The sun's brightness, and how it fades as it sets.
+const SUN_INTENSITY: f32 = 1000.0;
+const CUTOFF_ANGLE: f32 = 1.6110731556870734;
+const STEEPNESS: f32 = 1.5;
+// The cosine of the sun's angular radius.
+const SUN_DISK_COS: f32 = 0.9999566769464484;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	// The world space points on the near and far planes seen through this
+	// fragment, before the perspective division, which doesn't interpolate.
+	@location(0) near: vec4<f32>,
+	@location(1) far: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	let ndc = uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(ndc, 0.0, 1.0);
+	out.near = sky.inv_proj_view * vec4<f32>(ndc, 0.0, 1.0);
+	out.far = sky.inv_proj_view * vec4<f32>(ndc, 1.0, 1.0);
+	return out;
+}
+
+fn sun_intensity(zenith_cos: f32) -> f32 {
+	let angle = acos(clamp(zenith_cos, -1.0, 1.0));
+	return SUN_INTENSITY * max(0.0, 1.0 - exp(-(CUTOFF_ANGLE - angle) / STEEPNESS));
+}
+
+fn total_mie(turbidity: f32) -> vec3<f32> {
+	let c = 0.2 * turbidity * 10e-18;
+	return 0.434 * c * MIE_CONST;
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+	return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Henyey-Greenstein.
+fn mie_phase(cos_theta: f32, g: f32) -> f32 {
+	let g2 = g * g;
+	return 1.0 / (4.0 * PI) * (1.0 - g2) / pow(1.0 - 2.0 * g * cos_theta + g2, 1.5);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let dir = normalize(in.far.xyz / in.far.w - in.near.xyz / in.near.w);
+	let sun = sky.sun_direction;
+	let sun_e = sun_intensity(dot(sun, UP));
+	let beta_r = TOTAL_RAYLEIGH;
+	let beta_m = total_mie(sky.turbidity) * MIE_COEFFICIENT;
+
+	// The optical length through the atmosphere, which is the longest at the
+	// horizon. Below it, the sky is seen as at the horizon.
+	let zenith = acos(max(0.0, dot(UP, dir)));
+	let inv_length = 1.0
+		/ (cos(zenith) + 0.15 * pow(93.885 - zenith * 180.0 / PI, -1.253));
+	let extinction = exp(-(beta_r * RAYLEIGH_ZENITH_LENGTH + beta_m * MIE_ZENITH_LENGTH) * inv_length);
+
+	// The light scattered towards the camera.
+	let cos_theta = dot(dir, sun);
+	let beta_r_theta = beta_r * rayleigh_phase(cos_theta * 0.5 + 0.5);
+	let beta_m_theta = beta_m * mie_phase(cos_theta, MIE_DIRECTIONAL_G);
+	let scattered = sun_e * (beta_r_theta + beta_m_theta) / (beta_r + beta_m);
+	var in_scatter = pow(scattered * (1.0 - extinction), vec3<f32>(1.5));
+	// Redder around a low sun.
+	let sunset = clamp(pow(1.0 - dot(UP, sun), 5.0), 0.0, 1.0);
+	in_scatter *= mix(vec3<f32>(1.0), sqrt(scattered * extinction), sunset);
+
+	var sky_light = vec3<f32>(0.1) * extinction;
+	let disk = smoothstep(SUN_DISK_COS, SUN_DISK_COS + 0.00002, cos_theta);
+	sky_light += sun_e * 19000.0 * extinction * disk;
+	var color = pow((in_scatter + sky_light) * 0.04 + vec3<f32>(0.0, 0.0003, 0.00075), vec3<f32>(1.0 / 2.4));
+
+	// The ground reflects the light of the horizon, without the sun's disk.
+	let horizon = pow(in_scatter * 0.04 + vec3<f32>(0.0, 0.0003, 0.00075), vec3<f32>(1.0 / 2.4));
+	let ground = 1.0 - smoothstep(-0.02, 0.0, dir.y);
+	color = mix(color, horizon * sky.ground_albedo, ground);
+	return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,223 @@
+//! CPU and GPU timing of frames.
+
+use log::{debug, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Statistics about a single rendered frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameStats {
+	/// Time spent recording and submitting the frame on the CPU.
+	pub cpu_ms: f32,
+	/// Time the GPU spent executing the latest frame that was read back, which
+	/// lags a frame or two behind. 0 when timestamp queries are not supported.
+	pub gpu_ms: f32,
+	pub draw_calls: u32,
+	pub triangles: u32,
+	/// Number of `set_bind_group` calls.
+	pub texture_switches: u32,
+	/// Bytes written with `queue.write_buffer`.
+	pub bytes_uploaded: u64,
+}
+
+/// The GPU time of a scope recorded with [`GpuProfiler::begin`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScopeTiming {
+	pub name: &'static str,
+	pub ms: f32,
+	/// The number of scopes this one is nested in.
+	pub depth: usize,
+}
+
+/// A scope as `(name, begin query index, depth)`.
+type Scope = (&'static str, u32, usize);
+
+/// A buffer the results of a frame's queries are copied into, to be mapped once
+/// the GPU is done with them.
+struct Readback {
+	buf: wgpu::Buffer,
+	/// The scopes of the frame copied into `buf`. Empty when `buf` is free.
+	scopes: Vec<Scope>,
+	/// Set once `buf` is mapped. `None` until mapping is requested.
+	mapped: Option<Arc<AtomicBool>>,
+}
+
+/// Measures the GPU time of named scopes using timestamp queries.
+///
+/// Requires [`wgpu::Features::TIMESTAMP_QUERY`]. Scopes are written on the command
+/// encoder, so they can only surround whole passes.
+///
+/// Results are read back asynchronously through several buffers, so that the CPU
+/// doesn't wait for the GPU. They arrive a frame or two late.
+pub struct GpuProfiler {
+	query_set: wgpu::QuerySet,
+	resolve_buf: wgpu::Buffer,
+	readbacks: Vec<Readback>,
+	/// Index into `readbacks` of the buffer the next frame is copied into.
+	next_readback: usize,
+	/// Nanoseconds per timestamp tick.
+	period_ns: f32,
+	/// The scopes of the frame being recorded.
+	scopes: Vec<Scope>,
+	/// Indices into `scopes` that have not been ended yet.
+	open: Vec<usize>,
+	/// The timing of each scope of the last read back frame.
+	results: Vec<ScopeTiming>,
+	/// The sum of the outermost scopes of the last read back frame.
+	total_ms: f32,
+}
+impl GpuProfiler {
+	/// The maximum number of scopes per frame.
+	pub const MAX_SCOPES: u32 = 16;
+	/// How many frames can be in flight before we start dropping their timings.
+	const READBACK_FRAMES: usize = 2;
+
+	/// Returns `None` if `device` doesn't have timestamp queries enabled.
+	pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+		if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+			return None;
+		}
+		let count = Self::MAX_SCOPES * 2;
+		let size = (count * wgpu::QUERY_SIZE) as wgpu::BufferAddress;
+		Some(Self {
+			query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+				label: Some("GpuProfiler Queries"),
+				ty: wgpu::QueryType::Timestamp,
+				count,
+			}),
+			resolve_buf: device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some("GpuProfiler Resolve"),
+				size,
+				usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+				mapped_at_creation: false,
+			}),
+			readbacks: (0..Self::READBACK_FRAMES)
+				.map(|_| Readback {
+					buf: device.create_buffer(&wgpu::BufferDescriptor {
+						label: Some("GpuProfiler Readback"),
+						size,
+						usage: wgpu::BufferUsages::MAP_READ
+							| wgpu::BufferUsages::COPY_DST,
+						mapped_at_creation: false,
+					}),
+					scopes: Vec::new(),
+					mapped: None,
+				})
+				.collect(),
+			next_readback: 0,
+			period_ns: queue.get_timestamp_period(),
+			scopes: Vec::new(),
+			open: Vec::new(),
+			results: Vec::new(),
+			total_ms: 0.,
+		})
+	}
+
+	/// Starts a scope named `name`. Scopes may nest, and must be closed with
+	/// [`Self::end`].
+	pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) {
+		if self.scopes.len() as u32 >= Self::MAX_SCOPES {
+			warn!("Too many GpuProfiler scopes, ignoring {name:?}");
+			return;
+		}
+		let index = self.scopes.len() as u32 * 2;
+		encoder.write_timestamp(&self.query_set, index);
+		let depth = self.open.len();
+		self.open.push(self.scopes.len());
+		self.scopes.push((name, index, depth));
+	}
+
+	/// Ends the innermost open scope.
+	pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		let Some(scope) = self.open.pop() else {
+			return;
+		};
+		encoder.write_timestamp(&self.query_set, self.scopes[scope].1 + 1);
+	}
+
+	/// Resolves the queries of this frame. Must be called after all scopes have
+	/// ended, and before `encoder` is finished.
+	pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		debug_assert!(self.open.is_empty(), "Unclosed GpuProfiler scopes");
+		let count = self.scopes.len() as u32 * 2;
+		if count == 0 {
+			return;
+		}
+		let readback = &mut self.readbacks[self.next_readback];
+		if !readback.scopes.is_empty() {
+			debug!("GPU is behind, dropping the timings of this frame");
+			self.scopes.clear();
+			return;
+		}
+		encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buf, 0);
+		encoder.copy_buffer_to_buffer(
+			&self.resolve_buf,
+			0,
+			&readback.buf,
+			0,
+			(count * wgpu::QUERY_SIZE) as wgpu::BufferAddress,
+		);
+		readback.scopes = std::mem::take(&mut self.scopes);
+	}
+
+	/// Starts reading back the frame that was just submitted, and collects the
+	/// results of earlier frames that have finished. Doesn't block.
+	pub fn read_back(&mut self, device: &wgpu::Device) {
+		let submitted = &mut self.readbacks[self.next_readback];
+		if !submitted.scopes.is_empty() && submitted.mapped.is_none() {
+			let mapped = Arc::new(AtomicBool::new(false));
+			submitted.buf.slice(..).map_async(wgpu::MapMode::Read, {
+				let mapped = mapped.clone();
+				move |result| match result {
+					Ok(()) => mapped.store(true, Ordering::Release),
+					Err(err) => warn!("Failed to read back GPU timings: {err}"),
+				}
+			});
+			submitted.mapped = Some(mapped);
+			self.next_readback = (self.next_readback + 1) % self.readbacks.len();
+		}
+		device.poll(wgpu::Maintain::Poll);
+
+		// Oldest first, so the newest results are the ones kept.
+		for i in 0..self.readbacks.len() {
+			let i = (self.next_readback + i) % self.readbacks.len();
+			let readback = &mut self.readbacks[i];
+			let Some(mapped) = &readback.mapped else {
+				continue;
+			};
+			if !mapped.load(Ordering::Acquire) {
+				continue;
+			}
+			self.results.clear();
+			self.total_ms = 0.;
+			{
+				let data = readback.buf.slice(..).get_mapped_range();
+				let timestamps: &[u64] = bytemuck::cast_slice(&data);
+				for &(name, index, depth) in readback.scopes.iter() {
+					let begin = timestamps[index as usize];
+					let end = timestamps[index as usize + 1];
+					let ms =
+						end.wrapping_sub(begin) as f32 * self.period_ns / 1_000_000.;
+					if depth == 0 {
+						self.total_ms += ms;
+					}
+					self.results.push(ScopeTiming { name, ms, depth });
+				}
+			}
+			readback.buf.unmap();
+			readback.scopes.clear();
+			readback.mapped = None;
+		}
+	}
+
+	/// The timing of each scope in the last read back frame, in the order they
+	/// were begun.
+	pub fn results(&self) -> &[ScopeTiming] {
+		&self.results
+	}
+
+	/// The total duration of the last read back frame, in milliseconds.
+	pub fn total_ms(&self) -> f32 {
+		self.total_ms
+	}
+}
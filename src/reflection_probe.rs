@@ -0,0 +1,300 @@
+//! Image-based lighting baked from an environment cubemap, see
+//! `reflection_probe.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, eyre::WrapErr, Result};
+use wgpu::util::DeviceExt;
+
+use crate::cubemap::Cubemap;
+use crate::shader::ShaderPreprocessor;
+use crate::tex2d::SamplerConfig;
+
+/// The layout of `reflection_probe.wgsl`'s `BakeUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct BakeUniform {
+	roughness: f32,
+	face_size: u32,
+	source_size: u32,
+	_pad: u32,
+}
+
+/// The lighting of an environment, baked for PBR materials with the split sum
+/// approximation: the diffuse light is read from `irradiance_map`, and the
+/// specular light from `prefiltered_map`, scaled and biased by `brdf_lut`.
+pub struct ReflectionProbe {
+	/// The light reaching a diffuse surface facing each direction.
+	pub irradiance_map: Cubemap,
+	/// The environment blurred by the GGX distribution, for a roughness of 0 at
+	/// the first mip level up to 1 at the last.
+	pub prefiltered_map: Cubemap,
+	/// The scale (red) and bias (green) of the reflectance at normal incidence,
+	/// by the cosine between the normal and view (u) and the roughness (v).
+	pub brdf_lut: wgpu::TextureView,
+}
+impl ReflectionProbe {
+	const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	const IRRADIANCE_SIZE: u32 = 32;
+	const PREFILTERED_SIZE: u32 = 128;
+	/// Down to 8x8, past which the blurred faces would show their seams.
+	const PREFILTERED_MIP_LEVELS: u32 = 5;
+	const BRDF_LUT_SIZE: u32 = 256;
+	const WORKGROUP_SIZE: u32 = 8;
+
+	/// Bakes the maps from `source` with compute shaders, waiting for them to
+	/// finish.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	pub fn bake(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		source: &Cubemap,
+	) -> Result<Self> {
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+		let irradiance_map =
+			create_cubemap(device, Self::IRRADIANCE_SIZE, 1, "Probe Irradiance");
+		let prefiltered_map = create_cubemap(
+			device,
+			Self::PREFILTERED_SIZE,
+			Self::PREFILTERED_MIP_LEVELS,
+			"Probe Pre-filtered Radiance",
+		);
+		let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("BRDF Lookup Table"),
+			size: wgpu::Extent3d {
+				width: Self::BRDF_LUT_SIZE,
+				height: Self::BRDF_LUT_SIZE,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: Self::FORMAT,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING
+				| wgpu::TextureUsages::STORAGE_BINDING,
+			view_formats: &[],
+		});
+		let brdf_lut_view = brdf_lut.create_view(&Default::default());
+
+		let texture_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty,
+			count: None,
+		};
+		let storage_texture = |view_dimension| wgpu::BindingType::StorageTexture {
+			access: wgpu::StorageTextureAccess::WriteOnly,
+			format: Self::FORMAT,
+			view_dimension,
+		};
+		let cube_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Probe Bake Bind Group Layout"),
+				entries: &[
+					texture_entry(
+						0,
+						wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::Cube,
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: true,
+							},
+						},
+					),
+					texture_entry(
+						1,
+						wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					),
+					texture_entry(
+						2,
+						wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+					),
+					texture_entry(
+						3,
+						storage_texture(wgpu::TextureViewDimension::D2Array),
+					),
+				],
+			});
+		let lut_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("BRDF Lookup Table Bind Group Layout"),
+				entries: &[texture_entry(
+					4,
+					storage_texture(wgpu::TextureViewDimension::D2),
+				)],
+			});
+		let source = ShaderPreprocessor::new()
+			.process(include_str!("reflection_probe.wgsl"))
+			.wrap_err("Failed to expand the includes of reflection_probe.wgsl")?;
+		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some("reflection_probe.wgsl"),
+			source: wgpu::ShaderSource::Wgsl(source.into()),
+		});
+		let create_pipeline = |layout, entry_point| {
+			let layout =
+				device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+					label: Some("Probe Bake Pipeline Layout"),
+					bind_group_layouts: &[layout],
+					push_constant_ranges: &[],
+				});
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some(entry_point),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point,
+			})
+		};
+		let irradiance_pipeline = create_pipeline(&cube_layout, "cs_irradiance");
+		let prefilter_pipeline = create_pipeline(&cube_layout, "cs_prefilter");
+		let lut_pipeline = create_pipeline(&lut_layout, "cs_brdf_lut");
+
+		// A bind group writing a mip level of `target`, with its uniform.
+		let source_size = source.texture.width();
+		let bind_mip_level = |target: &Cubemap, mip_level: u32, roughness: f32| {
+			let face_size = target.texture.width() >> mip_level;
+			let uniform = BakeUniform {
+				roughness,
+				face_size,
+				source_size,
+				_pad: 0,
+			};
+			let uniform_buf =
+				device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("Probe Bake Uniform"),
+					contents: bytemuck::bytes_of(&uniform),
+					usage: wgpu::BufferUsages::UNIFORM,
+				});
+			let view = target.texture.create_view(&wgpu::TextureViewDescriptor {
+				dimension: Some(wgpu::TextureViewDimension::D2Array),
+				base_mip_level: mip_level,
+				mip_level_count: Some(1),
+				..Default::default()
+			});
+			let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("probe_bake_bind_group"),
+				layout: &cube_layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: wgpu::BindingResource::TextureView(&source.view),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: wgpu::BindingResource::Sampler(&source.sampler),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: uniform_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 3,
+						resource: wgpu::BindingResource::TextureView(&view),
+					},
+				],
+			});
+			(bind_group, face_size)
+		};
+		let irradiance_bind_group = bind_mip_level(&irradiance_map, 0, 0.);
+		let max_mip = Self::PREFILTERED_MIP_LEVELS - 1;
+		let prefilter_bind_groups: Vec<_> = (0..Self::PREFILTERED_MIP_LEVELS)
+			.map(|mip_level| {
+				let roughness = mip_level as f32 / max_mip as f32;
+				bind_mip_level(&prefiltered_map, mip_level, roughness)
+			})
+			.collect();
+		let lut_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("brdf_lut_bind_group"),
+			layout: &lut_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 4,
+				resource: wgpu::BindingResource::TextureView(&brdf_lut_view),
+			}],
+		});
+
+		let workgroups = |size: u32| (size - 1) / Self::WORKGROUP_SIZE + 1;
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+				label: Some("Probe Bake Encoder"),
+			});
+		{
+			let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+				label: Some("Probe Bake Pass"),
+			});
+			pass.set_pipeline(&irradiance_pipeline);
+			let (bind_group, size) = &irradiance_bind_group;
+			pass.set_bind_group(0, bind_group, &[]);
+			pass.dispatch_workgroups(workgroups(*size), workgroups(*size), 6);
+
+			pass.set_pipeline(&prefilter_pipeline);
+			for (bind_group, size) in &prefilter_bind_groups {
+				pass.set_bind_group(0, bind_group, &[]);
+				pass.dispatch_workgroups(workgroups(*size), workgroups(*size), 6);
+			}
+
+			pass.set_pipeline(&lut_pipeline);
+			pass.set_bind_group(0, &lut_bind_group, &[]);
+			let size = workgroups(Self::BRDF_LUT_SIZE);
+			pass.dispatch_workgroups(size, size, 1);
+		}
+		queue.submit(Some(encoder.finish()));
+		device.poll(wgpu::Maintain::Wait);
+
+		Ok(Self {
+			irradiance_map,
+			prefiltered_map,
+			brdf_lut: brdf_lut_view,
+		})
+	}
+
+	/// The mip level of `prefiltered_map` for a roughness of 1.
+	pub fn max_mip(&self) -> u32 {
+		self.prefiltered_map.mip_level_count() - 1
+	}
+}
+
+/// An empty cubemap the bake writes, with faces `size` texels wide.
+fn create_cubemap(
+	device: &wgpu::Device,
+	size: u32,
+	mip_level_count: u32,
+	label: &str,
+) -> Cubemap {
+	let texture = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some(label),
+		size: wgpu::Extent3d {
+			width: size,
+			height: size,
+			depth_or_array_layers: 6,
+		},
+		mip_level_count,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: ReflectionProbe::FORMAT,
+		usage: wgpu::TextureUsages::TEXTURE_BINDING
+			| wgpu::TextureUsages::STORAGE_BINDING,
+		view_formats: &[],
+	});
+	let view = texture.create_view(&wgpu::TextureViewDescriptor {
+		label: Some(label),
+		dimension: Some(wgpu::TextureViewDimension::Cube),
+		..Default::default()
+	});
+	let sampler = SamplerConfig {
+		mag_filter: wgpu::FilterMode::Linear,
+		min_filter: wgpu::FilterMode::Linear,
+		mipmap_filter: wgpu::FilterMode::Linear,
+		..SamplerConfig::default()
+	}
+	.create_sampler(device);
+	Cubemap {
+		texture,
+		view,
+		sampler,
+	}
+}
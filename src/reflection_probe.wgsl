@@ -0,0 +1,177 @@
+// Bakes the maps of `reflection_probe.rs` from an environment cubemap: the
+// irradiance it lights diffuse surfaces with, its radiance pre-filtered for
+// increasing roughness, and the scale and bias of the split sum approximation.
+
+// #include "common.wgsl"
+// #include "pbr.wgsl"
+
+// Per texel of the pre-filtered map and of the BRDF lookup table.
+const SAMPLE_COUNT: u32 = 256u;
+// The grid of directions the irradiance sums over the hemisphere.
+const PHI_STEPS: u32 = 64u;
+const THETA_STEPS: u32 = 16u;
+
+struct BakeUniform {
+	// Of the pre-filtered mip level being baked.
+	roughness: f32,
+	// The width of the faces being baked, in texels.
+	face_size: u32,
+	// The width of the source's faces, in texels.
+	source_size: u32,
+};
+
+@group(0) @binding(0)
+var source_t: texture_cube<f32>;
+@group(0) @binding(1)
+var source_s: sampler;
+@group(0) @binding(2)
+var<uniform> bake: BakeUniform;
+// The faces of a mip level of the cubemap being baked.
+@group(0) @binding(3)
+var output_t: texture_storage_2d_array<rgba16float, write>;
+// By the cosine between the normal and view in x and by roughness in y.
+@group(0) @binding(4)
+var brdf_lut_t: texture_storage_2d<rgba16float, write>;
+
+// The direction through the center of texel `id.xy` of face `id.z`, in the
+// order +x, -x, +y, -y, +z, -z.
+fn texel_direction(id: vec3<u32>) -> vec3<f32> {
+	let uv = (vec2<f32>(id.xy) + 0.5) / f32(bake.face_size) * 2.0 - 1.0;
+	var dir: vec3<f32>;
+	switch id.z {
+		case 0u: {
+			dir = vec3<f32>(1.0, -uv.y, -uv.x);
+		}
+		case 1u: {
+			dir = vec3<f32>(-1.0, -uv.y, uv.x);
+		}
+		case 2u: {
+			dir = vec3<f32>(uv.x, 1.0, uv.y);
+		}
+		case 3u: {
+			dir = vec3<f32>(uv.x, -1.0, -uv.y);
+		}
+		case 4u: {
+			dir = vec3<f32>(uv.x, -uv.y, 1.0);
+		}
+		default: {
+			dir = vec3<f32>(-uv.x, -uv.y, -1.0);
+		}
+	}
+	return normalize(dir);
+}
+
+// Maps directions around z to directions around `n`.
+fn tangent_to_world(n: vec3<f32>) -> mat3x3<f32> {
+	let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(n.y) < 0.999);
+	let tangent = normalize(cross(up, n));
+	let bitangent = cross(n, tangent);
+	return mat3x3<f32>(tangent, bitangent, n);
+}
+
+// The `i`th point of the Hammersley sequence, spread evenly over the unit
+// square.
+fn hammersley(i: u32) -> vec2<f32> {
+	return vec2<f32>(f32(i) / f32(SAMPLE_COUNT), f32(reverseBits(i)) * 2.3283064e-10);
+}
+
+// A halfway vector around `n`, more likely where the GGX distribution has more
+// microfacets.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+	let a = roughness * roughness;
+	let phi = 2.0 * PI * xi.x;
+	let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+	let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+	let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+	return tangent_to_world(n) * h;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= bake.face_size || id.y >= bake.face_size {
+		return;
+	}
+	let n = texel_direction(id);
+	let basis = tangent_to_world(n);
+	// The radiance of the hemisphere around `n`, weighted by its cosine.
+	var irradiance = vec3<f32>(0.0);
+	for (var i = 0u; i < PHI_STEPS; i += 1u) {
+		let phi = (f32(i) + 0.5) / f32(PHI_STEPS) * 2.0 * PI;
+		for (var j = 0u; j < THETA_STEPS; j += 1u) {
+			let theta = (f32(j) + 0.5) / f32(THETA_STEPS) * 0.5 * PI;
+			let dir = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+			let radiance = textureSampleLevel(source_t, source_s, basis * dir, 0.0).rgb;
+			// The sine makes up for the grid being denser near the pole.
+			irradiance += radiance * cos(theta) * sin(theta);
+		}
+	}
+	irradiance *= PI / f32(PHI_STEPS * THETA_STEPS);
+	textureStore(output_t, vec2<i32>(id.xy), i32(id.z), vec4<f32>(irradiance, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= bake.face_size || id.y >= bake.face_size {
+		return;
+	}
+	// Viewed head on, so the reflections aren't stretched at grazing angles.
+	let n = texel_direction(id);
+	let v = n;
+	let source_size = f32(bake.source_size);
+	let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+	var color = vec3<f32>(0.0);
+	var total_weight = 0.0;
+	for (var i = 0u; i < SAMPLE_COUNT; i += 1u) {
+		let h = importance_sample_ggx(hammersley(i), n, bake.roughness);
+		let l = normalize(2.0 * dot(v, h) * h - v);
+		let n_dot_l = dot(n, l);
+		if n_dot_l > 0.0 {
+			// Samples covering many texels read a blurrier mip of the source, so
+			// that bright texels between samples don't show as dots.
+			let n_dot_h = max(dot(n, h), 0.0);
+			let pdf = distribution_ggx(n_dot_h, bake.roughness) / 4.0 + 0.0001;
+			let sample_solid_angle = 1.0 / (f32(SAMPLE_COUNT) * pdf + 0.0001);
+			let lod = select(
+				max(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0),
+				0.0,
+				bake.roughness == 0.0,
+			);
+			color += textureSampleLevel(source_t, source_s, l, lod).rgb * n_dot_l;
+			total_weight += n_dot_l;
+		}
+	}
+	color /= max(total_weight, 0.0001);
+	textureStore(output_t, vec2<i32>(id.xy), i32(id.z), vec4<f32>(color, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = textureDimensions(brdf_lut_t);
+	if id.x >= size.x || id.y >= size.y {
+		return;
+	}
+	let n_dot_v = (f32(id.x) + 0.5) / f32(size.x);
+	let roughness = (f32(id.y) + 0.5) / f32(size.y);
+	let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+	let n = vec3<f32>(0.0, 0.0, 1.0);
+	// Schlick's approximation of GGX, remapped for image-based lighting.
+	let k = roughness * roughness / 2.0;
+	var scale = 0.0;
+	var bias = 0.0;
+	for (var i = 0u; i < SAMPLE_COUNT; i += 1u) {
+		let h = importance_sample_ggx(hammersley(i), n, roughness);
+		let l = normalize(2.0 * dot(v, h) * h - v);
+		let n_dot_l = l.z;
+		if n_dot_l > 0.0 {
+			let n_dot_h = max(h.z, 0.0001);
+			let v_dot_h = max(dot(v, h), 0.0);
+			let g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+			let visibility = g * v_dot_h / (n_dot_h * n_dot_v);
+			let fresnel = pow(1.0 - v_dot_h, 5.0);
+			scale += (1.0 - fresnel) * visibility;
+			bias += fresnel * visibility;
+		}
+	}
+	let brdf = vec2<f32>(scale, bias) / f32(SAMPLE_COUNT);
+	textureStore(brdf_lut_t, vec2<i32>(id.xy), vec4<f32>(brdf, 0.0, 1.0));
+}
@@ -0,0 +1,245 @@
+//! Passes ordered by the textures they read and write, rather than by hand.
+//!
+//! wgpu tracks how textures are used and inserts the barriers between passes
+//! itself, so the graph only has to record the passes in a valid order.
+
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Identifies a texture of a [`RenderGraph`], see [`RenderGraph::import`] and
+/// [`RenderGraph::import_transient`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// What a pass can use while it is recorded.
+pub struct Resources<'a> {
+	pub device: &'a wgpu::Device,
+	pub queue: &'a wgpu::Queue,
+	views: &'a [&'a wgpu::TextureView],
+}
+impl<'a> Resources<'a> {
+	/// The view of the texture `id`.
+	///
+	/// # Panics
+	/// If `id` belongs to another graph.
+	pub fn view(&self, id: ResourceId) -> &'a wgpu::TextureView {
+		self.views[id.0]
+	}
+}
+
+type Exec<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &Resources) + 'a>;
+
+struct Pass<'a> {
+	name: &'static str,
+	inputs: Vec<ResourceId>,
+	outputs: Vec<ResourceId>,
+	exec: Exec<'a>,
+}
+
+/// A frame's passes, with the textures they read from and draw into.
+///
+/// A pass reading an imported texture runs after the last pass drawing into it
+/// that was added before it, and before the next one, so that textures can be
+/// read and drawn over again, such as history textures. A pass reading a
+/// transient texture runs after all passes drawing into it, whichever order they
+/// were added in. Passes drawing into the same texture run in the order they
+/// were added.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+	resources: Vec<&'a wgpu::TextureView>,
+	/// The resources added with [`Self::import_transient`].
+	transient: HashSet<ResourceId>,
+	passes: Vec<Pass<'a>>,
+	/// Indices into `passes`, in the order they run. Empty until compiled.
+	order: Vec<usize>,
+}
+impl<'a> RenderGraph<'a> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a texture the passes can use, whose contents are kept from before the
+	/// frame. Passes reading it before any pass draws into it see those.
+	pub fn import(&mut self, view: &'a wgpu::TextureView) -> ResourceId {
+		self.resources.push(view);
+		ResourceId(self.resources.len() - 1)
+	}
+
+	/// Adds a texture the passes can use, whose contents are drawn during the
+	/// frame. Passes reading it see what the last pass drawing into it drew.
+	pub fn import_transient(&mut self, view: &'a wgpu::TextureView) -> ResourceId {
+		let id = self.import(view);
+		self.transient.insert(id);
+		id
+	}
+
+	/// Adds a pass named `name`, which reads `inputs` and draws into `outputs`
+	/// when `exec` is called.
+	pub fn add_pass(
+		&mut self,
+		name: &'static str,
+		inputs: &[ResourceId],
+		outputs: &[ResourceId],
+		exec: impl FnOnce(&mut wgpu::CommandEncoder, &Resources) + 'a,
+	) {
+		self.passes.push(Pass {
+			name,
+			inputs: inputs.to_vec(),
+			outputs: outputs.to_vec(),
+			exec: Box::new(exec),
+		});
+		self.order.clear();
+	}
+
+	/// Orders the passes so that each runs after the ones it depends on. Fails if
+	/// passes depend on each other in a cycle.
+	pub fn compile(&mut self) -> Result<()> {
+		let count = self.passes.len();
+		// The passes each pass must run before.
+		let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); count];
+		let mut dependencies = vec![0; count];
+		let mut depend = |before: usize, after: usize| {
+			if before != after && !dependents[before].contains(&after) {
+				dependents[before].push(after);
+				dependencies[after] += 1;
+			}
+		};
+		let mut final_writer = HashMap::new();
+		for (i, pass) in self.passes.iter().enumerate() {
+			for &output in &pass.outputs {
+				final_writer.insert(output, i);
+			}
+		}
+		let mut last_writer = HashMap::new();
+		// The passes reading each imported texture since it was last drawn into.
+		let mut readers: HashMap<ResourceId, Vec<usize>> = HashMap::new();
+		for (i, pass) in self.passes.iter().enumerate() {
+			for input in &pass.inputs {
+				if self.transient.contains(input) {
+					if let Some(&writer) = final_writer.get(input) {
+						depend(writer, i);
+					}
+				} else {
+					if let Some(&writer) = last_writer.get(input) {
+						depend(writer, i);
+					}
+					readers.entry(*input).or_default().push(i);
+				}
+			}
+			for &output in &pass.outputs {
+				if let Some(prev) = last_writer.insert(output, i) {
+					depend(prev, i);
+				}
+				// Drawing over a texture waits for the passes reading it.
+				for reader in readers.remove(&output).into_iter().flatten() {
+					depend(reader, i);
+				}
+			}
+		}
+
+		// Kahn's algorithm, taking the earliest added pass that is ready.
+		let mut ready: BinaryHeap<Reverse<usize>> = (0..count)
+			.filter(|&i| dependencies[i] == 0)
+			.map(Reverse)
+			.collect();
+		let mut order = Vec::with_capacity(count);
+		while let Some(Reverse(i)) = ready.pop() {
+			order.push(i);
+			for &dependent in &dependents[i] {
+				dependencies[dependent] -= 1;
+				if dependencies[dependent] == 0 {
+					ready.push(Reverse(dependent));
+				}
+			}
+		}
+		if order.len() < count {
+			let cycle: Vec<_> = (0..count)
+				.filter(|&i| dependencies[i] > 0)
+				.map(|i| self.passes[i].name)
+				.collect();
+			bail!("The render graph has a cycle between the passes {cycle:?}");
+		}
+		self.order = order;
+		Ok(())
+	}
+
+	/// Records the passes into `encoder`, in order. Compiles the graph first if
+	/// needed.
+	pub fn execute(
+		mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+	) -> Result<()> {
+		if self.order.len() != self.passes.len() {
+			self.compile()?;
+		}
+		let resources = Resources {
+			device,
+			queue,
+			views: &self.resources,
+		};
+		let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+		for i in self.order {
+			let pass = passes[i].take().expect("Passes run once");
+			let _span = tracing::debug_span!("pass", name = pass.name).entered();
+			(pass.exec)(encoder, &resources);
+		}
+		Ok(())
+	}
+
+	/// The names of the passes, in the order they run once compiled.
+	pub fn pass_names(&self) -> Vec<&'static str> {
+		self.order.iter().map(|&i| self.passes[i].name).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn add_pass(
+		graph: &mut RenderGraph<'_>,
+		name: &'static str,
+		inputs: &[usize],
+		outputs: &[usize],
+	) {
+		let ids =
+			|ids: &[usize]| ids.iter().map(|&id| ResourceId(id)).collect::<Vec<_>>();
+		graph.add_pass(name, &ids(inputs), &ids(outputs), |_, _| {});
+	}
+
+	#[test]
+	fn transient_inputs_run_after_their_writers() {
+		let mut graph = RenderGraph::new();
+		graph.transient.insert(ResourceId(0));
+		add_pass(&mut graph, "post_process", &[0], &[1]);
+		add_pass(&mut graph, "scene", &[], &[0]);
+		graph.compile().unwrap();
+		assert_eq!(graph.pass_names(), ["scene", "post_process"]);
+	}
+
+	#[test]
+	fn imported_textures_can_be_read_then_drawn_over() {
+		let mut graph = RenderGraph::new();
+		// Reads last frame's history, then replaces it.
+		add_pass(&mut graph, "taa", &[0], &[1]);
+		add_pass(&mut graph, "copy_history", &[1], &[0]);
+		add_pass(&mut graph, "overlay", &[], &[1]);
+		graph.compile().unwrap();
+		assert_eq!(graph.pass_names(), ["taa", "copy_history", "overlay"]);
+	}
+
+	#[test]
+	fn cycles_are_errors() {
+		let mut graph = RenderGraph::new();
+		graph.transient.insert(ResourceId(0));
+		graph.transient.insert(ResourceId(1));
+		add_pass(&mut graph, "a", &[0], &[1]);
+		add_pass(&mut graph, "b", &[1], &[0]);
+		add_pass(&mut graph, "c", &[], &[2]);
+		assert!(graph.compile().is_err());
+	}
+}
@@ -0,0 +1,184 @@
+//! Meshes drawn in addition to `RenderState`'s own, each with its own material
+//! and transform.
+
+use nalgebra::Matrix4;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::mesh::{GpuMesh, LodMesh};
+use crate::render_state::StencilMode;
+use crate::types::mat4_to_wgsl;
+
+/// A mesh to draw, see [`RenderState::add_object`].
+///
+/// [`RenderState::add_object`]: crate::render_state::RenderState::add_object
+#[derive(Clone)]
+pub struct RenderObject {
+	pub mesh: Arc<GpuMesh>,
+	pub material: Arc<Material>,
+	/// The model matrix. Instances of `RenderState`'s own mesh aren't affected.
+	pub transform: Matrix4<f32>,
+	pub stencil: StencilMode,
+}
+
+/// A mesh drawn with the level of detail for its distance from the camera, see
+/// [`RenderState::add_lod_object`].
+///
+/// [`RenderState::add_lod_object`]: crate::render_state::RenderState::add_lod_object
+#[derive(Clone)]
+pub struct LodObject {
+	pub mesh: Arc<LodMesh>,
+	pub material: Arc<Material>,
+	/// The model matrix.
+	pub transform: Matrix4<f32>,
+}
+
+/// Size of a WGSL `mat4x4<f32>`.
+const MATRIX_SIZE: u64 = 64;
+
+/// The push constant range holding the model matrix, when the device supports
+/// push constants.
+pub(crate) const MODEL_PUSH_CONSTANT_RANGE: wgpu::PushConstantRange =
+	wgpu::PushConstantRange {
+		stages: wgpu::ShaderStages::VERTEX,
+		range: 0..MATRIX_SIZE as u32,
+	};
+
+/// The bind group of [`ObjectUniforms`], when push constants aren't supported.
+pub(crate) const OBJECT_BIND_GROUP: u32 = 3;
+
+/// Declares the `model` matrix used by `shader.wgsl`, which must be prepended to
+/// it.
+pub(crate) fn model_declaration(push_constants: bool) -> &'static str {
+	if push_constants {
+		"var<push_constant> model: mat4x4<f32>;\n"
+	} else {
+		"@group(3) @binding(0)\nvar<uniform> model: mat4x4<f32>;\n"
+	}
+}
+
+/// Sets the model matrix of the following draws. It is pushed as a push
+/// constant when there are no `uniforms`, otherwise the `index`th matrix of
+/// their last upload is bound.
+pub(crate) fn set_model<'a>(
+	render_pass: &mut wgpu::RenderPass<'a>,
+	uniforms: Option<&'a ObjectUniforms>,
+	index: usize,
+	transform: &Matrix4<f32>,
+) {
+	match uniforms {
+		Some(uniforms) => render_pass.set_bind_group(
+			OBJECT_BIND_GROUP,
+			&uniforms.bind_group,
+			&[index as u32 * uniforms.stride],
+		),
+		None => render_pass.set_push_constants(
+			MODEL_PUSH_CONSTANT_RANGE.stages,
+			0,
+			bytemuck::cast_slice(&mat4_to_wgsl(*transform)),
+		),
+	}
+}
+
+/// The model matrix of every object drawn in a frame, in a single uniform buffer.
+/// Each draw binds its own matrix with a dynamic offset. Only used when push
+/// constants aren't supported.
+pub(crate) struct ObjectUniforms {
+	buf: wgpu::Buffer,
+	bind_group_layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	/// How many matrices fit in `buf`.
+	capacity: u32,
+	/// Distance between matrices in `buf`, as offsets must be aligned.
+	stride: u32,
+}
+impl ObjectUniforms {
+	pub fn new(device: &wgpu::Device) -> Self {
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Object Bind Group Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: true,
+						min_binding_size: wgpu::BufferSize::new(MATRIX_SIZE),
+					},
+					count: None,
+				}],
+			});
+		let alignment = device.limits().min_uniform_buffer_offset_alignment;
+		let stride = ((MATRIX_SIZE as u32 - 1) / alignment + 1) * alignment;
+		let (buf, bind_group) =
+			Self::create_buffer(device, &bind_group_layout, 1, stride);
+		Self {
+			buf,
+			bind_group_layout,
+			bind_group,
+			capacity: 1,
+			stride,
+		}
+	}
+
+	fn create_buffer(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		capacity: u32,
+		stride: u32,
+	) -> (wgpu::Buffer, wgpu::BindGroup) {
+		let buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Object Uniforms"),
+			size: capacity as u64 * stride as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("object_bind_group"),
+			layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+					buffer: &buf,
+					offset: 0,
+					size: wgpu::BufferSize::new(MATRIX_SIZE),
+				}),
+			}],
+		});
+		(buf, bind_group)
+	}
+
+	pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+		&self.bind_group_layout
+	}
+
+	/// Replaces the matrices, growing the buffer if needed. Returns the number of
+	/// bytes written.
+	pub fn upload(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		transforms: &[Matrix4<f32>],
+	) -> u64 {
+		let count = transforms.len() as u32;
+		if count > self.capacity {
+			self.capacity = count.next_power_of_two();
+			(self.buf, self.bind_group) = Self::create_buffer(
+				device,
+				&self.bind_group_layout,
+				self.capacity,
+				self.stride,
+			);
+		}
+		let mut bytes = vec![0; count as usize * self.stride as usize];
+		for (chunk, transform) in
+			bytes.chunks_exact_mut(self.stride as usize).zip(transforms)
+		{
+			let matrix = mat4_to_wgsl(*transform);
+			chunk[..MATRIX_SIZE as usize]
+				.copy_from_slice(bytemuck::cast_slice(&matrix));
+		}
+		queue.write_buffer(&self.buf, 0, &bytes);
+		bytes.len() as u64
+	}
+}
@@ -1,98 +1,584 @@
-use color_eyre::{eyre::bail, eyre::eyre, eyre::WrapErr, Help, Result};
+use color_eyre::{eyre::bail, eyre::eyre, eyre::WrapErr, Result};
 use instant::Instant;
-use log::{debug, warn};
+use log::{debug, error, info, warn};
 use nalgebra::geometry::{IsometryMatrix3, Point3};
-use nalgebra::{point, vector, Vector3};
-use std::fmt::Write;
+use nalgebra::{Matrix4, Vector3};
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
+use winit::event::WindowEvent;
 use winit::window::Window;
 use winit_input_helper::WinitInputHelper;
 
-use crate::camera::Camera;
-use crate::tex2d::Tex2d;
-use crate::vertex::{Pos, Uv, Vertex};
+use crate::async_pipeline::{MakePipeline, PendingPipeline};
+use crate::bloom::BloomSettings;
+use crate::builder::RenderStateBuilder;
+use crate::camera::{reverse_z, CameraLike, CameraUniform, Ray};
+use crate::camera_path::{CameraPath, CameraPathPlayer};
+use crate::cascades::{CascadedShadowMap, N_CASCADES};
+use crate::cel_outline::CelOutlinePass;
+use crate::cloth::ClothSimulation;
+use crate::color_correction::ColorCorrectionUniform;
+use crate::color_mesh::ColorMeshes;
+use crate::compute::ComputePass;
+use crate::cubemap::Cubemap;
+use crate::debug_lines::DebugLines;
+use crate::debug_ui::DebugUi;
+use crate::decal::DecalRenderer;
+use crate::depth_of_field::DepthOfFieldPass;
+use crate::dynamic_resolution::DynamicResolutionScaler;
+use crate::ecs::{visibility_system, World};
+use crate::environment::EnvironmentMap;
+use crate::fog::FogUniform;
+use crate::gbuffer::GBuffer;
+use crate::gizmo::{GizmoAxis, GizmoRenderer};
+use crate::gltf_loader::{load_gltf, GltfScene};
+use crate::gpu_context::{create_instance, SharedGpuContext};
+use crate::gpu_culling::{CullData, GpuCuller, GpuLodCuller, MAX_CULLED_LODS};
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::FileWatcher;
+use crate::indirect::DrawCall;
+use crate::light::{self, LightUniform};
+use crate::material::{DefaultMaterial, Material, MaterialBuilder};
+use crate::memory::{GpuMemoryTracker, MemoryReport, TrackedBuffer, TrackedTexture};
+use crate::mesh::{GpuMesh, LodMesh, QUAD_INDICES, QUAD_VERTICES};
+use crate::morph::MorphedMesh;
+use crate::msaa_resolve::{MsaaResolvePass, ResolveFilter};
+use crate::obj_loader::load_obj;
+use crate::occlusion::OcclusionCuller;
+use crate::particles::{ParticlePipeline, ParticleSystem};
+use crate::perf::{FrameGraph, FrameTimer};
+use crate::picking::IdPicker;
+use crate::pipeline_cache::{descriptor_hash, PipelineCache};
+use crate::point_shadow::{
+	self, PointLightsUniform, PointShadowMap, MAX_POINT_SHADOWS,
+};
+use crate::pool::BufferPool;
+use crate::post_effects::PostEffects;
+use crate::post_process::PostProcessSettings;
+use crate::procedural_sky::ProceduralSky;
+use crate::profiler::{FrameStats, GpuProfiler};
+use crate::reflection_probe::ReflectionProbe;
+use crate::render_graph::RenderGraph;
+use crate::render_object::{
+	model_declaration, set_model, LodObject, ObjectUniforms, RenderObject,
+	MODEL_PUSH_CONSTANT_RANGE, OBJECT_BIND_GROUP,
+};
+use crate::resources::{Handle, ResourceManager};
+#[cfg(feature = "serde")]
+use crate::scene_file::SceneObject;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+use crate::scene_file::{scene_dir, Light, SceneFile};
+use crate::sdf_ao::{SdfAoPass, SdfAoSettings};
+use crate::shader;
+use crate::shadow::ShadowMap;
+use crate::skinning::{Skin, SkinnedMesh, MAX_BONES};
+use crate::skybox::{Sky, SkyboxPipeline};
+#[cfg(feature = "serde")]
+use crate::snapshot::SceneSnapshot;
+use crate::spatial::SpatialHash;
+use crate::ssao::{SsaoPass, SsaoSettings};
+use crate::ssr::SsrPass;
+use crate::stencil_decal::StencilDecalRenderer;
+use crate::taa::TaaPass;
+use crate::terrain::{Terrain, TerrainChunk};
+use crate::tex2d::{SamplerConfig, Tex2d};
+use crate::text::{TextBackend, TextRenderer};
+use crate::texture_loader::TextureLoader;
+use crate::title::{TitleFormatter, TitleInfo};
+use crate::tonemap::{ToneMapSettings, HDR_FORMAT};
+use crate::uv_animation::UvAnimation;
+use crate::vertex::{ColorVertex, Instance, SkinnedVertex, Vertex};
+use crate::viewport::{RenderPassExt, ScissorRect, Viewport};
+use crate::volumetric_fog::VolumetricFog;
+use crate::wboit::WboitPass;
+use crate::wind::WindUniform;
+
+/// The file extensions listed by [`RenderState::open_texture_dialog`]. KTX2 files
+/// are listed, but can't be decoded yet, so picking one logs an error.
+const TEXTURE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "ktx2"];
+
+#[derive(Debug)]
+pub enum RenderError {
+	/// None of the surface's formats could be configured without the surface
+	/// being lost.
+	SurfaceConfigFailed,
+}
+impl std::fmt::Display for RenderError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::SurfaceConfigFailed => write!(f, "Failed to configure the surface"),
+		}
+	}
+}
+impl std::error::Error for RenderError {}
+
+/// How to recover from [`RenderState::render`] failing, see
+/// [`RenderState::handle_surface_error`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecoveryAction {
+	/// Carry on, the next frame may succeed.
+	Skip,
+	/// Reconfigure the surface with [`RenderState::resize`].
+	Resize,
+	/// Recreate the surface with [`RenderState::recreate_surface`].
+	Recreate,
+	/// Stop rendering, as the error can't be recovered from.
+	Exit,
+}
+
+/// How the scene's fragments are combined with what is already in the target.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+	/// Fragments replace what is behind them, ignoring alpha.
+	#[default]
+	Opaque,
+	/// Fragments are blended over what is behind them by their (straight, not
+	/// premultiplied) alpha. They are depth tested but don't write depth.
+	Alpha,
+	/// Fragments are blended in any order by weighted blended order-independent
+	/// transparency, after all opaque and alpha blended geometry, see
+	/// [`WboitPass`]. They are depth tested but don't write depth. Only for
+	/// objects' materials, the mesh falls back to `Alpha`.
+	Transparent,
+}
+impl BlendMode {
+	/// The color targets of pipelines drawing into `format` textures, or into the
+	/// WBOIT targets if transparent.
+	fn color_targets(
+		self,
+		format: wgpu::TextureFormat,
+	) -> Vec<Option<wgpu::ColorTargetState>> {
+		let blend = match self {
+			Self::Opaque => wgpu::BlendState::REPLACE,
+			Self::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+			Self::Transparent => return WboitPass::accumulation_targets().to_vec(),
+		};
+		vec![Some(wgpu::ColorTargetState {
+			format,
+			blend: Some(blend),
+			// We are writing to all RGBA channels
+			write_mask: wgpu::ColorWrites::ALL,
+		})]
+	}
+}
+
+/// How a draw uses the stencil buffer. Anything but `Disabled` requires a
+/// stencil buffer, see [`RenderStateBuilder::stencil`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum StencilMode {
+	#[default]
+	Disabled,
+	/// Sets the stencil value to the reference wherever something is drawn. Depth
+	/// isn't written, so that what is later drawn in the marked area, such as a
+	/// reflection, isn't hidden by it.
+	WriteRef(u32),
+	/// Only draws where the stencil value equals the reference.
+	TestEqual(u32),
+}
+impl StencilMode {
+	fn reference(self) -> u32 {
+		match self {
+			Self::Disabled => 0,
+			Self::WriteRef(reference) | Self::TestEqual(reference) => reference,
+		}
+	}
+
+	/// `self` without its reference, which is set per draw rather than being part
+	/// of the pipeline.
+	fn pipeline_variant(self) -> Self {
+		match self {
+			Self::Disabled => Self::Disabled,
+			Self::WriteRef(_) => Self::WriteRef(0),
+			Self::TestEqual(_) => Self::TestEqual(0),
+		}
+	}
+
+	fn stencil_state(self) -> wgpu::StencilState {
+		let face = |compare, pass_op| wgpu::StencilFaceState {
+			compare,
+			fail_op: wgpu::StencilOperation::Keep,
+			depth_fail_op: wgpu::StencilOperation::Keep,
+			pass_op,
+		};
+		let face = match self {
+			Self::Disabled => return wgpu::StencilState::default(),
+			Self::WriteRef(_) => face(
+				wgpu::CompareFunction::Always,
+				wgpu::StencilOperation::Replace,
+			),
+			Self::TestEqual(_) => {
+				face(wgpu::CompareFunction::Equal, wgpu::StencilOperation::Keep)
+			}
+		};
+		wgpu::StencilState {
+			front: face,
+			back: face,
+			read_mask: !0,
+			write_mask: !0,
+		}
+	}
+}
+
+/// Where frames are rendered to.
+enum FrameTarget {
+	Window {
+		// Fields dropped in order of declaration.
+		// Surface must be dropped before window.
+		surface: wgpu::Surface,
+		window: Window,
+		/// The formats supported by `surface`, most preferred first.
+		formats: Vec<wgpu::TextureFormat>,
+		/// The present modes supported by `surface`, most preferred first.
+		present_modes: Vec<wgpu::PresentMode>,
+	},
+	/// Renders into a texture, for when there is no display.
+	Headless { texture: wgpu::Texture },
+}
 
 pub struct RenderState {
 	// Fields dropped in order of declaration.
-	// Surface must be dropped before window.
-	surface: wgpu::Surface,
-	window: Window,
-	device: wgpu::Device,
-	queue: wgpu::Queue,
+	target: FrameTarget,
+	/// Shared with the passes of the frame's [`RenderGraph`], and with the
+	/// states of other windows through `context`.
+	device: Arc<wgpu::Device>,
+	queue: Arc<wgpu::Queue>,
+	/// Holds `device` and `queue`, and created the window's surface.
+	context: Arc<SharedGpuContext>,
+	/// Describes the render target, even when it isn't a surface.
 	config: wgpu::SurfaceConfiguration,
-	pipeline: wgpu::RenderPipeline,
+	/// MSAA samples per pixel, as supported by the adapter.
+	sample_count: u32,
+	/// The multisampled color texture the scene is drawn into, and its view,
+	/// when `sample_count > 1`. It is resolved into the render target.
+	msaa_texture: Option<(TrackedTexture, wgpu::TextureView)>,
+	/// Resolves `msaa_texture` after the passes drawing into it, instead of each
+	/// of them resolving it, when a [`ResolveFilter`] is set.
+	msaa_resolve: Option<MsaaResolvePass>,
+	/// The color the scene is drawn over, as written into the frame.
+	clear_color: wgpu::Color,
+	/// The color the scene is drawn over with HDR, in linear light.
+	hdr_clear_color: [f32; 4],
+	/// Either [`Self::DEPTH_FORMAT`] or [`Self::DEPTH_STENCIL_FORMAT`].
+	depth_format: wgpu::TextureFormat,
+	depth_tex: TrackedTexture,
+	depth_view: wgpu::TextureView,
+	/// Whether depth goes from 1 at the near plane to 0 at the far plane.
+	reverse_z: bool,
+	/// Shared with the pipelines created in the background.
+	shader: Arc<wgpu::ShaderModule>,
+	/// The [`descriptor_hash`] of `shader`'s source.
+	shader_hash: u64,
+	#[cfg(feature = "hot-reload")]
+	shader_watcher: Option<FileWatcher>,
+	pipeline_layout: Arc<wgpu::PipelineLayout>,
+	/// Like `pipeline_layout`, with the skin bind group in place of the object
+	/// uniforms and no push constants.
+	skinned_pipeline_layout: Arc<wgpu::PipelineLayout>,
+	/// The variants of the main pipeline used so far, created when first needed.
+	pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+	/// Shares `pipelines` that are created from the same descriptor.
+	pipeline_cache: PipelineCache,
+	/// Whether missing variants of the pipeline are created in the background.
+	async_pipelines: bool,
+	/// The variants of `pipelines` being created in the background, with their
+	/// descriptor hashes. They are drawn with `placeholder_shader` meanwhile.
+	pending_pipelines: HashMap<PipelineKey, (u64, PendingPipeline)>,
+	/// Compiled when the first variant is created in the background.
+	placeholder_shader: Option<wgpu::ShaderModule>,
+	blend_mode: BlendMode,
+	stencil_mode: StencilMode,
+	/// Can be written to, see [`Self::upload_vertices`].
 	vtx_buf: wgpu::Buffer,
 	idx_buf: wgpu::Buffer,
 	num_indices: u32,
-	diffuse_bind_group: wgpu::BindGroup,
-	camera: Camera,
+	/// Streams geometry into `vtx_buf`, `idx_buf` and `debug_lines`.
+	staging_belt: wgpu::util::StagingBelt,
+	/// Buffers that are reused rather than created for every use.
+	buffer_pool: BufferPool,
+	instance_buf: wgpu::Buffer,
+	num_instances: u32,
+	/// Replace drawing all of the mesh's indices and instances, with their count.
+	indirect_draws: Option<(wgpu::Buffer, u32)>,
+	/// Culls the mesh's instances for the main camera before it is drawn, taking
+	/// precedence over `indirect_draws`.
+	gpu_culling: Option<GpuCuller>,
+	/// An instanced mesh and its material, whose instances are culled and given
+	/// their level of detail by [`Self::dispatch_cull_lod`].
+	cull_lod: Option<(Arc<LodMesh>, Arc<Material>, GpuLodCuller)>,
+	/// How many instances fit in `instance_buf`.
+	instance_capacity: u32,
+	/// Holds the textures below, and those added through
+	/// [`Self::resources_mut`].
+	resources: ResourceManager,
+	diffuse_tex: Handle<Tex2d>,
+	normal_map: Handle<Tex2d>,
+	/// Black, as the mesh has no specular highlights. Also used by
+	/// [`Self::create_material`].
+	specular_map: Handle<Tex2d>,
+	/// Loads images to replace `diffuse_tex` with in the background.
+	texture_loader: TextureLoader,
+	/// See [`Material::layout`].
+	material_bind_group_layout: wgpu::BindGroupLayout,
+	/// The mesh's material. Its blend mode is ignored in favor of `blend_mode`.
+	material: Material,
+	camera: Box<dyn CameraLike>,
+	/// The camera's view before the last simulation tick, for interpolation.
+	prev_view: IsometryMatrix3<f32>,
 	camera_buf: wgpu::Buffer,
+	camera_bind_group_layout: wgpu::BindGroupLayout,
 	camera_bind_group: wgpu::BindGroup,
+	/// Where `camera` draws into, if not the whole frame.
+	viewport: Option<Viewport>,
+	/// Limits what `camera` draws, if set. Otherwise it is limited to `viewport`.
+	scissor: Option<ScissorRect>,
+	/// Drawn after the scene seen from `camera`, in the same pass.
+	views: Vec<View>,
+	/// The contents of `light_buf`.
+	light: LightUniform,
+	light_buf: wgpu::Buffer,
+	light_bind_group_layout: wgpu::BindGroupLayout,
+	/// Binds `light_buf`, `shadow_map`, `fog_buf`, `environment_map`,
+	/// `point_shadows` and the occlusion of `sdf_ao` or `ssao`.
+	light_bind_group: wgpu::BindGroup,
+	/// The contents of `fog_buf`, once uploaded.
+	fog: FogUniform,
+	fog_buf: wgpu::Buffer,
+	/// Whether `fog` changed since it was last uploaded.
+	fog_dirty: bool,
+	/// The contents of `uv_animation_buf`, advanced every simulation tick.
+	uv_animation: UvAnimation,
+	uv_animation_buf: wgpu::Buffer,
+	/// The contents of `wind_buf`, advanced every simulation tick.
+	wind: WindUniform,
+	wind_buf: wgpu::Buffer,
+	/// The contents of `color_correction_buf`.
+	color_correction: ColorCorrectionUniform,
+	color_correction_buf: wgpu::Buffer,
+	shadow_map: ShadowMap,
+	/// Replaces `shadow_map` while enabled. Its cascades are a single texel wide
+	/// while disabled.
+	cascaded_shadows: CascadedShadowMap,
+	/// The point lights, each casting shadows into a cubemap of its own.
+	point_shadows: Vec<PointShadowMap>,
+	/// The lights of `point_shadows`, as a `PointLightsUniform`.
+	point_lights_buf: wgpu::Buffer,
+	/// Bound in place of the point shadow maps that aren't there.
+	no_point_shadow: wgpu::TextureView,
+	/// Reflected by PBR materials.
+	environment_map: EnvironmentMap,
+	/// Darkens the ambient light in creases and corners, while enabled.
+	ssao: Option<SsaoPass>,
+	/// Ambient occlusion from a distance field of the objects, used instead of
+	/// `ssao`'s while both are enabled.
+	sdf_ao: Option<SdfAoPass>,
+	/// The depth and normals of the opaque geometry, while SSAO or SSR read them.
+	gbuffer: Option<GBuffer>,
+	/// Bound in place of the occlusion while SSAO is disabled.
+	no_occlusion: Tex2d,
+	/// Drawn after the mesh, in [`Self::draw_order`].
+	render_queue: Vec<RenderObject>,
+	/// The objects of `render_queue` added from scene files, by their index, to
+	/// save them again.
+	#[cfg(feature = "serde")]
+	scene_objects: Vec<(usize, SceneObject)>,
+	/// The boxes of `render_queue`, rebuilt every frame to find those the main
+	/// camera sees.
+	spatial_hash: SpatialHash,
+	/// Whether each object of `render_queue` is in the main camera's frustum, as
+	/// of the last frame.
+	visible_objects: Vec<bool>,
+	/// Drawn after `render_queue`, each with the level of detail for its
+	/// distance from the main camera.
+	lod_objects: Vec<LodObject>,
+	/// Drawn after the other objects, only in the frame of [`Self::render_mt`].
+	frame_objects: Vec<RenderObject>,
+	/// Its entities are drawn after the level of detail objects, with the meshes
+	/// and materials of `resources`.
+	world: World,
+	/// Created with the first decal. Projects the decals queued during the frame
+	/// onto the scene after the main pass.
+	decals: Option<DecalRenderer>,
+	/// Created with the first stencil decal. Projects the stencil decals queued
+	/// during the frame onto the scene after the decals.
+	stencil_decals: Option<StencilDecalRenderer>,
+	/// Recorded earlier in the frame than the encoder, to be submitted before it.
+	pending_commands: Vec<wgpu::CommandBuffer>,
+	/// Only exists when the device doesn't support push constants.
+	object_uniforms: Option<ObjectUniforms>,
+	/// A single identity instance, for drawing `render_queue`.
+	identity_instance_buf: wgpu::Buffer,
+	/// Drawn behind everything, if set.
+	skybox: Option<Sky>,
+	/// Skips the objects hidden in earlier frames, if enabled.
+	occlusion: Option<OcclusionCuller>,
+	/// The index of the object of `render_queue` at each pixel, for
+	/// `pick_at`. Created by the first `request_picking`.
+	picking: Option<IdPicker>,
+	/// Whether the next frame draws `picking`.
+	picking_requested: bool,
+	/// Dispatched at the start of every frame, with their workgroup counts.
+	compute_passes: Vec<(ComputePass, [u32; 3])>,
+	/// Blended after the compute passes, every frame.
+	morphed_meshes: Vec<MorphedMesh>,
+	/// Stepped after the morphed meshes, every frame.
+	cloths: Vec<ClothSimulation>,
+	/// Simulated after the compute passes, and drawn after the objects.
+	particles: Option<(ParticleSystem, ParticlePipeline)>,
+	/// Drawn after the mesh, in the chunks the main camera sees.
+	terrain: Option<Terrain>,
+	/// Drawn after the objects. Created with the first mesh.
+	color_meshes: Option<ColorMeshes>,
+	/// The skeleton moving `skinned_meshes`.
+	skin: Skin,
+	/// Drawn after the color meshes, with the mesh's material.
+	skinned_meshes: Vec<SkinnedMesh>,
+	/// Blends the objects with [`BlendMode::Transparent`] over the scene, after
+	/// everything else. Created with the first frame drawing one.
+	wboit: Option<WboitPass>,
+	/// Draws the scene, as drawn into the target of the first, into the frame.
+	post_effects: PostEffects,
+	/// The number of frames rendered, which picks the jitter of temporal
+	/// anti-aliasing.
+	frame_count: u64,
+	/// Draws outlines over the opaque scene, before transparent objects, while
+	/// enabled.
+	cel_outline: Option<CelOutlinePass>,
+	/// Blends fog lit by the light over the scene, after everything else, while
+	/// enabled.
+	volumetric_fog: Option<VolumetricFog>,
+	/// With HDR, the scene is drawn in [`HDR_FORMAT`] and then tone mapped into
+	/// the frame by `post_effects`.
+	hdr: bool,
+	/// Picks the resolution the scene is drawn at, while dynamic resolution is
+	/// enabled. `post_effects` then upscales the scene into the frame.
+	resolution_scaler: Option<DynamicResolutionScaler>,
+	profiler: Option<GpuProfiler>,
+	/// Drawn over the frame and cleared at the end of every frame.
+	debug_lines: DebugLines,
+	/// Drawn over the frame at `gizmo_at`'s center, transform and scale.
+	gizmo: GizmoRenderer,
+	gizmo_at: Option<(Point3<f32>, Matrix4<f32>, f32)>,
+	/// Only exists when rendering to a window.
+	debug_ui: Option<DebugUi>,
+	/// Frames in a row that failed because the surface was lost.
+	lost_frames: u32,
+	frame_timer: FrameTimer,
+	frame_graph: FrameGraph,
+	/// Whether `frame_graph` is drawn over the frame.
+	show_frame_graph: bool,
+	/// Draws the text queued during the frame over it.
+	text_renderer: TextRenderer,
+	/// Physical pixels per logical pixel of the window, 1 when headless.
+	scale_factor: f32,
+	/// Whether the frame rate is drawn in the bottom left corner.
+	show_fps: bool,
+	/// Stats of the frame currently being prepared.
+	frame_stats: FrameStats,
+	last_frame_stats: FrameStats,
 	fps: f32,
 	last_render: Instant,
 	last_title: Instant,
+	/// Counts the memory of the resources created through `RenderState`.
+	memory_tracker: GpuMemoryTracker,
+	last_memory_report: Instant,
 	title: String,
+	title_formatter: TitleFormatter,
+	adapter_info: wgpu::AdapterInfo,
 }
 impl RenderState {
-	pub async fn new(window: Window) -> Result<Self> {
-		let size = window.inner_size();
+	pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+	/// The depth format when there is a stencil buffer.
+	pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat =
+		wgpu::TextureFormat::Depth24PlusStencil8;
+	/// Size of the buffers geometry is streamed through. Larger uploads get
+	/// buffers of their own.
+	const STAGING_CHUNK_SIZE: u64 = 1 << 20;
+	/// Pooled buffers that aren't reused for this many frames are destroyed.
+	const BUFFER_POOL_IDLE_FRAMES: u64 = 60;
+	/// Width of the cells of `spatial_hash`, about the size of an object.
+	const SPATIAL_CELL_SIZE: f32 = 8.;
+	/// Lost surfaces are reconfigured this many frames in a row before being
+	/// recreated.
+	pub const MAX_LOST_FRAMES: u32 = 3;
 
-		let backends =
-			wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
-		let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-			backends,
-			dx12_shader_compiler: Default::default(),
-		});
+	/// Creates a `RenderState` drawing to `window`, with `sample_count` samples
+	/// per pixel for MSAA. The sample count is lowered to one the adapter
+	/// supports, if needed. See [`RenderStateBuilder`] for more options.
+	pub async fn new(window: Window, sample_count: u32) -> Result<Self> {
+		RenderStateBuilder::new()
+			.sample_count(sample_count)
+			.build(window)
+			.await
+	}
 
-		debug!(
-			"Available wgpu adapters: {:#?}",
-			instance
-				.enumerate_adapters(backends)
-				.map(|a| a.get_info())
-				.collect::<Vec<_>>()
-		);
+	/// Creates a `RenderState` that renders into a texture instead of a window,
+	/// for use without a display, such as in tests. Read frames back with
+	/// [`Self::capture_screenshot`].
+	pub async fn new_headless(
+		width: u32,
+		height: u32,
+		sample_count: u32,
+	) -> Result<Self> {
+		RenderStateBuilder::new()
+			.sample_count(sample_count)
+			.build_headless(width, height)
+			.await
+	}
 
+	/// Creates a `RenderState` drawing to `window` with the device of `context`,
+	/// which it shares with the states of other windows.
+	pub fn new_with_context(
+		window: Window,
+		context: Arc<SharedGpuContext>,
+	) -> Result<Self> {
+		RenderStateBuilder::new().build_with_context(window, context)
+	}
+
+	pub(crate) async fn from_builder(
+		window: Window,
+		options: &RenderStateBuilder,
+	) -> Result<Self> {
+		let instance = create_instance();
 		// Safety: we store both `window` and `surface` in `State` so we can be sure that `surface`
 		// is dropped first.
 		let surface = unsafe { instance.create_surface(&window) }?;
+		let context =
+			SharedGpuContext::request(instance, options, Some(&surface)).await?;
+		Self::with_surface(window, surface, Arc::new(context), options)
+	}
 
-		let adapter = instance
-			.request_adapter(&wgpu::RequestAdapterOptions {
-				power_preference: wgpu::PowerPreference::LowPower,
-				force_fallback_adapter: false,
-				/// Surface that is required to be presentable with the requested adapter. This does not
-				/// create the surface, only guarantees that the adapter can present to said surface.
-				compatible_surface: Some(&surface),
-			})
-			.await
-			.ok_or(eyre!("Failed to get a wgpu Adapter"))?;
+	pub(crate) fn context_from_builder(
+		window: Window,
+		context: Arc<SharedGpuContext>,
+		options: &RenderStateBuilder,
+	) -> Result<Self> {
+		// Safety: as in `from_builder`.
+		let surface = unsafe { context.instance.create_surface(&window) }?;
+		Self::with_surface(window, surface, context, options)
+	}
+
+	/// Configures `surface`, created for `window` by the instance of `context`.
+	fn with_surface(
+		window: Window,
+		surface: wgpu::Surface,
+		context: Arc<SharedGpuContext>,
+		options: &RenderStateBuilder,
+	) -> Result<Self> {
+		let size = window.inner_size();
+		let adapter = &context.adapter;
 		if !adapter.is_surface_supported(&surface) {
 			bail!("Adapter does not support surface!");
 		}
-		debug!("Chosen adapter: {:#?}", adapter.get_info());
-
-		let (device, queue) = {
-			let limits = if cfg!(target_arch = "wasm32") {
-				wgpu::Limits::downlevel_webgl2_defaults()
-			} else {
-				wgpu::Limits::downlevel_defaults()
-			};
-			let desc = wgpu::DeviceDescriptor {
-				label: None,
-				features: wgpu::Features::empty(),
-				limits,
-			};
-			adapter
-				.request_device(&desc, None)
-				.await
-				.wrap_err("Failed to get wgpu Device")
-				.with_note(|| format!("WGPU Adapter was: {:#?}", adapter.get_info()))?
-		};
+		let device = &context.device;
 
-		let config = {
-			// NOTE: all capabilities have the most preferred option as the 0th element.
-			let caps = surface.get_capabilities(&adapter);
+		// NOTE: all capabilities have the most preferred option as the 0th element.
+		let caps = surface.get_capabilities(adapter);
+		let mut config = {
 			let format = caps
 				.formats
 				.iter()
@@ -103,282 +589,5970 @@ impl RenderState {
 					warn!("GPU doesn't support sRGB, colors might not be as expected!");
 					caps.formats[0]
 				});
+			let present_mode = match options.present_mode {
+				Some(mode) if caps.present_modes.contains(&mode) => mode,
+				Some(mode) => {
+					let fallback = caps.present_modes[0];
+					warn!("Present mode {mode:?} is not supported, using {fallback:?}");
+					fallback
+				}
+				None => caps.present_modes[0],
+			};
 			wgpu::SurfaceConfiguration {
 				// This lets the texture write to the screen (?)
 				usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
 				format,
 				width: size.width,
 				height: size.height,
-				present_mode: caps.present_modes[0],
+				present_mode,
 				alpha_mode: caps.alpha_modes[0],
 				view_formats: vec![],
 			}
 		};
-		surface.configure(&device, &config);
+		Self::configure_surface(&surface, device, &mut config, &caps.formats)?;
+		let sample_count = supported_sample_count(
+			adapter,
+			device,
+			options.color_format(config.format),
+			options.depth_format(),
+			options.sample_count,
+		);
 
-		let diffuse_tex = Tex2d::new_from_img_bytes(
-			&device,
-			&queue,
-			include_bytes!("tree.png"),
-			Some("Diffuse Texture"),
-		);
-		let tex_bind_group_layout = Tex2d::layout(&device);
-		let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			label: Some("diffuse_bind_group"),
-			layout: &tex_bind_group_layout,
-			entries: &[
-				wgpu::BindGroupEntry {
-					binding: 0,
-					resource: wgpu::BindingResource::TextureView(&diffuse_tex.view),
-				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: wgpu::BindingResource::Sampler(&diffuse_tex.sampler),
-				},
-			],
-		});
+		let debug_ui = DebugUi::new(&window, device, config.format);
+		let target = FrameTarget::Window {
+			surface,
+			window,
+			formats: caps.formats,
+			present_modes: caps.present_modes,
+		};
+		Self::with_target(
+			context,
+			config,
+			sample_count,
+			options,
+			target,
+			Some(debug_ui),
+		)
+	}
 
-		let camera = {
-			// to_radians() wasn't const yet :(
-			const FOVY: f32 = 45.0 / 180.0 * std::f32::consts::PI;
-			const ZNEAR: f32 = 0.1;
-			const ZFAR: f32 = 100.0;
-			const EYE: Point3<f32> = point![0., 0., 1.];
-			const ORIGIN: Point3<f32> = point![0., 0., 0.];
-			const UP: Vector3<f32> = vector![0., 1., 0.];
-			Camera {
-				view: IsometryMatrix3::look_at_rh(&EYE, &ORIGIN, &UP),
-				proj: nalgebra::geometry::Perspective3::new(
-					config.width as f32 / config.height as f32,
-					FOVY,
-					ZNEAR,
-					ZFAR,
-				),
-				speed: 0.2,
-			}
+	pub(crate) async fn headless_from_builder(
+		width: u32,
+		height: u32,
+		options: &RenderStateBuilder,
+	) -> Result<Self> {
+		let context =
+			SharedGpuContext::request(create_instance(), options, None).await?;
+		Self::headless_context_from_builder(Arc::new(context), width, height, options)
+	}
+
+	pub(crate) fn headless_context_from_builder(
+		context: Arc<SharedGpuContext>,
+		width: u32,
+		height: u32,
+		options: &RenderStateBuilder,
+	) -> Result<Self> {
+		let device = &context.device;
+
+		let config = wgpu::SurfaceConfiguration {
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::COPY_SRC,
+			format: wgpu::TextureFormat::Rgba8UnormSrgb,
+			width,
+			height,
+			// Not used, as there is no surface.
+			present_mode: wgpu::PresentMode::Fifo,
+			alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+			view_formats: vec![],
 		};
-		let camera_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Camera Uniform"),
-			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-			contents: bytemuck::cast_slice(&[camera.proj_view()]),
-		});
-		let camera_bind_group_layout =
-			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-				label: Some("Camera Bind Group Layout"),
-				entries: &[wgpu::BindGroupLayoutEntry {
-					binding: 0,
-					visibility: wgpu::ShaderStages::VERTEX,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						// We could specify this for more performance, but meh
-						min_binding_size: None,
-					},
-					count: None,
-				}],
-			});
-		let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			label: Some("camera_bind_group"),
-			layout: &camera_bind_group_layout,
-			entries: &[wgpu::BindGroupEntry {
-				binding: 0,
-				resource: camera_buf.as_entire_binding(),
-			}],
+		let sample_count = supported_sample_count(
+			&context.adapter,
+			device,
+			options.color_format(config.format),
+			options.depth_format(),
+			options.sample_count,
+		);
+		let target = FrameTarget::Headless {
+			texture: create_target_texture(device, &config),
+		};
+		Self::with_target(context, config, sample_count, options, target, None)
+	}
+
+	/// The rest of the initialization, shared by all render targets.
+	fn with_target(
+		context: Arc<SharedGpuContext>,
+		config: wgpu::SurfaceConfiguration,
+		sample_count: u32,
+		options: &RenderStateBuilder,
+		target: FrameTarget,
+		debug_ui: Option<DebugUi>,
+	) -> Result<Self> {
+		let adapter_info = context.adapter.get_info();
+		let device = context.device.clone();
+		let queue = context.queue.clone();
+		let depth_format = options.depth_format();
+		let color_format = options.color_format(config.format);
+		let memory_tracker = GpuMemoryTracker::new();
+		let msaa_texture = create_msaa_texture(
+			&device,
+			&memory_tracker,
+			(config.width, config.height),
+			color_format,
+			sample_count,
+		);
+		let (depth_tex, depth_view) = create_depth_texture(
+			&device,
+			&memory_tracker,
+			(config.width, config.height),
+			sample_count,
+			depth_format,
+		);
+		let msaa_resolve = options.resolve_filter.and_then(|filter| {
+			MsaaResolvePass::for_target(
+				&device,
+				&queue,
+				color_format,
+				(config.width, config.height),
+				sample_count,
+				filter,
+			)
 		});
 
-		let pipeline = {
-			// Can also use `include_wgsl!()`
-			let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-				label: Some("shader.wgsl"),
-				source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-			});
+		let mut resources = ResourceManager::new();
+		let DefaultMaterial {
+			layout: material_bind_group_layout,
+			material,
+			diffuse_tex,
+			normal_map,
+			specular_map,
+		} = DefaultMaterial::new(&device, &queue, &mut resources);
 
-			let pipeline_layout =
-				device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-					label: Some("Render Pipeline Layout"),
-					bind_group_layouts: &[
-						&tex_bind_group_layout,
-						&camera_bind_group_layout,
-					],
-					push_constant_ranges: &[],
-				});
+		let camera = options.default_camera(config.width as f32 / config.height as f32);
+		let camera_buf = create_uniform_buffer(
+			&device,
+			"Camera Uniform",
+			&CameraUniform::from_view(&camera, &camera.view, options.use_reverse_z),
+		);
+		let camera_bind_group_layout = CameraUniform::create_bind_group_layout(&device);
+		let camera_bind_group = CameraUniform::create_bind_group(
+			&device,
+			&camera_bind_group_layout,
+			&camera_buf,
+			"camera_bind_group",
+		);
 
-			device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-				label: Some("Render Pipeline"),
-				layout: Some(&pipeline_layout),
-				vertex: wgpu::VertexState {
-					module: &shader,
-					entry_point: "vs_main",
-					buffers: &[Vertex::vb_layout()],
-				},
-				fragment: Some(wgpu::FragmentState {
-					module: &shader,
-					entry_point: "fs_main",
-					targets: &[Some(wgpu::ColorTargetState {
-						// Shader texture format will be same as what we configured earlier
-						format: config.format,
-						// Blend will simply replace old pixel data with new
-						blend: Some(wgpu::BlendState::REPLACE),
-						// We are writing to all RGBA channels
-						write_mask: wgpu::ColorWrites::ALL,
-					})],
-				}),
-				primitive: wgpu::PrimitiveState {
-					topology: wgpu::PrimitiveTopology::TriangleList,
-					strip_index_format: None,
-					front_face: wgpu::FrontFace::Ccw,
-					cull_mode: Some(wgpu::Face::Back),
-					// The next three avoid needing additional features
-					unclipped_depth: false,
-					polygon_mode: wgpu::PolygonMode::Fill,
-					conservative: false,
-				},
-				depth_stencil: None,
-				// We won't be using multisampling, so do 1x
-				multisample: wgpu::MultisampleState {
-					count: 1,
-					mask: !0,
-					alpha_to_coverage_enabled: false,
-				},
-				// I don't understand this one, but the tutorial set it to `None`
-				multiview: None,
-			})
-		};
+		let light = LightUniform::default();
+		let light_buf = create_uniform_buffer(&device, "Light Uniform", &light);
+		let light_bind_group_layout = light::create_bind_group_layout(&device);
+		let fog = FogUniform::default();
+		let fog_buf = create_uniform_buffer(&device, "Fog Uniform", &fog);
+		let uv_animation = UvAnimation::default();
+		let uv_animation_buf =
+			create_uniform_buffer(&device, "UV Animation Uniform", &uv_animation);
+		let wind = WindUniform::default();
+		let wind_buf = create_uniform_buffer(&device, "Wind Uniform", &wind);
+		let color_correction = ColorCorrectionUniform::default();
+		let color_correction_buf = create_uniform_buffer(
+			&device,
+			"Color Correction Uniform",
+			&color_correction,
+		);
 
-		// Describes a square.
-		const VERTICES: &[Vertex] = &[
-			// Starts at top left of square, goes Ccw
-			Vertex::new(Pos::new(-0.5, 0.5, 0.0), Uv { u: 0.0, v: 0.0 }),
-			Vertex::new(Pos::new(-0.5, -0.5, 0.0), Uv { u: 0.0, v: 1.0 }),
-			Vertex::new(Pos::new(0.5, -0.5, 0.0), Uv { u: 1.0, v: 1.0 }),
-			Vertex::new(Pos::new(0.5, 0.5, 0.0), Uv { u: 1.0, v: 0.0 }),
-		];
+		let push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS);
+		let object_uniforms = (!push_constants).then(|| ObjectUniforms::new(&device));
 
-		const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
+		let (shader, shader_hash) =
+			shader::create_main_shader(&device, push_constants)?;
 
-		let vtx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Vertex Buffer"),
-			contents: bytemuck::cast_slice(VERTICES),
-			usage: wgpu::BufferUsages::VERTEX,
-		});
+		let push_constant_ranges = match object_uniforms {
+			Some(_) => vec![],
+			None => vec![MODEL_PUSH_CONSTANT_RANGE],
+		};
+		let skin = Skin::new(&device, &queue);
+		let (pipeline_layout, skinned_pipeline_layout) = create_pipeline_layouts(
+			&device,
+			[
+				&material_bind_group_layout,
+				&camera_bind_group_layout,
+				&light_bind_group_layout,
+			],
+			object_uniforms.as_ref(),
+			&push_constant_ranges,
+			&skin,
+		);
 
-		let idx_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Index Buffer"),
-			contents: bytemuck::cast_slice(INDICES),
-			usage: wgpu::BufferUsages::INDEX,
-		});
+		let mut shadow_map = ShadowMap::new(
+			&device,
+			ShadowMap::DEFAULT_SIZE,
+			&shader,
+			&camera_bind_group_layout,
+			object_uniforms.as_ref().map(|u| u.bind_group_layout()),
+			&push_constant_ranges,
+		);
+		shadow_map.set_direction(&queue, Vector3::from(light.direction));
+		let cascaded_shadows = CascadedShadowMap::new(
+			&device,
+			1,
+			&shader,
+			&camera_bind_group_layout,
+			object_uniforms.as_ref().map(|u| u.bind_group_layout()),
+			&push_constant_ranges,
+		);
+		let point_lights_buf = create_uniform_buffer(
+			&device,
+			"Point Lights Uniform",
+			&PointLightsUniform::new(&[]),
+		);
+		let no_point_shadow = point_shadow::create_placeholder(&device);
+		let environment_map = EnvironmentMap::new(&device, &queue);
+		let no_occlusion =
+			Tex2d::from_color(&device, &queue, Some("No Occlusion"), [255; 4]);
+		let light_bind_group = light::create_bind_group(
+			&device,
+			&light_bind_group_layout,
+			&light_buf,
+			&shadow_map,
+			&cascaded_shadows,
+			&fog_buf,
+			&environment_map,
+			&no_occlusion.view,
+			&uv_animation_buf,
+			&wind_buf,
+			&color_correction_buf,
+			&point_lights_buf,
+			&[],
+			&no_point_shadow,
+		);
+
+		let GpuMesh {
+			vtx_buf,
+			idx_buf,
+			num_indices,
+			..
+		} = GpuMesh::new(&device, QUAD_VERTICES, QUAD_INDICES);
+		let instance_buf = create_instance_buffer(&device, 1);
+		queue.write_buffer(
+			&instance_buf,
+			0,
+			bytemuck::bytes_of(&Instance::new(Matrix4::identity())),
+		);
+		let identity_instance_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Identity Instance Buffer"),
+				contents: bytemuck::bytes_of(&Instance::new(Matrix4::identity())),
+				usage: wgpu::BufferUsages::VERTEX,
+			});
+
+		let profiler = GpuProfiler::new(&device, &queue);
+		let mut post_effects = PostEffects::new(config.format, options.use_reverse_z);
+		if options.hdr {
+			post_effects.enable_tone_mapping(
+				&device,
+				&queue,
+				(config.width, config.height),
+			);
+		}
+		let frame_graph = FrameGraph::new(&device, config.format);
+		let scale_factor = match &target {
+			FrameTarget::Window { window, .. } => window.scale_factor() as f32,
+			FrameTarget::Headless { .. } => 1.,
+		};
+		let mut text_renderer = TextRenderer::new(
+			&device,
+			&queue,
+			config.format,
+			config.width,
+			config.height,
+			TextBackend::Raster,
+		);
+		text_renderer.set_scale_factor(scale_factor);
+		let debug_lines =
+			DebugLines::new(&device, config.format, &camera_bind_group_layout);
+		let gizmo =
+			GizmoRenderer::new(&device, config.format, &camera_bind_group_layout);
 
 		Ok(Self {
-			surface,
-			window,
+			target,
 			device,
 			queue,
+			context,
 			config,
-			pipeline,
+			sample_count,
+			clear_color: options.clear_color,
+			hdr_clear_color: linear_clear_color(options.clear_color, config.format),
+			msaa_texture,
+			msaa_resolve,
+			depth_format,
+			depth_tex,
+			depth_view,
+			reverse_z: options.use_reverse_z,
+			shader: Arc::new(shader),
+			shader_hash,
+			#[cfg(feature = "hot-reload")]
+			shader_watcher: FileWatcher::new(concat!(
+				env!("CARGO_MANIFEST_DIR"),
+				"/src/shader.wgsl"
+			))
+			.map_err(|err| warn!("Shader hot reloading disabled: {err:#}"))
+			.ok(),
+			pipeline_layout: Arc::new(pipeline_layout),
+			skinned_pipeline_layout: Arc::new(skinned_pipeline_layout),
+			pipelines: HashMap::new(),
+			pipeline_cache: PipelineCache::default(),
+			async_pipelines: false,
+			pending_pipelines: HashMap::new(),
+			placeholder_shader: None,
+			blend_mode: BlendMode::default(),
+			stencil_mode: StencilMode::default(),
 			vtx_buf,
 			idx_buf,
-			num_indices: INDICES.len() as u32,
-			diffuse_bind_group,
-			camera,
+			num_indices,
+			staging_belt: wgpu::util::StagingBelt::new(Self::STAGING_CHUNK_SIZE),
+			buffer_pool: BufferPool::new(Self::BUFFER_POOL_IDLE_FRAMES),
+			instance_buf,
+			num_instances: 1,
+			indirect_draws: None,
+			gpu_culling: None,
+			cull_lod: None,
+			instance_capacity: 1,
+			resources,
+			diffuse_tex,
+			normal_map,
+			specular_map,
+			texture_loader: TextureLoader::new(),
+			material_bind_group_layout,
+			material,
+			prev_view: camera.view,
+			camera: Box::new(camera),
 			camera_buf,
+			camera_bind_group_layout,
 			camera_bind_group,
+			viewport: None,
+			scissor: None,
+			views: Vec::new(),
+			light,
+			light_buf,
+			light_bind_group_layout,
+			light_bind_group,
+			fog,
+			fog_buf,
+			fog_dirty: false,
+			uv_animation,
+			uv_animation_buf,
+			wind,
+			wind_buf,
+			color_correction,
+			color_correction_buf,
+			shadow_map,
+			cascaded_shadows,
+			point_shadows: Vec::new(),
+			point_lights_buf,
+			no_point_shadow,
+			environment_map,
+			ssao: None,
+			sdf_ao: None,
+			gbuffer: None,
+			no_occlusion,
+			render_queue: Vec::new(),
+			#[cfg(feature = "serde")]
+			scene_objects: Vec::new(),
+			spatial_hash: SpatialHash::new(Self::SPATIAL_CELL_SIZE),
+			visible_objects: Vec::new(),
+			lod_objects: Vec::new(),
+			frame_objects: Vec::new(),
+			world: World::new(),
+			decals: None,
+			stencil_decals: None,
+			pending_commands: Vec::new(),
+			object_uniforms,
+			identity_instance_buf,
+			skybox: None,
+			occlusion: None,
+			picking: None,
+			picking_requested: false,
+			compute_passes: Vec::new(),
+			morphed_meshes: Vec::new(),
+			cloths: Vec::new(),
+			particles: None,
+			terrain: None,
+			color_meshes: None,
+			skin,
+			skinned_meshes: Vec::new(),
+			wboit: None,
+			post_effects,
+			frame_count: 0,
+			cel_outline: None,
+			volumetric_fog: None,
+			hdr: options.hdr,
+			resolution_scaler: None,
+			profiler,
+			debug_lines,
+			gizmo,
+			gizmo_at: None,
+			debug_ui,
+			lost_frames: 0,
+			frame_timer: FrameTimer::new(),
+			frame_graph,
+			show_frame_graph: false,
+			text_renderer,
+			scale_factor,
+			show_fps: false,
+			frame_stats: FrameStats::default(),
+			last_frame_stats: FrameStats::default(),
 			fps: 0.,
 			last_render: Instant::now(),
 			last_title: Instant::now(),
+			memory_tracker,
+			last_memory_report: Instant::now(),
 			title: String::new(),
+			title_formatter: TitleFormatter::default(),
+			adapter_info,
 		})
 	}
 
-	pub fn update(&mut self, input: &WinitInputHelper) {
+	/// Applies this frame's mouse look, then steps the simulation `n` times by
+	/// `dt` seconds each.
+	pub fn run_simulation_ticks(&mut self, n: u32, dt: f32, input: &WinitInputHelper) {
 		self.camera.update(input);
-		self.queue.write_buffer(
-			&self.camera_buf,
-			0,
-			bytemuck::cast_slice(&[self.camera.proj_view()]),
-		);
+		for _ in 0..n {
+			self.prev_view = self.camera.view();
+			self.camera.step(input, dt);
+			self.update_uv_animation(dt);
+			self.update_wind(dt);
+		}
 	}
 
-	pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-		// Do fps calculation
-		{
-			// Values closer to 1 weight new values more.
-			const SMOOTHING_FACTOR: f32 = 0.2;
-			let now = Instant::now();
-			let elapsed = now - self.last_render;
-			let new_fps = 1.0 / elapsed.as_secs_f32();
-			self.fps = self.fps * (1.0 - SMOOTHING_FACTOR) + new_fps * SMOOTHING_FACTOR;
-			self.last_render = now;
+	/// Scrolls the UVs textures are sampled at by `velocity`, in UV units per
+	/// second, as the simulation runs. Only textures whose samplers repeat wrap
+	/// around.
+	pub fn set_uv_animation(&mut self, velocity: [f32; 2]) {
+		self.uv_animation.velocity = velocity;
+	}
 
-			if (now - self.last_title).as_millis() > 100 {
-				self.title.clear();
-				write!(&mut self.title, "FPS: {:.1}", self.fps).ok();
-				self.window.set_title(&self.title);
-				self.last_title = now;
-			}
+	/// Stops scrolling UVs, and puts textures back where they started.
+	pub fn clear_uv_animation(&mut self) {
+		self.uv_animation = UvAnimation::default();
+		self.upload_uv_animation();
+	}
+
+	pub fn uv_animation(&self) -> UvAnimation {
+		self.uv_animation
+	}
+
+	/// Advances the UV offset by `dt` seconds and uploads it, if it is moving.
+	fn update_uv_animation(&mut self, dt: f32) {
+		if self.uv_animation.is_moving() {
+			self.uv_animation.advance(dt);
+			self.upload_uv_animation();
 		}
+	}
 
-		let output = self.surface.get_current_texture()?;
-		let view = output
-			.texture
-			.create_view(&wgpu::TextureViewDescriptor::default());
-		let mut encoder =
-			self.device
-				.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-					label: Some("Render Encoder"),
-				});
+	fn upload_uv_animation(&mut self) {
+		let bytes = bytemuck::bytes_of(&self.uv_animation);
+		self.queue.write_buffer(&self.uv_animation_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+	}
 
-		{
-			let mut render_pass =
-				encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-					label: Some("Render Pass"),
-					color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-						view: &view,
-						resolve_target: None,
-						ops: wgpu::Operations {
-							load: wgpu::LoadOp::Clear(wgpu::Color {
-								r: 0.1,
-								g: 0.2,
-								b: 0.3,
-								a: 1.0,
-							}),
-							store: true,
-						},
-					})],
-					depth_stencil_attachment: None,
-				});
+	/// Sets the wind swaying [`Material::foliage`] meshes: vertices move along
+	/// `direction` by up to `strength` world units, in waves `frequency` per
+	/// world unit along x. A strength of 0 stops the wind.
+	pub fn set_wind_params(
+		&mut self,
+		direction: Vector3<f32>,
+		strength: f32,
+		frequency: f32,
+	) {
+		self.wind.direction = direction.normalize().into();
+		self.wind.strength = strength;
+		self.wind.frequency = frequency;
+		self.upload_wind();
+	}
 
-			render_pass.set_pipeline(&self.pipeline);
+	pub fn wind(&self) -> WindUniform {
+		self.wind
+	}
 
-			render_pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
-			render_pass
-				.set_index_buffer(self.idx_buf.slice(..), wgpu::IndexFormat::Uint16);
+	/// Moves the wind's waves on by `dt` seconds and uploads them, if the wind
+	/// is blowing. [`Self::run_simulation_ticks`] calls it every tick.
+	pub fn update_wind(&mut self, dt: f32) {
+		if self.wind.is_blowing() {
+			self.wind.advance(dt);
+			self.upload_wind();
+		}
+	}
+
+	fn upload_wind(&mut self) {
+		let bytes = bytemuck::bytes_of(&self.wind);
+		self.queue.write_buffer(&self.wind_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+	}
+
+	/// Replaces the camera the scene is drawn from. Its aspect ratio is set to the
+	/// frame's.
+	pub fn set_camera(&mut self, mut camera: Box<dyn CameraLike>) {
+		camera.on_resize(self.camera_aspect());
+		// Not interpolated from the old camera.
+		self.prev_view = camera.view();
+		self.camera = camera;
+		self.interpolate(1.);
+	}
+
+	/// Replaces the camera with one flying along `path` as the simulation runs,
+	/// with the current camera's projection. Input no longer moves the camera.
+	///
+	/// # Panics
+	/// If the path has no waypoints.
+	pub fn set_camera_path(&mut self, path: CameraPath) {
+		let player = CameraPathPlayer::new(path, *self.camera.projection());
+		self.set_camera(Box::new(player));
+	}
 
-			render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-			render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-			// render_pass.draw(0..self.num_vertices, 0..1)
-			render_pass.draw_indexed(0..self.num_indices, 0, 0..1)
+	/// Saves the pose of the camera and the transforms of the objects as JSON.
+	/// Fails if the camera can't be converted to a [`Camera`].
+	///
+	/// [`Camera`]: crate::camera::Camera
+	#[cfg(feature = "serde")]
+	pub fn save_snapshot(&self) -> Result<String> {
+		let snapshot = SceneSnapshot {
+			camera: self
+				.camera
+				.to_camera()
+				.ok_or_else(|| eyre!("The camera doesn't support snapshots"))?,
+			object_transforms: self
+				.render_queue
+				.iter()
+				.map(|object| object.transform)
+				.collect(),
+		};
+		serde_json::to_string_pretty(&snapshot).wrap_err("Failed to serialize snapshot")
+	}
+
+	/// Restores a snapshot from [`Self::save_snapshot`], replacing the camera with
+	/// the saved one. The objects must be the same as when it was saved.
+	#[cfg(feature = "serde")]
+	pub fn load_snapshot(&mut self, json: &str) -> Result<()> {
+		let snapshot: SceneSnapshot =
+			serde_json::from_str(json).wrap_err("Failed to parse snapshot")?;
+		if snapshot.object_transforms.len() != self.render_queue.len() {
+			bail!(
+				"Snapshot has {} objects, but the scene has {}",
+				snapshot.object_transforms.len(),
+				self.render_queue.len()
+			);
 		}
+		for (object, transform) in
+			self.render_queue.iter_mut().zip(snapshot.object_transforms)
+		{
+			object.transform = transform;
+		}
+		self.set_camera(Box::new(snapshot.camera));
+		Ok(())
+	}
 
-		let commands = encoder.finish();
-		self.queue.submit([commands]);
-		output.present();
+	/// Saves the camera, the objects added with [`Self::add_scene_object`] and
+	/// the lights and fog to `path`, see [`SceneFile::to_bytes`] for the format.
+	/// Other objects aren't saved, and the paths of those that are are saved
+	/// relative to the scene's directory. Fails if the camera can't be converted
+	/// to a [`Camera`].
+	///
+	/// [`Camera`]: crate::camera::Camera
+	#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+	pub fn save_scene(&self, path: &Path) -> Result<()> {
+		let dir = scene_dir(path)?;
+		let objects = self
+			.scene_objects
+			.iter()
+			.map(|(index, object)| SceneObject {
+				transform: self.render_queue[*index].transform.into(),
+				..object.relative_paths(&dir)
+			})
+			.collect();
+		let lights = std::iter::once(Light::Directional(self.light))
+			.chain(self.point_shadows.iter().map(|light| Light::Point {
+				position: light.position().into(),
+				range: light.range(),
+				color: light.color(),
+			}))
+			.collect();
+		let scene = SceneFile {
+			camera: self
+				.camera
+				.to_camera()
+				.ok_or_else(|| eyre!("The camera doesn't support saving scenes"))?,
+			objects,
+			lights,
+			fog: self.fog.is_enabled().then_some(self.fog),
+		};
+		let bytes = scene.to_bytes(path)?;
+		std::fs::write(path, bytes)
+			.wrap_err_with(|| format!("Failed to write scene {}", path.display()))
+	}
 
+	/// Replaces the objects, lights, fog and camera with those of a scene saved
+	/// with [`Self::save_scene`]. The scene is left as it was if any of its assets
+	/// fail to load.
+	#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+	pub fn load_scene(&mut self, path: &Path) -> Result<()> {
+		let bytes = std::fs::read(path)
+			.wrap_err_with(|| format!("Failed to read scene {}", path.display()))?;
+		let scene = SceneFile::from_bytes(&bytes, path)
+			.wrap_err_with(|| format!("Failed to load scene {}", path.display()))?;
+		let directional = scene
+			.lights
+			.iter()
+			.filter(|light| matches!(light, Light::Directional(_)))
+			.count();
+		if directional > 1 {
+			bail!(
+				"Scene has {directional} directional lights, but only 1 is supported"
+			);
+		}
+		let points = scene.lights.len() - directional;
+		if points > MAX_POINT_SHADOWS {
+			bail!(
+				"Scene has {points} point lights, but at most {MAX_POINT_SHADOWS} cast \
+				 shadows"
+			);
+		}
+		// Kept absolute, so that saving elsewhere still finds the assets.
+		let dir = scene_dir(path)?;
+		let scene_objects: Vec<_> = scene
+			.objects
+			.iter()
+			.map(|object| object.resolve_paths(&dir))
+			.collect();
+		let objects = scene_objects
+			.iter()
+			.map(|object| self.load_scene_object(object))
+			.collect::<Result<Vec<_>>>()?;
+
+		self.clear_objects();
+		for (object, scene_object) in objects.into_iter().zip(scene_objects) {
+			self.scene_objects
+				.push((self.render_queue.len(), scene_object));
+			self.add_object(object);
+		}
+		while !self.point_shadows.is_empty() {
+			self.remove_point_light(self.point_shadows.len() - 1);
+		}
+		for light in scene.lights {
+			match light {
+				Light::Directional(light) => self.set_light(light),
+				Light::Point {
+					position,
+					range,
+					color,
+				} => {
+					self.add_point_light(position.into(), range, color);
+				}
+			}
+		}
+		match scene.fog {
+			Some(fog) => self.set_fog(fog),
+			None => self.disable_fog(),
+		}
+		self.set_camera(Box::new(scene.camera));
 		Ok(())
 	}
 
-	pub fn resize(&mut self, size: PhysicalSize<u32>) {
-		if size.width == 0 && size.height == 0 {
-			return;
+	/// Loads `object`'s mesh and texture, and adds it to the objects saved with
+	/// [`Self::save_scene`]. Relative paths are relative to the working
+	/// directory.
+	#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+	pub fn add_scene_object(&mut self, object: SceneObject) -> Result<()> {
+		let dir =
+			std::env::current_dir().wrap_err("Failed to get working directory")?;
+		// Kept absolute, so that the scene can be saved anywhere.
+		let object = object.resolve_paths(&dir);
+		let render_object = self.load_scene_object(&object)?;
+		self.scene_objects.push((self.render_queue.len(), object));
+		self.add_object(render_object);
+		Ok(())
+	}
+
+	/// Creates the object drawing `object`.
+	#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+	fn load_scene_object(&self, object: &SceneObject) -> Result<RenderObject> {
+		let mesh_path = Path::new(&object.mesh_path);
+		let bytes = std::fs::read(&mesh_path)
+			.wrap_err_with(|| format!("Failed to read mesh {}", mesh_path.display()))?;
+		let (vertices, indices) = load_obj(&bytes).wrap_err_with(|| {
+			format!("Failed to parse mesh {}", mesh_path.display())
+		})?;
+		let diffuse = Tex2d::load_from_path(
+			&self.device,
+			&self.queue,
+			Path::new(&object.material_path),
+			SamplerConfig::default(),
+		)?;
+		let normal_map = Tex2d::flat_normal_map(&self.device, &self.queue);
+		Ok(RenderObject {
+			mesh: Arc::new(GpuMesh::new(&self.device, &vertices, &indices)),
+			material: Arc::new(self.create_material(&diffuse, &normal_map)),
+			transform: object.transform.into(),
+			stencil: StencilMode::Disabled,
+		})
+	}
+
+	/// Uploads the state to render, interpolated `alpha` of the way from the
+	/// second to last simulation tick to the last one.
+	pub fn interpolate(&mut self, alpha: f32) {
+		let view = self.prev_view.lerp_slerp(&self.camera.view(), alpha);
+		self.upload_camera(&view);
+		for view in &self.views {
+			self.frame_stats.bytes_uploaded += view.update(&self.queue, self.reverse_z);
 		}
-		self.config.width = size.width;
-		self.config.height = size.height;
-		self.surface.configure(&self.device, &self.config);
 	}
 
-	pub fn size(&self) -> PhysicalSize<u32> {
-		PhysicalSize {
-			width: self.config.width,
-			height: self.config.height,
+	/// Uploads the main camera's projection, from `view` rather than its own.
+	fn upload_camera(&mut self, view: &IsometryMatrix3<f32>) {
+		let jitter = self.post_effects.jitter_matrix();
+		let uniform = CameraUniform::from_jittered_view(
+			&*self.camera,
+			view,
+			self.reverse_z,
+			&jitter,
+		);
+		self.queue
+			.write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&uniform));
+		self.frame_stats.bytes_uploaded += std::mem::size_of_val(&uniform) as u64;
+		if let Some(skybox) = &mut self.skybox {
+			self.frame_stats.bytes_uploaded +=
+				skybox.update(&self.queue, &*self.camera, view);
+		}
+		if let Some(ssao) = &mut self.ssao {
+			self.frame_stats.bytes_uploaded +=
+				ssao.update(&self.queue, &*self.camera, view);
+		}
+		if let Some(sdf_ao) = &mut self.sdf_ao {
+			self.frame_stats.bytes_uploaded +=
+				sdf_ao.update(&self.queue, &*self.camera, view);
+		}
+		self.frame_stats.bytes_uploaded +=
+			self.post_effects
+				.update_camera(&self.queue, &*self.camera, view);
+		if let Some(cel_outline) = &mut self.cel_outline {
+			self.frame_stats.bytes_uploaded +=
+				cel_outline.update(&self.queue, &*self.camera);
+		}
+		if let Some(volumetric_fog) = &mut self.volumetric_fog {
+			self.frame_stats.bytes_uploaded +=
+				volumetric_fog.update(&self.queue, &*self.camera, view);
+		}
+		if let Some(decals) = &self.decals {
+			self.frame_stats.bytes_uploaded +=
+				decals.update(&self.queue, &*self.camera, view);
+		}
+		if let Some(decals) = &self.stencil_decals {
+			self.frame_stats.bytes_uploaded +=
+				decals.update(&self.queue, &*self.camera, view);
+		}
+	}
+
+	/// Limits the camera to drawing into `viewport`, in pixels, or the whole frame
+	/// if `None`. The camera's aspect ratio is set to the viewport's.
+	///
+	/// Viewports aren't resized with the frame: parts outside of it aren't drawn.
+	pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+		self.viewport = viewport;
+		self.camera.on_resize(self.camera_aspect());
+	}
+
+	pub fn viewport(&self) -> Option<Viewport> {
+		self.viewport
+	}
+
+	/// Discards everything the camera draws outside of `scissor`, in pixels. If
+	/// `None`, only what's outside of the viewport is discarded. The clear color
+	/// is kept everywhere else.
+	pub fn set_scissor(&mut self, scissor: Option<ScissorRect>) {
+		self.scissor = scissor;
+	}
+
+	pub fn scissor(&self) -> Option<ScissorRect> {
+		self.scissor
+	}
+
+	/// Also draws the scene from `camera` into `viewport`, in the same pass as the
+	/// main camera, for split screen. The skybox is only drawn by the main
+	/// camera, and `camera` isn't moved by input.
+	pub fn add_view(&mut self, viewport: Viewport, mut camera: Box<dyn CameraLike>) {
+		camera.on_resize(viewport.aspect());
+		let view = View::new(
+			&self.device,
+			&self.camera_bind_group_layout,
+			viewport,
+			camera,
+			self.reverse_z,
+		);
+		self.views.push(view);
+	}
+
+	/// Removes the views added with [`Self::add_view`].
+	pub fn clear_views(&mut self) {
+		self.views.clear();
+	}
+
+	/// The aspect ratio of the main camera's viewport.
+	fn camera_aspect(&self) -> f32 {
+		match self.viewport {
+			Some(viewport) => viewport.aspect(),
+			None => self.config.width as f32 / self.config.height as f32,
+		}
+	}
+
+	/// The window being rendered to, if any.
+	pub fn window(&self) -> Option<&Window> {
+		match &self.target {
+			FrameTarget::Window { window, .. } => Some(window),
+			FrameTarget::Headless { .. } => None,
+		}
+	}
+
+	/// Physical pixels per logical pixel, which text and other overlays are
+	/// positioned and sized in.
+	pub fn scale_factor(&self) -> f32 {
+		self.scale_factor
+	}
+
+	/// Sets the physical pixels per logical pixel, as when the window moves to a
+	/// monitor with a different DPI. The frame is resized separately.
+	pub fn set_scale_factor(&mut self, scale_factor: f32) {
+		self.scale_factor = scale_factor;
+		self.text_renderer.set_scale_factor(scale_factor);
+	}
+
+	/// The size of the frame in logical pixels.
+	pub fn logical_size(&self) -> (f32, f32) {
+		(
+			self.config.width as f32 / self.scale_factor,
+			self.config.height as f32 / self.scale_factor,
+		)
+	}
+
+	/// The size the scene is drawn at, smaller than the frame's while dynamic
+	/// resolution lowers it.
+	pub fn render_size(&self) -> (u32, u32) {
+		let (width, height) = (self.config.width, self.config.height);
+		match &self.resolution_scaler {
+			Some(scaler) => scaler.scaled_size(width, height),
+			None => (width, height),
 		}
 	}
+
+	/// The ratio of [`Self::render_size`] to the size of the frame, which
+	/// viewports and scissor rectangles are scaled by.
+	fn render_scale(&self) -> f32 {
+		self.render_size().0 as f32 / self.config.width as f32
+	}
+
+	/// The device and queue, to create the states of other windows with
+	/// [`Self::new_with_context`].
+	pub fn context(&self) -> &Arc<SharedGpuContext> {
+		&self.context
+	}
+
+	pub fn device(&self) -> &wgpu::Device {
+		&self.device
+	}
+
+	pub fn queue(&self) -> &wgpu::Queue {
+		&self.queue
+	}
+
+	/// Creates an opaque material without specular highlights for
+	/// [`RenderObject`]s. Use [`Tex2d::flat_normal_map`] for surfaces without a
+	/// normal map, or [`Self::build_material`] for more options.
+	pub fn create_material(&self, diffuse: &Tex2d, normal_map: &Tex2d) -> Material {
+		Material::new(
+			&self.device,
+			&self.material_bind_group_layout,
+			diffuse,
+			normal_map,
+			self.texture(self.specular_map),
+			Some("Material"),
+		)
+	}
+
+	/// Creates the material described by `builder`, for [`RenderObject`]s.
+	pub fn build_material(&self, builder: MaterialBuilder<'_>) -> Material {
+		builder.build(&self.device, &self.queue, &self.material_bind_group_layout)
+	}
+
+	/// Queues `object` to be drawn every frame, after the mesh. Objects with a
+	/// stencil mode are drawn first, in the order they were added. Then opaque
+	/// objects are drawn front to back and alpha blended ones back to front, by
+	/// the distance of their bounding box's center along the camera's view.
+	/// Objects with [`BlendMode::Transparent`] are blended over all of them,
+	/// unsorted.
+	pub fn add_object(&mut self, mut object: RenderObject) {
+		object.stencil = self.supported_stencil_mode(object.stencil);
+		self.render_queue.push(object);
+	}
+
+	/// Removes all objects added with [`Self::add_object`] and
+	/// [`Self::add_lod_object`].
+	pub fn clear_objects(&mut self) {
+		self.render_queue.clear();
+		#[cfg(feature = "serde")]
+		self.scene_objects.clear();
+		self.lod_objects.clear();
+	}
+
+	/// Queues `object` to be drawn every frame, after the other objects. Each
+	/// view draws the level of detail for the distance between the main camera
+	/// and the center of the mesh's bounding box.
+	pub fn add_lod_object(&mut self, object: LodObject) {
+		self.lod_objects.push(object);
+	}
+
+	/// The entities drawn every frame, in the main pass after the objects with a
+	/// level of detail. Their [`MeshHandle`]s and [`MaterialHandle`]s refer to
+	/// [`Self::resources`]. They cast no shadows, and [`BlendMode::Transparent`]
+	/// materials are blended as `Alpha`.
+	///
+	/// [`MeshHandle`]: crate::ecs::MeshHandle
+	/// [`MaterialHandle`]: crate::ecs::MaterialHandle
+	pub fn world(&self) -> &World {
+		&self.world
+	}
+
+	pub fn world_mut(&mut self) -> &mut World {
+		&mut self.world
+	}
+
+	/// Projects `texture` onto the scene this frame, within a box of `size`
+	/// centered on `position` and turned by `rotation`, see
+	/// [`DecalRenderer::add_decal`]. Decals are only drawn without MSAA, as they
+	/// read the depth buffer.
+	pub fn add_decal(
+		&mut self,
+		position: Point3<f32>,
+		rotation: IsometryMatrix3<f32>,
+		size: [f32; 3],
+		texture: Handle<Tex2d>,
+	) {
+		if self.decals.is_none() {
+			let decals =
+				DecalRenderer::new(&self.device, self.color_format(), self.reverse_z);
+			self.frame_stats.bytes_uploaded +=
+				decals.update(&self.queue, &*self.camera, &self.camera.view());
+			self.decals = Some(decals);
+		}
+		if let Some(decals) = &mut self.decals {
+			decals.add_decal(position, rotation, size, texture);
+		}
+	}
+
+	/// Projects `texture` onto the surfaces within the box reaching `extent`
+	/// from `center` along each axis this frame, masked by the stencil buffer,
+	/// see [`StencilDecalRenderer`]. They are ignored without a stencil buffer,
+	/// and only drawn without MSAA. The G-buffer is kept from the first one on.
+	pub fn add_stencil_decal(
+		&mut self,
+		center: Point3<f32>,
+		extent: [f32; 3],
+		texture: Handle<Tex2d>,
+	) {
+		if !self.depth_format.has_stencil_aspect() {
+			warn!("Stencil decal ignored, as there is no stencil buffer");
+			return;
+		}
+		if self.stencil_decals.is_none() {
+			let decals = StencilDecalRenderer::new(
+				&self.device,
+				self.color_format(),
+				self.depth_format,
+				self.reverse_z,
+			);
+			self.frame_stats.bytes_uploaded +=
+				decals.update(&self.queue, &*self.camera, &self.camera.view());
+			self.stencil_decals = Some(decals);
+			self.update_gbuffer();
+		}
+		if let Some(decals) = &mut self.stencil_decals {
+			decals.add(center, extent, texture);
+		}
+	}
+
+	/// The transforms, meshes and materials of the entities of `world` to draw,
+	/// in the order their model matrices are uploaded. Entities whose mesh or
+	/// material were removed from `resources` are skipped.
+	fn world_draws<'a>(
+		world: &'a World,
+		resources: &'a ResourceManager,
+	) -> Vec<(&'a Matrix4<f32>, &'a GpuMesh, &'a Material)> {
+		world
+			.drawables()
+			.filter_map(|(_, transform, mesh, material)| {
+				Some((
+					&transform.0,
+					resources.get(mesh.0)?,
+					resources.get(material.0)?,
+				))
+			})
+			.collect()
+	}
+
+	/// Adds a compute pass that is dispatched with `workgroups` every frame, before
+	/// the scene is drawn. Passes run in the order they were added.
+	pub fn add_compute_pass(&mut self, pass: ComputePass, workgroups: [u32; 3]) {
+		self.compute_passes.push((pass, workgroups));
+	}
+
+	/// Adds a mesh whose vertices are blended every frame, after the compute
+	/// passes. It is drawn by the objects using its [`MorphedMesh::mesh`]. Returns
+	/// its index in [`Self::morphed_meshes_mut`].
+	pub fn add_morphed_mesh(&mut self, mesh: MorphedMesh) -> usize {
+		self.morphed_meshes.push(mesh);
+		self.morphed_meshes.len() - 1
+	}
+
+	/// The meshes blended every frame, to update their weights.
+	pub fn morphed_meshes_mut(&mut self) -> &mut [MorphedMesh] {
+		&mut self.morphed_meshes
+	}
+
+	/// Adds cloth simulated every frame, after the morphed meshes, pushed by the
+	/// wind. It is drawn by the objects using its [`ClothSimulation::mesh`].
+	/// Returns its index in [`Self::cloths_mut`].
+	pub fn add_cloth(&mut self, cloth: ClothSimulation) -> usize {
+		self.cloths.push(cloth);
+		self.cloths.len() - 1
+	}
+
+	/// The cloths simulated every frame, to change their settings.
+	pub fn cloths_mut(&mut self) -> &mut [ClothSimulation] {
+		&mut self.cloths
+	}
+
+	/// Lines to draw over the next frame, see [`DebugLines`].
+	pub fn debug_lines_mut(&mut self) -> &mut DebugLines {
+		&mut self.debug_lines
+	}
+
+	/// Draws the arrows of a [`GizmoRenderer`] over every frame, at `center`,
+	/// along the axes of `transform` and `scale` long, until hidden with `None`.
+	pub fn set_gizmo(&mut self, gizmo: Option<(Point3<f32>, Matrix4<f32>, f32)>) {
+		self.gizmo_at = gizmo;
+	}
+
+	/// The axis of the gizmo, as last drawn, that `ray` grabs. See
+	/// [`GizmoRenderer::hit_test`].
+	pub fn gizmo_hit_test(&self, ray: &Ray) -> Option<GizmoAxis> {
+		let (center, ..) = self.gizmo_at?;
+		self.gizmo.hit_test(ray, center)
+	}
+
+	/// The index of the object of the render queue nearest the camera under the
+	/// pixel at `screen_pos`, in pixels from the top left corner of the frame.
+	/// Objects are hit by their bounding boxes, see [`GpuMesh::intersect_ray`].
+	pub fn pick_object(&self, screen_pos: (f32, f32)) -> Option<usize> {
+		let viewport = self.viewport.unwrap_or_else(|| {
+			Viewport::new(0., 0., self.config.width as f32, self.config.height as f32)
+		});
+		let ray = self.camera.ray_from_screen(
+			(screen_pos.0 - viewport.x, screen_pos.1 - viewport.y),
+			(viewport.width as u32, viewport.height as u32),
+		);
+		(self.render_queue.iter().enumerate())
+			.filter_map(|(i, object)| {
+				Some((i, object.mesh.intersect_ray(&ray, object.transform)?))
+			})
+			.min_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map(|(i, _)| i)
+	}
+
+	/// Draws the index of each object of the render queue into a texture during
+	/// the next frame, for [`Self::pick_at`] to read. Nothing is drawn for
+	/// picking in the frames without a request.
+	pub fn request_picking(&mut self) {
+		self.picking_requested = true;
+		if self.picking.is_none() {
+			self.picking = Some(IdPicker::new(
+				&self.device,
+				self.config.width,
+				self.config.height,
+				&self.shader,
+				&self.camera_bind_group_layout,
+				self.object_uniforms.as_ref().map(|u| u.bind_group_layout()),
+				match self.object_uniforms {
+					Some(_) => &[],
+					None => &[MODEL_PUSH_CONSTANT_RANGE],
+				},
+				self.reverse_z,
+			));
+		}
+	}
+
+	/// The index of the object of the render queue drawn at the pixel at
+	/// `screen_x`, `screen_y`, from the top left corner of the frame, as of the
+	/// last frame after [`Self::request_picking`]. Unlike [`Self::pick_object`],
+	/// objects are hit by their triangles.
+	///
+	/// Returns `None` where no object was drawn, or if no frame was drawn for
+	/// picking since the last resize.
+	///
+	/// NOTE: This waits for the GPU to copy the pixel. Mapping is asynchronous on
+	/// the web, so this is native only.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn pick_at(&self, screen_x: u32, screen_y: u32) -> Option<usize> {
+		let picking = self.picking.as_ref()?;
+		let buffer = self.buffer_pool.acquire(
+			&self.device,
+			4,
+			wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		);
+		let mut encoder =
+			self.device
+				.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+					label: Some("Picking Encoder"),
+				});
+		if !picking.copy_pixel(&mut encoder, screen_x, screen_y, &buffer) {
+			return None;
+		}
+		self.queue.submit([encoder.finish()]);
+
+		// The pooled buffer may be bigger than the pixel.
+		let slice = buffer.slice(..4);
+		let (tx, rx) = std::sync::mpsc::channel();
+		slice.map_async(wgpu::MapMode::Read, move |result| {
+			tx.send(result).ok();
+		});
+		self.device.poll(wgpu::Maintain::Wait);
+		if !matches!(rx.recv(), Ok(Ok(()))) {
+			error!("Failed to map the picking buffer");
+			return None;
+		}
+		let mut color = [0; 4];
+		color.copy_from_slice(&slice.get_mapped_range());
+		buffer.unmap();
+		buffer.release();
+		crate::picking::color_to_id(color).map(|id| id as usize)
+	}
+
+	/// Draws the triangles of `vertices` listed by `indices` every frame, after the
+	/// objects, with their vertex colors and no lighting. The vertices are in
+	/// world space.
+	pub fn add_color_mesh(&mut self, vertices: &[ColorVertex], indices: &[u32]) {
+		let format = self.color_format();
+		let color_meshes = self.color_meshes.get_or_insert_with(|| {
+			ColorMeshes::new(
+				&self.device,
+				&self.camera_bind_group_layout,
+				format,
+				self.sample_count,
+				self.depth_format,
+				self.reverse_z,
+			)
+		});
+		color_meshes.add(&self.device, vertices, indices);
+	}
+
+	/// Removes all meshes added with [`Self::add_color_mesh`].
+	pub fn clear_color_meshes(&mut self) {
+		if let Some(color_meshes) = &mut self.color_meshes {
+			color_meshes.clear();
+		}
+	}
+
+	/// Draws the triangles of `vertices` listed by `indices` every frame, moved by
+	/// the skeleton set with [`Self::update_skeleton`]. They are drawn with the
+	/// mesh's material and blend mode, and don't cast shadows.
+	pub fn add_skinned_mesh(&mut self, vertices: &[SkinnedVertex], indices: &[u32]) {
+		let vtx_buf =
+			self.device
+				.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("Skinned Mesh Vertex Buffer"),
+					contents: bytemuck::cast_slice(vertices),
+					usage: wgpu::BufferUsages::VERTEX,
+				});
+		let idx_buf =
+			self.device
+				.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("Skinned Mesh Index Buffer"),
+					contents: bytemuck::cast_slice(indices),
+					usage: wgpu::BufferUsages::INDEX,
+				});
+		self.skinned_meshes.push(SkinnedMesh {
+			vtx_buf,
+			idx_buf,
+			num_indices: indices.len() as u32,
+		});
+	}
+
+	/// Removes all meshes added with [`Self::add_skinned_mesh`].
+	pub fn clear_skinned_meshes(&mut self) {
+		self.skinned_meshes.clear();
+	}
+
+	/// Sets the matrices of the skeleton's bones, from the bind pose into world
+	/// space. Bones past [`MAX_BONES`] are ignored.
+	pub fn update_skeleton(&mut self, bones: &[Matrix4<f32>]) {
+		if bones.len() > MAX_BONES {
+			warn!(
+				"Ignoring {} bones past the first {MAX_BONES}",
+				bones.len() - MAX_BONES
+			);
+		}
+		let bones = &bones[..bones.len().min(MAX_BONES)];
+		self.frame_stats.bytes_uploaded += self.skin.update(&self.queue, bones);
+	}
+
+	/// Sets the particles simulated and drawn every frame, or removes them with
+	/// `None`.
+	pub fn set_particles(&mut self, system: Option<ParticleSystem>) {
+		self.particles = system.map(|system| {
+			let pipeline = ParticlePipeline::new(
+				&self.device,
+				&self.camera_bind_group_layout,
+				self.color_format(),
+				self.sample_count,
+				self.depth_format,
+				self.reverse_z,
+			);
+			(system, pipeline)
+		});
+	}
+
+	pub fn particles_mut(&mut self) -> Option<&mut ParticleSystem> {
+		self.particles.as_mut().map(|(system, _)| system)
+	}
+
+	/// Sets the terrain drawn after the mesh, or removes it with `None`. Every
+	/// frame, its chunks are culled by the main camera's frustum, for all views.
+	/// It casts no shadows, and [`BlendMode::Transparent`] materials are blended
+	/// as `Alpha`.
+	pub fn set_terrain(&mut self, terrain: Option<Terrain>) {
+		self.terrain = terrain;
+	}
+
+	pub fn terrain(&self) -> Option<&Terrain> {
+		self.terrain.as_ref()
+	}
+
+	pub fn terrain_mut(&mut self) -> Option<&mut Terrain> {
+		self.terrain.as_mut()
+	}
+
+	/// Sets the cubemap drawn behind the scene, or removes the sky with `None` to
+	/// only show the clear color.
+	pub fn set_skybox(&mut self, cubemap: Option<&Cubemap>) {
+		self.skybox = cubemap.map(|cubemap| {
+			let mut skybox = Sky::Cubemap(SkyboxPipeline::new(
+				&self.device,
+				cubemap,
+				self.color_format(),
+				self.sample_count,
+				self.depth_format,
+				self.reverse_z,
+			));
+			skybox.update(&self.queue, &*self.camera, &self.camera.view());
+			skybox
+		});
+	}
+
+	/// Draws `sky` behind the scene instead of a cubemap, or removes the sky with
+	/// `None`. Its pipeline is recreated for the frame's attachments.
+	pub fn set_procedural_sky(&mut self, sky: Option<ProceduralSky>) {
+		self.skybox = sky.map(|sky| {
+			let mut skybox = Sky::Procedural(sky);
+			skybox.set_format(
+				&self.device,
+				self.color_format(),
+				self.sample_count,
+				self.depth_format,
+			);
+			skybox.update(&self.queue, &*self.camera, &self.camera.view());
+			skybox
+		});
+	}
+
+	/// The procedural sky, if it is the one drawn.
+	pub fn procedural_sky(&self) -> Option<&ProceduralSky> {
+		match &self.skybox {
+			Some(Sky::Procedural(sky)) => Some(sky),
+			_ => None,
+		}
+	}
+
+	/// Points the sun of the procedural sky along `direction`, if it is the one
+	/// drawn. See [`ProceduralSky::set_sun_direction`].
+	pub fn set_sky_sun_direction(&mut self, direction: Vector3<f32>) {
+		if let Some(Sky::Procedural(sky)) = &mut self.skybox {
+			sky.set_sun_direction(&self.queue, direction);
+		}
+	}
+
+	/// Replaces the scene's directional light, which also casts shadows.
+	pub fn set_light(&mut self, light: LightUniform) {
+		self.light = light;
+		let bytes = bytemuck::bytes_of(&light);
+		self.queue.write_buffer(&self.light_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+		self.frame_stats.bytes_uploaded += self
+			.shadow_map
+			.set_direction(&self.queue, Vector3::from(light.direction));
+	}
+
+	/// Points the directional light along `direction` with the given color,
+	/// keeping its ambient term.
+	pub fn set_directional_light(&mut self, direction: Vector3<f32>, color: [f32; 3]) {
+		let light = LightUniform::new(direction.into(), color, self.light.ambient);
+		self.set_light(light);
+	}
+
+	/// Fades the objects into `fog.color` with their distance from the camera.
+	/// It is uploaded with the next frame.
+	pub fn set_fog(&mut self, fog: FogUniform) {
+		if fog != self.fog {
+			self.fog = fog;
+			self.fog_dirty = true;
+		}
+	}
+
+	pub fn disable_fog(&mut self) {
+		self.set_fog(FogUniform::disabled());
+	}
+
+	pub fn fog(&self) -> FogUniform {
+		self.fog
+	}
+
+	/// Sets how the mesh and objects' final colors are adjusted, see
+	/// [`ColorCorrectionUniform`]. With tone mapping enabled, this applies before
+	/// it.
+	pub fn set_color_correction(&mut self, uniform: ColorCorrectionUniform) {
+		self.color_correction = uniform;
+		let bytes = bytemuck::bytes_of(&self.color_correction);
+		self.queue
+			.write_buffer(&self.color_correction_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+	}
+
+	pub fn color_correction(&self) -> ColorCorrectionUniform {
+		self.color_correction
+	}
+
+	/// Sets the cubemap reflected by PBR materials. Its mip levels are sampled
+	/// with increasing roughness, see [`Cubemap::from_mip_images`].
+	pub fn set_environment_map(&mut self, cubemap: Cubemap) {
+		self.replace_environment_map(Some(cubemap));
+	}
+
+	/// Stops PBR materials from reflecting an environment map.
+	pub fn clear_environment_map(&mut self) {
+		self.replace_environment_map(None);
+	}
+
+	pub fn environment_map(&self) -> Option<&Cubemap> {
+		self.environment_map.cubemap()
+	}
+
+	/// Lights PBR materials with `probe`, baked with [`ReflectionProbe::bake`],
+	/// in place of the environment map, or goes back to the environment map with
+	/// `None`.
+	pub fn set_reflection_probe(&mut self, probe: Option<ReflectionProbe>) {
+		self.frame_stats.bytes_uploaded +=
+			self.environment_map.set_probe(&self.queue, probe);
+		self.recreate_light_bind_group();
+	}
+
+	pub fn reflection_probe(&self) -> Option<&ReflectionProbe> {
+		self.environment_map.probe()
+	}
+
+	fn replace_environment_map(&mut self, cubemap: Option<Cubemap>) {
+		self.frame_stats.bytes_uploaded +=
+			self.environment_map.set(&self.queue, cubemap);
+		self.recreate_light_bind_group();
+	}
+
+	/// Rebinds the textures of the light bind group, after they were replaced.
+	fn recreate_light_bind_group(&mut self) {
+		let occlusion_view = match (&self.sdf_ao, &self.ssao) {
+			(Some(sdf_ao), _) => sdf_ao.occlusion_view(),
+			(None, Some(ssao)) => ssao.occlusion_view(),
+			(None, None) => &self.no_occlusion.view,
+		};
+		self.light_bind_group = light::create_bind_group(
+			&self.device,
+			&self.light_bind_group_layout,
+			&self.light_buf,
+			&self.shadow_map,
+			&self.cascaded_shadows,
+			&self.fog_buf,
+			&self.environment_map,
+			occlusion_view,
+			&self.uv_animation_buf,
+			&self.wind_buf,
+			&self.color_correction_buf,
+			&self.point_lights_buf,
+			&self.point_shadows,
+			&self.no_point_shadow,
+		);
+	}
+
+	/// Sets the width and height of the shadow map, in texels. It is only
+	/// recreated if the size changed.
+	pub fn set_shadow_map_size(&mut self, size: u32) {
+		if self.shadow_map.set_size(&self.device, size) {
+			self.recreate_light_bind_group();
+		}
+	}
+
+	/// Limits shadows to those within `radius` of `center`. A smaller region gets
+	/// sharper shadows. Defaults to a radius of 2 around the origin.
+	pub fn set_shadow_bounds(&mut self, center: Point3<f32>, radius: f32) {
+		self.frame_stats.bytes_uploaded +=
+			self.shadow_map.set_bounds(&self.queue, center, radius);
+	}
+
+	/// Sets how far apart, in texels, each fragment's shadow samples are, in the
+	/// shadow map or its cascades. Defaults to
+	/// [`CascadedShadowMap::DEFAULT_PCF_RADIUS`].
+	pub fn set_shadow_pcf_radius(&mut self, radius: f32) {
+		self.frame_stats.bytes_uploaded +=
+			self.cascaded_shadows.set_pcf_radius(&self.queue, radius);
+	}
+
+	pub fn shadow_pcf_radius(&self) -> f32 {
+		self.cascaded_shadows.pcf_radius()
+	}
+
+	/// Splits the shadows into [`N_CASCADES`] maps of `size`×`size` texels, each
+	/// covering a slice of the camera's view further away than the last, instead
+	/// of the single map within [`Self::set_shadow_bounds`]. See
+	/// [`CascadedShadowMap::compute_splits`] for `lambda`.
+	pub fn enable_cascaded_shadows(&mut self, size: u32, lambda: f32) {
+		self.cascaded_shadows.set_lambda(lambda);
+		self.cascaded_shadows.set_enabled(&self.queue, true);
+		if self.cascaded_shadows.set_size(&self.device, size) {
+			self.recreate_light_bind_group();
+		}
+	}
+
+	/// Goes back to the single shadow map, freeing the cascades.
+	pub fn disable_cascaded_shadows(&mut self) {
+		self.frame_stats.bytes_uploaded +=
+			self.cascaded_shadows.set_enabled(&self.queue, false);
+		if self.cascaded_shadows.set_size(&self.device, 1) {
+			self.recreate_light_bind_group();
+		}
+	}
+
+	pub fn cascaded_shadows(&self) -> Option<&CascadedShadowMap> {
+		Some(&self.cascaded_shadows).filter(|c| c.is_enabled())
+	}
+
+	pub fn cascaded_shadows_mut(&mut self) -> Option<&mut CascadedShadowMap> {
+		Some(&mut self.cascaded_shadows).filter(|c| c.is_enabled())
+	}
+
+	/// Adds a point light of `color` at `position`, lighting and casting shadows
+	/// up to `range` away. Returns its index, which shifts down as lights before
+	/// it are removed.
+	///
+	/// # Panics
+	///
+	/// If there are already [`MAX_POINT_SHADOWS`] point lights.
+	pub fn add_point_light(
+		&mut self,
+		position: Point3<f32>,
+		range: f32,
+		color: [f32; 3],
+	) -> usize {
+		assert!(
+			self.point_shadows.len() < MAX_POINT_SHADOWS,
+			"At most {MAX_POINT_SHADOWS} point lights cast shadows"
+		);
+		let mut point_shadow = PointShadowMap::new(
+			&self.device,
+			&self.queue,
+			PointShadowMap::DEFAULT_SIZE,
+			position,
+			range,
+			&self.shader,
+			&self.camera_bind_group_layout,
+			self.object_uniforms.as_ref().map(|u| u.bind_group_layout()),
+			match self.object_uniforms {
+				Some(_) => &[],
+				None => &[MODEL_PUSH_CONSTANT_RANGE],
+			},
+		);
+		point_shadow.set_color(color);
+		self.point_shadows.push(point_shadow);
+		self.upload_point_lights();
+		self.recreate_light_bind_group();
+		self.point_shadows.len() - 1
+	}
+
+	/// Removes the point light at `index`, and its shadow map.
+	pub fn remove_point_light(&mut self, index: usize) {
+		self.point_shadows.remove(index);
+		self.upload_point_lights();
+		self.recreate_light_bind_group();
+	}
+
+	/// Moves the point light at `index` to `position`.
+	pub fn set_point_light_position(&mut self, index: usize, position: Point3<f32>) {
+		self.frame_stats.bytes_uploaded +=
+			self.point_shadows[index].set_position(&self.queue, position);
+		self.upload_point_lights();
+	}
+
+	/// Sets how far the point light at `index` reaches, and its shadows with it.
+	pub fn set_point_light_range(&mut self, index: usize, range: f32) {
+		self.frame_stats.bytes_uploaded +=
+			self.point_shadows[index].set_range(&self.queue, range);
+		self.upload_point_lights();
+	}
+
+	pub fn set_point_light_color(&mut self, index: usize, color: [f32; 3]) {
+		self.point_shadows[index].set_color(color);
+		self.upload_point_lights();
+	}
+
+	/// Sets the width and height of each face of the shadow map of the point
+	/// light at `index`, in texels. It is only recreated if the size changed.
+	pub fn set_point_shadow_size(&mut self, index: usize, size: u32) {
+		if self.point_shadows[index].set_size(&self.device, size) {
+			self.recreate_light_bind_group();
+		}
+	}
+
+	pub fn point_lights(&self) -> &[PointShadowMap] {
+		&self.point_shadows
+	}
+
+	fn upload_point_lights(&mut self) {
+		let uniform = PointLightsUniform::new(&self.point_shadows);
+		let bytes = bytemuck::bytes_of(&uniform);
+		self.queue.write_buffer(&self.point_lights_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+	}
+
+	/// Must be called with every window event, before it is used for anything
+	/// else. Returns `true` if the debug UI consumed the event.
+	pub fn on_window_event(&mut self, event: &WindowEvent<'_>) -> bool {
+		match &mut self.debug_ui {
+			Some(debug_ui) => debug_ui.on_event(event),
+			None => false,
+		}
+	}
+
+	/// Replaces the mesh that is drawn.
+	pub fn load_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) {
+		let mesh = GpuMesh::new(&self.device, vertices, indices);
+		self.vtx_buf = mesh.vtx_buf;
+		self.idx_buf = mesh.idx_buf;
+		self.num_indices = mesh.num_indices;
+	}
+
+	/// Creates a buffer counted in [`Self::memory_report`] while it lives.
+	pub fn create_buffer(&self, desc: &wgpu::BufferDescriptor<'_>) -> TrackedBuffer {
+		self.memory_tracker.create_buffer(&self.device, desc)
+	}
+
+	/// Creates a buffer with `desc`'s contents, counted in
+	/// [`Self::memory_report`] while it lives.
+	pub fn create_buffer_init(
+		&self,
+		desc: &wgpu::util::BufferInitDescriptor<'_>,
+	) -> TrackedBuffer {
+		self.memory_tracker.create_buffer_init(&self.device, desc)
+	}
+
+	/// Creates a texture counted in [`Self::memory_report`] while it lives.
+	pub fn create_texture(&self, desc: &wgpu::TextureDescriptor<'_>) -> TrackedTexture {
+		self.memory_tracker.create_texture(&self.device, desc)
+	}
+
+	/// The memory of the frame's depth and MSAA textures, and of the buffers and
+	/// textures created through `RenderState`. It is logged every second at the
+	/// debug level.
+	pub fn memory_report(&self) -> MemoryReport {
+		self.memory_tracker.report()
+	}
+
+	/// Buffers to reuse for data that is replaced often, such as streamed
+	/// geometry. A frame passes in the pool with every [`Self::render`].
+	pub fn buffer_pool(&self) -> &BufferPool {
+		&self.buffer_pool
+	}
+
+	/// Creates an encoder to record [`Self::upload_vertices`] and
+	/// [`Self::upload_indices`] into. Submit it with [`Self::submit_uploads`].
+	pub fn begin_uploads(&self) -> wgpu::CommandEncoder {
+		self.device
+			.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+				label: Some("Upload Encoder"),
+			})
+	}
+
+	/// Records replacing the vertices of the mesh that is drawn, without
+	/// recreating its vertex buffer unless it is too small. For geometry that
+	/// changes every frame.
+	pub fn upload_vertices(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		vertices: &[Vertex],
+	) {
+		let bytes: &[u8] = bytemuck::cast_slice(vertices);
+		if bytes.len() as u64 > self.vtx_buf.size() {
+			self.vtx_buf = create_streaming_buffer(
+				&self.device,
+				"Vertex Buffer",
+				bytes.len() as u64,
+				wgpu::BufferUsages::VERTEX,
+			);
+		}
+		self.frame_stats.bytes_uploaded += stage(
+			&mut self.staging_belt,
+			&self.device,
+			encoder,
+			&self.vtx_buf,
+			bytes,
+		);
+	}
+
+	/// Records replacing the indices of the mesh that is drawn, like
+	/// [`Self::upload_vertices`].
+	pub fn upload_indices(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		indices: &[u32],
+	) {
+		let bytes: &[u8] = bytemuck::cast_slice(indices);
+		if bytes.len() as u64 > self.idx_buf.size() {
+			self.idx_buf = create_streaming_buffer(
+				&self.device,
+				"Index Buffer",
+				bytes.len() as u64,
+				wgpu::BufferUsages::INDEX,
+			);
+		}
+		self.frame_stats.bytes_uploaded += stage(
+			&mut self.staging_belt,
+			&self.device,
+			encoder,
+			&self.idx_buf,
+			bytes,
+		);
+		self.num_indices = indices.len() as u32;
+	}
+
+	/// Submits the uploads recorded into `encoder`, which must come from
+	/// [`Self::begin_uploads`]. They are done before the next frame is drawn.
+	pub fn submit_uploads(&mut self, encoder: wgpu::CommandEncoder) {
+		// The staging buffers must be unmapped before the copies are submitted,
+		// and can only be reused once they are done.
+		self.staging_belt.finish();
+		self.queue.submit([encoder.finish()]);
+		self.staging_belt.recall();
+	}
+
+	/// Replaces the mesh that is drawn with the contents of an OBJ file. The old
+	/// mesh is kept if parsing fails.
+	pub fn load_mesh_from_obj(&mut self, bytes: &[u8]) -> Result<()> {
+		let (vertices, indices) = load_obj(bytes).wrap_err("Failed to parse OBJ")?;
+		self.load_mesh(&vertices, &indices);
+		Ok(())
+	}
+
+	/// Uploads the meshes, textures and cameras of a binary glTF (GLB) file. They
+	/// aren't drawn, see [`GltfScene`] for how they fit together.
+	pub fn load_gltf(&self, bytes: &[u8]) -> Result<GltfScene> {
+		load_gltf(&self.device, &self.queue, bytes).wrap_err("Failed to load glTF")
+	}
+
+	/// Sets the model matrices of the instances of the mesh to draw. The mesh is
+	/// drawn once per transform, in a single draw call.
+	pub fn set_instances(&mut self, transforms: &[Matrix4<f32>]) {
+		let instances: Vec<Instance> =
+			transforms.iter().copied().map(Instance::new).collect();
+		let count = instances.len() as u32;
+		if count > self.instance_capacity {
+			self.instance_capacity = count.next_power_of_two();
+			self.instance_buf =
+				create_instance_buffer(&self.device, self.instance_capacity);
+		}
+		let bytes: &[u8] = bytemuck::cast_slice(&instances);
+		self.queue.write_buffer(&self.instance_buf, 0, bytes);
+		self.frame_stats.bytes_uploaded += bytes.len() as u64;
+		self.num_instances = count;
+	}
+
+	/// Draws the mesh with the first `count` [`DrawCall`]s of `buffer`, made with
+	/// [`build_indirect_buffer`], instead of all of its indices and instances. The
+	/// draws must stay within the mesh's indices and the instances set with
+	/// [`Self::set_instances`]. `None` draws the mesh directly again.
+	///
+	/// The buffer can be rewritten every frame, including by compute passes.
+	/// Indirect draws aren't supported on WebGL.
+	///
+	/// [`build_indirect_buffer`]: crate::indirect::build_indirect_buffer
+	pub fn set_indirect_draws(&mut self, draws: Option<(wgpu::Buffer, u32)>) {
+		self.indirect_draws = draws;
+	}
+
+	/// Culls the instances of the mesh against the main camera's frustum with a
+	/// compute shader every frame, and draws the visible ones with a single
+	/// indirect draw, or draws them all again with `None`. `objects` bound the
+	/// instances set with [`Self::set_instances`] in world space, in the same
+	/// order. Other views draw every instance.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If `objects` is empty.
+	pub fn set_gpu_culling(&mut self, objects: Option<&[CullData]>) -> Result<()> {
+		self.gpu_culling = match objects {
+			Some(objects) => Some(GpuCuller::new(&self.device, objects)?),
+			None => None,
+		};
+		Ok(())
+	}
+
+	/// Draws the instances of `mesh` with `material` in the main camera's view
+	/// every frame, or stops with `None`. Up to `capacity` instances are culled
+	/// and given their level of detail by [`Self::dispatch_cull_lod`], and each
+	/// level is drawn with a single indirect draw. Other views and shadows don't
+	/// draw them.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	///
+	/// # Panics
+	/// If `capacity` is 0, `mesh` has more than [`MAX_CULLED_LODS`] levels, or
+	/// the material is [`BlendMode::Transparent`].
+	pub fn set_cull_lod(
+		&mut self,
+		mesh: Option<(Arc<LodMesh>, Arc<Material>)>,
+		capacity: u32,
+	) -> Result<()> {
+		self.cull_lod = match mesh {
+			Some((mesh, material)) => {
+				assert!(
+					mesh.lods.len() <= MAX_CULLED_LODS,
+					"At most {MAX_CULLED_LODS} levels of detail can be culled"
+				);
+				assert!(
+					material.blend != BlendMode::Transparent,
+					"Culled instances are drawn in the main pass, without WBOIT"
+				);
+				let culler = GpuLodCuller::new(&self.device, capacity)?;
+				Some((mesh, material, culler))
+			}
+			None => None,
+		};
+		Ok(())
+	}
+
+	/// Records culling the first `count` [`InstanceData`] of `instances` against
+	/// the main camera's frustum, and picking their levels of detail by their
+	/// distance from it, with a single compute pass. `instances` needs
+	/// [`wgpu::BufferUsages::STORAGE`]. `encoder` must be submitted before the
+	/// next frame is rendered, which draws the visible instances.
+	///
+	/// [`InstanceData`]: crate::gpu_culling::InstanceData
+	///
+	/// # Panics
+	/// If no mesh was set with [`Self::set_cull_lod`].
+	pub fn dispatch_cull_lod(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		instances: &wgpu::Buffer,
+		count: u32,
+	) {
+		let (mesh, _, culler) = (self.cull_lod.as_ref())
+			.expect("A mesh must be set with set_cull_lod to cull its instances");
+		let eye = self.camera.view().inverse() * Point3::origin();
+		self.frame_stats.bytes_uploaded += culler.cull(
+			&self.device,
+			&self.queue,
+			encoder,
+			instances,
+			count,
+			mesh,
+			&eye,
+			&self.camera.frustum(),
+		);
+	}
+
+	/// Records drawing the mesh with the [`DrawCall`] at `offset` in
+	/// `indirect_buf`, into a pass with the same attachments as the main one.
+	/// The pipeline and bind groups of the mesh must already be set.
+	pub fn draw_indirect<'a>(
+		&'a self,
+		render_pass: &mut wgpu::RenderPass<'a>,
+		indirect_buf: &'a wgpu::Buffer,
+		offset: u64,
+	) {
+		render_pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+		render_pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+		render_pass.set_index_buffer(self.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+		render_pass.draw_indexed_indirect(indirect_buf, offset);
+	}
+
+	/// Starts loading the image at `path` on another thread. It replaces the
+	/// diffuse texture in the first frame rendered after it is loaded, and errors
+	/// are logged.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn load_texture_in_background(&mut self, path: PathBuf) {
+		self.texture_loader.load_path(path);
+	}
+
+	/// Starts fetching the image at `url`. It replaces the diffuse texture in the
+	/// first frame rendered after it is loaded, and errors while loading are
+	/// logged.
+	///
+	/// Only supported on the web: native builds have no HTTP client, so this
+	/// returns an error there.
+	pub fn load_texture_from_url(&mut self, url: &str) -> Result<()> {
+		cfg_if::cfg_if! {
+			if #[cfg(target_arch = "wasm32")] {
+				self.texture_loader.load_url(url);
+				Ok(())
+			} else {
+				bail!("Can't load {url}, textures are only loaded from URLs on the web")
+			}
+		}
+	}
+
+	/// Replaces the diffuse texture with the images that finished loading in the
+	/// background, the last one winning.
+	fn finish_texture_loads(&mut self) {
+		for loaded in self.texture_loader.poll() {
+			match loaded.image {
+				Ok(image) => {
+					let texture = Tex2d::new_from_img(
+						&self.device,
+						&self.queue,
+						Some(&loaded.source),
+						image,
+						SamplerConfig::default(),
+					);
+					self.resources.remove(self.diffuse_tex);
+					self.diffuse_tex = self.resources.insert(texture);
+					self.update_material_bind_group();
+					info!("Loaded texture {}", loaded.source);
+				}
+				Err(err) => error!("{err:?}"),
+			}
+		}
+	}
+
+	/// Replaces the diffuse texture with the image at `path`.
+	pub fn load_texture(&mut self, path: &Path) -> Result<()> {
+		let texture = Tex2d::load_from_path(
+			&self.device,
+			&self.queue,
+			path,
+			SamplerConfig::default(),
+		)?;
+		self.resources.remove(self.diffuse_tex);
+		self.diffuse_tex = self.resources.insert(texture);
+		self.update_material_bind_group();
+		Ok(())
+	}
+
+	/// Replaces the normal map with the image at `path`.
+	pub fn load_normal_map(&mut self, path: &Path) -> Result<()> {
+		let texture = Tex2d::load_normal_map_from_path(
+			&self.device,
+			&self.queue,
+			path,
+			SamplerConfig::default(),
+		)?;
+		self.resources.remove(self.normal_map);
+		self.normal_map = self.resources.insert(texture);
+		self.update_material_bind_group();
+		Ok(())
+	}
+
+	fn update_material_bind_group(&mut self) {
+		self.material = Material::new(
+			&self.device,
+			&self.material_bind_group_layout,
+			self.texture(self.diffuse_tex),
+			self.texture(self.normal_map),
+			self.texture(self.specular_map),
+			Some("material_bind_group"),
+		);
+	}
+
+	/// One of the mesh's textures.
+	fn texture(&self, handle: Handle<Tex2d>) -> &Tex2d {
+		self.resources
+			.get(handle)
+			.expect("The mesh's textures are never removed")
+	}
+
+	/// The textures, meshes and other resources stored by handle.
+	pub fn resources(&self) -> &ResourceManager {
+		&self.resources
+	}
+
+	/// Like [`Self::resources`]. The mesh's own textures are stored here too, and
+	/// must not be removed.
+	pub fn resources_mut(&mut self) -> &mut ResourceManager {
+		&mut self.resources
+	}
+
+	/// Asks the user for an image to use as a texture, with a native file dialog.
+	///
+	/// NOTE: This blocks until the dialog is closed, so it must not be called while
+	/// a frame is being rendered.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn open_texture_dialog() -> Option<PathBuf> {
+		rfd::FileDialog::new()
+			.set_title("Open Texture")
+			.add_filter("Images", TEXTURE_EXTENSIONS)
+			.pick_file()
+	}
+
+	/// Asks the user for an image with the browser's file picker. Like with
+	/// [`Self::load_texture_from_url`], the image replaces the diffuse texture in
+	/// the first frame rendered after it is loaded, and errors are logged.
+	#[cfg(target_arch = "wasm32")]
+	pub fn open_texture_dialog(&mut self) {
+		let dialog = rfd::AsyncFileDialog::new()
+			.set_title("Open Texture")
+			.add_filter("Images", TEXTURE_EXTENSIONS);
+		self.texture_loader.load_picked(dialog);
+	}
+
+	/// Asks the user where to save the scene, with a native file dialog.
+	///
+	/// NOTE: This blocks until the dialog is closed, so it must not be called while
+	/// a frame is being rendered.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn save_scene_dialog() -> Option<PathBuf> {
+		rfd::FileDialog::new()
+			.set_title("Save Scene")
+			.add_filter("Scene", &["json", "ron"])
+			.save_file()
+	}
+
+	/// Recompiles the shader and rebuilds the pipeline if the shader file changed
+	/// since the last call. If that fails, the old pipeline is kept.
+	#[cfg(feature = "hot-reload")]
+	pub fn try_reload_shader(&mut self) -> Result<()> {
+		let Some(watcher) = &self.shader_watcher else {
+			return Ok(());
+		};
+		if !watcher.take_changed() {
+			return Ok(());
+		}
+		let path = watcher.path();
+		let src = std::fs::read_to_string(path)
+			.wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+		let src = shader::shader_source(&src, self.object_uniforms.is_none())?;
+
+		// Catch validation errors instead of letting them panic.
+		self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+		let shader = self
+			.device
+			.create_shader_module(wgpu::ShaderModuleDescriptor {
+				label: Some("shader.wgsl"),
+				source: wgpu::ShaderSource::Wgsl(src.as_str().into()),
+			});
+		let shader_hash = descriptor_hash(&src);
+		// Validates the shader against the default pipeline.
+		let key = PipelineKey::mesh(BlendMode::default(), StencilMode::default());
+		let mut cache = std::mem::take(&mut self.pipeline_cache);
+		let pipeline = self.create_pipeline(&mut cache, &shader, shader_hash, key);
+		self.pipeline_cache = cache;
+		if let Some(err) = pollster::block_on(self.device.pop_error_scope()) {
+			bail!("Failed to reload {}: {err}", path.display());
+		}
+		self.shadow_map.set_shader(&self.device, &shader);
+		self.cascaded_shadows.set_shader(&self.device, &shader);
+		for point_shadow in &mut self.point_shadows {
+			point_shadow.set_shader(&self.device, &shader);
+		}
+		if let Some(gbuffer) = &mut self.gbuffer {
+			gbuffer.set_shader(&self.device, &shader);
+		}
+		if let Some(picking) = &mut self.picking {
+			picking.set_shader(&self.device, &shader);
+		}
+		self.shader = Arc::new(shader);
+		self.shader_hash = shader_hash;
+		self.pipelines.clear();
+		self.pending_pipelines.clear();
+		self.pipelines.insert(key, pipeline);
+		info!("Reloaded {}", path.display());
+		Ok(())
+	}
+
+	/// Sets the template of the window title, see [`TitleFormatter`]. Fails if
+	/// it has unknown tokens.
+	pub fn set_title_format(&mut self, template: &str) -> Result<()> {
+		self.title_formatter = TitleFormatter::new(template)?;
+		Ok(())
+	}
+
+	pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+		&self.adapter_info
+	}
+
+	/// The time the GPU spent on the latest frame whose timings have been read
+	/// back, in microseconds. This lags a frame or two behind, and is 0 when the
+	/// adapter doesn't support timestamp queries.
+	pub fn gpu_frame_time_us(&self) -> f64 {
+		self.profiler
+			.as_ref()
+			.map_or(0., |profiler| profiler.total_ms() as f64 * 1000.)
+	}
+
+	/// Sets how the mesh is blended with the background. Objects are blended by
+	/// their [`Material::blend`].
+	///
+	/// With [`BlendMode::Alpha`], transparent triangles are only blended with
+	/// what was drawn before them, so they must be submitted back to front, both
+	/// within the mesh and across instances. [`BlendMode::Transparent`] is only
+	/// supported by objects, the mesh is blended with `Alpha` instead.
+	pub fn set_blend_mode(&mut self, mode: BlendMode) {
+		self.blend_mode = if mode == BlendMode::Transparent {
+			warn!("The mesh doesn't support order-independent transparency");
+			BlendMode::Alpha
+		} else {
+			mode
+		};
+	}
+
+	/// Sets how the mesh uses the stencil buffer. Objects have their own
+	/// [`RenderObject::stencil`]. The stencil buffer is cleared to 0 every frame.
+	pub fn set_stencil_mode(&mut self, mode: StencilMode) {
+		self.stencil_mode = self.supported_stencil_mode(mode);
+	}
+
+	/// `mode`, or `Disabled` with a warning if there is no stencil buffer.
+	fn supported_stencil_mode(&self, mode: StencilMode) -> StencilMode {
+		if mode != StencilMode::Disabled && !self.depth_format.has_stencil_aspect() {
+			warn!("Stencil mode {mode:?} ignored, as there is no stencil buffer");
+			return StencilMode::Disabled;
+		}
+		mode
+	}
+
+	/// The layouts of the main pipelines, without and with skinning, given the
+	/// material, camera and light bind group layouts.
+	fn create_pipeline_layouts(
+		device: &wgpu::Device,
+		layouts: [&wgpu::BindGroupLayout; 3],
+		object_uniforms: Option<&ObjectUniforms>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+		skin: &Skin,
+	) -> (wgpu::PipelineLayout, wgpu::PipelineLayout) {
+		let mut bind_group_layouts = layouts.to_vec();
+		bind_group_layouts.extend(object_uniforms.map(|u| u.bind_group_layout()));
+		let pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Render Pipeline Layout"),
+				bind_group_layouts: &bind_group_layouts,
+				push_constant_ranges,
+			});
+		let [material, camera, light] = layouts;
+		let skinned_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Skinned Render Pipeline Layout"),
+				bind_group_layouts: &[material, camera, light, skin.layout()],
+				push_constant_ranges: &[],
+			});
+		(pipeline_layout, skinned_pipeline_layout)
+	}
+
+	/// A uniform buffer holding `value`, which can be written to later.
+	fn create_uniform_buffer<T: bytemuck::Pod>(
+		device: &wgpu::Device,
+		label: &str,
+		value: &T,
+	) -> wgpu::Buffer {
+		device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some(label),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			contents: bytemuck::bytes_of(value),
+		})
+	}
+
+	/// Creates the `key` variant of the pipeline using `shader`, whose source
+	/// hashes to `shader_hash`, or gets it from `cache` if it is still in use.
+	fn create_pipeline(
+		&self,
+		cache: &mut PipelineCache,
+		shader: &wgpu::ShaderModule,
+		shader_hash: u64,
+		key: PipelineKey,
+	) -> Arc<wgpu::RenderPipeline> {
+		let layout = self.pipeline_layout_for(key);
+		let (format, sample_count) = (self.color_format(), self.sample_count);
+		let (depth_format, reverse_z) = (self.depth_format, self.reverse_z);
+		let hash = self.pipeline_hash(shader_hash, key);
+		cache.get_or_create(&self.device, hash, |device| {
+			create_pipeline(
+				device,
+				layout,
+				shader,
+				format,
+				sample_count,
+				depth_format,
+				reverse_z,
+				key,
+			)
+		})
+	}
+
+	fn pipeline_layout_for(&self, key: PipelineKey) -> &Arc<wgpu::PipelineLayout> {
+		if key.skinned {
+			&self.skinned_pipeline_layout
+		} else {
+			&self.pipeline_layout
+		}
+	}
+
+	/// The [`descriptor_hash`] of the `key` variant of the pipeline using a
+	/// shader whose source hashes to `shader_hash`.
+	fn pipeline_hash(&self, shader_hash: u64, key: PipelineKey) -> u64 {
+		// The key and formats determine the vertex buffer layouts, blending,
+		// topology and depth stencil state.
+		descriptor_hash((
+			shader_hash,
+			key,
+			self.color_format(),
+			self.sample_count,
+			self.depth_format,
+			self.reverse_z,
+		))
+	}
+
+	/// Starts creating the `key` variant of the pipeline in the background,
+	/// unless `cache` still has it. Returns it, or its placeholder until
+	/// [`Self::swap_pipelines_when_ready`] replaces it.
+	fn create_pipeline_in_background(
+		&mut self,
+		cache: &mut PipelineCache,
+		key: PipelineKey,
+	) -> Arc<wgpu::RenderPipeline> {
+		let hash = self.pipeline_hash(self.shader_hash, key);
+		if let Some(pipeline) = cache.get(hash) {
+			return pipeline;
+		}
+		let layout = self.pipeline_layout_for(key).clone();
+		let (format, sample_count) = (self.color_format(), self.sample_count);
+		let (depth_format, reverse_z) = (self.depth_format, self.reverse_z);
+		let (thread_layout, shader) = (layout.clone(), self.shader.clone());
+		let pending = self.create_pipeline_async(move |device| {
+			create_pipeline(
+				device,
+				&thread_layout,
+				&shader,
+				format,
+				sample_count,
+				depth_format,
+				reverse_z,
+				key,
+			)
+		});
+		self.pending_pipelines.insert(key, (hash, pending));
+
+		let push_constants = self.object_uniforms.is_none();
+		let placeholder_shader = self.placeholder_shader.get_or_insert_with(|| {
+			let src = format!(
+				"{}\n{}",
+				model_declaration(push_constants),
+				include_str!("placeholder.wgsl")
+			);
+			self.device
+				.create_shader_module(wgpu::ShaderModuleDescriptor {
+					label: Some("placeholder.wgsl"),
+					source: wgpu::ShaderSource::Wgsl(src.into()),
+				})
+		});
+		let placeholder_hash = descriptor_hash(("placeholder.wgsl", hash));
+		cache.get_or_create(&self.device, placeholder_hash, |device| {
+			create_pipeline(
+				device,
+				&layout,
+				placeholder_shader,
+				format,
+				sample_count,
+				depth_format,
+				reverse_z,
+				key,
+			)
+		})
+	}
+
+	/// Replaces the placeholders of the variants of the pipeline that were
+	/// created in the background since the last call. Those that failed keep
+	/// their placeholder.
+	fn swap_pipelines_when_ready(&mut self) {
+		let cache = &mut self.pipeline_cache;
+		self.pending_pipelines.retain(|key, (hash, pending)| {
+			match pending.try_take() {
+				None => return true,
+				Some(Ok(pipeline)) => {
+					self.pipelines.insert(*key, cache.insert(*hash, pipeline));
+				}
+				Some(Err(err)) => error!("{err:#}, drawing {key:?} with a placeholder"),
+			}
+			false
+		});
+	}
+
+	/// Starts creating a render pipeline with `make_pipeline` on another thread,
+	/// so that compiling its shaders doesn't hold up frames. It is created right
+	/// away on the web.
+	pub fn create_pipeline_async(
+		&self,
+		make_pipeline: impl MakePipeline,
+	) -> PendingPipeline {
+		PendingPipeline::spawn(self.device.clone(), make_pipeline)
+	}
+
+	/// Sets whether the variants of the pipeline that the mesh and objects need
+	/// are created in the background, rather than before the frame that first
+	/// draws with them. Until they are ready, surfaces are drawn in a solid color
+	/// by a placeholder, and they replace it at the start of a later frame.
+	pub fn set_async_pipelines(&mut self, enabled: bool) {
+		self.async_pipelines = enabled;
+	}
+
+	pub fn async_pipelines(&self) -> bool {
+		self.async_pipelines
+	}
+
+	/// How many variants of the pipeline are still being created in the
+	/// background, to show progress while loading.
+	pub fn pending_pipelines(&self) -> usize {
+		self.pending_pipelines.len()
+	}
+
+	/// Creates the pipelines needed to draw the mesh and objects, that don't
+	/// exist yet.
+	fn prepare_pipelines(&mut self) {
+		let skinned = (!self.skinned_meshes.is_empty())
+			.then(|| PipelineKey::skinned(self.blend_mode));
+		let terrain = (self.terrain.as_ref())
+			.map(|terrain| PipelineKey::terrain(&terrain.material));
+		let keys =
+			std::iter::once(PipelineKey::mesh(self.blend_mode, self.stencil_mode))
+				.chain(self.render_queue.iter().map(PipelineKey::object))
+				.chain(self.lod_objects.iter().map(PipelineKey::lod_object))
+				.chain(
+					(self.cull_lod.iter())
+						.map(|(_, m, _)| PipelineKey::lod_material(m)),
+				)
+				.chain(self.frame_objects.iter().map(PipelineKey::object))
+				.chain(
+					Self::world_draws(&self.world, &self.resources)
+						.into_iter()
+						.map(|(_, mesh, material)| PipelineKey::entity(mesh, material)),
+				)
+				.chain(skinned)
+				.chain(terrain)
+				.collect::<Vec<_>>();
+		self.swap_pipelines_when_ready();
+		let mut cache = std::mem::take(&mut self.pipeline_cache);
+		for key in keys {
+			if self.pipelines.contains_key(&key) {
+				continue;
+			}
+			let pipeline = if self.async_pipelines {
+				self.create_pipeline_in_background(&mut cache, key)
+			} else {
+				self.create_pipeline(&mut cache, &self.shader, self.shader_hash, key)
+			};
+			self.pipelines.insert(key, pipeline);
+		}
+		self.pipeline_cache = cache;
+	}
+
+	/// Skips drawing objects whose bounding boxes were hidden in the previous
+	/// frame, as tested with queries. Needs
+	/// [`wgpu::Features::PIPELINE_STATISTICS_QUERY`], otherwise everything is
+	/// still drawn.
+	pub fn set_occlusion_culling(&mut self, enabled: bool) {
+		if !enabled {
+			self.occlusion = None;
+		} else if self.occlusion.is_none() {
+			self.occlusion = OcclusionCuller::new(
+				&self.device,
+				&self.camera_bind_group_layout,
+				self.depth_format,
+				self.sample_count,
+				self.reverse_z,
+			);
+			if self.occlusion.is_none() {
+				warn!("Occlusion culling needs pipeline statistics queries");
+			}
+		}
+	}
+
+	pub fn occlusion_culling(&self) -> bool {
+		self.occlusion.is_some()
+	}
+
+	/// Finds the objects of `render_queue` in the main camera's frustum, through
+	/// `spatial_hash` rebuilt from their boxes in world space.
+	fn update_visible_objects(&mut self) {
+		self.spatial_hash.clear();
+		for (i, object) in self.render_queue.iter().enumerate() {
+			let aabb = object.mesh.aabb.transformed(&object.transform);
+			self.spatial_hash.insert(i, &aabb);
+		}
+		self.visible_objects.clear();
+		self.visible_objects.resize(self.render_queue.len(), false);
+		for i in self.spatial_hash.query_frustum(&self.camera.frustum()) {
+			self.visible_objects[i] = true;
+		}
+	}
+
+	/// Whether the `index`th object of `render_queue` may be visible from `eye`,
+	/// the main camera's position. Objects with a stencil mode are always drawn,
+	/// as others may depend on their stencil values.
+	fn is_object_visible(&self, index: usize, eye: &Point3<f32>) -> bool {
+		let object = &self.render_queue[index];
+		if object.stencil != StencilMode::Disabled {
+			return true;
+		}
+		if !self.visible_objects.get(index).copied().unwrap_or(true) {
+			return false;
+		}
+		let Some(occlusion) = &self.occlusion else {
+			return true;
+		};
+		if occlusion.is_visible(index) {
+			return true;
+		}
+		// From inside its box, the box's faces are behind the camera or the object.
+		let aabb = &object.mesh.aabb;
+		match object.transform.try_inverse() {
+			Some(inverse) => {
+				let eye = inverse.transform_point(eye);
+				(0..3).all(|axis| {
+					aabb.min[axis] <= eye[axis] && eye[axis] <= aabb.max[axis]
+				})
+			}
+			None => true,
+		}
+	}
+
+	/// Enables bloom with the given settings, or disables it with `None`.
+	pub fn set_bloom(&mut self, settings: Option<BloomSettings>) {
+		let size = self.render_size();
+		(self.post_effects).set_bloom(&self.device, &self.queue, settings, size);
+	}
+
+	pub fn bloom(&self) -> Option<BloomSettings> {
+		self.post_effects.bloom()
+	}
+
+	/// Enables screen-space ambient occlusion with the given settings, or
+	/// disables it with `None`. It darkens the ambient and diffuse light of the
+	/// main camera's view.
+	pub fn set_ssao(&mut self, settings: Option<SsaoSettings>) {
+		let Some(settings) = settings else {
+			if self.ssao.take().is_some() {
+				self.update_gbuffer();
+				self.recreate_light_bind_group();
+			}
+			return;
+		};
+		if self.ssao.is_none() {
+			let (width, height) = self.render_size();
+			let mut ssao =
+				SsaoPass::new(&self.device, &self.queue, width, height, self.reverse_z);
+			ssao.update(&self.queue, &*self.camera, &self.camera.view());
+			self.ssao = Some(ssao);
+			self.update_gbuffer();
+			self.recreate_light_bind_group();
+		}
+		if let Some(ssao) = &mut self.ssao {
+			ssao.set_settings(&self.queue, settings);
+		}
+	}
+
+	pub fn ssao(&self) -> Option<SsaoSettings> {
+		self.ssao.as_ref().map(SsaoPass::settings)
+	}
+
+	/// Enables ambient occlusion traced through a distance field of the objects
+	/// with the given settings, or disables it with `None`. Like SSAO, it darkens
+	/// the ambient and diffuse light of the main camera's view, and replaces
+	/// SSAO's while both are enabled.
+	///
+	/// The distance field is baked from the objects added with
+	/// [`Self::add_object`] as they are now, and again every time this is called
+	/// with settings.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	pub fn set_sdf_ao(&mut self, settings: Option<SdfAoSettings>) -> Result<()> {
+		let Some(settings) = settings else {
+			if self.sdf_ao.take().is_some() {
+				self.update_gbuffer();
+				self.recreate_light_bind_group();
+			}
+			return Ok(());
+		};
+		let (width, height) = self.render_size();
+		let mut sdf_ao = SdfAoPass::bake(
+			&self.device,
+			&self.queue,
+			&self.render_queue,
+			width,
+			height,
+			self.reverse_z,
+		)?;
+		sdf_ao.set_settings(&self.queue, settings);
+		sdf_ao.update(&self.queue, &*self.camera, &self.camera.view());
+		self.sdf_ao = Some(sdf_ao);
+		self.update_gbuffer();
+		self.recreate_light_bind_group();
+		Ok(())
+	}
+
+	pub fn sdf_ao(&self) -> Option<SdfAoSettings> {
+		self.sdf_ao.as_ref().map(SdfAoPass::settings)
+	}
+
+	/// Enables or disables screen-space reflections, blended over the scene after
+	/// the main pass and before bloom. Their knobs are on [`Self::ssr_mut`].
+	pub fn set_ssr(&mut self, enable_ssr: bool) {
+		let size = self.render_size();
+		let toggled = self.post_effects.set_ssr(
+			&self.device,
+			&self.queue,
+			&*self.camera,
+			enable_ssr,
+			size,
+		);
+		if toggled {
+			self.update_gbuffer();
+		}
+	}
+
+	pub fn ssr(&self) -> Option<&SsrPass> {
+		self.post_effects.ssr()
+	}
+
+	/// The reflections' knobs, which apply from the next frame.
+	pub fn ssr_mut(&mut self) -> Option<&mut SsrPass> {
+		self.post_effects.ssr_mut()
+	}
+
+	/// Resolves MSAA with a compute shader weighting the samples by `filter`, or
+	/// in hardware with `None`. Ignored without MSAA, or if the device lacks
+	/// compute shaders.
+	pub fn set_resolve_filter(&mut self, filter: Option<ResolveFilter>) {
+		let Some(filter) = filter else {
+			self.msaa_resolve = None;
+			return;
+		};
+		if self.sample_count == 1
+			|| self.device.limits().max_compute_workgroups_per_dimension == 0
+		{
+			return;
+		}
+		match &mut self.msaa_resolve {
+			Some(msaa_resolve) => {
+				self.frame_stats.bytes_uploaded +=
+					msaa_resolve.set_filter(&self.queue, filter);
+			}
+			None => {
+				let (width, height) = self.render_size();
+				self.msaa_resolve = Some(MsaaResolvePass::new(
+					&self.device,
+					&self.queue,
+					self.color_format(),
+					width,
+					height,
+					filter,
+				));
+			}
+		}
+	}
+
+	/// The filter MSAA is resolved with, or `None` if it is resolved in hardware.
+	pub fn resolve_filter(&self) -> Option<ResolveFilter> {
+		self.msaa_resolve.as_ref().map(MsaaResolvePass::filter)
+	}
+
+	/// Enables or disables temporal anti-aliasing, which jitters the camera's
+	/// projection within a pixel every frame and blends the frames together,
+	/// after the main pass and before screen-space reflections. Its knobs are
+	/// on [`Self::taa_mut`].
+	pub fn set_taa(&mut self, enable_taa: bool) {
+		let size = self.render_size();
+		if self.post_effects.set_taa(&self.device, enable_taa, size) {
+			self.update_gbuffer();
+			// With or without the jitter.
+			self.upload_camera(&self.camera.view());
+		}
+	}
+
+	pub fn taa(&self) -> Option<&TaaPass> {
+		self.post_effects.taa()
+	}
+
+	/// The anti-aliasing's knobs, which apply from the next frame.
+	pub fn taa_mut(&mut self) -> Option<&mut TaaPass> {
+		self.post_effects.taa_mut()
+	}
+
+	/// The number of frames rendered so far.
+	pub fn frame_count(&self) -> u64 {
+		self.frame_count
+	}
+
+	/// Enables or disables outlines for cel shading, drawn over the opaque scene
+	/// where its depth or normals change sharply. Their knobs are on
+	/// [`Self::cel_outline_mut`].
+	pub fn set_cel_outline(&mut self, enable_cel_outline: bool) {
+		if !enable_cel_outline {
+			if self.cel_outline.take().is_some() {
+				self.update_gbuffer();
+			}
+			return;
+		}
+		if self.cel_outline.is_none() {
+			let format = self.color_format();
+			let mut cel_outline =
+				CelOutlinePass::new(&self.device, format, self.reverse_z);
+			cel_outline.update(&self.queue, &*self.camera);
+			self.cel_outline = Some(cel_outline);
+			self.update_gbuffer();
+		}
+	}
+
+	pub fn cel_outline(&self) -> Option<&CelOutlinePass> {
+		self.cel_outline.as_ref()
+	}
+
+	/// The outlines' knobs, which apply from the next frame.
+	pub fn cel_outline_mut(&mut self) -> Option<&mut CelOutlinePass> {
+		self.cel_outline.as_mut()
+	}
+
+	/// Enables volumetric fog, with its density sliced `depth_slices` times by
+	/// distance from the camera, or disables it with `None`. It is lit by the
+	/// light where the shadow map doesn't hide it, and blended over the scene
+	/// after everything else. Its knobs are on [`Self::volumetric_fog_mut`].
+	///
+	/// # Panics
+	/// If `depth_slices` is 0.
+	pub fn set_volumetric_fog(&mut self, depth_slices: Option<u32>) {
+		let Some(depth_slices) = depth_slices else {
+			if self.volumetric_fog.take().is_some() {
+				self.update_gbuffer();
+			}
+			return;
+		};
+		let (width, height) = self.render_size();
+		let mut volumetric_fog = VolumetricFog::new(
+			&self.device,
+			self.color_format(),
+			width,
+			height,
+			depth_slices,
+			self.reverse_z,
+		);
+		volumetric_fog.update(&self.queue, &*self.camera, &self.camera.view());
+		self.volumetric_fog = Some(volumetric_fog);
+		self.update_gbuffer();
+	}
+
+	pub fn volumetric_fog(&self) -> Option<&VolumetricFog> {
+		self.volumetric_fog.as_ref()
+	}
+
+	/// The volumetric fog's knobs, which apply from the next frame.
+	pub fn volumetric_fog_mut(&mut self) -> Option<&mut VolumetricFog> {
+		self.volumetric_fog.as_mut()
+	}
+
+	/// Enables or disables depth of field, which blurs the scene by how far it
+	/// is out of focus, after screen-space reflections and before bloom. Its
+	/// knobs are on [`Self::depth_of_field_mut`].
+	pub fn set_depth_of_field(&mut self, enable_depth_of_field: bool) {
+		let size = self.render_size();
+		let toggled = self.post_effects.set_depth_of_field(
+			&self.device,
+			&self.queue,
+			&*self.camera,
+			enable_depth_of_field,
+			size,
+		);
+		if toggled {
+			self.update_gbuffer();
+		}
+	}
+
+	pub fn depth_of_field(&self) -> Option<&DepthOfFieldPass> {
+		self.post_effects.depth_of_field()
+	}
+
+	/// The depth of field's knobs, which apply from the next frame.
+	pub fn depth_of_field_mut(&mut self) -> Option<&mut DepthOfFieldPass> {
+		self.post_effects.depth_of_field_mut()
+	}
+
+	/// Creates the G-buffer while SSAO, SDF AO, SSR, TAA, the cel outlines, the
+	/// volumetric fog, the stencil decals or the depth of field read it, and
+	/// frees it otherwise.
+	fn update_gbuffer(&mut self) {
+		if self.ssao.is_none()
+			&& self.sdf_ao.is_none()
+			&& !self.post_effects.reads_gbuffer()
+			&& self.cel_outline.is_none()
+			&& self.volumetric_fog.is_none()
+			&& self.stencil_decals.is_none()
+		{
+			self.gbuffer = None;
+			return;
+		}
+		if self.gbuffer.is_none() {
+			let (width, height) = self.render_size();
+			self.gbuffer = Some(GBuffer::new(
+				&self.device,
+				width,
+				height,
+				&self.shader,
+				&self.camera_bind_group_layout,
+				self.object_uniforms.as_ref().map(|u| u.bind_group_layout()),
+				match self.object_uniforms {
+					Some(_) => &[],
+					None => &[MODEL_PUSH_CONSTANT_RANGE],
+				},
+				self.reverse_z,
+			));
+		}
+	}
+
+	/// Changes how HDR frames are tone mapped. Does nothing unless HDR was enabled
+	/// with [`RenderStateBuilder::hdr`].
+	pub fn set_tone_mapping(&mut self, settings: ToneMapSettings) {
+		if !self.post_effects.set_tone_mapping(&self.queue, settings) {
+			warn!("Ignoring tone mapping settings, as HDR is disabled");
+		}
+	}
+
+	/// How HDR frames are tone mapped, or `None` without HDR.
+	pub fn tone_mapping(&self) -> Option<ToneMapSettings> {
+		self.post_effects.tone_mapping()
+	}
+
+	pub fn clear_color(&self) -> wgpu::Color {
+		self.clear_color
+	}
+
+	/// Sets the color the scene is drawn over, as written into the frame: in
+	/// linear light for sRGB frames, and as is for others. The HDR clear color is
+	/// set to the same color in linear light.
+	pub fn set_clear_color(&mut self, color: wgpu::Color) {
+		self.clear_color = color;
+		self.hdr_clear_color = linear_clear_color(color, self.config.format);
+	}
+
+	pub fn hdr_clear_color(&self) -> [f32; 4] {
+		self.hdr_clear_color
+	}
+
+	/// Sets the color the scene is drawn over with HDR, in linear light before
+	/// tone mapping, so it may be brighter than 1. Overrides the color from
+	/// [`Self::set_clear_color`] until it is set again.
+	pub fn set_hdr_clear_color(&mut self, color: [f32; 4]) {
+		self.hdr_clear_color = color;
+	}
+
+	/// The format the scene is drawn in: [`HDR_FORMAT`] with HDR, the frame's
+	/// format otherwise.
+	fn color_format(&self) -> wgpu::TextureFormat {
+		if self.hdr {
+			HDR_FORMAT
+		} else {
+			self.config.format
+		}
+	}
+
+	/// Enables chromatic aberration and a vignette with the given settings, or
+	/// disables them with `None`.
+	pub fn set_post_process(&mut self, settings: Option<PostProcessSettings>) {
+		let size = self.render_size();
+		(self.post_effects).set_post_process(&self.device, &self.queue, settings, size);
+	}
+
+	pub fn post_process(&self) -> Option<PostProcessSettings> {
+		self.post_effects.post_process()
+	}
+
+	/// Enables or disables the color inversion post-processing demo.
+	pub fn set_invert_colors(&mut self, enabled: bool) {
+		let size = self.render_size();
+		self.post_effects.set_invert(&self.device, enabled, size);
+	}
+
+	pub fn invert_colors(&self) -> bool {
+		self.post_effects.invert()
+	}
+
+	/// Draws the scene at the resolution `scaler` picks from the time frames
+	/// take, and upscales it to the frame with bilinear filtering. Overlays like
+	/// text and the debug UI are drawn at full resolution.
+	pub fn enable_dynamic_resolution(&mut self, scaler: DynamicResolutionScaler) {
+		self.resolution_scaler = Some(scaler);
+		let size = self.render_size();
+		(self.post_effects).set_dynamic_resolution(&self.device, Some(size));
+		self.resize_targets(self.config.format);
+	}
+
+	/// Draws the scene at the frame's resolution again.
+	pub fn disable_dynamic_resolution(&mut self) {
+		self.post_effects.set_dynamic_resolution(&self.device, None);
+		if self.resolution_scaler.take().is_some() {
+			self.resize_targets(self.config.format);
+		}
+	}
+
+	/// The scaler picking the resolution, with its current scale, if dynamic
+	/// resolution is enabled.
+	pub fn dynamic_resolution(&self) -> Option<&DynamicResolutionScaler> {
+		self.resolution_scaler.as_ref()
+	}
+
+	/// Shows or hides the graph of recent frame times.
+	pub fn set_show_frame_graph(&mut self, show: bool) {
+		self.show_frame_graph = show;
+	}
+
+	pub fn show_frame_graph(&self) -> bool {
+		self.show_frame_graph
+	}
+
+	/// Shows or hides the frame rate in the bottom left corner of the frame. It
+	/// is in the window title either way.
+	pub fn set_show_fps(&mut self, show: bool) {
+		self.show_fps = show;
+	}
+
+	pub fn show_fps(&self) -> bool {
+		self.show_fps
+	}
+
+	/// Queues text to draw over the next frame, after the scene.
+	pub fn text_renderer_mut(&mut self) -> &mut TextRenderer {
+		&mut self.text_renderer
+	}
+
+	/// Replaces the text renderer with one drawing glyphs with `backend`,
+	/// dropping any queued text.
+	pub fn set_text_backend(&mut self, backend: TextBackend) {
+		self.text_renderer = TextRenderer::new(
+			&self.device,
+			&self.queue,
+			self.config.format,
+			self.config.width,
+			self.config.height,
+			backend,
+		);
+		self.text_renderer.set_scale_factor(self.scale_factor);
+	}
+
+	/// The durations of recent frames.
+	pub fn frame_timer(&self) -> &FrameTimer {
+		&self.frame_timer
+	}
+
+	/// Stats of the last frame that was rendered.
+	pub fn last_frame_stats(&self) -> FrameStats {
+		self.last_frame_stats
+	}
+
+	/// Draws and presents a frame.
+	///
+	/// The mesh's triangles are drawn in index order, instance by instance. In
+	/// [`BlendMode::Alpha`] that order must be back to front for the result to be
+	/// correct. Objects are sorted, see [`Self::add_object`].
+	pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+		let _span = tracing::debug_span!("render").entered();
+		let start = Instant::now();
+		self.finish_texture_loads();
+		if self.fog_dirty {
+			let bytes = bytemuck::bytes_of(&self.fog);
+			self.queue.write_buffer(&self.fog_buf, 0, bytes);
+			self.frame_stats.bytes_uploaded += bytes.len() as u64;
+			self.fog_dirty = false;
+		}
+		let frame_time;
+		// Do fps calculation
+		{
+			// Values closer to 1 weight new values more.
+			const SMOOTHING_FACTOR: f32 = 0.2;
+			let now = Instant::now();
+			let elapsed = now - self.last_render;
+			frame_time = elapsed.as_secs_f32();
+			self.frame_timer.record(frame_time);
+			let new_fps = 1.0 / elapsed.as_secs_f32();
+			self.fps = self.fps * (1.0 - SMOOTHING_FACTOR) + new_fps * SMOOTHING_FACTOR;
+			self.last_render = now;
+
+			if (now - self.last_title).as_millis() > 100 {
+				let info = TitleInfo {
+					fps: self.fps,
+					frame_ms: 1000. / self.fps,
+					width: self.config.width,
+					height: self.config.height,
+					adapter: &self.adapter_info.name,
+					present_mode: self.config.present_mode,
+					gpu_ms: self.last_frame_stats.gpu_ms,
+				};
+				self.title_formatter.format_into(&info, &mut self.title);
+				if let FrameTarget::Window { window, .. } = &self.target {
+					set_window_title(window, &self.title);
+				}
+				self.last_title = now;
+			}
+			if (now - self.last_memory_report).as_secs() >= 1 {
+				debug!("GPU memory: {}", self.memory_tracker.report());
+				self.last_memory_report = now;
+			}
+		}
+
+		let render_size = self.render_size();
+		if let Some(scaler) = &mut self.resolution_scaler {
+			scaler.update(frame_time);
+		}
+		if self.render_size() != render_size {
+			self.resize_targets(self.config.format);
+		}
+
+		let begin_span = tracing::debug_span!("begin_encoder").entered();
+		let (output, view) = match &self.target {
+			FrameTarget::Window { surface, .. } => {
+				let output = surface.get_current_texture()?;
+				self.lost_frames = 0;
+				let view = output
+					.texture
+					.create_view(&wgpu::TextureViewDescriptor::default());
+				(Some(output), view)
+			}
+			FrameTarget::Headless { texture } => (
+				None,
+				texture.create_view(&wgpu::TextureViewDescriptor::default()),
+			),
+		};
+		let mut encoder =
+			self.device
+				.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+					label: Some("Render Encoder"),
+				});
+		drop(begin_span);
+
+		let record_span = tracing::debug_span!("record_passes").entered();
+		self.dispatch_compute(&mut encoder, frame_time);
+		if let Some(profiler) = &mut self.profiler {
+			profiler.begin(&mut encoder, "main");
+		}
+		self.draw_frame(&mut encoder, &view);
+		self.draw_overlays(&mut encoder, &view);
+		if let Some(profiler) = &mut self.profiler {
+			profiler.end(&mut encoder);
+			profiler.begin(&mut encoder, "debug_ui");
+		}
+		if let (Some(debug_ui), FrameTarget::Window { window, .. }) =
+			(&mut self.debug_ui, &self.target)
+		{
+			debug_ui.render(
+				window,
+				&self.device,
+				&self.queue,
+				&mut encoder,
+				&view,
+				self.fps,
+				self.profiler.as_ref(),
+			);
+		}
+		if let Some(profiler) = &mut self.profiler {
+			profiler.end(&mut encoder);
+			profiler.resolve(&mut encoder);
+		}
+
+		self.staging_belt.finish();
+		let commands = encoder.finish();
+		drop(record_span);
+		tracing::debug_span!("queue_submit").in_scope(|| {
+			self.queue
+				.submit(self.pending_commands.drain(..).chain([commands]));
+		});
+		self.staging_belt.recall();
+		if let Some(output) = output {
+			tracing::debug_span!("present").in_scope(|| output.present());
+		}
+		self.frame_stats.cpu_ms = start.elapsed().as_secs_f32() * 1000.;
+
+		if let Some(occlusion) = &mut self.occlusion {
+			occlusion.read_back(&self.device);
+		}
+		if let Some(profiler) = &mut self.profiler {
+			profiler.read_back(&self.device);
+			self.frame_stats.gpu_ms = profiler.total_ms();
+		}
+		self.last_frame_stats = std::mem::take(&mut self.frame_stats);
+		self.buffer_pool.next_frame();
+		self.update_previous_transforms();
+		self.frame_count += 1;
+		self.post_effects.next_frame(self.frame_count);
+
+		Ok(())
+	}
+
+	/// Records the compute work of the frame: the compute passes, blending the
+	/// morphed meshes, stepping the cloths by `frame_time` seconds, culling the
+	/// mesh's instances and simulating the particles.
+	fn dispatch_compute(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		frame_time: f32,
+	) {
+		// Compute and render passes can share an encoder. wgpu orders their accesses
+		// to shared buffers, so draws see the results of earlier dispatches.
+		if !self.compute_passes.is_empty()
+			|| !self.morphed_meshes.is_empty()
+			|| !self.cloths.is_empty()
+			|| self.gpu_culling.is_some()
+			|| self.particles.is_some()
+		{
+			if let Some(profiler) = &mut self.profiler {
+				profiler.begin(encoder, "compute");
+			}
+			for (pass, [x, y, z]) in &self.compute_passes {
+				pass.dispatch(encoder, *x, *y, *z);
+			}
+			for mesh in &self.morphed_meshes {
+				mesh.dispatch_blend(encoder);
+			}
+			// Long frames are cut short, so that the cloth doesn't overshoot.
+			let wind = Vector3::from(self.wind.direction) * self.wind.strength;
+			for cloth in &mut self.cloths {
+				self.frame_stats.bytes_uploaded += cloth.step(
+					&self.queue,
+					encoder,
+					frame_time.min(1. / 30.),
+					wind.into(),
+				);
+			}
+			if let Some(culler) = &self.gpu_culling {
+				self.frame_stats.bytes_uploaded += culler.cull(
+					&self.queue,
+					encoder,
+					&self.instance_buf,
+					self.num_instances,
+					self.num_indices,
+					&self.camera.frustum(),
+				);
+			}
+			if let Some((system, _)) = &mut self.particles {
+				self.frame_stats.bytes_uploaded +=
+					system.simulate(&self.queue, encoder, frame_time);
+			}
+			if let Some(profiler) = &mut self.profiler {
+				profiler.end(encoder);
+			}
+		}
+	}
+
+	/// Records drawing the frame graph, the debug lines, the gizmo and the text
+	/// over `view`.
+	fn draw_overlays(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		if self.show_frame_graph {
+			let (width, height) = self.logical_size();
+			self.frame_stats.bytes_uploaded +=
+				self.frame_graph
+					.update(&self.queue, &self.frame_timer, width, height);
+			self.frame_graph.draw(encoder, view);
+			self.frame_stats.draw_calls += 1;
+		}
+		if !self.debug_lines.is_empty() {
+			self.frame_stats.bytes_uploaded += self.debug_lines.flush(
+				&self.device,
+				&mut self.staging_belt,
+				encoder,
+				view,
+				&self.camera_bind_group,
+			);
+			self.debug_lines.clear();
+			self.frame_stats.draw_calls += 1;
+		}
+		if let Some((center, transform, scale)) = self.gizmo_at {
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Gizmo Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: None,
+			});
+			pass.set_bind_group(0, &self.camera_bind_group, &[]);
+			self.frame_stats.bytes_uploaded +=
+				self.gizmo
+					.draw(&self.queue, &mut pass, center, transform, scale);
+			self.frame_stats.draw_calls += 1;
+		}
+		if self.show_fps {
+			let text = format!("{:.1} FPS", self.fps);
+			let (_, height) = self.logical_size();
+			let y = height - 24.;
+			self.text_renderer
+				.draw_text(&text, 8., y, 16., [1., 1., 1., 1.]);
+		}
+		if !self.text_renderer.is_empty() {
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Text Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: None,
+			});
+			self.frame_stats.bytes_uploaded +=
+				self.text_renderer
+					.flush(&self.device, &self.queue, &mut pass);
+			drop(pass);
+			self.frame_stats.draw_calls += 1;
+		}
+	}
+
+	/// Draws and presents a frame like [`Self::render`], with `objects` drawn
+	/// after the scene in that frame only.
+	///
+	/// The opaque objects are split between rayon's threads, which record them
+	/// into command buffers of their own. These are submitted in order, between
+	/// the scene and the effects drawn over it. Objects with a stencil mode and
+	/// transparent objects are drawn in order, so they are recorded on this
+	/// thread.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn render_mt(
+		&mut self,
+		objects: &[RenderObject],
+	) -> Result<(), wgpu::SurfaceError> {
+		self.frame_objects = objects
+			.iter()
+			.map(|object| RenderObject {
+				stencil: self.supported_stencil_mode(object.stencil),
+				..object.clone()
+			})
+			.collect();
+		let result = self.render();
+		self.frame_objects.clear();
+		result
+	}
+
+	/// Renders the scene into an offscreen texture and reads it back.
+	///
+	/// Surface textures can't be copied from on all backends, so this draws a
+	/// separate frame rather than capturing the last presented one. The debug UI
+	/// is not included.
+	///
+	/// NOTE: This waits for the GPU to finish rendering. Mapping is asynchronous
+	/// on the web, so this is native only.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn capture_screenshot(&mut self) -> Result<image::RgbaImage> {
+		use wgpu::TextureFormat as F;
+		let bgra = match self.config.format {
+			F::Rgba8Unorm | F::Rgba8UnormSrgb => false,
+			F::Bgra8Unorm | F::Bgra8UnormSrgb => true,
+			format => bail!("Can't take screenshots of surface format {format:?}"),
+		};
+		let (width, height) = (self.config.width, self.config.height);
+		let size = wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		};
+		// The pipeline renders to the surface format, so we use it here too.
+		let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Screenshot Texture"),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: self.config.format,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::COPY_SRC,
+			view_formats: &[],
+		});
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+		// Rows of buffer copies must be padded to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+		const ALIGN: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		let row_bytes = width * 4;
+		let padded_row_bytes = row_bytes + (ALIGN - row_bytes % ALIGN) % ALIGN;
+		let buffer_size = padded_row_bytes as u64 * height as u64;
+		let buffer = self.buffer_pool.acquire(
+			&self.device,
+			buffer_size,
+			wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		);
+
+		let mut encoder =
+			self.device
+				.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+					label: Some("Screenshot Encoder"),
+				});
+		self.draw_frame(&mut encoder, &view);
+		encoder.copy_texture_to_buffer(
+			texture.as_image_copy(),
+			wgpu::ImageCopyBuffer {
+				buffer: &buffer,
+				layout: wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: Some(padded_row_bytes),
+					rows_per_image: None,
+				},
+			},
+			size,
+		);
+		self.queue
+			.submit(self.pending_commands.drain(..).chain([encoder.finish()]));
+
+		// The pooled buffer may be bigger than the image.
+		let slice = buffer.slice(..buffer_size);
+		let (tx, rx) = std::sync::mpsc::channel();
+		slice.map_async(wgpu::MapMode::Read, move |result| {
+			tx.send(result).ok();
+		});
+		self.device.poll(wgpu::Maintain::Wait);
+		rx.recv()
+			.wrap_err("Screenshot buffer was never mapped")?
+			.wrap_err("Failed to map screenshot buffer")?;
+		let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+		for row in slice
+			.get_mapped_range()
+			.chunks_exact(padded_row_bytes as usize)
+		{
+			pixels.extend_from_slice(&row[..row_bytes as usize]);
+		}
+		buffer.unmap();
+		buffer.release();
+		if bgra {
+			for pixel in pixels.chunks_exact_mut(4) {
+				pixel.swap(0, 2);
+			}
+		}
+		image::RgbaImage::from_raw(width, height, pixels)
+			.ok_or_else(|| eyre!("Screenshot has the wrong size"))
+	}
+
+	/// Records drawing the scene into `view`, followed by post-processing.
+	fn draw_frame(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		// Taken out so the scene can be drawn into them while `self` is borrowed.
+		let mut gbuffer = self.gbuffer.take();
+		if let Some(gbuffer) = &mut gbuffer {
+			self.frame_stats.bytes_uploaded += self.upload_motion_transforms(gbuffer);
+		}
+		let post_effects = self.post_effects.take();
+		let mut graph = RenderGraph::new();
+		let output = graph.import(view);
+		let scene_target =
+			post_effects.add_passes(&mut graph, output, gbuffer.as_ref());
+		let (device, queue) = (self.device.clone(), self.queue.clone());
+		// Added last, but drawn first as the effects read what it draws.
+		graph.add_pass("scene", &[], &[scene_target], |encoder, res| {
+			self.draw_scene(encoder, res.view(scene_target), gbuffer.as_ref());
+			self.draw_decals(encoder, res.view(scene_target));
+			if let Some(gbuffer) = &gbuffer {
+				self.draw_stencil_decals(encoder, res.view(scene_target), gbuffer);
+			}
+			#[cfg(not(target_arch = "wasm32"))]
+			if !self.frame_objects.is_empty() {
+				self.draw_frame_objects(encoder, res.view(scene_target));
+			}
+			self.resolve_msaa(encoder, res.view(scene_target));
+			if let (Some(cel_outline), Some(gbuffer)) = (&self.cel_outline, &gbuffer) {
+				cel_outline.apply(
+					res.device,
+					encoder,
+					gbuffer.depth_view(),
+					gbuffer.normal_view(),
+					res.view(scene_target),
+				);
+				self.frame_stats.draw_calls += 1;
+			}
+			self.draw_wboit(encoder, res.view(scene_target));
+			if let (Some(volumetric_fog), Some(gbuffer)) =
+				(&mut self.volumetric_fog, &gbuffer)
+			{
+				// The shadow map's matrix is only known once it is drawn.
+				self.frame_stats.bytes_uploaded += volumetric_fog.set_light(
+					&self.queue,
+					&self.light,
+					&self.shadow_map.light_view_proj,
+				);
+				volumetric_fog.apply(
+					res.device,
+					encoder,
+					gbuffer.depth_view(),
+					&self.shadow_map,
+					res.view(scene_target),
+				);
+				self.frame_stats.draw_calls += 1;
+			}
+		});
+		graph
+			.execute(&device, &queue, encoder)
+			.expect("The frame's passes form no cycle");
+		let draws = post_effects.draws(gbuffer.is_some());
+		self.frame_stats.draw_calls += draws;
+		self.frame_stats.texture_switches += draws;
+		self.gbuffer = gbuffer;
+		self.post_effects = post_effects;
+	}
+
+	/// Records resolving the multisampled texture into `view` with the
+	/// [`ResolveFilter`], if one is set, once the passes drawing into it are done.
+	fn resolve_msaa(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		let (Some((_, msaa_view)), Some(msaa_resolve)) =
+			(&self.msaa_texture, &self.msaa_resolve)
+		else {
+			return;
+		};
+		let (width, height) = self.render_size();
+		msaa_resolve.apply(&self.device, encoder, msaa_view, view, width, height);
+		// The resolve, then the copy.
+		self.frame_stats.draw_calls += 2;
+	}
+
+	/// Records drawing the particles into `view` for every camera, in a pass of
+	/// their own after the main pass, as they read its depth buffer.
+	fn draw_particles(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		let Some((system, pipeline)) = &self.particles else {
+			return;
+		};
+		// With MSAA, the depth buffer can't be read, and particles don't fade.
+		let depth_view = (self.sample_count == 1).then(|| {
+			// The stencil can't be read along with the depth.
+			self.depth_tex.create_view(&wgpu::TextureViewDescriptor {
+				aspect: wgpu::TextureAspect::DepthOnly,
+				..Default::default()
+			})
+		});
+		let proj = self.camera.proj_view_from(&IsometryMatrix3::identity());
+		self.frame_stats.bytes_uploaded +=
+			pipeline.update(&self.queue, system, depth_view.is_some(), &proj);
+		let soft_bind_group = pipeline.bind_depth(&self.device, depth_view.as_ref());
+		let (view, resolve_target) =
+			msaa_attachments(&self.msaa_texture, &self.msaa_resolve, view);
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Particle Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			// Read-only, so that it can also be bound.
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_view,
+				depth_ops: None,
+				stencil_ops: None,
+			}),
+		});
+		let (width, height) = self.render_size();
+		let scale = self.render_scale();
+		// The main camera's view comes first, as in `draw_scene`.
+		let views = std::iter::once((&self.camera_bind_group, self.viewport)).chain(
+			self.views
+				.iter()
+				.map(|view| (&view.bind_group, Some(view.viewport))),
+		);
+		for (view_index, (camera_bind_group, viewport)) in views.enumerate() {
+			if let Some(viewport) = viewport {
+				let Some(viewport) = viewport.scaled(scale).clamped(width, height) else {
+					continue;
+				};
+				render_pass.apply_viewport(&viewport);
+				render_pass.apply_scissor(&viewport.scissor());
+			}
+			if view_index == 0 {
+				if let Some(scissor) = &self.scissor {
+					render_pass
+						.apply_scissor(&scissor.scaled(scale).clamped(width, height));
+				}
+			}
+			pipeline.draw(
+				&mut render_pass,
+				camera_bind_group,
+				&soft_bind_group,
+				system,
+			);
+			self.frame_stats.draw_calls += 1;
+		}
+		// The GL backend resolves MSAA with the last scissor rectangle still set.
+		render_pass.apply_scissor(&ScissorRect::new(0, 0, width, height));
+	}
+
+	/// Records projecting the queued decals onto the scene drawn into `view`.
+	/// They are dropped with MSAA, whose depth buffer can't be read.
+	fn draw_decals(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		let Some(decals) = &mut self.decals else {
+			return;
+		};
+		if self.sample_count != 1 {
+			if !decals.is_empty() {
+				warn!("Dropping {} decals, as MSAA is enabled", decals.len());
+			}
+			decals.clear();
+			return;
+		}
+		// The stencil can't be sampled along with the depth.
+		let depth_view = self.depth_tex.create_view(&wgpu::TextureViewDescriptor {
+			aspect: wgpu::TextureAspect::DepthOnly,
+			..Default::default()
+		});
+		let draw_calls = decals.flush(
+			&self.device,
+			&self.queue,
+			encoder,
+			&self.resources,
+			&depth_view,
+			view,
+		);
+		self.frame_stats.draw_calls += draw_calls;
+		self.frame_stats.texture_switches += draw_calls;
+	}
+
+	/// Records projecting the queued stencil decals onto the scene drawn into
+	/// `view`, placed by the depth of `gbuffer`. They are dropped with MSAA, as
+	/// they are drawn single sampled.
+	fn draw_stencil_decals(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		gbuffer: &GBuffer,
+	) {
+		let Some(decals) = &mut self.stencil_decals else {
+			return;
+		};
+		if self.sample_count != 1 {
+			if !decals.is_empty() {
+				warn!(
+					"Dropping {} stencil decals, as MSAA is enabled",
+					decals.len()
+				);
+			}
+			decals.clear();
+			return;
+		}
+		let draw_calls = decals.flush(
+			&self.device,
+			&self.queue,
+			encoder,
+			&self.resources,
+			gbuffer.depth_view(),
+			&self.depth_view,
+			view,
+		);
+		self.frame_stats.draw_calls += draw_calls;
+	}
+
+	/// Uploads the matrices placing the objects drawn into `gbuffer`, from which
+	/// their motion is found. Returns the number of bytes written.
+	fn upload_motion_transforms(&self, gbuffer: &mut GBuffer) -> u64 {
+		let mut view_proj = self.camera.proj_view_from(&self.camera.view());
+		if self.reverse_z {
+			view_proj = reverse_z(&view_proj);
+		}
+		// Indexed like the object uniforms, up to the entities which the G-buffer
+		// doesn't draw.
+		let transforms: Vec<Matrix4<f32>> = std::iter::once(Matrix4::identity())
+			.chain(self.render_queue.iter().map(|object| object.transform))
+			.chain(self.lod_objects.iter().map(|object| object.transform))
+			.chain(self.frame_objects.iter().map(|object| object.transform))
+			.collect();
+		gbuffer.upload_transforms(&self.device, &self.queue, &view_proj, &transforms)
+	}
+
+	/// Keeps the transforms of the objects drawn in the last frame, for the next
+	/// frame's motion vectors. Called at the end of every frame.
+	pub fn update_previous_transforms(&mut self) {
+		if let Some(gbuffer) = &mut self.gbuffer {
+			gbuffer.update_previous_transforms();
+		}
+	}
+
+	/// Records the main pass, drawing the scene into `view`, after the G-buffer
+	/// pass if there is one.
+	fn draw_scene(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		gbuffer: Option<&GBuffer>,
+	) {
+		visibility_system(&*self.camera, &mut self.world);
+		self.prepare_pipelines();
+		if let Some(uniforms) = &mut self.object_uniforms {
+			// The mesh's own instances are placed by their instance transforms only.
+			let transforms: Vec<Matrix4<f32>> = std::iter::once(Matrix4::identity())
+				.chain(self.render_queue.iter().map(|object| object.transform))
+				.chain(self.lod_objects.iter().map(|object| object.transform))
+				.chain(self.frame_objects.iter().map(|object| object.transform))
+				.chain(
+					Self::world_draws(&self.world, &self.resources)
+						.into_iter()
+						.map(|(transform, ..)| *transform),
+				)
+				.collect();
+			self.frame_stats.bytes_uploaded +=
+				uniforms.upload(&self.device, &self.queue, &transforms);
+		}
+		self.draw_shadow_map(encoder);
+		self.draw_point_shadows(encoder);
+		if let Some(gbuffer) = gbuffer {
+			self.draw_gbuffer(encoder, gbuffer);
+		}
+		if self.picking_requested {
+			self.picking_requested = false;
+			self.draw_picking(encoder);
+		}
+		self.update_visible_objects();
+		let draw_order = self.draw_order();
+		let eye = self.camera.view().inverse() * Point3::origin();
+		// Taken out so that the pass can borrow it while `self` is mutated.
+		let mut terrain = self.terrain.take();
+		if let Some(terrain) = &mut terrain {
+			terrain.update_visible(&*self.camera);
+		}
+		let world_draws = Self::world_draws(&self.world, &self.resources);
+
+		// With MSAA, we draw into the multisampled texture and resolve it into
+		// `view`.
+		let frame_view = view;
+		let (view, resolve_target) =
+			msaa_attachments(&self.msaa_texture, &self.msaa_resolve, view);
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Render Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(if self.hdr {
+						let [r, g, b, a] = self.hdr_clear_color.map(f64::from);
+						wgpu::Color { r, g, b, a }
+					} else {
+						self.clear_color
+					}),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_view,
+				depth_ops: Some(wgpu::Operations {
+					// The far plane.
+					load: wgpu::LoadOp::Clear(if self.reverse_z { 0.0 } else { 1.0 }),
+					store: true,
+				}),
+				stencil_ops: self.depth_format.has_stencil_aspect().then_some(
+					wgpu::Operations {
+						load: wgpu::LoadOp::Clear(0),
+						store: true,
+					},
+				),
+			}),
+		});
+
+		let (width, height) = self.render_size();
+		let scale = self.render_scale();
+		// The main camera's view comes first.
+		let views = std::iter::once((&self.camera_bind_group, self.viewport)).chain(
+			self.views
+				.iter()
+				.map(|view| (&view.bind_group, Some(view.viewport))),
+		);
+		for (view_index, (camera_bind_group, viewport)) in views.enumerate() {
+			if let Some(viewport) = viewport {
+				let Some(viewport) = viewport.scaled(scale).clamped(width, height) else {
+					continue;
+				};
+				render_pass.apply_viewport(&viewport);
+				render_pass.apply_scissor(&viewport.scissor());
+			}
+			if view_index == 0 {
+				if let Some(scissor) = &self.scissor {
+					render_pass
+						.apply_scissor(&scissor.scaled(scale).clamped(width, height));
+				}
+				if let Some(skybox) = &self.skybox {
+					skybox.draw(&mut render_pass);
+					self.frame_stats.draw_calls += 1;
+					self.frame_stats.texture_switches += 1;
+				}
+			}
+
+			let mut key = PipelineKey::mesh(self.blend_mode, self.stencil_mode);
+			render_pass.set_pipeline(&self.pipelines[&key]);
+			render_pass.set_stencil_reference(self.stencil_mode.reference());
+
+			render_pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+			render_pass
+				.set_index_buffer(self.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+
+			render_pass.set_bind_group(0, &self.material.bind_group, &[]);
+			render_pass.set_bind_group(1, camera_bind_group, &[]);
+			render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+			let uniforms = self.object_uniforms.as_ref();
+			set_model(&mut render_pass, uniforms, 0, &Matrix4::identity());
+			self.frame_stats.texture_switches += 3 + uniforms.is_some() as u32;
+			render_pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+			match (&self.gpu_culling, &self.indirect_draws) {
+				(Some(culler), _) if view_index == 0 => {
+					culler.draw(&mut render_pass);
+					// The instances drawn aren't known on the CPU.
+					self.frame_stats.draw_calls += 1;
+				}
+				(_, Some((indirect_buf, count))) => {
+					for i in 0..*count {
+						let offset = i as u64 * DrawCall::SIZE;
+						render_pass.draw_indexed_indirect(indirect_buf, offset);
+					}
+					// The triangles drawn aren't known on the CPU.
+					self.frame_stats.draw_calls += count;
+				}
+				_ => {
+					// render_pass.draw(0..self.num_vertices, 0..1)
+					render_pass.draw_indexed(
+						0..self.num_indices,
+						0,
+						0..self.num_instances,
+					);
+					self.frame_stats.draw_calls += 1;
+					self.frame_stats.triangles +=
+						self.num_indices / 3 * self.num_instances;
+				}
+			}
+
+			render_pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+			if let Some(terrain) = &terrain {
+				key = PipelineKey::terrain(&terrain.material);
+				let (draw_calls, triangles) = Self::draw_terrain(
+					&mut render_pass,
+					&self.pipelines[&key],
+					uniforms,
+					terrain,
+				);
+				self.frame_stats.draw_calls += draw_calls;
+				self.frame_stats.triangles += triangles;
+				self.frame_stats.texture_switches += 1 + uniforms.is_some() as u32;
+			}
+			// Pipelines and materials are only switched when they change.
+			let mut material: Option<&Arc<Material>> = None;
+			for &i in &draw_order {
+				// Other views may see what the main camera doesn't.
+				if view_index == 0 && !self.is_object_visible(i, &eye) {
+					continue;
+				}
+				let object = &self.render_queue[i];
+				let GpuMesh {
+					vtx_buf,
+					idx_buf,
+					num_indices,
+					..
+				} = &*object.mesh;
+				let object_key = PipelineKey::object(object);
+				if object_key != key {
+					key = object_key;
+					render_pass.set_pipeline(&self.pipelines[&key]);
+				}
+				render_pass.set_stencil_reference(object.stencil.reference());
+				render_pass.set_vertex_buffer(0, vtx_buf.slice(..));
+				render_pass
+					.set_index_buffer(idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+				if !matches!(material, Some(m) if Arc::ptr_eq(m, &object.material)) {
+					material = Some(&object.material);
+					render_pass.set_bind_group(0, &object.material.bind_group, &[]);
+					self.frame_stats.texture_switches += 1;
+				}
+				set_model(&mut render_pass, uniforms, i + 1, &object.transform);
+				self.frame_stats.texture_switches += uniforms.is_some() as u32;
+				render_pass.draw_indexed(0..*num_indices, 0, 0..1);
+				self.frame_stats.draw_calls += 1;
+				self.frame_stats.triangles += num_indices / 3;
+			}
+			// Their uniforms come after those of the render queue.
+			let first_lod_index = 1 + self.render_queue.len();
+			for (j, object) in self.lod_objects.iter().enumerate() {
+				if object.material.blend == BlendMode::Transparent {
+					continue;
+				}
+				let object_key = PipelineKey::lod_object(object);
+				if object_key != key {
+					key = object_key;
+					render_pass.set_pipeline(&self.pipelines[&key]);
+				}
+				render_pass.set_stencil_reference(0);
+				if !matches!(material, Some(m) if Arc::ptr_eq(m, &object.material)) {
+					material = Some(&object.material);
+					render_pass.set_bind_group(0, &object.material.bind_group, &[]);
+					self.frame_stats.texture_switches += 1;
+				}
+				self.frame_stats.triangles += Self::draw_lod_mesh(
+					&mut render_pass,
+					uniforms,
+					first_lod_index + j,
+					&eye,
+					&object.mesh,
+					&object.transform,
+				);
+				self.frame_stats.texture_switches += uniforms.is_some() as u32;
+				self.frame_stats.draw_calls += 1;
+			}
+			if let (0, Some((mesh, cull_material, culler))) =
+				(view_index, &self.cull_lod)
+			{
+				let cull_key = PipelineKey::lod_material(cull_material);
+				if cull_key != key {
+					key = cull_key;
+					render_pass.set_pipeline(&self.pipelines[&key]);
+				}
+				render_pass.set_stencil_reference(0);
+				render_pass.set_bind_group(0, &cull_material.bind_group, &[]);
+				// The instances are placed in world space, like the mesh's.
+				set_model(&mut render_pass, uniforms, 0, &Matrix4::identity());
+				self.frame_stats.texture_switches += 1 + uniforms.is_some() as u32;
+				// The instances drawn aren't known on the CPU.
+				self.frame_stats.draw_calls += culler.draw(&mut render_pass, mesh);
+				render_pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+			}
+			// Their uniforms come after those of the frame objects.
+			let first_entity_index =
+				first_lod_index + self.lod_objects.len() + self.frame_objects.len();
+			let mut entity_material: Option<&Material> = None;
+			for (j, &(transform, mesh, material)) in world_draws.iter().enumerate() {
+				let entity_key = PipelineKey::entity(mesh, material);
+				if entity_key != key {
+					key = entity_key;
+					render_pass.set_pipeline(&self.pipelines[&key]);
+				}
+				render_pass.set_stencil_reference(0);
+				render_pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+				render_pass.set_index_buffer(
+					mesh.idx_buf.slice(..),
+					wgpu::IndexFormat::Uint32,
+				);
+				if !matches!(entity_material, Some(m) if std::ptr::eq(m, material)) {
+					entity_material = Some(material);
+					render_pass.set_bind_group(0, &material.bind_group, &[]);
+					self.frame_stats.texture_switches += 1;
+				}
+				set_model(
+					&mut render_pass,
+					uniforms,
+					first_entity_index + j,
+					transform,
+				);
+				self.frame_stats.texture_switches += uniforms.is_some() as u32;
+				render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+				self.frame_stats.draw_calls += 1;
+				self.frame_stats.triangles += mesh.num_indices / 3;
+			}
+
+			if let Some(color_meshes) = &self.color_meshes {
+				self.frame_stats.draw_calls +=
+					color_meshes.draw(&mut render_pass, camera_bind_group);
+			}
+
+			if !self.skinned_meshes.is_empty() {
+				let key = PipelineKey::skinned(self.blend_mode);
+				render_pass.set_pipeline(&self.pipelines[&key]);
+				render_pass.set_bind_group(0, &self.material.bind_group, &[]);
+				render_pass.set_bind_group(1, camera_bind_group, &[]);
+				render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+				render_pass.set_bind_group(
+					OBJECT_BIND_GROUP,
+					self.skin.bind_group(),
+					&[],
+				);
+				self.frame_stats.texture_switches += 4;
+				for mesh in &self.skinned_meshes {
+					render_pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+					render_pass.set_index_buffer(
+						mesh.idx_buf.slice(..),
+						wgpu::IndexFormat::Uint32,
+					);
+					render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+					self.frame_stats.draw_calls += 1;
+					self.frame_stats.triangles += mesh.num_indices / 3;
+				}
+			}
+		}
+		// The GL backend resolves MSAA with the last scissor rectangle still set.
+		render_pass.apply_scissor(&ScissorRect::new(0, 0, width, height));
+		drop(render_pass);
+		self.terrain = terrain;
+		self.draw_particles(encoder, frame_view);
+
+		if let Some(occlusion) = &mut self.occlusion {
+			// Unit cubes, scaled and moved over the objects' bounding boxes.
+			let proxies: Vec<Matrix4<f32>> = self
+				.render_queue
+				.iter()
+				.map(|object| {
+					let aabb = &object.mesh.aabb;
+					object.transform
+						* Matrix4::new_translation(&aabb.center().coords)
+						* Matrix4::new_nonuniform_scaling(&aabb.half_extents())
+				})
+				.collect();
+			let viewport = self
+				.viewport
+				.and_then(|v| v.scaled(scale).clamped(width, height));
+			self.frame_stats.bytes_uploaded += occlusion.record(
+				&self.device,
+				&self.queue,
+				encoder,
+				&self.depth_view,
+				&self.camera_bind_group,
+				viewport.as_ref(),
+				&proxies,
+			);
+		}
+	}
+
+	/// The order `render_queue` is drawn in. Objects with a stencil mode come
+	/// first, in queue order, as they may depend on each other's stencil values.
+	/// Then opaque objects front to back, so that hidden fragments fail the depth
+	/// test early, and transparent objects back to front, so that they blend over
+	/// what is behind them.
+	fn draw_order(&self) -> Vec<usize> {
+		let [stenciled, opaque, transparent] = self.sort_objects(&self.render_queue);
+		stenciled
+			.into_iter()
+			.chain(opaque)
+			.chain(transparent)
+			.collect()
+	}
+
+	/// The indices of the objects with a stencil mode, in order, the opaque ones
+	/// front to back and the alpha blended ones back to front. See
+	/// [`Self::draw_order`]. Objects with [`BlendMode::Transparent`] are left to
+	/// [`Self::draw_wboit`].
+	fn sort_objects(&self, objects: &[RenderObject]) -> [Vec<usize>; 3] {
+		let view = self.camera.view();
+		// How far the center is in front of the camera, which looks down -z.
+		let depth = |object: &RenderObject| {
+			let center = object.transform.transform_point(&object.mesh.aabb.center());
+			-view.transform_point(&center).z
+		};
+		let mut stenciled = Vec::new();
+		let mut opaque = Vec::new();
+		let mut transparent = Vec::new();
+		for (i, object) in objects.iter().enumerate() {
+			if object.material.blend == BlendMode::Transparent {
+				continue;
+			} else if object.stencil != StencilMode::Disabled {
+				stenciled.push(i);
+			} else if object.material.blend == BlendMode::Opaque {
+				opaque.push((depth(object), i));
+			} else {
+				transparent.push((depth(object), i));
+			}
+		}
+		opaque.sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+		transparent.sort_unstable_by(|(a, _), (b, _)| b.total_cmp(a));
+		[
+			stenciled,
+			opaque.into_iter().map(|(_, i)| i).collect(),
+			transparent.into_iter().map(|(_, i)| i).collect(),
+		]
+	}
+
+	/// Records drawing `frame_objects` into `view`, after the scene. The opaque
+	/// objects are split into a chunk per rayon thread, each recorded by its
+	/// thread into a command buffer of its own. What `encoder` recorded so far is
+	/// queued ahead of them in `pending_commands`, and it is replaced to record
+	/// what comes after them.
+	#[cfg(not(target_arch = "wasm32"))]
+	fn draw_frame_objects(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		use rayon::prelude::*;
+
+		let [stenciled, opaque, transparent] = self.sort_objects(&self.frame_objects);
+		let (width, height) = self.render_size();
+		let scale = self.render_scale();
+		// Drawn into the same attachments as the scene.
+		let (view, resolve_target) =
+			msaa_attachments(&self.msaa_texture, &self.msaa_resolve, view);
+		let recorder = ObjectRecorder {
+			objects: &self.frame_objects,
+			first_index: 1 + self.render_queue.len() + self.lod_objects.len(),
+			pipelines: &self.pipelines,
+			camera_bind_group: &self.camera_bind_group,
+			light_bind_group: &self.light_bind_group,
+			identity_instance_buf: &self.identity_instance_buf,
+			object_uniforms: self.object_uniforms.as_ref(),
+			view,
+			resolve_target,
+			depth_view: &self.depth_view,
+			has_stencil: self.depth_format.has_stencil_aspect(),
+			size: (width, height),
+			viewport: self
+				.viewport
+				.and_then(|v| v.scaled(scale).clamped(width, height)),
+			scissor: self.scissor.map(|s| s.scaled(scale).clamped(width, height)),
+		};
+		// They may depend on each other's stencil values.
+		recorder.record(encoder, &stenciled);
+		let threads = rayon::current_num_threads();
+		// Rounded up, so that there are no more chunks than threads.
+		let chunk_size =
+			(opaque.len() / threads + (opaque.len() % threads != 0) as usize).max(1);
+		let device = &self.device;
+		let chunk_commands: Vec<wgpu::CommandBuffer> = opaque
+			.par_chunks(chunk_size)
+			.map(|chunk| {
+				let mut encoder =
+					device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+						label: Some("Object Chunk Encoder"),
+					});
+				recorder.record(&mut encoder, chunk);
+				encoder.finish()
+			})
+			.collect();
+		let scene_encoder = std::mem::replace(
+			encoder,
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+				label: Some("Render Encoder"),
+			}),
+		);
+		// They blend over what is behind them, in order.
+		recorder.record(encoder, &transparent);
+		self.pending_commands.push(scene_encoder.finish());
+		self.pending_commands.extend(chunk_commands);
+
+		// Those with order-independent transparency are counted by `draw_wboit`.
+		let drawn = [stenciled, opaque, transparent].concat();
+		self.frame_stats.draw_calls += drawn.len() as u32;
+		self.frame_stats.triangles += (drawn.iter())
+			.map(|&i| self.frame_objects[i].mesh.num_indices / 3)
+			.sum::<u32>();
+	}
+
+	/// Records accumulating the objects with [`BlendMode::Transparent`] for every
+	/// view, and blending them over `view`. Must come after everything else is
+	/// drawn into `view`, as they are blended over it. The [`WboitPass`] is
+	/// created with the first frame drawing such objects.
+	fn draw_wboit(
+		&mut self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+	) {
+		let is_transparent =
+			|material: &Material| material.blend == BlendMode::Transparent;
+		let materials = (self.render_queue.iter().chain(&self.frame_objects))
+			.map(|object| &*object.material)
+			.chain(self.lod_objects.iter().map(|object| &*object.material));
+		if !materials.into_iter().any(is_transparent) {
+			return;
+		}
+		let (width, height) = self.render_size();
+		let scale = self.render_scale();
+		if self.wboit.is_none() {
+			let format = self.color_format();
+			self.wboit = Some(WboitPass::new(
+				&self.device,
+				width,
+				height,
+				format,
+				self.sample_count,
+			));
+		}
+		let Some(wboit) = &self.wboit else {
+			return;
+		};
+		let eye = self.camera.view().inverse() * Point3::origin();
+		let uniforms = self.object_uniforms.as_ref();
+		let first_lod_index = 1 + self.render_queue.len();
+		let first_frame_index = first_lod_index + self.lod_objects.len();
+		let (mut draw_calls, mut triangles, mut texture_switches) = (0, 0, 0);
+		let mut pass = wboit.begin_accumulation(
+			encoder,
+			&self.depth_view,
+			self.depth_format.has_stencil_aspect(),
+		);
+		// The main camera's view comes first, as in `draw_scene`.
+		let views = std::iter::once((&self.camera_bind_group, self.viewport)).chain(
+			self.views
+				.iter()
+				.map(|view| (&view.bind_group, Some(view.viewport))),
+		);
+		for (view_index, (camera_bind_group, viewport)) in views.enumerate() {
+			if let Some(viewport) = viewport {
+				let Some(viewport) = viewport.scaled(scale).clamped(width, height) else {
+					continue;
+				};
+				pass.apply_viewport(&viewport);
+				pass.apply_scissor(&viewport.scissor());
+			}
+			if view_index == 0 {
+				if let Some(scissor) = &self.scissor {
+					pass.apply_scissor(&scissor.scaled(scale).clamped(width, height));
+				}
+			}
+			pass.set_bind_group(1, camera_bind_group, &[]);
+			pass.set_bind_group(2, &self.light_bind_group, &[]);
+			pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+			texture_switches += 2;
+
+			// In any order, which is the point.
+			let queue_objects = (self.render_queue.iter().enumerate())
+				.filter(|&(i, _)| view_index != 0 || self.is_object_visible(i, &eye))
+				.map(|(i, object)| (i + 1, object));
+			let frame_objects = (self.frame_objects.iter().enumerate())
+				.map(|(i, object)| (first_frame_index + i, object));
+			for (index, object) in queue_objects.chain(frame_objects) {
+				if !is_transparent(&object.material) {
+					continue;
+				}
+				let mesh = &*object.mesh;
+				pass.set_pipeline(&self.pipelines[&PipelineKey::object(object)]);
+				pass.set_stencil_reference(object.stencil.reference());
+				pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+				pass.set_index_buffer(
+					mesh.idx_buf.slice(..),
+					wgpu::IndexFormat::Uint32,
+				);
+				pass.set_bind_group(0, &object.material.bind_group, &[]);
+				set_model(&mut pass, uniforms, index, &object.transform);
+				pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+				draw_calls += 1;
+				triangles += mesh.num_indices / 3;
+				texture_switches += 1 + uniforms.is_some() as u32;
+			}
+			for (j, object) in self.lod_objects.iter().enumerate() {
+				if !is_transparent(&object.material) {
+					continue;
+				}
+				pass.set_pipeline(&self.pipelines[&PipelineKey::lod_object(object)]);
+				pass.set_stencil_reference(0);
+				pass.set_bind_group(0, &object.material.bind_group, &[]);
+				triangles += Self::draw_lod_mesh(
+					&mut pass,
+					uniforms,
+					first_lod_index + j,
+					&eye,
+					&object.mesh,
+					&object.transform,
+				);
+				draw_calls += 1;
+				texture_switches += 1 + uniforms.is_some() as u32;
+			}
+		}
+		// The GL backend resolves MSAA with the last scissor rectangle still set.
+		pass.apply_scissor(&ScissorRect::new(0, 0, width, height));
+		drop(pass);
+		wboit.composite(encoder, view);
+		self.frame_stats.draw_calls += draw_calls + 1;
+		self.frame_stats.triangles += triangles;
+		self.frame_stats.texture_switches += texture_switches + 1;
+	}
+
+	/// Records the shadow pass, drawing the opaque mesh and objects into the
+	/// shadow map, or a pass per cascade if enabled. Must come after the object
+	/// uniforms are uploaded.
+	fn draw_shadow_map(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		if self.cascaded_shadows.is_enabled() {
+			self.frame_stats.bytes_uploaded += self.cascaded_shadows.update(
+				&self.queue,
+				&*self.camera,
+				Vector3::from(self.light.direction),
+			);
+			for i in 0..N_CASCADES {
+				let (mut draw_calls, mut triangles) = (0, 0);
+				self.cascaded_shadows.render_cascade(
+					encoder,
+					|pass| {
+						(draw_calls, triangles) = self.draw_opaque_geometry(pass, None)
+					},
+					i,
+				);
+				self.frame_stats.draw_calls += draw_calls;
+				self.frame_stats.triangles += triangles;
+			}
+			return;
+		}
+		let mut pass = self.shadow_map.begin_pass(encoder);
+		// Transparent surfaces let light through, so they cast no shadows. The map
+		// is cleared either way.
+		let (draw_calls, triangles) = self.draw_opaque_geometry(&mut pass, None);
+		drop(pass);
+		self.frame_stats.draw_calls += draw_calls;
+		self.frame_stats.triangles += triangles;
+	}
+
+	/// Records a pass per face of each point light's shadow map, drawing the
+	/// opaque mesh and objects. Must come after the object uniforms are uploaded.
+	fn draw_point_shadows(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		for point_shadow in &self.point_shadows {
+			for face in 0..6 {
+				let (mut draw_calls, mut triangles) = (0, 0);
+				point_shadow.render_face(
+					encoder,
+					|pass| {
+						(draw_calls, triangles) = self.draw_opaque_geometry(pass, None)
+					},
+					face,
+				);
+				self.frame_stats.draw_calls += draw_calls;
+				self.frame_stats.triangles += triangles;
+			}
+		}
+	}
+
+	/// Records the picking pass, drawing the index of each object of the render
+	/// queue. Must come after the object uniforms are uploaded.
+	fn draw_picking(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		// Taken out so that the pass can borrow it while `self` is mutated.
+		let Some(mut picking) = self.picking.take() else {
+			return;
+		};
+		self.frame_stats.bytes_uploaded += picking.prepare_pass(
+			&self.device,
+			&self.queue,
+			self.render_queue.len() as u32,
+		);
+		let mut pass = picking.begin_pass(encoder);
+		pass.set_bind_group(1, &self.camera_bind_group, &[]);
+		if let Some(viewport) = self
+			.viewport
+			.and_then(|v| v.clamped(self.config.width, self.config.height))
+		{
+			pass.apply_viewport(&viewport);
+		}
+		pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+		let uniforms = self.object_uniforms.as_ref();
+		let mut draw_calls = 0;
+		for (i, object) in self.render_queue.iter().enumerate() {
+			let mesh = &*object.mesh;
+			// The pipeline only draws triangle lists.
+			if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+				continue;
+			}
+			pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+			pass.set_index_buffer(mesh.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			// The mesh's own uniforms come before the render queue's.
+			set_model(&mut pass, uniforms, i + 1, &object.transform);
+			picking.set_id(&mut pass, i);
+			pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+			draw_calls += 1;
+		}
+		drop(pass);
+		self.frame_stats.draw_calls += draw_calls;
+		self.picking = Some(picking);
+	}
+
+	/// Records the G-buffer pass, and estimating the occlusion from it if SSAO is
+	/// enabled. Must come after the object uniforms are uploaded.
+	fn draw_gbuffer(&mut self, encoder: &mut wgpu::CommandEncoder, gbuffer: &GBuffer) {
+		let mut pass = gbuffer.begin_pass(encoder);
+		pass.set_bind_group(1, &self.camera_bind_group, &[]);
+		let (width, height) = self.render_size();
+		let scale = self.render_scale();
+		if let Some(viewport) = self
+			.viewport
+			.and_then(|v| v.scaled(scale).clamped(width, height))
+		{
+			pass.apply_viewport(&viewport);
+		}
+		let (draw_calls, triangles) =
+			self.draw_opaque_geometry(&mut pass, Some(gbuffer));
+		drop(pass);
+		self.frame_stats.draw_calls += draw_calls;
+		self.frame_stats.triangles += triangles;
+		if let Some(sdf_ao) = &self.sdf_ao {
+			sdf_ao.apply(
+				&self.device,
+				encoder,
+				gbuffer.depth_view(),
+				gbuffer.normal_view(),
+				sdf_ao.occlusion_view(),
+			);
+			self.frame_stats.draw_calls += 1;
+		} else if let Some(ssao) = &self.ssao {
+			ssao.apply(
+				&self.device,
+				encoder,
+				gbuffer.depth_view(),
+				gbuffer.normal_view(),
+				ssao.occlusion_view(),
+			);
+			// The occlusion is estimated and blurred in a pass each.
+			self.frame_stats.draw_calls += 2;
+		}
+	}
+
+	/// Draws the visible chunks of `terrain` with `pipeline`, one draw call each.
+	/// The camera and light must already be set, and the identity instance
+	/// buffer. Returns the number of draw calls and triangles.
+	fn draw_terrain<'a>(
+		render_pass: &mut wgpu::RenderPass<'a>,
+		pipeline: &'a wgpu::RenderPipeline,
+		uniforms: Option<&'a ObjectUniforms>,
+		terrain: &'a Terrain,
+	) -> (u32, u32) {
+		render_pass.set_pipeline(pipeline);
+		render_pass.set_stencil_reference(0);
+		render_pass.set_bind_group(0, &terrain.material.bind_group, &[]);
+		// The chunks are placed in world space, like the mesh.
+		set_model(render_pass, uniforms, 0, &Matrix4::identity());
+		let (mut draw_calls, mut triangles) = (0, 0);
+		for mesh in terrain.visible_chunks().filter_map(TerrainChunk::mesh) {
+			render_pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+			render_pass
+				.set_index_buffer(mesh.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+			draw_calls += 1;
+			triangles += mesh.num_indices / 3;
+		}
+		(draw_calls, triangles)
+	}
+
+	/// Draws `lod_mesh` with the level of detail for its distance from `eye`,
+	/// placed by `transform` or the `index`th object uniform. The pipeline,
+	/// material and camera must already be set. Returns the number of triangles
+	/// drawn.
+	fn draw_lod_mesh<'a>(
+		render_pass: &mut wgpu::RenderPass<'a>,
+		uniforms: Option<&'a ObjectUniforms>,
+		index: usize,
+		eye: &Point3<f32>,
+		lod_mesh: &'a LodMesh,
+		transform: &Matrix4<f32>,
+	) -> u32 {
+		let center = transform.transform_point(&lod_mesh.aabb.center());
+		let (idx_buf, num_indices) =
+			lod_mesh.select_lod(nalgebra::distance(eye, &center));
+		render_pass.set_vertex_buffer(0, lod_mesh.vtx_buf.slice(..));
+		render_pass.set_index_buffer(idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+		set_model(render_pass, uniforms, index, transform);
+		render_pass.draw_indexed(0..num_indices, 0, 0..1);
+		num_indices / 3
+	}
+
+	/// Draws the mesh, when opaque, and the opaque triangles of the render queue,
+	/// LOD objects and objects of [`Self::render_mt`] with the pipeline already set on `pass`. With the
+	/// `gbuffer` being drawn, binds its transforms too. Returns the number of
+	/// draw calls and triangles.
+	fn draw_opaque_geometry<'a>(
+		&'a self,
+		pass: &mut wgpu::RenderPass<'a>,
+		gbuffer: Option<&'a GBuffer>,
+	) -> (u32, u32) {
+		let (mut draw_calls, mut triangles) = (0, 0);
+		let uniforms = self.object_uniforms.as_ref();
+		// The G-buffer's transforms are indexed like the object uniforms.
+		let set_transform = |pass: &mut wgpu::RenderPass<'a>, index| {
+			if let Some(gbuffer) = gbuffer {
+				gbuffer.set_transform(pass, index);
+			}
+		};
+		if self.blend_mode == BlendMode::Opaque {
+			pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+			pass.set_index_buffer(self.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+			set_model(pass, uniforms, 0, &Matrix4::identity());
+			set_transform(pass, 0);
+			pass.draw_indexed(0..self.num_indices, 0, 0..self.num_instances);
+			draw_calls += 1;
+			triangles += self.num_indices / 3 * self.num_instances;
+		}
+
+		pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+		// The objects of `render_mt` have their uniforms after the LOD objects'.
+		let first_frame_index = 1 + self.render_queue.len() + self.lod_objects.len();
+		let objects = (self.render_queue.iter().enumerate())
+			.map(|(i, object)| (i + 1, object))
+			.chain(
+				(self.frame_objects.iter().enumerate())
+					.map(|(i, object)| (first_frame_index + i, object)),
+			);
+		for (index, object) in objects {
+			let mesh = &*object.mesh;
+			// The pipelines only draw triangle lists.
+			if object.material.blend != BlendMode::Opaque
+				|| mesh.topology != wgpu::PrimitiveTopology::TriangleList
+			{
+				continue;
+			}
+			pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+			pass.set_index_buffer(mesh.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			set_model(pass, uniforms, index, &object.transform);
+			set_transform(pass, index);
+			pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+			draw_calls += 1;
+			triangles += mesh.num_indices / 3;
+		}
+		// The levels are picked for the main camera in every pass, so that shadows
+		// match what it sees.
+		let eye = self.camera.view().inverse() * Point3::origin();
+		let first_lod_index = 1 + self.render_queue.len();
+		for (j, object) in self.lod_objects.iter().enumerate() {
+			if object.material.blend != BlendMode::Opaque {
+				continue;
+			}
+			set_transform(pass, first_lod_index + j);
+			triangles += Self::draw_lod_mesh(
+				pass,
+				uniforms,
+				first_lod_index + j,
+				&eye,
+				&object.mesh,
+				&object.transform,
+			);
+			draw_calls += 1;
+		}
+		(draw_calls, triangles)
+	}
+
+	pub fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), RenderError> {
+		if size.width == 0 && size.height == 0 {
+			return Ok(());
+		}
+		self.config.width = size.width;
+		self.config.height = size.height;
+		self.text_renderer.resize(size.width, size.height);
+		let old_format = self.config.format;
+		let device = self.device.clone();
+		self.safe_configure(&device)?;
+		if let Some(picking) = &mut self.picking {
+			picking.resize(&self.device, size.width, size.height);
+		}
+		self.resize_targets(old_format);
+		Ok(())
+	}
+
+	/// Recreates the textures the scene is drawn into at [`Self::render_size`],
+	/// and the pipelines drawing into them if the frame's format isn't
+	/// `old_format` anymore.
+	fn resize_targets(&mut self, old_format: wgpu::TextureFormat) {
+		let (width, height) = self.render_size();
+		let color_format = self.color_format();
+		self.msaa_texture = create_msaa_texture(
+			&self.device,
+			&self.memory_tracker,
+			(width, height),
+			color_format,
+			self.sample_count,
+		);
+		if let Some(msaa_resolve) = &mut self.msaa_resolve {
+			msaa_resolve.resize(&self.device, width, height);
+			if self.config.format != old_format {
+				msaa_resolve.set_format(&self.device, color_format);
+			}
+		}
+		(self.depth_tex, self.depth_view) = create_depth_texture(
+			&self.device,
+			&self.memory_tracker,
+			(width, height),
+			self.sample_count,
+			self.depth_format,
+		);
+		self.camera.on_resize(self.camera_aspect());
+		// Otherwise the old aspect ratio stretches the frames until the next
+		// `interpolate`.
+		self.upload_camera(&self.camera.view());
+		self.post_effects.resize(
+			&self.device,
+			&self.queue,
+			&*self.camera,
+			(width, height),
+			self.config.format,
+		);
+		if let Some(gbuffer) = &mut self.gbuffer {
+			gbuffer.resize(&self.device, width, height);
+		}
+		if let Some(ssao) = &mut self.ssao {
+			ssao.resize(&self.device, width, height);
+			self.recreate_light_bind_group();
+		}
+		if let Some(sdf_ao) = &mut self.sdf_ao {
+			sdf_ao.resize(&self.device, width, height);
+			self.recreate_light_bind_group();
+		}
+		if let Some(cel_outline) = &mut self.cel_outline {
+			if self.config.format != old_format {
+				cel_outline.set_format(&self.device, color_format);
+			}
+		}
+		if let Some(volumetric_fog) = &mut self.volumetric_fog {
+			volumetric_fog.resize(&self.device, width, height);
+			if self.config.format != old_format {
+				volumetric_fog.set_format(&self.device, color_format);
+			}
+		}
+		if let Some(wboit) = &mut self.wboit {
+			wboit.resize(&self.device, width, height);
+			if self.config.format != old_format {
+				wboit.set_format(&self.device, color_format);
+			}
+		}
+		if let Some(decals) = &mut self.decals {
+			if self.config.format != old_format {
+				decals.set_format(&self.device, color_format);
+			}
+		}
+		if let Some(decals) = &mut self.stencil_decals {
+			if self.config.format != old_format {
+				decals.set_format(&self.device, color_format);
+			}
+		}
+		if self.config.format != old_format {
+			self.pipelines.clear();
+			self.pending_pipelines.clear();
+			if let Some(skybox) = &mut self.skybox {
+				skybox.set_format(
+					&self.device,
+					color_format,
+					self.sample_count,
+					self.depth_format,
+				);
+			}
+			if let Some((_, pipeline)) = &mut self.particles {
+				pipeline.set_format(
+					&self.device,
+					color_format,
+					self.sample_count,
+					self.depth_format,
+				);
+			}
+			if let Some(color_meshes) = &mut self.color_meshes {
+				color_meshes.set_format(
+					&self.device,
+					color_format,
+					self.sample_count,
+					self.depth_format,
+				);
+			}
+			self.frame_graph
+				.set_format(&self.device, self.config.format);
+			self.text_renderer
+				.set_format(&self.device, self.config.format);
+			self.debug_lines
+				.set_format(&self.device, self.config.format);
+			self.gizmo.set_format(&self.device, self.config.format);
+			if let Some(debug_ui) = &mut self.debug_ui {
+				debug_ui.set_format(&self.device, self.config.format);
+			}
+		}
+	}
+
+	/// Configures the surface with the current size and format on `device`,
+	/// falling back to the other supported formats (in order of preference) if
+	/// the surface is lost right after configuring. When headless, recreates the
+	/// texture frames are drawn into instead.
+	///
+	/// Some Wayland compositors accept any configuration, and only report the
+	/// surface as lost on the next `get_current_texture`. So we probe for that here
+	/// rather than failing in the middle of a frame.
+	pub fn safe_configure(&mut self, device: &wgpu::Device) -> Result<(), RenderError> {
+		match &mut self.target {
+			FrameTarget::Window {
+				surface, formats, ..
+			} => Self::configure_surface(surface, device, &mut self.config, formats),
+			FrameTarget::Headless { texture } => {
+				*texture = create_target_texture(device, &self.config);
+				Ok(())
+			}
+		}
+	}
+
+	/// Configures `surface` with `config`, falling back to the other `formats`,
+	/// see [`Self::safe_configure`].
+	fn configure_surface(
+		surface: &wgpu::Surface,
+		device: &wgpu::Device,
+		config: &mut wgpu::SurfaceConfiguration,
+		formats: &[wgpu::TextureFormat],
+	) -> Result<(), RenderError> {
+		let preferred = config.format;
+		let candidates = std::iter::once(preferred)
+			.chain(formats.iter().copied().filter(|f| *f != preferred));
+		for format in candidates {
+			config.format = format;
+			surface.configure(device, config);
+			match surface.get_current_texture() {
+				Err(wgpu::SurfaceError::Lost) => {
+					warn!("Surface was lost after configuring it with {format:?}");
+				}
+				// Dropping the texture without presenting it just discards it.
+				_ => {
+					if format != preferred {
+						warn!("Fell back to surface format {format:?}");
+					}
+					return Ok(());
+				}
+			}
+		}
+		config.format = preferred;
+		Err(RenderError::SurfaceConfigFailed)
+	}
+
+	pub fn present_mode(&self) -> wgpu::PresentMode {
+		self.config.present_mode
+	}
+
+	/// The present modes the surface supports, most preferred first. Only the
+	/// current one when headless.
+	pub fn present_modes(&self) -> &[wgpu::PresentMode] {
+		match &self.target {
+			FrameTarget::Window { present_modes, .. } => present_modes,
+			FrameTarget::Headless { .. } => {
+				std::slice::from_ref(&self.config.present_mode)
+			}
+		}
+	}
+
+	/// Reconfigures the surface to present with `mode`. Falls back to `Fifo`,
+	/// which is always supported, if the surface doesn't support `mode`.
+	pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+		if let FrameTarget::Window {
+			surface,
+			present_modes,
+			..
+		} = &self.target
+		{
+			self.config.present_mode = supported_present_mode(mode, present_modes);
+			surface.configure(&self.device, &self.config);
+		} else {
+			self.config.present_mode = mode;
+		}
+	}
+
+	/// Logs `err`, returned by [`Self::render`], and decides how to recover from
+	/// it. A surface still lost after [`Self::MAX_LOST_FRAMES`] resizes in a row is
+	/// recreated instead.
+	pub fn handle_surface_error(&mut self, err: wgpu::SurfaceError) -> RecoveryAction {
+		use wgpu::SurfaceError as E;
+		match err {
+			E::Lost if self.lost_frames < Self::MAX_LOST_FRAMES => {
+				self.lost_frames += 1;
+				warn!("Surface was lost, reconfiguring it");
+				RecoveryAction::Resize
+			}
+			E::Lost => {
+				self.lost_frames = 0;
+				warn!("Surface is still lost, recreating it");
+				RecoveryAction::Recreate
+			}
+			E::Outdated => {
+				warn!("Surface is outdated, recreating it");
+				RecoveryAction::Recreate
+			}
+			E::OutOfMemory => {
+				error!("Out of memory!");
+				RecoveryAction::Exit
+			}
+			E::Timeout => {
+				warn!("Timed out getting the next frame");
+				RecoveryAction::Skip
+			}
+		}
+	}
+
+	/// Recreates the window's surface, or the headless target, along with
+	/// everything sized to it. The pipelines are recreated as they are next
+	/// needed, for the new surface's format. The device is kept, so resources
+	/// created with it stay valid.
+	pub fn recreate_surface(&mut self) -> Result<()> {
+		let size = match &mut self.target {
+			FrameTarget::Window {
+				surface, window, ..
+			} => {
+				// Safety: the new surface replaces the old one in `target`, so it is
+				// dropped before `window` too.
+				*surface = unsafe { self.context.instance.create_surface(&*window) }
+					.wrap_err("Failed to recreate the surface")?;
+				window.inner_size()
+			}
+			FrameTarget::Headless { .. } => self.size(),
+		};
+		self.pipelines.clear();
+		self.pending_pipelines.clear();
+		self.resize(size)?;
+		Ok(())
+	}
+
+	pub fn size(&self) -> PhysicalSize<u32> {
+		PhysicalSize {
+			width: self.config.width,
+			height: self.config.height,
+		}
+	}
+}
+
+/// What recording objects into a pass of their own needs from `RenderState`,
+/// shared between the threads of [`RenderState::render_mt`].
+#[cfg(not(target_arch = "wasm32"))]
+struct ObjectRecorder<'a> {
+	objects: &'a [RenderObject],
+	/// The object uniform of the first of `objects`.
+	first_index: usize,
+	pipelines: &'a HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+	camera_bind_group: &'a wgpu::BindGroup,
+	light_bind_group: &'a wgpu::BindGroup,
+	identity_instance_buf: &'a wgpu::Buffer,
+	object_uniforms: Option<&'a ObjectUniforms>,
+	view: &'a wgpu::TextureView,
+	resolve_target: Option<&'a wgpu::TextureView>,
+	depth_view: &'a wgpu::TextureView,
+	has_stencil: bool,
+	/// The size of the frame.
+	size: (u32, u32),
+	viewport: Option<Viewport>,
+	scissor: Option<ScissorRect>,
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl ObjectRecorder<'_> {
+	/// Records a pass drawing the objects at `indices`, over what was drawn
+	/// before it.
+	fn record(&self, encoder: &mut wgpu::CommandEncoder, indices: &[usize]) {
+		if indices.is_empty() {
+			return;
+		}
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Objects Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: self.view,
+				resolve_target: self.resolve_target,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: self.depth_view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				}),
+				stencil_ops: self.has_stencil.then_some(wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				}),
+			}),
+		});
+		if let Some(viewport) = &self.viewport {
+			pass.apply_viewport(viewport);
+			pass.apply_scissor(&viewport.scissor());
+		}
+		if let Some(scissor) = &self.scissor {
+			pass.apply_scissor(scissor);
+		}
+		pass.set_bind_group(1, self.camera_bind_group, &[]);
+		pass.set_bind_group(2, self.light_bind_group, &[]);
+		pass.set_vertex_buffer(1, self.identity_instance_buf.slice(..));
+		for &i in indices {
+			let object = &self.objects[i];
+			let mesh = &*object.mesh;
+			pass.set_pipeline(&self.pipelines[&PipelineKey::object(object)]);
+			pass.set_stencil_reference(object.stencil.reference());
+			pass.set_vertex_buffer(0, mesh.vtx_buf.slice(..));
+			pass.set_index_buffer(mesh.idx_buf.slice(..), wgpu::IndexFormat::Uint32);
+			pass.set_bind_group(0, &object.material.bind_group, &[]);
+			let index = self.first_index + i;
+			set_model(&mut pass, self.object_uniforms, index, &object.transform);
+			pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+		}
+		// The GL backend resolves MSAA with the last scissor rectangle still set.
+		let (width, height) = self.size;
+		pass.apply_scissor(&ScissorRect::new(0, 0, width, height));
+	}
+}
+
+/// What the variants of the main pipeline differ by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+	blend_mode: BlendMode,
+	cull_mode: Option<wgpu::Face>,
+	topology: wgpu::PrimitiveTopology,
+	stencil_mode: StencilMode,
+	/// Whether the vertices are [`SkinnedVertex`]es, drawn with `vs_skinned`.
+	skinned: bool,
+	/// Whether the material is shaded with `fs_pbr`, see [`Material::pbr`].
+	pbr: bool,
+	/// Whether the material is drawn with parallax occlusion mapping, by the
+	/// `_parallax` fragment entry points.
+	parallax: bool,
+	/// Whether the vertices sway in the wind, with `vs_foliage`, see
+	/// [`Material::foliage`].
+	foliage: bool,
+}
+impl PipelineKey {
+	/// The variant drawing `RenderState`'s own mesh.
+	fn mesh(blend_mode: BlendMode, stencil_mode: StencilMode) -> Self {
+		Self {
+			blend_mode,
+			cull_mode: Some(wgpu::Face::Back),
+			topology: wgpu::PrimitiveTopology::TriangleList,
+			stencil_mode: stencil_mode.pipeline_variant(),
+			skinned: false,
+			pbr: false,
+			parallax: false,
+			foliage: false,
+		}
+	}
+
+	/// The variant drawing `object`, as its material and mesh require.
+	fn object(object: &RenderObject) -> Self {
+		Self {
+			blend_mode: object.material.blend,
+			cull_mode: object.material.pipeline_cull_mode(),
+			topology: object.mesh.topology,
+			stencil_mode: object.stencil.pipeline_variant(),
+			skinned: false,
+			pbr: object.material.pbr,
+			parallax: object.material.parallax_depth_scale() != 0.,
+			foliage: object.material.foliage,
+		}
+	}
+
+	/// The variant drawing `object`, as its material requires.
+	fn lod_object(object: &LodObject) -> Self {
+		Self::lod_material(&object.material)
+	}
+
+	/// The variant drawing a [`LodMesh`] with `material`, as it requires.
+	fn lod_material(material: &Material) -> Self {
+		Self {
+			blend_mode: material.blend,
+			cull_mode: material.pipeline_cull_mode(),
+			topology: wgpu::PrimitiveTopology::TriangleList,
+			stencil_mode: StencilMode::Disabled,
+			skinned: false,
+			pbr: material.pbr,
+			parallax: material.parallax_depth_scale() != 0.,
+			foliage: material.foliage,
+		}
+	}
+
+	/// The variant drawing terrain chunks with `material`, which can't be blended
+	/// by WBOIT as the chunks are drawn in the main pass.
+	fn terrain(material: &Material) -> Self {
+		let blend_mode = match material.blend {
+			BlendMode::Transparent => BlendMode::Alpha,
+			blend_mode => blend_mode,
+		};
+		Self {
+			blend_mode,
+			cull_mode: material.pipeline_cull_mode(),
+			topology: wgpu::PrimitiveTopology::TriangleList,
+			stencil_mode: StencilMode::Disabled,
+			skinned: false,
+			pbr: material.pbr,
+			parallax: material.parallax_depth_scale() != 0.,
+			foliage: material.foliage,
+		}
+	}
+
+	/// The variant drawing an entity of the world with `mesh` and `material`,
+	/// which like terrain is drawn in the main pass.
+	fn entity(mesh: &GpuMesh, material: &Material) -> Self {
+		Self {
+			topology: mesh.topology,
+			..Self::terrain(material)
+		}
+	}
+
+	/// The variant drawing the skinned meshes, with the mesh's material.
+	fn skinned(blend_mode: BlendMode) -> Self {
+		Self {
+			skinned: true,
+			..Self::mesh(blend_mode, StencilMode::Disabled)
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+	sample_count: u32,
+	depth_format: wgpu::TextureFormat,
+	reverse_z: bool,
+	PipelineKey {
+		blend_mode,
+		cull_mode,
+		topology,
+		stencil_mode,
+		skinned,
+		pbr,
+		parallax,
+		foliage,
+	}: PipelineKey,
+) -> wgpu::RenderPipeline {
+	let (shading, fs_entry_point) = match (pbr, blend_mode, parallax) {
+		(true, BlendMode::Transparent, false) => ("PBR ", "fs_wboit_pbr"),
+		(true, BlendMode::Transparent, true) => {
+			("PBR Parallax ", "fs_wboit_pbr_parallax")
+		}
+		(true, _, false) => ("PBR ", "fs_pbr"),
+		(true, _, true) => ("PBR Parallax ", "fs_pbr_parallax"),
+		(false, BlendMode::Transparent, false) => ("", "fs_wboit"),
+		(false, BlendMode::Transparent, true) => ("Parallax ", "fs_wboit_parallax"),
+		(false, _, false) => ("", "fs_main"),
+		(false, _, true) => ("Parallax ", "fs_parallax"),
+	};
+	let (kind, entry_point, buffers) = if skinned {
+		("Skinned ", "vs_skinned", &[SkinnedVertex::vb_layout()][..])
+	} else {
+		let (kind, entry_point) = if foliage {
+			("Foliage ", "vs_foliage")
+		} else {
+			("", "vs_main")
+		};
+		(
+			kind,
+			entry_point,
+			&[Vertex::vb_layout(), Instance::vb_layout()][..],
+		)
+	};
+	let label = format!(
+		"{kind}{shading}{blend_mode:?} {cull_mode:?} {topology:?} {stencil_mode:?} Render Pipeline"
+	);
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some(&label),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point,
+			buffers,
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: fs_entry_point,
+			// Shader texture format will be same as what we configured earlier
+			targets: &blend_mode.color_targets(format),
+		}),
+		primitive: wgpu::PrimitiveState {
+			topology,
+			// Strips are restarted by the largest index.
+			strip_index_format: topology
+				.is_strip()
+				.then_some(wgpu::IndexFormat::Uint32),
+			front_face: wgpu::FrontFace::Ccw,
+			cull_mode,
+			// The next three avoid needing additional features
+			unclipped_depth: false,
+			polygon_mode: wgpu::PolygonMode::Fill,
+			conservative: false,
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: depth_format,
+			// Transparent surfaces shouldn't hide what is drawn behind them later.
+			depth_write_enabled: blend_mode == BlendMode::Opaque
+				&& !matches!(stencil_mode, StencilMode::WriteRef(_)),
+			// Nearer fragments have smaller depth, or greater with reverse-Z.
+			depth_compare: if reverse_z {
+				wgpu::CompareFunction::Greater
+			} else {
+				wgpu::CompareFunction::Less
+			},
+			stencil: stencil_mode.stencil_state(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState {
+			count: sample_count,
+			mask: !0,
+			alpha_to_coverage_enabled: false,
+		},
+		// I don't understand this one, but the tutorial set it to `None`
+		multiview: None,
+	})
+}
+
+/// Shows `title` in the window's title bar, or as the page's title on the web.
+fn set_window_title(window: &Window, title: &str) {
+	cfg_if::cfg_if! {
+		if #[cfg(target_arch = "wasm32")] {
+			let _ = window;
+			if let Some(document) = web_sys::window().and_then(|w| w.document()) {
+				document.set_title(title);
+			}
+		} else {
+			window.set_title(title);
+		}
+	}
+}
+
+/// Creates the texture that headless `RenderState`s render into.
+fn create_target_texture(
+	device: &wgpu::Device,
+	config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+	device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Headless Target"),
+		size: wgpu::Extent3d {
+			width: config.width,
+			height: config.height,
+			depth_or_array_layers: 1,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: config.format,
+		usage: config.usage,
+		view_formats: &[],
+	})
+}
+
+/// Records copying `bytes` to the start of `buffer` through `belt`. Returns the
+/// number of bytes written.
+fn stage(
+	belt: &mut wgpu::util::StagingBelt,
+	device: &wgpu::Device,
+	encoder: &mut wgpu::CommandEncoder,
+	buffer: &wgpu::Buffer,
+	bytes: &[u8],
+) -> u64 {
+	let Some(size) = wgpu::BufferSize::new(bytes.len() as u64) else {
+		return 0;
+	};
+	belt.write_buffer(encoder, buffer, 0, size, device)
+		.copy_from_slice(bytes);
+	size.get()
+}
+
+/// Creates a buffer of at least `size` bytes that can be written to, for
+/// geometry that changes. Its size is rounded up to a power of two, so that it
+/// isn't recreated every time it grows.
+fn create_streaming_buffer(
+	device: &wgpu::Device,
+	label: &str,
+	size: u64,
+	usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+	device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some(label),
+		size: size.next_power_of_two(),
+		usage: usage | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	})
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+	device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Instance Buffer"),
+		size: capacity as u64 * std::mem::size_of::<Instance>() as u64,
+		// Copied from to be culled on the GPU.
+		usage: wgpu::BufferUsages::VERTEX
+			| wgpu::BufferUsages::COPY_DST
+			| wgpu::BufferUsages::COPY_SRC,
+		mapped_at_creation: false,
+	})
+}
+
+/// Creates a `width`×`height` depth texture, the size the scene is drawn at.
+fn create_depth_texture(
+	device: &wgpu::Device,
+	tracker: &GpuMemoryTracker,
+	(width, height): (u32, u32),
+	sample_count: u32,
+	format: wgpu::TextureFormat,
+) -> (TrackedTexture, wgpu::TextureView) {
+	// Multisampled depth textures that can be sampled break MSAA resolves on the
+	// GL backend, so only single sampled ones are bindable.
+	let usage = if sample_count == 1 {
+		wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+	} else {
+		wgpu::TextureUsages::RENDER_ATTACHMENT
+	};
+	let texture = tracker.create_texture(
+		device,
+		&wgpu::TextureDescriptor {
+			label: Some("Depth Texture"),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage,
+			view_formats: &[],
+		},
+	);
+	let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+	(texture, view)
+}
+
+/// `color`, written into `format` frames, in linear light. Colors are written
+/// into sRGB frames in linear light already, and into others as sRGB values.
+fn linear_clear_color(color: wgpu::Color, format: wgpu::TextureFormat) -> [f32; 4] {
+	let linear = |c: f64| {
+		let c = c as f32;
+		if format.is_srgb() {
+			c
+		} else if c <= 0.04045 {
+			c / 12.92
+		} else {
+			((c + 0.055) / 1.055).powf(2.4)
+		}
+	};
+	[
+		linear(color.r),
+		linear(color.g),
+		linear(color.b),
+		color.a as f32,
+	]
+}
+
+/// The view to draw a frame drawn into `view` into, and the one to resolve it
+/// into: with MSAA, the multisampled texture resolved into `view`, unless
+/// `msaa_resolve` resolves it once the scene is drawn.
+fn msaa_attachments<'a>(
+	msaa_texture: &'a Option<(TrackedTexture, wgpu::TextureView)>,
+	msaa_resolve: &Option<MsaaResolvePass>,
+	view: &'a wgpu::TextureView,
+) -> (&'a wgpu::TextureView, Option<&'a wgpu::TextureView>) {
+	match msaa_texture {
+		Some((_, msaa_view)) => (msaa_view, msaa_resolve.is_none().then_some(view)),
+		None => (view, None),
+	}
+}
+
+/// Creates the multisampled texture the scene is drawn into before being
+/// resolved, or `None` without MSAA.
+fn create_msaa_texture(
+	device: &wgpu::Device,
+	tracker: &GpuMemoryTracker,
+	(width, height): (u32, u32),
+	format: wgpu::TextureFormat,
+	sample_count: u32,
+) -> Option<(TrackedTexture, wgpu::TextureView)> {
+	if sample_count == 1 {
+		return None;
+	}
+	let texture = tracker.create_texture(
+		device,
+		&wgpu::TextureDescriptor {
+			label: Some("MSAA Texture"),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			// Bound by `MsaaResolvePass`.
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		},
+	);
+	let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+	Some((texture, view))
+}
+
+/// The highest power of two no greater than `requested` that both `format` and
+/// `depth_format` support as a sample count. WebGL2 for example only supports 1
+/// and 4.
+fn supported_sample_count(
+	adapter: &wgpu::Adapter,
+	device: &wgpu::Device,
+	format: wgpu::TextureFormat,
+	depth_format: wgpu::TextureFormat,
+	requested: u32,
+) -> u32 {
+	// Mirrors how wgpu validates sample counts: adapter specific support is only
+	// used with the feature enabled, or on downlevel backends.
+	let features = |format: wgpu::TextureFormat| {
+		let adapter_specific = device
+			.features()
+			.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+		if adapter_specific
+			|| !adapter.get_downlevel_capabilities().is_webgpu_compliant()
+		{
+			adapter.get_texture_format_features(format)
+		} else {
+			format.guaranteed_format_features(device.features())
+		}
+	};
+	let supported = |count| {
+		[format, depth_format]
+			.into_iter()
+			.all(|f| features(f).flags.sample_count_supported(count))
+	};
+	let mut count = 1 << (u32::BITS - 1 - requested.max(1).leading_zeros());
+	while count > 1 && !supported(count) {
+		count /= 2;
+	}
+	if count != requested {
+		warn!("{requested}x MSAA is not supported, using {count}x instead");
+	}
+	count
+}
+
+/// `mode` if it is in `supported`, or else `Fifo`, which every surface supports.
+fn supported_present_mode(
+	mode: wgpu::PresentMode,
+	supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+	if supported.contains(&mode) {
+		mode
+	} else {
+		warn!("Present mode {mode:?} is not supported, using Fifo");
+		wgpu::PresentMode::Fifo
+	}
+}
+
+/// A camera drawing the scene into part of the frame, see
+/// [`RenderState::add_view`].
+struct View {
+	viewport: Viewport,
+	camera: Box<dyn CameraLike>,
+	camera_buf: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+}
+impl View {
+	fn new(
+		device: &wgpu::Device,
+		camera_layout: &wgpu::BindGroupLayout,
+		viewport: Viewport,
+		camera: Box<dyn CameraLike>,
+		reverse_z: bool,
+	) -> Self {
+		let camera_buf = create_uniform_buffer(
+			device,
+			"View Camera Uniform",
+			&CameraUniform::from_view(&*camera, &camera.view(), reverse_z),
+		);
+		let bind_group = CameraUniform::create_bind_group(
+			device,
+			camera_layout,
+			&camera_buf,
+			"View Camera Bind Group",
+		);
+		Self {
+			viewport,
+			camera,
+			camera_buf,
+			bind_group,
+		}
+	}
+
+	/// Uploads the camera's current view, for a reverse-Z depth buffer if
+	/// `reverse_z` is set. Returns the number of bytes uploaded.
+	fn update(&self, queue: &wgpu::Queue, reverse_z: bool) -> u64 {
+		let uniform =
+			CameraUniform::from_view(&*self.camera, &self.camera.view(), reverse_z);
+		queue.write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of_val(&uniform) as u64
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+	use crate::camera::Camera;
+	use crate::gpu_context::{test_context, test_state};
+	use crate::mesh::{generate_cube, generate_sphere};
+	use crate::morph::MorphTarget;
+	use crate::tex2d::Shape;
+	use crate::vertex::{Color, Normal, Pos, Uv};
+	use nalgebra::{Orthographic3, Perspective3, Rotation3};
+	use std::f32::consts::FRAC_PI_2;
+
+	/// The top left pixel of a screenshot, away from the default quad in the
+	/// middle of the frame.
+	fn corner(state: &mut RenderState) -> [u8; 4] {
+		pixel(state, 0, 0)
+	}
+
+	/// The pixel at `x`, `y` of a screenshot.
+	fn pixel(state: &mut RenderState, x: u32, y: u32) -> [u8; 4] {
+		state.capture_screenshot().unwrap().get_pixel(x, y).0
+	}
+
+	/// Asserts that the color channels of `actual` are within `tolerance` of
+	/// `expected`'s.
+	#[track_caller]
+	fn assert_rgb_near(actual: [u8; 4], expected: [u8; 3], tolerance: u8) {
+		let near = (0..3).all(|i| actual[i].abs_diff(expected[i]) <= tolerance);
+		assert!(near, "{actual:?} is not within {tolerance} of {expected:?}");
+	}
+
+	/// Lights surfaces facing the camera with ambient light only, as the light
+	/// comes from behind them, so that they are drawn with the color of their
+	/// diffuse texture, over black. The orthographic camera looks down -z from
+	/// z = 5, and sees from -1 to 1 on both axes, so the default quad covers the
+	/// middle half of square frames.
+	fn flat_scene(state: &mut RenderState) {
+		state.set_clear_color(wgpu::Color::BLACK);
+		state.set_light(LightUniform::new([1., 0., 1.], [1.; 3], 1.));
+		let proj = Orthographic3::new(-1., 1., -1., 1., 0.1, 100.);
+		let camera = Camera::new(Point3::new(0., 0., 5.), 0., 0., proj);
+		state.set_camera(Box::new(camera));
+	}
+
+	/// Replaces the default quad's texture with one of the solid color `rgba`.
+	fn set_diffuse(state: &mut RenderState, rgba: [u8; 4]) {
+		let texture = Tex2d::from_color(&state.device, &state.queue, None, rgba);
+		state.resources.remove(state.diffuse_tex);
+		state.diffuse_tex = state.resources.insert(texture);
+		state.update_material_bind_group();
+	}
+
+	/// Adds an object of the default quad's shape with the solid color `rgba`,
+	/// moved by `transform`.
+	fn add_quad(
+		state: &mut RenderState,
+		rgba: [u8; 4],
+		blend: BlendMode,
+		transform: Matrix4<f32>,
+	) {
+		let texture = Tex2d::from_color(&state.device, &state.queue, None, rgba);
+		let builder = MaterialBuilder::new().diffuse(&texture).blend(blend);
+		let material = state.build_material(builder);
+		let mesh = GpuMesh::new(&state.device, QUAD_VERTICES, QUAD_INDICES);
+		state.add_object(RenderObject {
+			mesh: Arc::new(mesh),
+			material: Arc::new(material),
+			transform,
+			stencil: StencilMode::Disabled,
+		});
+	}
+
+	/// Adds an object of the default quad's shape, shaded physically with the
+	/// solid colors `albedo` and `metallic_roughness`.
+	fn add_pbr_quad(
+		state: &mut RenderState,
+		albedo: [u8; 4],
+		metallic_roughness: [u8; 4],
+	) {
+		let solid = |rgba| Tex2d::from_color(&state.device, &state.queue, None, rgba);
+		let (albedo, metallic_roughness) = (solid(albedo), solid(metallic_roughness));
+		let builder = MaterialBuilder::new()
+			.diffuse(&albedo)
+			.metallic_roughness(&metallic_roughness);
+		let material = state.build_material(builder);
+		let mesh = GpuMesh::new(&state.device, QUAD_VERTICES, QUAD_INDICES);
+		state.add_object(RenderObject {
+			mesh: Arc::new(mesh),
+			material: Arc::new(material),
+			transform: Matrix4::identity(),
+			stencil: StencilMode::Disabled,
+		});
+	}
+
+	/// Hides the default quad behind the camera, leaving the objects.
+	fn hide_default_quad(state: &mut RenderState) {
+		let hidden: Vec<_> = QUAD_VERTICES
+			.iter()
+			.map(|v| Vertex {
+				pos: Pos::new(v.pos.x, v.pos.y, 10.),
+				..*v
+			})
+			.collect();
+		state.load_mesh(&hidden, QUAD_INDICES);
+	}
+
+	#[test]
+	fn screenshot_has_clear_color() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 48) else {
+			return;
+		};
+		state.set_clear_color(wgpu::Color::GREEN);
+		let img = state.capture_screenshot().unwrap();
+		assert_eq!(img.dimensions(), (64, 48));
+		// Away from the default quad in the middle of the frame.
+		assert_eq!(img.get_pixel(0, 0).0, [0, 255, 0, 255]);
+	}
+
+	#[test]
+	fn frame_without_geometry_is_the_clear_color() {
+		let builder = RenderStateBuilder::new().clear_color(wgpu::Color::BLUE);
+		let Some(mut state) = test_state(builder, 64, 48) else {
+			return;
+		};
+		hide_default_quad(&mut state);
+		let img = state.capture_screenshot().unwrap();
+		assert!(img.pixels().all(|p| p.0 == [0, 0, 255, 255]));
+
+		state.set_clear_color(wgpu::Color::RED);
+		let img = state.capture_screenshot().unwrap();
+		assert!(img.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+	}
+
+	#[test]
+	fn logical_size_is_scaled_down_by_the_scale_factor() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 48) else {
+			return;
+		};
+		// Headless states have no monitor to take it from.
+		assert_eq!(state.logical_size(), (64., 48.));
+		state.set_scale_factor(2.);
+		assert_eq!(state.logical_size(), (32., 24.));
+		assert_eq!((state.config.width, state.config.height), (64, 48));
+	}
+
+	#[test]
+	fn resize_updates_camera_aspect() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 800, 600) else {
+			return;
+		};
+		let before = state.camera.proj_view();
+		state.resize(PhysicalSize::new(1600, 600)).unwrap();
+		let after = state.camera.proj_view();
+		assert_ne!(before, after);
+		assert!((after[(0, 0)] - before[(0, 0)] / 2.).abs() < 1e-5);
+	}
+
+	#[test]
+	fn new_headless_renders_without_window() {
+		if test_context().is_none() {
+			return;
+		}
+		let mut state =
+			pollster::block_on(RenderState::new_headless(32, 32, 1)).unwrap();
+		state.render().unwrap();
+		state.set_clear_color(wgpu::Color::BLUE);
+		assert_eq!(corner(&mut state), [0, 0, 255, 255]);
+	}
+
+	#[test]
+	fn builder_clear_color() {
+		if test_context().is_none() {
+			return;
+		}
+		let builder = RenderStateBuilder::new().clear_color(wgpu::Color::RED);
+		let mut state = pollster::block_on(builder.build_headless(800, 600)).unwrap();
+		assert_eq!(corner(&mut state), [255, 0, 0, 255]);
+	}
+
+	#[test]
+	fn blend_modes_select_different_pipelines() {
+		let Some(context) = test_context() else {
+			return;
+		};
+		let device = &context.device;
+		let tex = Tex2d::from_color(device, &context.queue, None, [255; 4]);
+		let layout = Material::layout(device);
+		let opaque = Material::new(device, &layout, &tex, &tex, &tex, None);
+		let mut alpha = Material::new(device, &layout, &tex, &tex, &tex, None);
+		alpha.blend = BlendMode::Alpha;
+
+		let (opaque, alpha) = (
+			PipelineKey::lod_material(&opaque),
+			PipelineKey::lod_material(&alpha),
+		);
+		assert_ne!(opaque, alpha);
+		assert_eq!(opaque.cull_mode, alpha.cull_mode);
+		assert_eq!(opaque.topology, alpha.topology);
+	}
+
+	#[test]
+	fn alpha_blending_mixes_with_the_background() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		state.set_clear_color(wgpu::Color::BLUE);
+		set_diffuse(&mut state, [255, 0, 0, 128]);
+		state.set_blend_mode(BlendMode::Alpha);
+		// Half of each, in linear space, is 188 in sRGB.
+		assert_rgb_near(pixel(&mut state, 32, 32), [188, 0, 188], 3);
+		assert_eq!(corner(&mut state), [0, 0, 255, 255]);
+
+		state.set_blend_mode(BlendMode::Opaque);
+		assert_rgb_near(pixel(&mut state, 32, 32), [255, 0, 0], 1);
+	}
+
+	#[test]
+	fn objects_are_moved_by_their_transform() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// Covers 0.25 to 0.75 on both axes, with the model matrix in push constants
+		// or the uniform buffer replacing them.
+		let transform = Matrix4::new_translation(&Vector3::new(0.5, 0.5, 0.))
+			* Matrix4::new_scaling(0.5);
+		add_quad(&mut state, [255, 0, 0, 255], BlendMode::Opaque, transform);
+		assert_rgb_near(pixel(&mut state, 48, 16), [255, 0, 0], 1);
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 0], 0);
+		assert_rgb_near(pixel(&mut state, 36, 16), [0, 0, 0], 0);
+	}
+
+	#[test]
+	fn stencil_mask_limits_drawing_to_a_circle() {
+		let builder = RenderStateBuilder::new().stencil(true);
+		let Some(mut state) = test_state(builder, 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		let material = |rgba| {
+			let texture = Tex2d::from_color(&state.device, &state.queue, None, rgba);
+			Arc::new(state.build_material(MaterialBuilder::new().diffuse(&texture)))
+		};
+		let (green, red) = (material([0, 255, 0, 255]), material([255, 0, 0, 255]));
+		let vertex = |x, y| {
+			Vertex::new(
+				Pos::new(x, y, 0.),
+				Uv { u: 0., v: 0. },
+				Normal::new(0., 0., 1.),
+			)
+			.with_tangents([1., 0., 0.], [0., -1., 0.])
+		};
+
+		// A fan of triangles around the center, of radius 0.5.
+		const SEGMENTS: u32 = 32;
+		let mut circle = vec![vertex(0., 0.)];
+		circle.extend((0..SEGMENTS).map(|i| {
+			let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+			vertex(0.5 * angle.cos(), 0.5 * angle.sin())
+		}));
+		let indices: Vec<u32> = (0..SEGMENTS)
+			.flat_map(|i| [0, i + 1, (i + 1) % SEGMENTS + 1])
+			.collect();
+		state.add_object(RenderObject {
+			mesh: Arc::new(GpuMesh::new(&state.device, &circle, &indices)),
+			material: green,
+			transform: Matrix4::identity(),
+			stencil: StencilMode::WriteRef(1),
+		});
+		// Covers the whole frame, but is only drawn over the circle.
+		state.add_object(RenderObject {
+			mesh: Arc::new(GpuMesh::new(&state.device, QUAD_VERTICES, QUAD_INDICES)),
+			material: red,
+			transform: Matrix4::new_scaling(2.),
+			stencil: StencilMode::TestEqual(1),
+		});
+
+		let img = state.capture_screenshot().unwrap();
+		assert_rgb_near(img.get_pixel(32, 32).0, [255, 0, 0], 1);
+		// Inside the quad, but outside the circle.
+		for (x, y) in [(2, 2), (60, 32), (32, 4), (56, 56)] {
+			assert_rgb_near(img.get_pixel(x, y).0, [0, 0, 0], 0);
+		}
+	}
+
+	#[test]
+	fn streamed_vertices_replace_the_previous_frames() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [255, 0, 0, 255]);
+		// The default quad moved by `dx`, into the left or right half.
+		let moved = |dx: f32| -> Vec<Vertex> {
+			QUAD_VERTICES
+				.iter()
+				.map(|v| Vertex {
+					pos: Pos::new(v.pos.x + dx, v.pos.y, v.pos.z),
+					..*v
+				})
+				.collect()
+		};
+		let upload = |state: &mut RenderState, dx| {
+			let mut encoder = state.begin_uploads();
+			state.upload_vertices(&mut encoder, &moved(dx));
+			state.upload_indices(&mut encoder, QUAD_INDICES);
+			state.submit_uploads(encoder);
+			state.capture_screenshot().unwrap()
+		};
+
+		let left = upload(&mut state, -0.5);
+		assert_rgb_near(left.get_pixel(16, 32).0, [255, 0, 0], 1);
+		assert_rgb_near(left.get_pixel(48, 32).0, [0, 0, 0], 0);
+		let right = upload(&mut state, 0.5);
+		assert_rgb_near(right.get_pixel(16, 32).0, [0, 0, 0], 0);
+		assert_rgb_near(right.get_pixel(48, 32).0, [255, 0, 0], 1);
+	}
+
+	#[test]
+	fn scissor_keeps_the_clear_color_outside() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		state.set_clear_color(wgpu::Color::BLUE);
+		set_diffuse(&mut state, [255, 0, 0, 255]);
+		// The left half of the frame, which cuts the default quad in two.
+		state.set_scissor(Some(ScissorRect::new(0, 0, 32, 64)));
+		let img = state.capture_screenshot().unwrap();
+		assert_rgb_near(img.get_pixel(24, 32).0, [255, 0, 0], 1);
+		assert_eq!(img.get_pixel(40, 32).0, [0, 0, 255, 255]);
+		assert_eq!(img.get_pixel(63, 0).0, [0, 0, 255, 255]);
+	}
+
+	#[test]
+	fn reverse_z_keeps_nearer_objects_in_front() {
+		let builder = RenderStateBuilder::new().use_reverse_z(true);
+		let Some(mut state) = test_state(builder, 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// Overlapping from -0.25 to 0.25, with red nearer to the camera.
+		let at = |x: f32, z: f32| Matrix4::new_translation(&Vector3::new(x, 0., z));
+		let (red, green) = ([255, 0, 0, 255], [0, 255, 0, 255]);
+		add_quad(&mut state, red, BlendMode::Opaque, at(-0.25, 1.));
+		add_quad(&mut state, green, BlendMode::Opaque, at(0.25, -1.));
+		let img = state.capture_screenshot().unwrap();
+		assert_rgb_near(img.get_pixel(12, 32).0, [255, 0, 0], 1);
+		assert_rgb_near(img.get_pixel(32, 32).0, [255, 0, 0], 1);
+		assert_rgb_near(img.get_pixel(52, 32).0, [0, 255, 0], 1);
+	}
+
+	#[test]
+	fn transparent_objects_blend_back_to_front() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// Added front to back, so that they are only blended right if sorted.
+		let at = |z: f32| Matrix4::new_translation(&Vector3::new(0., 0., z));
+		add_quad(&mut state, [255, 0, 0, 128], BlendMode::Alpha, at(1.));
+		add_quad(&mut state, [0, 255, 0, 128], BlendMode::Alpha, at(-1.));
+		// Half of the red over a quarter of the green, in linear space, is 188 and
+		// 137 in sRGB. The other order would swap them.
+		assert_rgb_near(pixel(&mut state, 32, 32), [188, 137, 0], 3);
+	}
+
+	#[test]
+	fn set_present_mode_updates_the_config() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 32, 32) else {
+			return;
+		};
+		state.set_present_mode(wgpu::PresentMode::Fifo);
+		assert_eq!(state.config.present_mode, wgpu::PresentMode::Fifo);
+		assert_eq!(state.present_modes(), [wgpu::PresentMode::Fifo]);
+	}
+
+	#[test]
+	fn unsupported_present_modes_fall_back_to_fifo() {
+		use wgpu::PresentMode::{Fifo, Immediate, Mailbox};
+		assert_eq!(supported_present_mode(Mailbox, &[Fifo, Mailbox]), Mailbox);
+		assert_eq!(supported_present_mode(Mailbox, &[Fifo, Immediate]), Fifo);
+	}
+
+	#[test]
+	fn color_mesh_interpolates_vertex_colors() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		let vertices = [
+			ColorVertex::new(Pos::new(-1., -1., 0.), Color::new(1., 0., 0., 1.)),
+			ColorVertex::new(Pos::new(1., -1., 0.), Color::new(0., 1., 0., 1.)),
+			ColorVertex::new(Pos::new(0., 1., 0.), Color::new(0., 0., 1., 1.)),
+		];
+		state.add_color_mesh(&vertices, &[0, 1, 2]);
+		// A third of each at the centroid, (0, -1/3), in linear space, which is 156
+		// in sRGB.
+		assert_rgb_near(pixel(&mut state, 32, 42), [156, 156, 156], 3);
+		assert_rgb_near(corner(&mut state), [0, 0, 0], 0);
+	}
+
+	#[test]
+	fn single_joint_moves_vertices_like_its_matrix() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		set_diffuse(&mut state, [255, 0, 0, 255]);
+		let vertices: Vec<_> = QUAD_VERTICES
+			.iter()
+			.map(|&v| SkinnedVertex::new(v, [0; 4], [1., 0., 0., 0.]))
+			.collect();
+		state.add_skinned_mesh(&vertices, QUAD_INDICES);
+		// Like `objects_are_moved_by_their_transform`, from 0.25 to 0.75 on both
+		// axes.
+		let bone = Matrix4::new_translation(&Vector3::new(0.5, 0.5, 0.))
+			* Matrix4::new_scaling(0.5);
+		state.update_skeleton(&[bone]);
+		assert_rgb_near(pixel(&mut state, 48, 16), [255, 0, 0], 1);
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 0], 0);
+		assert_rgb_near(pixel(&mut state, 36, 16), [0, 0, 0], 0);
+	}
+
+	#[test]
+	fn distant_objects_fade_into_the_fog() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// 50 units from the camera, far past where the fog ends.
+		let far = Matrix4::new_translation(&Vector3::new(0., 0., -45.));
+		add_quad(&mut state, [255, 0, 0, 255], BlendMode::Opaque, far);
+		state.set_fog(FogUniform::linear([0., 0., 1.], 0., 10.));
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 255], 1);
+
+		state.disable_fog();
+		assert_rgb_near(pixel(&mut state, 32, 32), [255, 0, 0], 1);
+	}
+
+	#[test]
+	fn placeholder_is_drawn_until_the_pipeline_is_created() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		state.set_async_pipelines(true);
+		// Physically shaded, so that it needs a variant of its own.
+		add_pbr_quad(&mut state, [255, 0, 0, 255], [0, 255, 0, 255]);
+		// The placeholder's linear gray 0.5.
+		assert_rgb_near(pixel(&mut state, 32, 32), [188; 3], 1);
+
+		while !state.pending_pipelines.values().all(|(_, p)| p.is_ready()) {
+			std::thread::sleep(std::time::Duration::from_millis(1));
+		}
+		let [r, g, ..] = pixel(&mut state, 32, 32);
+		assert!(r > g + 100, "still the placeholder: {r}, {g}");
+		assert_eq!(state.pending_pipelines(), 0);
+	}
+
+	#[test]
+	fn occluders_shadow_the_plane_behind_them() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [255; 4]);
+		// Travels along +x as much as along -z, without ambient light.
+		state.set_light(LightUniform::new([1., 0., -1.], [1.; 3], 0.));
+		// Half as wide as the default quad, 0.5 in front of its left side. Its
+		// shadow falls 0.5 further along x, on the middle of the quad.
+		let occluder = Matrix4::new_translation(&Vector3::new(-0.5, 0., 0.5))
+			* Matrix4::new_scaling(0.5);
+		add_quad(&mut state, [255; 4], BlendMode::Opaque, occluder);
+		let img = state.capture_screenshot().unwrap();
+		let linear = |x| {
+			let red = img.get_pixel(x, 32).0[0] as f32 / 255.;
+			((red + 0.055) / 1.055).powf(2.4)
+		};
+		// On the quad's right side, lit at 45°.
+		let (lit, shadowed) = (linear(44), linear(32));
+		assert!(lit > 0.65, "{lit} is not fully lit");
+		assert!(shadowed < 0.2 * lit, "{shadowed} is not in shadow");
+	}
+
+	#[test]
+	fn depth_of_field_only_blurs_what_is_out_of_focus() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [255, 0, 0, 255]);
+		state.set_depth_of_field(true);
+		let Some(dof) = state.depth_of_field_mut() else {
+			panic!("depth of field wasn't enabled");
+		};
+		// On the quad, 5 units away. Wide enough that the far plane is blurred
+		// by the largest radius.
+		dof.focus_distance = 5.;
+		dof.aperture = 1.;
+		// Just inside the quad's left edge.
+		assert_rgb_near(pixel(&mut state, 16, 32), [255, 0, 0], 1);
+
+		// The orthographic camera sees the quad the same size, 50 units away.
+		let proj = Orthographic3::new(-1., 1., -1., 1., 0.1, 100.);
+		let camera = Camera::new(Point3::new(0., 0., 50.), 0., 0., proj);
+		state.set_camera(Box::new(camera));
+		let [red, ..] = pixel(&mut state, 16, 32);
+		assert!(red < 230, "{red} wasn't blurred with the black around it");
+	}
+
+	/// The number of pixels partly covered by a red triangle with a diagonal
+	/// edge, drawn by a state from `builder`, or `None` without a GPU or if it
+	/// has no MSAA resolve pass when `msaa` is set.
+	fn partly_covered_pixels(builder: RenderStateBuilder, msaa: bool) -> Option<usize> {
+		let mut state = test_state(builder, 64, 64)?;
+		if msaa && state.resolve_filter().is_none() {
+			return None;
+		}
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [255, 0, 0, 255]);
+		let vertex = |x, y| {
+			Vertex::new(
+				Pos::new(x, y, 0.),
+				Uv { u: 0., v: 0. },
+				Normal::new(0., 0., 1.),
+			)
+			.with_tangents([1., 0., 0.], [0., -1., 0.])
+		};
+		// Its hypotenuse crosses the rows at different fractions of a pixel.
+		let triangle = [vertex(-1., -1.), vertex(1., -1.), vertex(-1., 0.6)];
+		state.load_mesh(&triangle, &[0, 1, 2]);
+		let img = state.capture_screenshot().unwrap();
+		Some(
+			img.pixels()
+				.filter(|p| (20..=235).contains(&p.0[0]))
+				.count(),
+		)
+	}
+
+	#[test]
+	fn msaa_resolve_filter_smooths_diagonal_edges() {
+		let Some(aliased) = partly_covered_pixels(RenderStateBuilder::new(), false)
+		else {
+			return;
+		};
+		assert_eq!(aliased, 0);
+		let builder = RenderStateBuilder::new()
+			.sample_count(4)
+			.resolve_filter(ResolveFilter::Tent);
+		let Some(smoothed) = partly_covered_pixels(builder, true) else {
+			return;
+		};
+		// About a pixel per column along the edge.
+		assert!(smoothed > 32, "only {smoothed} pixels were smoothed");
+	}
+
+	#[test]
+	fn point_lights_cast_shadows_away_from_themselves() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [255; 4]);
+		// Only the point light lights the floor, which fills the frame.
+		state.set_light(LightUniform::new([0., 0., -1.], [0.; 3], 0.));
+		let floor: Vec<_> = QUAD_VERTICES
+			.iter()
+			.map(|v| Vertex {
+				pos: Pos::new(v.pos.x * 2., v.pos.y * 2., 0.),
+				..*v
+			})
+			.collect();
+		state.load_mesh(&floor, QUAD_INDICES);
+
+		// Halfway between the light and the middle of the floor, which it shades.
+		let (vertices, indices) = generate_sphere(0.1, 8, 16);
+		let indices: Vec<u32> = indices.into_iter().map(u32::from).collect();
+		let texture = Tex2d::from_color(&state.device, &state.queue, None, [255; 4]);
+		let material = state.build_material(MaterialBuilder::new().diffuse(&texture));
+		state.add_object(RenderObject {
+			mesh: Arc::new(GpuMesh::new(&state.device, &vertices, &indices)),
+			material: Arc::new(material),
+			transform: Matrix4::new_translation(&Vector3::new(-0.2, 0., 0.2)),
+			stencil: StencilMode::Disabled,
+		});
+		state.add_point_light(Point3::new(-0.4, 0., 0.4), 5., [4.; 3]);
+
+		let img = state.capture_screenshot().unwrap();
+		let linear = |x| {
+			let red = img.get_pixel(x, 32).0[0] as f32 / 255.;
+			((red + 0.055) / 1.055).powf(2.4)
+		};
+		// As far from the light as the middle of the floor, on the other side.
+		let (lit, shadowed) = (linear(6), linear(32));
+		assert!(lit > 0.5, "{lit} is not lit");
+		assert!(shadowed < 0.2 * lit, "{shadowed} is not in shadow");
+	}
+
+	#[test]
+	fn sdf_ao_darkens_the_floor_next_to_a_wall() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		let white = [255; 4];
+		// Fills the frame.
+		let floor = Matrix4::new_scaling(2.);
+		add_quad(&mut state, white, BlendMode::Opaque, floor);
+		// Seen edge on, standing on the middle of the floor and facing -x, 1 high.
+		let wall = Matrix4::new_translation(&Vector3::new(0., 0., 0.5))
+			* Rotation3::from_axis_angle(&Vector3::y_axis(), -FRAC_PI_2)
+				.to_homogeneous()
+			* Matrix4::new_nonuniform_scaling(&Vector3::new(1., 2., 1.));
+		add_quad(&mut state, white, BlendMode::Opaque, wall);
+		let settings = SdfAoSettings {
+			max_distance: 0.5,
+			intensity: 1.,
+		};
+		if state.set_sdf_ao(Some(settings)).is_err() {
+			return;
+		}
+
+		let img = state.capture_screenshot().unwrap();
+		// About 0.06 from the wall, and 0.8 from it.
+		let red = |x| img.get_pixel(x, 32).0[0] as u32;
+		let (corner, open) = (red(30), red(6));
+		assert!(corner + 20 < open, "{corner} is not darker than {open}");
+	}
+
+	#[test]
+	fn rough_dielectrics_have_no_specular_highlight() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// From the camera, so that smooth surfaces reflect it in the middle. With a
+		// black albedo, only the specular light is left.
+		state.set_light(LightUniform::new([0., 0., -1.], [1.; 3], 0.));
+		let black = [0, 0, 0, 255];
+
+		add_pbr_quad(&mut state, black, [0, 0, 0, 255]);
+		let smooth = state.capture_screenshot().unwrap();
+		assert!(smooth.get_pixel(32, 32).0[0] > 250);
+		assert!(smooth.get_pixel(18, 32).0[0] < 60);
+
+		state.clear_objects();
+		add_pbr_quad(&mut state, black, [0, 255, 0, 255]);
+		// Fully rough surfaces reflect the 4% of a dielectric evenly, 25 in sRGB.
+		let rough = state.capture_screenshot().unwrap();
+		let (middle, edge) = (rough.get_pixel(32, 32).0, rough.get_pixel(18, 32).0);
+		assert_rgb_near(middle, [25; 3], 3);
+		assert_rgb_near(edge, [middle[0]; 3], 2);
+	}
+
+	#[test]
+	fn smooth_metals_reflect_the_environment_map() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// Lit from behind and without ambient light, so that only the environment
+		// is reflected.
+		state.set_light(LightUniform::new([1., 0., 1.], [1.; 3], 0.));
+		let green =
+			Cubemap::from_color(&state.device, &state.queue, None, [0, 255, 0, 255]);
+		state.set_environment_map(green);
+		add_pbr_quad(&mut state, [255; 4], [0, 0, 255, 255]);
+		// Roughness is at least 0.04, which reflects 96% of it, 250 in sRGB.
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 250, 0], 3);
+
+		state.clear_environment_map();
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 0], 1);
+	}
+
+	#[test]
+	fn gamma_correction_encodes_linear_gray() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		// Linear 0.5 is 188 in sRGB.
+		set_diffuse(&mut state, [188, 188, 188, 255]);
+		state.set_color_correction(ColorCorrectionUniform::gamma(2.2, 1.));
+		// The frame is sRGB, so what the shader wrote is decoded from it.
+		let red = pixel(&mut state, 32, 32)[0] as f32 / 255.;
+		let written = ((red + 0.055) / 1.055).powf(2.4);
+		assert!((written - 0.73).abs() < 0.01, "{written} is not about 0.73");
+	}
+
+	#[test]
+	fn decals_are_projected_onto_the_surface() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [0, 0, 0, 255]);
+		let red =
+			Tex2d::from_color(&state.device, &state.queue, None, [255, 0, 0, 255]);
+		let red = state.resources.insert(red);
+		// Centered on the default quad, and half as wide.
+		state.add_decal(Point3::origin(), IsometryMatrix3::identity(), [0.5; 3], red);
+		let img = state.capture_screenshot().unwrap();
+		assert_rgb_near(img.get_pixel(32, 32).0, [255, 0, 0], 1);
+		// On the quad, outside of the decal's box.
+		assert_rgb_near(img.get_pixel(20, 32).0, [0, 0, 0], 0);
+	}
+
+	#[test]
+	fn stencil_decals_only_cover_the_surface_within_their_box() {
+		let builder = RenderStateBuilder::new().stencil(true);
+		let Some(mut state) = test_state(builder, 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		set_diffuse(&mut state, [0, 0, 0, 255]);
+		let red =
+			Tex2d::from_color(&state.device, &state.queue, None, [255, 0, 0, 255]);
+		let red = state.resources.insert(red);
+		// Centered on the default quad, and half as wide.
+		state.add_stencil_decal(Point3::origin(), [0.25; 3], red);
+		let img = state.capture_screenshot().unwrap();
+		assert_rgb_near(img.get_pixel(32, 32).0, [255, 0, 0], 1);
+		// On the quad, outside of the decal's box.
+		assert_rgb_near(img.get_pixel(20, 32).0, [0, 0, 0], 0);
+
+		// In front of the quad, so that the box holds no surface.
+		state.add_stencil_decal(Point3::new(0., 0., 1.), [0.25; 3], red);
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 0], 0);
+	}
+
+	#[test]
+	fn full_weight_moves_vertices_to_the_morph_target() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		// From 0.25 to 0.75 on both axes, like
+		// `objects_are_moved_by_their_transform`.
+		let target = MorphTarget {
+			vertices: QUAD_VERTICES
+				.iter()
+				.map(|v| Vertex {
+					pos: Pos::new(v.pos.x * 0.5 + 0.5, v.pos.y * 0.5 + 0.5, v.pos.z),
+					..*v
+				})
+				.collect(),
+			weight: 1.,
+		};
+		let Ok(morphed) =
+			MorphedMesh::new(&state.device, QUAD_VERTICES, QUAD_INDICES, &[target])
+		else {
+			return;
+		};
+		let texture =
+			Tex2d::from_color(&state.device, &state.queue, None, [255, 0, 0, 255]);
+		let material = state.build_material(MaterialBuilder::new().diffuse(&texture));
+		state.add_object(RenderObject {
+			mesh: morphed.mesh(),
+			material: Arc::new(material),
+			transform: Matrix4::identity(),
+			stencil: StencilMode::Disabled,
+		});
+		let index = state.add_morphed_mesh(morphed);
+		assert_rgb_near(pixel(&mut state, 48, 16), [255, 0, 0], 1);
+		assert_rgb_near(pixel(&mut state, 32, 32), [0, 0, 0], 0);
+		assert_rgb_near(pixel(&mut state, 36, 16), [0, 0, 0], 0);
+
+		// Back to the base shape, in the middle.
+		state.morphed_meshes[index].update_weights(&state.queue, &[0.]);
+		assert_rgb_near(pixel(&mut state, 32, 32), [255, 0, 0], 1);
+	}
+
+	#[test]
+	fn parallax_shifts_uvs_away_from_the_viewer() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		hide_default_quad(&mut state);
+		let proj = Perspective3::new(1., std::f32::consts::FRAC_PI_3, 0.1, 100.);
+		state.set_camera(Box::new(Camera::new(Point3::new(0., 0., 2.), 0., 0., proj)));
+		// Redder along u, with a surface as deep as the height map goes.
+		let gradient: Vec<u8> = (0..=255).flat_map(|x| [x, 0, 0, 255]).collect();
+		let shape = |width| Shape { width, height: 1 };
+		let (device, queue) = (&state.device, &state.queue);
+		let diffuse = Tex2d::new_from_rgba8(
+			device,
+			queue,
+			None,
+			&gradient,
+			shape(256),
+			SamplerConfig::default(),
+		);
+		let height = Tex2d::new_from_r8(
+			device,
+			queue,
+			None,
+			&[0],
+			shape(1),
+			SamplerConfig::default(),
+		);
+
+		// The red left and right of the middle, where the viewer is seen to the
+		// right and left.
+		let mut red_around_middle = |builder: MaterialBuilder| {
+			state.clear_objects();
+			let material = state.build_material(builder.diffuse(&diffuse));
+			let mesh = GpuMesh::new(&state.device, QUAD_VERTICES, QUAD_INDICES);
+			state.add_object(RenderObject {
+				mesh: Arc::new(mesh),
+				material: Arc::new(material),
+				transform: Matrix4::identity(),
+				stencil: StencilMode::Disabled,
+			});
+			let img = state.capture_screenshot().unwrap();
+			[22, 41].map(|x| img.get_pixel(x, 32).0[0] as i32)
+		};
+		let [flat_left, flat_right] = red_around_middle(MaterialBuilder::new());
+		let [left, right] = red_around_middle(
+			MaterialBuilder::new()
+				.height_map(&height)
+				.parallax_depth_scale(0.25),
+		);
+		// Deeper points are seen further from the viewer, at the side of the middle.
+		assert!(left < flat_left - 5, "{left} is not below {flat_left}");
+		assert!(right > flat_right + 5, "{right} is not above {flat_right}");
+	}
+
+	#[test]
+	fn cel_outline_darkens_the_silhouette() {
+		let Some(mut state) = test_state(RenderStateBuilder::new(), 64, 64) else {
+			return;
+		};
+		flat_scene(&mut state);
+		state.set_clear_color(wgpu::Color::WHITE);
+		set_diffuse(&mut state, [255; 4]);
+		// Its front face covers the middle half of the frame.
+		let (vertices, indices) = generate_cube(0.5);
+		let indices: Vec<u32> = indices.into_iter().map(u32::from).collect();
+		state.load_mesh(&vertices, &indices);
+		state.set_cel_outline(true);
+
+		let img = state.capture_screenshot().unwrap();
+		let brightness = |x, y| {
+			img.get_pixel(x, y).0[..3]
+				.iter()
+				.map(|&c| c as u32)
+				.sum::<u32>()
+		};
+		let silhouette = (14..=17).map(|x| brightness(x, 32)).min().unwrap();
+		let interior = brightness(32, 32);
+		assert!(silhouette < interior / 2, "{silhouette} vs. {interior}");
+	}
 }
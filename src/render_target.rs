@@ -0,0 +1,97 @@
+use crate::render_state::RenderState;
+use crate::tex2d::{SamplerConfig, Tex2d};
+
+/// An offscreen texture to render into, with its own depth buffer, whose result
+/// can be sampled in later passes.
+pub struct RenderTarget {
+	color: Tex2d,
+	depth_tex: wgpu::Texture,
+	depth_view: wgpu::TextureView,
+	label: String,
+}
+impl RenderTarget {
+	/// Creates a `width` x `height` target. The depth buffer has
+	/// [`RenderState::DEPTH_FORMAT`].
+	pub fn new(
+		device: &wgpu::Device,
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+		label: &str,
+	) -> Self {
+		let size = wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING
+				| wgpu::TextureUsages::COPY_SRC,
+			view_formats: &[],
+		});
+		let sampler_config = SamplerConfig::default();
+		let color = Tex2d {
+			view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+			texture,
+			sampler: sampler_config.create_sampler(device),
+			sampler_config,
+		};
+
+		let depth_tex = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(&format!("{label} Depth")),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: RenderState::DEPTH_FORMAT,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+		let depth_view = depth_tex.create_view(&wgpu::TextureViewDescriptor::default());
+
+		Self {
+			color,
+			depth_tex,
+			depth_view,
+			label: label.to_owned(),
+		}
+	}
+
+	pub fn color(&self) -> &Tex2d {
+		&self.color
+	}
+
+	pub fn color_view(&self) -> &wgpu::TextureView {
+		&self.color.view
+	}
+
+	pub fn depth_texture(&self) -> &wgpu::Texture {
+		&self.depth_tex
+	}
+
+	pub fn depth_view(&self) -> &wgpu::TextureView {
+		&self.depth_view
+	}
+
+	pub fn size(&self) -> wgpu::Extent3d {
+		self.color.texture.size()
+	}
+
+	/// Creates a bind group to sample the color texture, matching
+	/// [`Tex2d::layout`].
+	pub fn bind_group(
+		&self,
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+	) -> wgpu::BindGroup {
+		self.color.bind_group(device, layout, Some(&self.label))
+	}
+}
@@ -0,0 +1,189 @@
+//! Storage for GPU resources, referred to by typed handles.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Refers to a `T` in a [`ResourceManager`]. Once the resource is removed, the
+/// handle stays invalid, even if its slot is reused.
+pub struct Handle<T> {
+	index: u32,
+	/// Which use of the slot at `index` the handle refers to.
+	generation: u32,
+	_marker: PhantomData<fn() -> T>,
+}
+// Derives would require `T` to implement the traits too.
+impl<T> Clone for Handle<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<T> Copy for Handle<T> {}
+impl<T> PartialEq for Handle<T> {
+	fn eq(&self, other: &Self) -> bool {
+		(self.index, self.generation) == (other.index, other.generation)
+	}
+}
+impl<T> Eq for Handle<T> {}
+impl<T> std::hash::Hash for Handle<T> {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		(self.index, self.generation).hash(state);
+	}
+}
+impl<T> std::fmt::Debug for Handle<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Handle")
+			.field("type", &std::any::type_name::<T>())
+			.field("index", &self.index)
+			.field("generation", &self.generation)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+impl<T> Handle<T> {
+	/// A handle for tests that need one without creating the resource.
+	pub(crate) fn for_tests(index: u32) -> Self {
+		Self {
+			index,
+			generation: 0,
+			_marker: PhantomData,
+		}
+	}
+}
+
+struct Slot<T> {
+	generation: u32,
+	value: Option<T>,
+}
+
+/// The resources of a single type.
+struct Pool<T> {
+	slots: Vec<Slot<T>>,
+	/// Indices of the empty slots.
+	free: Vec<u32>,
+}
+impl<T> Pool<T> {
+	fn new() -> Self {
+		Self {
+			slots: Vec::new(),
+			free: Vec::new(),
+		}
+	}
+
+	fn insert(&mut self, value: T) -> Handle<T> {
+		let index = match self.free.pop() {
+			Some(index) => index,
+			None => {
+				self.slots.push(Slot {
+					generation: 0,
+					value: None,
+				});
+				(self.slots.len() - 1) as u32
+			}
+		};
+		let slot = &mut self.slots[index as usize];
+		slot.value = Some(value);
+		Handle {
+			index,
+			generation: slot.generation,
+			_marker: PhantomData,
+		}
+	}
+
+	fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+		self.slots
+			.get(handle.index as usize)
+			.filter(|slot| slot.generation == handle.generation)
+	}
+
+	fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+		self.slots
+			.get_mut(handle.index as usize)
+			.filter(|slot| slot.generation == handle.generation)
+	}
+
+	fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+		let slot = self.slot_mut(handle)?;
+		let value = slot.value.take()?;
+		// Invalidates the existing handles to the slot.
+		slot.generation = slot.generation.wrapping_add(1);
+		self.free.push(handle.index);
+		Some(value)
+	}
+}
+
+/// Owns resources of any type, such as [`Tex2d`]s and [`GpuMesh`]es, handing
+/// out [`Handle`]s to them.
+///
+/// [`Tex2d`]: crate::tex2d::Tex2d
+/// [`GpuMesh`]: crate::mesh::GpuMesh
+#[derive(Default)]
+pub struct ResourceManager {
+	/// A `Pool<T>` per type `T`.
+	pools: HashMap<TypeId, Box<dyn Any>>,
+}
+impl ResourceManager {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn insert<T: 'static>(&mut self, value: T) -> Handle<T> {
+		self.pools
+			.entry(TypeId::of::<T>())
+			.or_insert_with(|| Box::new(Pool::<T>::new()))
+			.downcast_mut::<Pool<T>>()
+			.expect("Pools are stored by their type")
+			.insert(value)
+	}
+
+	/// The resource `handle` refers to, or `None` if it was removed.
+	pub fn get<T: 'static>(&self, handle: Handle<T>) -> Option<&T> {
+		self.pool::<T>()?.slot(handle)?.value.as_ref()
+	}
+
+	pub fn get_mut<T: 'static>(&mut self, handle: Handle<T>) -> Option<&mut T> {
+		self.pool_mut::<T>()?.slot_mut(handle)?.value.as_mut()
+	}
+
+	/// Takes the resource `handle` refers to out of the manager, invalidating the
+	/// handle. Returns `None` if it was already removed.
+	pub fn remove<T: 'static>(&mut self, handle: Handle<T>) -> Option<T> {
+		self.pool_mut::<T>()?.remove(handle)
+	}
+
+	fn pool<T: 'static>(&self) -> Option<&Pool<T>> {
+		self.pools.get(&TypeId::of::<T>())?.downcast_ref()
+	}
+
+	fn pool_mut<T: 'static>(&mut self) -> Option<&mut Pool<T>> {
+		self.pools.get_mut(&TypeId::of::<T>())?.downcast_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn stale_handles_are_rejected() {
+		let mut resources = ResourceManager::new();
+		let first = resources.insert("first");
+		assert_eq!(resources.remove(first), Some("first"));
+		// Reuses the slot of `first`.
+		let second = resources.insert("second");
+		assert_eq!(resources.get(first), None);
+		assert_eq!(resources.get_mut(first), None);
+		assert_eq!(resources.remove(first), None);
+		assert_eq!(resources.get(second), Some(&"second"));
+	}
+
+	#[test]
+	fn types_have_separate_pools() {
+		let mut resources = ResourceManager::new();
+		let number = resources.insert(1_u32);
+		let text = resources.insert("one");
+		assert_eq!(resources.get(number), Some(&1));
+		assert_eq!(resources.get(text), Some(&"one"));
+	}
+}
@@ -0,0 +1,216 @@
+//! Scenes saved to files, with the assets of their objects referred to by path.
+
+use color_eyre::{eyre::WrapErr, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::camera::Camera;
+use crate::fog::FogUniform;
+use crate::light::LightUniform;
+
+/// Everything [`RenderState::save_scene`] saves: the camera, the objects added
+/// from files and the lights.
+///
+/// [`RenderState::save_scene`]: crate::render_state::RenderState::save_scene
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneFile {
+	pub camera: Camera,
+	/// In the order the objects were added.
+	pub objects: Vec<SceneObject>,
+	pub lights: Vec<Light>,
+	/// `None` without fog.
+	pub fog: Option<FogUniform>,
+}
+impl SceneFile {
+	/// Parses a scene saved with [`Self::to_bytes`] for a file at `path`.
+	pub fn from_bytes(bytes: &[u8], path: &Path) -> Result<Self> {
+		match SceneFormat::from_path(path)? {
+			SceneFormat::Json => {
+				serde_json::from_slice(bytes).wrap_err("Failed to parse scene JSON")
+			}
+			#[cfg(feature = "msgpack")]
+			SceneFormat::MsgPack => rmp_serde::from_slice(bytes)
+				.wrap_err("Failed to parse scene MessagePack"),
+		}
+	}
+
+	/// Serializes the scene in the format of `path`'s extension: MessagePack for
+	/// `.msgpack`, which needs the `msgpack` feature, and JSON otherwise.
+	pub fn to_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+		match SceneFormat::from_path(path)? {
+			SceneFormat::Json => {
+				serde_json::to_vec_pretty(self).wrap_err("Failed to serialize scene")
+			}
+			#[cfg(feature = "msgpack")]
+			SceneFormat::MsgPack => {
+				rmp_serde::to_vec_named(self).wrap_err("Failed to serialize scene")
+			}
+		}
+	}
+}
+
+/// A [`RenderObject`] with a mesh from an OBJ file and a material from an image.
+/// Relative paths are relative to the scene file's directory, and saved scenes
+/// refer to their assets relative to it where possible.
+///
+/// [`RenderObject`]: crate::render_object::RenderObject
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneObject {
+	pub name: String,
+	/// An OBJ file.
+	pub mesh_path: String,
+	/// The diffuse texture of the object's material.
+	pub material_path: String,
+	/// The model matrix, column by column.
+	pub transform: [[f32; 4]; 4],
+}
+impl SceneObject {
+	/// The object with its relative paths resolved against `dir`.
+	pub fn resolve_paths(&self, dir: &Path) -> Self {
+		let resolve = |path: &str| dir.join(path).to_string_lossy().into_owned();
+		Self {
+			mesh_path: resolve(&self.mesh_path),
+			material_path: resolve(&self.material_path),
+			..self.clone()
+		}
+	}
+
+	/// The object with its absolute paths made relative to the absolute `dir`.
+	/// Paths with nothing in common with it, such as on other drives, are kept.
+	pub fn relative_paths(&self, dir: &Path) -> Self {
+		let relative = |path: &str| {
+			relative_path(Path::new(path), dir)
+				.to_string_lossy()
+				.into_owned()
+		};
+		Self {
+			mesh_path: relative(&self.mesh_path),
+			material_path: relative(&self.material_path),
+			..self.clone()
+		}
+	}
+}
+
+/// The path to `path` from `dir`, both absolute, going up with `..` where
+/// needed.
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+	if !path.is_absolute() || !dir.is_absolute() {
+		return path.to_owned();
+	}
+	let mut path_components = path.components().peekable();
+	let mut dir_components = dir.components().peekable();
+	let mut shared = 0;
+	while let (Some(a), Some(b)) = (path_components.peek(), dir_components.peek()) {
+		if a != b {
+			break;
+		}
+		path_components.next();
+		dir_components.next();
+		shared += 1;
+	}
+	// Paths on other drives share nothing on Windows.
+	if shared == 0 {
+		return path.to_owned();
+	}
+	dir_components
+		.map(|_| Component::ParentDir)
+		.chain(path_components)
+		.collect()
+}
+
+/// The absolute directory of the scene file at `path`, which needn't exist yet.
+pub(crate) fn scene_dir(path: &Path) -> Result<PathBuf> {
+	let dir = match path.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+	std::fs::canonicalize(dir).wrap_err_with(|| {
+		format!("Failed to find the directory of scene {}", path.display())
+	})
+}
+
+/// A light of a [`SceneFile`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Light {
+	/// The scene's directional light. There is at most one.
+	Directional(LightUniform),
+	/// A point light casting shadows, see [`RenderState::add_point_light`].
+	///
+	/// [`RenderState::add_point_light`]: crate::render_state::RenderState::add_point_light
+	Point {
+		position: [f32; 3],
+		range: f32,
+		color: [f32; 3],
+	},
+}
+
+enum SceneFormat {
+	Json,
+	#[cfg(feature = "msgpack")]
+	MsgPack,
+}
+impl SceneFormat {
+	fn from_path(path: &Path) -> Result<Self> {
+		match path.extension().and_then(|extension| extension.to_str()) {
+			#[cfg(feature = "msgpack")]
+			Some("msgpack") => Ok(Self::MsgPack),
+			#[cfg(not(feature = "msgpack"))]
+			Some("msgpack") => color_eyre::eyre::bail!(
+				"Saving scenes as MessagePack needs the msgpack feature"
+			),
+			_ => Ok(Self::Json),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::{point, Matrix4, Perspective3, Vector3};
+
+	fn object(
+		mesh_path: &str,
+		material_path: &str,
+		transform: Matrix4<f32>,
+	) -> SceneObject {
+		SceneObject {
+			name: "crate".to_owned(),
+			mesh_path: mesh_path.to_owned(),
+			material_path: material_path.to_owned(),
+			transform: transform.into(),
+		}
+	}
+
+	#[test]
+	fn objects_round_trip() {
+		let transform = Matrix4::new_translation(&Vector3::new(1., 2., 3.))
+			* Matrix4::new_nonuniform_scaling(&Vector3::new(2., 1., 0.5));
+		let proj = Perspective3::new(1., 1., 0.1, 100.);
+		let scene = SceneFile {
+			camera: Camera::new(point![0., 0., 1.], 0., 0., proj),
+			objects: vec![object("crate.obj", "crate.png", transform)],
+			lights: Vec::new(),
+			fog: None,
+		};
+		let path = Path::new("scene.json");
+		let loaded =
+			SceneFile::from_bytes(&scene.to_bytes(path).unwrap(), path).unwrap();
+		assert_eq!(loaded.objects, scene.objects);
+		assert_eq!(Matrix4::from(loaded.objects[0].transform), transform);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn paths_are_saved_relative_to_the_scene() {
+		let object = object(
+			"/assets/meshes/crate.obj",
+			"/textures/crate.png",
+			Matrix4::identity(),
+		);
+		let saved = object.relative_paths(Path::new("/assets/scenes"));
+		assert_eq!(saved.mesh_path, "../meshes/crate.obj");
+		assert_eq!(saved.material_path, "../../textures/crate.png");
+		let loaded = saved.resolve_paths(Path::new("/assets/scenes"));
+		assert_eq!(loaded.mesh_path, "/assets/scenes/../meshes/crate.obj");
+	}
+}
@@ -0,0 +1,22 @@
+//! Saving of frames captured with [`RenderState::capture_screenshot`].
+//!
+//! [`RenderState::capture_screenshot`]: crate::render_state::RenderState::capture_screenshot
+
+use color_eyre::{eyre::WrapErr, Result};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes `img` to `path` as a PNG, regardless of the extension of `path`.
+pub fn save_screenshot(img: image::RgbaImage, path: &Path) -> Result<()> {
+	img.save_with_format(path, image::ImageFormat::Png)
+		.wrap_err_with(|| format!("Failed to save screenshot to {}", path.display()))
+}
+
+/// A unique file name in the working directory, based on the current time.
+pub fn default_path() -> PathBuf {
+	let millis = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_millis())
+		.unwrap_or_default();
+	PathBuf::from(format!("screenshot-{millis}.png"))
+}
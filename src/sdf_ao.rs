@@ -0,0 +1,588 @@
+//! Ambient occlusion traced through a distance field of the scene, which unlike
+//! SSAO sees occluders that are off screen or far away.
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::{eyre::bail, Result};
+use nalgebra::{IsometryMatrix3, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::aabb::Aabb;
+use crate::camera::{reverse_z, CameraLike};
+use crate::render_object::RenderObject;
+use crate::types::mat4_to_wgsl;
+use crate::vertex::Vertex;
+
+/// How strong the ambient occlusion is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SdfAoSettings {
+	/// How far away surfaces occlude each other, in world units.
+	pub max_distance: f32,
+	/// Scales the occlusion, where 1 fully darkens surfaces hidden on all sides.
+	pub intensity: f32,
+}
+impl Default for SdfAoSettings {
+	fn default() -> Self {
+		Self {
+			max_distance: 1.0,
+			intensity: 1.0,
+		}
+	}
+}
+
+/// The layout of `sdf_bake.wgsl`'s `VolumeUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct VolumeUniform {
+	min: [f32; 3],
+	resolution: u32,
+	voxel_size: [f32; 3],
+	step: u32,
+}
+
+/// The layout of `sdf_bake.wgsl`'s `MeshUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct MeshUniform {
+	model: [[f32; 4]; 4],
+	stride: u32,
+	triangle_count: u32,
+	_pad: [u32; 2],
+}
+
+/// The layout of `sdf_ao.wgsl`'s `SdfAoUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SdfAoUniform {
+	inv_view_proj: [[f32; 4]; 4],
+	volume_min: [f32; 3],
+	far_depth: f32,
+	volume_size: [f32; 3],
+	max_distance: f32,
+	intensity: f32,
+	bias: f32,
+	_pad: [f32; 2],
+}
+
+/// Estimates how occluded each pixel is by tracing cones around its normal
+/// through a volume holding the distance to the nearest surface, from the depth
+/// and normals of the opaque geometry in a [`GBuffer`](crate::gbuffer::GBuffer).
+///
+/// The volume covers the objects it is baked from, in
+/// [`Self::RESOLUTION`]³ voxels, and must be baked again when they move.
+/// Surfaces have no inside, so the distances are unsigned.
+pub struct SdfAoPass {
+	settings: SdfAoSettings,
+	/// The camera's matrix, for `uniform_buf`.
+	inv_view_proj: [[f32; 4]; 4],
+	far_depth: f32,
+	reverse_z: bool,
+	volume: Aabb,
+	uniform_buf: wgpu::Buffer,
+	sdf_view: wgpu::TextureView,
+	layout: wgpu::BindGroupLayout,
+	pipeline: wgpu::RenderPipeline,
+	/// The occlusion, sampled by the scene.
+	occlusion_view: wgpu::TextureView,
+}
+impl SdfAoPass {
+	/// Voxels along each axis of the volume.
+	pub const RESOLUTION: u32 = 64;
+	const SDF_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+	const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+	const WORKGROUP_SIZE: u32 = 64;
+	const VOLUME_WORKGROUP_SIZE: u32 = 4;
+
+	/// Bakes the distance field of the triangle lists of `scene` with compute
+	/// shaders, and creates a pass for `width` x `height` frames with a depth
+	/// buffer that is optionally reversed. The baking is submitted to `queue`.
+	///
+	/// Fails if the device doesn't support compute shaders.
+	pub fn bake(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		scene: &[RenderObject],
+		width: u32,
+		height: u32,
+		reverse_z: bool,
+	) -> Result<Self> {
+		if device.limits().max_compute_workgroups_per_dimension == 0 {
+			bail!("Compute shaders are not supported by this device");
+		}
+		let scene: Vec<&RenderObject> = scene
+			.iter()
+			.filter(|object| {
+				object.mesh.topology == wgpu::PrimitiveTopology::TriangleList
+					&& object.mesh.num_indices >= 3
+			})
+			.collect();
+		let volume = bounds(&scene);
+		let sdf_view = bake_volume(device, queue, &scene, &volume);
+
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("SDF AO Uniform"),
+			size: std::mem::size_of::<SdfAoUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension,
+				// The textures are read with `textureLoad`, so they need no samplers.
+				sample_type: wgpu::TextureSampleType::Float { filterable: false },
+			},
+			count: None,
+		};
+		let layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("SDF AO Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					texture_entry(1, wgpu::TextureViewDimension::D2),
+					texture_entry(2, wgpu::TextureViewDimension::D2),
+					texture_entry(3, wgpu::TextureViewDimension::D3),
+				],
+			});
+		let pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("SDF AO Pipeline Layout"),
+				bind_group_layouts: &[&layout],
+				push_constant_ranges: &[],
+			});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("sdf_ao.wgsl"));
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("SDF AO Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[Some(Self::OCCLUSION_FORMAT.into())],
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview: None,
+		});
+
+		let mut result = Self {
+			settings: SdfAoSettings::default(),
+			inv_view_proj: mat4_to_wgsl(Matrix4::identity()),
+			far_depth: if reverse_z { 0. } else { 1. },
+			reverse_z,
+			volume,
+			uniform_buf,
+			sdf_view,
+			layout,
+			pipeline,
+			occlusion_view: create_occlusion(device, width, height),
+		};
+		result.upload(queue);
+		Ok(result)
+	}
+
+	pub fn settings(&self) -> SdfAoSettings {
+		self.settings
+	}
+
+	pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: SdfAoSettings) {
+		self.settings = settings;
+		self.upload(queue);
+	}
+
+	/// The box the distance field covers, in world space.
+	pub fn volume(&self) -> Aabb {
+		self.volume
+	}
+
+	/// Uploads the matrices of `camera` seen from `view`. Returns the number of
+	/// bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut view_proj = camera.proj_view_from(view);
+		if self.reverse_z {
+			view_proj = reverse_z(&view_proj);
+		}
+		self.inv_view_proj = mat4_to_wgsl(view_proj.try_inverse().unwrap_or_default());
+		self.upload(queue)
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		let size = self.volume.max - self.volume.min;
+		let voxel_size = size / Self::RESOLUTION as f32;
+		let uniform = SdfAoUniform {
+			inv_view_proj: self.inv_view_proj,
+			volume_min: self.volume.min.into(),
+			far_depth: self.far_depth,
+			volume_size: size.into(),
+			max_distance: self.settings.max_distance,
+			intensity: self.settings.intensity,
+			// Past the voxel the surface is in.
+			bias: voxel_size.norm(),
+			_pad: [0.; 2],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SdfAoUniform>() as u64
+	}
+
+	/// Recreates the occlusion for `width` x `height` frames. Bind groups
+	/// sampling [`Self::occlusion_view`] must be recreated too.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		self.occlusion_view = create_occlusion(device, width, height);
+	}
+
+	/// The occlusion, 1 where nothing is occluded.
+	pub fn occlusion_view(&self) -> &wgpu::TextureView {
+		&self.occlusion_view
+	}
+
+	/// Records estimating the occlusion of the world positions reconstructed from
+	/// `depth_view`, around the normals of `normal_view`, into all of
+	/// `output_view`, which must be an `R8Unorm` texture.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		normal_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("sdf_ao_bind_group"),
+			layout: &self.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(normal_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(&self.sdf_view),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("SDF AO Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &bind_group, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+/// The box around `scene` in world space, grown by a few voxels so that the
+/// distances just outside it are known too. A unit box without objects.
+fn bounds(scene: &[&RenderObject]) -> Aabb {
+	let Some(aabb) = scene
+		.iter()
+		.map(|object| object.mesh.aabb.transformed(&object.transform))
+		.reduce(|a, b| {
+			Aabb::new(
+				a.min.coords.inf(&b.min.coords).into(),
+				a.max.coords.sup(&b.max.coords).into(),
+			)
+		})
+	else {
+		return Aabb::new(Point3::origin(), Point3::new(1., 1., 1.));
+	};
+	let size = (aabb.max - aabb.min).max().max(f32::EPSILON);
+	let margin = Vector3::repeat(size * 4. / SdfAoPass::RESOLUTION as f32);
+	Aabb::new(aabb.min - margin, aabb.max + margin)
+}
+
+/// Records and submits baking the distance field of `scene` over `volume`:
+/// seeding the voxels with points of the triangles, flooding each voxel's
+/// nearest point to the others and writing the distances to them.
+fn bake_volume(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	scene: &[&RenderObject],
+	volume: &Aabb,
+) -> wgpu::TextureView {
+	let resolution = SdfAoPass::RESOLUTION;
+	let voxel_size = (volume.max - volume.min) / resolution as f32;
+	let voxels = (resolution * resolution * resolution) as u64;
+	let sdf = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("SDF AO Volume"),
+		size: wgpu::Extent3d {
+			width: resolution,
+			height: resolution,
+			depth_or_array_layers: resolution,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D3,
+		format: SdfAoPass::SDF_FORMAT,
+		usage: wgpu::TextureUsages::STORAGE_BINDING
+			| wgpu::TextureUsages::TEXTURE_BINDING,
+		view_formats: &[],
+	});
+	let sdf_view = sdf.create_view(&Default::default());
+	// Zeroed, so that no voxel has a point yet.
+	let seeds = ["SDF Seeds A", "SDF Seeds B"].map(|label| {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some(label),
+			size: voxels * std::mem::size_of::<[f32; 4]>() as u64,
+			usage: wgpu::BufferUsages::STORAGE,
+			mapped_at_creation: false,
+		})
+	});
+
+	let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility: wgpu::ShaderStages::COMPUTE,
+		ty: wgpu::BindingType::Buffer {
+			ty,
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		},
+		count: None,
+	};
+	let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+	let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+	let volume_layout =
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("SDF Volume Layout"),
+			entries: &[
+				buffer_entry(0, wgpu::BufferBindingType::Uniform),
+				buffer_entry(1, read_write),
+				buffer_entry(2, read_only),
+				wgpu::BindGroupLayoutEntry {
+					binding: 3,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::StorageTexture {
+						access: wgpu::StorageTextureAccess::WriteOnly,
+						format: SdfAoPass::SDF_FORMAT,
+						view_dimension: wgpu::TextureViewDimension::D3,
+					},
+					count: None,
+				},
+			],
+		});
+	let mesh_layout =
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("SDF Mesh Layout"),
+			entries: &[
+				buffer_entry(0, wgpu::BufferBindingType::Uniform),
+				buffer_entry(1, read_only),
+				buffer_entry(2, read_only),
+			],
+		});
+	let shader = device.create_shader_module(wgpu::include_wgsl!("sdf_bake.wgsl"));
+	let pipeline = |entry_point, bind_group_layouts: &[_]| {
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("SDF Bake Pipeline Layout"),
+			bind_group_layouts,
+			push_constant_ranges: &[],
+		});
+		device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+			label: Some("SDF Bake Pipeline"),
+			layout: Some(&layout),
+			module: &shader,
+			entry_point,
+		})
+	};
+	let seed_pipeline = pipeline("cs_seed", &[&volume_layout, &mesh_layout]);
+	let flood_pipeline = pipeline("cs_flood", &[&volume_layout]);
+	let distance_pipeline = pipeline("cs_distance", &[&volume_layout]);
+
+	// Writes into `seeds[output]` and reads the other one, `step` voxels apart.
+	let volume_bind_group = |step: u32, output: usize| {
+		let uniform = VolumeUniform {
+			min: volume.min.into(),
+			resolution,
+			voxel_size: voxel_size.into(),
+			step,
+		};
+		let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("SDF Volume Uniform"),
+			contents: bytemuck::bytes_of(&uniform),
+			usage: wgpu::BufferUsages::UNIFORM,
+		});
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("sdf_volume_bind_group"),
+			layout: &volume_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: seeds[output].as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: seeds[1 - output].as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(&sdf_view),
+				},
+			],
+		})
+	};
+
+	let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+		label: Some("SDF Bake Encoder"),
+	});
+	// The meshes' buffers can't be bound as storage, so they are copied.
+	let meshes: Vec<(u32, wgpu::BindGroup)> = scene
+		.iter()
+		.map(|object| {
+			let storage_copy = |source: &wgpu::Buffer, label| {
+				let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+					label: Some(label),
+					size: source.size(),
+					usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+					mapped_at_creation: false,
+				});
+				encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+				buffer
+			};
+			let vertices = storage_copy(&object.mesh.vtx_buf, "SDF Vertices");
+			let indices = storage_copy(&object.mesh.idx_buf, "SDF Indices");
+			let triangle_count = object.mesh.num_indices / 3;
+			let uniform = MeshUniform {
+				model: mat4_to_wgsl(object.transform),
+				stride: (std::mem::size_of::<Vertex>() / std::mem::size_of::<f32>())
+					as u32,
+				triangle_count,
+				_pad: [0; 2],
+			};
+			let uniform_buf =
+				device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("SDF Mesh Uniform"),
+					contents: bytemuck::bytes_of(&uniform),
+					usage: wgpu::BufferUsages::UNIFORM,
+				});
+			let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("sdf_mesh_bind_group"),
+				layout: &mesh_layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: uniform_buf.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: vertices.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: indices.as_entire_binding(),
+					},
+				],
+			});
+			(triangle_count, bind_group)
+		})
+		.collect();
+
+	let volume_workgroups = resolution / SdfAoPass::VOLUME_WORKGROUP_SIZE;
+	let seed_bind_group = volume_bind_group(0, 0);
+	// Halving down to 1, and 1 again, which fixes most of the voxels the larger
+	// steps got wrong.
+	let steps: Vec<u32> = std::iter::successors(Some(resolution / 2), |step| {
+		(*step > 1).then_some(step / 2)
+	})
+	.chain([1])
+	.collect();
+	let flood_bind_groups: Vec<_> = steps
+		.iter()
+		.enumerate()
+		.map(|(i, &step)| volume_bind_group(step, (i + 1) % 2))
+		.collect();
+	// Reads the seeds the last step wrote.
+	let distance_bind_group = volume_bind_group(0, (steps.len() + 1) % 2);
+	{
+		let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("SDF Bake Pass"),
+		});
+		pass.set_pipeline(&seed_pipeline);
+		pass.set_bind_group(0, &seed_bind_group, &[]);
+		for (triangle_count, bind_group) in &meshes {
+			pass.set_bind_group(1, bind_group, &[]);
+			let workgroups = (triangle_count - 1) / SdfAoPass::WORKGROUP_SIZE + 1;
+			pass.dispatch_workgroups(workgroups, 1, 1);
+		}
+		pass.set_pipeline(&flood_pipeline);
+		for bind_group in &flood_bind_groups {
+			pass.set_bind_group(0, bind_group, &[]);
+			pass.dispatch_workgroups(
+				volume_workgroups,
+				volume_workgroups,
+				volume_workgroups,
+			);
+		}
+		pass.set_pipeline(&distance_pipeline);
+		pass.set_bind_group(0, &distance_bind_group, &[]);
+		pass.dispatch_workgroups(
+			volume_workgroups,
+			volume_workgroups,
+			volume_workgroups,
+		);
+	}
+	queue.submit([encoder.finish()]);
+	sdf_view
+}
+
+fn create_occlusion(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+) -> wgpu::TextureView {
+	device
+		.create_texture(&wgpu::TextureDescriptor {
+			label: Some("SDF AO Occlusion"),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: SdfAoPass::OCCLUSION_FORMAT,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		})
+		.create_view(&wgpu::TextureViewDescriptor::default())
+}
@@ -0,0 +1,116 @@
+// Ambient occlusion from a distance field of the scene: pixels are darkened by
+// how soon cones around their normal run into surfaces.
+
+// Matches `SdfAoUniform` in `sdf_ao.rs`.
+struct SdfAoUniform {
+	inv_view_proj: mat4x4<f32>,
+	// The corner of the volume, in world space.
+	volume_min: vec3<f32>,
+	// The depth the buffer is cleared to, where nothing was drawn.
+	far_depth: f32,
+	volume_size: vec3<f32>,
+	// How far the cones are followed, in world units.
+	max_distance: f32,
+	intensity: f32,
+	// How far from the surface the cones start, so that they don't hit it.
+	bias: f32,
+};
+@group(0) @binding(0)
+var<uniform> ao: SdfAoUniform;
+// The depth, in the red channel.
+@group(0) @binding(1)
+var depth_t: texture_2d<f32>;
+// World space normals, mapped to 0 to 1.
+@group(0) @binding(2)
+var normal_t: texture_2d<f32>;
+// The distance to the nearest surface at each voxel's center.
+@group(0) @binding(3)
+var sdf_t: texture_3d<f32>;
+
+const CONES: u32 = 8u;
+const STEPS: u32 = 6u;
+// The tangent of the half angle of each cone, wide enough for the cones to
+// cover the hemisphere together.
+const CONE_RATIO: f32 = 0.5;
+// How far the cones around the middle one lean from the normal, in radians.
+const CONE_TILT: f32 = 0.9;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+// The distance to the nearest surface at `pos`, interpolated between voxels,
+// as the format can't be filtered. Far outside the volume.
+fn sdf(pos: vec3<f32>) -> f32 {
+	let size = vec3<i32>(textureDimensions(sdf_t));
+	let coords = (pos - ao.volume_min) / ao.volume_size * vec3<f32>(size) - 0.5;
+	if any(coords < vec3<f32>(-0.5)) || any(coords > vec3<f32>(size) - 0.5) {
+		return 1e30;
+	}
+	let base = vec3<i32>(floor(coords));
+	let t = coords - floor(coords);
+	var result = 0.0;
+	for (var i = 0; i < 8; i += 1) {
+		let corner = vec3<i32>(i & 1, (i >> 1u) & 1, (i >> 2u) & 1);
+		let texel = clamp(base + corner, vec3<i32>(0), size - 1);
+		let weights = select(1.0 - t, t, corner == vec3<i32>(1));
+		result += textureLoad(sdf_t, texel, 0).r * weights.x * weights.y * weights.z;
+	}
+	return result;
+}
+
+// The share of the cone from `pos` along `direction` that reaches
+// `ao.max_distance` without running into a surface. Occluders further away
+// matter less.
+fn cone_visibility(pos: vec3<f32>, direction: vec3<f32>) -> f32 {
+	var visibility = 1.0;
+	for (var i = 1u; i <= STEPS; i += 1u) {
+		let t = ao.max_distance * f32(i) / f32(STEPS);
+		let d = sdf(pos + direction * t);
+		let covered = clamp(d / (t * CONE_RATIO), 0.0, 1.0);
+		visibility = min(visibility, mix(covered, 1.0, t / ao.max_distance * 0.5));
+	}
+	return visibility;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.clip_pos.xy);
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth == ao.far_depth {
+		return vec4<f32>(1.0);
+	}
+	let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(textureDimensions(depth_t, 0));
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = ao.inv_view_proj * ndc;
+	let n = normalize(textureLoad(normal_t, coords, 0).xyz * 2.0 - 1.0);
+	let pos = world.xyz / world.w + n * ao.bias;
+
+	// Any direction perpendicular to the normal.
+	let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.y) > 0.9);
+	let tangent = normalize(cross(up, n));
+	let tbn = mat3x3<f32>(tangent, cross(n, tangent), n);
+	// One cone along the normal and the others around it.
+	var visibility = cone_visibility(pos, n);
+	for (var i = 1u; i < CONES; i += 1u) {
+		let angle = f32(i) / f32(CONES - 1u) * 6.28318530;
+		let direction = vec3<f32>(
+			sin(CONE_TILT) * cos(angle),
+			sin(CONE_TILT) * sin(angle),
+			cos(CONE_TILT),
+		);
+		visibility += cone_visibility(pos, tbn * direction);
+	}
+	visibility /= f32(CONES);
+	return vec4<f32>(clamp(1.0 - (1.0 - visibility) * ao.intensity, 0.0, 1.0));
+}
@@ -0,0 +1,124 @@
+// Bakes the distance to the nearest surface at each voxel of a volume, for
+// `sdf_ao.rs`.
+//
+// `cs_seed` stores points of the meshes' triangles in the voxels they fall in,
+// `cs_flood` spreads each voxel's nearest point to its neighbors with the jump
+// flooding algorithm, and `cs_distance` writes the distance to it.
+
+// Matches `VolumeUniform` in `sdf_ao.rs`.
+struct VolumeUniform {
+	// The corner of the volume, in world space.
+	min: vec3<f32>,
+	// Voxels along each axis.
+	resolution: u32,
+	voxel_size: vec3<f32>,
+	// How far apart the voxels `cs_flood` compares are, in voxels.
+	step: u32,
+};
+@group(0) @binding(0)
+var<uniform> volume: VolumeUniform;
+// The nearest surface point of each voxel, with a w of 1 if there is one.
+@group(0) @binding(1)
+var<storage, read_write> seeds_out: array<vec4<f32>>;
+@group(0) @binding(2)
+var<storage, read> seeds_in: array<vec4<f32>>;
+@group(0) @binding(3)
+var sdf_t: texture_storage_3d<r32float, write>;
+
+// Matches `MeshUniform` in `sdf_ao.rs`.
+struct MeshUniform {
+	model: mat4x4<f32>,
+	// Floats per vertex. The position is the first 3.
+	stride: u32,
+	triangle_count: u32,
+};
+@group(1) @binding(0)
+var<uniform> mesh: MeshUniform;
+@group(1) @binding(1)
+var<storage, read> vertices: array<f32>;
+@group(1) @binding(2)
+var<storage, read> indices: array<u32>;
+
+// The most points along a triangle's edges, bounding the work per triangle.
+const MAX_SUBDIVISIONS: u32 = 64u;
+
+fn voxel_index(voxel: vec3<u32>) -> u32 {
+	return (voxel.z * volume.resolution + voxel.y) * volume.resolution + voxel.x;
+}
+
+fn voxel_center(voxel: vec3<u32>) -> vec3<f32> {
+	return volume.min + (vec3<f32>(voxel) + 0.5) * volume.voxel_size;
+}
+
+fn vertex_pos(index: u32) -> vec3<f32> {
+	let base = indices[index] * mesh.stride;
+	let pos = vec3<f32>(vertices[base], vertices[base + 1u], vertices[base + 2u]);
+	let world = mesh.model * vec4<f32>(pos, 1.0);
+	return world.xyz / world.w;
+}
+
+// Samples each triangle on a grid finer than the voxels, so that every voxel it
+// crosses gets a point.
+@compute @workgroup_size(64)
+fn cs_seed(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= mesh.triangle_count {
+		return;
+	}
+	let a = vertex_pos(id.x * 3u);
+	let b = vertex_pos(id.x * 3u + 1u);
+	let c = vertex_pos(id.x * 3u + 2u);
+	let longest = max(length(b - a), max(length(c - a), length(c - b)));
+	let spacing = min(volume.voxel_size.x, min(volume.voxel_size.y, volume.voxel_size.z)) * 0.5;
+	let n = clamp(u32(ceil(longest / spacing)), 1u, MAX_SUBDIVISIONS);
+	for (var i = 0u; i <= n; i += 1u) {
+		for (var j = 0u; j <= n - i; j += 1u) {
+			let p = a + (b - a) * (f32(i) / f32(n)) + (c - a) * (f32(j) / f32(n));
+			let voxel = floor((p - volume.min) / volume.voxel_size);
+			if all(voxel >= vec3<f32>(0.0)) && all(voxel < vec3<f32>(f32(volume.resolution))) {
+				// Any point in the voxel will do, so racing writes are fine.
+				seeds_out[voxel_index(vec3<u32>(voxel))] = vec4<f32>(p, 1.0);
+			}
+		}
+	}
+}
+
+// Keeps the nearest of the points of the voxel and of those `volume.step`
+// voxels away along each axis and diagonal.
+@compute @workgroup_size(4, 4, 4)
+fn cs_flood(@builtin(global_invocation_id) id: vec3<u32>) {
+	if any(id >= vec3<u32>(volume.resolution)) {
+		return;
+	}
+	let center = voxel_center(id);
+	var best = seeds_in[voxel_index(id)];
+	var best_distance = select(1e30, distance(best.xyz, center), best.w > 0.0);
+	for (var z = -1; z <= 1; z += 1) {
+		for (var y = -1; y <= 1; y += 1) {
+			for (var x = -1; x <= 1; x += 1) {
+				let neighbor = vec3<i32>(id) + vec3<i32>(x, y, z) * i32(volume.step);
+				if any(neighbor < vec3<i32>(0)) || any(neighbor >= vec3<i32>(i32(volume.resolution))) {
+					continue;
+				}
+				let seed = seeds_in[voxel_index(vec3<u32>(neighbor))];
+				if seed.w > 0.0 && distance(seed.xyz, center) < best_distance {
+					best = seed;
+					best_distance = distance(seed.xyz, center);
+				}
+			}
+		}
+	}
+	seeds_out[voxel_index(id)] = best;
+}
+
+// Writes the distance from each voxel's center to its nearest point, or a
+// distance past the volume if there are no surfaces at all.
+@compute @workgroup_size(4, 4, 4)
+fn cs_distance(@builtin(global_invocation_id) id: vec3<u32>) {
+	if any(id >= vec3<u32>(volume.resolution)) {
+		return;
+	}
+	let seed = seeds_in[voxel_index(id)];
+	let far = length(volume.voxel_size * f32(volume.resolution));
+	let d = select(far, distance(seed.xyz, voxel_center(id)), seed.w > 0.0);
+	textureStore(sdf_t, vec3<i32>(id), vec4<f32>(d, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,217 @@
+//! Fonts rendered from a signed distance field, which stay sharp at any size.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use color_eyre::{
+	eyre::{bail, ensure, WrapErr},
+	Result,
+};
+
+use crate::atlas::UvRect;
+use crate::tex2d::{SamplerConfig, Shape, Tex2d};
+
+/// Where a glyph is in the atlas and how it is placed, in ems.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GlyphMetrics {
+	/// The glyph's quad, including the margin the distance field fades out in.
+	pub uv_rect: UvRect,
+	/// How far the pen moves after the glyph.
+	pub advance: f32,
+	/// From the pen position on the baseline to the top left corner of the quad,
+	/// with y pointing down.
+	pub bearing: [f32; 2],
+	/// The width and height of the quad.
+	pub size: [f32; 2],
+}
+
+/// The metrics of every glyph of an [`SdfFont`], in ems.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SdfMetrics {
+	/// From the top of a line to its baseline.
+	pub ascent: f32,
+	/// From one baseline to the next.
+	pub line_height: f32,
+	pub glyphs: HashMap<char, GlyphMetrics>,
+}
+impl SdfMetrics {
+	/// Starts the binary metrics format.
+	const MAGIC: &'static [u8; 4] = b"SDFM";
+	const VERSION: u32 = 1;
+	/// The code point, then the 10 floats of its [`GlyphMetrics`].
+	const GLYPH_SIZE: usize = 4 + 10 * 4;
+
+	/// Parses the binary metrics format, in which every number is 4 bytes little
+	/// endian: the magic `SDFM`, the version (1), the ascent and line height as
+	/// floats, the number of glyphs, and then for each glyph its code point
+	/// followed by its UV rectangle (`u_min`, `v_min`, `u_max`, `v_max`),
+	/// advance, bearing and size as floats.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+		ensure!(
+			bytes.len() >= 20 && &bytes[..4] == Self::MAGIC,
+			"Not an SDF font metrics file"
+		);
+		let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+		let f32_at = |i: usize| f32::from_bits(u32_at(i));
+		let version = u32_at(4);
+		ensure!(
+			version == Self::VERSION,
+			"Unsupported SDF font metrics version {version}"
+		);
+		let num_glyphs = u32_at(16) as usize;
+		let glyphs_start = 20;
+		ensure!(
+			bytes.len() == glyphs_start + num_glyphs * Self::GLYPH_SIZE,
+			"SDF font metrics are {} bytes, expected {} for {num_glyphs} glyphs",
+			bytes.len(),
+			glyphs_start + num_glyphs * Self::GLYPH_SIZE,
+		);
+		let mut glyphs = HashMap::with_capacity(num_glyphs);
+		for i in 0..num_glyphs {
+			let start = glyphs_start + i * Self::GLYPH_SIZE;
+			let Some(c) = char::from_u32(u32_at(start)) else {
+				bail!("Invalid code point {:#x} in SDF font metrics", u32_at(start));
+			};
+			let float = |n: usize| f32_at(start + 4 + n * 4);
+			let metrics = GlyphMetrics {
+				uv_rect: UvRect {
+					u_min: float(0),
+					v_min: float(1),
+					u_max: float(2),
+					v_max: float(3),
+				},
+				advance: float(4),
+				bearing: [float(5), float(6)],
+				size: [float(7), float(8)],
+			};
+			glyphs.insert(c, metrics);
+		}
+		Ok(Self {
+			ascent: f32_at(8),
+			line_height: f32_at(12),
+			glyphs,
+		})
+	}
+
+	/// Parses metrics serialized as JSON, with the glyphs keyed by their
+	/// character.
+	#[cfg(feature = "serde")]
+	pub fn from_json(json: &str) -> Result<Self> {
+		serde_json::from_str(json).wrap_err("Failed to parse SDF font metrics")
+	}
+}
+
+/// A glyph of laid out text, in pixels from the top left corner of its first
+/// line.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphQuad {
+	/// The top left corner.
+	pub min: [f32; 2],
+	/// The bottom right corner.
+	pub max: [f32; 2],
+	pub uv_rect: UvRect,
+}
+
+/// A font of pre-generated glyphs, such as from `msdfgen`, whose atlas holds the
+/// signed distance to the outline of the glyphs in its alpha channel, with the
+/// outline at 0.5 and the inside above it.
+pub struct SdfFont {
+	pub atlas: Tex2d,
+	pub metrics: SdfMetrics,
+}
+impl SdfFont {
+	/// Drawn for characters that aren't in the font, if it has it.
+	const REPLACEMENT_CHAR: char = '?';
+
+	/// Loads the atlas image and its metrics. Metrics ending in `.json` are
+	/// parsed as JSON, which needs the `serde` feature, and others as the binary
+	/// format of [`SdfMetrics::from_bytes`].
+	pub fn load_from_path(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		atlas_path: &Path,
+		metrics_path: &Path,
+	) -> Result<Self> {
+		let bytes = std::fs::read(metrics_path).wrap_err_with(|| {
+			format!("Failed to read SDF font metrics {}", metrics_path.display())
+		})?;
+		let metrics = if metrics_path.extension().map_or(false, |ext| ext == "json") {
+			#[cfg(feature = "serde")]
+			{
+				let json = String::from_utf8(bytes)
+					.wrap_err("SDF font metrics aren't UTF-8")?;
+				SdfMetrics::from_json(&json)?
+			}
+			#[cfg(not(feature = "serde"))]
+			bail!("Parsing JSON font metrics needs the serde feature")
+		} else {
+			SdfMetrics::from_bytes(&bytes)?
+		};
+		let img = image::open(atlas_path).wrap_err_with(|| {
+			format!("Failed to load SDF font atlas {}", atlas_path.display())
+		})?;
+		let shape = Shape {
+			width: img.width(),
+			height: img.height(),
+		};
+		let label = atlas_path.to_string_lossy();
+		let atlas = Tex2d::new_from_linear_rgba8(
+			device,
+			queue,
+			Some(&label),
+			&img.into_rgba8(),
+			shape,
+			// The distances are interpolated between texels.
+			SamplerConfig {
+				mag_filter: wgpu::FilterMode::Linear,
+				min_filter: wgpu::FilterMode::Linear,
+				..Default::default()
+			},
+		);
+		Ok(Self { atlas, metrics })
+	}
+
+	/// The metrics of `c`, or of `'?'` for characters that aren't in the font.
+	pub fn glyph(&self, c: char) -> Option<&GlyphMetrics> {
+		let glyphs = &self.metrics.glyphs;
+		glyphs
+			.get(&c)
+			.or_else(|| glyphs.get(&Self::REPLACEMENT_CHAR))
+	}
+
+	/// Lays out `text` in lines `scale` pixels per em, separated by `'\n'`.
+	/// Characters missing from the font, along with `'?'`, are skipped.
+	pub fn layout_text(&self, text: &str, scale: f32) -> Vec<GlyphQuad> {
+		let mut quads = Vec::with_capacity(text.len());
+		let mut pen_x = 0.;
+		let mut baseline = self.metrics.ascent * scale;
+		for c in text.chars() {
+			if c == '\n' {
+				pen_x = 0.;
+				baseline += self.metrics.line_height * scale;
+				continue;
+			}
+			let Some(glyph) = self.glyph(c) else {
+				continue;
+			};
+			if glyph.size != [0., 0.] {
+				let min = [
+					pen_x + glyph.bearing[0] * scale,
+					baseline + glyph.bearing[1] * scale,
+				];
+				quads.push(GlyphQuad {
+					min,
+					max: [
+						min[0] + glyph.size[0] * scale,
+						min[1] + glyph.size[1] * scale,
+					],
+					uv_rect: glyph.uv_rect,
+				});
+			}
+			pen_x += glyph.advance * scale;
+		}
+		quads
+	}
+}
@@ -0,0 +1,173 @@
+//! Expanding `// #include "name.wgsl"` directives in WGSL, so that shaders can
+//! share code, and building the main shader with them.
+
+use color_eyre::{eyre::bail, eyre::eyre, eyre::WrapErr, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use log::warn;
+use std::collections::HashMap;
+
+use crate::pipeline_cache::descriptor_hash;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::pipeline_cache::PipelineDiskCache;
+use crate::render_object::model_declaration;
+
+/// Replaces the lines of WGSL that are `// #include "name"` with the snippet
+/// registered as `name`, after expanding the snippet's own includes. Each
+/// snippet is included once per shader, where it is first included, as WGSL
+/// doesn't allow declaring things twice.
+#[derive(Clone, Debug)]
+pub struct ShaderPreprocessor<'a> {
+	snippets: HashMap<&'a str, &'a str>,
+}
+impl<'a> ShaderPreprocessor<'a> {
+	/// A preprocessor with the snippets of this crate: `common.wgsl` with math
+	/// constants, `pbr.wgsl` with the terms of the Cook-Torrance BRDF and
+	/// `virtual_texture.wgsl` with sampling of virtual textures.
+	pub fn new() -> Self {
+		let mut result = Self {
+			snippets: HashMap::new(),
+		};
+		result.register("common.wgsl", include_str!("common.wgsl"));
+		result.register("pbr.wgsl", include_str!("pbr.wgsl"));
+		result.register("virtual_texture.wgsl", include_str!("virtual_texture.wgsl"));
+		result
+	}
+
+	/// Makes `source` includable as `name`, replacing any snippet of that name.
+	pub fn register(&mut self, name: &'a str, source: &'a str) {
+		self.snippets.insert(name, source);
+	}
+
+	/// `source` with its includes expanded. Fails if a snippet isn't registered
+	/// or includes itself, directly or not.
+	pub fn process(&self, source: &str) -> Result<String> {
+		let mut output = String::with_capacity(source.len());
+		let mut included = Vec::new();
+		self.expand(source, &mut Vec::new(), &mut included, &mut output)?;
+		Ok(output)
+	}
+
+	/// Appends `source` to `output` with its includes expanded. `stack` holds the
+	/// snippets being expanded, and `included` those already in `output`.
+	fn expand(
+		&self,
+		source: &str,
+		stack: &mut Vec<&'a str>,
+		included: &mut Vec<&'a str>,
+		output: &mut String,
+	) -> Result<()> {
+		for (i, line) in source.lines().enumerate() {
+			let Some(name) = include_directive(line) else {
+				output.push_str(line);
+				output.push('\n');
+				continue;
+			};
+			let (&name, &snippet) = self
+				.snippets
+				.get_key_value(name)
+				.ok_or_else(|| eyre!("Line {}: no snippet named {name:?}", i + 1))?;
+			if stack.contains(&name) {
+				bail!("{name:?} includes itself, through {}", stack.join(" -> "));
+			}
+			if included.contains(&name) {
+				continue;
+			}
+			included.push(name);
+			stack.push(name);
+			self.expand(snippet, stack, included, output)
+				.wrap_err_with(|| format!("In {name:?}"))?;
+			stack.pop();
+		}
+		Ok(())
+	}
+}
+impl Default for ShaderPreprocessor<'_> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Completes `shader.wgsl`, given whether the model matrix is a push constant,
+/// with the entry point of `foliage_shader.wgsl`, and expands its includes.
+pub(crate) fn shader_source(shader_wgsl: &str, push_constants: bool) -> Result<String> {
+	let source = format!(
+		"{}\n{shader_wgsl}\n{}",
+		model_declaration(push_constants),
+		include_str!("foliage_shader.wgsl")
+	);
+	ShaderPreprocessor::new()
+		.process(&source)
+		.wrap_err("Failed to expand the includes of shader.wgsl")
+}
+
+/// Compiles `shader.wgsl`, through the pipeline disk cache when there is one,
+/// and hashes its source to tell when it changes.
+pub(crate) fn create_main_shader(
+	device: &wgpu::Device,
+	push_constants: bool,
+) -> Result<(wgpu::ShaderModule, u64)> {
+	const SHADER_LABEL: &str = "shader.wgsl";
+	let shader_src = shader_source(include_str!("shader.wgsl"), push_constants)?;
+	let compile_wgsl = || {
+		device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some(SHADER_LABEL),
+			source: wgpu::ShaderSource::Wgsl(shader_src.as_str().into()),
+		})
+	};
+	#[cfg(not(target_arch = "wasm32"))]
+	let shader = PipelineDiskCache::open_default()
+		.and_then(|cache| {
+			cache.create_shader_module(
+				device,
+				Some(SHADER_LABEL),
+				&shader_src,
+				(SHADER_LABEL, push_constants),
+			)
+		})
+		.unwrap_or_else(|err| {
+			warn!("Pipeline cache unavailable, compiling WGSL: {err:#}");
+			compile_wgsl()
+		});
+	#[cfg(target_arch = "wasm32")]
+	let shader = compile_wgsl();
+	Ok((shader, descriptor_hash(&shader_src)))
+}
+
+/// The name in `line`, if it is an include directive.
+fn include_directive(line: &str) -> Option<&str> {
+	line.trim()
+		.strip_prefix("//")?
+		.trim_start()
+		.strip_prefix("#include")?
+		.trim()
+		.strip_prefix('"')?
+		.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn includes_are_expanded_once() {
+		let mut preprocessor = ShaderPreprocessor::new();
+		preprocessor.register("a.wgsl", "// #include \"b.wgsl\"\nconst A = B;");
+		preprocessor.register("b.wgsl", "const B = 1;");
+		let source = "// #include \"a.wgsl\"\n  //#include \"b.wgsl\"\nconst C = A;";
+		let expanded = preprocessor.process(source).unwrap();
+		assert_eq!(expanded, "const B = 1;\nconst A = B;\nconst C = A;\n");
+	}
+
+	#[test]
+	fn cycles_are_errors() {
+		let mut preprocessor = ShaderPreprocessor::new();
+		preprocessor.register("a.wgsl", "// #include \"b.wgsl\"");
+		preprocessor.register("b.wgsl", "// #include \"a.wgsl\"");
+		let error = preprocessor.process("// #include \"a.wgsl\"").unwrap_err();
+		assert_eq!(
+			error.root_cause().to_string(),
+			"\"a.wgsl\" includes itself, through a.wgsl -> b.wgsl"
+		);
+		assert!(preprocessor.process("// #include \"c.wgsl\"").is_err());
+	}
+}
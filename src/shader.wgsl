@@ -1,37 +1,762 @@
 
 struct CameraUniform {
-	view_proj: mat4x4<f32>
+	view_proj: mat4x4<f32>,
+	// In world space.
+	position: vec3<f32>,
 };
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct LightUniform {
+	// The direction the light travels in.
+	direction: vec3<f32>,
+	color: vec3<f32>,
+	ambient: f32,
+};
+@group(2) @binding(0)
+var<uniform> light: LightUniform;
+// Transforms world space into the clip space of the shadow map.
+@group(2) @binding(1)
+var<uniform> light_camera: CameraUniform;
+@group(2) @binding(2)
+var shadow_t: texture_depth_2d;
+@group(2) @binding(3)
+var shadow_s: sampler_comparison;
+
+// See `fog.rs`. It shares the light's group, as all four groups are in use.
+struct FogUniform {
+	color: vec4<f32>,
+	start: f32,
+	end: f32,
+	density: f32,
+	// 0 for linear, 1 for exponential, 2 for exponential squared, otherwise off.
+	mode: u32,
+};
+@group(2) @binding(4)
+var<uniform> fog: FogUniform;
+
+// See `environment.rs`. Black when no environment map is set.
+@group(2) @binding(5)
+var env_t: texture_cube<f32>;
+@group(2) @binding(6)
+var env_s: sampler;
+struct EnvironmentUniform {
+	// The mip level sampled by fully rough surfaces.
+	max_lod: f32,
+	// 1 when `env_t` is a reflection probe's pre-filtered map, with its
+	// irradiance and BRDF lookup table bound too.
+	probe: u32,
+};
+@group(2) @binding(7)
+var<uniform> environment: EnvironmentUniform;
+
+// How much ambient light reaches each pixel of the frame, see `ssao.rs`. White
+// when SSAO is disabled.
+@group(2) @binding(8)
+var occlusion_t: texture_2d<f32>;
+
+// See `uv_animation.rs`.
+struct UvAnimation {
+	offset: vec2<f32>,
+	velocity: vec2<f32>,
+};
+@group(2) @binding(9)
+var<uniform> uv_animation: UvAnimation;
+
+// See `color_correction.rs`.
+struct ColorCorrectionUniform {
+	gamma: f32,
+	exposure: f32,
+	// 0 for linear, 1 for gamma encoding, 2 for ACES.
+	output_mode: u32,
+};
+@group(2) @binding(10)
+var<uniform> color_correction: ColorCorrectionUniform;
+
+// See `cascades.rs`. They replace `shadow_t` when enabled, sampled with
+// `shadow_s` too. `pcf_radius` applies to both.
+struct CascadeUniform {
+	view_proj: array<mat4x4<f32>, 4>,
+	// The view depth each cascade ends at.
+	splits: vec4<f32>,
+	// The direction the camera looks in, in world space.
+	forward: vec3<f32>,
+	// 0 when the cascades are disabled.
+	count: u32,
+	// The share of each cascade, at its far end, that fades into the next.
+	blend: f32,
+	// How far apart the shadow samples of each fragment are, in texels.
+	pcf_radius: f32,
+};
+@group(2) @binding(11)
+var<uniform> cascades: CascadeUniform;
+@group(2) @binding(12)
+var cascade_t: texture_depth_2d_array;
+
+// See `reflection_probe.rs`, sampled with `env_s`. Black without a probe.
+@group(2) @binding(13)
+var irradiance_t: texture_cube<f32>;
+@group(2) @binding(14)
+var brdf_lut_t: texture_2d<f32>;
+
+// See `point_shadow.rs`.
+struct PointLight {
+	position: vec3<f32>,
+	// How far the light reaches, and the far plane of its shadow map.
+	range: f32,
+	color: vec3<f32>,
+	// The near plane of its shadow map.
+	near: f32,
+};
+struct PointLightsUniform {
+	lights: array<PointLight, 4>,
+	count: u32,
+};
+@group(2) @binding(16)
+var<uniform> point_lights: PointLightsUniform;
+@group(2) @binding(17)
+var cube_shadow_sampler: sampler_comparison;
+// The shadow map of each point light. The faces are mirrored along z, see
+// `point_shadow_factor`.
+@group(2) @binding(18)
+var cube_shadow_0: texture_depth_cube;
+@group(2) @binding(19)
+var cube_shadow_1: texture_depth_cube;
+@group(2) @binding(20)
+var cube_shadow_2: texture_depth_cube;
+@group(2) @binding(21)
+var cube_shadow_3: texture_depth_cube;
+
+// `model`, the object's transform, is declared by `render_object.rs` as either a
+// push constant or a uniform. It is applied after the instance's transform.
+
 struct VertexInput {
 	@location(0) pos: vec3<f32>,
 	@location(1) uv: vec2<f32>,
+	@location(2) normal: vec3<f32>,
+	@location(3) tangent: vec3<f32>,
+	@location(4) bitangent: vec3<f32>,
+};
+
+struct InstanceInput {
+	// The columns of the model matrix.
+	@location(5) transform_0: vec4<f32>,
+	@location(6) transform_1: vec4<f32>,
+	@location(7) transform_2: vec4<f32>,
+	@location(8) transform_3: vec4<f32>,
 };
 
 struct VertexOutput {
 	@builtin(position) clip_pos: vec4<f32>,
 	@location(0) uv: vec2<f32>,
+	@location(1) world_normal: vec3<f32>,
+	@location(2) world_tangent: vec3<f32>,
+	@location(3) world_bitangent: vec3<f32>,
+	@location(4) world_pos: vec3<f32>,
 };
 
+// Transforms `verts` by `transform`, into world space and then clip space.
+fn transform_vertex(verts: VertexInput, transform: mat4x4<f32>) -> VertexOutput {
+	var out: VertexOutput;
+	out.uv = verts.uv;
+	// Only correct for uniform scaling, otherwise we'd need the inverse
+	// transpose.
+	out.world_normal = (transform * vec4<f32>(verts.normal, 0.0)).xyz;
+	out.world_tangent = (transform * vec4<f32>(verts.tangent, 0.0)).xyz;
+	out.world_bitangent = (transform * vec4<f32>(verts.bitangent, 0.0)).xyz;
+	let world_pos = transform * vec4<f32>(verts.pos, 1.0);
+	out.world_pos = world_pos.xyz;
+	out.clip_pos = camera.view_proj * world_pos;
+	return out;
+}
+
 @vertex
 fn vs_main(
-	verts: VertexInput
+	verts: VertexInput,
+	instance: InstanceInput,
 ) -> VertexOutput {
-	var out: VertexOutput;
-	out.uv = verts.uv;
-	out.clip_pos = camera.view_proj * vec4<f32>(verts.pos, 1.0);
+	let transform = model * mat4x4<f32>(
+		instance.transform_0,
+		instance.transform_1,
+		instance.transform_2,
+		instance.transform_3,
+	);
+	return transform_vertex(verts, transform);
+}
+
+// This and the previous frame's projection times model matrix of the object,
+// without jitter, see `gbuffer.rs`. Only used by `vs_motion`, in the geometry
+// pass whose group 2 holds no light.
+struct PreviousTransform {
+	current: mat4x4<f32>,
+	previous: mat4x4<f32>,
+};
+@group(2) @binding(15)
+var<uniform> previous_transform: PreviousTransform;
+
+struct MotionOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(1) world_normal: vec3<f32>,
+	// Where the vertex is this frame and was in the previous one, in clip space.
+	@location(5) current_clip: vec4<f32>,
+	@location(6) previous_clip: vec4<f32>,
+};
+
+// Like `vs_main`, also placing the vertex by `previous_transform`, so that the
+// geometry pass can write how far it moved.
+@vertex
+fn vs_motion(verts: VertexInput, instance: InstanceInput) -> MotionOutput {
+	let instance_transform = mat4x4<f32>(
+		instance.transform_0,
+		instance.transform_1,
+		instance.transform_2,
+		instance.transform_3,
+	);
+	let transformed = transform_vertex(verts, model * instance_transform);
+	var out: MotionOutput;
+	out.clip_pos = transformed.clip_pos;
+	out.world_normal = transformed.world_normal;
+	let pos = instance_transform * vec4<f32>(verts.pos, 1.0);
+	out.current_clip = previous_transform.current * pos;
+	out.previous_clip = previous_transform.previous * pos;
 	return out;
 }
 
+// The bones of the skeleton, see `skinning.rs`. Only used by `vs_skinned`, so it
+// doesn't clash with `model` when that is a uniform.
+struct SkinUniform {
+	bones: array<mat4x4<f32>, 64>,
+};
+@group(3) @binding(1)
+var<uniform> skin: SkinUniform;
+
+struct SkinInput {
+	@location(9) joints: vec4<u32>,
+	@location(10) weights: vec4<f32>,
+};
+
+// Linear blend skinning: vertices are moved by the weighted sum of their bones.
+// The bones already include the model transform, so `model` isn't applied.
+@vertex
+fn vs_skinned(verts: VertexInput, skin_in: SkinInput) -> VertexOutput {
+	let transform = skin.bones[skin_in.joints.x] * skin_in.weights.x
+		+ skin.bones[skin_in.joints.y] * skin_in.weights.y
+		+ skin.bones[skin_in.joints.z] * skin_in.weights.z
+		+ skin.bones[skin_in.joints.w] * skin_in.weights.w;
+	return transform_vertex(verts, transform);
+}
+
 
 @group(0) @binding(0)
 var diffuse_t: texture_2d<f32>;
 @group(0) @binding(1)
 var diffuse_s: sampler;
+@group(0) @binding(2)
+var normal_map_t: texture_2d<f32>;
+@group(0) @binding(3)
+var normal_map_s: sampler;
+@group(0) @binding(4)
+var specular_t: texture_2d<f32>;
+@group(0) @binding(5)
+var specular_s: sampler;
+// Roughness in green and metalness in blue, as in glTF. Only used by `fs_pbr`.
+@group(0) @binding(6)
+var metallic_roughness_t: texture_2d<f32>;
+@group(0) @binding(7)
+var metallic_roughness_s: sampler;
+// How high the surface is, in red, from 0 at the deepest to 1. Only used by the
+// `_parallax` entry points.
+@group(0) @binding(8)
+var height_t: texture_2d<f32>;
+@group(0) @binding(9)
+var height_s: sampler;
+struct MaterialUniform {
+	// How deep the height map's 0 is below the surface, in uv units.
+	parallax_depth_scale: f32,
+};
+@group(0) @binding(10)
+var<uniform> material: MaterialUniform;
+
+// The sharpness of specular highlights.
+const SHININESS: f32 = 32.0;
+
+// Where `shadow_factor` samples the shadow map around each fragment: a Poisson
+// disk, in units of `cascades.pcf_radius` texels.
+var<private> PCF_KERNEL: array<vec2<f32>, 9> = array<vec2<f32>, 9>(
+	vec2<f32>(0.0, 0.0),
+	vec2<f32>(0.1709, 0.7377),
+	vec2<f32>(-0.6812, -0.4363),
+	vec2<f32>(0.8414, -0.2632),
+	vec2<f32>(0.3884, -0.77),
+	vec2<f32>(-0.959, 0.1498),
+	vec2<f32>(-0.6228, 0.77),
+	vec2<f32>(0.784, 0.3933),
+	vec2<f32>(-0.2303, -0.9669),
+);
+
+// How much of the light reaches `world_pos`, from 0 in shadow to 1. Averages
+// the shadow map's comparisons over `PCF_KERNEL` to soften the edges.
+fn shadow_factor(world_pos: vec3<f32>) -> f32 {
+	if cascades.count > 0u {
+		return cascaded_shadow_factor(world_pos);
+	}
+	let light_pos = light_camera.view_proj * vec4<f32>(world_pos, 1.0);
+	let ndc = light_pos.xyz / light_pos.w;
+	// Beyond the shadow map, nothing is in shadow.
+	if any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+		return 1.0;
+	}
+	let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+	let spread = cascades.pcf_radius / vec2<f32>(textureDimensions(shadow_t, 0));
+	var lit = 0.0;
+	for (var i = 0; i < 9; i += 1) {
+		let offset = PCF_KERNEL[i] * spread;
+		lit += textureSampleCompareLevel(shadow_t, shadow_s, uv + offset, ndc.z);
+	}
+	return lit / 9.0;
+}
+
+// Like `shadow_factor`, in the cascade at `index`.
+fn cascade_shadow_factor(world_pos: vec3<f32>, index: u32) -> f32 {
+	let light_pos = cascades.view_proj[index] * vec4<f32>(world_pos, 1.0);
+	let ndc = light_pos.xyz / light_pos.w;
+	if any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+		return 1.0;
+	}
+	let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+	let spread = cascades.pcf_radius / vec2<f32>(textureDimensions(cascade_t, 0));
+	var lit = 0.0;
+	for (var i = 0; i < 9; i += 1) {
+		let offset = PCF_KERNEL[i] * spread;
+		lit += textureSampleCompareLevel(
+			cascade_t,
+			shadow_s,
+			uv + offset,
+			i32(index),
+			ndc.z,
+		);
+	}
+	return lit / 9.0;
+}
+
+// Like `shadow_factor`, in the cascade of the view depth of `world_pos`. Near
+// the end of a cascade, it fades into the next one, and the last one fades out.
+fn cascaded_shadow_factor(world_pos: vec3<f32>) -> f32 {
+	let depth = dot(world_pos - camera.position, cascades.forward);
+	var index = 0u;
+	while index < cascades.count && depth > cascades.splits[index] {
+		index += 1u;
+	}
+	if index == cascades.count {
+		return 1.0;
+	}
+	let end = cascades.splits[index];
+	var start = 0.0;
+	if index > 0u {
+		start = cascades.splits[index - 1u];
+	}
+	let blend_start = end - (end - start) * cascades.blend;
+	let shadow = cascade_shadow_factor(world_pos, index);
+	if depth <= blend_start {
+		return shadow;
+	}
+	var next = 1.0;
+	if index + 1u < cascades.count {
+		next = cascade_shadow_factor(world_pos, index + 1u);
+	}
+	return mix(shadow, next, smoothstep(blend_start, end, depth));
+}
+
+// How much of the point light at `index` reaches `world_pos`, from 0 in shadow
+// to 1.
+fn point_shadow_factor(index: u32, world_pos: vec3<f32>) -> f32 {
+	let point_light = point_lights.lights[index];
+	let light_to_frag = world_pos - point_light.position;
+	// The faces are drawn by right-handed cameras, which mirrors them along z
+	// compared to how cubemaps are sampled.
+	let dir = light_to_frag * vec3<f32>(1.0, 1.0, -1.0);
+	// The depth of the fragment in the face it falls on, whose camera looks
+	// along the major axis.
+	let axis_distance = max(
+		max(abs(light_to_frag.x), abs(light_to_frag.y)),
+		abs(light_to_frag.z),
+	);
+	let closest_depth = point_light.range / (point_light.range - point_light.near)
+		* (1.0 - point_light.near / axis_distance);
+	// Each map is bound on its own, as cubemap arrays aren't supported everywhere.
+	if index == 0u {
+		return textureSampleCompareLevel(cube_shadow_0, cube_shadow_sampler, dir, closest_depth);
+	}
+	if index == 1u {
+		return textureSampleCompareLevel(cube_shadow_1, cube_shadow_sampler, dir, closest_depth);
+	}
+	if index == 2u {
+		return textureSampleCompareLevel(cube_shadow_2, cube_shadow_sampler, dir, closest_depth);
+	}
+	return textureSampleCompareLevel(cube_shadow_3, cube_shadow_sampler, dir, closest_depth);
+}
+
+// How much of a point light `distance` away is left: the inverse square law,
+// windowed to fade out at its range.
+fn point_attenuation(point_light: PointLight, distance: f32) -> f32 {
+	let window = clamp(1.0 - pow(distance / point_light.range, 4.0), 0.0, 1.0);
+	return window * window / (distance * distance + 1.0);
+}
+
+// The light of the point light at `index` arriving at `world_pos`, shadowed
+// and attenuated.
+fn point_radiance(index: u32, world_pos: vec3<f32>) -> vec3<f32> {
+	let point_light = point_lights.lights[index];
+	let attenuation =
+		point_attenuation(point_light, distance(point_light.position, world_pos));
+	return point_light.color * attenuation * point_shadow_factor(index, world_pos);
+}
+
+// How much of a surface `distance` away from the camera the fog hides, from 0
+// to 1.
+fn fog_factor(distance: f32) -> f32 {
+	switch fog.mode {
+		case 0u: {
+			return clamp((distance - fog.start) / (fog.end - fog.start), 0.0, 1.0);
+		}
+		case 1u: {
+			return 1.0 - exp(-fog.density * distance);
+		}
+		case 2u: {
+			let d = fog.density * distance;
+			return 1.0 - exp(-d * d);
+		}
+		default: {
+			return 0.0;
+		}
+	}
+}
+
+// Where textures are sampled at `uv`, as scrolled by the UV animation. Samplers
+// have to repeat for textures to wrap around.
+fn scrolled_uv(uv: vec2<f32>) -> vec2<f32> {
+	return uv + uv_animation.offset;
+}
+
+// Turns tangent space, along `u`, `v` and the normal, into world space.
+fn tangent_basis(in: VertexOutput) -> mat3x3<f32> {
+	// Interpolation denormalizes the basis vectors.
+	return mat3x3<f32>(
+		normalize(in.world_tangent),
+		normalize(in.world_bitangent),
+		normalize(in.world_normal),
+	);
+}
+
+// The normal at `in`, in world space, perturbed by the normal map at `uv`.
+fn mapped_normal(in: VertexOutput, uv: vec2<f32>) -> vec3<f32> {
+	let tbn = tangent_basis(in);
+	var tangent_normal = textureSample(normal_map_t, normal_map_s, uv).xyz * 2.0 - 1.0;
+	// Normal maps are assumed to follow the OpenGL convention of green pointing
+	// up the image, but `v` increases downwards.
+	tangent_normal.y = -tangent_normal.y;
+	return normalize(tbn * tangent_normal);
+}
+
+// The most and fewest layers of the height map stepped through, when looking at
+// the surface at a grazing angle and head on.
+const MAX_PARALLAX_STEPS: f32 = 32.0;
+const MIN_PARALLAX_STEPS: f32 = 8.0;
+// Halvings of the last step, to find where it crosses the surface.
+const PARALLAX_REFINE_STEPS: u32 = 4u;
+
+// Parallax occlusion mapping: where the view ray through `in` hits the surface
+// described by the height map, as scrolled texture coordinates.
+fn parallax_uv(in: VertexOutput) -> vec2<f32> {
+	let uv = scrolled_uv(in.uv);
+	let to_eye = transpose(tangent_basis(in)) * normalize(camera.position - in.world_pos);
+	// Taken before the loops, which aren't in uniform control flow.
+	let uv_dx = dpdx(uv);
+	let uv_dy = dpdy(uv);
+	let num_layers = mix(MAX_PARALLAX_STEPS, MIN_PARALLAX_STEPS, abs(to_eye.z));
+	let layer_depth = 1.0 / num_layers;
+	// Deeper points are seen further away from the eye along the surface. Limits
+	// the shift at grazing angles.
+	let shift = to_eye.xy / max(to_eye.z, 0.05) * material.parallax_depth_scale
+		* layer_depth;
+
+	// Steps down through the layers until below the surface.
+	var current_uv = uv;
+	var current_depth = 0.0;
+	var surface_depth = 1.0 - textureSampleGrad(height_t, height_s, current_uv, uv_dx, uv_dy).r;
+	for (var i = 0u; i < u32(num_layers) && current_depth < surface_depth; i += 1u) {
+		current_uv -= shift;
+		current_depth += layer_depth;
+		surface_depth = 1.0 - textureSampleGrad(height_t, height_s, current_uv, uv_dx, uv_dy).r;
+	}
+
+	// Binary search of the last step.
+	var step_uv = shift;
+	var step_depth = layer_depth;
+	for (var i = 0u; i < PARALLAX_REFINE_STEPS; i += 1u) {
+		step_uv *= 0.5;
+		step_depth *= 0.5;
+		surface_depth = 1.0 - textureSampleGrad(height_t, height_s, current_uv, uv_dx, uv_dy).r;
+		if current_depth > surface_depth {
+			current_uv += step_uv;
+			current_depth -= step_depth;
+		} else {
+			current_uv -= step_uv;
+			current_depth += step_depth;
+		}
+	}
+	return current_uv;
+}
+
+// How much of the ambient light reaches the pixel at `clip_pos`, from 0 to 1.
+fn ambient_occlusion(clip_pos: vec4<f32>) -> f32 {
+	let size = vec2<i32>(textureDimensions(occlusion_t, 0));
+	let coords = min(vec2<i32>(clip_pos.xy), size - 1);
+	return textureLoad(occlusion_t, coords, 0).r;
+}
+
+// Fades `color` into the fog, by the distance of `world_pos` from the camera.
+fn apply_fog(color: vec3<f32>, world_pos: vec3<f32>) -> vec3<f32> {
+	let fog_amount = fog_factor(distance(camera.position, world_pos));
+	return mix(color, fog.color.rgb, fog_amount);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve, as in `tonemap.wgsl`.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+	let a = 2.51;
+	let b = 0.03;
+	let c = 2.43;
+	let d = 0.59;
+	let e = 0.14;
+	return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Applies the output mode of the color correction to the final `color`.
+fn correct_color(color: vec3<f32>) -> vec3<f32> {
+	if color_correction.output_mode == 0u {
+		return color;
+	}
+	var exposed = max(color * color_correction.exposure, vec3<f32>(0.0));
+	if color_correction.output_mode == 2u {
+		exposed = aces(exposed);
+	}
+	return pow(exposed, vec3<f32>(1.0 / color_correction.gamma));
+}
+
+// Blinn-Phong shading, for materials without a metallic-roughness map, with the
+// textures sampled at `uv`.
+fn blinn_phong(in: VertexOutput, uv: vec2<f32>) -> vec4<f32> {
+	let albedo = textureSample(diffuse_t, diffuse_s, uv);
+	let n = mapped_normal(in, uv);
+	let shadow = shadow_factor(in.world_pos);
+	let diffuse = max(dot(n, -light.direction), 0.0) * shadow;
+	// Blinn-Phong, tinted by the specular map.
+	let to_eye = normalize(camera.position - in.world_pos);
+	let halfway = normalize(to_eye - light.direction);
+	let highlight = pow(max(dot(n, halfway), 0.0), SHININESS) * shadow;
+	var point_diffuse = vec3<f32>(0.0);
+	var point_highlight = vec3<f32>(0.0);
+	for (var i = 0u; i < point_lights.count; i += 1u) {
+		let radiance = point_radiance(i, in.world_pos);
+		let to_light = normalize(point_lights.lights[i].position - in.world_pos);
+		point_diffuse += radiance * max(dot(n, to_light), 0.0);
+		let point_halfway = normalize(to_eye + to_light);
+		point_highlight += radiance * pow(max(dot(n, point_halfway), 0.0), SHININESS);
+	}
+	let specular = textureSample(specular_t, specular_s, uv).rgb;
+	let occlusion = ambient_occlusion(in.clip_pos);
+	let lit = (light.color * (light.ambient + diffuse) + point_diffuse) * occlusion;
+	let color = albedo.rgb * lit
+		+ specular * (light.color * highlight + point_highlight);
+	return vec4<f32>(correct_color(apply_fog(color, in.world_pos)), albedo.a);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-	return textureSample(diffuse_t, diffuse_s, in.uv);
+	return blinn_phong(in, scrolled_uv(in.uv));
+}
+
+// The `_parallax` entry points are the others with parallax occlusion mapping,
+// for materials with a height map. It is left out of the others' pipelines.
+@fragment
+fn fs_parallax(in: VertexOutput) -> @location(0) vec4<f32> {
+	return blinn_phong(in, parallax_uv(in));
+}
+
+// The color encoding the index of the object drawn, see `picking.rs`. Only used
+// by `fs_id`, in the picking pass whose group 2 holds no light.
+struct PickingId {
+	color: vec4<u32>,
+};
+@group(2) @binding(22)
+var<uniform> picking_id: PickingId;
+
+// Writes the index of the object, for the picking pass of `picking.rs`.
+@fragment
+fn fs_id(in: VertexOutput) -> @location(0) vec4<u32> {
+	return picking_id.color;
+}
+
+struct GeometryOutput {
+	// The normal, mapped to 0 to 1.
+	@location(0) normal: vec4<f32>,
+	// The depth, in the red channel.
+	@location(1) depth: vec4<f32>,
+	// How far the surface moved in NDC since the previous frame, in the red and
+	// green channels.
+	@location(2) motion: vec4<f32>,
+};
+
+// Writes the normal, depth and motion of the surface, for the geometry pass of
+// `gbuffer.rs`.
+@fragment
+fn fs_normal(in: MotionOutput) -> GeometryOutput {
+	var out: GeometryOutput;
+	out.normal = vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+	out.depth = vec4<f32>(in.clip_pos.z, 0.0, 0.0, 1.0);
+	let current = in.current_clip.xy / in.current_clip.w;
+	let previous = in.previous_clip.xy / in.previous_clip.w;
+	out.motion = vec4<f32>(current - previous, 0.0, 1.0);
+	return out;
+}
+
+// #include "pbr.wgsl"
+
+// The diffuse and specular light reflected by a microfacet surface, per unit of
+// radiance arriving along `l`.
+struct Reflected {
+	diffuse: vec3<f32>,
+	specular: vec3<f32>,
+};
+
+// Cook-Torrance's BRDF for light arriving along `l` and leaving along `v`, times
+// the cosine of the light's angle.
+fn reflected(
+	n: vec3<f32>,
+	v: vec3<f32>,
+	l: vec3<f32>,
+	albedo: vec3<f32>,
+	metallic: f32,
+	roughness: f32,
+	f0: vec3<f32>,
+) -> Reflected {
+	let h = normalize(v + l);
+	let n_dot_v = max(dot(n, v), 0.0001);
+	let n_dot_l = max(dot(n, l), 0.0);
+	let n_dot_h = max(dot(n, h), 0.0);
+	let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+	let d = distribution_ggx(n_dot_h, roughness);
+	let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+	var out: Reflected;
+	out.specular = d * g * f / (4.0 * n_dot_v * max(n_dot_l, 0.0001)) * n_dot_l;
+	out.diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI * n_dot_l;
+	return out;
+}
+
+// Cook-Torrance shading, for materials with a metallic-roughness map, with the
+// textures sampled at `uv`.
+fn cook_torrance(in: VertexOutput, uv: vec2<f32>) -> vec4<f32> {
+	let albedo = textureSample(diffuse_t, diffuse_s, uv);
+	let metallic_roughness =
+		textureSample(metallic_roughness_t, metallic_roughness_s, uv);
+	let metallic = metallic_roughness.b;
+	// Perfectly smooth surfaces would have infinitely small highlights.
+	let roughness = clamp(metallic_roughness.g, 0.04, 1.0);
+
+	let n = mapped_normal(in, uv);
+	let v = normalize(camera.position - in.world_pos);
+	let n_dot_v = max(dot(n, v), 0.0001);
+
+	// Metals reflect in their own color, and absorb what they don't reflect.
+	let f0 = mix(vec3<f32>(DIELECTRIC_F0), albedo.rgb, metallic);
+
+	// The lights' colors are their irradiance divided by pi, so that a white
+	// Lambertian surface facing them is as bright as with `fs_main`.
+	let sun =
+		reflected(n, v, -light.direction, albedo.rgb, metallic, roughness, f0);
+	let radiance = light.color * PI * shadow_factor(in.world_pos);
+	var diffuse = sun.diffuse * radiance;
+	var specular = sun.specular * radiance;
+	for (var i = 0u; i < point_lights.count; i += 1u) {
+		let point_light = point_radiance(i, in.world_pos) * PI;
+		let l = normalize(point_lights.lights[i].position - in.world_pos);
+		let lit = reflected(n, v, l, albedo.rgb, metallic, roughness, f0);
+		diffuse += lit.diffuse * point_light;
+		specular += lit.specular * point_light;
+	}
+	let ambient = light.color * light.ambient * albedo.rgb;
+
+	// Rough surfaces reflect the environment's blurrier mips.
+	let r = reflect(-v, n);
+	let lod = roughness * environment.max_lod;
+	let env = textureSampleLevel(env_t, env_s, r, lod).rgb;
+	var env_diffuse = vec3<f32>(0.0);
+	var env_specular: vec3<f32>;
+	if environment.probe != 0u {
+		// The split sum approximation: the pre-filtered light, times the BRDF
+		// integrated over the hemisphere as a scale and bias to `f0`.
+		let brdf = textureSampleLevel(brdf_lut_t, env_s, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+		env_specular = env * (f0 * brdf.x + brdf.y);
+		let irradiance = textureSampleLevel(irradiance_t, env_s, n, 0.0).rgb;
+		let k_d = (1.0 - fresnel_schlick(n_dot_v, f0)) * (1.0 - metallic);
+		env_diffuse = k_d * irradiance * albedo.rgb;
+	} else {
+		// Without a probe, less of the environment is reflected the rougher it is.
+		env_specular = env * fresnel_schlick(n_dot_v, f0) * (1.0 - roughness);
+	}
+
+	let occlusion = ambient_occlusion(in.clip_pos);
+	let color = (ambient + env_diffuse + diffuse) * occlusion + env_specular + specular;
+	return vec4<f32>(correct_color(apply_fog(color, in.world_pos)), albedo.a);
+}
+
+@fragment
+fn fs_pbr(in: VertexOutput) -> @location(0) vec4<f32> {
+	return cook_torrance(in, scrolled_uv(in.uv));
+}
+
+@fragment
+fn fs_pbr_parallax(in: VertexOutput) -> @location(0) vec4<f32> {
+	return cook_torrance(in, parallax_uv(in));
+}
+
+// The targets of weighted blended order-independent transparency, see
+// `wboit.rs`.
+struct AccumulationOutput {
+	// The premultiplied color and alpha, times the weight.
+	@location(0) accumulation: vec4<f32>,
+	// The alpha, by which the revealage is multiplied.
+	@location(1) revealage: vec4<f32>,
+};
+
+// Weights `color` by its alpha and distance, so that nearer and more opaque
+// surfaces dominate the average. Equation 7 of McGuire and Bavoil.
+fn accumulate(color: vec4<f32>, world_pos: vec3<f32>) -> AccumulationOutput {
+	let d = distance(camera.position, world_pos);
+	let weight = color.a
+		* clamp(10.0 / (1e-5 + pow(d / 5.0, 2.0) + pow(d / 200.0, 6.0)), 1e-2, 3e3);
+	var out: AccumulationOutput;
+	out.accumulation = vec4<f32>(color.rgb * color.a, color.a) * weight;
+	out.revealage = vec4<f32>(color.a);
+	return out;
+}
+
+@fragment
+fn fs_wboit(in: VertexOutput) -> AccumulationOutput {
+	return accumulate(blinn_phong(in, scrolled_uv(in.uv)), in.world_pos);
+}
+
+@fragment
+fn fs_wboit_parallax(in: VertexOutput) -> AccumulationOutput {
+	return accumulate(blinn_phong(in, parallax_uv(in)), in.world_pos);
+}
+
+@fragment
+fn fs_wboit_pbr(in: VertexOutput) -> AccumulationOutput {
+	return accumulate(cook_torrance(in, scrolled_uv(in.uv)), in.world_pos);
+}
+
+@fragment
+fn fs_wboit_pbr_parallax(in: VertexOutput) -> AccumulationOutput {
+	return accumulate(cook_torrance(in, parallax_uv(in)), in.world_pos);
 }
@@ -0,0 +1,341 @@
+//! Shadows of the directional light, from a depth map rendered from its point of
+//! view.
+
+use nalgebra::{IsometryMatrix3, Matrix4, Orthographic3, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraUniform;
+use crate::vertex::{Instance, Vertex};
+
+/// The depth of the scene as seen by a directional light, and the pipeline
+/// drawing it. Only shadows within a sphere around [`Self::set_bounds`]'s center
+/// are captured.
+pub struct ShadowMap {
+	texture: wgpu::Texture,
+	pub view: wgpu::TextureView,
+	/// Compares depths, filtering the results of neighbouring texels.
+	pub sampler: wgpu::Sampler,
+	/// Transforms world space into the light's clip space, whose depth is stored
+	/// in the map.
+	pub light_view_proj: Matrix4<f32>,
+	/// `light_view_proj` and the light's position, as a [`CameraUniform`].
+	pub uniform_buf: wgpu::Buffer,
+	/// Width and height of the map, in texels.
+	size: u32,
+	/// The direction the light travels in.
+	direction: Vector3<f32>,
+	/// Where the light is seen from, behind the bounds.
+	eye: Point3<f32>,
+	center: Point3<f32>,
+	radius: f32,
+	/// Binds `uniform_buf` as the camera of `shader.wgsl`.
+	camera_bind_group: wgpu::BindGroup,
+	depth_pass: DepthPass,
+}
+impl ShadowMap {
+	pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+	pub const DEFAULT_SIZE: u32 = 1024;
+
+	/// Creates a `size`×`size` map, drawn with the vertex stage of `shader`.
+	///
+	/// The pass uses the main pipeline's camera and object bind group layouts,
+	/// so that `shader` can be reused as is.
+	pub fn new(
+		device: &wgpu::Device,
+		size: u32,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+	) -> Self {
+		let (texture, view) = create_texture(device, size);
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("Shadow Sampler"),
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::FilterMode::Nearest,
+			// Lit where the fragment is no further from the light than the map.
+			compare: Some(wgpu::CompareFunction::LessEqual),
+			..Default::default()
+		});
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Shadow Uniform"),
+				contents: bytemuck::bytes_of(&CameraUniform::new(
+					Matrix4::identity(),
+					Point3::origin(),
+				)),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("shadow_camera_bind_group"),
+			layout: camera_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			}],
+		});
+		let depth_pass = DepthPass::new(
+			device,
+			shader,
+			camera_layout,
+			object_layout,
+			push_constant_ranges,
+		);
+
+		let mut result = Self {
+			texture,
+			view,
+			sampler,
+			light_view_proj: Matrix4::identity(),
+			uniform_buf,
+			size,
+			direction: -Vector3::y(),
+			eye: Point3::origin(),
+			center: Point3::origin(),
+			radius: 2.,
+			camera_bind_group,
+			depth_pass,
+		};
+		result.update_matrix();
+		result
+	}
+
+	/// Recreates the map if its size changed. Returns whether it did, in which
+	/// case bind groups sampling it must be recreated too.
+	pub fn set_size(&mut self, device: &wgpu::Device, size: u32) -> bool {
+		if size == self.size {
+			return false;
+		}
+		(self.texture, self.view) = create_texture(device, size);
+		self.size = size;
+		true
+	}
+
+	/// Recreates the pipeline with a new version of `shader.wgsl`.
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		self.depth_pass.set_shader(device, shader);
+	}
+
+	/// Points the light along `direction`. Returns the number of bytes uploaded.
+	pub fn set_direction(
+		&mut self,
+		queue: &wgpu::Queue,
+		direction: Vector3<f32>,
+	) -> u64 {
+		self.direction = direction;
+		self.update_matrix();
+		self.upload(queue)
+	}
+
+	/// Captures shadows within `radius` of `center`. Returns the number of bytes
+	/// uploaded.
+	pub fn set_bounds(
+		&mut self,
+		queue: &wgpu::Queue,
+		center: Point3<f32>,
+		radius: f32,
+	) -> u64 {
+		self.center = center;
+		self.radius = radius;
+		self.update_matrix();
+		self.upload(queue)
+	}
+
+	fn update_matrix(&mut self) {
+		(self.eye, self.light_view_proj) =
+			light_projection(self.direction, self.center, self.radius, 0.);
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		let uniform = CameraUniform::new(self.light_view_proj, self.eye);
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of_val(&uniform) as u64
+	}
+
+	/// Begins the pass drawing into the map, with its pipeline and bind groups set.
+	/// The object bind group, if any, is left to the caller.
+	pub fn begin_pass<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+	) -> wgpu::RenderPass<'a> {
+		self.depth_pass.begin(
+			"Shadow Pass",
+			encoder,
+			&self.view,
+			&self.camera_bind_group,
+		)
+	}
+}
+
+/// Draws the depth of the scene from a light's point of view, with the vertex
+/// stage of `shader.wgsl`.
+pub(crate) struct DepthPass {
+	/// Bound to the groups of the main pipeline layout that the pass doesn't use.
+	empty_bind_group: wgpu::BindGroup,
+	#[cfg(feature = "hot-reload")]
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+impl DepthPass {
+	/// See [`ShadowMap::new`].
+	pub fn new(
+		device: &wgpu::Device,
+		shader: &wgpu::ShaderModule,
+		camera_layout: &wgpu::BindGroupLayout,
+		object_layout: Option<&wgpu::BindGroupLayout>,
+		push_constant_ranges: &[wgpu::PushConstantRange],
+	) -> Self {
+		let empty_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Empty Bind Group Layout"),
+				entries: &[],
+			});
+		let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("empty_bind_group"),
+			layout: &empty_layout,
+			entries: &[],
+		});
+		// The material and light groups are left empty.
+		let mut bind_group_layouts = vec![&empty_layout, camera_layout, &empty_layout];
+		bind_group_layouts.extend(object_layout);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Shadow Pipeline Layout"),
+			bind_group_layouts: &bind_group_layouts,
+			push_constant_ranges,
+		});
+		let pipeline = create_pipeline(device, &layout, shader);
+		Self {
+			empty_bind_group,
+			#[cfg(feature = "hot-reload")]
+			layout,
+			pipeline,
+		}
+	}
+
+	#[cfg(feature = "hot-reload")]
+	pub fn set_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) {
+		self.pipeline = create_pipeline(device, &self.layout, shader);
+	}
+
+	/// Begins a pass clearing `view` and drawing into it, as seen by the camera
+	/// of `camera_bind_group`.
+	pub fn begin<'a>(
+		&'a self,
+		label: &str,
+		encoder: &'a mut wgpu::CommandEncoder,
+		view: &'a wgpu::TextureView,
+		camera_bind_group: &'a wgpu::BindGroup,
+	) -> wgpu::RenderPass<'a> {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some(label),
+			color_attachments: &[],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(1.0),
+					store: true,
+				}),
+				stencil_ops: None,
+			}),
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.empty_bind_group, &[]);
+		pass.set_bind_group(1, camera_bind_group, &[]);
+		pass.set_bind_group(2, &self.empty_bind_group, &[]);
+		pass
+	}
+}
+
+/// Where a light travelling along `direction` is seen from, and the transform of
+/// world space into its clip space, for an orthographic projection of the sphere
+/// at `center` of `radius`. Casters up to `margin` beyond the sphere, towards the
+/// light, are captured too.
+pub(crate) fn light_projection(
+	direction: Vector3<f32>,
+	center: Point3<f32>,
+	radius: f32,
+	margin: f32,
+) -> (Point3<f32>, Matrix4<f32>) {
+	let direction = direction
+		.try_normalize(f32::EPSILON)
+		.unwrap_or_else(|| -Vector3::y());
+	// Any up vector works, as long as it isn't parallel to the light.
+	let up = if direction.y.abs() > 0.99 {
+		Vector3::z()
+	} else {
+		Vector3::y()
+	};
+	let eye = center - direction * (radius + margin);
+	let view = IsometryMatrix3::look_at_rh(&eye, &center, &up);
+	let r = radius;
+	let proj = Orthographic3::new(-r, r, -r, r, 0., 2. * r + margin);
+	// nalgebra's depth goes from -1 to 1, wgpu's from 0 to 1.
+	let depth_to_wgpu = Matrix4::new_translation(&Vector3::new(0., 0., 0.5))
+		* Matrix4::new_nonuniform_scaling(&Vector3::new(1., 1., 0.5));
+	(eye, depth_to_wgpu * proj.as_matrix() * view.to_matrix())
+}
+
+fn create_texture(
+	device: &wgpu::Device,
+	size: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+	let texture = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Shadow Map"),
+		size: wgpu::Extent3d {
+			width: size,
+			height: size,
+			depth_or_array_layers: 1,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: ShadowMap::FORMAT,
+		usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+			| wgpu::TextureUsages::TEXTURE_BINDING,
+		view_formats: &[],
+	});
+	let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+	(texture, view)
+}
+
+/// A depth-only pipeline, with the vertex stage of `shader.wgsl`.
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Shadow Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[Vertex::vb_layout(), Instance::vb_layout()],
+		},
+		fragment: None,
+		primitive: wgpu::PrimitiveState {
+			cull_mode: Some(wgpu::Face::Back),
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: ShadowMap::FORMAT,
+			depth_write_enabled: true,
+			depth_compare: wgpu::CompareFunction::Less,
+			stencil: wgpu::StencilState::default(),
+			// Pushes the depths back, so that surfaces don't shadow themselves where
+			// the map's resolution runs out ("shadow acne").
+			bias: wgpu::DepthBiasState {
+				constant: 2,
+				slope_scale: 2.0,
+				clamp: 0.0,
+			},
+		}),
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
@@ -0,0 +1,178 @@
+//! Skinned meshes, whose vertices follow the bones of a skeleton.
+//!
+//! Each [`SkinnedVertex`] is moved by a weighted sum of up to four bone
+//! matrices (linear blend skinning), in `shader.wgsl`'s `vs_skinned`.
+//!
+//! [`SkinnedVertex`]: crate::vertex::SkinnedVertex
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{Matrix4, UnitQuaternion, Vector3};
+
+use crate::types::mat4_to_wgsl;
+
+/// How many bones a skeleton can have.
+pub const MAX_BONES: usize = 64;
+
+/// The binding of the skin uniform in its bind group. `shader.wgsl` declares the
+/// object uniforms at binding 0 of the same group.
+const SKIN_BINDING: u32 = 1;
+
+/// The layout of `shader.wgsl`'s `SkinUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SkinUniform {
+	/// Transform the vertices from the bind pose into world space.
+	bones: [[[f32; 4]; 4]; MAX_BONES],
+}
+
+/// The bone matrices of the skeleton, in a uniform buffer.
+pub(crate) struct Skin {
+	buf: wgpu::Buffer,
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+}
+impl Skin {
+	/// Creates a skeleton whose bones are all the identity.
+	pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+		let buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Skin Uniform"),
+			size: std::mem::size_of::<SkinUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Skin Bind Group Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: SKIN_BINDING,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("skin_bind_group"),
+			layout: &layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: SKIN_BINDING,
+				resource: buf.as_entire_binding(),
+			}],
+		});
+		let skin = Self {
+			buf,
+			layout,
+			bind_group,
+		};
+		skin.update(queue, &[Matrix4::identity(); MAX_BONES]);
+		skin
+	}
+
+	pub fn layout(&self) -> &wgpu::BindGroupLayout {
+		&self.layout
+	}
+
+	pub fn bind_group(&self) -> &wgpu::BindGroup {
+		&self.bind_group
+	}
+
+	/// Uploads `bones`, at most [`MAX_BONES`] of them, returning how many bytes
+	/// were written. The bones after them keep their matrices.
+	pub fn update(&self, queue: &wgpu::Queue, bones: &[Matrix4<f32>]) -> u64 {
+		let bones: Vec<_> = bones.iter().map(|&bone| mat4_to_wgsl(bone)).collect();
+		let bytes: &[u8] = bytemuck::cast_slice(&bones);
+		queue.write_buffer(&self.buf, 0, bytes);
+		bytes.len() as u64
+	}
+}
+
+/// A mesh of [`SkinnedVertex`]es on the GPU.
+///
+/// [`SkinnedVertex`]: crate::vertex::SkinnedVertex
+pub(crate) struct SkinnedMesh {
+	pub vtx_buf: wgpu::Buffer,
+	pub idx_buf: wgpu::Buffer,
+	pub num_indices: u32,
+}
+
+/// A bone of a [`Skeleton`], posed relative to its parent.
+#[derive(Clone, Debug)]
+pub struct Bone {
+	/// The index of the parent bone, which must come before this one, or `None`
+	/// for a root.
+	pub parent: Option<usize>,
+	/// The pose relative to the parent when an animation doesn't move the bone.
+	pub translation: Vector3<f32>,
+	pub rotation: UnitQuaternion<f32>,
+	pub scale: Vector3<f32>,
+	/// From the mesh's bind pose into the bone's space.
+	pub inverse_bind: Matrix4<f32>,
+}
+impl Bone {
+	/// The rest pose relative to the parent, from its translation, rotation and
+	/// scale.
+	pub fn local_transform(&self) -> Matrix4<f32> {
+		compose(self.translation, self.rotation, self.scale)
+	}
+}
+
+/// The hierarchy of bones an animation poses.
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+	bones: Vec<Bone>,
+}
+impl Skeleton {
+	/// # Panics
+	/// If there are more than [`MAX_BONES`] bones, or a bone comes before its
+	/// parent.
+	pub fn new(bones: Vec<Bone>) -> Self {
+		assert!(
+			bones.len() <= MAX_BONES,
+			"A skeleton can have at most {MAX_BONES} bones"
+		);
+		for (i, bone) in bones.iter().enumerate() {
+			assert!(
+				bone.parent.map_or(true, |parent| parent < i),
+				"Bone {i} comes before its parent"
+			);
+		}
+		Self { bones }
+	}
+
+	pub fn bones(&self) -> &[Bone] {
+		&self.bones
+	}
+
+	/// Combines the `local` transform of each bone with its parents', and the
+	/// result with its inverse bind matrix, for [`RenderState::update_skeleton`].
+	///
+	/// [`RenderState::update_skeleton`]: crate::render_state::RenderState::update_skeleton
+	pub fn skinning_matrices(&self, local: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+		assert_eq!(local.len(), self.bones.len(), "A transform per bone");
+		let mut world: Vec<Matrix4<f32>> = Vec::with_capacity(local.len());
+		for (bone, local) in self.bones.iter().zip(local) {
+			let transform = match bone.parent {
+				Some(parent) => world[parent] * local,
+				None => *local,
+			};
+			world.push(transform);
+		}
+		(world.iter().zip(&self.bones))
+			.map(|(world, bone)| world * bone.inverse_bind)
+			.collect()
+	}
+}
+
+/// The matrix scaling, then rotating, then translating.
+pub(crate) fn compose(
+	translation: Vector3<f32>,
+	rotation: UnitQuaternion<f32>,
+	scale: Vector3<f32>,
+) -> Matrix4<f32> {
+	Matrix4::new_translation(&translation)
+		* rotation.to_homogeneous()
+		* Matrix4::new_nonuniform_scaling(&scale)
+}
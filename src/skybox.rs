@@ -0,0 +1,277 @@
+//! Drawing a cubemap as the sky, or a [`ProceduralSky`] instead.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::cubemap::Cubemap;
+use crate::procedural_sky::ProceduralSky;
+use crate::types::mat4_to_wgsl;
+
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SkyUniform {
+	view: [[f32; 4]; 4],
+	proj: [[f32; 4]; 4],
+	/// The depth of the far plane.
+	far_depth: f32,
+	_pad: [f32; 3],
+}
+
+/// Draws a cubemap on a cube around the camera, at the far plane. Draw it before
+/// anything else, in the same render pass.
+pub struct SkyboxPipeline {
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	/// Whether the depth buffer is reverse-Z, with the far plane at 0.
+	reverse_z: bool,
+	uniform_buf: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+}
+impl SkyboxPipeline {
+	/// Creates a pipeline for render passes with the given color format, sample
+	/// count and depth format, which is reverse-Z if `reverse_z` is set.
+	pub fn new(
+		device: &wgpu::Device,
+		cubemap: &Cubemap,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Skybox Bind Group Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::VERTEX,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::Cube,
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: true,
+							},
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 2,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Sampler(
+							wgpu::SamplerBindingType::Filtering,
+						),
+						count: None,
+					},
+				],
+			});
+		let uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Skybox Uniform"),
+				contents: bytemuck::bytes_of(&SkyUniform {
+					view: mat4_to_wgsl(Matrix4::identity()),
+					proj: mat4_to_wgsl(Matrix4::identity()),
+					far_depth: 1.,
+					_pad: [0.; 3],
+				}),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("skybox_bind_group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(&cubemap.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+				},
+			],
+		});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Skybox Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl"));
+		let pipeline = create_pipeline(
+			device,
+			&layout,
+			&shader,
+			format,
+			sample_count,
+			depth_format,
+			reverse_z,
+		);
+		Self {
+			shader,
+			layout,
+			pipeline,
+			reverse_z,
+			uniform_buf,
+			bind_group,
+		}
+	}
+
+	/// Recreates the pipeline for render passes with different attachments.
+	pub fn set_format(
+		&mut self,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+	) {
+		self.pipeline = create_pipeline(
+			device,
+			&self.layout,
+			&self.shader,
+			format,
+			sample_count,
+			depth_format,
+			self.reverse_z,
+		);
+	}
+
+	/// Uploads the orientation and projection of `camera`, seen from `view`
+	/// rather than the camera's own view. Returns the number of bytes written.
+	pub fn update(
+		&self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			proj = reverse_z(&proj);
+			far_depth = 0.;
+		}
+		let uniform = SkyUniform {
+			view: mat4_to_wgsl(view.to_matrix()),
+			proj: mat4_to_wgsl(proj),
+			far_depth,
+			_pad: [0.; 3],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SkyUniform>() as u64
+	}
+
+	pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.draw(0..14, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+	sample_count: u32,
+	depth_format: wgpu::TextureFormat,
+	reverse_z: bool,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Skybox Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState {
+			topology: wgpu::PrimitiveTopology::TriangleStrip,
+			// The camera is inside the cube, so only back faces are visible.
+			cull_mode: None,
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: depth_format,
+			// The sky is at the far plane, where the depth buffer is cleared to, and
+			// must not hide anything drawn after it.
+			depth_write_enabled: false,
+			depth_compare: if reverse_z {
+				wgpu::CompareFunction::GreaterEqual
+			} else {
+				wgpu::CompareFunction::LessEqual
+			},
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState {
+			count: sample_count,
+			..Default::default()
+		},
+		multiview: None,
+	})
+}
+
+/// What is drawn behind everything else.
+pub(crate) enum Sky {
+	Cubemap(SkyboxPipeline),
+	Procedural(ProceduralSky),
+}
+impl Sky {
+	/// Recreates the pipeline for render passes with different attachments.
+	pub fn set_format(
+		&mut self,
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+		depth_format: wgpu::TextureFormat,
+	) {
+		match self {
+			Self::Cubemap(skybox) => {
+				skybox.set_format(device, format, sample_count, depth_format)
+			}
+			Self::Procedural(sky) => {
+				sky.set_format(device, format, sample_count, depth_format)
+			}
+		}
+	}
+
+	/// Uploads the orientation and projection of `camera`, seen from `view`.
+	/// Returns the number of bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		match self {
+			Self::Cubemap(skybox) => skybox.update(queue, camera, view),
+			Self::Procedural(sky) => sky.update(queue, camera, view),
+		}
+	}
+
+	pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+		match self {
+			Self::Cubemap(skybox) => skybox.draw(render_pass),
+			Self::Procedural(sky) => sky.draw(render_pass),
+		}
+	}
+}
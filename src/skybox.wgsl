@@ -0,0 +1,48 @@
+// Draws the sky from a cubemap, behind everything else.
+
+struct SkyUniform {
+	// The camera's view matrix. Only its rotation is used, so that the sky never
+	// moves with the camera.
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	// 1, or 0 with a reverse-Z depth buffer.
+	far_depth: f32,
+};
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+@group(0) @binding(1)
+var sky_t: texture_cube<f32>;
+@group(0) @binding(2)
+var sky_s: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	// The world space direction seen through this fragment.
+	@location(0) dir: vec3<f32>,
+};
+
+// Draw with 14 vertices as a triangle strip, with no vertex buffer. They form a
+// cube around the camera.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let corner = vec3<u32>(0x287au, 0x02afu, 0x31e3u) >> vec3<u32>(index);
+	let pos = vec3<f32>(corner & vec3<u32>(1u)) * 2.0 - 1.0;
+	// Without the translation column, only the rotation is left.
+	let view = mat4x4<f32>(
+		sky.view[0],
+		sky.view[1],
+		sky.view[2],
+		vec4<f32>(0.0, 0.0, 0.0, 1.0),
+	);
+	let clip_pos = sky.proj * view * vec4<f32>(pos, 1.0);
+	var out: VertexOutput;
+	// On the far plane, so that everything else is in front of it.
+	out.clip_pos = vec4<f32>(clip_pos.xy, sky.far_depth * clip_pos.w, clip_pos.w);
+	out.dir = pos;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	return textureSample(sky_t, sky_s, in.dir);
+}
@@ -0,0 +1,43 @@
+//! Saving the state of a scene, to restore it in a later session.
+
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+
+/// The pose of the camera and the transforms of the objects, see
+/// [`RenderState::save_snapshot`].
+///
+/// [`RenderState::save_snapshot`]: crate::render_state::RenderState::save_snapshot
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SceneSnapshot {
+	pub camera: Camera,
+	/// In the order the objects were added.
+	pub object_transforms: Vec<Matrix4<f32>>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::{point, Orthographic3, Vector3};
+
+	#[test]
+	fn json_round_trip() {
+		let proj = Orthographic3::new(-2., 2., -1., 1., 0.1, 50.);
+		let snapshot = SceneSnapshot {
+			camera: Camera::new(point![1., 2., 3.], 0.5, -0.25, proj),
+			object_transforms: vec![
+				Matrix4::new_translation(&Vector3::new(4., 5., 6.)),
+				Matrix4::new_scaling(2.),
+			],
+		};
+		let json = serde_json::to_string(&snapshot).unwrap();
+		let loaded: SceneSnapshot = serde_json::from_str(&json).unwrap();
+		assert_eq!(loaded.object_transforms, snapshot.object_transforms);
+		let (a, b) = (&loaded.camera, &snapshot.camera);
+		assert_eq!((a.position, a.yaw, a.pitch), (b.position, b.yaw, b.pitch));
+		assert_eq!(a.view, b.view);
+		assert_eq!(a.proj.as_matrix(), b.proj.as_matrix());
+		assert_eq!(a.proj_view(), b.proj_view());
+	}
+}
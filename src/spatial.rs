@@ -0,0 +1,170 @@
+//! Finding the objects near a point or in view, without going through all of
+//! them.
+
+use nalgebra::{Point3, Vector3};
+use rustc_hash::FxHashMap;
+
+use crate::aabb::Aabb;
+use crate::camera::Frustum;
+
+/// The first and last cells on each axis that a box overlaps.
+type CellRange = ([i32; 3], [i32; 3]);
+
+/// Objects bucketed by the cubic cells of a grid their boxes overlap, with only
+/// the cells that hold objects stored. Queries only look at the objects in the
+/// cells they overlap.
+#[derive(Clone, Debug)]
+pub struct SpatialHash {
+	cell_size: f32,
+	/// The objects overlapping each cell.
+	cells: FxHashMap<(i32, i32, i32), Vec<usize>>,
+	/// The box of every object, and the cells it is in.
+	objects: FxHashMap<usize, (Aabb, CellRange)>,
+}
+impl SpatialHash {
+	/// Creates an empty grid of cells `cell_size` wide. Cells about the size of
+	/// the objects keep them in few cells each, without many in a single cell.
+	///
+	/// # Panics
+	/// If `cell_size` isn't positive.
+	pub fn new(cell_size: f32) -> Self {
+		assert!(cell_size > 0., "Cells must have a size");
+		Self {
+			cell_size,
+			cells: FxHashMap::default(),
+			objects: FxHashMap::default(),
+		}
+	}
+
+	pub fn cell_size(&self) -> f32 {
+		self.cell_size
+	}
+
+	pub fn len(&self) -> usize {
+		self.objects.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.objects.is_empty()
+	}
+
+	/// Adds the object `id`, bounded by `aabb`, replacing its box if it was
+	/// already added.
+	pub fn insert(&mut self, id: usize, aabb: &Aabb) {
+		self.remove(id);
+		let range = self.cell_range(&aabb.min, &aabb.max);
+		for cell in cells_in(range) {
+			self.cells.entry(cell).or_default().push(id);
+		}
+		self.objects.insert(id, (*aabb, range));
+	}
+
+	/// Removes the object `id`, if it was added.
+	pub fn remove(&mut self, id: usize) {
+		let Some((_, range)) = self.objects.remove(&id) else {
+			return;
+		};
+		for cell in cells_in(range) {
+			if let Some(ids) = self.cells.get_mut(&cell) {
+				ids.retain(|&other| other != id);
+				if ids.is_empty() {
+					self.cells.remove(&cell);
+				}
+			}
+		}
+	}
+
+	/// Removes every object, keeping the allocations for those added next.
+	pub fn clear(&mut self) {
+		self.cells.clear();
+		self.objects.clear();
+	}
+
+	/// The objects whose boxes are within `radius` of `center`, by increasing id.
+	pub fn query_sphere(&self, center: Point3<f32>, radius: f32) -> Vec<usize> {
+		let extent = Vector3::repeat(radius);
+		let range = self.cell_range(&(center - extent), &(center + extent));
+		self.query(range, |aabb| aabb.intersects_sphere(&center, radius))
+	}
+
+	/// The objects whose boxes may be inside `frustum`, by increasing id. Only the
+	/// cells overlapping the box around the frustum are looked at.
+	pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+		let bounds = Aabb::from_points(&frustum.corners());
+		let range = self.cell_range(&bounds.min, &bounds.max);
+		self.query(range, |aabb| aabb.intersects_frustum(frustum))
+	}
+
+	/// The cells `min` and `max` are in.
+	fn cell_range(&self, min: &Point3<f32>, max: &Point3<f32>) -> CellRange {
+		let cell = |p: &Point3<f32>| p.map(|x| (x / self.cell_size).floor() as i32);
+		(cell(min).into(), cell(max).into())
+	}
+
+	/// The objects in the cells of `range` whose boxes pass `test`, without
+	/// duplicates.
+	fn query(&self, range: CellRange, test: impl Fn(&Aabb) -> bool) -> Vec<usize> {
+		let (min, max) = range;
+		let num_cells = (0..3).fold(1_u64, |n, axis| {
+			n.saturating_mul((i64::from(max[axis]) - i64::from(min[axis]) + 1) as u64)
+		});
+		let mut ids: Vec<usize> = if num_cells > self.cells.len() as u64 {
+			// Checking every stored cell is faster for ranges with more cells, such
+			// as that of a deep frustum.
+			(self.cells.iter())
+				.filter(|(&(x, y, z), _)| {
+					let cell = [x, y, z];
+					(0..3)
+						.all(|axis| min[axis] <= cell[axis] && cell[axis] <= max[axis])
+				})
+				.flat_map(|(_, ids)| ids)
+				.copied()
+				.collect()
+		} else {
+			cells_in(range)
+				.filter_map(|cell| self.cells.get(&cell))
+				.flatten()
+				.copied()
+				.collect()
+		};
+		ids.sort_unstable();
+		ids.dedup();
+		ids.retain(|id| test(&self.objects[id].0));
+		ids
+	}
+}
+
+/// Every cell of `range`.
+fn cells_in(range: CellRange) -> impl Iterator<Item = (i32, i32, i32)> {
+	let (min, max) = range;
+	(min[0]..=max[0]).flat_map(move |x| {
+		(min[1]..=max[1]).flat_map(move |y| (min[2]..=max[2]).map(move |z| (x, y, z)))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::point;
+
+	fn unit_box(x: f32) -> Aabb {
+		Aabb::new(point![x, 0., 0.], point![x + 1., 1., 1.])
+	}
+
+	#[test]
+	fn query_sphere() {
+		let mut hash = SpatialHash::new(2.);
+		for (id, x) in [(3, 0.), (1, 3.), (2, 10.), (0, -5.)] {
+			hash.insert(id, &unit_box(x));
+		}
+		assert_eq!(hash.query_sphere(point![2., 0.5, 0.5], 1.5), [1, 3]);
+		// Spans more cells than are stored.
+		assert_eq!(hash.query_sphere(point![-10., 0.5, 0.5], 5.5), [0]);
+		assert!(hash.query_sphere(point![6., 0.5, 0.5], 1.).is_empty());
+
+		hash.insert(2, &unit_box(6.));
+		hash.remove(1);
+		assert_eq!(hash.query_sphere(point![5., 0.5, 0.5], 2.5), [2]);
+		assert_eq!(hash.len(), 3);
+	}
+}
@@ -0,0 +1,66 @@
+//! Sound sources attached to entities, panned and attenuated by where they are
+//! relative to the camera. The sound itself is left to an [`AudioBackend`].
+
+use nalgebra::Point3;
+
+use crate::camera::CameraLike;
+use crate::ecs::{Transform, World};
+
+/// Plays the sound sources of [`SoundEmitter`]s, such as with an audio library.
+pub trait AudioBackend {
+	/// Sets the volume of the source `source_id`, from 0, silent, to 1.
+	fn set_source_gain(&mut self, source_id: u64, gain: f32);
+	/// Sets the stereo balance of the source `source_id`, from -1, fully left, to
+	/// 1, fully right.
+	fn set_source_pan(&mut self, source_id: u64, pan: f32);
+}
+
+/// A component making an entity emit the sound of an [`AudioBackend`]'s source.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SoundEmitter {
+	/// The source of the backend playing the sound.
+	pub source_id: u64,
+	/// Where the sound comes from, moved by the entity's [`Transform`] if it has
+	/// one.
+	pub position: Point3<f32>,
+	/// The distance from the camera past which the sound can't be heard. It
+	/// fades out linearly up to there.
+	pub max_distance: f32,
+}
+impl SoundEmitter {
+	/// The gain and pan of the emitter at `position`, in the view space of a
+	/// camera.
+	fn gain_and_pan(&self, position: Point3<f32>) -> (f32, f32) {
+		let distance = position.coords.norm();
+		let gain = 1. - (distance / self.max_distance).clamp(0., 1.);
+		// A sound at the camera comes from both sides.
+		let pan = if distance > f32::EPSILON {
+			position.x / distance
+		} else {
+			0.
+		};
+		(gain, pan)
+	}
+}
+
+/// Sets the gain of the source of every [`SoundEmitter`] by its distance from
+/// `camera`, and its pan by how far it is to the camera's left or right.
+pub fn spatial_audio_system(
+	camera: &dyn CameraLike,
+	world: &World,
+	audio: &mut dyn AudioBackend,
+) {
+	let view = camera.view();
+	for entity in world.entities() {
+		let Some(emitter) = world.get::<SoundEmitter>(entity) else {
+			continue;
+		};
+		let position = match world.get::<Transform>(entity) {
+			Some(Transform(transform)) => transform.transform_point(&emitter.position),
+			None => emitter.position,
+		};
+		let (gain, pan) = emitter.gain_and_pan(view * position);
+		audio.set_source_gain(emitter.source_id, gain);
+		audio.set_source_pan(emitter.source_id, pan);
+	}
+}
@@ -0,0 +1,429 @@
+//! Screen-space ambient occlusion: creases and corners are darkened by how much
+//! of the sky above them nearby surfaces hide.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Point3, Vector3};
+use rand::{Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::types::mat4_to_wgsl;
+
+/// How strong the ambient occlusion is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SsaoSettings {
+	/// How far away surfaces occlude each other, in world units.
+	pub radius: f32,
+	/// Keeps surfaces from occluding themselves where the depth buffer's
+	/// precision runs out, in world units.
+	pub bias: f32,
+	/// Scales the occlusion, where 1 fully darkens surfaces hidden on all sides.
+	pub intensity: f32,
+	/// How many points of the hemisphere are tested per pixel. At most
+	/// [`SsaoPass::MAX_SAMPLES`].
+	pub samples: u32,
+}
+impl Default for SsaoSettings {
+	fn default() -> Self {
+		Self {
+			radius: 0.5,
+			bias: 0.025,
+			intensity: 1.0,
+			samples: 32,
+		}
+	}
+}
+
+/// The layout of the shader's `SsaoUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SsaoUniform {
+	view_proj: [[f32; 4]; 4],
+	inv_view_proj: [[f32; 4]; 4],
+	eye: [f32; 3],
+	far_depth: f32,
+	radius: f32,
+	bias: f32,
+	intensity: f32,
+	samples: u32,
+}
+
+/// Estimates how occluded each pixel is from the depth and normals of the
+/// opaque geometry in a [`GBuffer`](crate::gbuffer::GBuffer).
+///
+/// The occlusion is estimated into one texture from points in the hemisphere
+/// above each pixel, rotated randomly in a tiling 4x4 pattern, and the pattern
+/// is then blurred away into the output.
+pub struct SsaoPass {
+	settings: SsaoSettings,
+	/// The camera's matrices, for `uniform_buf`.
+	camera: SsaoUniform,
+	uniform_buf: wgpu::Buffer,
+	/// The points of the hemisphere, see `ssao.wgsl`.
+	kernel_buf: wgpu::Buffer,
+	/// Random vectors, rotating the hemisphere around the normals.
+	noise_view: wgpu::TextureView,
+	reverse_z: bool,
+	/// The occlusion before it is blurred.
+	raw_view: wgpu::TextureView,
+	raw_bind_group: wgpu::BindGroup,
+	/// The blurred occlusion, sampled by the scene.
+	occlusion_view: wgpu::TextureView,
+	input_layout: wgpu::BindGroupLayout,
+	blur_layout: wgpu::BindGroupLayout,
+	/// Bound to the first group of the blur.
+	empty_bind_group: wgpu::BindGroup,
+	ssao_pipeline: wgpu::RenderPipeline,
+	blur_pipeline: wgpu::RenderPipeline,
+}
+impl SsaoPass {
+	pub const MAX_SAMPLES: u32 = 64;
+	const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+	const NOISE_SIZE: u32 = 4;
+
+	/// Creates a pass for `width` x `height` frames, with a depth buffer that is
+	/// optionally reversed.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		width: u32,
+		height: u32,
+		reverse_z: bool,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("SSAO Uniform"),
+			size: std::mem::size_of::<SsaoUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		// Seeded, so that the pattern is the same every run.
+		let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+		let kernel: Vec<[f32; 4]> = (0..Self::MAX_SAMPLES)
+			.map(|i| {
+				let direction = Vector3::new(
+					rng.gen_range(-1.0..1.0),
+					rng.gen_range(-1.0..1.0),
+					rng.gen_range(0.0..1.0),
+				)
+				.try_normalize(f32::EPSILON)
+				.unwrap_or_else(Vector3::z);
+				// More points close to the pixel, where occluders matter most.
+				let t = i as f32 / Self::MAX_SAMPLES as f32;
+				let scale = 0.1 + 0.9 * t * t;
+				let point = direction * rng.gen_range(0.0..1.0f32) * scale;
+				[point.x, point.y, point.z, 0.]
+			})
+			.collect();
+		let kernel_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("SSAO Kernel"),
+			contents: bytemuck::cast_slice(&kernel),
+			usage: wgpu::BufferUsages::UNIFORM,
+		});
+		let noise: Vec<u8> = (0..Self::NOISE_SIZE * Self::NOISE_SIZE)
+			.flat_map(|_| [rng.gen(), rng.gen(), 128, 255])
+			.collect();
+		let noise_view = device
+			.create_texture_with_data(
+				queue,
+				&wgpu::TextureDescriptor {
+					label: Some("SSAO Noise"),
+					size: wgpu::Extent3d {
+						width: Self::NOISE_SIZE,
+						height: Self::NOISE_SIZE,
+						depth_or_array_layers: 1,
+					},
+					mip_level_count: 1,
+					sample_count: 1,
+					dimension: wgpu::TextureDimension::D2,
+					format: wgpu::TextureFormat::Rgba8Unorm,
+					usage: wgpu::TextureUsages::TEXTURE_BINDING
+						| wgpu::TextureUsages::COPY_DST,
+					view_formats: &[],
+				},
+				&noise,
+			)
+			.create_view(&wgpu::TextureViewDescriptor::default());
+
+		let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type,
+			},
+			count: None,
+		};
+		// The textures are read with `textureLoad`, so they need no samplers.
+		let float = wgpu::TextureSampleType::Float { filterable: false };
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("SSAO Input Layout"),
+				entries: &[
+					uniform_entry(0),
+					uniform_entry(1),
+					texture_entry(2, float),
+					texture_entry(3, float),
+					texture_entry(4, float),
+				],
+			});
+		let blur_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("SSAO Blur Layout"),
+				entries: &[texture_entry(0, float)],
+			});
+		let empty_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Empty Bind Group Layout"),
+				entries: &[],
+			});
+		let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("empty_bind_group"),
+			layout: &empty_layout,
+			entries: &[],
+		});
+
+		let ssao_shader = device.create_shader_module(wgpu::include_wgsl!("ssao.wgsl"));
+		let pipeline = |label, entry_point, bind_group_layouts: &[_]| {
+			let layout =
+				device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+					label: Some(label),
+					bind_group_layouts,
+					push_constant_ranges: &[],
+				});
+			device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: Some(label),
+				layout: Some(&layout),
+				vertex: wgpu::VertexState {
+					module: &ssao_shader,
+					entry_point: "vs_main",
+					buffers: &[],
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: &ssao_shader,
+					entry_point,
+					targets: &[Some(Self::OCCLUSION_FORMAT.into())],
+				}),
+				primitive: wgpu::PrimitiveState::default(),
+				depth_stencil: None,
+				multisample: wgpu::MultisampleState::default(),
+				multiview: None,
+			})
+		};
+		let ssao_pipeline = pipeline("SSAO Pipeline", "fs_ssao", &[&input_layout]);
+		let blur_pipeline = pipeline(
+			"SSAO Blur Pipeline",
+			"fs_blur",
+			&[&empty_layout, &blur_layout],
+		);
+
+		let targets = Targets::new(device, &blur_layout, width, height);
+		let mut result = Self {
+			settings: SsaoSettings::default(),
+			camera: SsaoUniform::zeroed(),
+			uniform_buf,
+			kernel_buf,
+			noise_view,
+			reverse_z,
+			raw_view: targets.raw_view,
+			raw_bind_group: targets.raw_bind_group,
+			occlusion_view: targets.occlusion_view,
+			input_layout,
+			blur_layout,
+			empty_bind_group,
+			ssao_pipeline,
+			blur_pipeline,
+		};
+		result.set_settings(queue, SsaoSettings::default());
+		result
+	}
+
+	pub fn settings(&self) -> SsaoSettings {
+		self.settings
+	}
+
+	pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: SsaoSettings) {
+		self.settings = settings;
+		self.upload(queue);
+	}
+
+	/// Uploads the matrices of `camera` seen from `view`. Returns the number of
+	/// bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut view_proj = camera.proj_view_from(view);
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			view_proj = reverse_z(&view_proj);
+			far_depth = 0.;
+		}
+		let inv_view_proj = view_proj.try_inverse().unwrap_or_default();
+		let eye = view.inverse_transform_point(&Point3::origin());
+		self.camera.view_proj = mat4_to_wgsl(view_proj);
+		self.camera.inv_view_proj = mat4_to_wgsl(inv_view_proj);
+		self.camera.eye = eye.into();
+		self.camera.far_depth = far_depth;
+		self.upload(queue)
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		let uniform = SsaoUniform {
+			radius: self.settings.radius,
+			bias: self.settings.bias,
+			intensity: self.settings.intensity,
+			samples: self.settings.samples.min(Self::MAX_SAMPLES),
+			..self.camera
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SsaoUniform>() as u64
+	}
+
+	/// Recreates the textures for `width` x `height` frames. Bind groups
+	/// sampling [`Self::occlusion_view`] must be recreated too.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		let targets = Targets::new(device, &self.blur_layout, width, height);
+		self.raw_view = targets.raw_view;
+		self.raw_bind_group = targets.raw_bind_group;
+		self.occlusion_view = targets.occlusion_view;
+	}
+
+	/// The blurred occlusion, 1 where nothing is occluded.
+	pub fn occlusion_view(&self) -> &wgpu::TextureView {
+		&self.occlusion_view
+	}
+
+	/// Records estimating the occlusion from `depth_view` and `normal_view` and
+	/// blurring it into all of `output_view`, which must be an `R8Unorm` texture.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		normal_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("ssao_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: self.kernel_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(normal_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: wgpu::BindingResource::TextureView(&self.noise_view),
+				},
+			],
+		});
+		let passes = [
+			(
+				"SSAO Pass",
+				&self.ssao_pipeline,
+				&self.raw_view,
+				&[&input][..],
+			),
+			(
+				"SSAO Blur Pass",
+				&self.blur_pipeline,
+				output_view,
+				&[&self.empty_bind_group, &self.raw_bind_group],
+			),
+		];
+		for (label, pipeline, view, bind_groups) in passes {
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some(label),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						// Every pixel is drawn over.
+						load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: None,
+			});
+			pass.set_pipeline(pipeline);
+			for (i, bind_group) in bind_groups.iter().enumerate() {
+				pass.set_bind_group(i as u32, bind_group, &[]);
+			}
+			pass.draw(0..3, 0..1);
+		}
+	}
+}
+
+/// The textures of an [`SsaoPass`] sized to the frame.
+struct Targets {
+	raw_view: wgpu::TextureView,
+	raw_bind_group: wgpu::BindGroup,
+	occlusion_view: wgpu::TextureView,
+}
+impl Targets {
+	fn new(
+		device: &wgpu::Device,
+		blur_layout: &wgpu::BindGroupLayout,
+		width: u32,
+		height: u32,
+	) -> Self {
+		let view = |label, format| {
+			device
+				.create_texture(&wgpu::TextureDescriptor {
+					label: Some(label),
+					size: wgpu::Extent3d {
+						width,
+						height,
+						depth_or_array_layers: 1,
+					},
+					mip_level_count: 1,
+					sample_count: 1,
+					dimension: wgpu::TextureDimension::D2,
+					format,
+					usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+						| wgpu::TextureUsages::TEXTURE_BINDING,
+					view_formats: &[],
+				})
+				.create_view(&wgpu::TextureViewDescriptor::default())
+		};
+		let raw_view = view("SSAO Raw", SsaoPass::OCCLUSION_FORMAT);
+		let raw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("ssao_raw_bind_group"),
+			layout: blur_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::TextureView(&raw_view),
+			}],
+		});
+		Self {
+			raw_view,
+			raw_bind_group,
+			occlusion_view: view("SSAO Occlusion", SsaoPass::OCCLUSION_FORMAT),
+		}
+	}
+}
@@ -0,0 +1,115 @@
+// Screen-space ambient occlusion: pixels are darkened by how much of the
+// hemisphere above them nearby surfaces hide.
+//
+// `fs_ssao` tests points of the hemisphere against the depth of the geometry
+// pass, and `fs_blur` averages the result over the 4x4 tiles of the noise that
+// rotates the hemisphere.
+
+struct SsaoUniform {
+	view_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	// The camera's position, in world space.
+	eye: vec3<f32>,
+	// The depth the buffer is cleared to, where nothing was drawn.
+	far_depth: f32,
+	radius: f32,
+	bias: f32,
+	intensity: f32,
+	samples: u32,
+};
+@group(0) @binding(0)
+var<uniform> ssao: SsaoUniform;
+
+// Points in the hemisphere around +z, further out the higher their index.
+struct Kernel {
+	points: array<vec4<f32>, 64>,
+};
+@group(0) @binding(1)
+var<uniform> kernel: Kernel;
+
+// The depth, in the red channel.
+@group(0) @binding(2)
+var depth_t: texture_2d<f32>;
+// World space normals, mapped to 0 to 1.
+@group(0) @binding(3)
+var normal_t: texture_2d<f32>;
+// Random directions in the xy plane, mapped to 0 to 1.
+@group(0) @binding(4)
+var noise_t: texture_2d<f32>;
+
+// The occlusion to blur, only used by `fs_blur`.
+@group(1) @binding(0)
+var occlusion_t: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+// The world space position drawn at the pixel `coords`, at `depth`.
+fn world_pos(coords: vec2<i32>, depth: f32) -> vec3<f32> {
+	let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(textureDimensions(depth_t, 0));
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = ssao.inv_view_proj * ndc;
+	return world.xyz / world.w;
+}
+
+@fragment
+fn fs_ssao(in: VertexOutput) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.clip_pos.xy);
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth == ssao.far_depth {
+		return vec4<f32>(1.0);
+	}
+	let pos = world_pos(coords, depth);
+	let distance = length(pos - ssao.eye);
+	let n = normalize(textureLoad(normal_t, coords, 0).xyz * 2.0 - 1.0);
+	let random = textureLoad(noise_t, coords % vec2<i32>(4), 0).xyz * 2.0 - 1.0;
+	// The random direction, made perpendicular to the normal.
+	let tangent = normalize(random - n * dot(random, n));
+	let tbn = mat3x3<f32>(tangent, cross(n, tangent), n);
+
+	let size = vec2<i32>(textureDimensions(depth_t, 0));
+	let sample_count = min(ssao.samples, 64u);
+	var occlusion = 0.0;
+	for (var i = 0u; i < sample_count; i += 1u) {
+		let point = pos + tbn * kernel.points[i].xyz * ssao.radius;
+		let clip = ssao.view_proj * vec4<f32>(point, 1.0);
+		let ndc = clip.xy / clip.w;
+		let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+		let point_coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+		let occluder = world_pos(point_coords, textureLoad(depth_t, point_coords, 0).r);
+		let occluder_distance = length(occluder - ssao.eye);
+		// Surfaces far in front of the pixel don't occlude it.
+		let in_range = smoothstep(0.0, 1.0, ssao.radius / abs(distance - occluder_distance));
+		if occluder_distance < length(point - ssao.eye) - ssao.bias {
+			occlusion += in_range;
+		}
+	}
+	let visibility = 1.0 - occlusion / f32(max(sample_count, 1u)) * ssao.intensity;
+	return vec4<f32>(clamp(visibility, 0.0, 1.0));
+}
+
+// Averages the 4x4 pixels around each pixel, one tile of the noise.
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.clip_pos.xy);
+	let size = vec2<i32>(textureDimensions(occlusion_t, 0));
+	var sum = 0.0;
+	for (var y = -2; y < 2; y += 1) {
+		for (var x = -2; x < 2; x += 1) {
+			let offset = clamp(coords + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+			sum += textureLoad(occlusion_t, offset, 0).r;
+		}
+	}
+	return vec4<f32>(sum / 16.0);
+}
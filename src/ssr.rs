@@ -0,0 +1,230 @@
+//! Screen-space reflections: surfaces reflect what is drawn around them on
+//! screen.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::IsometryMatrix3;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::types::mat4_to_wgsl;
+
+/// The layout of the shader's `SsrUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SsrUniform {
+	view: [[f32; 4]; 4],
+	proj: [[f32; 4]; 4],
+	inv_proj: [[f32; 4]; 4],
+	far_depth: f32,
+	max_steps: u32,
+	step_size: f32,
+	thickness: f32,
+	reflectivity: f32,
+	_pad: [f32; 3],
+}
+
+/// Blends the reflections of the frame's colors over it, from the depth and
+/// normals of a [`GBuffer`](crate::gbuffer::GBuffer).
+///
+/// The reflected ray of each pixel is marched in view space until it passes
+/// behind the depth buffer. The color found there is blended in by Schlick's
+/// approximation of the Fresnel factor, and fades out towards the edges of the
+/// screen. Rays that leave the screen or pass behind a surface reflect nothing.
+///
+/// The public fields are uploaded by [`Self::update`].
+pub struct SsrPass {
+	/// How many steps a ray is marched for, at most.
+	pub max_steps: u32,
+	/// How far each step marches the ray, in world units.
+	pub step_size: f32,
+	/// How far behind the depth buffer a ray may pass and still hit the surface
+	/// drawn there, in world units.
+	pub thickness: f32,
+	/// The share of the reflection blended in where the surface faces the
+	/// camera. Grazing angles reflect more.
+	pub reflectivity: f32,
+	/// The camera's matrices, for `uniform_buf`.
+	camera: SsrUniform,
+	uniform_buf: wgpu::Buffer,
+	input_layout: wgpu::BindGroupLayout,
+	reverse_z: bool,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	pipeline: wgpu::RenderPipeline,
+}
+impl SsrPass {
+	/// Creates a pass drawing into `format` textures, with a depth buffer that is
+	/// optionally reversed.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("SSR Uniform"),
+			size: std::mem::size_of::<SsrUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: false },
+			},
+			count: None,
+		};
+		// The textures are read with `textureLoad`, so they need no samplers.
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("SSR Input Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					texture_entry(1),
+					texture_entry(2),
+					texture_entry(3),
+				],
+			});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("SSR Pipeline Layout"),
+			bind_group_layouts: &[&input_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("ssr.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		Self {
+			max_steps: 64,
+			step_size: 0.1,
+			thickness: 0.2,
+			reflectivity: 0.2,
+			camera: SsrUniform::zeroed(),
+			uniform_buf,
+			input_layout,
+			reverse_z,
+			layout,
+			shader,
+			pipeline,
+		}
+	}
+
+	/// Recreates the pipeline to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Uploads the matrices of `camera` seen from `view`, and the public fields.
+	/// Returns the number of bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut proj = camera.proj_view_from(&IsometryMatrix3::identity());
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			proj = reverse_z(&proj);
+			far_depth = 0.;
+		}
+		self.camera.view = mat4_to_wgsl(view.to_homogeneous());
+		self.camera.proj = mat4_to_wgsl(proj);
+		self.camera.inv_proj = mat4_to_wgsl(proj.try_inverse().unwrap_or_default());
+		self.camera.far_depth = far_depth;
+		let uniform = SsrUniform {
+			max_steps: self.max_steps,
+			step_size: self.step_size,
+			thickness: self.thickness,
+			reflectivity: self.reflectivity,
+			..self.camera
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SsrUniform>() as u64
+	}
+
+	/// Records blending the reflections of `color_view` over it into all of
+	/// `output_view`, from the G-buffer's `normal_view` and `depth_view`.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		normal_view: &wgpu::TextureView,
+		depth_view: &wgpu::TextureView,
+		color_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("ssr_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(normal_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(color_view),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("SSR Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("SSR Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
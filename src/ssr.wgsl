@@ -0,0 +1,108 @@
+// Screen-space reflections: surfaces reflect the frame's colors, where the
+// reflected ray hits something on screen.
+//
+// The ray is marched in view space from each pixel of the G-buffer, projecting
+// every step onto the screen, until it passes behind the depth drawn there.
+
+struct SsrUniform {
+	// Transforms world space into view space.
+	view: mat4x4<f32>,
+	// Transforms view space into clip space.
+	proj: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	// The depth of the G-buffer where nothing was drawn.
+	far_depth: f32,
+	max_steps: u32,
+	step_size: f32,
+	thickness: f32,
+	reflectivity: f32,
+};
+@group(0) @binding(0)
+var<uniform> ssr: SsrUniform;
+
+// The depth, in the red channel.
+@group(0) @binding(1)
+var depth_t: texture_2d<f32>;
+// World space normals, mapped to 0 to 1.
+@group(0) @binding(2)
+var normal_t: texture_2d<f32>;
+// The frame before the reflections.
+@group(0) @binding(3)
+var color_t: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+// The view space position drawn at `uv`, at `depth`.
+fn view_pos(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let view = ssr.inv_proj * ndc;
+	return view.xyz / view.w;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.clip_pos.xy);
+	let color = textureLoad(color_t, coords, 0);
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth == ssr.far_depth {
+		return color;
+	}
+	let size = vec2<f32>(textureDimensions(depth_t, 0));
+	let pos = view_pos((vec2<f32>(coords) + 0.5) / size, depth);
+	let world_normal = textureLoad(normal_t, coords, 0).xyz * 2.0 - 1.0;
+	let n = normalize((ssr.view * vec4<f32>(world_normal, 0.0)).xyz);
+	// The camera is at the origin of view space.
+	let to_pos = normalize(pos);
+	let dir = reflect(to_pos, n);
+
+	// The reflected color, with how much of it is shown in alpha.
+	var hit = vec4<f32>(0.0);
+	for (var i = 1u; i <= ssr.max_steps; i += 1u) {
+		let point = pos + dir * ssr.step_size * f32(i);
+		// Behind the camera, nothing can be hit.
+		if point.z >= 0.0 {
+			break;
+		}
+		let clip = ssr.proj * vec4<f32>(point, 1.0);
+		let ndc = clip.xy / clip.w;
+		let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+		if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) {
+			break;
+		}
+		let hit_coords = vec2<i32>(uv * size);
+		let hit_depth = textureLoad(depth_t, hit_coords, 0).r;
+		if hit_depth == ssr.far_depth {
+			continue;
+		}
+		// How far the ray passed behind the surface drawn there. Further than
+		// the surface's thickness, the ray went behind it rather than into it.
+		let behind = view_pos(uv, hit_depth).z - point.z;
+		if behind > 0.0 {
+			if behind < ssr.thickness {
+				// Fades out towards the edges of the screen, where reflections
+				// are cut off.
+				let edge = min(uv, 1.0 - uv);
+				let fade = clamp(min(edge.x, edge.y) * 10.0, 0.0, 1.0);
+				hit = vec4<f32>(textureLoad(color_t, hit_coords, 0).rgb, fade);
+			}
+			break;
+		}
+	}
+
+	// Schlick's approximation: surfaces reflect more at grazing angles.
+	let cos_theta = max(dot(-to_pos, n), 0.0);
+	let fresnel = ssr.reflectivity + (1.0 - ssr.reflectivity) * pow(1.0 - cos_theta, 5.0);
+	return vec4<f32>(mix(color.rgb, hit.rgb, hit.a * fresnel), color.a);
+}
@@ -0,0 +1,463 @@
+//! Decals masked by the stencil buffer to the surfaces within their boxes, see
+//! `stencil_decal.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Point3, Vector3};
+use rustc_hash::FxHashMap;
+use wgpu::util::DeviceExt;
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::mesh::generate_cube;
+use crate::resources::{Handle, ResourceManager};
+use crate::tex2d::Tex2d;
+use crate::types::mat4_to_wgsl;
+
+/// The layout of `stencil_decal.wgsl`'s `DecalCamera`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DecalCamera {
+	proj_view: [[f32; 4]; 4],
+	inv_proj_view: [[f32; 4]; 4],
+}
+
+/// The box of a decal, read from the instance buffer.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DecalInstance {
+	/// Scales and moves a unit cube centered on the origin over the box.
+	model: [[f32; 4]; 4],
+	inv_model: [[f32; 4]; 4],
+}
+impl DecalInstance {
+	const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
+		// A matrix doesn't fit in one attribute, so it is passed as its columns.
+		const ATTRIBS: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+			1 => Float32x4, 2 => Float32x4, 3 => Float32x4, 4 => Float32x4,
+			5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4
+		];
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<DecalInstance>() as _,
+			step_mode: wgpu::VertexStepMode::Instance,
+			attributes: &ATTRIBS,
+		}
+	}
+}
+
+/// A decal waiting to be drawn.
+struct Decal {
+	transform: Matrix4<f32>,
+	texture: Handle<Tex2d>,
+}
+
+/// Projects textures onto the scene within boxes, like
+/// [`DecalRenderer`](crate::decal::DecalRenderer), but finds the surfaces within
+/// each box with the depth test rather than by reading the depth buffer, which
+/// stays attached.
+///
+/// Each decal is drawn twice. The first draw depth tests both sides of its box
+/// against the scene, and flips the top bit of the stencil where they fail: the
+/// same as incrementing on back faces and decrementing on front faces, without
+/// changing the scene's other bits. The bit is left set only where a surface is
+/// between the two sides. The second draw covers the box's back faces, colors
+/// those pixels only, and clears the bit again.
+///
+/// The texture is placed by the surface's position, reconstructed from the
+/// depth that the [`GBuffer`](crate::gbuffer::GBuffer) keeps in a color texture.
+pub struct StencilDecalRenderer {
+	decals: Vec<Decal>,
+	/// The positions and `u16` indices of a unit cube centered on the origin.
+	cube_vtx_buf: wgpu::Buffer,
+	cube_idx_buf: wgpu::Buffer,
+	num_cube_indices: u32,
+	camera_buf: wgpu::Buffer,
+	/// Binds `camera_buf` and the G-buffer's depth.
+	input_layout: wgpu::BindGroupLayout,
+	texture_layout: wgpu::BindGroupLayout,
+	/// Holds `capacity` instances.
+	instance_buf: wgpu::Buffer,
+	capacity: usize,
+	depth_format: wgpu::TextureFormat,
+	reverse_z: bool,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	/// Flips the stencil bit.
+	mask_pipeline: wgpu::RenderPipeline,
+	/// Draws the decal where the bit is set, and clears it.
+	draw_pipeline: wgpu::RenderPipeline,
+}
+impl StencilDecalRenderer {
+	/// The stencil bit marking the surfaces within the box being drawn.
+	const STENCIL_BIT: u32 = 0x80;
+
+	/// Creates a renderer drawing into `format` textures, over a `depth_format`
+	/// buffer with a stencil, that is optionally reversed.
+	///
+	/// # Panics
+	/// If `depth_format` has no stencil.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		depth_format: wgpu::TextureFormat,
+		reverse_z: bool,
+	) -> Self {
+		assert!(
+			depth_format.has_stencil_aspect(),
+			"Stencil decals need a stencil buffer"
+		);
+		let camera_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Stencil Decal Camera"),
+			size: std::mem::size_of::<DecalCamera>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Stencil Decal Input Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					// Read with `textureLoad`, so it needs no sampler.
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: false,
+							},
+						},
+						count: None,
+					},
+				],
+			});
+		let texture_layout = Tex2d::layout(device);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Stencil Decal Pipeline Layout"),
+			bind_group_layouts: &[&input_layout, &texture_layout],
+			push_constant_ranges: &[],
+		});
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("stencil_decal.wgsl"));
+		let (mask_pipeline, draw_pipeline) =
+			create_pipelines(device, &layout, &shader, format, depth_format, reverse_z);
+		let (vertices, indices) = generate_cube(0.5);
+		let positions: Vec<[f32; 3]> = vertices
+			.iter()
+			.map(|v| [v.pos.x, v.pos.y, v.pos.z])
+			.collect();
+		let cube_vtx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Stencil Decal Cube Vertices"),
+				contents: bytemuck::cast_slice(&positions),
+				usage: wgpu::BufferUsages::VERTEX,
+			});
+		let cube_idx_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Stencil Decal Cube Indices"),
+				contents: bytemuck::cast_slice(&indices),
+				usage: wgpu::BufferUsages::INDEX,
+			});
+		let capacity = 1;
+		Self {
+			decals: Vec::new(),
+			cube_vtx_buf,
+			cube_idx_buf,
+			num_cube_indices: indices.len() as u32,
+			camera_buf,
+			input_layout,
+			texture_layout,
+			instance_buf: create_instance_buffer(device, capacity),
+			capacity,
+			depth_format,
+			reverse_z,
+			layout,
+			shader,
+			mask_pipeline,
+			draw_pipeline,
+		}
+	}
+
+	/// Recreates the pipelines to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		(self.mask_pipeline, self.draw_pipeline) = create_pipelines(
+			device,
+			&self.layout,
+			&self.shader,
+			format,
+			self.depth_format,
+			self.reverse_z,
+		);
+	}
+
+	/// Queues a decal projecting `texture` onto the surfaces within the box
+	/// reaching `extent` from `center` along each axis. The texture's u goes
+	/// along the x axis and its v down the y axis, and it is projected along the
+	/// z axis.
+	pub fn add(
+		&mut self,
+		center: Point3<f32>,
+		extent: [f32; 3],
+		texture: Handle<Tex2d>,
+	) {
+		let transform = Matrix4::new_translation(&center.coords)
+			* Matrix4::new_nonuniform_scaling(&(Vector3::from(extent) * 2.));
+		self.decals.push(Decal { transform, texture });
+	}
+
+	/// The number of decals queued since the last flush.
+	pub fn len(&self) -> usize {
+		self.decals.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.decals.is_empty()
+	}
+
+	/// Drops the queued decals without drawing them.
+	pub fn clear(&mut self) {
+		self.decals.clear();
+	}
+
+	/// Uploads the projection of `camera`, seen from `view`. Returns the number of
+	/// bytes written.
+	pub fn update(
+		&self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut proj_view = camera.proj_view_from(view);
+		if self.reverse_z {
+			proj_view = reverse_z(&proj_view);
+		}
+		let uniform = DecalCamera {
+			proj_view: mat4_to_wgsl(proj_view),
+			inv_proj_view: mat4_to_wgsl(proj_view.try_inverse().unwrap_or_default()),
+		};
+		queue.write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<DecalCamera>() as u64
+	}
+
+	/// Records drawing the queued decals over `color_view`, masked through
+	/// `depth_stencil_view`, the scene's single sampled depth and stencil buffer.
+	/// The texture is placed by the G-buffer's `gbuffer_depth_view`. Empties the
+	/// queue, skipping decals whose texture was removed from `resources`.
+	/// Returns the number of draw calls.
+	#[allow(clippy::too_many_arguments)]
+	pub fn flush(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		encoder: &mut wgpu::CommandEncoder,
+		resources: &ResourceManager,
+		gbuffer_depth_view: &wgpu::TextureView,
+		depth_stencil_view: &wgpu::TextureView,
+		color_view: &wgpu::TextureView,
+	) -> u32 {
+		let mut decals = std::mem::take(&mut self.decals);
+		decals.retain(|decal| resources.get(decal.texture).is_some());
+		if decals.is_empty() {
+			return 0;
+		}
+		// Grouped by texture, to bind each texture once.
+		let mut groups: FxHashMap<Handle<Tex2d>, Vec<DecalInstance>> =
+			FxHashMap::default();
+		for decal in &decals {
+			groups
+				.entry(decal.texture)
+				.or_default()
+				.push(DecalInstance {
+					model: mat4_to_wgsl(decal.transform),
+					inv_model: mat4_to_wgsl(
+						decal.transform.try_inverse().unwrap_or_default(),
+					),
+				});
+		}
+		let instances: Vec<_> = groups.values().flatten().copied().collect();
+		if instances.len() > self.capacity {
+			self.capacity = instances.len().next_power_of_two();
+			self.instance_buf = create_instance_buffer(device, self.capacity);
+		}
+		queue.write_buffer(&self.instance_buf, 0, bytemuck::cast_slice(&instances));
+
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("stencil_decal_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.camera_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(gbuffer_depth_view),
+				},
+			],
+		});
+		let textures: Vec<_> = groups
+			.iter()
+			.map(|(&handle, group)| {
+				let texture =
+					resources.get(handle).expect("Removed decals were skipped");
+				let bind_group = texture.bind_group(
+					device,
+					&self.texture_layout,
+					Some("stencil_decal_texture_bind_group"),
+				);
+				(bind_group, group.len() as u32)
+			})
+			.collect();
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Stencil Decal Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: color_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: depth_stencil_view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				}),
+				stencil_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				}),
+			}),
+		});
+		pass.set_bind_group(0, &input, &[]);
+		pass.set_stencil_reference(Self::STENCIL_BIT);
+		pass.set_vertex_buffer(0, self.cube_vtx_buf.slice(..));
+		pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+		pass.set_index_buffer(self.cube_idx_buf.slice(..), wgpu::IndexFormat::Uint16);
+		let mut first_instance = 0;
+		for (bind_group, count) in &textures {
+			pass.set_bind_group(1, bind_group, &[]);
+			// One at a time, as overlapping boxes would flip the bit back.
+			for i in first_instance..first_instance + count {
+				pass.set_pipeline(&self.mask_pipeline);
+				pass.draw_indexed(0..self.num_cube_indices, 0, i..i + 1);
+				pass.set_pipeline(&self.draw_pipeline);
+				pass.draw_indexed(0..self.num_cube_indices, 0, i..i + 1);
+			}
+			first_instance += count;
+		}
+		// Keeps the allocation for the next frame's decals.
+		decals.clear();
+		self.decals = decals;
+		2 * instances.len() as u32
+	}
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+	device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some("Stencil Decal Instances"),
+		contents: bytemuck::cast_slice(&vec![DecalInstance::zeroed(); capacity]),
+		usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+	})
+}
+
+/// The pipelines flipping the stencil bit within each box, and drawing where it
+/// is set.
+fn create_pipelines(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+	depth_format: wgpu::TextureFormat,
+	reverse_z: bool,
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+	let bit = StencilDecalRenderer::STENCIL_BIT;
+	let create_pipeline =
+		|label, entry_point, write_mask, cull_mode, depth_compare, face| {
+			device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: Some(label),
+				layout: Some(layout),
+				vertex: wgpu::VertexState {
+					module: shader,
+					entry_point: "vs_main",
+					buffers: &[
+						wgpu::VertexBufferLayout {
+							array_stride: std::mem::size_of::<[f32; 3]>() as _,
+							step_mode: wgpu::VertexStepMode::Vertex,
+							attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+						},
+						DecalInstance::vb_layout(),
+					],
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: shader,
+					entry_point,
+					targets: &[Some(wgpu::ColorTargetState {
+						format,
+						blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+						write_mask,
+					})],
+				}),
+				primitive: wgpu::PrimitiveState {
+					cull_mode,
+					..Default::default()
+				},
+				depth_stencil: Some(wgpu::DepthStencilState {
+					format: depth_format,
+					depth_write_enabled: false,
+					depth_compare,
+					stencil: wgpu::StencilState {
+						front: face,
+						back: face,
+						read_mask: bit,
+						write_mask: bit,
+					},
+					bias: wgpu::DepthBiasState::default(),
+				}),
+				multisample: wgpu::MultisampleState::default(),
+				multiview: None,
+			})
+		};
+	let mask_pipeline = create_pipeline(
+		"Stencil Decal Mask Pipeline",
+		"fs_mask",
+		wgpu::ColorWrites::empty(),
+		None,
+		// Nearer fragments have smaller depth, or greater with reverse-Z.
+		if reverse_z {
+			wgpu::CompareFunction::Greater
+		} else {
+			wgpu::CompareFunction::Less
+		},
+		wgpu::StencilFaceState {
+			compare: wgpu::CompareFunction::Always,
+			fail_op: wgpu::StencilOperation::Keep,
+			depth_fail_op: wgpu::StencilOperation::Invert,
+			pass_op: wgpu::StencilOperation::Keep,
+		},
+	);
+	let draw_pipeline = create_pipeline(
+		"Stencil Decal Pipeline",
+		"fs_main",
+		wgpu::ColorWrites::ALL,
+		// The back faces, which are there with the camera inside the box too.
+		Some(wgpu::Face::Front),
+		wgpu::CompareFunction::Always,
+		wgpu::StencilFaceState {
+			compare: wgpu::CompareFunction::Equal,
+			fail_op: wgpu::StencilOperation::Keep,
+			depth_fail_op: wgpu::StencilOperation::Keep,
+			pass_op: wgpu::StencilOperation::Zero,
+		},
+	);
+	(mask_pipeline, draw_pipeline)
+}
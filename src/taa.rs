@@ -0,0 +1,351 @@
+//! Temporal anti-aliasing: frames drawn with their projection jittered within a
+//! pixel are blended together over time, see `taa.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Vector3};
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::tex2d::SamplerConfig;
+use crate::types::mat4_to_wgsl;
+
+/// The layout of the shader's `TaaUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct TaaUniform {
+	inv_view_proj: [[f32; 4]; 4],
+	prev_view_proj: [[f32; 4]; 4],
+	jitter: [f32; 2],
+	blend: f32,
+	has_history: u32,
+	far_depth: f32,
+	_pad: [f32; 3],
+}
+
+/// The `index`th number of the Halton sequence in `base`, between 0 and 1.
+fn halton(mut index: u32, base: u32) -> f32 {
+	let (mut result, mut fraction) = (0., 1.);
+	while index > 0 {
+		fraction /= base as f32;
+		result += fraction * (index % base) as f32;
+		index /= base;
+	}
+	result
+}
+
+/// Blends each frame into the history of the previous ones, from the depth and
+/// motion vectors of a [`GBuffer`](crate::gbuffer::GBuffer), which smooths
+/// edges with a single sample per pixel.
+///
+/// Each frame's projection is offset by [`Self::jitter_matrix`], cycling
+/// through the first 8 points of the Halton sequence within a pixel. The history
+/// is reprojected by the motion vectors, or by the camera's motion from the
+/// depth where nothing was drawn. It is clamped to the colors around each pixel
+/// so that it doesn't ghost, and blended with the frame. It alternates between
+/// two textures, one read while the other is written.
+///
+/// The public fields are uploaded by [`Self::update`].
+pub struct TaaPass {
+	/// The share of each frame blended into the history. Lower is smoother but
+	/// slower to follow changes.
+	pub blend: f32,
+	/// The offset of the projection, in pixels to the right and down.
+	jitter: [f32; 2],
+	/// The size of the frames, in pixels.
+	size: (u32, u32),
+	/// The projection last uploaded, without jitter.
+	view_proj: Matrix4<f32>,
+	/// The projection of the previous frame, without jitter.
+	prev_view_proj: Matrix4<f32>,
+	/// The history, as written by the last frame and this one in turns.
+	history: [wgpu::TextureView; 2],
+	/// The index of the history written by this frame.
+	current: usize,
+	has_history: bool,
+	uniform_buf: wgpu::Buffer,
+	input_layout: wgpu::BindGroupLayout,
+	sampler: wgpu::Sampler,
+	reverse_z: bool,
+	format: wgpu::TextureFormat,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	pipeline: wgpu::RenderPipeline,
+}
+impl TaaPass {
+	/// How many jitter offsets are cycled through.
+	const JITTER_SAMPLES: u64 = 8;
+
+	/// Creates a pass for `width`×`height` frames in `format`, with a depth
+	/// buffer that is optionally reversed.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+		reverse_z: bool,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("TAA Uniform"),
+			size: std::mem::size_of::<TaaUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let texture_entry = |binding, filterable| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable },
+			},
+			count: None,
+		};
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("TAA Input Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					texture_entry(1, true),
+					texture_entry(2, true),
+					// The depth and motion are read with `textureLoad`.
+					texture_entry(3, false),
+					wgpu::BindGroupLayoutEntry {
+						binding: 4,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Sampler(
+							wgpu::SamplerBindingType::Filtering,
+						),
+						count: None,
+					},
+					texture_entry(5, false),
+				],
+			});
+		let sampler = SamplerConfig {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..SamplerConfig::default()
+		}
+		.create_sampler(device);
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("TAA Pipeline Layout"),
+			bind_group_layouts: &[&input_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("taa.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		Self {
+			blend: 0.1,
+			jitter: [0.; 2],
+			size: (width, height),
+			view_proj: Matrix4::identity(),
+			prev_view_proj: Matrix4::identity(),
+			history: create_history(device, width, height, format),
+			current: 0,
+			has_history: false,
+			uniform_buf,
+			input_layout,
+			sampler,
+			reverse_z,
+			format,
+			layout,
+			shader,
+			pipeline,
+		}
+	}
+
+	/// Recreates the history for `width`×`height` frames, which starts over.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		self.size = (width, height);
+		self.history = create_history(device, width, height, self.format);
+		self.has_history = false;
+	}
+
+	/// Recreates the history and pipeline for `format` frames.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.format = format;
+		self.resize(device, self.size.0, self.size.1);
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// Moves on to the `frame`th frame, after the previous one was drawn: the
+	/// projection is jittered by the next offset, and the history just written is
+	/// read.
+	pub fn update_jitter(&mut self, frame: u64) {
+		let index = (frame % Self::JITTER_SAMPLES) as u32 + 1;
+		self.jitter = [halton(index, 2) - 0.5, halton(index, 3) - 0.5];
+		self.prev_view_proj = self.view_proj;
+		self.current = (frame % 2) as usize;
+		self.has_history = true;
+	}
+
+	/// Offsets clip space by the jitter, to be applied to the camera's projection
+	/// before anything is drawn.
+	pub fn jitter_matrix(&self) -> Matrix4<f32> {
+		let (width, height) = self.size;
+		let [x, y] = self.jitter;
+		// Clip space's y points up, and its x and y span 2 units.
+		Matrix4::new_translation(&Vector3::new(
+			2. * x / width as f32,
+			-2. * y / height as f32,
+			0.,
+		))
+	}
+
+	/// Uploads the matrices of `camera` seen from `view`, and the public fields.
+	/// Returns the number of bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut view_proj = camera.proj_view_from(view);
+		if self.reverse_z {
+			view_proj = reverse_z(&view_proj);
+		}
+		self.view_proj = view_proj;
+		let jittered = self.jitter_matrix() * view_proj;
+		let (width, height) = self.size;
+		let [x, y] = self.jitter;
+		let uniform = TaaUniform {
+			inv_view_proj: mat4_to_wgsl(jittered.try_inverse().unwrap_or_default()),
+			prev_view_proj: mat4_to_wgsl(self.prev_view_proj),
+			jitter: [x / width as f32, y / height as f32],
+			blend: self.blend,
+			has_history: self.has_history.into(),
+			far_depth: if self.reverse_z { 0. } else { 1. },
+			_pad: [0.; 3],
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<TaaUniform>() as u64
+	}
+
+	/// Records blending `color_view` into the history, reprojected by the
+	/// G-buffer's `depth_view` and `motion_view`, into all of `output_view` and
+	/// the next frame's history.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		color_view: &wgpu::TextureView,
+		depth_view: &wgpu::TextureView,
+		motion_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("taa_input_bind_group"),
+			layout: &self.input_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(color_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(
+						&self.history[1 - self.current],
+					),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: wgpu::BindingResource::TextureView(motion_view),
+				},
+			],
+		});
+		// Every pixel is drawn over.
+		let attachment = |view| {
+			Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})
+		};
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("TAA Pass"),
+			color_attachments: &[
+				attachment(output_view),
+				attachment(&self.history[self.current]),
+			],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+fn create_history(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+	format: wgpu::TextureFormat,
+) -> [wgpu::TextureView; 2] {
+	[0, 1].map(|i| {
+		device
+			.create_texture(&wgpu::TextureDescriptor {
+				label: Some(&format!("TAA History {i}")),
+				size: wgpu::Extent3d {
+					width,
+					height,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+					| wgpu::TextureUsages::TEXTURE_BINDING,
+				view_formats: &[],
+			})
+			.create_view(&Default::default())
+	})
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("TAA Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into()), Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
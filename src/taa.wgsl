@@ -0,0 +1,103 @@
+// Temporal anti-aliasing: each frame is drawn with its projection offset by
+// less than a pixel, and blended into the history of the previous frames,
+// reprojected by the motion vectors.
+
+// Matches `TaaUniform` in `taa.rs`.
+struct TaaUniform {
+	// The inverse of the frame's jittered projection.
+	inv_view_proj: mat4x4<f32>,
+	// The previous frame's projection, without jitter.
+	prev_view_proj: mat4x4<f32>,
+	// The frame's jitter, in texture coordinates.
+	jitter: vec2<f32>,
+	// The share of the frame blended into the history.
+	blend: f32,
+	// Whether the history holds a previous frame.
+	has_history: u32,
+	// The depth where nothing was drawn.
+	far_depth: f32,
+};
+@group(0) @binding(0)
+var<uniform> taa: TaaUniform;
+
+@group(0) @binding(1)
+var color_t: texture_2d<f32>;
+@group(0) @binding(2)
+var history_t: texture_2d<f32>;
+// The G-buffer's depth, in the red channel.
+@group(0) @binding(3)
+var depth_t: texture_2d<f32>;
+@group(0) @binding(4)
+var color_s: sampler;
+// The G-buffer's motion vectors, in NDC.
+@group(0) @binding(5)
+var motion_t: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+struct FragmentOutput {
+	@location(0) color: vec4<f32>,
+	// The next frame's history.
+	@location(1) history: vec4<f32>,
+};
+
+// Where the surface drawn at `uv` was on the previous frame's screen, from its
+// motion vector. Where nothing was drawn, the background is moved by the
+// camera's motion from its depth instead.
+fn reproject(uv: vec2<f32>, coords: vec2<i32>) -> vec2<f32> {
+	let depth = textureLoad(depth_t, coords, 0).r;
+	if depth != taa.far_depth {
+		let motion = textureLoad(motion_t, coords, 0).xy;
+		return uv - motion * vec2<f32>(0.5, -0.5);
+	}
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	// Kept homogeneous, so that points at infinity reproject too.
+	let world = taa.inv_view_proj * ndc;
+	let prev_clip = taa.prev_view_proj * world;
+	return prev_clip.xy / prev_clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+	let current = textureSampleLevel(color_t, color_s, in.uv + taa.jitter, 0.0);
+	var out: FragmentOutput;
+	out.color = current;
+	out.history = current;
+	let prev_uv = reproject(in.uv, vec2<i32>(in.clip_pos.xy));
+	if taa.has_history == 0u || any(prev_uv != clamp(prev_uv, vec2<f32>(0.0), vec2<f32>(1.0))) {
+		return out;
+	}
+
+	// The history is clamped to the colors around the pixel, so that what it
+	// no longer shows doesn't ghost.
+	let size = vec2<i32>(textureDimensions(color_t));
+	let center = vec2<i32>(in.clip_pos.xy);
+	var low = current;
+	var high = current;
+	for (var y = -1; y <= 1; y += 1) {
+		for (var x = -1; x <= 1; x += 1) {
+			let coords = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+			let color = textureLoad(color_t, coords, 0);
+			low = min(low, color);
+			high = max(high, color);
+		}
+	}
+	let history = clamp(textureSampleLevel(history_t, color_s, prev_uv, 0.0), low, high);
+	out.color = mix(history, current, taa.blend);
+	out.history = out.color;
+	return out;
+}
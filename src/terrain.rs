@@ -0,0 +1,158 @@
+//! Terrain from heightmaps too large to upload at once, split into chunks that
+//! are only uploaded while the camera sees them.
+
+use image::GrayImage;
+use nalgebra::Point3;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::camera::CameraLike;
+use crate::material::Material;
+use crate::mesh::{generate_terrain_region, GpuMesh};
+
+/// A square of a [`Terrain`]'s pixels, with the mesh of its vertices while
+/// uploaded.
+pub struct TerrainChunk {
+	/// The heightmap's columns and rows the chunk covers, including the edge
+	/// shared with the next chunk.
+	cols: Range<u32>,
+	rows: Range<u32>,
+	/// Bounds the chunk's vertices in world space, also while not uploaded.
+	aabb: Aabb,
+	mesh: Option<GpuMesh>,
+}
+impl TerrainChunk {
+	pub fn aabb(&self) -> &Aabb {
+		&self.aabb
+	}
+
+	/// The uploaded vertices and triangles, `None` while the chunk isn't visible.
+	pub fn mesh(&self) -> Option<&GpuMesh> {
+		self.mesh.as_ref()
+	}
+}
+
+/// Terrain as from [`generate_terrain`], drawn in `chunk_size` x `chunk_size`
+/// chunks of one draw call each. Only the chunks in the camera's frustum are
+/// uploaded, see [`Self::update_visible`], so the heightmap may have many more
+/// vertices than fit in memory.
+///
+/// Draw it with [`RenderState::set_terrain`].
+///
+/// [`generate_terrain`]: crate::mesh::generate_terrain
+/// [`RenderState::set_terrain`]: crate::render_state::RenderState::set_terrain
+pub struct Terrain {
+	pub material: Arc<Material>,
+	chunks: Vec<TerrainChunk>,
+	chunk_size: u32,
+	/// The indices of the chunks found visible by `update_visible`.
+	visible: Vec<usize>,
+	heightmap: GrayImage,
+	max_height: f32,
+	device: Arc<wgpu::Device>,
+}
+impl Terrain {
+	/// Splits `heightmap` into chunks of `chunk_size` x `chunk_size` quads, lifted
+	/// by up to `max_height`, without uploading any.
+	///
+	/// # Panics
+	/// If `chunk_size` is 0, or the heightmap is less than 2x2 pixels.
+	pub fn new(
+		device: Arc<wgpu::Device>,
+		heightmap: GrayImage,
+		max_height: f32,
+		chunk_size: u32,
+		material: Arc<Material>,
+	) -> Self {
+		assert!(chunk_size > 0, "Terrain chunks need at least one quad");
+		let (width, depth) = heightmap.dimensions();
+		assert!(
+			width >= 2 && depth >= 2,
+			"Terrain needs at least 2x2 pixels"
+		);
+		// The last pixels are shared by the chunks before them.
+		let ranges = |size: u32| {
+			(0..size - 1)
+				.step_by(chunk_size as usize)
+				.map(move |start| start..(start + chunk_size + 1).min(size))
+		};
+		let pixels = &heightmap;
+		let chunks = ranges(depth)
+			.flat_map(|rows| ranges(width).map(move |cols| (cols, rows.clone())))
+			.map(|(cols, rows)| {
+				let heights = rows.clone().flat_map(|row| {
+					cols.clone().map(move |col| pixels.get_pixel(col, row).0[0])
+				});
+				let (min, max) = heights
+					.fold((u8::MAX, 0), |(min, max), h| (min.min(h), max.max(h)));
+				// Placed as by `generate_terrain`, centered on the origin.
+				let corner = |col: u32, row: u32, h: u8| {
+					Point3::new(
+						col as f32 - (width - 1) as f32 / 2.,
+						h as f32 / 255. * max_height,
+						row as f32 - (depth - 1) as f32 / 2.,
+					)
+				};
+				TerrainChunk {
+					aabb: Aabb::new(
+						corner(cols.start, rows.start, min),
+						corner(cols.end - 1, rows.end - 1, max),
+					),
+					cols,
+					rows,
+					mesh: None,
+				}
+			})
+			.collect();
+		Self {
+			material,
+			chunks,
+			chunk_size,
+			visible: Vec::new(),
+			heightmap,
+			max_height,
+			device,
+		}
+	}
+
+	pub fn chunks(&self) -> &[TerrainChunk] {
+		&self.chunks
+	}
+
+	pub fn chunk_size(&self) -> u32 {
+		self.chunk_size
+	}
+
+	/// Uploads the chunks in `camera`'s frustum that weren't yet, and frees those
+	/// outside of it. Returns the visible chunks, which are also those drawn.
+	pub fn update_visible(&mut self, camera: &dyn CameraLike) -> Vec<&TerrainChunk> {
+		let frustum = camera.frustum();
+		let (width, depth) = self.heightmap.dimensions();
+		self.visible.clear();
+		for (i, chunk) in self.chunks.iter_mut().enumerate() {
+			if !chunk.aabb.intersects_frustum(&frustum) {
+				chunk.mesh = None;
+				continue;
+			}
+			if chunk.mesh.is_none() {
+				let (vertices, indices) = generate_terrain_region(
+					self.heightmap.as_raw(),
+					width,
+					depth,
+					self.max_height,
+					chunk.cols.clone(),
+					chunk.rows.clone(),
+				);
+				chunk.mesh = Some(GpuMesh::new(&self.device, &vertices, &indices));
+			}
+			self.visible.push(i);
+		}
+		self.visible_chunks().collect()
+	}
+
+	/// The chunks found visible by the last [`Self::update_visible`].
+	pub fn visible_chunks(&self) -> impl Iterator<Item = &TerrainChunk> {
+		self.visible.iter().map(|&i| &self.chunks[i])
+	}
+}
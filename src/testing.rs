@@ -0,0 +1,130 @@
+//! Comparing headless frames against reference screenshots, to catch rendering
+//! regressions in tests.
+
+use color_eyre::{eyre::bail, eyre::WrapErr, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::builder::RenderStateBuilder;
+use crate::gpu_context::SharedGpuContext;
+use crate::render_state::RenderState;
+use crate::screenshot::save_screenshot;
+
+/// Renders a headless frame and compares it to the reference screenshot of the
+/// same name. The [`Self::UPDATE_VAR`] environment variable saves the frame as
+/// the reference instead. Passes without checking anything on machines without
+/// a GPU.
+#[derive(Clone, Debug)]
+pub struct ScreenshotTest {
+	pub width: u32,
+	pub height: u32,
+	/// Where the references are, relative to the working directory, which is
+	/// the package's root in tests.
+	pub directory: PathBuf,
+}
+impl Default for ScreenshotTest {
+	fn default() -> Self {
+		Self {
+			width: 256,
+			height: 256,
+			directory: PathBuf::from("tests/screenshots"),
+		}
+	}
+}
+impl ScreenshotTest {
+	/// Set to 1 to capture the references, replacing them with the frames rendered
+	/// now.
+	pub const UPDATE_VAR: &'static str = "WGPU_UPDATE_SCREENSHOTS";
+
+	/// Checks the frame drawn after `setup` against the reference `name` with
+	/// the default size and directory, see [`Self::check`].
+	pub fn run(
+		name: &str,
+		setup: impl FnOnce(&mut RenderState),
+		tolerance: f32,
+	) -> Result<()> {
+		Self::default().check(name, setup, tolerance)
+	}
+
+	/// Renders a frame of a headless state after `setup` and compares it to
+	/// `<directory>/<name>.png`. Fails if their root mean square error, over
+	/// all channels from 0 to 1, is above `tolerance`, in which case the frame
+	/// is saved next to the reference as `<name>.actual.png`, or if there is no
+	/// reference.
+	pub fn check(
+		&self,
+		name: &str,
+		setup: impl FnOnce(&mut RenderState),
+		tolerance: f32,
+	) -> Result<()> {
+		let context = match pollster::block_on(SharedGpuContext::new()) {
+			Ok(context) => Arc::new(context),
+			Err(err) => {
+				eprintln!("Skipping screenshot test {name}: {err}");
+				return Ok(());
+			}
+		};
+		let mut state = RenderStateBuilder::new().build_headless_with_context(
+			context,
+			self.width,
+			self.height,
+		)?;
+		setup(&mut state);
+		state.render()?;
+		let img = state.capture_screenshot()?;
+
+		let path = self.directory.join(format!("{name}.png"));
+		let update = matches!(std::env::var(Self::UPDATE_VAR).as_deref(), Ok("1"));
+		if update {
+			std::fs::create_dir_all(&self.directory).wrap_err_with(|| {
+				format!("Failed to create {}", self.directory.display())
+			})?;
+			return save_screenshot(img, &path);
+		}
+		if !path.exists() {
+			bail!(
+				"{name} has no reference {}. Set {}=1 to capture it",
+				path.display(),
+				Self::UPDATE_VAR
+			);
+		}
+		let reference = image::open(&path)
+			.wrap_err_with(|| format!("Failed to load reference {}", path.display()))?
+			.to_rgba8();
+		if reference.dimensions() != img.dimensions() {
+			bail!(
+				"{name} is {:?}, but its reference {} is {:?}",
+				img.dimensions(),
+				path.display(),
+				reference.dimensions()
+			);
+		}
+		let error = rmse(&img, &reference);
+		if error > tolerance {
+			let actual = self.directory.join(format!("{name}.actual.png"));
+			save_screenshot(img, &actual)?;
+			bail!(
+				"{name} differs from {} by an RMSE of {error}, above {tolerance}. It was \
+				 saved to {}",
+				path.display(),
+				actual.display()
+			);
+		}
+		Ok(())
+	}
+}
+
+/// The root mean square error between the channels of `a` and `b`, from 0 to 1.
+/// They must be the same size.
+fn rmse(a: &image::RgbaImage, b: &image::RgbaImage) -> f32 {
+	let sum: f64 = a
+		.as_raw()
+		.iter()
+		.zip(b.as_raw())
+		.map(|(&a, &b)| {
+			let d = (a as f64 - b as f64) / 255.;
+			d * d
+		})
+		.sum();
+	(sum / a.as_raw().len().max(1) as f64).sqrt() as f32
+}
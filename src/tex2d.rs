@@ -1,63 +1,355 @@
-use wgpu::util::DeviceExt;
+use color_eyre::{eyre::ensure, eyre::WrapErr, Result};
+use std::path::Path;
 
 pub struct Shape {
 	pub width: u32,
 	pub height: u32,
 }
 
+/// How a [`Tex2d`] is sampled.
+///
+/// The default matches `wgpu::SamplerDescriptor::default()`: nearest filtering
+/// and clamped UVs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SamplerConfig {
+	pub mag_filter: wgpu::FilterMode,
+	pub min_filter: wgpu::FilterMode,
+	pub mipmap_filter: wgpu::FilterMode,
+	pub address_mode_u: wgpu::AddressMode,
+	pub address_mode_v: wgpu::AddressMode,
+	/// Values above 1 enable anisotropic filtering, and require all filters to
+	/// be `Linear`.
+	pub anisotropy_clamp: u16,
+	/// Whether to generate a full mip chain when creating the texture. Otherwise
+	/// it only has the base level, and `mipmap_filter` has no effect.
+	pub generate_mipmaps: bool,
+}
+impl Default for SamplerConfig {
+	fn default() -> Self {
+		Self {
+			mag_filter: wgpu::FilterMode::Nearest,
+			min_filter: wgpu::FilterMode::Nearest,
+			mipmap_filter: wgpu::FilterMode::Nearest,
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			anisotropy_clamp: 1,
+			generate_mipmaps: false,
+		}
+	}
+}
+impl SamplerConfig {
+	/// Linear filtering, mipmaps and repeating UVs, for tiling textures.
+	pub fn tiling() -> Self {
+		Self {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::FilterMode::Linear,
+			address_mode_u: wgpu::AddressMode::Repeat,
+			address_mode_v: wgpu::AddressMode::Repeat,
+			anisotropy_clamp: 1,
+			generate_mipmaps: true,
+		}
+	}
+
+	pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+		device.create_sampler(&wgpu::SamplerDescriptor {
+			label: None,
+			address_mode_u: self.address_mode_u,
+			address_mode_v: self.address_mode_v,
+			mag_filter: self.mag_filter,
+			min_filter: self.min_filter,
+			mipmap_filter: self.mipmap_filter,
+			anisotropy_clamp: self.anisotropy_clamp,
+			..Default::default()
+		})
+	}
+}
+
 pub struct Tex2d {
 	pub texture: wgpu::Texture,
 	pub view: wgpu::TextureView,
 	pub sampler: wgpu::Sampler,
+	/// What `sampler` was created from.
+	pub sampler_config: SamplerConfig,
 }
 impl Tex2d {
-	/// The amount of multisampling
-	const N_SAMPLES: u8 = 1;
 	const VIEW_DIM: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D2;
+	const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+	/// For data that isn't a color, like normal maps.
+	const LINEAR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 
 	pub fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
 		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
 			label: Some("Texture Bind Group Layout"),
-			entries: &[
-				wgpu::BindGroupLayoutEntry {
-					binding: 0,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Texture {
-						sample_type: wgpu::TextureSampleType::Float {
-							filterable: true,
-						},
-						view_dimension: Self::VIEW_DIM,
-						multisampled: Self::N_SAMPLES > 1,
-					},
-					// Not an array, so we use `None`
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry {
-					binding: 1,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-					count: None,
+			entries: &Self::layout_entries(0),
+		})
+	}
+
+	/// A layout with a diffuse texture and sampler at bindings 0 and 1, and a
+	/// normal map and its sampler at bindings 2 and 3.
+	pub fn normal_mapped_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		let [diffuse_t, diffuse_s] = Self::layout_entries(0);
+		let [normal_t, normal_s] = Self::layout_entries(2);
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("Normal Mapped Bind Group Layout"),
+			entries: &[diffuse_t, diffuse_s, normal_t, normal_s],
+		})
+	}
+
+	/// The entries for a texture at `binding` and its sampler at `binding + 1`.
+	pub(crate) fn layout_entries(binding: u32) -> [wgpu::BindGroupLayoutEntry; 2] {
+		[
+			wgpu::BindGroupLayoutEntry {
+				binding,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Texture {
+					sample_type: wgpu::TextureSampleType::Float { filterable: true },
+					view_dimension: Self::VIEW_DIM,
+					multisampled: false,
 				},
-			],
+				// Not an array, so we use `None`
+				count: None,
+			},
+			wgpu::BindGroupLayoutEntry {
+				binding: binding + 1,
+				visibility: wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+				count: None,
+			},
+		]
+	}
+
+	/// Creates a bind group matching [`Self::layout`].
+	pub fn bind_group(
+		&self,
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		label: Option<&str>,
+	) -> wgpu::BindGroup {
+		let [view, sampler] = self.bind_group_entries(0);
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label,
+			layout,
+			entries: &[view, sampler],
 		})
 	}
 
+	/// Creates a bind group matching [`Self::normal_mapped_layout`].
+	pub fn normal_mapped_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		diffuse: &Tex2d,
+		normal_map: &Tex2d,
+		label: Option<&str>,
+	) -> wgpu::BindGroup {
+		let [diffuse_t, diffuse_s] = diffuse.bind_group_entries(0);
+		let [normal_t, normal_s] = normal_map.bind_group_entries(2);
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label,
+			layout,
+			entries: &[diffuse_t, diffuse_s, normal_t, normal_s],
+		})
+	}
+
+	/// The entries binding `self` at `binding` and its sampler at `binding + 1`.
+	pub(crate) fn bind_group_entries(
+		&self,
+		binding: u32,
+	) -> [wgpu::BindGroupEntry<'_>; 2] {
+		[
+			wgpu::BindGroupEntry {
+				binding,
+				resource: wgpu::BindingResource::TextureView(&self.view),
+			},
+			wgpu::BindGroupEntry {
+				binding: binding + 1,
+				resource: wgpu::BindingResource::Sampler(&self.sampler),
+			},
+		]
+	}
+
+	/// Loads an image file, labeling the texture with its path.
+	pub fn load_from_path(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		path: &Path,
+		sampler: SamplerConfig,
+	) -> Result<Self> {
+		let img = image::open(path)
+			.wrap_err_with(|| format!("Failed to load texture {}", path.display()))?;
+		let label = path.to_string_lossy();
+		Ok(Self::new_from_img(
+			device,
+			queue,
+			Some(&label),
+			img,
+			sampler,
+		))
+	}
+
+	/// Loads a tangent space normal map. Unlike color textures, normal maps are
+	/// stored linearly, so they aren't sRGB decoded.
+	pub fn load_normal_map_from_path(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		path: &Path,
+		sampler: SamplerConfig,
+	) -> Result<Self> {
+		let img = image::open(path).wrap_err_with(|| {
+			format!("Failed to load normal map {}", path.display())
+		})?;
+		let label = path.to_string_lossy();
+		let shape = Shape {
+			width: img.width(),
+			height: img.height(),
+		};
+		Ok(Self::new_from_bytes_as(
+			device,
+			queue,
+			Some(&label),
+			&img.into_rgba8(),
+			shape,
+			sampler,
+			Self::LINEAR_FORMAT,
+		))
+	}
+
+	/// A 1x1 normal map that leaves normals unchanged, for meshes without one.
+	pub fn flat_normal_map(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+		Self::new_from_bytes_as(
+			device,
+			queue,
+			Some("Flat Normal Map"),
+			// (0, 0, 1) in tangent space.
+			&[128, 128, 255, 255],
+			Shape {
+				width: 1,
+				height: 1,
+			},
+			SamplerConfig::default(),
+			Self::LINEAR_FORMAT,
+		)
+	}
+
+	/// A 1x1 texture of a single sRGB color, for material slots without a
+	/// texture.
+	pub fn from_color(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		rgba: [u8; 4],
+	) -> Self {
+		Self::new_from_rgba8(
+			device,
+			queue,
+			label,
+			&rgba,
+			Shape {
+				width: 1,
+				height: 1,
+			},
+			SamplerConfig::default(),
+		)
+	}
+
 	pub fn new_from_img_bytes(
 		device: &wgpu::Device,
 		queue: &wgpu::Queue,
 		bytes: &[u8],
 		label: Option<&str>,
+		sampler: SamplerConfig,
 	) -> Self {
 		let img = image::load_from_memory(bytes).unwrap();
-		Self::new_from_img(device, queue, label, img)
+		Self::new_from_img(device, queue, label, img, sampler)
 	}
 
+	/// Creates an sRGB texture from 4 bytes per pixel, in RGBA order.
+	pub fn new_from_rgba8(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		bytes: &[u8],
+		shape: Shape,
+		sampler_config: SamplerConfig,
+	) -> Self {
+		Self::new_from_bytes_as(
+			device,
+			queue,
+			label,
+			bytes,
+			shape,
+			sampler_config,
+			Self::COLOR_FORMAT,
+		)
+	}
+
+	/// Creates a texture from 4 bytes per pixel, in RGBA order, stored linearly
+	/// for data that isn't a color, like distance fields.
+	pub fn new_from_linear_rgba8(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		bytes: &[u8],
+		shape: Shape,
+		sampler_config: SamplerConfig,
+	) -> Self {
+		Self::new_from_bytes_as(
+			device,
+			queue,
+			label,
+			bytes,
+			shape,
+			sampler_config,
+			Self::LINEAR_FORMAT,
+		)
+	}
+
+	/// Creates an sRGB texture from 3 bytes per pixel, in RGB order. The pixels
+	/// are made opaque, as GPUs have no 3 channel formats.
 	pub fn new_from_rgb8(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		bytes: &[u8],
+		shape: Shape,
+		sampler_config: SamplerConfig,
+	) -> Self {
+		let rgba = rgb8_to_rgba8(bytes);
+		Self::new_from_rgba8(device, queue, label, &rgba, shape, sampler_config)
+	}
+
+	/// Creates a single channel texture from 1 byte per pixel, for data like
+	/// height or roughness maps. It is stored linearly, and shaders read it as
+	/// `vec4(r, 0, 0, 1)`, through the same [`Self::layout`] as color textures.
+	pub fn new_from_r8(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		label: Option<&str>,
+		bytes: &[u8],
+		shape: Shape,
+		sampler_config: SamplerConfig,
+	) -> Self {
+		Self::new_from_bytes_as(
+			device,
+			queue,
+			label,
+			bytes,
+			shape,
+			sampler_config,
+			wgpu::TextureFormat::R8Unorm,
+		)
+	}
+
+	/// Creates a texture of `format` from `bytes`, which are tightly packed rows
+	/// of `format`'s texels.
+	fn new_from_bytes_as(
 		device: &wgpu::Device,
 		queue: &wgpu::Queue,
 		label: Option<&str>,
 		bytes: &[u8],
 		Shape { width, height }: Shape,
+		sampler_config: SamplerConfig,
+		format: wgpu::TextureFormat,
 	) -> Self {
 		let tex_size = wgpu::Extent3d {
 			width,
@@ -65,29 +357,50 @@ impl Tex2d {
 			// We are not using an array of images, so its just 1
 			depth_or_array_layers: 1,
 		};
-		let texture = device.create_texture_with_data(
-			&queue,
-			&wgpu::TextureDescriptor {
-				label,
-				size: tex_size,
-				mip_level_count: 1,
-				sample_count: 1,
-				dimension: Self::VIEW_DIM.compatible_texture_dimension(),
-				format: wgpu::TextureFormat::Rgba8UnormSrgb,
-				usage: wgpu::TextureUsages::TEXTURE_BINDING
-					| wgpu::TextureUsages::COPY_DST,
-				view_formats: &[],
+		let (mip_level_count, mip_usage) = if sampler_config.generate_mipmaps {
+			// Mip levels are rendered to.
+			(
+				mip_level_count(width, height),
+				wgpu::TextureUsages::RENDER_ATTACHMENT,
+			)
+		} else {
+			(1, wgpu::TextureUsages::empty())
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label,
+			size: tex_size,
+			mip_level_count,
+			sample_count: 1,
+			dimension: Self::VIEW_DIM.compatible_texture_dimension(),
+			format,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING
+				| wgpu::TextureUsages::COPY_DST
+				| wgpu::TextureUsages::COPY_SRC
+				| mip_usage,
+			view_formats: &[],
+		});
+		// Only the base level, the others are generated.
+		queue.write_texture(
+			texture.as_image_copy(),
+			bytes,
+			wgpu::ImageDataLayout {
+				offset: 0,
+				bytes_per_row: format.block_size(None).map(|size| width * size),
+				rows_per_image: None,
 			},
-			&bytes,
+			tex_size,
 		);
+		if mip_level_count > 1 {
+			generate_mipmaps(device, queue, &texture, format);
+		}
 		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-		// NOTE: The tutorial does this one manually instead of default.
-		let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+		let sampler = sampler_config.create_sampler(device);
 
 		Self {
 			texture,
 			view,
 			sampler,
+			sampler_config,
 		}
 	}
 
@@ -96,10 +409,222 @@ impl Tex2d {
 		queue: &wgpu::Queue,
 		label: Option<&str>,
 		img: image::DynamicImage,
+		sampler: SamplerConfig,
 	) -> Self {
 		let width = img.width();
 		let height = img.height();
 		let rgba = img.into_rgba8();
-		Self::new_from_rgb8(device, queue, label, &rgba, Shape { width, height })
+		let shape = Shape { width, height };
+		Self::new_from_rgba8(device, queue, label, &rgba, shape, sampler)
+	}
+
+	/// Records a copy of the `extent` region of `src` at `src_origin` into `self`
+	/// at `dst_origin`.
+	pub fn copy_from(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		src: &Tex2d,
+		src_origin: wgpu::Origin3d,
+		dst_origin: wgpu::Origin3d,
+		extent: wgpu::Extent3d,
+	) -> Result<()> {
+		let fits = |origin: wgpu::Origin3d, size: wgpu::Extent3d| {
+			origin.x + extent.width <= size.width
+				&& origin.y + extent.height <= size.height
+				&& origin.z + extent.depth_or_array_layers <= size.depth_or_array_layers
+		};
+		ensure!(
+			fits(src_origin, src.texture.size()),
+			"Copy region doesn't fit in the source texture"
+		);
+		ensure!(
+			fits(dst_origin, self.texture.size()),
+			"Copy region doesn't fit in the destination texture"
+		);
+		ensure!(
+			src.texture.format() == self.texture.format(),
+			"Can't copy from {:?} to {:?}",
+			src.texture.format(),
+			self.texture.format(),
+		);
+
+		encoder.copy_texture_to_texture(
+			wgpu::ImageCopyTexture {
+				origin: src_origin,
+				..src.texture.as_image_copy()
+			},
+			wgpu::ImageCopyTexture {
+				origin: dst_origin,
+				..self.texture.as_image_copy()
+			},
+			extent,
+		);
+		Ok(())
+	}
+
+	/// Creates a new texture with the same size and format as `self`, and records a
+	/// copy of `self` into it.
+	///
+	/// The copy only happens once `encoder` is submitted, so `_queue` is unused
+	/// for now; it's taken like in the other constructors.
+	pub fn clone_gpu(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		_queue: &wgpu::Queue,
+	) -> Tex2d {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: None,
+			size: self.texture.size(),
+			mip_level_count: self.texture.mip_level_count(),
+			sample_count: self.texture.sample_count(),
+			dimension: self.texture.dimension(),
+			format: self.texture.format(),
+			usage: self.texture.usage() | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let clone = Self {
+			texture,
+			view,
+			sampler: self.sampler_config.create_sampler(device),
+			sampler_config: self.sampler_config,
+		};
+		let origin = wgpu::Origin3d::ZERO;
+		clone
+			.copy_from(encoder, self, origin, origin, self.texture.size())
+			.expect("Texture has the same size and format as itself");
+		clone
+	}
+}
+
+/// The number of mip levels of a full mip chain, down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+	// floor(log2(max(width, height))) + 1
+	u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Fills mip levels 1 and up of `texture` by repeatedly downsampling the level
+/// above, and submits the work.
+///
+/// `texture` must have `COPY_SRC` and `RENDER_ATTACHMENT` usages, and `format`
+/// must be filterable and renderable.
+pub fn generate_mipmaps(
+	device: &wgpu::Device,
+	queue: &wgpu::Queue,
+	texture: &wgpu::Texture,
+	format: wgpu::TextureFormat,
+) {
+	let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+	let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Mipmap Blit Pipeline"),
+		// Derived from the shader.
+		layout: None,
+		vertex: wgpu::VertexState {
+			module: &shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: &shader,
+			entry_point: "fs_main",
+			targets: &[Some(format.into())],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	});
+	let layout = pipeline.get_bind_group_layout(0);
+	let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+		label: Some("Mipmap Sampler"),
+		mag_filter: wgpu::FilterMode::Linear,
+		min_filter: wgpu::FilterMode::Linear,
+		..Default::default()
+	});
+
+	let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+		label: Some("Mipmap Encoder"),
+	});
+	for level in 1..texture.mip_level_count() {
+		// The GL backend can't sample views that don't start at mip 0, so we copy
+		// the previous level into its own texture first.
+		let src_size = texture
+			.size()
+			.mip_level_size(level - 1, wgpu::TextureDimension::D2);
+		let src_tex = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Mip Source"),
+			size: src_size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		encoder.copy_texture_to_texture(
+			wgpu::ImageCopyTexture {
+				mip_level: level - 1,
+				..texture.as_image_copy()
+			},
+			src_tex.as_image_copy(),
+			src_size,
+		);
+		let src = src_tex.create_view(&wgpu::TextureViewDescriptor::default());
+		let dst = texture.create_view(&wgpu::TextureViewDescriptor {
+			label: Some("Mip Level"),
+			base_mip_level: level,
+			mip_level_count: Some(1),
+			..Default::default()
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&src),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&sampler),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Mipmap Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: &dst,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&pipeline);
+		pass.set_bind_group(0, &bind_group, &[]);
+		pass.draw(0..3, 0..1);
+	}
+	queue.submit([encoder.finish()]);
+}
+
+/// Appends an opaque alpha to each pixel of 3 bytes, in RGB order.
+fn rgb8_to_rgba8(bytes: &[u8]) -> Vec<u8> {
+	bytes
+		.chunks_exact(3)
+		.flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rgb8_white_is_opaque_white() {
+		let rgba = rgb8_to_rgba8(&[255, 255, 255, 10, 20, 30]);
+		assert_eq!(rgba, [255, 255, 255, 255, 10, 20, 30, 255]);
 	}
 }
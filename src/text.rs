@@ -0,0 +1,546 @@
+//! Text drawn over the frame, from an atlas of the printable ASCII glyphs or
+//! of an [`SdfFont`].
+
+use ab_glyph::{Font, FontArc, ScaleFont};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::sdf_font::SdfFont;
+use crate::tex2d::{SamplerConfig, Shape, Tex2d};
+
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct TextVertex {
+	pos: [f32; 2],
+	uv: [f32; 2],
+	color: [f32; 4],
+}
+
+/// The layout of `text.wgsl`'s `SdfUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct SdfUniform {
+	outline_color: [f32; 4],
+	outline_width: f32,
+	_pad: [f32; 3],
+}
+
+/// Where a glyph is in the atlas and how it is placed, in pixels at
+/// [`TextRenderer::ATLAS_PX`].
+#[derive(Copy, Clone, Debug, Default)]
+struct GlyphInfo {
+	/// The top left corner in the atlas.
+	origin: [u32; 2],
+	/// Empty for glyphs with no outline, such as the space.
+	size: [u32; 2],
+	/// From the pen position on the baseline to the top left corner.
+	offset: [f32; 2],
+	/// How far the pen moves after the glyph.
+	advance: f32,
+}
+
+/// How [`TextRenderer`] draws glyphs.
+pub enum TextBackend {
+	/// Egui's monospace font, rasterized at [`TextRenderer::ATLAS_PX`]. It blurs
+	/// when scaled up.
+	Raster,
+	/// A font of signed distance fields, sharp at any scale.
+	Sdf(SdfFont),
+}
+
+/// The glyphs of a [`TextBackend`].
+enum Glyphs {
+	Raster {
+		atlas: Tex2d,
+		/// The glyphs of `FIRST_CHAR` onwards.
+		glyphs: Vec<GlyphInfo>,
+		/// From the top of a line to its baseline, at `ATLAS_PX`.
+		ascent: f32,
+		/// From one baseline to the next, at `ATLAS_PX`.
+		line_height: f32,
+	},
+	Sdf(SdfFont),
+}
+impl Glyphs {
+	fn atlas(&self) -> &Tex2d {
+		match self {
+			Self::Raster { atlas, .. } => atlas,
+			Self::Sdf(font) => &font.atlas,
+		}
+	}
+
+	fn fragment_entry_point(&self) -> &'static str {
+		match self {
+			Self::Raster { .. } => "fs_main",
+			Self::Sdf(_) => "fs_sdf",
+		}
+	}
+}
+
+/// Queues text with [`Self::draw_text`] and draws it all with
+/// [`Self::flush`]. Positions are in logical pixels from the top left corner of
+/// the frame, which are [`Self::set_scale_factor`] physical pixels each.
+pub struct TextRenderer {
+	glyphs: Glyphs,
+	shader: wgpu::ShaderModule,
+	layout: wgpu::PipelineLayout,
+	pipeline: wgpu::RenderPipeline,
+	bind_group: wgpu::BindGroup,
+	/// A `SdfUniform`, unused by the raster backend.
+	sdf_uniform_buf: wgpu::Buffer,
+	vtx_buf: wgpu::Buffer,
+	/// How many vertices `vtx_buf` holds.
+	capacity: usize,
+	/// The quads of the queued text, in physical pixels.
+	queued: Vec<TextVertex>,
+	width: u32,
+	height: u32,
+	scale_factor: f32,
+}
+impl TextRenderer {
+	/// The pixel height the glyphs are rasterized at. Text is scaled from it.
+	pub const ATLAS_PX: f32 = 32.;
+	const ATLAS_WIDTH: u32 = 512;
+	/// The space. The atlas holds it and the printable characters after it.
+	const FIRST_CHAR: char = ' ';
+	const LAST_CHAR: char = '~';
+	/// Drawn for characters that aren't in the atlas.
+	const REPLACEMENT_CHAR: char = '?';
+
+	/// Prepares the glyphs of `backend`, for drawing into `width` x `height`
+	/// frames of `format`.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+		backend: TextBackend,
+	) -> Self {
+		let glyphs = match backend {
+			TextBackend::Raster => rasterize_egui_font(device, queue),
+			TextBackend::Sdf(font) => Glyphs::Sdf(font),
+		};
+		let atlas = glyphs.atlas();
+
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Text Bind Group Layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Texture {
+							sample_type: wgpu::TextureSampleType::Float {
+								filterable: true,
+							},
+							view_dimension: wgpu::TextureViewDimension::D2,
+							multisampled: false,
+						},
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Sampler(
+							wgpu::SamplerBindingType::Filtering,
+						),
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 2,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Uniform,
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+				],
+			});
+		// No outline until one is set.
+		let sdf_uniform = SdfUniform {
+			outline_color: [0.; 4],
+			outline_width: 0.,
+			_pad: [0.; 3],
+		};
+		let sdf_uniform_buf =
+			device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Text SDF Uniform"),
+				contents: bytemuck::bytes_of(&sdf_uniform),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("Text Bind Group"),
+			layout: &bind_group_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&atlas.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: sdf_uniform_buf.as_entire_binding(),
+				},
+			],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Text Pipeline Layout"),
+			bind_group_layouts: &[&bind_group_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = create_pipeline(
+			device,
+			&layout,
+			&shader,
+			glyphs.fragment_entry_point(),
+			format,
+		);
+		// Enough for a line of text, grown as needed.
+		let capacity = 6 * 64;
+		Self {
+			glyphs,
+			shader,
+			layout,
+			pipeline,
+			bind_group,
+			sdf_uniform_buf,
+			vtx_buf: create_vertex_buffer(device, capacity),
+			capacity,
+			queued: Vec::new(),
+			width,
+			height,
+			scale_factor: 1.,
+		}
+	}
+
+	/// The glyphs. The raster backend's hold how much of each texel they cover
+	/// in the red channel, and the SDF backend's their distance fields in alpha.
+	pub fn atlas(&self) -> &Tex2d {
+		self.glyphs.atlas()
+	}
+
+	/// Recreates the pipeline for a different frame format.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(
+			device,
+			&self.layout,
+			&self.shader,
+			self.glyphs.fragment_entry_point(),
+			format,
+		);
+	}
+
+	/// Outlines the glyphs of the SDF backend with `color`, `width` further out
+	/// than their edge in the distance field, where the edge is at 0.5. A width
+	/// of 0 draws no outline. The raster backend ignores it. Returns the number
+	/// of bytes uploaded.
+	pub fn set_outline(&self, queue: &wgpu::Queue, width: f32, color: [f32; 4]) -> u64 {
+		let uniform = SdfUniform {
+			outline_color: color,
+			outline_width: width.clamp(0., 0.5),
+			_pad: [0.; 3],
+		};
+		queue.write_buffer(&self.sdf_uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<SdfUniform>() as u64
+	}
+
+	/// Sets the size of the frames text is drawn into.
+	pub fn resize(&mut self, width: u32, height: u32) {
+		self.width = width;
+		self.height = height;
+	}
+
+	/// Sets how many physical pixels the text's logical pixels are, from the
+	/// next [`Self::draw_text`]. Defaults to 1.
+	pub fn set_scale_factor(&mut self, scale_factor: f32) {
+		self.scale_factor = scale_factor;
+	}
+
+	pub fn scale_factor(&self) -> f32 {
+		self.scale_factor
+	}
+
+	/// Whether no text is queued.
+	pub fn is_empty(&self) -> bool {
+		self.queued.is_empty()
+	}
+
+	/// Queues `text` with the top left corner of its first line at `x`, `y`, and
+	/// lines `scale` pixels high. Lines are separated by `'\n'`. The raster
+	/// backend draws characters other than printable ASCII as `'?'`, and the SDF
+	/// backend characters missing from its font.
+	pub fn draw_text(
+		&mut self,
+		text: &str,
+		x: f32,
+		y: f32,
+		scale: f32,
+		color: [f32; 4],
+	) {
+		let (x, y, scale) = (
+			x * self.scale_factor,
+			y * self.scale_factor,
+			scale * self.scale_factor,
+		);
+		let vertex = |pos, uv| TextVertex { pos, uv, color };
+		let (atlas, glyphs, ascent, line_height) = match &self.glyphs {
+			Glyphs::Raster {
+				atlas,
+				glyphs,
+				ascent,
+				line_height,
+			} => (atlas, glyphs, *ascent, *line_height),
+			Glyphs::Sdf(font) => {
+				for quad in font.layout_text(text, scale) {
+					let [left, top] = [x + quad.min[0], y + quad.min[1]];
+					let [right, bottom] = [x + quad.max[0], y + quad.max[1]];
+					let uv = quad.uv_rect;
+					self.queued.extend([
+						vertex([left, top], [uv.u_min, uv.v_min]),
+						vertex([left, bottom], [uv.u_min, uv.v_max]),
+						vertex([right, bottom], [uv.u_max, uv.v_max]),
+						vertex([left, top], [uv.u_min, uv.v_min]),
+						vertex([right, bottom], [uv.u_max, uv.v_max]),
+						vertex([right, top], [uv.u_max, uv.v_min]),
+					]);
+				}
+				return;
+			}
+		};
+		let factor = scale / Self::ATLAS_PX;
+		let [atlas_width, atlas_height] =
+			[atlas.texture.width() as f32, atlas.texture.height() as f32];
+		let mut pen_x = x;
+		let mut baseline = y + ascent * factor;
+		for c in text.chars() {
+			if c == '\n' {
+				pen_x = x;
+				baseline += line_height * factor;
+				continue;
+			}
+			let glyph = raster_glyph(glyphs, c);
+			if glyph.size != [0, 0] {
+				let left = pen_x + glyph.offset[0] * factor;
+				let top = baseline + glyph.offset[1] * factor;
+				let right = left + glyph.size[0] as f32 * factor;
+				let bottom = top + glyph.size[1] as f32 * factor;
+				let u_min = glyph.origin[0] as f32 / atlas_width;
+				let v_min = glyph.origin[1] as f32 / atlas_height;
+				let u_max = (glyph.origin[0] + glyph.size[0]) as f32 / atlas_width;
+				let v_max = (glyph.origin[1] + glyph.size[1]) as f32 / atlas_height;
+				self.queued.extend([
+					vertex([left, top], [u_min, v_min]),
+					vertex([left, bottom], [u_min, v_max]),
+					vertex([right, bottom], [u_max, v_max]),
+					vertex([left, top], [u_min, v_min]),
+					vertex([right, bottom], [u_max, v_max]),
+					vertex([right, top], [u_max, v_min]),
+				]);
+			}
+			pen_x += glyph.advance * factor;
+		}
+	}
+
+	/// Draws the queued text into `render_pass` and clears the queue. Returns
+	/// the number of bytes uploaded.
+	pub fn flush<'a>(
+		&'a mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		render_pass: &mut wgpu::RenderPass<'a>,
+	) -> u64 {
+		if self.queued.is_empty() {
+			return 0;
+		}
+		let (width, height) = (self.width as f32, self.height as f32);
+		// Pixels to clip space, with y pointing down.
+		for vertex in &mut self.queued {
+			let [x, y] = vertex.pos;
+			vertex.pos = [x / width * 2. - 1., 1. - y / height * 2.];
+		}
+		if self.queued.len() > self.capacity {
+			self.capacity = self.queued.len().next_power_of_two();
+			self.vtx_buf = create_vertex_buffer(device, self.capacity);
+		}
+		let bytes: &[u8] = bytemuck::cast_slice(&self.queued);
+		queue.write_buffer(&self.vtx_buf, 0, bytes);
+		let uploaded = bytes.len() as u64;
+		let num_vertices = self.queued.len() as u32;
+		self.queued.clear();
+
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.set_vertex_buffer(0, self.vtx_buf.slice(..));
+		render_pass.draw(0..num_vertices, 0..1);
+		uploaded
+	}
+}
+
+/// The glyph of `c` in the raster atlas, or of `'?'` if it isn't there.
+fn raster_glyph(glyphs: &[GlyphInfo], c: char) -> GlyphInfo {
+	let c = if (TextRenderer::FIRST_CHAR..=TextRenderer::LAST_CHAR).contains(&c) {
+		c
+	} else {
+		TextRenderer::REPLACEMENT_CHAR
+	};
+	glyphs[c as usize - TextRenderer::FIRST_CHAR as usize]
+}
+
+/// Egui's monospace font.
+fn egui_font() -> FontArc {
+	let font_data = egui::FontDefinitions::default()
+		.font_data
+		.remove("Hack")
+		.expect("egui has a Hack font");
+	FontArc::try_from_vec(font_data.font.into_owned()).expect("egui's fonts are valid")
+}
+
+/// Rasterizes the glyphs of egui's monospace font into an atlas.
+fn rasterize_egui_font(device: &wgpu::Device, queue: &wgpu::Queue) -> Glyphs {
+	let font = egui_font();
+	let (pixels, shape, glyphs) = rasterize_glyphs(&font);
+	let atlas = Tex2d::new_from_r8(
+		device,
+		queue,
+		Some("Glyph Atlas"),
+		&pixels,
+		shape,
+		SamplerConfig {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		},
+	);
+	let scaled = font.as_scaled(TextRenderer::ATLAS_PX);
+	Glyphs::Raster {
+		atlas,
+		glyphs,
+		ascent: scaled.ascent(),
+		line_height: scaled.height() + scaled.line_gap(),
+	}
+}
+
+/// Packs the glyphs of `FIRST_CHAR` to `LAST_CHAR` into rows of an R8 image, a
+/// pixel apart so that filtering doesn't bleed between them.
+fn rasterize_glyphs(font: &FontArc) -> (Vec<u8>, Shape, Vec<GlyphInfo>) {
+	const PADDING: u32 = 1;
+	let width = TextRenderer::ATLAS_WIDTH;
+	let scaled = font.as_scaled(TextRenderer::ATLAS_PX);
+	let mut glyphs = Vec::new();
+	// The outlines and their glyphs, drawn once the atlas' height is known.
+	let mut outlines = Vec::new();
+	let (mut x, mut y, mut row_height) = (PADDING, PADDING, 0);
+	for c in TextRenderer::FIRST_CHAR..=TextRenderer::LAST_CHAR {
+		let glyph = scaled.scaled_glyph(c);
+		let mut info = GlyphInfo {
+			advance: scaled.h_advance(glyph.id),
+			..Default::default()
+		};
+		if let Some(outline) = font.outline_glyph(glyph) {
+			let bounds = outline.px_bounds();
+			let size = [bounds.width() as u32, bounds.height() as u32];
+			if x + size[0] + PADDING > width {
+				x = PADDING;
+				y += row_height + PADDING;
+				row_height = 0;
+			}
+			info.origin = [x, y];
+			info.size = size;
+			info.offset = [bounds.min.x, bounds.min.y];
+			x += size[0] + PADDING;
+			row_height = row_height.max(size[1]);
+			outlines.push((outline, info.origin));
+		}
+		glyphs.push(info);
+	}
+	let height = y + row_height + PADDING;
+
+	let mut pixels = vec![0; (width * height) as usize];
+	for (outline, [origin_x, origin_y]) in outlines {
+		outline.draw(|x, y, coverage| {
+			let i = (origin_y + y) * width + origin_x + x;
+			pixels[i as usize] = (coverage.clamp(0., 1.) * 255.) as u8;
+		});
+	}
+	(pixels, Shape { width, height }, glyphs)
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+	device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("Text Vertices"),
+		size: (capacity * std::mem::size_of::<TextVertex>()) as u64,
+		usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	})
+}
+
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	fragment_entry_point: &str,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Text Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[wgpu::VertexBufferLayout {
+				array_stride: std::mem::size_of::<TextVertex>() as u64,
+				step_mode: wgpu::VertexStepMode::Vertex,
+				attributes: &wgpu::vertex_attr_array![
+					0 => Float32x2,
+					1 => Float32x2,
+					2 => Float32x4,
+				],
+			}],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: fragment_entry_point,
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ascii_glyphs_are_drawn_into_the_atlas() {
+		let (pixels, shape, glyphs) = rasterize_glyphs(&egui_font());
+		assert_eq!(pixels.len(), (shape.width * shape.height) as usize);
+		let space = raster_glyph(&glyphs, ' ');
+		assert_eq!(space.size, [0, 0]);
+		assert!(space.advance > 0.);
+		for c in '!'..=TextRenderer::LAST_CHAR {
+			let GlyphInfo { origin, size, .. } = raster_glyph(&glyphs, c);
+			let rows = origin[1]..origin[1] + size[1];
+			let covered = rows
+				.flat_map(|y| {
+					let start = (y * shape.width + origin[0]) as usize;
+					&pixels[start..start + size[0] as usize]
+				})
+				.any(|&p| p > 0);
+			assert!(covered, "{c:?} is empty");
+		}
+	}
+}
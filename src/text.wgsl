@@ -0,0 +1,54 @@
+// Draws the glyph quads of `text.rs`, positioned in clip space.
+
+struct SdfUniform {
+	outline_color: vec4<f32>,
+	// How far out from the edge the outline reaches, in distance.
+	outline_width: f32,
+};
+
+@group(0) @binding(0)
+var atlas_t: texture_2d<f32>;
+@group(0) @binding(1)
+var atlas_s: sampler;
+@group(0) @binding(2)
+var<uniform> sdf: SdfUniform;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+	@location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+	@location(0) pos: vec2<f32>,
+	@location(1) uv: vec2<f32>,
+	@location(2) color: vec4<f32>,
+) -> VertexOutput {
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(pos, 0.0, 1.0);
+	out.uv = uv;
+	out.color = color;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	// The atlas holds how much of each texel the glyph covers.
+	let coverage = textureSample(atlas_t, atlas_s, in.uv).r;
+	return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+
+@fragment
+fn fs_sdf(in: VertexOutput) -> @location(0) vec4<f32> {
+	// The atlas holds the distance to the glyph's edge, at 0.5, in alpha.
+	let distance = textureSample(atlas_t, atlas_s, in.uv).a;
+	// Half a pixel on screen, so the edge is antialiased at any scale.
+	let smoothing = max(fwidth(distance) * 0.5, 0.0001);
+	let fill = smoothstep(0.5 - smoothing, 0.5 + smoothing, distance);
+	let outline_edge = 0.5 - sdf.outline_width;
+	let outline = smoothstep(outline_edge - smoothing, outline_edge + smoothing, distance);
+	let color = mix(sdf.outline_color.rgb, in.color.rgb, fill);
+	let alpha = mix(sdf.outline_color.a * outline, in.color.a, fill);
+	return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,131 @@
+//! Loading images in the background, so that the render thread doesn't wait for
+//! files or the network.
+
+use color_eyre::Result;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// An image read and decoded by a [`TextureLoader`].
+pub struct LoadedImage {
+	/// The path or URL the image was loaded from.
+	pub source: String,
+	pub image: Result<image::DynamicImage>,
+}
+
+/// Reads and decodes images off the render thread: on other threads natively,
+/// and with `fetch` on the web. The images are collected with [`Self::poll`],
+/// to be turned into textures by the render thread.
+pub struct TextureLoader {
+	/// `None` is sent when there's nothing to load after all, like when a file
+	/// picker is cancelled.
+	sender: Sender<Option<LoadedImage>>,
+	receiver: Receiver<Option<LoadedImage>>,
+	/// Images still being loaded.
+	pending: usize,
+}
+impl Default for TextureLoader {
+	fn default() -> Self {
+		let (sender, receiver) = channel();
+		Self {
+			sender,
+			receiver,
+			pending: 0,
+		}
+	}
+}
+impl TextureLoader {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Starts reading and decoding the image file at `path` on another thread.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn load_path(&mut self, path: std::path::PathBuf) {
+		use color_eyre::eyre::WrapErr;
+
+		let sender = self.sender.clone();
+		std::thread::spawn(move || {
+			let image = image::open(&path)
+				.wrap_err_with(|| format!("Failed to load texture {}", path.display()));
+			let source = path.to_string_lossy().into_owned();
+			// The loader may have been dropped in the meantime.
+			sender.send(Some(LoadedImage { source, image })).ok();
+		});
+		self.pending += 1;
+	}
+
+	/// Starts fetching and decoding the image at `url`.
+	#[cfg(target_arch = "wasm32")]
+	pub fn load_url(&mut self, url: &str) {
+		let source = url.to_owned();
+		let sender = self.sender.clone();
+		wasm_bindgen_futures::spawn_local(async move {
+			let image = fetch(&source)
+				.await
+				.and_then(|bytes| Ok(image::load_from_memory(&bytes)?));
+			sender.send(Some(LoadedImage { source, image })).ok();
+		});
+		self.pending += 1;
+	}
+
+	/// Shows the browser's file picker with `dialog`, then reads and decodes the
+	/// picked image, if any.
+	#[cfg(target_arch = "wasm32")]
+	pub fn load_picked(&mut self, dialog: rfd::AsyncFileDialog) {
+		use color_eyre::eyre::eyre;
+
+		let sender = self.sender.clone();
+		wasm_bindgen_futures::spawn_local(async move {
+			let loaded = match dialog.pick_file().await {
+				Some(file) => {
+					let source = file.file_name();
+					let image = image::load_from_memory(&file.read().await)
+						.map_err(|err| eyre!("Failed to load texture {source}: {err}"));
+					Some(LoadedImage { source, image })
+				}
+				None => None,
+			};
+			sender.send(loaded).ok();
+		});
+		self.pending += 1;
+	}
+
+	/// The images that finished loading since the last call, successfully or not.
+	pub fn poll(&mut self) -> Vec<LoadedImage> {
+		let loaded: Vec<_> = self.receiver.try_iter().collect();
+		self.pending -= loaded.len();
+		loaded.into_iter().flatten().collect()
+	}
+
+	/// How many images are still being loaded.
+	pub fn pending(&self) -> usize {
+		self.pending
+	}
+}
+
+/// Reads the body of the response to a GET request for `url`.
+#[cfg(target_arch = "wasm32")]
+async fn fetch(url: &str) -> Result<Vec<u8>> {
+	use color_eyre::eyre::{ensure, eyre};
+	use wasm_bindgen::JsCast;
+	use wasm_bindgen_futures::JsFuture;
+
+	let window = web_sys::window().ok_or_else(|| eyre!("There is no window"))?;
+	let response = JsFuture::from(window.fetch_with_str(url))
+		.await
+		.map_err(|err| eyre!("Failed to fetch {url}: {err:?}"))?;
+	let response: web_sys::Response = response
+		.dyn_into()
+		.map_err(|_| eyre!("Fetching {url} didn't return a response"))?;
+	ensure!(
+		response.ok(),
+		"Failed to fetch {url}: status {}",
+		response.status()
+	);
+	let body = response
+		.array_buffer()
+		.map_err(|err| eyre!("Failed to read {url}: {err:?}"))?;
+	let body = JsFuture::from(body)
+		.await
+		.map_err(|err| eyre!("Failed to read {url}: {err:?}"))?;
+	Ok(js_sys::Uint8Array::new(&body).to_vec())
+}
@@ -0,0 +1,327 @@
+//! Streaming the mip levels of textures in and out by how large they appear on
+//! screen, so that distant objects don't hold detail nobody sees.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use image::imageops::FilterType;
+use image::RgbaImage;
+use log::error;
+
+use crate::resources::{Handle, ResourceManager};
+use crate::tex2d::{mip_level_count, SamplerConfig, Tex2d};
+
+/// Mip levels read from disk for a [`TextureStreamer`].
+struct LoadedLevels {
+	handle: Handle<Tex2d>,
+	/// From the finest level read.
+	levels: Result<Vec<(u32, RgbaImage)>>,
+}
+
+/// What a [`TextureStreamer`] knows of one of its textures.
+struct StreamedTexture {
+	path: PathBuf,
+	width: u32,
+	height: u32,
+	mip_level_count: u32,
+	/// The finest mip level uploaded. The levels after it are uploaded too.
+	resident: u32,
+	/// The finest mip level needed by the last update.
+	target: u32,
+	/// The finest mip level the texture's view shows.
+	base: u32,
+	/// Whether levels are being read from disk.
+	loading: bool,
+}
+
+/// Keeps the mip levels of textures loaded by [`Self::load`] down to what their
+/// size on screen needs.
+///
+/// Each texture is created with its full mip chain, but only its coarsest
+/// level is uploaded at first. [`Self::update`] picks the finest level each
+/// needs, from how many pixels it covers. Finer levels are read from its file
+/// and downsampled on another thread, and uploaded by [`Self::poll`]. Once a
+/// coarser level suffices, the texture's view starts from it instead.
+///
+/// The finer levels keep their memory, as wgpu can't free part of a texture,
+/// but aren't sampled. The views of textures are replaced, so bind groups
+/// reading them must be recreated: both methods return their handles.
+pub struct TextureStreamer {
+	textures: HashMap<Handle<Tex2d>, StreamedTexture>,
+	sender: Sender<LoadedLevels>,
+	receiver: Receiver<LoadedLevels>,
+}
+impl Default for TextureStreamer {
+	fn default() -> Self {
+		let (sender, receiver) = channel();
+		Self {
+			textures: HashMap::new(),
+			sender,
+			receiver,
+		}
+	}
+}
+impl TextureStreamer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Loads the image file at `path` into `resources` as a streamed texture,
+	/// with only its coarsest mip level uploaded.
+	pub fn load(
+		&mut self,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		resources: &mut ResourceManager,
+		path: &Path,
+		sampler_config: SamplerConfig,
+	) -> Result<Handle<Tex2d>> {
+		let image = read_image(path)?;
+		let (width, height) = image.dimensions();
+		let mip_level_count = mip_level_count(width, height);
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(&path.to_string_lossy()),
+			size: wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8UnormSrgb,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let coarsest = mip_level_count - 1;
+		write_level(queue, &texture, coarsest, &downsample(&image, coarsest));
+		let tex = Tex2d {
+			view: create_view(&texture, coarsest),
+			texture,
+			sampler: sampler_config.create_sampler(device),
+			sampler_config,
+		};
+		let handle = resources.insert(tex);
+		self.textures.insert(
+			handle,
+			StreamedTexture {
+				path: path.to_owned(),
+				width,
+				height,
+				mip_level_count,
+				resident: coarsest,
+				target: coarsest,
+				base: coarsest,
+				loading: false,
+			},
+		);
+		Ok(handle)
+	}
+
+	/// Picks the finest mip level needed by each texture from the pixels its
+	/// largest side covers on screen, the largest of its `visible_objects`.
+	/// Textures that aren't visible only need their coarsest level.
+	///
+	/// Starts reading the levels that are missing, and moves the views of the
+	/// textures that need coarser levels than they show. Returns the handles of
+	/// the textures whose views changed.
+	pub fn update(
+		&mut self,
+		resources: &mut ResourceManager,
+		visible_objects: &[(Handle<Tex2d>, f32)],
+	) -> Vec<Handle<Tex2d>> {
+		let mut coverage: HashMap<Handle<Tex2d>, f32> = HashMap::new();
+		for &(handle, pixels) in visible_objects {
+			let max = coverage.entry(handle).or_default();
+			*max = max.max(pixels);
+		}
+		let mut changed = Vec::new();
+		for (&handle, texture) in &mut self.textures {
+			let pixels = coverage.get(&handle).copied().unwrap_or(0.);
+			texture.target = target_level(texture, pixels);
+			if texture.target < texture.resident && !texture.loading {
+				texture.loading = true;
+				spawn_load(&self.sender, handle, texture);
+			}
+			let base = texture.target.max(texture.resident);
+			if base != texture.base {
+				if let Some(tex) = resources.get_mut(handle) {
+					tex.view = create_view(&tex.texture, base);
+					texture.base = base;
+					changed.push(handle);
+				}
+			}
+		}
+		changed
+	}
+
+	/// Uploads the mip levels read since the last call, and moves the views of
+	/// their textures to the finest level they need. Returns the handles of the
+	/// textures whose views changed.
+	pub fn poll(
+		&mut self,
+		queue: &wgpu::Queue,
+		resources: &mut ResourceManager,
+	) -> Vec<Handle<Tex2d>> {
+		let mut changed = Vec::new();
+		for loaded in self.receiver.try_iter() {
+			let Some(texture) = self.textures.get_mut(&loaded.handle) else {
+				continue;
+			};
+			texture.loading = false;
+			let levels = match loaded.levels {
+				Ok(levels) => levels,
+				Err(err) => {
+					error!("{err:?}");
+					continue;
+				}
+			};
+			let Some(tex) = resources.get_mut(loaded.handle) else {
+				continue;
+			};
+			for (level, image) in &levels {
+				write_level(queue, &tex.texture, *level, image);
+			}
+			if let Some(&(finest, _)) = levels.first() {
+				texture.resident = texture.resident.min(finest);
+			}
+			// The texture may need coarser levels by now.
+			let base = texture.target.max(texture.resident);
+			if base != texture.base {
+				tex.view = create_view(&tex.texture, base);
+				texture.base = base;
+				changed.push(loaded.handle);
+			}
+		}
+		changed
+	}
+
+	/// The finest mip level uploaded for `handle`, if it is streamed.
+	pub fn resident_level(&self, handle: Handle<Tex2d>) -> Option<u32> {
+		self.textures.get(&handle).map(|texture| texture.resident)
+	}
+
+	/// The finest mip level `handle` needed at the last update, if it is
+	/// streamed.
+	pub fn target_level(&self, handle: Handle<Tex2d>) -> Option<u32> {
+		self.textures.get(&handle).map(|texture| texture.target)
+	}
+
+	/// Stops streaming `handle`. The texture stays in its resource manager.
+	pub fn remove(&mut self, handle: Handle<Tex2d>) {
+		self.textures.remove(&handle);
+	}
+}
+
+/// Reads the levels of `texture` from its target down to the resident one on
+/// another thread.
+fn spawn_load(
+	sender: &Sender<LoadedLevels>,
+	handle: Handle<Tex2d>,
+	texture: &StreamedTexture,
+) {
+	let path = texture.path.clone();
+	let levels = texture.target..texture.resident;
+	let sender = sender.clone();
+	std::thread::spawn(move || {
+		let levels = read_image(&path).map(|image| {
+			levels
+				.map(|level| (level, downsample(&image, level)))
+				.collect()
+		});
+		// The streamer may have been dropped in the meantime.
+		sender.send(LoadedLevels { handle, levels }).ok();
+	});
+}
+
+/// The finest mip level needed to show `texture` over `pixels` along its
+/// largest side, which is 0 from its full size on.
+fn target_level(texture: &StreamedTexture, pixels: f32) -> u32 {
+	let coarsest = texture.mip_level_count - 1;
+	if pixels <= 0. {
+		return coarsest;
+	}
+	let size = texture.width.max(texture.height) as f32;
+	let level = (size / pixels).log2().floor().max(0.);
+	(level as u32).min(coarsest)
+}
+
+fn read_image(path: &Path) -> Result<RgbaImage> {
+	let image = image::open(path)
+		.wrap_err_with(|| format!("Failed to load texture {}", path.display()))?;
+	Ok(image.into_rgba8())
+}
+
+/// `image` at mip `level`, halved `level` times down to at least 1x1.
+fn downsample(image: &RgbaImage, level: u32) -> RgbaImage {
+	if level == 0 {
+		return image.clone();
+	}
+	let (width, height) = image.dimensions();
+	let (width, height) = ((width >> level).max(1), (height >> level).max(1));
+	image::imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+fn write_level(
+	queue: &wgpu::Queue,
+	texture: &wgpu::Texture,
+	level: u32,
+	image: &RgbaImage,
+) {
+	let (width, height) = image.dimensions();
+	queue.write_texture(
+		wgpu::ImageCopyTexture {
+			texture,
+			mip_level: level,
+			origin: wgpu::Origin3d::ZERO,
+			aspect: wgpu::TextureAspect::All,
+		},
+		image,
+		wgpu::ImageDataLayout {
+			offset: 0,
+			bytes_per_row: Some(4 * width),
+			rows_per_image: None,
+		},
+		wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		},
+	);
+}
+
+/// A view of `texture` from mip level `base` on.
+fn create_view(texture: &wgpu::Texture, base: u32) -> wgpu::TextureView {
+	texture.create_view(&wgpu::TextureViewDescriptor {
+		base_mip_level: base,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn levels_follow_the_coverage() {
+		let texture = StreamedTexture {
+			path: PathBuf::new(),
+			width: 1024,
+			height: 512,
+			mip_level_count: mip_level_count(1024, 512),
+			resident: 10,
+			target: 10,
+			base: 10,
+			loading: false,
+		};
+		assert_eq!(target_level(&texture, 2048.), 0);
+		assert_eq!(target_level(&texture, 1024.), 0);
+		assert_eq!(target_level(&texture, 256.), 2);
+		assert_eq!(target_level(&texture, 0.5), 10);
+		// Textures out of sight only need their coarsest level.
+		assert_eq!(target_level(&texture, 0.), 10);
+	}
+}
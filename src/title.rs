@@ -0,0 +1,178 @@
+//! The window title, filled in from a template every few frames.
+
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use std::fmt::Write;
+
+/// A value a [`TitleFormatter`] can show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Token {
+	Fps,
+	FrameMs,
+	Resolution,
+	Adapter,
+	PresentMode,
+	GpuMs,
+}
+impl Token {
+	const ALL: [(&'static str, Token); 6] = [
+		("fps", Token::Fps),
+		("frame_ms", Token::FrameMs),
+		("resolution", Token::Resolution),
+		("adapter", Token::Adapter),
+		("present_mode", Token::PresentMode),
+		("gpu_ms", Token::GpuMs),
+	];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+	Text(String),
+	Token(Token),
+}
+
+/// What the tokens of a [`TitleFormatter`] are replaced with.
+#[derive(Copy, Clone, Debug)]
+pub struct TitleInfo<'a> {
+	pub fps: f32,
+	/// The smoothed time between frames.
+	pub frame_ms: f32,
+	pub width: u32,
+	pub height: u32,
+	pub adapter: &'a str,
+	pub present_mode: wgpu::PresentMode,
+	/// 0 when timestamp queries aren't supported.
+	pub gpu_ms: f32,
+}
+
+/// Formats the window title from a template, in which `{fps}`, `{frame_ms}`,
+/// `{resolution}`, `{adapter}`, `{present_mode}` and `{gpu_ms}` are replaced
+/// with their values. `{{` and `}}` are literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TitleFormatter {
+	segments: Vec<Segment>,
+}
+impl TitleFormatter {
+	pub const DEFAULT_TEMPLATE: &'static str = "{adapter} | {resolution} | {fps} FPS";
+
+	/// Fails if `template` has unknown tokens or unmatched braces.
+	pub fn new(template: &str) -> Result<Self> {
+		let mut segments = Vec::new();
+		let mut text = String::new();
+		let mut chars = template.chars();
+		while let Some(c) = chars.next() {
+			match c {
+				'{' if chars.as_str().starts_with('{') => {
+					chars.next();
+					text.push('{');
+				}
+				'}' if chars.as_str().starts_with('}') => {
+					chars.next();
+					text.push('}');
+				}
+				'{' => {
+					let rest = chars.as_str();
+					let Some(end) = rest.find('}') else {
+						bail!("Unclosed '{{' in title template {template:?}");
+					};
+					let name = &rest[..end];
+					let Some(&(_, token)) =
+						Token::ALL.iter().find(|(token, _)| *token == name)
+					else {
+						let known: Vec<_> =
+							Token::ALL.iter().map(|(name, _)| name).collect();
+						bail!("Unknown token {{{name}}} in title template, expected one of {known:?}");
+					};
+					if !text.is_empty() {
+						segments.push(Segment::Text(std::mem::take(&mut text)));
+					}
+					segments.push(Segment::Token(token));
+					chars = rest[end + 1..].chars();
+				}
+				'}' => bail!("Unmatched '}}' in title template {template:?}"),
+				c => text.push(c),
+			}
+		}
+		if !text.is_empty() {
+			segments.push(Segment::Text(text));
+		}
+		Ok(Self { segments })
+	}
+
+	/// Replaces `title` with the template filled in with `info`.
+	pub fn format_into(&self, info: &TitleInfo, title: &mut String) {
+		title.clear();
+		for segment in &self.segments {
+			let token = match segment {
+				Segment::Text(text) => {
+					title.push_str(text);
+					continue;
+				}
+				Segment::Token(token) => token,
+			};
+			match token {
+				Token::Fps => write!(title, "{:.1}", info.fps),
+				Token::FrameMs => write!(title, "{:.2}", info.frame_ms),
+				Token::Resolution => write!(title, "{}x{}", info.width, info.height),
+				Token::Adapter => write!(title, "{}", info.adapter),
+				Token::PresentMode => write!(title, "{:?}", info.present_mode),
+				Token::GpuMs => write!(title, "{:.2}", info.gpu_ms),
+			}
+			.ok();
+		}
+	}
+
+	/// The template filled in with `info`.
+	pub fn format(&self, info: &TitleInfo) -> String {
+		let mut title = String::new();
+		self.format_into(info, &mut title);
+		title
+	}
+}
+impl Default for TitleFormatter {
+	fn default() -> Self {
+		Self::new(Self::DEFAULT_TEMPLATE).expect("The default template is valid")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INFO: TitleInfo<'static> = TitleInfo {
+		fps: 59.94,
+		frame_ms: 16.683,
+		width: 1280,
+		height: 720,
+		adapter: "Test GPU",
+		present_mode: wgpu::PresentMode::Fifo,
+		gpu_ms: 4.5,
+	};
+
+	#[test]
+	fn fills_in_tokens() {
+		let formatter = TitleFormatter::new(
+			"{adapter} {resolution} {{{fps}}} {frame_ms}/{gpu_ms} ms",
+		)
+		.unwrap();
+		assert_eq!(
+			formatter.format(&INFO),
+			"Test GPU 1280x720 {59.9} 16.68/4.50 ms"
+		);
+		assert_eq!(
+			TitleFormatter::default().format(&INFO),
+			"Test GPU | 1280x720 | 59.9 FPS"
+		);
+		assert_eq!(
+			TitleFormatter::new("{present_mode}").unwrap().format(&INFO),
+			"Fifo"
+		);
+	}
+
+	#[test]
+	fn rejects_bad_templates() {
+		assert!(TitleFormatter::new("{fps").is_err());
+		assert!(TitleFormatter::new("fps}").is_err());
+		assert!(TitleFormatter::new("{frames}").is_err());
+	}
+}
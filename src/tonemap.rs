@@ -0,0 +1,241 @@
+//! Tone mapping: HDR frames are compressed into the range the surface can show.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::tex2d::Tex2d;
+
+/// The format HDR frames are drawn in, before tone mapping.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The curve mapping HDR colors to the 0 to 1 range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapper {
+	/// `c / (1 + c)`, per channel. Preserves colors well, but washes out bright
+	/// ones.
+	Reinhard,
+	/// An approximation of the ACES filmic curve, with more contrast and
+	/// saturation.
+	Aces,
+}
+
+/// How HDR frames are tone mapped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapSettings {
+	pub tone_mapper: ToneMapper,
+	/// Colors are multiplied by this before the curve.
+	pub exposure: f32,
+	/// The mapped colors are raised to `1 / gamma`. sRGB surfaces already encode
+	/// their colors, so 1 leaves them as they are.
+	pub gamma: f32,
+}
+impl ToneMapSettings {
+	/// Tone maps a linear HDR color on the CPU, like `tonemap.wgsl` does on the
+	/// GPU.
+	pub fn map(&self, hdr: [f32; 3]) -> [f32; 3] {
+		hdr.map(|x| {
+			let x = (x * self.exposure).max(0.);
+			let mapped = match self.tone_mapper {
+				ToneMapper::Reinhard => x / (1. + x),
+				// Krzysztof Narkowicz's fit of the ACES filmic curve.
+				ToneMapper::Aces => {
+					let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+					((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0., 1.)
+				}
+			};
+			mapped.powf(1. / self.gamma)
+		})
+	}
+}
+impl Default for ToneMapSettings {
+	fn default() -> Self {
+		Self {
+			tone_mapper: ToneMapper::Aces,
+			exposure: 1.0,
+			gamma: 1.0,
+		}
+	}
+}
+
+/// The layout of the shader's `ToneMapUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct ToneMapUniform {
+	/// 0 for Reinhard, 1 for ACES.
+	tone_mapper: u32,
+	exposure: f32,
+	gamma: f32,
+	_pad: u32,
+}
+impl From<ToneMapSettings> for ToneMapUniform {
+	fn from(settings: ToneMapSettings) -> Self {
+		Self {
+			tone_mapper: match settings.tone_mapper {
+				ToneMapper::Reinhard => 0,
+				ToneMapper::Aces => 1,
+			},
+			exposure: settings.exposure,
+			gamma: settings.gamma,
+			_pad: 0,
+		}
+	}
+}
+
+/// Draws an HDR frame into an LDR texture with one of the [`ToneMapper`]s.
+pub struct ToneMapPass {
+	settings: ToneMapSettings,
+	uniform_buf: wgpu::Buffer,
+	uniform_bind_group: wgpu::BindGroup,
+	/// Layout of the bind group of the input, see [`Tex2d::layout`].
+	texture_layout: wgpu::BindGroupLayout,
+	sampler: wgpu::Sampler,
+	pipeline: wgpu::RenderPipeline,
+}
+impl ToneMapPass {
+	/// Creates a pass drawing into `format` textures.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		format: wgpu::TextureFormat,
+	) -> Self {
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Tone Map Uniform"),
+			size: std::mem::size_of::<ToneMapUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let uniform_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Tone Map Uniform Layout"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("tone_map_uniform_bind_group"),
+			layout: &uniform_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			}],
+		});
+		let texture_layout = Tex2d::layout(device);
+		// The input has the same size as the output, so there is no filtering.
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("Tone Map Sampler"),
+			..Default::default()
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("tonemap.wgsl"));
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Tone Map Pipeline Layout"),
+			bind_group_layouts: &[&uniform_layout, &texture_layout],
+			push_constant_ranges: &[],
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Tone Map Pipeline"),
+			layout: Some(&layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[Some(format.into())],
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview: None,
+		});
+
+		let mut result = Self {
+			settings: ToneMapSettings::default(),
+			uniform_buf,
+			uniform_bind_group,
+			texture_layout,
+			sampler,
+			pipeline,
+		};
+		result.set_settings(queue, ToneMapSettings::default());
+		result
+	}
+
+	pub fn settings(&self) -> ToneMapSettings {
+		self.settings
+	}
+
+	pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: ToneMapSettings) {
+		self.settings = settings;
+		let uniform = ToneMapUniform::from(settings);
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+	}
+
+	/// Records drawing the tone mapped `input` into all of `output`. `input` must
+	/// be an [`HDR_FORMAT`] texture of the same size.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		input_view: &wgpu::TextureView,
+		output_view: &wgpu::TextureView,
+	) {
+		let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("tone_map_input_bind_group"),
+			layout: &self.texture_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(input_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+			],
+		});
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Tone Map Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					// Every pixel is drawn over.
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+		pass.set_bind_group(1, &input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reinhard_maps_bright_red_into_range() {
+		let settings = ToneMapSettings {
+			tone_mapper: ToneMapper::Reinhard,
+			..Default::default()
+		};
+		let [r, g, b] = settings.map([2., 0., 0.]);
+		// c / (1 + c), so 2 becomes 2/3 and the channels stay below 1.
+		assert!((r - 2. / 3.).abs() < 1e-6, "{r}");
+		assert_eq!((g, b), (0., 0.));
+		assert!(settings.map([1000., 0., 0.])[0] < 1.);
+	}
+}
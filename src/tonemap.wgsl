@@ -0,0 +1,58 @@
+// Tone mapping: compresses an HDR frame into the 0 to 1 range.
+
+struct ToneMapUniform {
+	// 0 for Reinhard, 1 for ACES.
+	tone_mapper: u32,
+	exposure: f32,
+	gamma: f32,
+};
+@group(0) @binding(0)
+var<uniform> tone_map: ToneMapUniform;
+
+@group(1) @binding(0)
+var src_t: texture_2d<f32>;
+@group(1) @binding(1)
+var src_s: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen, with UVs from 0 to 1 over the visible part.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.uv = uv;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+	return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+	let a = 2.51;
+	let b = 0.03;
+	let c = 2.43;
+	let d = 0.59;
+	let e = 0.14;
+	return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let hdr = textureSample(src_t, src_s, in.uv);
+	let exposed = max(hdr.rgb * tone_map.exposure, vec3<f32>(0.0));
+	var mapped: vec3<f32>;
+	if tone_map.tone_mapper == 0u {
+		mapped = reinhard(exposed);
+	} else {
+		mapped = aces(exposed);
+	}
+	return vec4<f32>(pow(mapped, vec3<f32>(1.0 / tone_map.gamma)), hdr.a);
+}
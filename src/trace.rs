@@ -0,0 +1,60 @@
+//! Logging, and exporting the spans of the render loop to `chrome://tracing`.
+
+use env_logger::Env;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::layer::SubscriberExt;
+
+/// The environment variable that enables the trace when set to `1`.
+const TRACE_VAR: &str = "WGPU_TRACE";
+/// Where the trace is written, in the working directory.
+const TRACE_FILE: &str = "trace.json";
+
+/// Sets up `env_logger`, and when `WGPU_TRACE=1`, a subscriber writing the
+/// spans and events to `trace.json`. Log records are then also added to the
+/// trace, next to the spans they happen in.
+///
+/// The trace is only complete once the returned guard is dropped.
+pub fn init() -> Option<FlushGuard> {
+	let env = Env::default().default_filter_or("wgpu_experiments=debug");
+	let mut builder = env_logger::Builder::from_env(env);
+	if !matches!(std::env::var(TRACE_VAR).as_deref(), Ok("1")) {
+		builder.init();
+		return None;
+	}
+
+	let env_logger = builder.build();
+	log::set_max_level(env_logger.filter());
+	let logger = TeeLogger {
+		env_logger,
+		tracer: tracing_log::LogTracer::new(),
+	};
+	log::set_boxed_logger(Box::new(logger)).expect("Logger was already set");
+
+	let (layer, guard) = ChromeLayerBuilder::new().file(TRACE_FILE).build();
+	tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+		.expect("Tracing subscriber was already set");
+	log::info!("Writing a trace to {TRACE_FILE}");
+	Some(guard)
+}
+
+/// Passes log records to both `env_logger` and `tracing`, as only one logger can
+/// be set.
+struct TeeLogger {
+	env_logger: env_logger::Logger,
+	tracer: tracing_log::LogTracer,
+}
+impl log::Log for TeeLogger {
+	fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+		self.env_logger.enabled(metadata) || self.tracer.enabled(metadata)
+	}
+
+	fn log(&self, record: &log::Record<'_>) {
+		self.env_logger.log(record);
+		self.tracer.log(record);
+	}
+
+	fn flush(&self) {
+		self.env_logger.flush();
+		self.tracer.flush();
+	}
+}
@@ -0,0 +1,42 @@
+use nalgebra::Matrix4;
+
+/// Converts `m` to the layout of a WGSL `mat4x4<f32>`.
+///
+/// Both nalgebra and WGSL store matrices column-major, so this is equivalent to
+/// casting the matrix's bytes. We copy the columns out explicitly anyway so that
+/// the layout doesn't hinge on nalgebra's internal storage order.
+pub fn mat4_to_wgsl(m: Matrix4<f32>) -> [[f32; 4]; 4] {
+	let mut result = [[0.0; 4]; 4];
+	for (i, col) in result.iter_mut().enumerate() {
+		let c = m.column(i);
+		*col = [c[0], c[1], c[2], c[3]];
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Vector3;
+
+	#[test]
+	fn rotation_is_column_major() {
+		// A quarter turn around z, then a translation by (5, 6, 7).
+		let m = Matrix4::new_translation(&Vector3::new(5., 6., 7.))
+			* Matrix4::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2);
+		let expected = [
+			[0., 1., 0., 0.],
+			[-1., 0., 0., 0.],
+			[0., 0., 1., 0.],
+			[5., 6., 7., 1.],
+		];
+		let actual = mat4_to_wgsl(m);
+		for (actual, expected) in actual.iter().flatten().zip(expected.iter().flatten())
+		{
+			assert!(
+				(actual - expected).abs() < 1e-6,
+				"{actual:?} != {expected:?}"
+			);
+		}
+	}
+}
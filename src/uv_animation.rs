@@ -0,0 +1,47 @@
+//! Textures scrolling across surfaces, for water, conveyor belts or fire.
+
+use bytemuck::{Pod, Zeroable};
+
+/// How far textures are scrolled, in the layout of the shader's `UvAnimation`.
+/// Textures only wrap around if their samplers repeat.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct UvAnimation {
+	/// Added to the UVs textures are sampled at.
+	pub offset: [f32; 2],
+	/// How quickly `offset` changes, in UV units per second.
+	pub velocity: [f32; 2],
+}
+impl UvAnimation {
+	/// Moves `offset` by `velocity` over `dt` seconds. It is kept within 0 to 1,
+	/// which repeating samplers can't tell apart from the full offset, so that it
+	/// doesn't lose precision over time.
+	pub fn advance(&mut self, dt: f32) {
+		for (offset, velocity) in self.offset.iter_mut().zip(self.velocity) {
+			*offset = (*offset + velocity * dt).rem_euclid(1.);
+		}
+	}
+
+	/// Whether `offset` changes over time.
+	pub fn is_moving(&self) -> bool {
+		self.velocity != [0., 0.]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn offset_follows_the_velocity() {
+		let mut animation = UvAnimation {
+			velocity: [0.5, 0.],
+			..Default::default()
+		};
+		animation.advance(1.);
+		assert_eq!(animation.offset, [0.5, 0.]);
+		// Wraps around rather than growing past 1.
+		animation.advance(1.5);
+		assert_eq!(animation.offset, [0.25, 0.]);
+	}
+}
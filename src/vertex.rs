@@ -1,4 +1,7 @@
 use bytemuck::{Pod, Zeroable};
+use nalgebra::Matrix4;
+
+use crate::types::mat4_to_wgsl;
 
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 #[repr(C)]
@@ -20,20 +23,102 @@ pub struct Uv {
 	pub v: f32,
 }
 
+/// A surface normal. Should be normalized.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct Normal {
+	pub x: f32,
+	pub y: f32,
+	pub z: f32,
+}
+impl Normal {
+	pub const fn new(x: f32, y: f32, z: f32) -> Self {
+		Self { x, y, z }
+	}
+}
+
+/// A linear RGBA color, with components from 0 to 1.
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct Color {
+	pub r: f32,
+	pub g: f32,
+	pub b: f32,
+	pub a: f32,
+}
+impl Color {
+	pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+		Self { r, g, b, a }
+	}
+}
+impl From<[f32; 4]> for Color {
+	fn from([r, g, b, a]: [f32; 4]) -> Self {
+		Self::new(r, g, b, a)
+	}
+}
+
+/// A vertex of untextured, unlit geometry, such as debug lines.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct ColorVertex {
+	pub pos: Pos,
+	pub color: Color,
+}
+impl ColorVertex {
+	pub const fn new(pos: Pos, color: Color) -> Self {
+		Self { pos, color }
+	}
+
+	pub const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
+		const ATTRIBS: [wgpu::VertexAttribute; 2] =
+			wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<ColorVertex>() as _,
+			step_mode: wgpu::VertexStepMode::Vertex,
+			attributes: &ATTRIBS,
+		}
+	}
+}
+
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 #[repr(C)]
 pub struct Vertex {
 	pub pos: Pos,
 	pub uv: Uv,
+	pub normal: Normal,
+	/// The direction in which `u` increases, for normal mapping.
+	pub tangent: [f32; 3],
+	/// The direction in which `v` increases, for normal mapping.
+	pub bitangent: [f32; 3],
 }
 impl Vertex {
-	pub const fn new(pos: Pos, uv: Uv) -> Self {
-		Vertex { pos, uv }
+	/// Creates a vertex without tangents. They must be set with
+	/// [`Self::with_tangents`] or [`crate::mesh::compute_tangents`] before the
+	/// vertex is rendered.
+	pub const fn new(pos: Pos, uv: Uv, normal: Normal) -> Self {
+		Vertex {
+			pos,
+			uv,
+			normal,
+			tangent: [0.; 3],
+			bitangent: [0.; 3],
+		}
+	}
+
+	pub const fn with_tangents(self, tangent: [f32; 3], bitangent: [f32; 3]) -> Self {
+		Self {
+			tangent,
+			bitangent,
+			..self
+		}
 	}
 
 	pub const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
-		const ATTRIBS: [wgpu::VertexAttribute; 2] =
-			wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+		const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+			0 => Float32x3, 1 => Float32x2, 2 => Float32x3,
+			3 => Float32x3, 4 => Float32x3
+		];
 
 		wgpu::VertexBufferLayout {
 			array_stride: std::mem::size_of::<Vertex>() as _,
@@ -42,3 +127,82 @@ impl Vertex {
 		}
 	}
 }
+
+/// A [`Vertex`] moved by up to four joints of a skeleton, see
+/// [`crate::skinning`].
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct SkinnedVertex {
+	pub pos: Pos,
+	pub uv: Uv,
+	pub normal: Normal,
+	pub tangent: [f32; 3],
+	pub bitangent: [f32; 3],
+	/// Indices of the bones moving the vertex.
+	pub joints: [u32; 4],
+	/// How much each of `joints` moves the vertex. Should add up to 1.
+	pub weights: [f32; 4],
+}
+impl SkinnedVertex {
+	pub const fn new(vertex: Vertex, joints: [u32; 4], weights: [f32; 4]) -> Self {
+		let Vertex {
+			pos,
+			uv,
+			normal,
+			tangent,
+			bitangent,
+		} = vertex;
+		Self {
+			pos,
+			uv,
+			normal,
+			tangent,
+			bitangent,
+			joints,
+			weights,
+		}
+	}
+
+	/// Like [`Vertex::vb_layout`], with the joints and weights after the locations
+	/// of [`Instance`], which skinned meshes don't use.
+	pub const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
+		const ATTRIBS: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+			0 => Float32x3, 1 => Float32x2, 2 => Float32x3,
+			3 => Float32x3, 4 => Float32x3, 9 => Uint32x4, 10 => Float32x4
+		];
+
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<SkinnedVertex>() as _,
+			step_mode: wgpu::VertexStepMode::Vertex,
+			attributes: &ATTRIBS,
+		}
+	}
+}
+
+/// Per-instance data, read from a second vertex buffer.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct Instance {
+	/// Model matrix, in the layout of a WGSL `mat4x4<f32>`.
+	pub transform: [[f32; 4]; 4],
+}
+impl Instance {
+	pub fn new(transform: Matrix4<f32>) -> Self {
+		Self {
+			transform: mat4_to_wgsl(transform),
+		}
+	}
+
+	pub const fn vb_layout() -> wgpu::VertexBufferLayout<'static> {
+		// A matrix doesn't fit in one attribute, so it is passed as its columns.
+		const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+			5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4
+		];
+
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<Instance>() as _,
+			step_mode: wgpu::VertexStepMode::Instance,
+			attributes: &ATTRIBS,
+		}
+	}
+}
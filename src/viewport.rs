@@ -0,0 +1,120 @@
+//! Drawing into parts of the frame.
+
+/// The rectangle of the frame that clip space is mapped to, in pixels from the
+/// top left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+}
+impl Viewport {
+	pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+		Self {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+
+	/// The width / height ratio, for the projection of a camera drawing into it.
+	pub fn aspect(&self) -> f32 {
+		self.width / self.height
+	}
+
+	/// The part of the viewport within a frame of `width` by `height` pixels, or
+	/// `None` if it is all outside of it.
+	pub fn clamped(&self, width: u32, height: u32) -> Option<Self> {
+		let (width, height) = (width as f32, height as f32);
+		let x = self.x.clamp(0., width);
+		let y = self.y.clamp(0., height);
+		let right = (self.x + self.width).clamp(0., width);
+		let bottom = (self.y + self.height).clamp(0., height);
+		(right > x && bottom > y).then(|| Self::new(x, y, right - x, bottom - y))
+	}
+
+	/// The viewport in a frame drawn at `scale` times the size.
+	pub fn scaled(&self, scale: f32) -> Self {
+		Self::new(
+			self.x * scale,
+			self.y * scale,
+			self.width * scale,
+			self.height * scale,
+		)
+	}
+
+	/// The pixels the viewport covers, to keep rasterization inside of it.
+	pub fn scissor(&self) -> ScissorRect {
+		let x = self.x.max(0.).floor() as u32;
+		let y = self.y.max(0.).floor() as u32;
+		let right = (self.x + self.width).max(0.).ceil() as u32;
+		let bottom = (self.y + self.height).max(0.).ceil() as u32;
+		ScissorRect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+	}
+}
+
+/// The rectangle of the frame outside of which nothing is drawn, in pixels from
+/// the top left corner. The clear color is kept outside of it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScissorRect {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+impl ScissorRect {
+	pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+		Self {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+
+	/// The part of the rectangle within a frame of `width` by `height` pixels,
+	/// which is empty if it is all outside of it.
+	pub fn clamped(&self, width: u32, height: u32) -> Self {
+		let x = self.x.min(width);
+		let y = self.y.min(height);
+		Self::new(x, y, self.width.min(width - x), self.height.min(height - y))
+	}
+
+	/// The rectangle in a frame drawn at `scale` times the size, covering every
+	/// pixel the original covers part of.
+	pub fn scaled(&self, scale: f32) -> Self {
+		let x = (self.x as f32 * scale).floor() as u32;
+		let y = (self.y as f32 * scale).floor() as u32;
+		let right = ((self.x + self.width) as f32 * scale).ceil() as u32;
+		let bottom = ((self.y + self.height) as f32 * scale).ceil() as u32;
+		Self::new(x, y, right - x, bottom - y)
+	}
+}
+
+/// Sets a [`wgpu::RenderPass`]'s viewport and scissor rectangle from the types
+/// above.
+pub trait RenderPassExt {
+	/// Draws into `viewport` over the full depth range.
+	fn apply_viewport(&mut self, viewport: &Viewport);
+
+	/// Discards everything drawn outside of `rect`, which must lie within the
+	/// attachments.
+	fn apply_scissor(&mut self, rect: &ScissorRect);
+}
+impl RenderPassExt for wgpu::RenderPass<'_> {
+	fn apply_viewport(&mut self, viewport: &Viewport) {
+		let Viewport {
+			x,
+			y,
+			width,
+			height,
+		} = *viewport;
+		self.set_viewport(x, y, width, height, 0., 1.);
+	}
+
+	fn apply_scissor(&mut self, rect: &ScissorRect) {
+		self.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+	}
+}
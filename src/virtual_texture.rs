@@ -0,0 +1,703 @@
+//! Sparse virtual texturing: textures too large for GPU memory, split into
+//! pages of which only those the camera sees are uploaded, into an atlas.
+//!
+//! The pages of each mip level are read from `<dir>/<mip>/<x>_<y>.png`, as
+//! written by [`bake_pages`]. Shaders sample the texture with
+//! `sample_virtual_texture` from `virtual_texture.wgsl`, through the bind group
+//! of [`VirtualTextureSystem::bind_group`].
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use image::imageops::FilterType;
+use image::RgbaImage;
+use log::{error, warn};
+use nalgebra::Point3;
+
+use crate::aabb::Aabb;
+use crate::camera::{CameraLike, ProjectionKind};
+use crate::tex2d::{mip_level_count, SamplerConfig, Tex2d};
+
+/// The width and height of a page, in texels. Matches `VT_PAGE_SIZE` in
+/// `virtual_texture.wgsl`.
+pub const PAGE_SIZE: u32 = 256;
+/// The width and height of the physical atlas, in texels.
+pub const ATLAS_SIZE: u32 = 4096;
+/// The pages the atlas holds, few enough for a slot's index to fit in the red
+/// or green channel of the page table.
+const ATLAS_SLOTS: usize =
+	((ATLAS_SIZE / PAGE_SIZE) * (ATLAS_SIZE / PAGE_SIZE)) as usize;
+
+/// The page at column `x` and row `y` of the pages of mip level `mip`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PageId {
+	pub x: u32,
+	pub y: u32,
+	pub mip: u32,
+}
+impl PageId {
+	pub fn new(x: u32, y: u32, mip: u32) -> Self {
+		Self { x, y, mip }
+	}
+
+	/// The page of the next coarser mip level covering this one.
+	pub fn parent(&self) -> Self {
+		Self::new(self.x / 2, self.y / 2, self.mip + 1)
+	}
+
+	/// The pages of the next finer mip level this one covers.
+	///
+	/// # Panics
+	/// If the page is of mip level 0.
+	pub fn children(&self) -> [Self; 4] {
+		assert!(self.mip > 0, "Pages of mip level 0 have no children");
+		let (x, y, mip) = (self.x * 2, self.y * 2, self.mip - 1);
+		[
+			Self::new(x, y, mip),
+			Self::new(x + 1, y, mip),
+			Self::new(x, y + 1, mip),
+			Self::new(x + 1, y + 1, mip),
+		]
+	}
+
+	/// Where [`bake_pages`] writes the page under `dir`.
+	pub fn path(&self, dir: &Path) -> PathBuf {
+		dir.join(self.mip.to_string())
+			.join(format!("{}_{}.png", self.x, self.y))
+	}
+}
+
+/// Where the page of each mip level is in the atlas: for each, the mip level and
+/// atlas slot of the page if it is resident, or of its finest resident
+/// ancestor otherwise. Mirrors the `Rg8Uint` texture shaders read.
+#[derive(Clone, Debug)]
+pub struct PageTable {
+	/// The pages along each side, per mip level.
+	sizes: Vec<(u32, u32)>,
+	entries: Vec<Vec<[u8; 2]>>,
+}
+impl PageTable {
+	/// A table of `pages_x` by `pages_y` pages at mip level 0, halved at each
+	/// level down to a single page, all pointing at slot 0 of the coarsest level.
+	pub fn new(pages_x: u32, pages_y: u32) -> Self {
+		let levels = mip_level_count(pages_x, pages_y);
+		let sizes: Vec<_> = (0..levels)
+			.map(|mip| ((pages_x >> mip).max(1), (pages_y >> mip).max(1)))
+			.collect();
+		let entries = sizes
+			.iter()
+			.map(|&(w, h)| vec![[(levels - 1) as u8, 0]; (w * h) as usize])
+			.collect();
+		Self { sizes, entries }
+	}
+
+	pub fn levels(&self) -> u32 {
+		self.sizes.len() as u32
+	}
+
+	/// The pages along each side of mip level `mip`.
+	pub fn level_size(&self, mip: u32) -> (u32, u32) {
+		self.sizes[mip as usize]
+	}
+
+	/// The mip level and slot `page` is sampled from.
+	pub fn entry(&self, page: PageId) -> [u8; 2] {
+		let (width, _) = self.level_size(page.mip);
+		self.entries[page.mip as usize][(page.y * width + page.x) as usize]
+	}
+
+	/// Points each page at its slot from `slot_of` if it is resident, or at its
+	/// parent's entry, coarsest level first. The coarsest page points at slot 0
+	/// if it isn't resident.
+	pub fn rebuild(&mut self, slot_of: impl Fn(PageId) -> Option<u8>) {
+		for mip in (0..self.levels()).rev() {
+			let (width, height) = self.level_size(mip);
+			for y in 0..height {
+				for x in 0..width {
+					let page = PageId::new(x, y, mip);
+					let entry = match slot_of(page) {
+						Some(slot) => [mip as u8, slot],
+						None if mip + 1 < self.levels() => self.entry(page.parent()),
+						None => [mip as u8, 0],
+					};
+					self.entries[mip as usize][(y * width + x) as usize] = entry;
+				}
+			}
+		}
+	}
+
+	/// Writes every level into `texture`, which must have a level per mip level.
+	fn upload(&self, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+		for (mip, (&(width, height), entries)) in
+			self.sizes.iter().zip(&self.entries).enumerate()
+		{
+			queue.write_texture(
+				wgpu::ImageCopyTexture {
+					texture,
+					mip_level: mip as u32,
+					origin: wgpu::Origin3d::ZERO,
+					aspect: wgpu::TextureAspect::All,
+				},
+				bytemuck::cast_slice(entries),
+				wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: Some(2 * width),
+					rows_per_image: None,
+				},
+				wgpu::Extent3d {
+					width,
+					height,
+					depth_or_array_layers: 1,
+				},
+			);
+		}
+	}
+}
+
+/// Which pages the slots of the atlas hold. Once they are all taken, new pages
+/// replace the least recently used one.
+#[derive(Clone, Debug)]
+pub struct PageCache {
+	/// The slot of each resident page, and the frame it was last used in.
+	pages: HashMap<PageId, (u8, u64)>,
+	free: Vec<u8>,
+}
+impl PageCache {
+	/// A cache of `slots` empty slots.
+	///
+	/// # Panics
+	/// If there are more than 256 slots, whose indices wouldn't fit in a byte.
+	pub fn new(slots: usize) -> Self {
+		assert!(slots <= 256, "Page cache slots must fit in a byte");
+		Self {
+			pages: HashMap::new(),
+			// Popped from the back, so that slot 0 is taken first.
+			free: (0..slots).rev().map(|slot| slot as u8).collect(),
+		}
+	}
+
+	/// The slot holding `page`, if it is resident.
+	pub fn slot(&self, page: PageId) -> Option<u8> {
+		self.pages.get(&page).map(|&(slot, _)| slot)
+	}
+
+	/// Marks `page` as used in `frame`, if it is resident. Returns whether it is.
+	pub fn touch(&mut self, page: PageId, frame: u64) -> bool {
+		let Some((_, last_used)) = self.pages.get_mut(&page) else {
+			return false;
+		};
+		*last_used = (*last_used).max(frame);
+		true
+	}
+
+	/// Gives `page` a slot, used in `frame`, evicting the least recently used page
+	/// if none is free. Returns the slot and the evicted page, or `None` if every
+	/// page was used in `frame` or later.
+	pub fn insert(&mut self, page: PageId, frame: u64) -> Option<(u8, Option<PageId>)> {
+		if let Some(slot) = self.slot(page) {
+			self.touch(page, frame);
+			return Some((slot, None));
+		}
+		let (slot, evicted) = match self.free.pop() {
+			Some(slot) => (slot, None),
+			None => {
+				let (&coldest, &(slot, last_used)) = self
+					.pages
+					.iter()
+					.min_by_key(|(_, (_, last_used))| *last_used)?;
+				if last_used >= frame {
+					return None;
+				}
+				self.pages.remove(&coldest);
+				(slot, Some(coldest))
+			}
+		};
+		self.pages.insert(page, (slot, frame));
+		Some((slot, evicted))
+	}
+
+	/// Keeps `page` resident for good, as pages used in every later frame are.
+	pub fn pin(&mut self, page: PageId) -> Option<u8> {
+		self.insert(page, u64::MAX).map(|(slot, _)| slot)
+	}
+
+	/// The number of resident pages.
+	pub fn len(&self) -> usize {
+		self.pages.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pages.is_empty()
+	}
+}
+
+/// A page read from disk for a [`VirtualTextureSystem`].
+struct LoadedPage {
+	page: PageId,
+	image: Result<RgbaImage>,
+}
+
+/// A texture of `width`×`height` texels, of which only the pages in use are
+/// uploaded into `physical_atlas`. `page_table` tells shaders where each page
+/// is, see [`PageTable`].
+///
+/// Pages are requested by [`Self::update`] from what the camera sees, or by
+/// [`Self::request_page`], and read from disk on another thread. [`Self::poll`]
+/// uploads those that were read, replacing the least recently used pages once
+/// the atlas is full. The page of the coarsest level, covering the whole
+/// texture, is always resident, so that something can be sampled anywhere.
+pub struct VirtualTextureSystem {
+	/// `Rg8Uint`, with a mip level per mip level of the texture.
+	pub page_table: Tex2d,
+	/// [`ATLAS_SIZE`]×[`ATLAS_SIZE`] texels, in slots of [`PAGE_SIZE`] texels.
+	pub physical_atlas: Tex2d,
+	table: PageTable,
+	cache: PageCache,
+	/// Pages being read.
+	pending: HashSet<PageId>,
+	requests: Sender<PageId>,
+	loaded: Receiver<LoadedPage>,
+	width: u32,
+	height: u32,
+	/// Counts the calls to `update`, for the cache to find the coldest pages.
+	frame: u64,
+}
+impl VirtualTextureSystem {
+	/// Creates the page table and atlas of a `width`×`height` texture whose pages
+	/// are in `dir`, and uploads its coarsest page.
+	///
+	/// # Panics
+	/// If `width` or `height` isn't [`PAGE_SIZE`] times a power of two.
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		dir: &Path,
+		width: u32,
+		height: u32,
+	) -> Result<Self> {
+		let (pages_x, pages_y) = page_count(width, height);
+		let table = PageTable::new(pages_x, pages_y);
+		let page_table = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Page Table"),
+			size: wgpu::Extent3d {
+				width: pages_x,
+				height: pages_y,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: table.levels(),
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rg8Uint,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let atlas = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Physical Atlas"),
+			size: wgpu::Extent3d {
+				width: ATLAS_SIZE,
+				height: ATLAS_SIZE,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8UnormSrgb,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		// The table is read with `textureLoad`, so its sampler goes unused.
+		let table_sampler = SamplerConfig::default();
+		let atlas_sampler = SamplerConfig {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		};
+		let (requests, loaded) = spawn_loader(dir.to_owned());
+		let mut result = Self {
+			page_table: Tex2d {
+				view: page_table.create_view(&wgpu::TextureViewDescriptor::default()),
+				texture: page_table,
+				sampler: table_sampler.create_sampler(device),
+				sampler_config: table_sampler,
+			},
+			physical_atlas: Tex2d {
+				view: atlas.create_view(&wgpu::TextureViewDescriptor::default()),
+				texture: atlas,
+				sampler: atlas_sampler.create_sampler(device),
+				sampler_config: atlas_sampler,
+			},
+			table,
+			cache: PageCache::new(ATLAS_SLOTS),
+			pending: HashSet::new(),
+			requests,
+			loaded,
+			width,
+			height,
+			frame: 0,
+		};
+		let root = result.root();
+		let image = read_page(&root.path(dir))?;
+		let slot = result.cache.pin(root).expect("The cache starts empty");
+		write_page(queue, &result.physical_atlas.texture, slot, &image);
+		result.upload_table(queue);
+		Ok(result)
+	}
+
+	/// The page of the coarsest level, covering the whole texture.
+	fn root(&self) -> PageId {
+		PageId::new(0, 0, self.table.levels() - 1)
+	}
+
+	/// Queues reading the page at column `page_x` and row `page_y` of mip level
+	/// `mip` from disk and uploading it, unless it is resident or being read.
+	/// Resident pages are marked as used instead.
+	pub fn request_page(&mut self, page_x: u32, page_y: u32, mip: u32) {
+		let page = PageId::new(page_x, page_y, mip);
+		let exists = mip < self.table.levels() && {
+			let (width, height) = self.table.level_size(mip);
+			page_x < width && page_y < height
+		};
+		if !exists {
+			warn!("Requested page {page:?}, which isn't in the virtual texture");
+			return;
+		}
+		if self.cache.touch(page, self.frame) || self.pending.contains(&page) {
+			return;
+		}
+		self.pending.insert(page);
+		// The loader only stops once the system is dropped.
+		self.requests.send(page).ok();
+	}
+
+	/// Requests the pages of the texture covering `bounds`, a box around a surface
+	/// textured from u 0 to 1 along x and v 0 to 1 along z, that `camera` sees
+	/// in a frame `screen_height` pixels high. Each page is requested at the mip
+	/// level whose texels are about a pixel on screen, along with its ancestors.
+	pub fn update(
+		&mut self,
+		camera: &dyn CameraLike,
+		bounds: &Aabb,
+		screen_height: u32,
+	) {
+		self.frame += 1;
+		let frustum = camera.frustum();
+		let eye = camera.view().inverse() * Point3::origin();
+		let projection = camera.projection();
+		// Pixels per world unit, at a distance of 1 for perspective projections.
+		let pixels_per_unit =
+			projection.as_matrix()[(1, 1)] * screen_height as f32 / 2.;
+		let texel_size = (bounds.max.x - bounds.min.x) / self.width as f32;
+		let mut stack = vec![self.root()];
+		while let Some(page) = stack.pop() {
+			let aabb = self.page_bounds(page, bounds);
+			if !aabb.intersects_frustum(&frustum) {
+				continue;
+			}
+			self.request_page(page.x, page.y, page.mip);
+			if page.mip == 0 {
+				continue;
+			}
+			let closest = eye.sup(&aabb.min).inf(&aabb.max);
+			let texel_pixels = texel_size * (1u32 << page.mip) as f32 * pixels_per_unit;
+			let texel_pixels = match projection {
+				ProjectionKind::Perspective(_) => {
+					texel_pixels / nalgebra::distance(&eye, &closest).max(1e-3)
+				}
+				ProjectionKind::Orthographic(_) => texel_pixels,
+			};
+			if texel_pixels > 1. {
+				let (width, height) = self.table.level_size(page.mip - 1);
+				stack.extend(
+					page.children()
+						.into_iter()
+						.filter(|child| child.x < width && child.y < height),
+				);
+			}
+		}
+	}
+
+	/// The part of `bounds` the texels of `page` cover.
+	fn page_bounds(&self, page: PageId, bounds: &Aabb) -> Aabb {
+		let texels = PAGE_SIZE << page.mip;
+		let u = |x: u32| (x * texels) as f32 / self.width as f32;
+		let v = |y: u32| (y * texels) as f32 / self.height as f32;
+		let size = bounds.max - bounds.min;
+		let (u_min, u_max) = (u(page.x), u(page.x + 1).min(1.));
+		let (v_min, v_max) = (v(page.y), v(page.y + 1).min(1.));
+		Aabb::new(
+			Point3::new(
+				bounds.min.x + u_min * size.x,
+				bounds.min.y,
+				bounds.min.z + v_min * size.z,
+			),
+			Point3::new(
+				bounds.min.x + u_max * size.x,
+				bounds.max.y,
+				bounds.min.z + v_max * size.z,
+			),
+		)
+	}
+
+	/// Uploads the pages read since the last call into the atlas, evicting the
+	/// least recently used pages if it is full, and updates the page table.
+	/// Returns the number of pages uploaded.
+	pub fn poll(&mut self, queue: &wgpu::Queue) -> usize {
+		let mut uploaded = 0;
+		for LoadedPage { page, image } in self.loaded.try_iter() {
+			self.pending.remove(&page);
+			let image = match image {
+				Ok(image) => image,
+				Err(err) => {
+					error!("{err:?}");
+					continue;
+				}
+			};
+			let Some((slot, _)) = self.cache.insert(page, self.frame) else {
+				warn!("Dropping page {page:?}, as the atlas is full of pages in use");
+				continue;
+			};
+			write_page(queue, &self.physical_atlas.texture, slot, &image);
+			uploaded += 1;
+		}
+		if uploaded > 0 {
+			self.upload_table(queue);
+		}
+		uploaded
+	}
+
+	fn upload_table(&mut self, queue: &wgpu::Queue) {
+		let cache = &self.cache;
+		self.table.rebuild(|page| cache.slot(page));
+		self.table.upload(queue, &self.page_table.texture);
+	}
+
+	/// What `page_table` holds.
+	pub fn table(&self) -> &PageTable {
+		&self.table
+	}
+
+	pub fn cache(&self) -> &PageCache {
+		&self.cache
+	}
+
+	/// The number of pages being read from disk.
+	pub fn pending_pages(&self) -> usize {
+		self.pending.len()
+	}
+
+	/// The layout of the bind group of [`Self::bind_group`]: the page table, the
+	/// atlas and its sampler, for `sample_virtual_texture` in fragment shaders.
+	pub fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("virtual_texture_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Uint,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float {
+							filterable: true,
+						},
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 2,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+			],
+		})
+	}
+
+	/// A bind group of the page table and atlas, matching [`Self::layout`].
+	pub fn bind_group(
+		&self,
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("virtual_texture_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&self.page_table.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(
+						&self.physical_atlas.view,
+					),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::Sampler(
+						&self.physical_atlas.sampler,
+					),
+				},
+			],
+		})
+	}
+}
+
+/// Splits `image` into the pages of every mip level, written under `dir` where
+/// [`VirtualTextureSystem`] reads them. Holds each whole level in memory, so
+/// this is meant to run offline.
+///
+/// # Panics
+/// If the image's width or height isn't [`PAGE_SIZE`] times a power of two.
+pub fn bake_pages(image: &RgbaImage, dir: &Path) -> Result<()> {
+	let (width, height) = image.dimensions();
+	let (pages_x, pages_y) = page_count(width, height);
+	let mut level = image.clone();
+	for mip in 0..mip_level_count(pages_x, pages_y) {
+		if mip > 0 {
+			let (width, height) = ((width >> mip).max(1), (height >> mip).max(1));
+			level =
+				image::imageops::resize(&level, width, height, FilterType::Triangle);
+		}
+		let level_dir = dir.join(mip.to_string());
+		std::fs::create_dir_all(&level_dir)
+			.wrap_err_with(|| format!("Failed to create {}", level_dir.display()))?;
+		let (pages_x, pages_y) = ((pages_x >> mip).max(1), (pages_y >> mip).max(1));
+		for y in 0..pages_y {
+			for x in 0..pages_x {
+				let (left, top) = (x * PAGE_SIZE, y * PAGE_SIZE);
+				let page = image::imageops::crop_imm(
+					&level,
+					left,
+					top,
+					PAGE_SIZE.min(level.width() - left),
+					PAGE_SIZE.min(level.height() - top),
+				)
+				.to_image();
+				let path = PageId::new(x, y, mip).path(dir);
+				page.save(&path)
+					.wrap_err_with(|| format!("Failed to save {}", path.display()))?;
+			}
+		}
+	}
+	Ok(())
+}
+
+/// The pages along each side of mip level 0.
+///
+/// # Panics
+/// If `width` or `height` isn't [`PAGE_SIZE`] times a power of two.
+fn page_count(width: u32, height: u32) -> (u32, u32) {
+	let pages = |size: u32| {
+		let pages = size / PAGE_SIZE;
+		assert!(
+			size % PAGE_SIZE == 0 && pages.is_power_of_two(),
+			"Virtual textures must be {PAGE_SIZE} times a power of two texels wide \
+			 and high, not {width}x{height}"
+		);
+		pages
+	};
+	(pages(width), pages(height))
+}
+
+/// Reads the pages requested through the returned sender on another thread, and
+/// sends them back through the returned receiver. The thread ends once the
+/// sender is dropped.
+fn spawn_loader(dir: PathBuf) -> (Sender<PageId>, Receiver<LoadedPage>) {
+	let (request_sender, request_receiver) = channel::<PageId>();
+	let (page_sender, page_receiver) = channel();
+	std::thread::spawn(move || {
+		for page in request_receiver {
+			let image = read_page(&page.path(&dir));
+			if page_sender.send(LoadedPage { page, image }).is_err() {
+				break;
+			}
+		}
+	});
+	(request_sender, page_receiver)
+}
+
+fn read_page(path: &Path) -> Result<RgbaImage> {
+	let image = image::open(path)
+		.wrap_err_with(|| format!("Failed to load page {}", path.display()))?;
+	Ok(image.into_rgba8())
+}
+
+/// Writes `image` into the top left corner of `slot` of the atlas.
+fn write_page(queue: &wgpu::Queue, atlas: &wgpu::Texture, slot: u8, image: &RgbaImage) {
+	let slots_per_row = ATLAS_SIZE / PAGE_SIZE;
+	let slot = slot as u32;
+	let (width, height) = image.dimensions();
+	queue.write_texture(
+		wgpu::ImageCopyTexture {
+			texture: atlas,
+			mip_level: 0,
+			origin: wgpu::Origin3d {
+				x: slot % slots_per_row * PAGE_SIZE,
+				y: slot / slots_per_row * PAGE_SIZE,
+				z: 0,
+			},
+			aspect: wgpu::TextureAspect::All,
+		},
+		image,
+		wgpu::ImageDataLayout {
+			offset: 0,
+			bytes_per_row: Some(4 * width),
+			rows_per_image: None,
+		},
+		wgpu::Extent3d {
+			width: width.min(PAGE_SIZE),
+			height: height.min(PAGE_SIZE),
+			depth_or_array_layers: 1,
+		},
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn least_recently_used_pages_are_evicted() {
+		let mut cache = PageCache::new(2);
+		let pages = [0, 1, 2].map(|x| PageId::new(x, 0, 0));
+		assert_eq!(cache.insert(pages[0], 1), Some((0, None)));
+		assert_eq!(cache.insert(pages[1], 2), Some((1, None)));
+		assert!(cache.touch(pages[0], 3));
+		assert_eq!(cache.insert(pages[2], 4), Some((1, Some(pages[1]))));
+		assert_eq!(cache.slot(pages[1]), None);
+		// Both pages were used in frame 3 or later, so neither is evicted for it.
+		assert_eq!(cache.insert(pages[1], 3), None);
+		assert_eq!(cache.len(), 2);
+	}
+
+	#[test]
+	fn missing_pages_fall_back_to_their_ancestors() {
+		let mut table = PageTable::new(4, 2);
+		assert_eq!(table.levels(), 3);
+		let root = PageId::new(0, 0, 2);
+		let resident = PageId::new(1, 0, 1);
+		table.rebuild(|page| match page {
+			page if page == root => Some(0),
+			page if page == resident => Some(7),
+			_ => None,
+		});
+		assert_eq!(table.entry(PageId::new(3, 1, 0)), [1, 7]);
+		assert_eq!(table.entry(PageId::new(1, 0, 0)), [2, 0]);
+		assert_eq!(table.entry(PageId::new(0, 0, 1)), [2, 0]);
+	}
+}
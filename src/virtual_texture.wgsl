@@ -0,0 +1,47 @@
+// Sampling virtual textures through their page table and physical atlas.
+
+// The size of pages in texels, as in `virtual_texture.rs`.
+const VT_PAGE_SIZE: u32 = 256u;
+
+// The color of a virtual texture at `uv`, from the finest resident page at the
+// mip level its screen footprint needs. The page table holds, per page and mip
+// level, the mip level of the page standing in for it in R and its atlas slot in
+// G. Must be called from uniform control flow, for the derivatives.
+fn sample_virtual_texture(
+	page_table: texture_2d<u32>,
+	atlas: texture_2d<f32>,
+	atlas_sampler: sampler,
+	uv: vec2<f32>,
+) -> vec4<f32> {
+	let size = textureDimensions(page_table, 0) * VT_PAGE_SIZE;
+	let texel_uv = uv * vec2<f32>(size);
+	let footprint = max(length(dpdx(texel_uv)), length(dpdy(texel_uv)));
+	let wanted = min(
+		u32(max(log2(footprint), 0.0)),
+		textureNumLevels(page_table) - 1u,
+	);
+
+	let clamped_uv = clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0));
+	let pages = textureDimensions(page_table, i32(wanted));
+	let page = min(vec2<u32>(clamped_uv * vec2<f32>(pages)), pages - 1u);
+	let entry = textureLoad(page_table, page, i32(wanted)).rg;
+
+	// Texel coordinates at the level of the resident page.
+	let level_size = max(size >> vec2<u32>(entry.r), vec2<u32>(1u));
+	let texel = min(
+		clamped_uv * vec2<f32>(level_size),
+		vec2<f32>(level_size) - 0.5,
+	);
+	let page_size = f32(VT_PAGE_SIZE);
+	let in_page = clamp(
+		texel - floor(texel / page_size) * page_size,
+		vec2<f32>(0.5),
+		vec2<f32>(min(level_size, vec2<u32>(VT_PAGE_SIZE))) - 0.5,
+	);
+
+	let atlas_size = textureDimensions(atlas);
+	let slots = atlas_size / VT_PAGE_SIZE;
+	let slot = vec2<u32>(entry.g % slots.x, entry.g / slots.x);
+	let atlas_uv = (vec2<f32>(slot * VT_PAGE_SIZE) + in_page) / vec2<f32>(atlas_size);
+	return textureSampleLevel(atlas, atlas_sampler, atlas_uv, 0.0);
+}
@@ -0,0 +1,600 @@
+//! Volumetric fog, scattering the directional light through a volume of density
+//! with its shadows, see `volumetric_fog.wgsl`.
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{IsometryMatrix3, Matrix4, Point3};
+
+use crate::camera::{reverse_z, CameraLike};
+use crate::light::LightUniform;
+use crate::shadow::ShadowMap;
+use crate::tex2d::Tex2d;
+use crate::types::mat4_to_wgsl;
+
+/// The layout of the shader's `VolumetricFogUniform`.
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct VolumetricFogUniform {
+	inv_view_proj: [[f32; 4]; 4],
+	light_view_proj: [[f32; 4]; 4],
+	eye: [f32; 3],
+	far_depth: f32,
+	light_color: [f32; 3],
+	absorption: f32,
+	scatter_color: [f32; 3],
+	step_count: u32,
+	max_distance: f32,
+	noise_scale: f32,
+	has_noise: u32,
+	_pad: u32,
+}
+
+/// Fog lit by the directional light, blended over the frame from the depth of a
+/// [`GBuffer`](crate::gbuffer::GBuffer).
+///
+/// The density of the fog is first written to a 3D texture sliced along the
+/// camera's frustum, optionally modulated by a noise texture. The ray of each
+/// pixel, at half the resolution, is then marched from the camera to the
+/// surface drawn there, adding up the light scattered towards the camera at
+/// each step where the shadow map doesn't hide the light, and how much the fog
+/// dims what is behind it. Finally, the frame is dimmed by that transmittance,
+/// and the scattered light is added.
+///
+/// The public fields are uploaded by [`Self::update`].
+pub struct VolumetricFog {
+	/// The share of the absorbed light scattered towards the camera, by channel.
+	pub scatter_color: [f32; 3],
+	/// How much light the fog absorbs per world unit, at a density of 1.
+	pub absorption: f32,
+	/// How many steps each ray is marched for.
+	pub step_count: u32,
+	/// How far the fog reaches from the camera, in world units.
+	pub max_distance: f32,
+	/// How many times the noise texture repeats per world unit, over x and z.
+	pub noise_scale: f32,
+	/// The uniform with the public fields left out, for `uniform_buf`.
+	uniform: VolumetricFogUniform,
+	uniform_buf: wgpu::Buffer,
+	reverse_z: bool,
+	depth_slices: u32,
+	/// The density, in the red channel.
+	volume: wgpu::Texture,
+	volume_sampler: wgpu::Sampler,
+	/// The scattered light, and the optical depth in alpha, at half resolution.
+	fog_color: wgpu::Texture,
+	fog_sampler: wgpu::Sampler,
+	/// Binds the uniform and `volume` to be written.
+	density_bind_group: wgpu::BindGroup,
+	density_layout: wgpu::BindGroupLayout,
+	/// Binds the noise texture, or an unused placeholder.
+	noise_bind_group: wgpu::BindGroup,
+	noise_layout: wgpu::BindGroupLayout,
+	scatter_layout: wgpu::BindGroupLayout,
+	composite_layout: wgpu::BindGroupLayout,
+	density_pipeline: wgpu::ComputePipeline,
+	scatter_pipeline: wgpu::ComputePipeline,
+	composite_pipeline_layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	composite_pipeline: wgpu::RenderPipeline,
+}
+impl VolumetricFog {
+	pub const DEFAULT_DEPTH_SLICES: u32 = 64;
+	const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	/// The half resolution pixels covered by each texel of the density volume,
+	/// along x and y.
+	const FROXEL_SIZE: u32 = 8;
+
+	/// Creates fog for `width`×`height` frames in `format`, with a depth buffer
+	/// that is optionally reversed. Its density is sliced `depth_slices` times
+	/// by distance from the camera.
+	///
+	/// # Panics
+	/// If `depth_slices` is 0.
+	pub fn new(
+		device: &wgpu::Device,
+		format: wgpu::TextureFormat,
+		width: u32,
+		height: u32,
+		depth_slices: u32,
+		reverse_z: bool,
+	) -> Self {
+		assert!(depth_slices > 0, "The fog needs at least one depth slice");
+		let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Volumetric Fog Uniform"),
+			size: std::mem::size_of::<VolumetricFogUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let linear_sampler = |label| {
+			device.create_sampler(&wgpu::SamplerDescriptor {
+				label: Some(label),
+				address_mode_u: wgpu::AddressMode::ClampToEdge,
+				address_mode_v: wgpu::AddressMode::ClampToEdge,
+				address_mode_w: wgpu::AddressMode::ClampToEdge,
+				mag_filter: wgpu::FilterMode::Linear,
+				min_filter: wgpu::FilterMode::Linear,
+				..Default::default()
+			})
+		};
+		let volume_sampler = linear_sampler("Fog Density Sampler");
+		let fog_sampler = linear_sampler("Fog Color Sampler");
+
+		let entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility,
+			ty,
+			count: None,
+		};
+		let compute = wgpu::ShaderStages::COMPUTE;
+		let uniform_entry = entry(
+			0,
+			compute,
+			wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+		);
+		let storage_texture = |view_dimension| wgpu::BindingType::StorageTexture {
+			access: wgpu::StorageTextureAccess::WriteOnly,
+			format: Self::FORMAT,
+			view_dimension,
+		};
+		let texture = |view_dimension, filterable| wgpu::BindingType::Texture {
+			multisampled: false,
+			view_dimension,
+			sample_type: wgpu::TextureSampleType::Float { filterable },
+		};
+		let filtering = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+		let density_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Fog Density Layout"),
+				entries: &[
+					uniform_entry,
+					entry(1, compute, storage_texture(wgpu::TextureViewDimension::D3)),
+				],
+			});
+		let noise_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Fog Noise Layout"),
+				entries: &[
+					entry(0, compute, texture(wgpu::TextureViewDimension::D2, true)),
+					entry(1, compute, filtering),
+				],
+			});
+		// The G-buffer's depth is read with `textureLoad`, so it needs no sampler.
+		let scatter_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Fog Scatter Layout"),
+				entries: &[
+					uniform_entry,
+					entry(2, compute, texture(wgpu::TextureViewDimension::D2, false)),
+					entry(
+						3,
+						compute,
+						wgpu::BindingType::Texture {
+							multisampled: false,
+							view_dimension: wgpu::TextureViewDimension::D2,
+							sample_type: wgpu::TextureSampleType::Depth,
+						},
+					),
+					entry(
+						4,
+						compute,
+						wgpu::BindingType::Sampler(
+							wgpu::SamplerBindingType::Comparison,
+						),
+					),
+					entry(5, compute, texture(wgpu::TextureViewDimension::D3, true)),
+					entry(6, compute, filtering),
+					entry(7, compute, storage_texture(wgpu::TextureViewDimension::D2)),
+				],
+			});
+		let fragment = wgpu::ShaderStages::FRAGMENT;
+		let composite_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("Fog Composite Layout"),
+				entries: &[
+					entry(8, fragment, texture(wgpu::TextureViewDimension::D2, true)),
+					entry(9, fragment, filtering),
+				],
+			});
+
+		let shader =
+			device.create_shader_module(wgpu::include_wgsl!("volumetric_fog.wgsl"));
+		let create_compute_pipeline = |layouts: &[&wgpu::BindGroupLayout],
+		                               entry_point| {
+			let layout =
+				device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+					label: Some("Volumetric Fog Compute Layout"),
+					bind_group_layouts: layouts,
+					push_constant_ranges: &[],
+				});
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some(entry_point),
+				layout: Some(&layout),
+				module: &shader,
+				entry_point,
+			})
+		};
+		let density_pipeline =
+			create_compute_pipeline(&[&density_layout, &noise_layout], "cs_density");
+		let scatter_pipeline =
+			create_compute_pipeline(&[&scatter_layout], "cs_scatter");
+		let composite_pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Fog Composite Pipeline Layout"),
+				bind_group_layouts: &[&composite_layout],
+				push_constant_ranges: &[],
+			});
+		let composite_pipeline = create_composite_pipeline(
+			device,
+			&composite_pipeline_layout,
+			&shader,
+			format,
+		);
+
+		// Never sampled while `has_noise` is 0.
+		let placeholder = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Fog Noise Placeholder"),
+			size: wgpu::Extent3d {
+				width: 1,
+				height: 1,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8Unorm,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+		let noise_bind_group = create_noise_bind_group(
+			device,
+			&noise_layout,
+			&placeholder.create_view(&Default::default()),
+			&fog_sampler,
+		);
+
+		let (volume, fog_color) = create_textures(device, width, height, depth_slices);
+		let density_bind_group =
+			create_density_bind_group(device, &density_layout, &uniform_buf, &volume);
+		Self {
+			scatter_color: [1.; 3],
+			absorption: 0.02,
+			step_count: 32,
+			max_distance: 100.,
+			noise_scale: 0.05,
+			uniform: VolumetricFogUniform::zeroed(),
+			uniform_buf,
+			reverse_z,
+			depth_slices,
+			volume,
+			volume_sampler,
+			fog_color,
+			fog_sampler,
+			density_bind_group,
+			density_layout,
+			noise_bind_group,
+			noise_layout,
+			scatter_layout,
+			composite_layout,
+			density_pipeline,
+			scatter_pipeline,
+			composite_pipeline_layout,
+			shader,
+			composite_pipeline,
+		}
+	}
+
+	/// Recreates the textures for `width`×`height` frames.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		(self.volume, self.fog_color) =
+			create_textures(device, width, height, self.depth_slices);
+		self.density_bind_group = create_density_bind_group(
+			device,
+			&self.density_layout,
+			&self.uniform_buf,
+			&self.volume,
+		);
+	}
+
+	/// Recreates the composite pipeline to draw into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.composite_pipeline = create_composite_pipeline(
+			device,
+			&self.composite_pipeline_layout,
+			&self.shader,
+			format,
+		);
+	}
+
+	/// Modulates the density of the fog by the red channel of `tex`, repeated
+	/// over the ground every `1 / noise_scale` world units. It should repeat
+	/// seamlessly.
+	pub fn set_density_texture(&mut self, device: &wgpu::Device, tex: &Tex2d) {
+		self.noise_bind_group = create_noise_bind_group(
+			device,
+			&self.noise_layout,
+			&tex.view,
+			&tex.sampler,
+		);
+		self.uniform.has_noise = 1;
+	}
+
+	/// Uploads the matrices of `camera` seen from `view`, and the public fields.
+	/// Returns the number of bytes written.
+	pub fn update(
+		&mut self,
+		queue: &wgpu::Queue,
+		camera: &dyn CameraLike,
+		view: &IsometryMatrix3<f32>,
+	) -> u64 {
+		let mut view_proj = camera.proj_view_from(view);
+		let mut far_depth = 1.;
+		if self.reverse_z {
+			view_proj = reverse_z(&view_proj);
+			far_depth = 0.;
+		}
+		let inv_view_proj = view_proj.try_inverse().unwrap_or_default();
+		self.uniform.inv_view_proj = mat4_to_wgsl(inv_view_proj);
+		self.uniform.eye = (view.inverse() * Point3::origin()).into();
+		self.uniform.far_depth = far_depth;
+		self.upload(queue)
+	}
+
+	/// Uploads the light and the matrix of the shadow map it is seen through, and
+	/// the public fields. Returns the number of bytes written.
+	pub fn set_light(
+		&mut self,
+		queue: &wgpu::Queue,
+		light: &LightUniform,
+		light_view_proj: &Matrix4<f32>,
+	) -> u64 {
+		self.uniform.light_color = light.color;
+		self.uniform.light_view_proj = mat4_to_wgsl(*light_view_proj);
+		self.upload(queue)
+	}
+
+	fn upload(&self, queue: &wgpu::Queue) -> u64 {
+		let uniform = VolumetricFogUniform {
+			scatter_color: self.scatter_color,
+			absorption: self.absorption,
+			step_count: self.step_count.max(1),
+			max_distance: self.max_distance,
+			noise_scale: self.noise_scale,
+			..self.uniform
+		};
+		queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+		std::mem::size_of::<VolumetricFogUniform>() as u64
+	}
+
+	/// Records marching the fog in front of the G-buffer's `depth_view`, lit
+	/// where `shadow_map` doesn't hide the light, and blending it over
+	/// `output_view`.
+	pub fn apply(
+		&self,
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		depth_view: &wgpu::TextureView,
+		shadow_map: &ShadowMap,
+		output_view: &wgpu::TextureView,
+	) {
+		let volume = self.volume.create_view(&Default::default());
+		let fog_color = self.fog_color.create_view(&Default::default());
+		let scatter_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("fog_scatter_bind_group"),
+			layout: &self.scatter_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: self.uniform_buf.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(depth_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: wgpu::BindingResource::TextureView(&volume),
+				},
+				wgpu::BindGroupEntry {
+					binding: 6,
+					resource: wgpu::BindingResource::Sampler(&self.volume_sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 7,
+					resource: wgpu::BindingResource::TextureView(&fog_color),
+				},
+			],
+		});
+		let composite_bind_group =
+			device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("fog_composite_bind_group"),
+				layout: &self.composite_layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 8,
+						resource: wgpu::BindingResource::TextureView(&fog_color),
+					},
+					wgpu::BindGroupEntry {
+						binding: 9,
+						resource: wgpu::BindingResource::Sampler(&self.fog_sampler),
+					},
+				],
+			});
+
+		let fog_size = self.fog_color.size();
+		let volume_size = self.volume.size();
+		{
+			let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+				label: Some("Volumetric Fog Pass"),
+			});
+			pass.set_pipeline(&self.density_pipeline);
+			pass.set_bind_group(0, &self.density_bind_group, &[]);
+			pass.set_bind_group(1, &self.noise_bind_group, &[]);
+			pass.dispatch_workgroups(
+				(volume_size.width - 1) / 4 + 1,
+				(volume_size.height - 1) / 4 + 1,
+				(volume_size.depth_or_array_layers - 1) / 4 + 1,
+			);
+			pass.set_pipeline(&self.scatter_pipeline);
+			pass.set_bind_group(0, &scatter_bind_group, &[]);
+			pass.dispatch_workgroups(
+				(fog_size.width - 1) / 8 + 1,
+				(fog_size.height - 1) / 8 + 1,
+				1,
+			);
+		}
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Volumetric Fog Composite Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.composite_pipeline);
+		pass.set_bind_group(0, &composite_bind_group, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+/// The density volume and the half resolution fog color for `width`×`height`
+/// frames.
+fn create_textures(
+	device: &wgpu::Device,
+	width: u32,
+	height: u32,
+	depth_slices: u32,
+) -> (wgpu::Texture, wgpu::Texture) {
+	let half = |size: u32| (size / 2).max(1);
+	let froxels = |size: u32| (half(size) - 1) / VolumetricFog::FROXEL_SIZE + 1;
+	let usage =
+		wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING;
+	let volume = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Fog Density"),
+		size: wgpu::Extent3d {
+			width: froxels(width),
+			height: froxels(height),
+			depth_or_array_layers: depth_slices,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D3,
+		format: VolumetricFog::FORMAT,
+		usage,
+		view_formats: &[],
+	});
+	let fog_color = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("Fog Color"),
+		size: wgpu::Extent3d {
+			width: half(width),
+			height: half(height),
+			depth_or_array_layers: 1,
+		},
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: VolumetricFog::FORMAT,
+		usage,
+		view_formats: &[],
+	});
+	(volume, fog_color)
+}
+
+fn create_density_bind_group(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	uniform_buf: &wgpu::Buffer,
+	volume: &wgpu::Texture,
+) -> wgpu::BindGroup {
+	let volume = volume.create_view(&Default::default());
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("fog_density_bind_group"),
+		layout,
+		entries: &[
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: uniform_buf.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: wgpu::BindingResource::TextureView(&volume),
+			},
+		],
+	})
+}
+
+fn create_noise_bind_group(
+	device: &wgpu::Device,
+	layout: &wgpu::BindGroupLayout,
+	view: &wgpu::TextureView,
+	sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+	device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("fog_noise_bind_group"),
+		layout,
+		entries: &[
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::TextureView(view),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: wgpu::BindingResource::Sampler(sampler),
+			},
+		],
+	})
+}
+
+fn create_composite_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	// The frame is dimmed by the fog's transmittance, in the alpha, and the
+	// scattered light added.
+	let blend = wgpu::BlendComponent {
+		src_factor: wgpu::BlendFactor::One,
+		dst_factor: wgpu::BlendFactor::SrcAlpha,
+		operation: wgpu::BlendOperation::Add,
+	};
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("Fog Composite Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_composite",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState {
+					color: blend,
+					alpha: wgpu::BlendComponent::OVER,
+				}),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
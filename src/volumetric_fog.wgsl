@@ -0,0 +1,154 @@
+// Volumetric fog: light of the directional light scattered towards the camera
+// by fog between it and each surface, ray-marched at half resolution and then
+// blended over the frame.
+
+// Matches `VolumetricFogUniform` in `volumetric_fog.rs`.
+struct VolumetricFogUniform {
+	inv_view_proj: mat4x4<f32>,
+	light_view_proj: mat4x4<f32>,
+	eye: vec3<f32>,
+	// The depth of the G-buffer where nothing was drawn.
+	far_depth: f32,
+	light_color: vec3<f32>,
+	// How much light the fog absorbs per world unit, at a density of 1.
+	absorption: f32,
+	// The share of the absorbed light scattered towards the camera, by channel.
+	scatter_color: vec3<f32>,
+	step_count: u32,
+	// How far the density volume reaches from the camera, in world units.
+	max_distance: f32,
+	// The noise texture's repeats per world unit, over x and z.
+	noise_scale: f32,
+	// Whether the density is modulated by the noise texture.
+	has_noise: u32,
+};
+@group(0) @binding(0)
+var<uniform> fog: VolumetricFogUniform;
+
+// `cs_density`'s output: the density in the red channel, for the camera's
+// frustum sliced by distance up to `max_distance`.
+@group(0) @binding(1)
+var density_out: texture_storage_3d<rgba16float, write>;
+@group(1) @binding(0)
+var noise_t: texture_2d<f32>;
+@group(1) @binding(1)
+var noise_s: sampler;
+
+// `cs_scatter`'s inputs and output.
+// The G-buffer's depth, in the red channel.
+@group(0) @binding(2)
+var depth_t: texture_2d<f32>;
+@group(0) @binding(3)
+var shadow_t: texture_depth_2d;
+@group(0) @binding(4)
+var shadow_s: sampler_comparison;
+@group(0) @binding(5)
+var volume_t: texture_3d<f32>;
+@group(0) @binding(6)
+var volume_s: sampler;
+// The scattered light, and the optical depth in alpha.
+@group(0) @binding(7)
+var fog_out: texture_storage_2d<rgba16float, write>;
+
+// `fs_composite`'s inputs.
+@group(0) @binding(8)
+var fog_t: texture_2d<f32>;
+@group(0) @binding(9)
+var fog_s: sampler;
+
+// The direction from the camera through `uv` on screen, in world space.
+fn view_ray(uv: vec2<f32>) -> vec3<f32> {
+	let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+	let pos = fog.inv_view_proj * vec4<f32>(ndc, 0.5, 1.0);
+	return normalize(pos.xyz / pos.w - fog.eye);
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_density(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = textureDimensions(density_out);
+	if any(id >= size) {
+		return;
+	}
+	let uvw = (vec3<f32>(id) + 0.5) / vec3<f32>(size);
+	let pos = fog.eye + view_ray(uvw.xy) * uvw.z * fog.max_distance;
+	var density = 1.0;
+	if fog.has_noise != 0u {
+		density = textureSampleLevel(noise_t, noise_s, pos.xz * fog.noise_scale, 0.0).r;
+	}
+	textureStore(density_out, vec3<i32>(id), vec4<f32>(density, 0.0, 0.0, 1.0));
+}
+
+// How much of the light reaches `pos`, unfiltered as the steps average it.
+fn light_visibility(pos: vec3<f32>) -> f32 {
+	let light_pos = fog.light_view_proj * vec4<f32>(pos, 1.0);
+	let ndc = light_pos.xyz / light_pos.w;
+	// Beyond the shadow map, nothing is in shadow.
+	if any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+		return 1.0;
+	}
+	let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+	return textureSampleCompareLevel(shadow_t, shadow_s, uv, ndc.z);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_scatter(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = textureDimensions(fog_out);
+	if any(id.xy >= size) {
+		return;
+	}
+	let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+	let depth_size = vec2<i32>(textureDimensions(depth_t));
+	let coords = min(vec2<i32>(uv * vec2<f32>(depth_size)), depth_size - 1);
+	let depth = textureLoad(depth_t, coords, 0).r;
+	var distance = fog.max_distance;
+	if depth != fog.far_depth {
+		let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+		let pos = fog.inv_view_proj * ndc;
+		distance = min(length(pos.xyz / pos.w - fog.eye), fog.max_distance);
+	}
+
+	// Marched from the camera, so that the light scattered at each step is
+	// dimmed by the fog in front of it.
+	let ray = view_ray(uv);
+	let step_length = distance / f32(fog.step_count);
+	var optical_depth = 0.0;
+	var scattered = vec3<f32>(0.0);
+	for (var i = 0u; i < fog.step_count; i += 1u) {
+		let t = (f32(i) + 0.5) * step_length;
+		let density = textureSampleLevel(
+			volume_t,
+			volume_s,
+			vec3<f32>(uv, t / fog.max_distance),
+			0.0,
+		).r;
+		let extinction = density * fog.absorption * step_length;
+		let light = fog.light_color * light_visibility(fog.eye + ray * t);
+		scattered += exp(-optical_depth) * light * fog.scatter_color * extinction;
+		optical_depth += extinction;
+	}
+	textureStore(fog_out, vec2<i32>(id.xy), vec4<f32>(scattered, optical_depth));
+}
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	out.uv = uv;
+	return out;
+}
+
+// Blended by adding the color to the frame dimmed by the alpha, the fog's
+// transmittance.
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+	let fog_color = textureSample(fog_t, fog_s, in.uv);
+	return vec4<f32>(fog_color.rgb, exp(-fog_color.a));
+}
@@ -0,0 +1,309 @@
+//! Weighted blended order-independent transparency, after McGuire and Bavoil,
+//! "Weighted Blended Order-Independent Transparency" (JCGT 2013).
+
+use crate::render_target::RenderTarget;
+
+/// Blends transparent surfaces in any order, so that intersecting meshes needn't
+/// be sorted, or split to be.
+///
+/// Transparent geometry is drawn in a single pass into two targets, with
+/// [`Self::accumulation_targets`]: the sum of its premultiplied colors and
+/// alphas, weighted by alpha and distance so that nearer surfaces dominate, and
+/// the product of one minus its alphas. [`Self::composite`] then divides the
+/// colors by the weights and blends the result over the opaque scene.
+pub struct WboitPass {
+	accumulation: RenderTarget,
+	revealage: RenderTarget,
+	/// The multisampled textures drawn into, resolved into the targets, when
+	/// drawing with more than one sample.
+	msaa_views: Option<(wgpu::TextureView, wgpu::TextureView)>,
+	sample_count: u32,
+	input_layout: wgpu::BindGroupLayout,
+	/// Reads the targets.
+	input: wgpu::BindGroup,
+	layout: wgpu::PipelineLayout,
+	shader: wgpu::ShaderModule,
+	pipeline: wgpu::RenderPipeline,
+}
+impl WboitPass {
+	pub const ACCUMULATION_FORMAT: wgpu::TextureFormat =
+		wgpu::TextureFormat::Rgba16Float;
+	pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+	/// Creates the targets for `width` x `height` frames, drawn into with
+	/// `sample_count` samples, and the pipeline compositing them into `format`
+	/// textures.
+	pub fn new(
+		device: &wgpu::Device,
+		width: u32,
+		height: u32,
+		format: wgpu::TextureFormat,
+		sample_count: u32,
+	) -> Self {
+		let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: false },
+			},
+			count: None,
+		};
+		// The targets are read with `textureLoad`, so they need no samplers.
+		let input_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("WBOIT Input Layout"),
+				entries: &[texture_entry(0), texture_entry(1)],
+			});
+		let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("WBOIT Composite Pipeline Layout"),
+			bind_group_layouts: &[&input_layout],
+			push_constant_ranges: &[],
+		});
+		let shader = device.create_shader_module(wgpu::include_wgsl!("wboit.wgsl"));
+		let pipeline = create_pipeline(device, &layout, &shader, format);
+		let (accumulation, revealage, msaa_views, input) =
+			create_targets(device, &input_layout, width, height, sample_count);
+		Self {
+			accumulation,
+			revealage,
+			msaa_views,
+			sample_count,
+			input_layout,
+			input,
+			layout,
+			shader,
+			pipeline,
+		}
+	}
+
+	/// Recreates the targets for `width` x `height` frames.
+	pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+		(
+			self.accumulation,
+			self.revealage,
+			self.msaa_views,
+			self.input,
+		) = create_targets(device, &self.input_layout, width, height, self.sample_count);
+	}
+
+	/// Recreates the pipeline to composite into `format` textures.
+	pub fn set_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+		self.pipeline = create_pipeline(device, &self.layout, &self.shader, format);
+	}
+
+	/// The color targets of the pipelines drawing transparent geometry, whose
+	/// fragment shaders output the weighted color to the first and the alpha to
+	/// the second.
+	pub fn accumulation_targets() -> [Option<wgpu::ColorTargetState>; 2] {
+		let add = wgpu::BlendComponent {
+			src_factor: wgpu::BlendFactor::One,
+			dst_factor: wgpu::BlendFactor::One,
+			operation: wgpu::BlendOperation::Add,
+		};
+		// Multiplies what is in the target by one minus the fragment's alpha.
+		let reveal = wgpu::BlendComponent {
+			src_factor: wgpu::BlendFactor::Zero,
+			dst_factor: wgpu::BlendFactor::OneMinusSrc,
+			operation: wgpu::BlendOperation::Add,
+		};
+		[
+			Some(wgpu::ColorTargetState {
+				format: Self::ACCUMULATION_FORMAT,
+				blend: Some(wgpu::BlendState {
+					color: add,
+					alpha: add,
+				}),
+				write_mask: wgpu::ColorWrites::ALL,
+			}),
+			Some(wgpu::ColorTargetState {
+				format: Self::REVEALAGE_FORMAT,
+				blend: Some(wgpu::BlendState {
+					color: reveal,
+					alpha: reveal,
+				}),
+				write_mask: wgpu::ColorWrites::ALL,
+			}),
+		]
+	}
+
+	/// Begins the pass accumulating transparent geometry, depth tested against
+	/// the opaque scene's `depth_view` without writing it. Its stencil values are
+	/// kept if `has_stencil`.
+	pub fn begin_accumulation<'a>(
+		&'a self,
+		encoder: &'a mut wgpu::CommandEncoder,
+		depth_view: &'a wgpu::TextureView,
+		has_stencil: bool,
+	) -> wgpu::RenderPass<'a> {
+		let accumulation = self.accumulation.color_view();
+		let revealage = self.revealage.color_view();
+		let ((accumulation, accumulation_resolve), (revealage, revealage_resolve)) =
+			match &self.msaa_views {
+				Some((msaa_accumulation, msaa_revealage)) => (
+					(msaa_accumulation, Some(accumulation)),
+					(msaa_revealage, Some(revealage)),
+				),
+				None => ((accumulation, None), (revealage, None)),
+			};
+		let keep = wgpu::Operations {
+			load: wgpu::LoadOp::Load,
+			store: true,
+		};
+		encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("WBOIT Accumulation Pass"),
+			color_attachments: &[
+				Some(wgpu::RenderPassColorAttachment {
+					view: accumulation,
+					resolve_target: accumulation_resolve,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+						store: true,
+					},
+				}),
+				Some(wgpu::RenderPassColorAttachment {
+					view: revealage,
+					resolve_target: revealage_resolve,
+					ops: wgpu::Operations {
+						// Everything shows through until something is drawn.
+						load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+						store: true,
+					},
+				}),
+			],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: depth_view,
+				depth_ops: Some(keep),
+				stencil_ops: has_stencil.then_some(wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				}),
+			}),
+		})
+	}
+
+	/// Records blending what was accumulated over `output_view`, which holds the
+	/// opaque scene.
+	pub fn composite(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		output_view: &wgpu::TextureView,
+	) {
+		let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("WBOIT Composite Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: output_view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: true,
+				},
+			})],
+			depth_stencil_attachment: None,
+		});
+		pass.set_pipeline(&self.pipeline);
+		pass.set_bind_group(0, &self.input, &[]);
+		pass.draw(0..3, 0..1);
+	}
+}
+
+/// The accumulation and revealage targets, the multisampled textures resolved
+/// into them if `sample_count > 1`, and the bind group reading them.
+fn create_targets(
+	device: &wgpu::Device,
+	input_layout: &wgpu::BindGroupLayout,
+	width: u32,
+	height: u32,
+	sample_count: u32,
+) -> (
+	RenderTarget,
+	RenderTarget,
+	Option<(wgpu::TextureView, wgpu::TextureView)>,
+	wgpu::BindGroup,
+) {
+	let accumulation = RenderTarget::new(
+		device,
+		width,
+		height,
+		WboitPass::ACCUMULATION_FORMAT,
+		"WBOIT Accumulation",
+	);
+	let revealage = RenderTarget::new(
+		device,
+		width,
+		height,
+		WboitPass::REVEALAGE_FORMAT,
+		"WBOIT Revealage",
+	);
+	let msaa_view = |label, format| {
+		device
+			.create_texture(&wgpu::TextureDescriptor {
+				label: Some(label),
+				size: wgpu::Extent3d {
+					width,
+					height,
+					depth_or_array_layers: 1,
+				},
+				mip_level_count: 1,
+				sample_count,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+				view_formats: &[],
+			})
+			.create_view(&wgpu::TextureViewDescriptor::default())
+	};
+	let msaa_views = (sample_count > 1).then(|| {
+		(
+			msaa_view("WBOIT MSAA Accumulation", WboitPass::ACCUMULATION_FORMAT),
+			msaa_view("WBOIT MSAA Revealage", WboitPass::REVEALAGE_FORMAT),
+		)
+	});
+	let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("wboit_input_bind_group"),
+		layout: input_layout,
+		entries: &[
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: wgpu::BindingResource::TextureView(accumulation.color_view()),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: wgpu::BindingResource::TextureView(revealage.color_view()),
+			},
+		],
+	});
+	(accumulation, revealage, msaa_views, input)
+}
+
+/// Blends the averaged colors over the target by their coverage.
+fn create_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	shader: &wgpu::ShaderModule,
+	format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some("WBOIT Composite Pipeline"),
+		layout: Some(layout),
+		vertex: wgpu::VertexState {
+			module: shader,
+			entry_point: "vs_main",
+			buffers: &[],
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: shader,
+			entry_point: "fs_main",
+			targets: &[Some(wgpu::ColorTargetState {
+				format,
+				blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+				write_mask: wgpu::ColorWrites::ALL,
+			})],
+		}),
+		primitive: wgpu::PrimitiveState::default(),
+		depth_stencil: None,
+		multisample: wgpu::MultisampleState::default(),
+		multiview: None,
+	})
+}
@@ -0,0 +1,40 @@
+// The composite of weighted blended order-independent transparency: the
+// accumulated colors are averaged by their weights and blended over the opaque
+// scene by how much of it they cover.
+
+// The sum of the premultiplied colors times their weights, with the sum of the
+// weighted alphas in alpha.
+@group(0) @binding(0)
+var accumulation_t: texture_2d<f32>;
+// The product of one minus the alphas: how much of the scene shows through.
+@group(0) @binding(1)
+var revealage_t: texture_2d<f32>;
+
+struct VertexOutput {
+	@builtin(position) clip_pos: vec4<f32>,
+};
+
+// Draw with 3 vertices and no vertex buffer. The triangle covers the whole
+// screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_pos = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let coords = vec2<i32>(in.clip_pos.xy);
+	let revealage = textureLoad(revealage_t, coords, 0).r;
+	// Nothing transparent was drawn here.
+	if revealage >= 1.0 {
+		discard;
+	}
+	let accumulation = textureLoad(accumulation_t, coords, 0);
+	// Clamped, as half floats overflow with many bright layers and the weights
+	// may be tiny.
+	let color = accumulation.rgb / clamp(accumulation.a, 1e-4, 5e4);
+	return vec4<f32>(color, 1.0 - revealage);
+}
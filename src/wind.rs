@@ -0,0 +1,75 @@
+//! Wind swaying foliage, for grass and leaves.
+
+use bytemuck::{Pod, Zeroable};
+
+/// The wind moving the vertices of foliage materials, in the layout of
+/// `foliage_shader.wgsl`'s `WindUniform`. Vertices move along `direction` by
+/// `sin(x * frequency + time) * strength * v`, where `x` is their world space
+/// x and `v` their texture coordinate, so that vertices at `v = 0` stay in
+/// place.
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct WindUniform {
+	/// Normalized, in world space.
+	pub direction: [f32; 3],
+	/// How far vertices at `v = 1` move at most, in world units.
+	pub strength: f32,
+	/// How many waves per world unit along x, and how quickly they pass.
+	pub frequency: f32,
+	/// The phase of the waves, advanced by `frequency` per second.
+	pub time: f32,
+	pub _pad: [f32; 2],
+}
+impl WindUniform {
+	/// Moves the waves on by `dt` seconds.
+	pub fn advance(&mut self, dt: f32) {
+		// Kept within a period, so that it doesn't lose precision over time.
+		self.time = (self.time + dt * self.frequency).rem_euclid(std::f32::consts::TAU);
+	}
+
+	/// How far `vs_foliage` moves a vertex at world space `x` with texture
+	/// coordinate `v`.
+	pub fn displacement(&self, x: f32, v: f32) -> [f32; 3] {
+		let sway = (x * self.frequency + self.time).sin() * self.strength * v;
+		self.direction.map(|d| d * sway)
+	}
+
+	/// Whether the wind moves anything.
+	pub fn is_blowing(&self) -> bool {
+		self.strength != 0. && self.frequency != 0.
+	}
+}
+impl Default for WindUniform {
+	/// No wind, blowing along x once it has a strength.
+	fn default() -> Self {
+		Self {
+			direction: [1., 0., 0.],
+			strength: 0.,
+			frequency: 1.,
+			time: 0.,
+			_pad: [0.; 2],
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts::FRAC_PI_2;
+
+	#[test]
+	fn displacement_follows_the_direction() {
+		let mut wind = WindUniform {
+			direction: [0., 0., -1.],
+			strength: 0.5,
+			..Default::default()
+		};
+		assert_eq!(wind.displacement(FRAC_PI_2, 1.), [0., 0., -0.5]);
+		assert_eq!(wind.displacement(-FRAC_PI_2, 1.), [0., 0., 0.5]);
+		assert_eq!(wind.displacement(FRAC_PI_2, 0.), [0., 0., 0.]);
+		// Half a period later, the sway is reversed.
+		wind.advance(std::f32::consts::PI);
+		let [_, _, z] = wind.displacement(FRAC_PI_2, 1.);
+		assert!((z - 0.5).abs() < 1e-6, "{z}");
+	}
+}
@@ -0,0 +1,133 @@
+//! Screenshot tests of basic scenes, see [`ScreenshotTest`]. The references are
+//! in `tests/screenshots`.
+//!
+//! The scenes only use unlit, solid colors, and their shapes are seen through an
+//! orthographic camera at 128 pixels per unit, with edges on pixel boundaries.
+//! Their frames then don't depend on the GPU's lighting precision or
+//! rasterization rules.
+#![cfg(all(feature = "test-utils", not(target_arch = "wasm32")))]
+
+use color_eyre::Result;
+use wgpu_experiments::camera::Camera;
+use wgpu_experiments::cubemap::Cubemap;
+use wgpu_experiments::render_state::RenderState;
+use wgpu_experiments::testing::ScreenshotTest;
+use wgpu_experiments::vertex::{Color, ColorVertex, Normal, Pos, Uv, Vertex};
+
+/// Leaves room for differences in rasterization and filtering between GPUs.
+const TOLERANCE: f32 = 0.02;
+
+const RED: Color = Color::new(1., 0., 0., 1.);
+const GREEN: Color = Color::new(0., 1., 0., 1.);
+
+/// Replaces the default textured quad with a triangle without area, so that
+/// only what the test adds is drawn, over black.
+fn empty_scene(state: &mut RenderState) {
+	let vertex = Vertex::new(
+		Pos::new(0., 0., 0.),
+		Uv { u: 0., v: 0. },
+		Normal::new(0., 0., 1.),
+	);
+	state.load_mesh(&[vertex; 3], &[0, 1, 2]);
+	state.set_clear_color(wgpu::Color::BLACK);
+}
+
+/// Sees from -1 to 1 on both axes, down -z, with `empty_scene`.
+fn flat_scene(state: &mut RenderState) {
+	empty_scene(state);
+	state.set_camera(Box::new(Camera::new_orthographic(
+		-1., 1., -1., 1., 0.1, 10.,
+	)));
+}
+
+/// Adds a rectangle facing the camera, from `min` to `max`, at depth `z`.
+fn add_rect(
+	vertices: &mut Vec<ColorVertex>,
+	indices: &mut Vec<u32>,
+	min: (f32, f32),
+	max: (f32, f32),
+	z: f32,
+	color: Color,
+) {
+	let start = vertices.len() as u32;
+	vertices.extend(
+		[
+			(min.0, max.1),
+			(min.0, min.1),
+			(max.0, min.1),
+			(max.0, max.1),
+		]
+		.map(|(x, y)| ColorVertex::new(Pos::new(x, y, z), color)),
+	);
+	indices.extend([0, 1, 2, 2, 3, 0].map(|i| start + i));
+}
+
+#[test]
+fn quad() -> Result<()> {
+	ScreenshotTest::run(
+		"quad",
+		|state| {
+			flat_scene(state);
+			let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+			add_rect(
+				&mut vertices,
+				&mut indices,
+				(-0.5, -0.5),
+				(0.5, 0.5),
+				-1.,
+				RED,
+			);
+			state.add_color_mesh(&vertices, &indices);
+		},
+		TOLERANCE,
+	)
+}
+
+#[test]
+fn depth_buffer() -> Result<()> {
+	// The nearer rectangle is drawn first, so it only stays in front with depth
+	// testing.
+	ScreenshotTest::run(
+		"depth_buffer",
+		|state| {
+			flat_scene(state);
+			let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+			add_rect(
+				&mut vertices,
+				&mut indices,
+				(-0.5, -0.5),
+				(0.25, 0.5),
+				-1.,
+				RED,
+			);
+			add_rect(
+				&mut vertices,
+				&mut indices,
+				(-0.25, -0.25),
+				(0.5, 0.25),
+				-2.,
+				GREEN,
+			);
+			state.add_color_mesh(&vertices, &indices);
+		},
+		TOLERANCE,
+	)
+}
+
+#[test]
+fn skybox() -> Result<()> {
+	ScreenshotTest::run(
+		"skybox",
+		|state| {
+			empty_scene(state);
+			let cubemap = Cubemap::from_color(
+				state.device(),
+				state.queue(),
+				Some("Test Sky"),
+				[64, 128, 255, 255],
+			);
+			state.set_skybox(Some(&cubemap));
+		},
+		TOLERANCE,
+	)
+}